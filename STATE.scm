@@ -23,7 +23,25 @@
     (blockers-and-issues
       ((critical . ())
        (high . ())
-       (medium . ())
+       (medium . ("C backend provenance comments and `#line` mapping (synth-835): blocked on code generation; there is no C backend to annotate"
+                  "Associated types and `where`-clause solving (synth-851): blocked on traits and generics, which are not implemented"
+                  "Profile-guided code generation (synth-870): blocked on code generation; `--profile-use` only reports the plan a profile gives"
+                  "Inline assembly in built programs (synth-859): blocked on code generation; the interpreter cannot run `asm!`"
+                  "SIMD registers in built programs (synth-860): blocked on code generation; vector types run lane by lane in the interpreter"
+                  "OS threads (synth-861): blocked on code generation; the interpreter runs spawned threads one at a time"
+                  "Blocking channels across OS threads (synth-862): blocked on code generation and OS threads; channels run in the interpreter"
+                  "Atomic instructions (synth-864): blocked on code generation; atomics and orderings are checked and run in the interpreter"
+                  "Mutex/RwLock on OS threads (synth-865): blocked on code generation; guards are checked and run in the interpreter"
+                  "Raw pointers as machine addresses (synth-866): blocked on code generation; the interpreter models pointers"
+                  "`#[repr]` layouts in built programs (synth-867): blocked on code generation; layouts are computed and reported only"
+                  "Sanitizer instrumentation of built programs (synth-868): blocked on code generation; the interpreter runs the checks"
+                  "One backend module per LTO build (synth-871): blocked on code generation; `--lto` merges and prunes the program only"
+                  "Solo functions in `solo bloat` (synth-872): blocked on code generation; there are no Solo binaries to read"
+                  "Guaranteed tail calls in built programs (synth-898): blocked on code generation; `become` runs in constant stack in the interpreter"
+                  "Arena allocators in built programs (synth-899): blocked on code generation; allocators run in the interpreter"
+                  "Arena checkpoints in built programs (synth-900): blocked on code generation; checkpoints run in the interpreter"
+                  "WASI executables (synth-858): blocked on code generation; `--target wasm32-wasi` only plans the link"
+                  "Session cfgs and artifacts (synth-838): partially blocked; `Options::cfgs` wait on a `#[cfg]` attribute and are only reported by `Session::cfg`, and `CompileResult::artifacts` stays empty until code generation writes files"))
        (low . ())))
 
     (critical-next-actions
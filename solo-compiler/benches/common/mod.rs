@@ -0,0 +1,22 @@
+//! Inputs shared by the benchmarks
+
+/// About 10,000 lines of functions, each with a comment
+pub fn source() -> String {
+    let mut source = String::from("struct Story { words: i64, draft: bool }\n\n");
+    let mut i = 0;
+    while source.lines().count() < 10_000 {
+        source.push_str(&format!(
+            "// Count the words of draft {i}\n\
+             fn count_{i}(s: &Story, n: i64) -> i64 {{\n\
+             \x20   let mut total = s.words + n;\n\
+             \x20   if s.draft {{\n\
+             \x20       total = total * 2;\n\
+             \x20   }}\n\
+             \x20   total\n\
+             }}\n\n"
+        ));
+        i += 1;
+    }
+    source.push_str("fn main() {\n    let s = Story { words: 3, draft: false };\n    print(count_0(&s, 1));\n}\n");
+    source
+}
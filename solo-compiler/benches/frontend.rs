@@ -0,0 +1,83 @@
+//! Front end speed and memory on a 10,000-line file
+//!
+//! Run with `cargo bench --bench frontend`. Before timing, it prints the
+//! heap allocations lexing and parsing make, counted by a global allocator.
+//! `lex cached` is lexing answered from the token cache.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use solo::lexer::Lexer;
+use solo::token::{Token, TokenKind};
+use solo::{parser, token_cache};
+
+mod common;
+
+/// The system allocator, counting allocations and their bytes
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+fn lex(source: &str) -> Vec<Token> {
+    let mut lexer = Lexer::new(source);
+    std::iter::from_fn(|| Some(lexer.next_token()))
+        .take_while(|token| token.kind != TokenKind::Eof)
+        .collect()
+}
+
+/// Print the allocations `f` makes
+fn allocations<T>(name: &str, f: impl FnOnce() -> T) -> T {
+    let (count, bytes) = (
+        ALLOCATIONS.load(Ordering::Relaxed),
+        BYTES.load(Ordering::Relaxed),
+    );
+    let result = f();
+    println!(
+        "{}: {} allocations, {} KiB",
+        name,
+        ALLOCATIONS.load(Ordering::Relaxed) - count,
+        (BYTES.load(Ordering::Relaxed) - bytes) / 1024
+    );
+    result
+}
+
+fn bench_frontend(c: &mut Criterion) {
+    let source = common::source();
+    let tokens = allocations("lex", || lex(&source));
+    println!(
+        "{} tokens of {} bytes",
+        tokens.len(),
+        std::mem::size_of::<Token>()
+    );
+    allocations("parse", || parser::parse(tokens.clone()).unwrap());
+
+    c.bench_function("lex", |b| b.iter(|| lex(&source)));
+    c.bench_function("lex cached", |b| b.iter(|| token_cache::lex(&source).0));
+    c.bench_function("parse", |b| {
+        b.iter_batched(
+            || tokens.clone(),
+            |tokens| parser::parse(tokens).unwrap(),
+            criterion::BatchSize::LargeInput,
+        )
+    });
+}
+
+criterion_group!(benches, bench_frontend);
+criterion_main!(benches);
@@ -0,0 +1,84 @@
+//! Language server latency on a 10,000-line file
+//!
+//! Run with `cargo bench --bench lsp`. A keystroke is one character typed
+//! into the middle of the file, and its undo, so that the file stays the
+//! same from one iteration to the next.
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use solo::cst::{self, Edit};
+use solo::json::Json;
+use solo::lsp::Server;
+
+mod common;
+
+const URI: &str = "file:///big.solo";
+
+fn change(line: usize, character: usize, end: usize, text: &str) -> Json {
+    Json::parse(&format!(
+        r#"{{"jsonrpc":"2.0","method":"textDocument/didChange","params":{{"textDocument":{{"uri":"{URI}"}},"contentChanges":[{{"range":{{"start":{{"line":{line},"character":{character}}},"end":{{"line":{line},"character":{end}}}}},"text":"{text}"}}]}}}}"#
+    ))
+    .unwrap()
+}
+
+fn bench_reparse(c: &mut Criterion) {
+    let source = common::source();
+    let at = source.len() / 2 + source[source.len() / 2..].find("total * 2").unwrap();
+    let mut edited = source.clone();
+    let edit = Edit {
+        start: at,
+        end: at,
+        text: "x".to_string(),
+    };
+    edit.apply(&mut edited);
+    let tree = cst::parse(&source);
+
+    c.bench_function("cst parse", |b| b.iter(|| cst::parse(&edited)));
+    c.bench_function("cst reparse keystroke", |b| {
+        b.iter_batched(
+            || tree.clone(),
+            |mut tree| {
+                tree.reparse(&edited, &edit);
+                tree
+            },
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+fn bench_server(c: &mut Criterion) {
+    let source = common::source();
+    let line = source.lines().count() / 2;
+    let open = Json::object([
+        ("method", Json::str("textDocument/didOpen")),
+        (
+            "params",
+            Json::object([(
+                "textDocument",
+                Json::object([
+                    ("uri", Json::str(URI)),
+                    ("text", Json::str(source.as_str())),
+                ]),
+            )]),
+        ),
+    ]);
+    let mut server = Server::new(false);
+    server.handle(&open);
+    let typed = change(line, 0, 0, " ");
+    let undone = change(line, 0, 1, "");
+
+    c.bench_function("didChange keystroke", |b| {
+        b.iter(|| {
+            server.update(&typed);
+            server.update(&undone);
+        })
+    });
+    c.bench_function("didChange keystroke to diagnostics", |b| {
+        b.iter(|| {
+            server.handle(&typed);
+            server.handle(&undone)
+        })
+    });
+}
+
+criterion_group!(benches, bench_reparse, bench_server);
+criterion_main!(benches);
@@ -0,0 +1,507 @@
+/**
+ * Tree-sitter grammar for Solo
+ *
+ * Follows the compiler's parser (src/parser.rs) rule for rule. The compiler
+ * bundles this file (`solo highlight --tree-sitter <dir>` writes it out), and
+ * its tests check KEYWORDS, PUNCTUATION and every string used below against
+ * the lexer, so the two cannot drift apart silently.
+ */
+
+// Words the lexer reserves (`token::KEYWORDS`)
+const KEYWORDS = [
+  'fn', 'let', 'mut', 'if', 'else', 'while', 'for', 'in', 'match', 'return', 'become', 'struct',
+  'enum', 'impl', 'trait', 'type', 'newtype', 'arena', 'defer', 'extern', 'pub', 'belief',
+  'where', 'macro', 'const', 'unsafe', 'as', 'true', 'false',
+];
+
+// Every operator and delimiter the lexer knows, except the brackets
+const PUNCTUATION = [
+  '+', '-', '*', '/', '%', '=', '==', '!=', '<', '<=', '>', '>=', '&&', '||', '!', '->', '=>',
+  '&', '|', '~', ',', ';', ':', '::', '.', '..', '..=', '$', '?', '#',
+];
+
+// Binding power of operators, as `BinaryOp::precedence` gives it
+const PREC = {
+  assign: 0,
+  or: 1,
+  and: 2,
+  compare: 3,
+  additive: 4,
+  multiplicative: 5,
+  cast: 6,
+  unary: 7,
+  postfix: 8,
+};
+
+module.exports = grammar({
+  name: 'solo',
+
+  extras: $ => [/\s/, $.line_comment, $.block_comment],
+
+  word: $ => $.identifier,
+
+  conflicts: $ => [
+    // `if x {` starts a struct literal or the block of the `if`
+    [$._expression, $.struct_expression],
+  ],
+
+  rules: {
+    // `#![library]` may come between items, starting a package's library
+    source_file: $ => repeat(choice($.inner_attribute, $._item)),
+
+    _item: $ => choice(
+      $.attribute,
+      $.function_item,
+      $.const_item,
+      $.struct_item,
+      $.enum_item,
+      $.type_item,
+      $.impl_item,
+      $.extern_block,
+      $.macro_definition,
+    ),
+
+    // Attributes, which apply to the `fn`, `struct` or `extern` item after them
+
+    inner_attribute: $ => seq('#', '!', '[', $._attribute_body, ']'),
+
+    attribute: $ => seq('#', '[', $._attribute_body, ']'),
+
+    _attribute_body: $ => seq(
+      field('name', $.identifier),
+      optional(seq('(', commaSep(field('argument', $.identifier)), ')')),
+    ),
+
+    // Items
+
+    visibility_modifier: _ => seq('pub', optional(seq('(', 'crate', ')'))),
+
+    function_item: $ => seq(
+      optional($.visibility_modifier),
+      optional(choice('const', seq('extern', field('abi', $.string_literal)))),
+      'fn',
+      field('name', $.identifier),
+      field('parameters', $.parameters),
+      optional(seq('->', field('return_type', choice($._type, $.never_type)))),
+      field('body', $.block),
+    ),
+
+    parameters: $ => seq('(', commaSep(choice($.self_parameter, $.parameter)), ')'),
+
+    self_parameter: $ => seq(optional('&'), optional('mut'), $.self),
+
+    parameter: $ => seq(field('name', $.identifier), ':', field('type', $._type)),
+
+    const_item: $ => seq(
+      optional($.visibility_modifier),
+      'const',
+      field('name', $.identifier),
+      ':',
+      field('type', $._type),
+      '=',
+      field('value', $._expression),
+      ';',
+    ),
+
+    struct_item: $ => seq(
+      optional($.visibility_modifier),
+      'struct',
+      field('name', $._type_identifier),
+      field('body', $.field_declaration_list),
+    ),
+
+    field_declaration_list: $ => seq('{', commaSep($.field_declaration), '}'),
+
+    field_declaration: $ => seq(
+      optional($.visibility_modifier),
+      field('name', $._field_identifier),
+      ':',
+      field('type', $._type),
+    ),
+
+    enum_item: $ => seq(
+      optional($.visibility_modifier),
+      'enum',
+      field('name', $._type_identifier),
+      field('body', $.enum_variant_list),
+    ),
+
+    enum_variant_list: $ => seq('{', commaSep($.enum_variant), '}'),
+
+    enum_variant: $ => seq(
+      field('name', $.identifier),
+      optional(seq('=', field('value', $._expression))),
+    ),
+
+    type_item: $ => seq(
+      optional($.visibility_modifier),
+      choice('type', 'newtype'),
+      field('name', $._type_identifier),
+      '=',
+      field('type', $._type),
+      ';',
+    ),
+
+    impl_item: $ => seq(
+      'impl',
+      field('type', $._type_identifier),
+      field('body', $.declaration_list),
+    ),
+
+    declaration_list: $ => seq('{', repeat($.function_item), '}'),
+
+    extern_block: $ => seq(
+      'extern',
+      field('abi', $.string_literal),
+      '{',
+      repeat($.function_signature_item),
+      '}',
+    ),
+
+    function_signature_item: $ => seq(
+      'fn',
+      field('name', $.identifier),
+      field('parameters', $.parameters),
+      optional(seq('->', field('return_type', choice($._type, $.never_type)))),
+      ';',
+    ),
+
+    // Macros match and produce token trees
+
+    macro_definition: $ => seq(
+      'macro',
+      field('name', $.identifier),
+      '{',
+      repeat(seq($.macro_rule, optional(';'))),
+      '}',
+    ),
+
+    macro_rule: $ => seq(field('left', $.token_tree), '=>', field('right', $.token_tree)),
+
+    macro_invocation: $ => seq(field('macro', $.identifier), '!', $.token_tree),
+
+    token_tree: $ => choice(
+      seq('(', repeat($._token), ')'),
+      seq('[', repeat($._token), ']'),
+      seq('{', repeat($._token), '}'),
+    ),
+
+    _token: $ => choice(
+      $.token_tree,
+      $.metavariable,
+      $.identifier,
+      $.self,
+      $.integer_literal,
+      $.float_literal,
+      $.string_literal,
+      ...KEYWORDS,
+      ...PUNCTUATION,
+    ),
+
+    metavariable: _ => /\$[\p{XID_Start}_][\p{XID_Continue}]*/u,
+
+    // Types
+
+    _type: $ => choice(
+      $._type_identifier,
+      $.generic_type,
+      $.reference_type,
+      $.pointer_type,
+      $.array_type,
+      $.unit_type,
+    ),
+
+    generic_type: $ => seq(
+      field('type', choice($._type_identifier, 'belief')),
+      field('arguments', $.type_arguments),
+    ),
+
+    // Types first, then bounds on the belief (`confidence >= 0.9`)
+    type_arguments: $ => seq('<', commaSep1(choice($._type, $.bound)), '>'),
+
+    bound: $ => seq(
+      field('property', $.identifier),
+      field('operator', choice('>=', '<=')),
+      field('value', choice($.integer_literal, $.float_literal)),
+    ),
+
+    reference_type: $ => seq('&', optional('mut'), field('type', $._type)),
+
+    pointer_type: $ => seq('*', choice('const', 'mut'), field('type', $._type)),
+
+    array_type: $ => seq('[', field('element', $._type), ';', field('length', $._expression), ']'),
+
+    unit_type: _ => seq('(', ')'),
+
+    never_type: _ => '!',
+
+    // Statements
+
+    block: $ => seq('{', repeat($._statement), optional(field('tail', $._expression)), '}'),
+
+    _statement: $ => choice(
+      $.let_declaration,
+      $.const_item,
+      $.defer_statement,
+      $.macro_definition,
+      $.expression_statement,
+      ';',
+    ),
+
+    let_declaration: $ => seq(
+      'let',
+      optional('mut'),
+      field('name', $.identifier),
+      optional(seq(':', field('type', $._type))),
+      optional(seq('=', field('value', $._expression))),
+      ';',
+    ),
+
+    defer_statement: $ => seq(
+      'defer',
+      choice(seq($._expression, ';'), prec(1, $._block_like)),
+    ),
+
+    // Block-like expressions end a statement without `;`
+    expression_statement: $ => choice(seq($._expression, ';'), prec(1, $._block_like)),
+
+    _block_like: $ => choice(
+      $.block,
+      $.if_expression,
+      $.while_expression,
+      $.for_expression,
+      $.match_expression,
+      $.arena_expression,
+      $.unsafe_expression,
+    ),
+
+    // Expressions
+
+    _expression: $ => choice(
+      $.assignment_expression,
+      $.binary_expression,
+      $.cast_expression,
+      $.unary_expression,
+      $.reference_expression,
+      $.call_expression,
+      $.field_expression,
+      $.index_expression,
+      $.distribution_expression,
+      $.struct_expression,
+      $.scoped_identifier,
+      $.macro_invocation,
+      $.parenthesized_expression,
+      $.array_expression,
+      $.return_expression,
+      $.become_expression,
+      $._block_like,
+      $.identifier,
+      $.self,
+      $.integer_literal,
+      $.float_literal,
+      $.string_literal,
+      $.boolean_literal,
+    ),
+
+    assignment_expression: $ => prec.right(PREC.assign, seq(
+      field('left', $._expression),
+      '=',
+      field('right', $._expression),
+    )),
+
+    binary_expression: $ => choice(...[
+      [PREC.or, '||'],
+      [PREC.and, '&&'],
+      [PREC.compare, choice('==', '!=', '<', '<=', '>', '>=')],
+      [PREC.additive, choice('+', '-')],
+      [PREC.multiplicative, choice('*', '/', '%')],
+    ].map(([precedence, operator]) => prec.left(precedence, seq(
+      field('left', $._expression),
+      field('operator', operator),
+      field('right', $._expression),
+    )))),
+
+    cast_expression: $ => prec.left(PREC.cast, seq(
+      field('value', $._expression),
+      'as',
+      field('type', $._type_identifier),
+    )),
+
+    unary_expression: $ => prec(PREC.unary, seq(choice('-', '!', '*'), $._expression)),
+
+    reference_expression: $ => prec(PREC.unary, seq('&', optional('mut'), field('value', $._expression))),
+
+    // Method calls are calls of a field expression, as in Rust grammars
+    call_expression: $ => prec(PREC.postfix, seq(
+      field('function', $._expression),
+      field('arguments', $.arguments),
+    )),
+
+    arguments: $ => seq('(', commaSep($._expression), ')'),
+
+    field_expression: $ => prec(PREC.postfix, seq(
+      field('value', $._expression),
+      '.',
+      // `.0` unwraps a newtype
+      field('field', choice($._field_identifier, $.integer_literal)),
+    )),
+
+    index_expression: $ => prec(PREC.postfix, seq($._expression, '[', $._expression, ']')),
+
+    // `~Normal(mean, sd)`
+    distribution_expression: $ => seq(
+      '~',
+      field('name', $.identifier),
+      field('arguments', $.arguments),
+    ),
+
+    struct_expression: $ => seq(
+      field('name', alias($.identifier, $.type_identifier)),
+      field('body', $.field_initializer_list),
+    ),
+
+    field_initializer_list: $ => seq('{', commaSep($.field_initializer), '}'),
+
+    field_initializer: $ => seq(
+      field('field', $._field_identifier),
+      ':',
+      field('value', $._expression),
+    ),
+
+    // `Type::function`, `Enum::Variant`, `env::arg`
+    scoped_identifier: $ => seq(
+      field('path', choice($.identifier, $.scoped_identifier)),
+      '::',
+      field('name', $.identifier),
+    ),
+
+    parenthesized_expression: $ => seq('(', $._expression, ')'),
+
+    array_expression: $ => seq(
+      '[',
+      choice(
+        seq($._expression, ';', field('length', $._expression)),
+        commaSep($._expression),
+      ),
+      ']',
+    ),
+
+    return_expression: $ => prec.right(seq('return', optional($._expression))),
+
+    become_expression: $ => seq('become', field('call', $.call_expression)),
+
+    if_expression: $ => seq(
+      'if',
+      field('condition', $._expression),
+      field('consequence', $.block),
+      optional(seq('else', field('alternative', choice($.block, $.if_expression)))),
+    ),
+
+    while_expression: $ => seq('while', field('condition', $._expression), field('body', $.block)),
+
+    // `.map(f)`, `.filter(p)` and `.take(n)` on the iterable are ordinary
+    // method calls here
+    for_expression: $ => seq(
+      'for',
+      field('pattern', $.identifier),
+      'in',
+      field('iterable', $._expression),
+      field('body', $.block),
+    ),
+
+    match_expression: $ => seq(
+      'match',
+      field('value', $._expression),
+      field('body', $.match_block),
+    ),
+
+    match_block: $ => seq(
+      '{',
+      repeat($.match_arm),
+      optional(alias($.last_match_arm, $.match_arm)),
+      '}',
+    ),
+
+    // Arms whose value is block-like need no `,` after them
+    match_arm: $ => seq(
+      $._arm_head,
+      choice(seq(field('value', $._expression), ','), field('value', prec(1, $._block_like))),
+    ),
+
+    last_match_arm: $ => seq($._arm_head, field('value', $._expression), optional(',')),
+
+    _arm_head: $ => seq(
+      field('pattern', $._pattern),
+      optional(seq('if', field('guard', $._expression))),
+      '=>',
+    ),
+
+    _pattern: $ => choice($.or_pattern, $._simple_pattern),
+
+    or_pattern: $ => prec.left(seq($._pattern, '|', $._pattern)),
+
+    // `_` is an identifier to the lexer
+    _simple_pattern: $ => choice(
+      $.tuple_struct_pattern,
+      $.scoped_identifier,
+      $.negative_literal,
+      $.identifier,
+      $.integer_literal,
+      $.string_literal,
+      $.boolean_literal,
+    ),
+
+    // `Some(pattern)`
+    tuple_struct_pattern: $ => seq(
+      field('type', $.identifier),
+      '(',
+      $._pattern,
+      ')',
+    ),
+
+    negative_literal: $ => seq('-', $.integer_literal),
+
+    arena_expression: $ => seq(
+      'arena',
+      optional(field('name', $.identifier)),
+      optional(seq('in', field('allocator', $._expression))),
+      field('body', $.block),
+    ),
+
+    unsafe_expression: $ => seq('unsafe', field('body', $.block)),
+
+    // Tokens
+
+    identifier: _ => /[\p{XID_Start}_][\p{XID_Continue}]*/u,
+
+    _type_identifier: $ => alias($.identifier, $.type_identifier),
+
+    _field_identifier: $ => alias($.identifier, $.field_identifier),
+
+    // An identifier to the lexer, but a parameter only methods may have
+    self: _ => 'self',
+
+    // With an optional type suffix, as in `255u8`
+    integer_literal: _ => /[0-9]+([a-z][a-z0-9]*)?/,
+
+    float_literal: _ => /[0-9]+\.[0-9]+([a-z][a-z0-9]*)?/,
+
+    // No escapes; strings may span lines
+    string_literal: _ => /"[^"]*"/,
+
+    boolean_literal: _ => choice('true', 'false'),
+
+    line_comment: _ => token(seq('//', /.*/)),
+
+    block_comment: _ => token(seq('/*', /[^*]*\*+([^/*][^*]*\*+)*/, '/')),
+  },
+});
+
+function commaSep(rule) {
+  return optional(commaSep1(rule));
+}
+
+// One or more, with an optional trailing comma
+function commaSep1(rule) {
+  return seq(rule, repeat(seq(',', rule)), optional(','));
+}
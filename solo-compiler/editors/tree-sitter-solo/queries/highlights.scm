@@ -0,0 +1,76 @@
+; Highlighting for Solo, in the capture names GitHub, Neovim and Helix share.
+; Earlier patterns take precedence.
+
+; Types
+
+((type_identifier) @type.builtin
+  (#match? @type.builtin "^(i8|i16|i32|i64|u8|u16|u32|u64|isize|usize|f32|f64|bool|str|String|Arena|Belief)$"))
+(generic_type "belief" @type.builtin)
+(never_type) @type.builtin
+(type_identifier) @type
+
+; Functions, methods and macros
+
+(function_item name: (identifier) @function)
+(function_signature_item name: (identifier) @function)
+(impl_item body: (declaration_list (function_item name: (identifier) @function.method)))
+(call_expression function: (field_expression field: (field_identifier) @function.method))
+(call_expression function: (scoped_identifier name: (identifier) @function))
+(call_expression function: (identifier) @function)
+(macro_definition name: (identifier) @function.macro)
+(macro_invocation macro: (identifier) @function.macro "!" @function.macro)
+(metavariable) @variable.parameter
+
+; Beliefs: `~Normal(0.0, 1.0)` and `confidence >= 0.9` bounds
+(distribution_expression "~" @operator name: (identifier) @function.builtin)
+(bound property: (identifier) @property)
+
+; Paths
+
+((scoped_identifier path: (identifier) @type)
+  (#match? @type "^[A-Z]"))
+((scoped_identifier name: (identifier) @constructor)
+  (#match? @constructor "^[A-Z]"))
+(scoped_identifier path: (identifier) @module)
+(tuple_struct_pattern type: (identifier) @constructor)
+(enum_variant name: (identifier) @constructor)
+
+; Variables
+
+(parameter name: (identifier) @variable.parameter)
+(const_item name: (identifier) @constant)
+((identifier) @constant
+  (#match? @constant "^[A-Z][A-Z0-9_]+$"))
+(field_identifier) @property
+(self) @variable.builtin
+(identifier) @variable
+
+; Literals and comments
+
+(integer_literal) @number
+(float_literal) @number
+(string_literal) @string
+(boolean_literal) @constant.builtin
+(line_comment) @comment
+(block_comment) @comment
+
+; Attributes
+
+(attribute) @attribute
+(inner_attribute) @attribute
+
+; Keywords, operators and punctuation
+
+[
+  "fn" "let" "mut" "if" "else" "while" "for" "in" "match" "return" "become" "struct" "enum" "impl"
+  "trait" "type" "newtype" "arena" "defer" "extern" "pub" "belief" "where" "macro" "const" "unsafe"
+  "as"
+] @keyword
+(visibility_modifier "crate" @keyword)
+
+[
+  "+" "-" "*" "/" "%" "=" "==" "!=" "<" "<=" ">" ">=" "&&" "||" "!" "&" "->" "=>"
+] @operator
+
+["(" ")" "[" "]" "{" "}"] @punctuation.bracket
+["," ";" ":" "::" "."] @punctuation.delimiter
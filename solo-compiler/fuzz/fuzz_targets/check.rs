@@ -0,0 +1,12 @@
+//! Checking arbitrary text, and the passes built on the checked tree,
+//! never panic
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|source: &str| {
+    let _ = solo::check(source);
+    let _ = solo::lint(source);
+    let _ = solo::minify::minify(source);
+});
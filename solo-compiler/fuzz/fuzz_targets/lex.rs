@@ -0,0 +1,9 @@
+//! Lexing arbitrary text returns tokens or diagnostics, never panics
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|source: &str| {
+    let _ = solo::compile_until(source, solo::Phase::Lex);
+});
@@ -0,0 +1,9 @@
+//! Expanding macros in and parsing arbitrary text never panics
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|source: &str| {
+    let _ = solo::compile_until(source, solo::Phase::Parse);
+});
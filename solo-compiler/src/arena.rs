@@ -0,0 +1,183 @@
+//! Arena bookkeeping for the interpreter
+//!
+//! Structs, arrays and beliefs created inside `arena { ... }` are charged to
+//! the innermost active arena and released together when it ends, or when
+//! the arena is reset to a checkpoint taken before them. With
+//! `solo run --profile-arenas` the interpreter keeps an [`ArenaProfile`] of
+//! every arena block, reported after the run to help decide where arenas
+//! belong.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crate::ast::Span;
+use crate::interp::Value;
+
+/// An arena that is currently live
+#[derive(Debug)]
+pub struct Frame {
+    pub span: Span,
+    /// Dropped with the frame, so that pointers into the arena can tell
+    /// that it was reset; replaced at every checkpoint and reset, so that
+    /// only pointers to what a reset frees see it dropped
+    pub live: Rc<()>,
+    /// Allocations and bytes charged to the arena, including those reset
+    pub allocations: usize,
+    pub bytes: u64,
+    /// Bytes the arena holds now, and the most it held at once
+    pub held: u64,
+    pub peak: u64,
+    /// Checkpoints still valid, oldest first
+    marks: Vec<Mark>,
+    /// Where the arena takes its memory from, for `arena in buffer { ... }`
+    pub allocator: Option<Allocator>,
+    /// When the block was entered, if its lifetime is being profiled
+    started: Option<Instant>,
+}
+
+/// What an arena held when a checkpoint was taken
+#[derive(Debug)]
+struct Mark {
+    held: u64,
+    /// Liveness of the data allocated before the checkpoint, only held
+    /// so that pointers to it stay valid
+    _live: Rc<()>,
+}
+
+/// A program-supplied allocator lent to an arena
+#[derive(Debug, Clone)]
+pub struct Allocator {
+    /// The type whose `allocate` and `reset` methods are called
+    pub ty: String,
+    /// The allocator itself, passed as `&mut self`
+    pub slot: Rc<RefCell<Value>>,
+}
+
+impl Frame {
+    pub fn new(span: Span, timed: bool) -> Self {
+        Self {
+            span,
+            live: Rc::new(()),
+            allocations: 0,
+            bytes: 0,
+            held: 0,
+            peak: 0,
+            marks: Vec::new(),
+            allocator: None,
+            started: timed.then(Instant::now),
+        }
+    }
+
+    /// Charge an allocation of `bytes` to the arena
+    pub fn charge(&mut self, bytes: u64) {
+        self.allocations += 1;
+        self.bytes += bytes;
+        self.held += bytes;
+        self.peak = self.peak.max(self.held);
+    }
+
+    /// Remember where the arena is now, returning the checkpoint's index
+    pub fn checkpoint(&mut self) -> usize {
+        let live = std::mem::replace(&mut self.live, Rc::new(()));
+        self.marks.push(Mark {
+            held: self.held,
+            _live: live,
+        });
+        self.marks.len() - 1
+    }
+
+    /// Free everything allocated since the checkpoint `index`, returning
+    /// the bytes freed; `None` if a reset to an earlier checkpoint already
+    /// discarded it. Later checkpoints are discarded, and the checkpoint
+    /// itself stays valid.
+    pub fn reset_to(&mut self, index: usize) -> Option<u64> {
+        let held = self.marks.get(index)?.held;
+        self.marks.truncate(index + 1);
+        self.live = Rc::new(());
+        let freed = self.held - held;
+        self.held = held;
+        Some(freed)
+    }
+}
+
+/// Statistics of one `arena` block, over every time it ran
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArenaStats {
+    pub span: Span,
+    /// Times the block was entered
+    pub entries: usize,
+    pub allocations: usize,
+    pub total_bytes: u64,
+    /// Most bytes held by a single run of the block
+    pub peak_bytes: u64,
+    /// Time spent inside the block, summed over runs
+    pub lifetime: Duration,
+}
+
+/// Statistics of every arena block that ran, in source order
+#[derive(Debug, Clone, Default)]
+pub struct ArenaProfile {
+    pub arenas: Vec<ArenaStats>,
+}
+
+impl ArenaProfile {
+    /// Fold a finished arena into the statistics of its block
+    pub fn record(&mut self, frame: Frame) {
+        let index = match self.arenas.binary_search_by_key(&frame.span, |a| a.span) {
+            Ok(index) => index,
+            Err(index) => {
+                self.arenas.insert(
+                    index,
+                    ArenaStats {
+                        span: frame.span,
+                        entries: 0,
+                        allocations: 0,
+                        total_bytes: 0,
+                        peak_bytes: 0,
+                        lifetime: Duration::ZERO,
+                    },
+                );
+                index
+            }
+        };
+        let stats = &mut self.arenas[index];
+        stats.entries += 1;
+        stats.allocations += frame.allocations;
+        stats.total_bytes += frame.bytes;
+        stats.peak_bytes = stats.peak_bytes.max(frame.peak);
+        if let Some(started) = frame.started {
+            stats.lifetime += started.elapsed();
+        }
+    }
+}
+
+impl fmt::Display for ArenaProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Arena profile:")?;
+        if self.arenas.is_empty() {
+            return writeln!(f, "  no arenas were entered");
+        }
+        writeln!(
+            f,
+            "  {:<10} {:>8} {:>8} {:>12} {:>12} {:>14}",
+            "arena", "entries", "allocs", "total bytes", "peak bytes", "mean lifetime"
+        )?;
+        for stats in &self.arenas {
+            let location = format!("{}:{}", stats.span.line, stats.span.column);
+            let mean = stats.lifetime / stats.entries.max(1) as u32;
+            writeln!(
+                f,
+                "  {:<10} {:>8} {:>8} {:>12} {:>12} {:>14}",
+                location,
+                stats.entries,
+                stats.allocations,
+                stats.total_bytes,
+                stats.peak_bytes,
+                format!("{:.1?}", mean)
+            )?;
+        }
+        Ok(())
+    }
+}
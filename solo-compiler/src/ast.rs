@@ -0,0 +1,880 @@
+//! Abstract syntax tree for Solo dialect
+//!
+//! Produced by the parser, consumed by the type checker and later phases.
+//! [`pretty`] prints it back as source.
+
+use std::fmt;
+use std::ops::{Index, IndexMut};
+
+use crate::numeric::Numeric;
+use crate::symbol::Symbol;
+
+pub mod pretty;
+
+/// Source position of a node (1-based line and column)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    pub fn new(line: usize, column: usize) -> Self {
+        Self { line, column }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Program {
+    /// Inner attributes at the top of the file (`#![feature(...)]`)
+    pub attrs: Vec<Attribute>,
+    pub items: Vec<Item>,
+    /// `#![library]`, after which the items are a package's library, used
+    /// by the items before it only through what it declares `pub`
+    pub library: Option<Span>,
+    /// The expressions nested in other nodes, which refer to them by
+    /// [`ExprId`]
+    pub exprs: Exprs,
+}
+
+impl Index<ExprId> for Program {
+    type Output = Expr;
+
+    fn index(&self, id: ExprId) -> &Expr {
+        &self.exprs[id]
+    }
+}
+
+impl Program {
+    /// Whether `span` is in the library part of the program
+    pub fn in_library(&self, span: Span) -> bool {
+        self.library.is_some_and(|start| span > start)
+    }
+
+    /// Features enabled by `#![feature(...)]`, with the attribute's span
+    pub fn features(&self) -> impl Iterator<Item = (&str, Span)> + '_ {
+        self.attrs
+            .iter()
+            .filter(|a| a.name == "feature")
+            .flat_map(|a| a.args.iter().map(move |arg| (arg.as_str(), a.span)))
+    }
+
+    /// Free functions and methods with the name they are called by
+    /// (`name` or `Type::name`) and, for methods, the implementing type
+    pub fn functions(&self) -> Vec<(String, Option<&str>, &Function)> {
+        let mut functions = Vec::new();
+        for item in &self.items {
+            match item {
+                Item::Function(f) => functions.push((f.name.to_string(), None, f)),
+                Item::Impl(block) => {
+                    for method in &block.methods {
+                        functions.push((
+                            block.qualified(&method.name),
+                            Some(block.self_ty.as_str()),
+                            method,
+                        ));
+                    }
+                }
+                _ => {}
+            }
+        }
+        functions
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Item {
+    Function(Function),
+    Const(ConstItem),
+    Enum(EnumDef),
+    Struct(StructDef),
+    Impl(ImplBlock),
+    Extern(ExternBlock),
+    TypeAlias(TypeAlias),
+}
+
+impl Item {
+    /// Position of the item's keyword
+    pub fn span(&self) -> Span {
+        match self {
+            Item::Function(f) => f.span,
+            Item::Const(c) => c.span,
+            Item::Enum(e) => e.span,
+            Item::Struct(s) => s.span,
+            Item::Impl(block) => block.span,
+            Item::Extern(block) => block.span,
+            Item::TypeAlias(alias) => alias.span,
+        }
+    }
+}
+
+/// Who may use an item, field or method; only a package's library has
+/// users that a modifier keeps out (see [`Program::library`])
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Visibility {
+    /// No modifier; `at` is where one would go
+    Private { at: Span },
+    /// `pub(crate)`, from `start` up to `end`: the library only, stated
+    /// explicitly
+    Crate { start: Span, end: Span },
+    /// `pub`
+    Public { at: Span },
+}
+
+impl Visibility {
+    pub fn is_public(self) -> bool {
+        matches!(self, Visibility::Public { .. })
+    }
+
+    pub fn is_private(self) -> bool {
+        matches!(self, Visibility::Private { .. })
+    }
+
+    /// The start and end of the text to replace, and its replacement, to
+    /// make this `pub`
+    pub fn make_public(self) -> (Span, Span, &'static str) {
+        match self {
+            Visibility::Private { at } => (at, at, "pub "),
+            Visibility::Crate { start, end } => (start, end, "pub"),
+            Visibility::Public { at } => (at, at, ""),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub vis: Visibility,
+    pub name: Symbol,
+    pub params: Vec<Param>,
+    pub return_type: Option<TypeExpr>,
+    pub body: Block,
+    pub is_const: bool,
+    /// `pub extern "C" fn`, callable from C under its
+    /// [symbol](crate::export::symbol)
+    pub exported: bool,
+    /// `self` parameter of a method (not included in `params`)
+    pub receiver: Option<Receiver>,
+    pub attrs: Vec<Attribute>,
+    pub span: Span,
+}
+
+impl Function {
+    /// Marked `#[test]`, so `solo test` runs it
+    pub fn is_test(&self) -> bool {
+        self.attrs.iter().any(|a| a.name == "test")
+    }
+
+    /// Marked `#[model]`, so it may `observe` and is only called where
+    /// `expect` samples
+    pub fn is_model(&self) -> bool {
+        self.attrs.iter().any(|a| a.name == "model")
+    }
+
+    /// Marked `#[no_mangle]`, so it is exported under its own name
+    pub fn is_no_mangle(&self) -> bool {
+        self.attrs.iter().any(|a| a.name == "no_mangle")
+    }
+}
+
+/// How a method takes its receiver
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Receiver {
+    /// `self`
+    Value,
+    /// `mut self`
+    MutValue,
+    /// `&self`
+    Ref,
+    /// `&mut self`
+    RefMut,
+}
+
+/// `impl Type { fn ... }`; `Self` is replaced by `Type` during parsing
+#[derive(Debug, Clone)]
+pub struct ImplBlock {
+    pub self_ty: Symbol,
+    pub methods: Vec<Function>,
+    pub span: Span,
+}
+
+impl ImplBlock {
+    /// Path by which a method of this block is called, e.g. `Point::new`
+    pub fn qualified(&self, method: &str) -> String {
+        format!("{}::{}", self.self_ty, method)
+    }
+}
+
+/// `extern "C" { fn name(params) -> ret; ... }`, optionally with
+/// `#[link(library)]` attributes naming the libraries defining them
+#[derive(Debug, Clone)]
+pub struct ExternBlock {
+    pub functions: Vec<ExternFn>,
+    pub attrs: Vec<Attribute>,
+    pub span: Span,
+}
+
+impl ExternBlock {
+    /// Libraries named by `#[link(...)]` attributes
+    pub fn links(&self) -> impl Iterator<Item = &str> + '_ {
+        self.attrs
+            .iter()
+            .filter(|a| a.name == "link")
+            .flat_map(|a| a.args.iter().map(String::as_str))
+    }
+}
+
+/// A C function declared in an `extern` block
+#[derive(Debug, Clone)]
+pub struct ExternFn {
+    pub name: Symbol,
+    pub params: Vec<Param>,
+    pub return_type: Option<TypeExpr>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct Param {
+    pub name: Symbol,
+    pub ty: TypeExpr,
+    pub span: Span,
+}
+
+/// `const NAME: Type = value;`
+#[derive(Debug, Clone)]
+pub struct ConstItem {
+    pub vis: Visibility,
+    pub name: Symbol,
+    pub ty: TypeExpr,
+    pub value: Expr,
+    pub span: Span,
+}
+
+/// `type Name = Type;`, another name for the same type, or
+/// `newtype Name = Type;`, a distinct type represented like `Type`
+#[derive(Debug, Clone)]
+pub struct TypeAlias {
+    pub vis: Visibility,
+    pub name: Symbol,
+    pub ty: TypeExpr,
+    pub newtype: bool,
+    pub span: Span,
+}
+
+/// Field-less enum with optional explicit discriminants
+#[derive(Debug, Clone)]
+pub struct EnumDef {
+    pub vis: Visibility,
+    pub name: Symbol,
+    pub variants: Vec<Variant>,
+    pub attrs: Vec<Attribute>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct Variant {
+    pub name: Symbol,
+    pub discriminant: Option<Expr>,
+    pub span: Span,
+}
+
+/// `#[name]` or `#[name(arg, ...)]`, or `#![...]` at the top of a file.
+/// An argument is a name, or a name with an integer such as `align(8)`
+/// kept as written
+#[derive(Debug, Clone)]
+pub struct Attribute {
+    pub name: String,
+    pub args: Vec<String>,
+    pub span: Span,
+}
+
+/// Whether `attrs` hold `#[allow(lint)]`
+pub fn allows(attrs: &[Attribute], lint: &str) -> bool {
+    attrs
+        .iter()
+        .any(|attr| attr.name == "allow" && attr.args.iter().any(|arg| arg == lint))
+}
+
+/// Struct with named fields
+#[derive(Debug, Clone)]
+pub struct StructDef {
+    pub vis: Visibility,
+    pub name: Symbol,
+    pub fields: Vec<FieldDef>,
+    pub attrs: Vec<Attribute>,
+    pub span: Span,
+}
+
+impl StructDef {
+    /// Names listed in `#[derive(...)]` attributes, with the attribute's span
+    pub fn derives(&self) -> impl Iterator<Item = (&str, Span)> + '_ {
+        self.attrs
+            .iter()
+            .filter(|a| a.name == "derive")
+            .flat_map(|a| a.args.iter().map(move |arg| (arg.as_str(), a.span)))
+    }
+
+    /// Hints listed in `#[repr(...)]` attributes, such as `C` or `align(8)`
+    pub fn repr_hints(&self) -> impl Iterator<Item = &str> + '_ {
+        self.attrs
+            .iter()
+            .filter(|a| a.name == "repr")
+            .flat_map(|a| a.args.iter().map(String::as_str))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FieldDef {
+    pub vis: Visibility,
+    pub name: Symbol,
+    pub ty: TypeExpr,
+    pub span: Span,
+}
+
+/// Types as written in source
+///
+/// Unlike expressions, a type keeps its children (`&T`, `*T`, `[T; N]`,
+/// `[T]`) boxed rather than in [`Exprs`]: types are few and shallow, and
+/// are read where no arena is at hand. The length of an array type is a
+/// root expression, owned like the arguments of a call.
+#[derive(Debug, Clone)]
+pub enum TypeExpr {
+    Named(Symbol, Span),
+    /// `Name<T, ...>`, optionally refined with bounds (`Belief<bool, confidence >= 0.9>`)
+    Generic {
+        name: Symbol,
+        args: Vec<TypeExpr>,
+        bounds: Vec<Bound>,
+        span: Span,
+    },
+    Ref {
+        mutable: bool,
+        inner: Box<TypeExpr>,
+    },
+    /// `*const T` or `*mut T`
+    Ptr {
+        mutable: bool,
+        inner: Box<TypeExpr>,
+    },
+    Array {
+        elem: Box<TypeExpr>,
+        len: Box<Expr>,
+    },
+    /// `[T]`, the elements of an array seen through a reference
+    Slice {
+        elem: Box<TypeExpr>,
+    },
+    Unit,
+    /// `!`, returned by functions that never return
+    Never(Span),
+}
+
+impl TypeExpr {
+    /// Refinement bounds written on this type
+    pub fn bounds(&self) -> &[Bound] {
+        match self {
+            TypeExpr::Generic { bounds, .. } => bounds,
+            _ => &[],
+        }
+    }
+}
+
+/// A refinement on a type argument list: `confidence >= 0.95`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bound {
+    pub property: String,
+    /// `Ge` or `Le`
+    pub op: BinaryOp,
+    pub value: f64,
+    pub span: Span,
+}
+
+impl fmt::Display for Bound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.property, self.op.symbol(), self.value)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Block {
+    pub stmts: Vec<Stmt>,
+    /// Trailing expression without a semicolon (the block's value)
+    pub tail: Option<ExprId>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum Stmt {
+    Let(LetStmt),
+    Const(ConstItem),
+    /// Expression evaluated for its effect (`expr;` or a block-like expression)
+    Expr(Expr),
+    /// `defer expr;`: evaluated when the enclosing block exits, on every
+    /// path out of it, after the expressions deferred later
+    Defer(Expr),
+}
+
+impl Stmt {
+    pub fn span(&self) -> Span {
+        match self {
+            Stmt::Let(stmt) => stmt.span,
+            Stmt::Const(item) => item.span,
+            Stmt::Expr(expr) | Stmt::Defer(expr) => expr.span,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LetStmt {
+    pub name: Symbol,
+    pub mutable: bool,
+    pub ty: Option<TypeExpr>,
+    pub value: Option<Expr>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+impl Expr {
+    pub fn new(kind: ExprKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+
+    /// Block-like expressions may appear as statements without a semicolon
+    pub fn is_block_like(&self) -> bool {
+        matches!(
+            self.kind,
+            ExprKind::If(..)
+                | ExprKind::While(..)
+                | ExprKind::For(..)
+                | ExprKind::Match(..)
+                | ExprKind::Block(_)
+                | ExprKind::Arena(..)
+                | ExprKind::Unsafe(_)
+        )
+    }
+
+    /// Split the iterable of a `for` loop into its source and the
+    /// [`Adapter`]s applied to it, innermost first
+    pub fn iteration<'a>(&'a self, exprs: &'a Exprs) -> (&'a Expr, Vec<Adapter<'a>>) {
+        let mut adapters = Vec::new();
+        let mut source = self;
+        while let ExprKind::MethodCall(receiver, method, args) = &source.kind {
+            let kind = match method.as_str() {
+                "map" => AdapterKind::Map,
+                "filter" => AdapterKind::Filter,
+                "take" => AdapterKind::Take,
+                _ => break,
+            };
+            adapters.push(Adapter {
+                kind,
+                args,
+                span: source.span,
+            });
+            source = &exprs[*receiver];
+        }
+        adapters.reverse();
+        (source, adapters)
+    }
+}
+
+/// Index of an expression in its program's [`Exprs`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ExprId(u32);
+
+/// The arena a program's nested expressions are allocated in
+///
+/// An expression inside another (an operand, a callee, a condition, the
+/// tail of a block) is pushed here and referred to by its [`ExprId`] instead
+/// of being boxed: parsing grows one buffer instead of allocating each
+/// node, and a whole tree is dropped at once with its program.
+#[derive(Debug, Clone, Default)]
+pub struct Exprs(Vec<Expr>);
+
+impl Exprs {
+    pub fn alloc(&mut self, expr: Expr) -> ExprId {
+        let id = u32::try_from(self.0.len()).expect("fewer than 2^32 expressions");
+        self.0.push(expr);
+        ExprId(id)
+    }
+
+    /// Move expression `id` out to rebuild it, leaving `false` in its place
+    /// until the result is stored back
+    pub fn take(&mut self, id: ExprId) -> Expr {
+        let placeholder = Expr::new(ExprKind::Bool(false), Span::default());
+        std::mem::replace(&mut self[id], placeholder)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl Index<ExprId> for Exprs {
+    type Output = Expr;
+
+    fn index(&self, id: ExprId) -> &Expr {
+        &self.0[id.0 as usize]
+    }
+}
+
+impl IndexMut<ExprId> for Exprs {
+    fn index_mut(&mut self, id: ExprId) -> &mut Expr {
+        &mut self.0[id.0 as usize]
+    }
+}
+
+/// `.map(f)`, `.filter(p)` or `.take(n)` on the iterable of a `for` loop
+///
+/// Adapters are fused into the loop: each item runs through them in turn
+/// as it is produced, so no intermediate array is ever allocated.
+#[derive(Debug, Clone, Copy)]
+pub struct Adapter<'a> {
+    pub kind: AdapterKind,
+    pub args: &'a [Expr],
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdapterKind {
+    /// Replace each item with `f(item)`
+    Map,
+    /// Keep the items for which `p(item)` is `true`
+    Filter,
+    /// Stop after `n` items
+    Take,
+}
+
+impl AdapterKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            AdapterKind::Map => "map",
+            AdapterKind::Filter => "filter",
+            AdapterKind::Take => "take",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ExprKind {
+    /// A number, with the type of its suffix (`5u8`)
+    Int(i64, Option<Numeric>),
+    Float(f64, Option<Numeric>),
+    Str(Symbol),
+    Bool(bool),
+    /// `name` or `Enum::Variant`
+    Path(Vec<Symbol>),
+    Unary(UnaryOp, ExprId),
+    Binary(BinaryOp, ExprId, ExprId),
+    /// `value as u8`: converts between numeric types, and from `bool` and
+    /// enums to integers
+    Cast(ExprId, TypeExpr),
+    Assign(ExprId, ExprId),
+    Call(ExprId, Vec<Expr>),
+    Index(ExprId, ExprId),
+    /// `base.field`
+    Field(ExprId, Symbol),
+    /// `receiver.method(args)`
+    MethodCall(ExprId, Symbol, Vec<Expr>),
+    /// `Name { field: value, ... }`
+    StructLit(Symbol, Vec<FieldInit>),
+    /// `~Normal(mean, std_dev)`, `~Bernoulli(p)`
+    Distribution(Symbol, Vec<Expr>),
+    /// `[a, b, c]`
+    Array(Vec<Expr>),
+    /// `[value; len]`
+    ArrayRepeat(ExprId, ExprId),
+    If(ExprId, Block, Option<ExprId>),
+    While(ExprId, Block),
+    /// `for name in iterable { ... }` over an array, a value with
+    /// `has_next` and `next` methods, or either through [`Adapter`]s
+    For(Symbol, ExprId, Block),
+    /// `match scrutinee { pattern if guard => value, ... }`: the value of
+    /// the first arm whose pattern matches and whose guard holds
+    Match(ExprId, Vec<Arm>),
+    /// `start..end`, or `start..=end` when inclusive: the integers from
+    /// `start` up to `end`, to iterate over or to slice with; either end
+    /// may be left out
+    Range(Option<ExprId>, Option<ExprId>, bool),
+    Block(Block),
+    /// `arena { ... }` or `arena name { ... }`: values allocated inside are
+    /// freed when it ends; `name` is a handle to the arena. `arena in buffer
+    /// { ... }` takes its memory from the allocator `buffer` instead of
+    /// bumping through chunks of its own
+    Arena(Option<Symbol>, Option<ExprId>, Block),
+    /// `unsafe { ... }`: allows calling C functions and dereferencing and
+    /// offsetting raw pointers
+    Unsafe(Block),
+    Return(Option<ExprId>),
+    /// `become f(args)`: a call in tail position that replaces the caller's
+    /// frame instead of growing the stack; always an `ExprKind::Call`
+    Become(ExprId),
+    /// `asm!("template", in(reg) a, out(reg) b)`: instructions passed to the
+    /// backend as written, with `{0}`, `{1}`... standing for the operands
+    Asm(String, Vec<AsmOperand>),
+}
+
+/// `pattern if guard => value` in a `match`
+#[derive(Debug, Clone)]
+pub struct Arm {
+    pub pattern: Pattern,
+    /// Checked once the pattern matches, with its bindings in scope
+    pub guard: Option<ExprId>,
+    pub value: ExprId,
+    pub span: Span,
+}
+
+/// A pattern of a `match` arm
+///
+/// Like [`TypeExpr`], a pattern keeps its children (of `Some(p)` and the
+/// ends of a range) boxed rather than in an arena.
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    pub kind: PatternKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum PatternKind {
+    /// `_`
+    Wildcard,
+    /// A name bound to the value matched
+    Binding(Symbol),
+    /// An integer, possibly negative, with the type of its suffix
+    Int(i64, Option<Numeric>),
+    Bool(bool),
+    Str(Symbol),
+    /// `Enum::Variant`
+    Variant(Symbol, Symbol),
+    /// `Some(pattern)`
+    Some(Box<Pattern>),
+    None,
+    /// `a | b`: matches what any of the alternatives matches
+    Or(Vec<Pattern>),
+    /// `start..end`, or `start..=end` when inclusive, where the ends are
+    /// [`PatternKind::Int`] and either may be left out
+    Range(Option<Box<Pattern>>, Option<Box<Pattern>>, bool),
+}
+
+impl Pattern {
+    /// The names the pattern binds, in order; every alternative of an
+    /// or-pattern binds the same names, so those of the first are given
+    pub fn bindings(&self) -> Vec<(Symbol, Span)> {
+        let mut names = Vec::new();
+        self.collect_bindings(&mut names);
+        names
+    }
+
+    fn collect_bindings(&self, names: &mut Vec<(Symbol, Span)>) {
+        match &self.kind {
+            PatternKind::Binding(name) => names.push((*name, self.span)),
+            PatternKind::Some(inner) => inner.collect_bindings(names),
+            PatternKind::Or(alternatives) => alternatives[0].collect_bindings(names),
+            _ => {}
+        }
+    }
+
+    /// Whether the pattern matches every value
+    pub fn is_irrefutable(&self) -> bool {
+        match &self.kind {
+            PatternKind::Wildcard | PatternKind::Binding(_) => true,
+            PatternKind::Or(alternatives) => alternatives.iter().any(Pattern::is_irrefutable),
+            _ => false,
+        }
+    }
+
+    /// The smallest and largest integers an integer or range pattern
+    /// matches, or `None` at an open end of a range
+    pub fn interval(&self) -> Option<(Option<i128>, Option<i128>)> {
+        let int = |end: &Option<Box<Pattern>>| match end.as_deref()?.kind {
+            PatternKind::Int(n, _) => Some(i128::from(n)),
+            _ => None,
+        };
+        match &self.kind {
+            PatternKind::Int(n, _) => Some((Some(i128::from(*n)), Some(i128::from(*n)))),
+            PatternKind::Range(start, end, inclusive) => {
+                let last = int(end).map(|end| if *inclusive { end } else { end - 1 });
+                Some((int(start), last))
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether an integer or range pattern matches `n`
+    pub fn matches_int(&self, n: i64) -> bool {
+        let n = i128::from(n);
+        self.interval().is_some_and(|(first, last)| {
+            first.is_none_or(|first| first <= n) && last.is_none_or(|last| n <= last)
+        })
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            PatternKind::Wildcard => write!(f, "_"),
+            PatternKind::Binding(name) => write!(f, "{}", name),
+            PatternKind::Int(n, suffix) => write!(f, "{}{}", n, suffix.map_or("", Numeric::name)),
+            PatternKind::Bool(b) => write!(f, "{}", b),
+            PatternKind::Str(s) => write!(f, "\"{}\"", s),
+            PatternKind::Variant(ty, variant) => write!(f, "{}::{}", ty, variant),
+            PatternKind::Some(inner) => write!(f, "Some({})", inner),
+            PatternKind::None => write!(f, "None"),
+            PatternKind::Or(alternatives) => {
+                for (i, alternative) in alternatives.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " | ")?;
+                    }
+                    write!(f, "{}", alternative)?;
+                }
+                Ok(())
+            }
+            PatternKind::Range(start, end, inclusive) => {
+                if let Some(start) = start {
+                    write!(f, "{}", start)?;
+                }
+                write!(f, "{}", if *inclusive { "..=" } else { ".." })?;
+                if let Some(end) = end {
+                    write!(f, "{}", end)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// An operand of `asm!`, such as `inout(reg) x`
+#[derive(Debug, Clone)]
+pub struct AsmOperand {
+    pub dir: AsmDir,
+    /// Register class: `reg` for integers and booleans, `freg` for floats
+    pub class: String,
+    pub expr: Expr,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AsmDir {
+    /// Read by the instructions
+    In,
+    /// Written by the instructions; must be a mutable place
+    Out,
+    /// Read and written; must be a mutable place
+    InOut,
+}
+
+impl AsmDir {
+    pub fn keyword(self) -> &'static str {
+        match self {
+            AsmDir::In => "in",
+            AsmDir::Out => "out",
+            AsmDir::InOut => "inout",
+        }
+    }
+
+    /// Whether the instructions write the operand
+    pub fn writes(self) -> bool {
+        self != AsmDir::In
+    }
+}
+
+/// A path as written, `Enum::Variant`
+pub fn join_path(segments: &[Symbol]) -> String {
+    let mut path = String::new();
+    for (i, segment) in segments.iter().enumerate() {
+        if i > 0 {
+            path.push_str("::");
+        }
+        path.push_str(segment);
+    }
+    path
+}
+
+#[derive(Debug, Clone)]
+pub struct FieldInit {
+    pub name: Symbol,
+    pub value: Expr,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+    Ref,
+    RefMut,
+    /// `*ptr`, of raw pointers only
+    Deref,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+}
+
+impl BinaryOp {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Rem => "%",
+            BinaryOp::Eq => "==",
+            BinaryOp::Ne => "!=",
+            BinaryOp::Lt => "<",
+            BinaryOp::Le => "<=",
+            BinaryOp::Gt => ">",
+            BinaryOp::Ge => ">=",
+            BinaryOp::And => "&&",
+            BinaryOp::Or => "||",
+        }
+    }
+
+    /// How tightly the operator binds, from 1 (`||`) to 5 (`*`, `/`, `%`);
+    /// all binary operators are left-associative
+    pub fn precedence(self) -> u8 {
+        match self {
+            BinaryOp::Or => 1,
+            BinaryOp::And => 2,
+            BinaryOp::Eq
+            | BinaryOp::Ne
+            | BinaryOp::Lt
+            | BinaryOp::Le
+            | BinaryOp::Gt
+            | BinaryOp::Ge => 3,
+            BinaryOp::Add | BinaryOp::Sub => 4,
+            BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem => 5,
+        }
+    }
+
+    pub fn is_comparison(self) -> bool {
+        matches!(
+            self,
+            BinaryOp::Eq | BinaryOp::Ne | BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge
+        )
+    }
+
+    pub fn is_logical(self) -> bool {
+        matches!(self, BinaryOp::And | BinaryOp::Or)
+    }
+}
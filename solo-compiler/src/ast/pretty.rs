@@ -0,0 +1,824 @@
+//! Printing the AST back as Solo source
+//!
+//! A program implements [`Display`](fmt::Display), and any other node
+//! prints through [`Exprs::display`] with the arena of its program, as
+//! source that parses back to the same tree, laid out one statement per
+//! line with four-space indentation. Comments are not in the AST and so
+//! are not printed; the [CST](crate::cst) keeps them.
+//!
+//! Parentheses are added only where precedence needs them, and braces
+//! wherever the parser would otherwise read the code differently: a
+//! struct literal in an `if` or `while` condition, a bare `return` used as
+//! a value, or a block-like statement followed by something that would
+//! continue it (`if a { f() } -1;`).
+
+use std::fmt;
+
+use super::*;
+
+/// Precedence of assignments, ranges and `return`, the loosest
+/// expressions
+const ASSIGN: u8 = 0;
+/// Precedence of `as` casts
+const CAST: u8 = 6;
+/// Precedence of unary operators
+const UNARY: u8 = 7;
+/// Precedence of calls, indexing, field access and primary expressions
+const POSTFIX: u8 = 8;
+
+struct Printer<'a> {
+    exprs: &'a Exprs,
+    out: String,
+    indent: usize,
+    /// Inside an `if` or `while` condition, where a struct literal needs
+    /// parentheses
+    no_struct_literal: bool,
+}
+
+impl<'a> Printer<'a> {
+    fn print(exprs: &'a Exprs, node: impl FnOnce(&mut Self)) -> String {
+        let mut printer = Self {
+            exprs,
+            out: String::new(),
+            indent: 0,
+            no_struct_literal: false,
+        };
+        node(&mut printer);
+        printer.out
+    }
+
+    fn at(&self, id: ExprId) -> &'a Expr {
+        &self.exprs[id]
+    }
+
+    fn write(&mut self, text: &str) {
+        self.out.push_str(text);
+    }
+
+    fn newline(&mut self) {
+        self.out.push('\n');
+        self.out.push_str(&"    ".repeat(self.indent));
+    }
+
+    fn program(&mut self, program: &Program) {
+        for attr in &program.attrs {
+            self.attribute(attr, true);
+            self.newline();
+        }
+        let mut library = program.library;
+        for (i, item) in program.items.iter().enumerate() {
+            if i > 0 || !program.attrs.is_empty() {
+                self.newline();
+            }
+            if library.is_some() && program.in_library(item.span()) {
+                library = None;
+                self.write("#![library]");
+                self.newline();
+                self.newline();
+            }
+            self.item(item);
+            self.newline();
+        }
+    }
+
+    fn attribute(&mut self, attr: &Attribute, inner: bool) {
+        self.write(if inner { "#![" } else { "#[" });
+        self.write(&attr.name);
+        if !attr.args.is_empty() {
+            self.write(&format!("({})", attr.args.join(", ")));
+        }
+        self.write("]");
+    }
+
+    fn outer_attributes(&mut self, attrs: &[Attribute]) {
+        for attr in attrs {
+            self.attribute(attr, false);
+            self.newline();
+        }
+    }
+
+    fn item(&mut self, item: &Item) {
+        match item {
+            Item::Function(function) => self.function(function),
+            Item::Const(item) => self.constant(item),
+            Item::Enum(def) => self.enumeration(def),
+            Item::Struct(def) => self.structure(def),
+            Item::Impl(block) => self.implementation(block),
+            Item::Extern(block) => self.extern_block(block),
+            Item::TypeAlias(alias) => self.type_alias(alias),
+        }
+    }
+
+    fn visibility(&mut self, vis: Visibility) {
+        match vis {
+            Visibility::Private { .. } => {}
+            Visibility::Crate { .. } => self.write("pub(crate) "),
+            Visibility::Public { .. } => self.write("pub "),
+        }
+    }
+
+    fn function(&mut self, function: &Function) {
+        self.outer_attributes(&function.attrs);
+        self.visibility(function.vis);
+        if function.exported {
+            self.write("extern \"C\" ");
+        }
+        if function.is_const {
+            self.write("const ");
+        }
+        self.write(&format!("fn {}(", function.name));
+        let receiver = function.receiver.map(|receiver| match receiver {
+            Receiver::Value => "self",
+            Receiver::MutValue => "mut self",
+            Receiver::Ref => "&self",
+            Receiver::RefMut => "&mut self",
+        });
+        if let Some(receiver) = receiver {
+            self.write(receiver);
+            if !function.params.is_empty() {
+                self.write(", ");
+            }
+        }
+        self.signature(&function.params, &function.return_type);
+        self.write(" ");
+        self.block(&function.body);
+    }
+
+    /// Parameters after the `(`, and the return type
+    fn signature(&mut self, params: &[Param], return_type: &Option<TypeExpr>) {
+        for (i, param) in params.iter().enumerate() {
+            if i > 0 {
+                self.write(", ");
+            }
+            self.write(&format!("{}: ", param.name));
+            self.ty(&param.ty);
+        }
+        self.write(")");
+        if let Some(ty) = return_type {
+            self.write(" -> ");
+            self.ty(ty);
+        }
+    }
+
+    fn type_alias(&mut self, alias: &TypeAlias) {
+        self.visibility(alias.vis);
+        let keyword = if alias.newtype { "newtype" } else { "type" };
+        self.write(&format!("{} {} = ", keyword, alias.name));
+        self.ty(&alias.ty);
+        self.write(";");
+    }
+
+    fn constant(&mut self, item: &ConstItem) {
+        self.visibility(item.vis);
+        self.write(&format!("const {}: ", item.name));
+        self.ty(&item.ty);
+        self.write(" = ");
+        self.expr(&item.value);
+        self.write(";");
+    }
+
+    fn enumeration(&mut self, def: &EnumDef) {
+        self.outer_attributes(&def.attrs);
+        self.visibility(def.vis);
+        self.write(&format!("enum {} {{", def.name));
+        self.indent += 1;
+        for variant in &def.variants {
+            self.newline();
+            self.write(&variant.name);
+            if let Some(discriminant) = &variant.discriminant {
+                self.write(" = ");
+                self.expr(discriminant);
+            }
+            self.write(",");
+        }
+        self.close(def.variants.is_empty());
+    }
+
+    fn structure(&mut self, def: &StructDef) {
+        self.outer_attributes(&def.attrs);
+        self.visibility(def.vis);
+        self.write(&format!("struct {} {{", def.name));
+        self.indent += 1;
+        for field in &def.fields {
+            self.newline();
+            self.visibility(field.vis);
+            self.write(&format!("{}: ", field.name));
+            self.ty(&field.ty);
+            self.write(",");
+        }
+        self.close(def.fields.is_empty());
+    }
+
+    fn implementation(&mut self, block: &ImplBlock) {
+        self.write(&format!("impl {} {{", block.self_ty));
+        self.indent += 1;
+        for (i, method) in block.methods.iter().enumerate() {
+            if i > 0 {
+                self.out.push('\n');
+            }
+            self.newline();
+            self.function(method);
+        }
+        self.close(block.methods.is_empty());
+    }
+
+    fn extern_block(&mut self, block: &ExternBlock) {
+        self.outer_attributes(&block.attrs);
+        self.write("extern \"C\" {");
+        self.indent += 1;
+        for function in &block.functions {
+            self.newline();
+            self.write(&format!("fn {}(", function.name));
+            self.signature(&function.params, &function.return_type);
+            self.write(";");
+        }
+        self.close(block.functions.is_empty());
+    }
+
+    /// The `}` of a braced list whose `{` raised the indentation
+    fn close(&mut self, empty: bool) {
+        self.indent -= 1;
+        if !empty {
+            self.newline();
+        }
+        self.write("}");
+    }
+
+    fn ty(&mut self, ty: &TypeExpr) {
+        match ty {
+            TypeExpr::Named(name, _) => self.write(name),
+            TypeExpr::Generic {
+                name, args, bounds, ..
+            } => {
+                self.write(&format!("{}<", name));
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        self.write(", ");
+                    }
+                    self.ty(arg);
+                }
+                for (i, bound) in bounds.iter().enumerate() {
+                    if i > 0 || !args.is_empty() {
+                        self.write(", ");
+                    }
+                    self.write(&bound.to_string());
+                }
+                self.write(">");
+            }
+            TypeExpr::Ref { mutable, inner } => {
+                self.write(if *mutable { "&mut " } else { "&" });
+                // `&&` is a single token
+                if matches!(**inner, TypeExpr::Ref { .. }) && !mutable {
+                    self.write(" ");
+                }
+                self.ty(inner);
+            }
+            TypeExpr::Ptr { mutable, inner } => {
+                self.write(if *mutable { "*mut " } else { "*const " });
+                self.ty(inner);
+            }
+            TypeExpr::Array { elem, len } => {
+                self.write("[");
+                self.ty(elem);
+                self.write("; ");
+                self.delimited(len);
+                self.write("]");
+            }
+            TypeExpr::Slice { elem } => {
+                self.write("[");
+                self.ty(elem);
+                self.write("]");
+            }
+            TypeExpr::Unit => self.write("()"),
+            TypeExpr::Never(_) => self.write("!"),
+        }
+    }
+
+    fn block(&mut self, block: &Block) {
+        let restriction = std::mem::replace(&mut self.no_struct_literal, false);
+        let tail = block.tail.map(|tail| self.at(tail));
+        match (block.stmts.as_slice(), tail) {
+            ([], None) => self.write("{}"),
+            ([], Some(tail)) if !tail.is_block_like() => {
+                self.write("{ ");
+                self.statement_expr(tail);
+                self.write(" }");
+            }
+            (stmts, tail) => {
+                self.write("{");
+                self.indent += 1;
+                for (i, stmt) in stmts.iter().enumerate() {
+                    self.newline();
+                    let next = match stmts.get(i + 1) {
+                        Some(Stmt::Expr(next)) => Some(next),
+                        Some(_) => None,
+                        None => tail,
+                    };
+                    // A block-like expression ends its statement unless
+                    // what follows would continue it, or it would become
+                    // the tail
+                    let last = i + 1 == stmts.len() && tail.is_none();
+                    let ends = !last && !next.is_some_and(|next| continues(self.exprs, next));
+                    self.stmt(stmt, ends);
+                }
+                if let Some(tail) = tail {
+                    self.newline();
+                    self.statement_expr(tail);
+                }
+                self.indent -= 1;
+                self.newline();
+                self.write("}");
+            }
+        }
+        self.no_struct_literal = restriction;
+    }
+
+    /// A statement; `ends` when a block-like expression needs no `;`
+    fn stmt(&mut self, stmt: &Stmt, ends: bool) {
+        match stmt {
+            Stmt::Let(stmt) => {
+                self.write("let ");
+                if stmt.mutable {
+                    self.write("mut ");
+                }
+                self.write(&stmt.name);
+                if let Some(ty) = &stmt.ty {
+                    self.write(": ");
+                    self.ty(ty);
+                }
+                if let Some(value) = &stmt.value {
+                    self.write(" = ");
+                    self.expr(value);
+                }
+                self.write(";");
+            }
+            Stmt::Const(item) => self.constant(item),
+            Stmt::Expr(expr) => {
+                self.statement_expr(expr);
+                if !(expr.is_block_like() && ends) {
+                    self.write(";");
+                }
+            }
+            Stmt::Defer(expr) => {
+                self.write("defer ");
+                self.statement_expr(expr);
+                self.write(";");
+            }
+        }
+    }
+
+    /// An expression followed by `;` or `}`, where a bare `return` may
+    /// stand
+    fn statement_expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Return(None) => self.write("return"),
+            _ => self.expr(expr),
+        }
+    }
+
+    /// An expression between delimiters, where anything goes
+    fn delimited(&mut self, expr: &Expr) {
+        let restriction = std::mem::replace(&mut self.no_struct_literal, false);
+        self.expr(expr);
+        self.no_struct_literal = restriction;
+    }
+
+    fn delimited_list(&mut self, exprs: &[Expr]) {
+        for (i, expr) in exprs.iter().enumerate() {
+            if i > 0 {
+                self.write(", ");
+            }
+            self.delimited(expr);
+        }
+    }
+
+    /// `expr` as an operand binding at least as tightly as `precedence`
+    fn operand(&mut self, expr: &Expr, precedence: u8) {
+        if self::precedence(expr) < precedence {
+            self.write("(");
+            self.delimited(expr);
+            self.write(")");
+        } else {
+            self.expr(expr);
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Int(i64::MIN, _) => self.write("(-9223372036854775807 - 1)"),
+            ExprKind::Int(n, suffix) => {
+                self.write(&format!("{}{}", n, suffix.map_or("", Numeric::name)))
+            }
+            ExprKind::Float(x, Some(suffix)) if x.is_nan() => {
+                self.write(&format!("(0.0{0} / 0.0{0})", suffix))
+            }
+            ExprKind::Float(x, suffix) => self.write(&format!(
+                "{}{}",
+                float(*x),
+                suffix.map_or("", Numeric::name)
+            )),
+            ExprKind::Str(s) => self.write(&format!("\"{}\"", s)),
+            ExprKind::Bool(b) => self.write(&b.to_string()),
+            ExprKind::Path(segments) => self.write(&join_path(segments)),
+            ExprKind::Unary(op, operand) => {
+                self.write(match op {
+                    UnaryOp::Neg => "-",
+                    UnaryOp::Not => "!",
+                    UnaryOp::Ref => "&",
+                    UnaryOp::RefMut => "&mut ",
+                    UnaryOp::Deref => "*",
+                });
+                // `&&` is a single token
+                let operand = self.at(*operand);
+                let nested_ref = matches!(
+                    operand.kind,
+                    ExprKind::Unary(UnaryOp::Ref | UnaryOp::RefMut, _)
+                );
+                if *op == UnaryOp::Ref && nested_ref {
+                    self.write(" ");
+                }
+                self.operand(operand, UNARY);
+            }
+            ExprKind::Binary(op, left, right) => {
+                self.operand(self.at(*left), op.precedence());
+                self.write(&format!(" {} ", op.symbol()));
+                self.operand(self.at(*right), op.precedence() + 1);
+            }
+            ExprKind::Range(start, end, inclusive) => {
+                if let Some(start) = start {
+                    self.operand(self.at(*start), ASSIGN + 1);
+                }
+                self.write(if *inclusive { "..=" } else { ".." });
+                if let Some(end) = end {
+                    self.operand(self.at(*end), ASSIGN + 1);
+                }
+            }
+            ExprKind::Cast(value, ty) => {
+                self.operand(self.at(*value), CAST);
+                self.write(" as ");
+                self.ty(ty);
+            }
+            ExprKind::Assign(target, value) => {
+                self.operand(self.at(*target), ASSIGN + 1);
+                self.write(" = ");
+                self.operand(self.at(*value), ASSIGN);
+            }
+            ExprKind::Call(callee, args) => {
+                self.operand(self.at(*callee), POSTFIX);
+                self.write("(");
+                self.delimited_list(args);
+                self.write(")");
+            }
+            ExprKind::Index(base, index) => {
+                self.operand(self.at(*base), POSTFIX);
+                self.write("[");
+                self.delimited(self.at(*index));
+                self.write("]");
+            }
+            ExprKind::Field(base, name) => {
+                self.operand(self.at(*base), POSTFIX);
+                self.write(&format!(".{}", name));
+            }
+            ExprKind::MethodCall(receiver, method, args) => {
+                self.operand(self.at(*receiver), POSTFIX);
+                self.write(&format!(".{}(", method));
+                self.delimited_list(args);
+                self.write(")");
+            }
+            ExprKind::StructLit(name, fields) => {
+                let parenthesized = self.no_struct_literal;
+                if parenthesized {
+                    self.write("(");
+                }
+                self.write(&format!("{} {{", name));
+                for (i, field) in fields.iter().enumerate() {
+                    self.write(if i > 0 { ", " } else { " " });
+                    self.write(&format!("{}: ", field.name));
+                    self.delimited(&field.value);
+                }
+                self.write(if fields.is_empty() { "}" } else { " }" });
+                if parenthesized {
+                    self.write(")");
+                }
+            }
+            ExprKind::Distribution(name, args) => {
+                self.write(&format!("~{}(", name));
+                self.delimited_list(args);
+                self.write(")");
+            }
+            ExprKind::Array(elems) => {
+                self.write("[");
+                self.delimited_list(elems);
+                self.write("]");
+            }
+            ExprKind::ArrayRepeat(value, len) => {
+                self.write("[");
+                self.delimited(self.at(*value));
+                self.write("; ");
+                self.delimited(self.at(*len));
+                self.write("]");
+            }
+            ExprKind::If(cond, then, otherwise) => {
+                self.write("if ");
+                self.condition(self.at(*cond));
+                self.write(" ");
+                self.block(then);
+                if let Some(otherwise) = otherwise {
+                    let otherwise = self.at(*otherwise);
+                    self.write(" else ");
+                    match &otherwise.kind {
+                        ExprKind::If(..) => self.expr(otherwise),
+                        ExprKind::Block(block) => self.block(block),
+                        _ => {
+                            self.write("{ ");
+                            self.statement_expr(otherwise);
+                            self.write(" }");
+                        }
+                    }
+                }
+            }
+            ExprKind::While(cond, body) => {
+                self.write("while ");
+                self.condition(self.at(*cond));
+                self.write(" ");
+                self.block(body);
+            }
+            ExprKind::For(name, iterable, body) => {
+                self.write(&format!("for {} in ", name));
+                self.condition(self.at(*iterable));
+                self.write(" ");
+                self.block(body);
+            }
+            ExprKind::Match(scrutinee, arms) => {
+                self.write("match ");
+                self.condition(self.at(*scrutinee));
+                self.write(" {");
+                self.indent += 1;
+                for arm in arms {
+                    self.newline();
+                    self.write(&arm.pattern.to_string());
+                    if let Some(guard) = arm.guard {
+                        self.write(" if ");
+                        self.delimited(self.at(guard));
+                    }
+                    self.write(" => ");
+                    self.delimited(self.at(arm.value));
+                    self.write(",");
+                }
+                self.indent -= 1;
+                if !arms.is_empty() {
+                    self.newline();
+                }
+                self.write("}");
+            }
+            ExprKind::Block(block) => self.block(block),
+            ExprKind::Arena(name, allocator, block) => {
+                self.write("arena ");
+                if let Some(name) = name {
+                    self.write(&format!("{} ", name));
+                }
+                if let Some(allocator) = allocator {
+                    self.write("in ");
+                    self.expr(self.at(*allocator));
+                    self.write(" ");
+                }
+                self.block(block);
+            }
+            ExprKind::Unsafe(block) => {
+                self.write("unsafe ");
+                self.block(block);
+            }
+            // Only `;` or `}` may follow a bare `return`
+            ExprKind::Return(None) => self.write("{ return }"),
+            ExprKind::Return(Some(value)) => {
+                self.write("return ");
+                self.expr(self.at(*value));
+            }
+            ExprKind::Become(call) => {
+                self.write("become ");
+                self.expr(self.at(*call));
+            }
+            ExprKind::Asm(template, operands) => {
+                self.write(&format!("asm!(\"{}\"", template));
+                for operand in operands {
+                    self.write(&format!(", {}({}) ", operand.dir.keyword(), operand.class));
+                    self.delimited(&operand.expr);
+                }
+                self.write(")");
+            }
+        }
+    }
+
+    fn condition(&mut self, cond: &Expr) {
+        let restriction = std::mem::replace(&mut self.no_struct_literal, true);
+        self.expr(cond);
+        self.no_struct_literal = restriction;
+    }
+}
+
+fn precedence(expr: &Expr) -> u8 {
+    match &expr.kind {
+        ExprKind::Assign(..)
+        | ExprKind::Range(..)
+        | ExprKind::Return(Some(_))
+        | ExprKind::Become(_) => ASSIGN,
+        ExprKind::Binary(op, ..) => op.precedence(),
+        ExprKind::Cast(..) => CAST,
+        ExprKind::Unary(..) => UNARY,
+        ExprKind::Int(n, _) if *n < 0 && *n != i64::MIN => UNARY,
+        ExprKind::Float(x, _) if x.is_sign_negative() && !x.is_nan() => UNARY,
+        _ => POSTFIX,
+    }
+}
+
+/// Whether `expr`, printed after a block-like expression, would be read
+/// as continuing it: with a binary `-`, a call or indexing
+fn continues(exprs: &Exprs, expr: &Expr) -> bool {
+    let (base, needed) = match &expr.kind {
+        ExprKind::Unary(UnaryOp::Neg, _) | ExprKind::Array(_) | ExprKind::ArrayRepeat(..) => {
+            return true
+        }
+        ExprKind::Int(..) | ExprKind::Float(..) => return precedence(expr) == UNARY,
+        ExprKind::Binary(op, left, _) => (left, op.precedence()),
+        ExprKind::Cast(value, _) => (value, CAST),
+        ExprKind::Assign(target, _) | ExprKind::Range(Some(target), ..) => (target, ASSIGN + 1),
+        ExprKind::Range(None, ..) => return true,
+        ExprKind::Call(base, _)
+        | ExprKind::Index(base, _)
+        | ExprKind::Field(base, _)
+        | ExprKind::MethodCall(base, ..) => (base, POSTFIX),
+        _ => return false,
+    };
+    let base = &exprs[*base];
+    // Parenthesized, or starting however its leftmost operand does
+    precedence(base) < needed || continues(exprs, base)
+}
+
+/// A float literal: always with a `.`, which `Display` for `f64` leaves
+/// out of whole numbers, and never in exponent notation
+fn float(x: f64) -> String {
+    if x.is_nan() {
+        return "(0.0 / 0.0)".to_string();
+    }
+    let sign = if x.is_sign_negative() { "-" } else { "" };
+    if x.is_infinite() {
+        // Lexes as the nearest float, which is infinity
+        return format!("{}1{}.0", sign, "0".repeat(309));
+    }
+    let text = x.to_string();
+    if text.contains('.') {
+        text
+    } else {
+        format!("{}.0", text)
+    }
+}
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&Printer::print(&self.exprs, |p| p.program(self)))
+    }
+}
+
+/// A node of a program, printed with [`Exprs::display`]
+pub struct Display<'a, T: ?Sized> {
+    exprs: &'a Exprs,
+    node: &'a T,
+}
+
+impl Exprs {
+    /// Print `node`, whose nested expressions are in this arena
+    pub fn display<'a, T: ?Sized>(&'a self, node: &'a T) -> Display<'a, T> {
+        Display { exprs: self, node }
+    }
+}
+
+impl fmt::Display for Display<'_, Item> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&Printer::print(self.exprs, |p| p.item(self.node)))
+    }
+}
+
+impl fmt::Display for Display<'_, Function> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&Printer::print(self.exprs, |p| p.function(self.node)))
+    }
+}
+
+impl fmt::Display for Display<'_, TypeExpr> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&Printer::print(self.exprs, |p| p.ty(self.node)))
+    }
+}
+
+impl fmt::Display for Display<'_, Block> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&Printer::print(self.exprs, |p| p.block(self.node)))
+    }
+}
+
+impl fmt::Display for Display<'_, Stmt> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&Printer::print(self.exprs, |p| p.stmt(self.node, false)))
+    }
+}
+
+impl fmt::Display for Display<'_, Expr> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&Printer::print(self.exprs, |p| p.expr(self.node)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Phase;
+
+    fn parse(source: &str) -> super::Program {
+        let program = crate::compile_until(source, Phase::Parse).unwrap();
+        program.into_ast().unwrap()
+    }
+
+    #[test]
+    fn test_pretty() {
+        let source = "#![feature(extern_c)]
+macro twice { ($x:expr) => { $x * 2 }; }
+#[derive(Copy)] struct P { x: i64, y: f64 }
+enum Mood { Calm, Tense = 3 }
+impl P { const fn new(x: i64) -> P { P { x: x, y: 1.0 } } fn get(&self) -> i64 { self.x } }
+#[link(m)] extern \"C\" { fn abs(x: i32) -> i32; }
+fn main() -> i64 {
+    let mut a: [i64; 3] = [1, 2, 3];
+    if (P { x: 1, y: 2.0 }).x == 1 { a[0] = -(1 + 2) * twice!(3); } else if !true { return; }
+    while false {}
+    { f() };
+    -1;
+    let b = ((-a[1] as u8) as f32 + 2.5f32) as i8;
+    arena scratch { defer print(\"done\"); }
+    match Some(a[0]) { Some(1 | -2) => {} Some(n) if n > 3 => print(n), _ => { f(); } }
+    for i in (0..1 + 1) { let s: &[i64] = &a[i..]; print(s[..=0]); }
+    match b { ..-1 | 10.. => {} 0..=9 => {} }
+    return -a[0] - -1
+}";
+        let printed = parse(source).to_string();
+        assert_eq!(
+            printed,
+            "#![feature(extern_c)]
+
+#[derive(Copy)]
+struct P {
+    x: i64,
+    y: f64,
+}
+
+enum Mood {
+    Calm,
+    Tense = 3,
+}
+
+impl P {
+    const fn new(x: i64) -> P { P { x: x, y: 1.0 } }
+
+    fn get(&self) -> i64 { self.x }
+}
+
+#[link(m)]
+extern \"C\" {
+    fn abs(x: i32) -> i32;
+}
+
+fn main() -> i64 {
+    let mut a: [i64; 3] = [1, 2, 3];
+    if (P { x: 1, y: 2.0 }).x == 1 {
+        a[0] = -(1 + 2) * 3 * 2;
+    } else if !true {
+        return;
+    }
+    while false {}
+    { f() };
+    -1;
+    let b = (-a[1] as u8 as f32 + 2.5f32) as i8;
+    arena scratch {
+        defer print(\"done\");
+    }
+    match Some(a[0]) {
+        Some(1 | -2) => {},
+        Some(n) if n > 3 => print(n),
+        _ => {
+            f();
+        },
+    }
+    for i in 0..1 + 1 {
+        let s: &[i64] = &a[i..];
+        print(s[..=0]);
+    }
+    match b {
+        ..-1 | 10.. => {},
+        0..=9 => {},
+    }
+    return -a[0] - -1
+}
+"
+        );
+        // Printing the reparsed program changes nothing
+        assert_eq!(parse(&printed).to_string(), printed);
+    }
+}
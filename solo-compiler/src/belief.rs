@@ -0,0 +1,655 @@
+//! Runtime representation of belief values
+//!
+//! A `Belief<T>` is a probability distribution over `T`. Bernoulli
+//! (`Belief<bool>`) and normal (`Belief<f64>`) beliefs stay in closed form
+//! under Bayesian update because each is conjugate to itself; marginalizing
+//! over an uncertain condition can produce a finite mixture.
+//!
+//! Beliefs can also be sampled with a seeded [`Rng`], so Monte Carlo runs
+//! replay exactly given the same seed.
+//!
+//! [`Belief::plot`] and [`Belief::to_json`] summarize a belief for people
+//! and for plotting tools: mean, variance, credible interval and a
+//! histogram, all computed exactly from the distribution, not sampled.
+
+use std::f64::consts::PI;
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::json::Json;
+
+/// A probability distribution
+#[derive(Debug, Clone, PartialEq)]
+pub enum Belief {
+    /// True with probability `p`
+    Bernoulli(f64),
+    Normal {
+        mean: f64,
+        std_dev: f64,
+    },
+    /// Weighted components; weights are positive and sum to 1
+    Mixture(Vec<(f64, Belief)>),
+}
+
+impl Belief {
+    /// `~Bernoulli(p)`
+    pub fn bernoulli(p: f64) -> Result<Belief, String> {
+        if (0.0..=1.0).contains(&p) {
+            Ok(Belief::Bernoulli(p))
+        } else {
+            Err(format!(
+                "Bernoulli probability must be between 0 and 1, found {}",
+                p
+            ))
+        }
+    }
+
+    /// `~Normal(mean, std_dev)`
+    pub fn normal(mean: f64, std_dev: f64) -> Result<Belief, String> {
+        if !mean.is_finite() {
+            Err(format!("normal mean must be finite, found {}", mean))
+        } else if !(std_dev > 0.0 && std_dev.is_finite()) {
+            Err(format!(
+                "normal standard deviation must be positive, found {}",
+                std_dev
+            ))
+        } else {
+            Ok(Belief::Normal { mean, std_dev })
+        }
+    }
+
+    /// Name of the value type the distribution ranges over
+    pub fn value_type(&self) -> &'static str {
+        match self {
+            Belief::Bernoulli(_) => "bool",
+            Belief::Normal { .. } => "f64",
+            Belief::Mixture(components) => components
+                .first()
+                .map_or("f64", |(_, component)| component.value_type()),
+        }
+    }
+
+    /// Expected value; the probability of `true` for `Belief<bool>`
+    pub fn expectation(&self) -> f64 {
+        match self {
+            Belief::Bernoulli(p) => *p,
+            Belief::Normal { mean, .. } => *mean,
+            Belief::Mixture(components) => components
+                .iter()
+                .map(|(w, component)| w * component.expectation())
+                .sum(),
+        }
+    }
+
+    /// Probability of `x` for `Belief<bool>`, where `true` is 1.0, and
+    /// probability density at `x` for `Belief<f64>`
+    pub fn likelihood(&self, x: f64) -> f64 {
+        match self {
+            Belief::Bernoulli(p) if x == 1.0 => *p,
+            Belief::Bernoulli(p) if x == 0.0 => 1.0 - p,
+            Belief::Bernoulli(_) => 0.0,
+            Belief::Normal { mean, std_dev } => normal_pdf(x - mean, std_dev * std_dev),
+            Belief::Mixture(components) => components
+                .iter()
+                .map(|(w, component)| w * component.likelihood(x))
+                .sum(),
+        }
+    }
+
+    /// Variance; `p (1 - p)` for `Belief<bool>`
+    pub fn variance(&self) -> f64 {
+        let mean = self.expectation();
+        match self.value_type() {
+            "bool" => mean * (1.0 - mean),
+            _ => self.second_moment() - mean * mean,
+        }
+    }
+
+    /// `E[X^2]` of a belief about `f64`
+    fn second_moment(&self) -> f64 {
+        match self {
+            Belief::Bernoulli(p) => *p,
+            Belief::Normal { mean, std_dev } => std_dev * std_dev + mean * mean,
+            Belief::Mixture(components) => components
+                .iter()
+                .map(|(w, component)| w * component.second_moment())
+                .sum(),
+        }
+    }
+
+    /// The central interval holding probability `level` of a belief about
+    /// `f64`; `None` for `Belief<bool>`
+    pub fn credible_interval(&self, level: f64) -> Option<(f64, f64)> {
+        if self.value_type() == "bool" {
+            return None;
+        }
+        let tail = (1.0 - level) / 2.0;
+        Some((self.quantile(tail), self.quantile(1.0 - tail)))
+    }
+
+    /// The probability of each of `bins` equal intervals spanning all but
+    /// the outer 0.1% on either side of a belief about `f64`, as `(start,
+    /// end, probability)`; the probabilities of `false` and `true` for
+    /// `Belief<bool>`, as `(0, 0, _)` and `(1, 1, _)`
+    pub fn histogram(&self, bins: usize) -> Vec<(f64, f64, f64)> {
+        if self.value_type() == "bool" {
+            let p = self.expectation();
+            return vec![(0.0, 0.0, 1.0 - p), (1.0, 1.0, p)];
+        }
+        let (low, high) = (self.quantile(0.001), self.quantile(0.999));
+        let width = (high - low) / bins.max(1) as f64;
+        (0..bins)
+            .map(|i| {
+                let (start, end) = (low + width * i as f64, low + width * (i + 1) as f64);
+                (start, end, self.cdf(end) - self.cdf(start))
+            })
+            .collect()
+    }
+
+    /// `P(X <= x)` of a belief about `f64`
+    fn cdf(&self, x: f64) -> f64 {
+        match self {
+            Belief::Bernoulli(p) => {
+                if x < 0.0 {
+                    0.0
+                } else if x < 1.0 {
+                    1.0 - p
+                } else {
+                    1.0
+                }
+            }
+            Belief::Normal { mean, std_dev } => {
+                0.5 * (1.0 + erf((x - mean) / (std_dev * std::f64::consts::SQRT_2)))
+            }
+            Belief::Mixture(components) => components
+                .iter()
+                .map(|(w, component)| w * component.cdf(x))
+                .sum(),
+        }
+    }
+
+    /// The least `x` with `P(X <= x) >= q`, by bisection
+    fn quantile(&self, q: f64) -> f64 {
+        let (mut low, mut high) = self.bounds();
+        for _ in 0..100 {
+            let middle = (low + high) / 2.0;
+            if self.cdf(middle) < q {
+                low = middle;
+            } else {
+                high = middle;
+            }
+        }
+        (low + high) / 2.0
+    }
+
+    /// An interval outside which there is no probability to speak of
+    fn bounds(&self) -> (f64, f64) {
+        match self {
+            Belief::Bernoulli(_) => (0.0, 1.0),
+            Belief::Normal { mean, std_dev } => (mean - 40.0 * std_dev, mean + 40.0 * std_dev),
+            Belief::Mixture(components) => components.iter().fold(
+                (f64::INFINITY, f64::NEG_INFINITY),
+                |(low, high), (_, component)| {
+                    let (l, h) = component.bounds();
+                    (low.min(l), high.max(h))
+                },
+            ),
+        }
+    }
+
+    /// Draw one value; `Belief<bool>` samples are 1.0 for `true` and 0.0 for `false`
+    pub fn sample(&self, rng: &mut Rng) -> f64 {
+        match self {
+            Belief::Bernoulli(p) => {
+                if rng.next_f64() < *p {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            Belief::Normal { mean, std_dev } => mean + std_dev * rng.standard_normal(),
+            Belief::Mixture(components) => {
+                let mut u = rng.next_f64();
+                for (w, component) in components {
+                    if u < *w {
+                        return component.sample(rng);
+                    }
+                    u -= w;
+                }
+                // Rounding left `u` past the last weight
+                components
+                    .last()
+                    .map_or(f64::NAN, |(_, component)| component.sample(rng))
+            }
+        }
+    }
+
+    /// Monte Carlo estimate of [`expectation`](Belief::expectation) from `samples` draws
+    pub fn estimate(&self, rng: &mut Rng, samples: usize) -> f64 {
+        let total: f64 = (0..samples).map(|_| self.sample(rng)).sum();
+        total / samples.max(1) as f64
+    }
+
+    /// Posterior after observing independent `evidence` about the same quantity
+    pub fn update(&self, evidence: &Belief) -> Result<Belief, String> {
+        let (posterior, likelihood) = self.posterior(evidence)?;
+        if likelihood > 0.0 && likelihood.is_finite() {
+            Ok(posterior)
+        } else {
+            Err("evidence has zero likelihood under the prior".to_string())
+        }
+    }
+
+    /// Distribution of `if condition { if_true } else { if_false }`
+    pub fn marginalize(
+        condition: &Belief,
+        if_true: &Belief,
+        if_false: &Belief,
+    ) -> Result<Belief, String> {
+        if condition.value_type() != "bool" {
+            return Err(format!(
+                "cannot marginalize over a belief about `{}`",
+                condition.value_type()
+            ));
+        }
+        if if_true.value_type() != if_false.value_type() {
+            return Err(mismatch(if_true, if_false));
+        }
+        let p = condition.expectation();
+        if let (Belief::Bernoulli(t), Belief::Bernoulli(f)) = (if_true, if_false) {
+            return Ok(Belief::Bernoulli(p * t + (1.0 - p) * f));
+        }
+        Ok(mixture(vec![
+            (p, if_true.clone()),
+            (1.0 - p, if_false.clone()),
+        ]))
+    }
+
+    /// Unnormalized posterior and the marginal likelihood of `evidence`
+    fn posterior(&self, evidence: &Belief) -> Result<(Belief, f64), String> {
+        match (self, evidence) {
+            (Belief::Mixture(components), _) => {
+                let mut updated = Vec::with_capacity(components.len());
+                for (w, component) in components {
+                    let (posterior, likelihood) = component.posterior(evidence)?;
+                    updated.push((w * likelihood, posterior));
+                }
+                let total = updated.iter().map(|(w, _)| w).sum();
+                Ok((mixture(updated), total))
+            }
+            (_, Belief::Mixture(components)) => {
+                let mut updated = Vec::with_capacity(components.len());
+                for (w, component) in components {
+                    let (posterior, likelihood) = self.posterior(component)?;
+                    updated.push((w * likelihood, posterior));
+                }
+                let total = updated.iter().map(|(w, _)| w).sum();
+                Ok((mixture(updated), total))
+            }
+            (Belief::Bernoulli(p), Belief::Bernoulli(q)) => {
+                let likelihood = p * q + (1.0 - p) * (1.0 - q);
+                if likelihood == 0.0 {
+                    return Ok((self.clone(), 0.0));
+                }
+                Ok((Belief::Bernoulli(p * q / likelihood), likelihood))
+            }
+            (
+                Belief::Normal {
+                    mean: m1,
+                    std_dev: s1,
+                },
+                Belief::Normal {
+                    mean: m2,
+                    std_dev: s2,
+                },
+            ) => {
+                let (v1, v2) = (s1 * s1, s2 * s2);
+                let mean = (m1 * v2 + m2 * v1) / (v1 + v2);
+                let std_dev = (v1 * v2 / (v1 + v2)).sqrt();
+                let likelihood = normal_pdf(m1 - m2, v1 + v2);
+                Ok((Belief::Normal { mean, std_dev }, likelihood))
+            }
+            _ => Err(mismatch(self, evidence)),
+        }
+    }
+}
+
+impl fmt::Display for Belief {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Belief::Bernoulli(p) => write!(f, "~Bernoulli({})", p),
+            Belief::Normal { mean, std_dev } => write!(f, "~Normal({}, {})", mean, std_dev),
+            Belief::Mixture(components) => {
+                write!(f, "~Mixture(")?;
+                for (i, (w, component)) in components.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", w, component)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+/// Bins of the histogram [`Belief::plot`] draws of a belief about `f64`
+const BINS: usize = 12;
+
+/// Columns of the longest bar [`Belief::plot`] draws
+const BAR_WIDTH: usize = 40;
+
+impl Belief {
+    /// The distribution, its mean, variance and 95% credible interval, and
+    /// a histogram, over several lines
+    ///
+    /// ```text
+    /// ~Normal(20, 2.5)
+    ///   mean 20, variance 6.25
+    ///   95% credible interval [15.1, 24.9]
+    ///   12.274 0.004 |#
+    ///   13.562 0.015 |###
+    ///   ...
+    /// ```
+    pub fn plot(&self) -> String {
+        let mut lines = vec![
+            self.to_string(),
+            format!(
+                "  mean {}, variance {}",
+                short(self.expectation()),
+                short(self.variance())
+            ),
+        ];
+        if let Some((low, high)) = self.credible_interval(0.95) {
+            lines.push(format!(
+                "  95% credible interval [{}, {}]",
+                short(low),
+                short(high)
+            ));
+        }
+        let rows: Vec<(String, f64)> = match self.value_type() {
+            "bool" => vec![
+                ("false".to_string(), 1.0 - self.expectation()),
+                ("true".to_string(), self.expectation()),
+            ],
+            _ => self
+                .histogram(BINS)
+                .into_iter()
+                .map(|(start, _, p)| (short(start), p))
+                .collect(),
+        };
+        let label = rows.iter().map(|(label, _)| label.len()).max().unwrap_or(0);
+        let most = rows.iter().map(|&(_, p)| p).fold(0.0, f64::max);
+        for (start, p) in rows {
+            let bar = if most > 0.0 {
+                (p / most * BAR_WIDTH as f64).round() as usize
+            } else {
+                0
+            };
+            lines.push(format!(
+                "  {:>label$} {:.3} |{}",
+                start,
+                p,
+                "#".repeat(bar),
+                label = label
+            ));
+        }
+        lines.join("\n")
+    }
+
+    /// The distribution as JSON, for tools that plot it: `type`,
+    /// `distribution`, `mean`, `variance`, `interval` (the 95% credible
+    /// interval, for `f64`) and `histogram`, a list of `start`, `end` and
+    /// `probability`, or of `value` and `probability` for `bool`
+    pub fn to_json(&self) -> Json {
+        let mut fields = vec![
+            ("type", Json::str(self.value_type())),
+            ("distribution", Json::str(self.to_string())),
+            ("mean", Json::Float(self.expectation())),
+            ("variance", Json::Float(self.variance())),
+        ];
+        if let Some((low, high)) = self.credible_interval(0.95) {
+            fields.push((
+                "interval",
+                Json::Array(vec![Json::Float(low), Json::Float(high)]),
+            ));
+        }
+        let histogram = self.histogram(BINS).into_iter().map(|(start, end, p)| {
+            let p = ("probability", Json::Float(p));
+            match self.value_type() {
+                "bool" => Json::object([("value", Json::Bool(start == 1.0)), p]),
+                _ => Json::object([("start", Json::Float(start)), ("end", Json::Float(end)), p]),
+            }
+        });
+        fields.push(("histogram", Json::Array(histogram.collect())));
+        Json::object(fields)
+    }
+}
+
+/// `x` to at most three decimals, without trailing zeros
+fn short(x: f64) -> String {
+    let text = format!("{:.3}", x);
+    let text = text.trim_end_matches('0').trim_end_matches('.');
+    match text {
+        "-0" => "0".to_string(),
+        text => text.to_string(),
+    }
+}
+
+/// The error function (Abramowitz and Stegun 7.1.26, error below 1.5e-7)
+fn erf(x: f64) -> f64 {
+    let t = 1.0 / (1.0 + 0.327_591_1 * x.abs());
+    let poly = t
+        * (0.254_829_592
+            + t * (-0.284_496_736
+                + t * (1.421_413_741 + t * (-1.453_152_027 + t * 1.061_405_429))));
+    (1.0 - poly * (-x * x).exp()).copysign(x)
+}
+
+/// Deterministic pseudo-random numbers (SplitMix64)
+#[derive(Debug, Clone)]
+pub struct Rng {
+    seed: u64,
+    state: u64,
+    draws: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            state: seed,
+            draws: 0,
+        }
+    }
+
+    /// The seed the generator started from
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Numbers drawn so far; none means the seed made no difference
+    pub fn draws(&self) -> u64 {
+        self.draws
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.draws += 1;
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `[0, 1)`
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Standard normal variate (Box-Muller)
+    pub fn standard_normal(&mut self) -> f64 {
+        let u = 1.0 - self.next_f64();
+        let v = self.next_f64();
+        (-2.0 * u.ln()).sqrt() * (2.0 * PI * v).cos()
+    }
+}
+
+/// A seed that differs from run to run, from the clock and the process id,
+/// for `--seed random`
+pub fn random_seed() -> u64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos() as u64);
+    Rng::new(nanos ^ u64::from(std::process::id()).rotate_left(32)).next_u64()
+}
+
+/// Normalize weights into a mixture, flattening nested mixtures and
+/// dropping impossible components
+fn mixture(components: Vec<(f64, Belief)>) -> Belief {
+    let mut flat = Vec::new();
+    for (w, component) in components {
+        match component {
+            Belief::Mixture(inner) => flat.extend(inner.into_iter().map(|(v, c)| (w * v, c))),
+            component => flat.push((w, component)),
+        }
+    }
+    flat.retain(|(w, _)| *w > 0.0);
+    let total: f64 = flat.iter().map(|(w, _)| w).sum();
+    if flat.len() == 1 {
+        return flat.remove(0).1;
+    }
+    Belief::Mixture(flat.into_iter().map(|(w, c)| (w / total, c)).collect())
+}
+
+fn normal_pdf(x: f64, variance: f64) -> f64 {
+    (-x * x / (2.0 * variance)).exp() / (2.0 * PI * variance).sqrt()
+}
+
+fn mismatch(a: &Belief, b: &Belief) -> String {
+    format!(
+        "cannot combine a belief about `{}` with a belief about `{}`",
+        a.value_type(),
+        b.value_type()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn test_conjugate_updates() {
+        let prior = Belief::bernoulli(0.5).unwrap();
+        let posterior = prior.update(&Belief::Bernoulli(0.8)).unwrap();
+        assert!(close(posterior.expectation(), 0.8));
+
+        let prior = Belief::normal(0.0, 1.0).unwrap();
+        let posterior = prior.update(&Belief::normal(2.0, 1.0).unwrap()).unwrap();
+        match posterior {
+            Belief::Normal { mean, std_dev } => {
+                assert!(close(mean, 1.0));
+                assert!(close(std_dev, 0.5f64.sqrt()));
+            }
+            other => panic!("expected a normal posterior, found {}", other),
+        }
+
+        let certain = Belief::Bernoulli(1.0);
+        assert!(certain.update(&Belief::Bernoulli(0.0)).is_err());
+        assert!(Belief::bernoulli(1.5).is_err());
+        assert!(Belief::normal(0.0, 0.0).is_err());
+    }
+
+    #[test]
+    fn test_marginalize() {
+        let rain = Belief::Bernoulli(0.25);
+        let wet = Belief::marginalize(&rain, &Belief::Bernoulli(0.9), &Belief::Bernoulli(0.1));
+        assert!(close(wet.unwrap().expectation(), 0.3));
+
+        let temp = Belief::marginalize(
+            &rain,
+            &Belief::normal(10.0, 2.0).unwrap(),
+            &Belief::normal(20.0, 2.0).unwrap(),
+        )
+        .unwrap();
+        assert!(close(temp.expectation(), 17.5));
+
+        // Evidence near 10 makes the rainy component dominate
+        let posterior = temp.update(&Belief::normal(10.0, 1.0).unwrap()).unwrap();
+        assert!(posterior.expectation() < 11.0);
+        assert!(Belief::marginalize(&temp, &rain, &rain).is_err());
+    }
+
+    #[test]
+    fn test_sampling() {
+        let temp = Belief::marginalize(
+            &Belief::Bernoulli(0.25),
+            &Belief::normal(10.0, 2.0).unwrap(),
+            &Belief::normal(20.0, 2.0).unwrap(),
+        )
+        .unwrap();
+        let estimate = temp.estimate(&mut Rng::new(7), 20_000);
+        assert!((estimate - 17.5).abs() < 0.1, "{}", estimate);
+
+        // The same seed replays the same draws
+        let draws = |seed| {
+            let mut rng = Rng::new(seed);
+            (0..5).map(|_| temp.sample(&mut rng)).collect::<Vec<_>>()
+        };
+        assert_eq!(draws(1), draws(1));
+        assert_ne!(draws(1), draws(2));
+    }
+
+    #[test]
+    fn test_summaries() {
+        let normal = Belief::normal(20.0, 2.5).unwrap();
+        assert!(close(normal.variance(), 6.25));
+        let (low, high) = normal.credible_interval(0.95).unwrap();
+        assert!((low - 15.1).abs() < 1e-3 && (high - 24.9).abs() < 1e-3);
+        let histogram = normal.histogram(12);
+        let total: f64 = histogram.iter().map(|(_, _, p)| p).sum();
+        assert!((total - 0.998).abs() < 1e-6, "{}", total);
+
+        let plot = normal.plot();
+        let lines: Vec<&str> = plot.lines().collect();
+        assert_eq!(
+            lines[..4],
+            [
+                "~Normal(20, 2.5)",
+                "  mean 20, variance 6.25",
+                "  95% credible interval [15.1, 24.9]",
+                "  12.274 0.004 |#",
+            ]
+        );
+        assert_eq!(lines.len(), 15);
+
+        // Mixing two normals widens the spread beyond either
+        let temp = Belief::marginalize(
+            &Belief::Bernoulli(0.5),
+            &Belief::normal(10.0, 1.0).unwrap(),
+            &Belief::normal(20.0, 1.0).unwrap(),
+        )
+        .unwrap();
+        assert!(close(temp.variance(), 26.0));
+
+        let rain = Belief::Bernoulli(0.25);
+        assert!(close(rain.variance(), 0.1875));
+        assert_eq!(rain.credible_interval(0.95), None);
+        assert_eq!(
+            rain.plot(),
+            "~Bernoulli(0.25)\n  mean 0.25, variance 0.188\n  \
+             false 0.750 |########################################\n   \
+             true 0.250 |#############"
+        );
+        assert_eq!(
+            rain.to_json().to_string(),
+            "{\"type\":\"bool\",\"distribution\":\"~Bernoulli(0.25)\",\"mean\":0.25,\
+             \"variance\":0.1875,\"histogram\":[{\"value\":false,\"probability\":0.75},\
+             {\"value\":true,\"probability\":0.25}]}"
+        );
+    }
+}
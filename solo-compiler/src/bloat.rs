@@ -0,0 +1,518 @@
+//! Binary size profiles (`solo bloat`)
+//!
+//! `solo bloat <binary> [file.solo]` reads the symbol table of an ELF
+//! executable, shared library or object file and attributes the size of its
+//! functions and statics to their owners, largest first, so that embedded
+//! users can see what to trim:
+//!
+//! - functions of the Solo program given, found under their symbols (see
+//!   [`export::symbol`]; methods are named `Type_method`)
+//! - components of the Solo runtime, the Rust functions under
+//!   `solo::stdlib::<component>`, reported as `std::<component>`
+//! - other Rust crates, by the first segment of the demangled path, and
+//!   C code, whose names are not mangled, as `[C]`
+//!
+//! A generic Rust function is compiled once per set of type arguments, and
+//! the instances share one demangled path; they are reported together, with
+//! their number. Only 64-bit little-endian ELF files are read for now.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+use crate::ast::Program;
+use crate::export;
+
+/// A function or static in a symbol table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    pub size: u64,
+}
+
+/// What `solo bloat` reads from a binary
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Binary {
+    pub file_size: u64,
+    /// Size of the `.text` section, where code goes
+    pub text_size: u64,
+    /// Defined functions and statics with a size
+    pub symbols: Vec<Symbol>,
+}
+
+const SHT_SYMTAB: u32 = 2;
+const SHT_DYNSYM: u32 = 11;
+const STT_OBJECT: u8 = 1;
+const STT_FUNC: u8 = 2;
+
+/// Read the sections and symbols of an ELF file
+pub fn read_elf(data: &[u8]) -> Result<Binary, String> {
+    if data.get(..4) != Some(b"\x7fELF".as_slice()) {
+        return Err("not an ELF file; only ELF binaries and objects can be read".to_string());
+    }
+    if data.get(4) != Some(&2) || data.get(5) != Some(&1) {
+        return Err("only 64-bit little-endian ELF files can be read".to_string());
+    }
+    let truncated = || "truncated ELF file".to_string();
+    let u16_at = |at: usize| {
+        let bytes = data.get(at..at + 2).ok_or_else(truncated)?;
+        Ok::<_, String>(u16::from_le_bytes(bytes.try_into().expect("2 bytes")))
+    };
+    let u32_at = |at: usize| {
+        let bytes = data.get(at..at + 4).ok_or_else(truncated)?;
+        Ok::<_, String>(u32::from_le_bytes(bytes.try_into().expect("4 bytes")))
+    };
+    let u64_at = |at: usize| {
+        let bytes = data.get(at..at + 8).ok_or_else(truncated)?;
+        Ok::<_, String>(u64::from_le_bytes(bytes.try_into().expect("8 bytes")))
+    };
+    let offset = |value: u64| usize::try_from(value).map_err(|_| truncated());
+
+    let section_headers = offset(u64_at(0x28)?)?;
+    let entry_size = usize::from(u16_at(0x3a)?);
+    let count = usize::from(u16_at(0x3c)?);
+    let names_index = usize::from(u16_at(0x3e)?);
+    let sections = (0..count)
+        .map(|i| {
+            let at = section_headers + i * entry_size;
+            Ok(Section {
+                name: u32_at(at)?,
+                kind: u32_at(at + 4)?,
+                offset: offset(u64_at(at + 24)?)?,
+                size: offset(u64_at(at + 32)?)?,
+                link: u32_at(at + 40)? as usize,
+                entry_size: offset(u64_at(at + 56)?)?,
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    let string = |table: &Section, at: usize| -> Result<String, String> {
+        let start = table.offset + at;
+        let bytes = data
+            .get(start..table.offset + table.size)
+            .ok_or_else(truncated)?;
+        let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+        Ok(String::from_utf8_lossy(&bytes[..end]).into_owned())
+    };
+
+    let mut binary = Binary {
+        file_size: data.len() as u64,
+        ..Binary::default()
+    };
+    if let Some(names) = sections.get(names_index) {
+        for section in &sections {
+            if string(names, section.name as usize)? == ".text" {
+                binary.text_size = section.size as u64;
+            }
+        }
+    }
+    // The full symbol table, or else the dynamic one of a stripped binary
+    let table = sections
+        .iter()
+        .find(|s| s.kind == SHT_SYMTAB)
+        .or_else(|| sections.iter().find(|s| s.kind == SHT_DYNSYM));
+    let Some(table) = table else {
+        return Ok(binary);
+    };
+    let names = sections.get(table.link).ok_or_else(truncated)?;
+    for i in 0..table.size / table.entry_size.max(24) {
+        let at = table.offset + i * table.entry_size.max(24);
+        let kind = data.get(at + 4).ok_or_else(truncated)? & 0xf;
+        let defined = u16_at(at + 6)? != 0;
+        let size = u64_at(at + 16)?;
+        if defined && size > 0 && (kind == STT_FUNC || kind == STT_OBJECT) {
+            binary.symbols.push(Symbol {
+                name: string(names, u32_at(at)? as usize)?,
+                size,
+            });
+        }
+    }
+    Ok(binary)
+}
+
+struct Section {
+    name: u32,
+    kind: u32,
+    offset: usize,
+    size: usize,
+    link: usize,
+    entry_size: usize,
+}
+
+/// The path of a Rust symbol in the legacy mangling (`_ZN...E`), without
+/// its hash
+pub fn demangle(symbol: &str) -> Option<String> {
+    let mut rest = symbol
+        .strip_prefix("_ZN")
+        .or_else(|| symbol.strip_prefix("__ZN"))?;
+    let mut segments = Vec::new();
+    while !rest.starts_with('E') {
+        let digits = rest.find(|c: char| !c.is_ascii_digit())?;
+        let len: usize = rest[..digits].parse().ok()?;
+        let segment = rest.get(digits..digits + len)?;
+        segments.push(segment);
+        rest = &rest[digits + len..];
+    }
+    let is_hash = |s: &&str| {
+        s.len() == 17 && s.starts_with('h') && s[1..].chars().all(|c| c.is_ascii_hexdigit())
+    };
+    if segments.last().is_some_and(is_hash) {
+        segments.pop();
+    }
+    let segments: Vec<String> = segments.into_iter().map(unescape).collect();
+    Some(segments.join("::"))
+}
+
+fn unescape(segment: &str) -> String {
+    const ESCAPES: [(&str, &str); 18] = [
+        ("$LT$", "<"),
+        ("$GT$", ">"),
+        ("$C$", ","),
+        ("$RF$", "&"),
+        ("$BP$", "*"),
+        ("$SP$", "@"),
+        ("$LP$", "("),
+        ("$RP$", ")"),
+        ("$u20$", " "),
+        ("$u21$", "!"),
+        ("$u22$", "\""),
+        ("$u27$", "'"),
+        ("$u2b$", "+"),
+        ("$u3b$", ";"),
+        ("$u5b$", "["),
+        ("$u5d$", "]"),
+        ("$u7b$", "{"),
+        ("$u7d$", "}"),
+    ];
+    let segment = segment
+        .strip_prefix("_$")
+        .map_or(segment.to_string(), |s| format!("${}", s));
+    let mut text = segment.replace("..", "::");
+    for (escape, c) in ESCAPES {
+        text = text.replace(escape, c);
+    }
+    text
+}
+
+/// Symbols of the functions of `program`, compiled as crate `crate_name`,
+/// and the paths of the functions
+pub fn solo_symbols(crate_name: &str, program: &Program) -> HashMap<String, String> {
+    program
+        .functions()
+        .into_iter()
+        .map(|(name, _, function)| {
+            let symbol = export::symbol(
+                crate_name,
+                &name.replace("::", "_"),
+                function.is_no_mangle(),
+            );
+            (symbol, name)
+        })
+        .collect()
+}
+
+/// Size attributed to one function, or to all instances of a generic one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// The Solo crate, `std::<component>`, a Rust crate or `[C]`
+    pub owner: String,
+    pub name: String,
+    pub size: u64,
+    /// Symbols sharing the name, more than one for a generic function
+    pub instances: usize,
+}
+
+/// Sizes by function and by owner, largest first
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+    pub file_size: u64,
+    pub text_size: u64,
+    pub symbols: usize,
+    pub functions: Vec<Entry>,
+    pub owners: Vec<(String, u64)>,
+}
+
+/// Attribute the symbols of `binary`, taking those in `solo` (see
+/// [`solo_symbols`]) to be functions of the Solo crate `crate_name`
+pub fn analyze(binary: &Binary, crate_name: &str, solo: &HashMap<String, String>) -> Report {
+    let mut entries: BTreeMap<(String, String), (u64, usize)> = BTreeMap::new();
+    for symbol in &binary.symbols {
+        let (owner, name) = match (solo.get(&symbol.name), demangle(&symbol.name)) {
+            (Some(path), _) => (crate_name.to_string(), path.clone()),
+            (None, Some(path)) => owner_of(&path),
+            (None, None) => ("[C]".to_string(), symbol.name.clone()),
+        };
+        let entry = entries.entry((owner, name)).or_default();
+        entry.0 += symbol.size;
+        entry.1 += 1;
+    }
+
+    let mut owners: BTreeMap<String, u64> = BTreeMap::new();
+    let mut functions: Vec<Entry> = entries
+        .into_iter()
+        .map(|((owner, name), (size, instances))| {
+            *owners.entry(owner.clone()).or_default() += size;
+            Entry {
+                owner,
+                name,
+                size,
+                instances,
+            }
+        })
+        .collect();
+    functions.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.name.cmp(&b.name)));
+    let mut owners: Vec<(String, u64)> = owners.into_iter().collect();
+    owners.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    Report {
+        file_size: binary.file_size,
+        text_size: binary.text_size,
+        symbols: binary.symbols.len(),
+        functions,
+        owners,
+    }
+}
+
+/// The owner of a demangled Rust path and the path relative to it
+///
+/// A trait method `<Type as Trait>::method` belongs to the crate of `Type`,
+/// or of `Trait` when `Type` is a type parameter or a primitive.
+fn owner_of(path: &str) -> (String, String) {
+    let inner = path.strip_prefix('<').unwrap_or(path);
+    let (ty, tr) = inner.split_once(" as ").unwrap_or((inner, ""));
+    let ty = ty.trim_start_matches(['&', '*', '[', '(']);
+    let ty = ["mut ", "const ", "dyn "]
+        .iter()
+        .fold(ty, |ty, prefix| ty.strip_prefix(prefix).unwrap_or(ty));
+    // A trait defined inside a method is owned like the method:
+    // `<T as <Type as Trait>::method::Inner>::f`
+    let tr = tr.trim_start_matches('<');
+    let qualified = |path: &str| {
+        let end = path.find(['<', ' ', ';', ']', '>']).unwrap_or(path.len());
+        path[..end].contains("::")
+    };
+    let owned = if qualified(ty) || tr.is_empty() {
+        ty
+    } else {
+        tr
+    };
+    if let Some(rest) = owned.strip_prefix("solo::stdlib::") {
+        if let Some((component, _)) = rest.split_once("::") {
+            let prefix = format!("solo::stdlib::{}::", component);
+            return (format!("std::{}", component), path.replacen(&prefix, "", 1));
+        }
+    }
+    let owner = owned.split("::").next().unwrap_or(owned);
+    (owner.to_string(), path.to_string())
+}
+
+impl Report {
+    /// The `limit` largest functions and every owner, as a table
+    pub fn render(&self, limit: usize) -> String {
+        let share = |size: u64, of: u64| {
+            if of == 0 {
+                "-".to_string()
+            } else {
+                format!("{:.1}%", size as f64 * 100.0 / of as f64)
+            }
+        };
+        let mut out = format!(
+            "{:>6} {:>6} {:>9}  {:<20} {}\n",
+            "File", ".text", "Size", "Owner", "Name"
+        );
+        for entry in self.functions.iter().take(limit) {
+            let instances = if entry.instances > 1 {
+                format!(" ({} instances)", entry.instances)
+            } else {
+                String::new()
+            };
+            let _ = writeln!(
+                out,
+                "{:>6} {:>6} {:>9}  {:<20} {}{}",
+                share(entry.size, self.file_size),
+                share(entry.size, self.text_size),
+                bytes(entry.size),
+                entry.owner,
+                entry.name,
+                instances
+            );
+        }
+        if self.functions.len() > limit {
+            let rest: u64 = self.functions[limit..].iter().map(|e| e.size).sum();
+            let _ = writeln!(
+                out,
+                "{:>6} {:>6} {:>9}  {} more",
+                share(rest, self.file_size),
+                share(rest, self.text_size),
+                bytes(rest),
+                self.functions.len() - limit
+            );
+        }
+        let _ = write!(out, "\n{:>6} {:>6} {:>9}  Owner\n", "File", ".text", "Size");
+        for (owner, size) in &self.owners {
+            let _ = writeln!(
+                out,
+                "{:>6} {:>6} {:>9}  {}",
+                share(*size, self.file_size),
+                share(*size, self.text_size),
+                bytes(*size),
+                owner
+            );
+        }
+        let _ = writeln!(
+            out,
+            "\n.text: {}, file: {}, {} symbol(s)",
+            bytes(self.text_size),
+            bytes(self.file_size),
+            self.symbols
+        );
+        out
+    }
+}
+
+fn bytes(size: u64) -> String {
+    match size {
+        0..1024 => format!("{}B", size),
+        1024..1_048_576 => format!("{:.1}KiB", size as f64 / 1024.0),
+        _ => format!("{:.1}MiB", size as f64 / 1_048_576.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An ELF file with a `.text` section of `text` bytes and the symbols
+    /// given, as `(name, size, type)`
+    fn elf(text: u64, symbols: &[(&str, u64, u8)]) -> Vec<u8> {
+        let mut strtab = vec![0];
+        let mut symtab = vec![0; 24];
+        for (name, size, kind) in symbols {
+            symtab.extend_from_slice(&(strtab.len() as u32).to_le_bytes());
+            symtab.extend_from_slice(&[*kind, 0, 1, 0]);
+            symtab.extend_from_slice(&0u64.to_le_bytes());
+            symtab.extend_from_slice(&size.to_le_bytes());
+            strtab.extend_from_slice(name.as_bytes());
+            strtab.push(0);
+        }
+        let shstrtab = b"\0.text\0.symtab\0.strtab\0.shstrtab\0".to_vec();
+        let mut out = vec![0; 64];
+        out[..6].copy_from_slice(b"\x7fELF\x02\x01");
+        let mut sections = vec![[0u64; 8]];
+        for (name, kind, data, link, entry) in [
+            (1, 1, vec![0; text as usize], 0, 0),
+            (7, SHT_SYMTAB, symtab, 3, 24),
+            (15, 3, strtab, 0, 0),
+            (23, 3, shstrtab, 0, 0),
+        ] {
+            sections.push([
+                name | (u64::from(kind) << 32),
+                0,
+                0,
+                out.len() as u64,
+                data.len() as u64,
+                link,
+                0,
+                entry,
+            ]);
+            out.extend(data);
+        }
+        let headers = out.len() as u64;
+        for section in sections {
+            for word in section {
+                out.extend_from_slice(&word.to_le_bytes());
+            }
+        }
+        out[0x28..0x30].copy_from_slice(&headers.to_le_bytes());
+        out[0x3a..0x3c].copy_from_slice(&64u16.to_le_bytes());
+        out[0x3c..0x3e].copy_from_slice(&5u16.to_le_bytes());
+        out[0x3e..0x40].copy_from_slice(&4u16.to_le_bytes());
+        out
+    }
+
+    #[test]
+    fn test_demangle() {
+        assert_eq!(
+            demangle("_ZN4solo6stdlib11collections14Table$LT$V$GT$6insert17had24ff5208a86ad1E")
+                .as_deref(),
+            Some("solo::stdlib::collections::Table<V>::insert")
+        );
+        assert_eq!(
+            demangle("_ZN91_$LT$solo..stdlib..io..Lines$LT$R$GT$$u20$as$u20$core..iter..traits..iterator..Iterator$GT$4next17hbe5bb2229e9d6a2bE").as_deref(),
+            Some("<solo::stdlib::io::Lines<R> as core::iter::traits::iterator::Iterator>::next")
+        );
+        assert_eq!(demangle("memcpy"), None);
+    }
+
+    #[test]
+    fn test_owner_of() {
+        let owner = |path| owner_of(path).0;
+        assert_eq!(owner("<dyn core::any::Any>::downcast_ref"), "core");
+        assert_eq!(
+            owner("<&mut solo::lexer::Lexer as core::fmt::Debug>::fmt"),
+            "solo"
+        );
+        assert_eq!(owner("<T as <std::ffi::OsString as core::convert::From<&T>>::from::SpecToOsString>::spec_to_os_string"), "std");
+        assert_eq!(owner("<u8 as core::fmt::Display>::fmt"), "core");
+    }
+
+    #[test]
+    fn test_analyze() {
+        let data = elf(
+            4096,
+            &[
+                ("wire_Story_score", 300, STT_FUNC),
+                ("wire_main", 100, STT_FUNC),
+                (
+                    "_ZN4solo6stdlib11collections14Table$LT$V$GT$6insert17had24ff5208a86ad1E",
+                    1000,
+                    STT_FUNC,
+                ),
+                (
+                    "_ZN4solo6stdlib11collections14Table$LT$V$GT$6insert17h0000000000000001E",
+                    1200,
+                    STT_FUNC,
+                ),
+                ("_ZN4core3fmt5write17h0123456789abcdefE", 500, STT_FUNC),
+                ("memcpy", 200, STT_FUNC),
+                ("TABLE", 64, STT_OBJECT),
+                ("section_marker", 0, STT_FUNC),
+            ],
+        );
+        let binary = read_elf(&data).unwrap();
+        assert_eq!(binary.text_size, 4096);
+        assert_eq!(binary.symbols.len(), 7);
+
+        let program = crate::compile_until(
+            "struct Story { words: i64 }
+             impl Story { fn score(&self) -> i64 { self.words } }
+             fn main() {}",
+            crate::Phase::Parse,
+        )
+        .unwrap()
+        .into_ast()
+        .unwrap();
+        let report = analyze(&binary, "wire", &solo_symbols("wire", &program));
+        let top = &report.functions[0];
+        assert_eq!(
+            (
+                top.owner.as_str(),
+                top.name.as_str(),
+                top.size,
+                top.instances
+            ),
+            ("std::collections", "Table<V>::insert", 2200, 2)
+        );
+        assert_eq!(report.owners[0], ("std::collections".to_string(), 2200));
+        assert!(report.owners.contains(&("wire".to_string(), 400)));
+        assert!(report.owners.contains(&("[C]".to_string(), 264)));
+
+        let table = report.render(2);
+        assert!(table.contains(
+            " 44.3%  53.7%    2.1KiB  std::collections     Table<V>::insert (2 instances)\n"
+        ));
+        assert!(table.contains("    664B  4 more\n"));
+        assert!(table.ends_with(".text: 4.0KiB, file: 4.9KiB, 7 symbol(s)\n"));
+
+        assert!(read_elf(b"MZ\x90\x00")
+            .unwrap_err()
+            .contains("not an ELF file"));
+    }
+}
@@ -0,0 +1,696 @@
+//! Built-in functions available to every Solo program
+//!
+//! User-defined functions with the same name take precedence. Builtins with
+//! qualified names (`env::arg`) are reached by their full path. Integer
+//! intrinsics are shared by the const evaluator and the interpreter so that
+//! compile-time and run-time results always agree.
+//!
+//! Belief operators are generic over the value type of the belief, and the
+//! checked and saturating integer operations over the integer type of their
+//! operands, so they are checked against their argument types instead of a
+//! fixed signature.
+
+use crate::numeric::Numeric;
+use crate::stdlib::atomic;
+use crate::stdlib::simd::{self, VectorType};
+use crate::stdlib::sync::{Access, GuardKind};
+use crate::typeck::Type;
+
+/// Names of the builtins with a [`signature`], besides the constructors of
+/// vector types (`f64x4::splat`, `f64x4::from_array`)
+pub const FUNCTIONS: &[&str] = &[
+    "print",
+    "panic",
+    "wrapping_add",
+    "wrapping_sub",
+    "wrapping_mul",
+    "wrapping_neg",
+    "popcount",
+    "ctz",
+    "clz",
+    "fma",
+    "env::args_len",
+    "env::arg",
+    "io::read_file",
+    "io::write_file",
+    "io::append_file",
+    "io::exists",
+    "io::lines",
+    "io::read_line",
+    "io::stdin_lines",
+    "io::print",
+    "io::eprint",
+    "io::flush",
+    "Vec::new",
+    "Vec::with_capacity",
+    "Map::new",
+    "StringBuf::new",
+    "StringBuf::from",
+    "StringBuf::from_utf8",
+    "channel::unbounded",
+    "channel::bounded",
+    "atomic::fence",
+];
+
+/// Distributions of `~name(...)` literals
+pub const DISTRIBUTIONS: &[&str] = &["Normal", "Bernoulli"];
+
+/// Belief operators, see [`belief_op`]
+pub const BELIEF_OPS: &[&str] = &[
+    "expectation",
+    "sample",
+    "update",
+    "marginalize",
+    "observe",
+    "expect",
+];
+
+/// Checked and saturating integer operations, see [`int_op`]
+pub const INT_OPS: &[&str] = &[
+    "checked_add",
+    "checked_sub",
+    "checked_mul",
+    "checked_div",
+    "checked_rem",
+    "checked_neg",
+    "saturating_add",
+    "saturating_sub",
+    "saturating_mul",
+];
+
+/// Signature of a builtin, or `None` if `name` is not a builtin
+///
+/// `Type::Unknown` parameters accept any type.
+pub fn signature(name: &str) -> Option<(Vec<Type>, Type)> {
+    let sig = match name {
+        "print" => (vec![Type::Unknown], Type::Unit),
+        // Never returns, so its result fits anywhere
+        "panic" => (vec![Type::Str], Type::Never),
+        "wrapping_add" | "wrapping_sub" | "wrapping_mul" => (vec![Type::Int, Type::Int], Type::Int),
+        "wrapping_neg" => (vec![Type::Int], Type::Int),
+        // Bit counts of the 64-bit representation
+        "popcount" | "ctz" | "clz" => (vec![Type::Int], Type::Int),
+        // `a * b + c` with a single rounding
+        "fma" => (vec![Type::Float, Type::Float, Type::Float], Type::Float),
+        // Command-line arguments, the program's path first
+        "env::args_len" => (Vec::new(), Type::Int),
+        "env::arg" => (vec![Type::Int], Type::Str),
+        // Files and the standard streams; failures panic
+        "io::read_file" => (vec![Type::Str], Type::Str),
+        "io::write_file" | "io::append_file" => (vec![Type::Str, Type::Str], Type::Unit),
+        "io::exists" => (vec![Type::Str], Type::Bool),
+        // Lines without their terminators, for `for` loops
+        "io::lines" => (vec![Type::Str], Type::Iter(Box::new(Type::Str))),
+        "io::stdin_lines" => (Vec::new(), Type::Iter(Box::new(Type::Str))),
+        // A line of stdin with its terminator, `""` at end of input
+        "io::read_line" => (Vec::new(), Type::Str),
+        // Like `print`, without the newline
+        "io::print" | "io::eprint" => (vec![Type::Unknown], Type::Unit),
+        "io::flush" => (Vec::new(), Type::Unit),
+        // Collections in the given arena; their element types come from
+        // the annotation of whatever receives them
+        "Vec::new" => (vec![Type::Arena], Type::Vec(Box::new(Type::Unknown))),
+        "Vec::with_capacity" => (
+            vec![Type::Arena, Type::Int],
+            Type::Vec(Box::new(Type::Unknown)),
+        ),
+        "Map::new" => (
+            vec![Type::Arena],
+            Type::Map(Box::new(Type::Unknown), Box::new(Type::Unknown)),
+        ),
+        // Strings in the given arena; `from_utf8` panics on invalid UTF-8
+        "StringBuf::new" => (vec![Type::Arena], Type::StringBuf),
+        "StringBuf::from" => (vec![Type::Arena, Type::Str], Type::StringBuf),
+        "StringBuf::from_utf8" => (
+            vec![Type::Arena, Type::Vec(Box::new(Type::Int))],
+            Type::StringBuf,
+        ),
+        // The receiving end of a new channel, with `sender()` for the
+        // other; `bounded` holds at most the given number of messages
+        "channel::unbounded" => (Vec::new(), Type::Receiver(Box::new(Type::Unknown))),
+        "channel::bounded" => (vec![Type::Int], Type::Receiver(Box::new(Type::Unknown))),
+        "atomic::fence" => (vec![ordering()], Type::Unit),
+        _ => return vector_constructor(name).or_else(|| atomic_constructor(name)),
+    };
+    Some(sig)
+}
+
+/// `new` of an atomic integer type, taking the initial value
+fn atomic_constructor(name: &str) -> Option<(Vec<Type>, Type)> {
+    let (ty, "new") = name.split_once("::")? else {
+        return None;
+    };
+    Some((vec![Type::Int], Type::Atomic(atomic::atomic_type(ty)?)))
+}
+
+/// The enum of memory orderings
+fn ordering() -> Type {
+    Type::Enum(atomic::ORDERING.to_string())
+}
+
+/// `splat` (every lane the same) and `from_array` of a vector type
+fn vector_constructor(name: &str) -> Option<(Vec<Type>, Type)> {
+    let (ty, constructor) = name.split_once("::")?;
+    let vector = simd::vector_type(ty)?;
+    let param = match constructor {
+        "splat" => lane_type(vector),
+        "from_array" => Type::Array(Box::new(lane_type(vector)), vector.lanes),
+        _ => return None,
+    };
+    Some((vec![param], Type::Simd(vector)))
+}
+
+/// Type of one lane of `vector`
+pub fn lane_type(vector: &VectorType) -> Type {
+    if vector.float {
+        Type::Float
+    } else {
+        Type::Int
+    }
+}
+
+/// Parameters and belief type of a distribution literal `~name(...)`
+pub fn distribution(name: &str) -> Option<(Vec<Type>, Type)> {
+    let sig = match name {
+        "Normal" => (vec![Type::Float, Type::Float], Type::Float),
+        "Bernoulli" => (vec![Type::Float], Type::Bool),
+        _ => return None,
+    };
+    Some((sig.0, Type::Belief(Box::new(sig.1))))
+}
+
+/// Whether `name` is a belief operator
+pub fn is_belief_op(name: &str) -> bool {
+    BELIEF_OPS.contains(&name)
+}
+
+/// Result type of belief operator `name` applied to `args`
+///
+/// - `expectation(Belief<T>) -> f64`
+/// - `sample(Belief<T>) -> T`
+/// - `update(prior: Belief<T>, evidence: Belief<T>) -> Belief<T>`
+/// - `marginalize(Belief<bool>, Belief<T>, Belief<T>) -> Belief<T>`
+/// - `observe(bool)`, `observe(Belief<T>, T)`
+/// - `expect(f64) -> f64`, `expect(bool) -> f64`
+pub fn belief_op(name: &str, args: &[Type]) -> Result<Type, String> {
+    let arity: &[usize] = match name {
+        "expectation" | "sample" | "expect" => &[1],
+        "observe" => &[1, 2],
+        "update" => &[2],
+        _ => &[3],
+    };
+    if !arity.contains(&args.len()) {
+        let arity: Vec<String> = arity.iter().map(ToString::to_string).collect();
+        return Err(format!(
+            "this function takes {} arguments but {} were supplied",
+            arity.join(" or "),
+            args.len()
+        ));
+    }
+
+    let value_type = |ty: &Type| match ty {
+        Type::Belief(inner) => Ok((**inner).clone()),
+        Type::Unknown => Ok(Type::Unknown),
+        other => Err(format!(
+            "mismatched types: expected `Belief<_>`, found `{}`",
+            other
+        )),
+    };
+    let unify = |a: Type, b: Type| match (a, b) {
+        (Type::Unknown, t) | (t, Type::Unknown) => Ok(t),
+        (a, b) if a == b => Ok(a),
+        (a, b) => Err(format!(
+            "mismatched types: expected `Belief<{}>`, found `Belief<{}>`",
+            a, b
+        )),
+    };
+
+    match name {
+        "expectation" => value_type(&args[0]).map(|_| Type::Float),
+        "sample" => value_type(&args[0]),
+        "update" => {
+            let t = unify(value_type(&args[0])?, value_type(&args[1])?)?;
+            Ok(Type::Belief(Box::new(t)))
+        }
+        "observe" => {
+            // A condition, or a value the belief is about
+            let (expected, found) = match args {
+                [condition] => (Type::Bool, condition),
+                _ => (value_type(&args[0])?, &args[1]),
+            };
+            match (&expected, found) {
+                (Type::Unknown, _) | (_, Type::Unknown) => Ok(Type::Unit),
+                (expected, found) if expected == found => Ok(Type::Unit),
+                (expected, found) => Err(format!(
+                    "mismatched types: expected `{}`, found `{}`",
+                    expected, found
+                )),
+            }
+        }
+        "expect" => match &args[0] {
+            Type::Float | Type::Bool | Type::Unknown => Ok(Type::Float),
+            other => Err(format!(
+                "mismatched types: expected `{}` or `{}`, found `{}`",
+                Type::Float,
+                Type::Bool,
+                other
+            )),
+        },
+        _ => {
+            unify(Type::Bool, value_type(&args[0])?)?;
+            let t = unify(value_type(&args[1])?, value_type(&args[2])?)?;
+            Ok(Type::Belief(Box::new(t)))
+        }
+    }
+}
+
+/// Whether `name` is a checked or saturating integer operation
+pub fn is_int_op(name: &str) -> bool {
+    INT_OPS.contains(&name)
+}
+
+/// Result type of the integer operation `name` applied to `args`, which
+/// must all be of one integer type `T`
+///
+/// - `checked_add(T, T) -> Option<T>`, and likewise `checked_sub`,
+///   `checked_mul`, `checked_div` and `checked_rem`
+/// - `checked_neg(T) -> Option<T>`
+/// - `saturating_add(T, T) -> T`, `saturating_sub` and `saturating_mul`
+pub fn int_op(name: &str, args: &[Type]) -> Result<Type, String> {
+    let arity = if name == "checked_neg" { 1 } else { 2 };
+    if args.len() != arity {
+        return Err(format!(
+            "this function takes {} arguments but {} were supplied",
+            arity,
+            args.len()
+        ));
+    }
+    let mut ty = Type::Unknown;
+    for arg in args {
+        let integer = match arg {
+            Type::Sized(numeric) => !numeric.is_float(),
+            other => matches!(other, Type::Int | Type::Unknown),
+        };
+        if !integer {
+            return Err(format!(
+                "mismatched types: expected an integer type, found `{}`",
+                arg
+            ));
+        }
+        if ty == Type::Unknown {
+            ty = arg.clone();
+        } else if *arg != Type::Unknown && *arg != ty {
+            return Err(format!(
+                "mismatched types: expected `{}`, found `{}`",
+                ty, arg
+            ));
+        }
+    }
+    if is_checked(name) {
+        Ok(Type::Option(Box::new(ty)))
+    } else {
+        Ok(ty)
+    }
+}
+
+/// Whether the integer operation `name` returns an `Option`
+pub fn is_checked(name: &str) -> bool {
+    name.starts_with("checked_")
+}
+
+/// Evaluate the integer operation `name` on integers of type `numeric`;
+/// `None` if `name` is not one. A checked operation gives `Some(None)`
+/// where the result is out of range or a division is by zero, and a
+/// saturating one gives the bound of the range the result is past.
+pub fn int_op_value(name: &str, args: &[i64], numeric: Numeric) -> Option<Option<i64>> {
+    let args: Vec<i128> = args.iter().map(|&arg| i128::from(arg)).collect();
+    let exact = match (name, args.as_slice()) {
+        ("checked_add" | "saturating_add", [a, b]) => Some(a + b),
+        ("checked_sub" | "saturating_sub", [a, b]) => Some(a - b),
+        ("checked_mul" | "saturating_mul", [a, b]) => Some(a * b),
+        ("checked_div", [a, b]) => (*b != 0).then(|| a / b),
+        // `MIN % -1` is out of range because `MIN / -1` is
+        ("checked_rem", [a, b]) => (*b != 0 && numeric.contains(a / b)).then(|| a % b),
+        ("checked_neg", [a]) => Some(-a),
+        _ => return None,
+    };
+    if is_checked(name) {
+        return Some(exact.filter(|&n| numeric.contains(n)).map(|n| n as i64));
+    }
+    let (min, max) = numeric.range();
+    Some(exact.map(|n| n.clamp(min.into(), max.into()) as i64))
+}
+
+/// Methods of `Vec`, `Map`, slices, `str`, `StringBuf`, `Option` and vector
+/// types, see [`collection_method`]
+pub const COLLECTION_METHODS: &[&str] = &[
+    "push",
+    "pop",
+    "insert",
+    "get",
+    "contains",
+    "remove",
+    "clear",
+    "len",
+    "capacity",
+    "push_str",
+    "char_count",
+    "is_char_boundary",
+    "slice",
+    "chars",
+    "bytes",
+    "to_array",
+    "reduce_sum",
+    "reduce_min",
+    "reduce_max",
+    "mul_add",
+    "send",
+    "recv",
+    "sender",
+    "load",
+    "store",
+    "swap",
+    "fetch_add",
+    "fetch_sub",
+    "fetch_and",
+    "fetch_or",
+    "fetch_xor",
+    "fetch_max",
+    "fetch_min",
+    "compare_exchange",
+    "lock",
+    "read",
+    "write",
+    "set",
+    "replace",
+    "unlock",
+    "as_ptr",
+    "as_mut_ptr",
+    "add",
+    "sub",
+    "is_some",
+    "is_none",
+    "unwrap",
+    "expect",
+    "unwrap_or",
+];
+
+/// Parameters after `self`, result type, and whether `self` is borrowed
+/// mutably, of the method `name` of the collection, slice, string, `Option`
+/// or vector type `ty`
+pub fn collection_method(ty: &Type, name: &str) -> Option<(Vec<Type>, Type, bool)> {
+    let sig = match (ty, name) {
+        (Type::Vec(elem), "push") => (vec![(**elem).clone()], Type::Unit, true),
+        // Panics when empty
+        (Type::Vec(elem), "pop") => (Vec::new(), (**elem).clone(), true),
+        (Type::Map(key, value), "insert") => {
+            (vec![(**key).clone(), (**value).clone()], Type::Unit, true)
+        }
+        // Both panic when the key is missing
+        (Type::Map(key, value), "get") => (vec![(**key).clone()], (**value).clone(), false),
+        (Type::Map(key, value), "remove") => (vec![(**key).clone()], (**value).clone(), true),
+        (Type::Map(key, _), "contains") => (vec![(**key).clone()], Type::Bool, false),
+        (Type::Vec(_) | Type::Map(..), "clear") => (Vec::new(), Type::Unit, true),
+        (Type::Vec(_) | Type::Map(..), "len" | "capacity") => (Vec::new(), Type::Int, false),
+        (Type::Slice(_), "len") => (Vec::new(), Type::Int, false),
+        // Lengths and offsets of strings are in bytes
+        (Type::StringBuf, "push_str") => (vec![Type::Str], Type::Unit, true),
+        (Type::StringBuf, "clear") => (Vec::new(), Type::Unit, true),
+        (Type::StringBuf, "capacity") => (Vec::new(), Type::Int, false),
+        (Type::Str | Type::StringBuf, "len" | "char_count") => (Vec::new(), Type::Int, false),
+        (Type::Str | Type::StringBuf, "is_char_boundary") => (vec![Type::Int], Type::Bool, false),
+        // Panics out of bounds or between the bytes of a char; a
+        // `StringBuf` is sliced into a new one in the same arena
+        (Type::Str | Type::StringBuf, "slice") => (vec![Type::Int, Type::Int], ty.clone(), false),
+        // Code points or bytes, for `for` loops
+        (Type::Str | Type::StringBuf, "chars" | "bytes") => {
+            (Vec::new(), Type::Iter(Box::new(Type::Int)), false)
+        }
+        (Type::Simd(vector), "to_array") => (
+            Vec::new(),
+            Type::Array(Box::new(lane_type(vector)), vector.lanes),
+            false,
+        ),
+        // Reductions across the lanes
+        (Type::Simd(vector), "reduce_sum" | "reduce_min" | "reduce_max") => {
+            (Vec::new(), lane_type(vector), false)
+        }
+        // `self * a + b` lane by lane, fused for floats
+        (Type::Simd(_), "mul_add") => (vec![ty.clone(), ty.clone()], ty.clone(), false),
+        // Sending moves the message; both wait, while the channel is full
+        // or empty
+        (Type::Sender(elem), "send") => (vec![(**elem).clone()], Type::Unit, false),
+        (Type::Receiver(elem), "recv") => (Vec::new(), (**elem).clone(), false),
+        (Type::Receiver(elem), "sender") => (Vec::new(), Type::Sender(elem.clone()), false),
+        // Every atomic operation takes the orderings it is performed with,
+        // and the read-modify-writes return the previous value
+        (Type::Atomic(_), "load") => (vec![ordering()], Type::Int, false),
+        (Type::Atomic(_), "store") => (vec![Type::Int, ordering()], Type::Unit, false),
+        (Type::Atomic(_), method) if atomic::Rmw::from_method(method).is_some() => {
+            (vec![Type::Int, ordering()], Type::Int, false)
+        }
+        // Current and new values, and the orderings on success and on
+        // failure; returns the value found, which is `current` on success
+        (Type::Atomic(_), "compare_exchange") => (
+            vec![Type::Int, Type::Int, ordering(), ordering()],
+            Type::Int,
+            false,
+        ),
+        // Locking waits until no other thread holds the lock in a way that
+        // excludes this one
+        (Type::Mutex(value), "lock") => (
+            Vec::new(),
+            Type::Guard(GuardKind::Mutex, value.clone()),
+            false,
+        ),
+        (Type::RwLock(value), "read") => (
+            Vec::new(),
+            Type::Guard(GuardKind::Read, value.clone()),
+            false,
+        ),
+        (Type::RwLock(value), "write") => (
+            Vec::new(),
+            Type::Guard(GuardKind::Write, value.clone()),
+            false,
+        ),
+        // A guard reads the locked value, and an exclusive one replaces it;
+        // `unlock` is checked apart, since it consumes the guard
+        (Type::Guard(_, value), "get") => (Vec::new(), (**value).clone(), false),
+        (Type::Guard(kind, value), "set") if kind.access() == Access::Exclusive => {
+            (vec![(**value).clone()], Type::Unit, false)
+        }
+        (Type::Guard(kind, value), "replace") if kind.access() == Access::Exclusive => {
+            (vec![(**value).clone()], (**value).clone(), false)
+        }
+        // Pointers to the first element; only a mutable array or `Vec` gives one
+        // that may be written through
+        (Type::Array(elem, _) | Type::Vec(elem), "as_ptr") => {
+            (Vec::new(), Type::Ptr(false, elem.clone()), false)
+        }
+        (Type::Array(elem, _) | Type::Vec(elem), "as_mut_ptr") => {
+            (Vec::new(), Type::Ptr(true, elem.clone()), true)
+        }
+        // Offsets in elements, which need `unsafe`
+        (Type::Ptr(..), "add" | "sub") => (vec![Type::Int], ty.clone(), false),
+        (Type::Option(_), "is_some" | "is_none") => (Vec::new(), Type::Bool, false),
+        // The value held; `unwrap` panics on `None`, and `expect` with the
+        // message given
+        (Type::Option(value), "unwrap") => (Vec::new(), (**value).clone(), false),
+        (Type::Option(value), "expect") => (vec![Type::Str], (**value).clone(), false),
+        (Type::Option(value), "unwrap_or") => (vec![(**value).clone()], (**value).clone(), false),
+        _ => return None,
+    };
+    Some(sig)
+}
+
+/// Evaluate an integer intrinsic; `None` if `name` is not one
+pub fn int_intrinsic(name: &str, args: &[i64]) -> Option<i64> {
+    match (name, args) {
+        ("wrapping_add", [a, b]) => Some(a.wrapping_add(*b)),
+        ("wrapping_sub", [a, b]) => Some(a.wrapping_sub(*b)),
+        ("wrapping_mul", [a, b]) => Some(a.wrapping_mul(*b)),
+        ("wrapping_neg", [a]) => Some(a.wrapping_neg()),
+        ("popcount", [a]) => Some(i64::from(a.count_ones())),
+        ("ctz", [a]) => Some(i64::from(a.trailing_zeros())),
+        ("clz", [a]) => Some(i64::from(a.leading_zeros())),
+        _ => None,
+    }
+}
+
+/// Evaluate a float intrinsic; `None` if `name` is not one
+pub fn float_intrinsic(name: &str, args: &[f64]) -> Option<f64> {
+    match (name, args) {
+        ("fma", [a, b, c]) => Some(a.mul_add(*b, *c)),
+        _ => None,
+    }
+}
+
+/// The elements `start..end` (or `start..=end` when `inclusive`) of an
+/// array of length `len`, as a half-open range of indices; either end may
+/// be left out
+pub fn slice_bounds(
+    start: Option<i64>,
+    end: Option<i64>,
+    inclusive: bool,
+    len: usize,
+) -> Result<(usize, usize), String> {
+    let len = len as i128;
+    let first = start.map_or(0, i128::from);
+    let last = match end {
+        Some(end) if inclusive => i128::from(end) + 1,
+        Some(end) => i128::from(end),
+        None => len,
+    };
+    if first > last {
+        return Err(format!(
+            "slice index starts at {} but ends at {}",
+            first, last
+        ));
+    }
+    if first < 0 || last > len {
+        let index = if first < 0 { first } else { last };
+        return Err(format!(
+            "range index {} out of range for slice of length {}",
+            index, len
+        ));
+    }
+    Ok((first as usize, last as usize))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrapping_intrinsics() {
+        assert_eq!(
+            int_intrinsic("wrapping_add", &[i64::MAX, 1]),
+            Some(i64::MIN)
+        );
+        assert_eq!(int_intrinsic("wrapping_neg", &[i64::MIN]), Some(i64::MIN));
+        assert_eq!(int_intrinsic("print", &[1]), None);
+        assert_eq!(int_intrinsic("popcount", &[-1]), Some(64));
+        assert_eq!(int_intrinsic("clz", &[1]), Some(63));
+        assert_eq!(int_intrinsic("ctz", &[0]), Some(64));
+        assert_eq!(
+            float_intrinsic("fma", &[0.1, 10.0, -1.0]),
+            Some(5.551115123125783e-17)
+        );
+        assert_eq!(
+            signature("f64x2::from_array").map(|(params, _)| params),
+            Some(vec![Type::Array(Box::new(Type::Float), 2)])
+        );
+        assert_eq!(signature("f64x3::splat"), None);
+        assert!(FUNCTIONS.iter().all(|name| signature(name).is_some()));
+        assert!(DISTRIBUTIONS
+            .iter()
+            .all(|name| distribution(name).is_some()));
+    }
+
+    #[test]
+    fn test_int_ops() {
+        let u8 = Type::Sized(Numeric::U8);
+        assert_eq!(
+            int_op("checked_add", &[u8.clone(), u8.clone()]),
+            Ok(Type::Option(Box::new(u8.clone())))
+        );
+        assert_eq!(
+            int_op("saturating_mul", &[Type::Unknown, Type::Int]),
+            Ok(Type::Int)
+        );
+        assert_eq!(
+            int_op("checked_sub", &[u8, Type::Int]),
+            Err("mismatched types: expected `u8`, found `int`".to_string())
+        );
+        assert!(int_op("checked_neg", &[Type::Float]).is_err());
+        assert!(int_op("checked_neg", &[Type::Int, Type::Int]).is_err());
+
+        assert_eq!(
+            int_op_value("checked_add", &[250, 6], Numeric::U8),
+            Some(None)
+        );
+        assert_eq!(
+            int_op_value("checked_add", &[250, 5], Numeric::U8),
+            Some(Some(255))
+        );
+        assert_eq!(
+            int_op_value("saturating_sub", &[-100, 100], Numeric::I8),
+            Some(Some(-128))
+        );
+        assert_eq!(
+            int_op_value("saturating_mul", &[i64::MAX, 2], Numeric::I64),
+            Some(Some(i64::MAX))
+        );
+        assert_eq!(
+            int_op_value("checked_div", &[1, 0], Numeric::I64),
+            Some(None)
+        );
+        assert_eq!(
+            int_op_value("checked_rem", &[i64::MIN, -1], Numeric::I64),
+            Some(None)
+        );
+        assert_eq!(int_op_value("checked_neg", &[1], Numeric::U32), Some(None));
+        assert_eq!(int_op_value("wrapping_add", &[1, 2], Numeric::I64), None);
+    }
+
+    #[test]
+    fn test_belief_op_types() {
+        let belief = |t: Type| Type::Belief(Box::new(t));
+        assert_eq!(
+            belief_op("update", &[belief(Type::Float), belief(Type::Float)]),
+            Ok(belief(Type::Float))
+        );
+        assert_eq!(
+            belief_op("expectation", &[belief(Type::Bool)]),
+            Ok(Type::Float)
+        );
+        assert_eq!(belief_op("sample", &[belief(Type::Bool)]), Ok(Type::Bool));
+        assert!(belief_op("update", &[belief(Type::Float), belief(Type::Bool)]).is_err());
+        assert!(belief_op(
+            "marginalize",
+            &[
+                belief(Type::Float),
+                belief(Type::Float),
+                belief(Type::Float)
+            ]
+        )
+        .is_err());
+        assert!(belief_op("expectation", &[Type::Float]).is_err());
+
+        assert_eq!(belief_op("observe", &[Type::Bool]), Ok(Type::Unit));
+        assert_eq!(
+            belief_op("observe", &[belief(Type::Float), Type::Float]),
+            Ok(Type::Unit)
+        );
+        assert_eq!(
+            belief_op("observe", &[belief(Type::Float), Type::Bool]),
+            Err("mismatched types: expected `float`, found `bool`".to_string())
+        );
+        assert_eq!(
+            belief_op("observe", &[]),
+            Err("this function takes 1 or 2 arguments but 0 were supplied".to_string())
+        );
+        assert_eq!(belief_op("expect", &[Type::Bool]), Ok(Type::Float));
+        assert!(belief_op("expect", &[belief(Type::Float)]).is_err());
+    }
+
+    #[test]
+    fn test_slice_bounds() {
+        assert_eq!(slice_bounds(Some(1), Some(3), false, 4), Ok((1, 3)));
+        assert_eq!(slice_bounds(Some(1), Some(3), true, 4), Ok((1, 4)));
+        assert_eq!(slice_bounds(None, None, false, 4), Ok((0, 4)));
+        assert_eq!(slice_bounds(Some(4), None, false, 4), Ok((4, 4)));
+        assert_eq!(
+            slice_bounds(Some(2), Some(1), false, 4),
+            Err("slice index starts at 2 but ends at 1".to_string())
+        );
+        assert_eq!(
+            slice_bounds(None, Some(4), true, 4),
+            Err("range index 5 out of range for slice of length 4".to_string())
+        );
+        assert_eq!(
+            slice_bounds(Some(-1), None, false, 4),
+            Err("range index -1 out of range for slice of length 4".to_string())
+        );
+    }
+}
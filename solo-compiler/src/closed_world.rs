@@ -0,0 +1,650 @@
+//! Whole-program optimization under a closed-world assumption
+//!
+//! `solo build --closed-world` asserts that the program is all the code
+//! there is: nothing is loaded at run time and nothing outside calls in.
+//! Under that assumption [`optimize`] may:
+//!
+//! - propagate the value of every global scalar constant into its uses,
+//!   across the package library and the target alike,
+//! - fold calls of integer intrinsics (`checked_add`, `saturating_mul`,
+//!   `popcount`, ...) whose arguments are literals, propagated constants
+//!   included, into their results, and
+//! - strip every item `main` cannot reach, `pub extern "C"` functions
+//!   included, since no external caller is left to want them.
+//!
+//! Solo has no trait objects or function pointers, so every call already
+//! names the function it runs and there is nothing to devirtualize; method
+//! calls are resolved by the type of their receiver, which the checker
+//! knows. The [`Report`] lists what was removed, for `--closed-world` to
+//! print.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::ast::*;
+use crate::builtins;
+use crate::const_eval::ConstValue;
+use crate::numeric::Numeric;
+use crate::symbol::Symbol;
+use crate::typeck::TypeInfo;
+use crate::visit::{self, Visit};
+
+/// An item stripped from the program
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Removed {
+    /// The file the item is in, once [attributed](Report::attribute)
+    pub file: Option<PathBuf>,
+    pub span: Span,
+    /// `function`, `method`, `struct`, `enum`, `constant` or `extern function`
+    pub kind: &'static str,
+    /// Name of the item, `Type::method` for methods
+    pub name: String,
+}
+
+/// What closed-world optimization did to a program
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+    /// Unreachable items, in source order
+    pub removed: Vec<Removed>,
+    /// Number of constant uses replaced by their value
+    pub propagated: usize,
+    /// Number of intrinsic calls on literals replaced by their result
+    pub folded: usize,
+}
+
+impl Report {
+    /// Place every removed item in a file: `place` returns the file a span
+    /// of the compiled source is in and makes the span relative to it, as
+    /// for a package target compiled with its library appended
+    pub fn attribute(&mut self, mut place: impl FnMut(&mut Span) -> PathBuf) {
+        for item in &mut self.removed {
+            item.file = Some(place(&mut item.span));
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for item in &self.removed {
+            write!(f, "removed {} `{}` (", item.kind, item.name)?;
+            if let Some(file) = &item.file {
+                write!(f, "{}:", file.display())?;
+            }
+            writeln!(f, "{}:{})", item.span.line, item.span.column)?;
+        }
+        write!(
+            f,
+            "{} item(s) removed, {} constant use(s) propagated",
+            self.removed.len(),
+            self.propagated
+        )?;
+        if self.folded > 0 {
+            write!(f, ", {} call(s) folded", self.folded)?;
+        }
+        writeln!(f)
+    }
+}
+
+/// Optimize a checked program whose only entry point is `main`
+pub fn optimize(program: &mut Program, info: &TypeInfo) -> Report {
+    optimize_from(program, info, &["main".to_string()])
+}
+
+/// Optimize a checked program whose only entry points are the items named
+/// in `roots`, which are kept whether reachable or not
+pub fn optimize_from(program: &mut Program, info: &TypeInfo, roots: &[String]) -> Report {
+    let functions = program
+        .functions()
+        .into_iter()
+        .map(|(name, ..)| name)
+        .collect();
+    let mut propagator = Propagator {
+        exprs: &mut program.exprs,
+        consts: &info.consts,
+        numeric: &info.numeric,
+        functions,
+        scopes: Vec::new(),
+        propagated: 0,
+        folded: 0,
+    };
+    for item in &mut program.items {
+        match item {
+            Item::Function(function) => propagator.function(function),
+            Item::Impl(block) => block
+                .methods
+                .iter_mut()
+                .for_each(|method| propagator.function(method)),
+            _ => {}
+        }
+    }
+    let (propagated, folded) = (propagator.propagated, propagator.folded);
+
+    let reachable = reachable(program, roots);
+    let mut removed = Vec::new();
+    let mut strip = |kind, name: &str, span| {
+        let keep = reachable.contains(name);
+        if !keep {
+            removed.push(Removed {
+                file: None,
+                span,
+                kind,
+                name: name.to_string(),
+            });
+        }
+        keep
+    };
+    program.items.retain_mut(|item| match item {
+        Item::Function(function) => strip("function", &function.name, function.span),
+        Item::Const(c) => strip("constant", &c.name, c.span),
+        Item::Enum(def) => strip("enum", &def.name, def.span),
+        Item::Struct(def) => strip("struct", &def.name, def.span),
+        Item::TypeAlias(alias) => strip("type alias", &alias.name, alias.span),
+        Item::Impl(block) => {
+            let self_ty = &block.self_ty;
+            block.methods.retain(|method| {
+                let name = format!("{}::{}", self_ty, method.name);
+                strip("method", &name, method.span)
+            });
+            !block.methods.is_empty()
+        }
+        Item::Extern(block) => {
+            block
+                .functions
+                .retain(|function| strip("extern function", &function.name, function.span));
+            !block.functions.is_empty()
+        }
+    });
+    removed.sort();
+    Report {
+        removed,
+        propagated,
+        folded,
+    }
+}
+
+/// Replaces uses of global constants with their values, and intrinsic
+/// calls on literals with their results
+struct Propagator<'a> {
+    exprs: &'a mut Exprs,
+    consts: &'a HashMap<String, ConstValue>,
+    /// The sized integer type of each intrinsic call that has one
+    numeric: &'a HashMap<Span, Numeric>,
+    /// Names of the functions and methods, which shadow builtins
+    functions: HashSet<String>,
+    /// Names of the locals in scope, which shadow constants
+    scopes: Vec<Vec<Symbol>>,
+    propagated: usize,
+    folded: usize,
+}
+
+impl Propagator<'_> {
+    fn function(&mut self, function: &mut Function) {
+        self.scopes = vec![function.params.iter().map(|p| p.name).collect()];
+        self.block(&mut function.body);
+    }
+
+    fn declare(&mut self, name: Symbol) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.push(name);
+        }
+    }
+
+    fn shadowed(&self, name: Symbol) -> bool {
+        self.scopes.iter().flatten().any(|&local| local == name)
+    }
+
+    /// Whether `name` names a builtin here, rather than a local, constant
+    /// or function
+    fn is_builtin(&self, name: Symbol) -> bool {
+        !self.shadowed(name)
+            && !self.consts.contains_key(name.as_str())
+            && !self.functions.contains(name.as_str())
+    }
+
+    /// The result of the call at `span` of an integer intrinsic whose
+    /// arguments are all literals, as an expression
+    fn fold(&mut self, callee: ExprId, args: &[Expr], span: Span) -> Option<ExprKind> {
+        let ExprKind::Path(segments) = &self.exprs[callee].kind else {
+            return None;
+        };
+        let [name] = segments[..] else {
+            return None;
+        };
+        if !self.is_builtin(name) {
+            return None;
+        }
+        let args: Vec<i64> = args
+            .iter()
+            .map(|arg| match arg.kind {
+                ExprKind::Int(n, _) => Some(n),
+                _ => None,
+            })
+            .collect::<Option<_>>()?;
+        if !builtins::is_int_op(&name) {
+            return builtins::int_intrinsic(&name, &args).map(|n| ExprKind::Int(n, None));
+        }
+        // Only sized types are recorded, and a literal of one is suffixed
+        let suffix = self.numeric.get(&span).copied();
+        let result = builtins::int_op_value(&name, &args, suffix.unwrap_or(Numeric::I64))?;
+        if !builtins::is_checked(&name) {
+            return result.map(|n| ExprKind::Int(n, suffix));
+        }
+        let (some, none) = (Symbol::intern("Some"), Symbol::intern("None"));
+        if !self.is_builtin(some) || !self.is_builtin(none) {
+            return None;
+        }
+        Some(match result {
+            Some(n) => {
+                let path = Expr::new(ExprKind::Path(vec![some]), self.exprs[callee].span);
+                let value = Expr::new(ExprKind::Int(n, suffix), span);
+                ExprKind::Call(self.exprs.alloc(path), vec![value])
+            }
+            None => ExprKind::Path(vec![none]),
+        })
+    }
+
+    fn block(&mut self, block: &mut Block) {
+        self.scopes.push(Vec::new());
+        for stmt in &mut block.stmts {
+            match stmt {
+                Stmt::Let(stmt) => {
+                    if let Some(value) = &mut stmt.value {
+                        self.expr(value);
+                    }
+                    self.declare(stmt.name);
+                }
+                Stmt::Const(c) => {
+                    self.expr(&mut c.value);
+                    self.declare(c.name);
+                }
+                Stmt::Expr(expr) | Stmt::Defer(expr) => self.expr(expr),
+            }
+        }
+        if let Some(tail) = block.tail {
+            self.nested(tail);
+        }
+        self.scopes.pop();
+    }
+
+    fn nested(&mut self, id: ExprId) {
+        let mut expr = self.exprs.take(id);
+        self.expr(&mut expr);
+        self.exprs[id] = expr;
+    }
+
+    fn expr(&mut self, expr: &mut Expr) {
+        match &mut expr.kind {
+            ExprKind::Path(segments) if segments.len() == 1 => {
+                let name = segments[0];
+                if self.shadowed(name) {
+                    return;
+                }
+                let kind = match self.consts.get(name.as_str()) {
+                    Some(ConstValue::Int(n)) => ExprKind::Int(*n, None),
+                    Some(ConstValue::Float(x)) => ExprKind::Float(*x, None),
+                    Some(ConstValue::Bool(b)) => ExprKind::Bool(*b),
+                    Some(ConstValue::Str(s)) => ExprKind::Str(Symbol::intern(s)),
+                    _ => return,
+                };
+                expr.kind = kind;
+                self.propagated += 1;
+            }
+            ExprKind::Int(..)
+            | ExprKind::Float(..)
+            | ExprKind::Str(_)
+            | ExprKind::Bool(_)
+            | ExprKind::Path(_) => {}
+            ExprKind::Unary(_, operand) | ExprKind::Cast(operand, _) => self.nested(*operand),
+            // Constants cannot be assigned to, so an assigned path stays
+            ExprKind::Binary(_, left, right)
+            | ExprKind::Index(left, right)
+            | ExprKind::Assign(left, right) => {
+                self.nested(*left);
+                self.nested(*right);
+            }
+            ExprKind::Call(callee, args) => {
+                if !matches!(self.exprs[*callee].kind, ExprKind::Path(_)) {
+                    self.nested(*callee);
+                }
+                args.iter_mut().for_each(|arg| self.expr(arg));
+                if let Some(kind) = self.fold(*callee, args, expr.span) {
+                    expr.kind = kind;
+                    self.folded += 1;
+                }
+            }
+            ExprKind::Field(receiver, _) => self.nested(*receiver),
+            ExprKind::MethodCall(receiver, _, args) => {
+                self.nested(*receiver);
+                args.iter_mut().for_each(|arg| self.expr(arg));
+            }
+            ExprKind::StructLit(_, fields) => {
+                fields
+                    .iter_mut()
+                    .for_each(|field| self.expr(&mut field.value));
+            }
+            ExprKind::Distribution(_, args) | ExprKind::Array(args) => {
+                args.iter_mut().for_each(|arg| self.expr(arg));
+            }
+            ExprKind::ArrayRepeat(value, len) => {
+                self.nested(*value);
+                self.nested(*len);
+            }
+            ExprKind::Range(start, end, _) => {
+                for end in [start, end].into_iter().flatten() {
+                    self.nested(*end);
+                }
+            }
+            ExprKind::If(cond, then, otherwise) => {
+                self.nested(*cond);
+                self.block(then);
+                if let Some(otherwise) = otherwise {
+                    self.nested(*otherwise);
+                }
+            }
+            ExprKind::While(cond, body) => {
+                self.nested(*cond);
+                self.block(body);
+            }
+            ExprKind::For(name, iterable, body) => {
+                self.nested(*iterable);
+                self.scopes.push(vec![*name]);
+                self.block(body);
+                self.scopes.pop();
+            }
+            ExprKind::Match(scrutinee, arms) => {
+                self.nested(*scrutinee);
+                for arm in arms {
+                    let names = arm.pattern.bindings().into_iter().map(|(name, _)| name);
+                    self.scopes.push(names.collect());
+                    if let Some(guard) = arm.guard {
+                        self.nested(guard);
+                    }
+                    self.nested(arm.value);
+                    self.scopes.pop();
+                }
+            }
+            ExprKind::Block(block) | ExprKind::Unsafe(block) => self.block(block),
+            ExprKind::Arena(name, allocator, block) => {
+                if let Some(allocator) = allocator {
+                    self.nested(*allocator);
+                }
+                self.scopes.push(name.iter().copied().collect());
+                self.block(block);
+                self.scopes.pop();
+            }
+            ExprKind::Return(value) => {
+                if let Some(value) = value {
+                    self.nested(*value);
+                }
+            }
+            ExprKind::Become(call) => self.nested(*call),
+            ExprKind::Asm(_, operands) => {
+                for operand in operands {
+                    self.expr(&mut operand.expr);
+                }
+            }
+        }
+    }
+}
+
+/// Names of the items reachable from `roots`, methods as `Type::method`
+///
+/// A method call reaches every method of that name: the receiver's type is
+/// not recorded in the tree, and keeping a method too many is harmless.
+pub(crate) fn reachable(program: &Program, roots: &[String]) -> HashSet<String> {
+    let mut items: HashMap<String, Vec<Node>> = HashMap::new();
+    let mut methods: HashMap<&str, Vec<String>> = HashMap::new();
+    for item in &program.items {
+        match item {
+            Item::Function(function) => {
+                items.insert(function.name.to_string(), vec![Node::Function(function)]);
+            }
+            Item::Const(c) => {
+                items.insert(
+                    c.name.to_string(),
+                    vec![Node::Type(&c.ty), Node::Expr(&c.value)],
+                );
+            }
+            Item::Enum(def) => {
+                let discriminants = def.variants.iter().filter_map(|v| v.discriminant.as_ref());
+                items.insert(
+                    def.name.to_string(),
+                    discriminants.map(Node::Expr).collect(),
+                );
+            }
+            Item::Struct(def) => {
+                let fields = def.fields.iter().map(|field| Node::Type(&field.ty));
+                items.insert(def.name.to_string(), fields.collect());
+            }
+            Item::TypeAlias(alias) => {
+                items.insert(alias.name.to_string(), vec![Node::Type(&alias.ty)]);
+            }
+            Item::Impl(block) => {
+                for method in &block.methods {
+                    let name = format!("{}::{}", block.self_ty, method.name);
+                    methods.entry(&method.name).or_default().push(name.clone());
+                    items.insert(name, vec![Node::Function(method)]);
+                }
+            }
+            Item::Extern(block) => {
+                for function in &block.functions {
+                    let params = function.params.iter().map(|p| Node::Type(&p.ty));
+                    let ret = function.return_type.iter().map(Node::Type);
+                    items.insert(function.name.to_string(), params.chain(ret).collect());
+                }
+            }
+        }
+    }
+
+    let mut reached = HashSet::new();
+    let mut pending = roots.to_vec();
+    while let Some(name) = pending.pop() {
+        let Some(nodes) = items.get(&name) else {
+            continue;
+        };
+        if !reached.insert(name) {
+            continue;
+        }
+        let mut refs = Refs {
+            exprs: &program.exprs,
+            refs: Vec::new(),
+        };
+        for node in nodes {
+            node.refs(&mut refs);
+        }
+        for name in refs.refs {
+            match name {
+                Ref::Name(name) => pending.push(name),
+                Ref::Method(method) => {
+                    pending.extend(methods.get(method.as_str()).into_iter().flatten().cloned())
+                }
+            }
+        }
+    }
+    reached
+}
+
+/// Part of an item that refers to other items
+enum Node<'a> {
+    Function(&'a Function),
+    Type(&'a TypeExpr),
+    Expr(&'a Expr),
+}
+
+/// A reference to an item
+enum Ref {
+    Name(String),
+    /// A method called by name on a value
+    Method(String),
+}
+
+impl Node<'_> {
+    fn refs(&self, refs: &mut Refs<'_>) {
+        match self {
+            Node::Function(function) => refs.visit_function(function),
+            Node::Type(ty) => refs.visit_type(ty),
+            Node::Expr(expr) => refs.visit_expr(expr),
+        }
+    }
+}
+
+/// Collects the references of the nodes it visits
+struct Refs<'a> {
+    exprs: &'a Exprs,
+    refs: Vec<Ref>,
+}
+
+impl<'a> Visit<'a> for Refs<'a> {
+    fn exprs(&self) -> &'a Exprs {
+        self.exprs
+    }
+
+    fn visit_type(&mut self, ty: &TypeExpr) {
+        if let TypeExpr::Named(name, _) | TypeExpr::Generic { name, .. } = ty {
+            self.refs.push(Ref::Name(name.to_string()));
+        }
+        visit::walk_type(self, ty);
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            // `f`, `CONST`, `Enum::Variant` and `Type::method`
+            ExprKind::Path(segments) => {
+                self.refs.push(Ref::Name(segments[0].to_string()));
+                if segments.len() > 1 {
+                    self.refs.push(Ref::Name(join_path(segments)));
+                }
+            }
+            ExprKind::MethodCall(_, method, _) => self.refs.push(Ref::Method(method.to_string())),
+            // Iterating over a value calls its `has_next` and `next`
+            ExprKind::For(..) => {
+                self.refs.push(Ref::Method("has_next".to_string()));
+                self.refs.push(Ref::Method("next".to_string()));
+            }
+            ExprKind::StructLit(name, _) => self.refs.push(Ref::Name(name.to_string())),
+            ExprKind::Match(_, arms) => {
+                for arm in arms {
+                    variant_refs(&arm.pattern, &mut self.refs);
+                }
+            }
+            _ => {}
+        }
+        visit::walk_expr(self, expr);
+    }
+}
+
+/// The enums and variants `Enum::Variant` patterns in `pattern` name
+fn variant_refs(pattern: &Pattern, refs: &mut Vec<Ref>) {
+    match &pattern.kind {
+        PatternKind::Variant(name, variant) => {
+            refs.push(Ref::Name(name.to_string()));
+            refs.push(Ref::Name(format!("{}::{}", name, variant)));
+        }
+        PatternKind::Some(pattern) => variant_refs(pattern, refs),
+        PatternKind::Or(patterns) => patterns.iter().for_each(|p| variant_refs(p, refs)),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_optimize() {
+        let source = "#![feature(extern_c)]
+             const LIMIT: i64 = 10;
+             const UNUSED: i64 = 1;
+             struct Story { words: i64 }
+             struct Draft { words: i64 }
+             impl Story {
+                 fn long(&self) -> bool { self.words > LIMIT }
+                 fn retract(&self) {}
+             }
+             extern \"C\" { fn abs(x: i32) -> i32; fn labs(x: i64) -> i64; }
+             pub extern \"C\" fn score() -> i64 { 1 }
+             fn helper(LIMIT: i64) -> i64 { LIMIT }
+             fn main() {
+                 let story = Story { words: 12 };
+                 print(story.long());
+                 print(unsafe { labs(0 - LIMIT) });
+             }";
+        let (mut program, info) = crate::analyze(source).unwrap();
+        let report = optimize(&mut program, &info);
+        let removed: Vec<(&str, &str)> = report
+            .removed
+            .iter()
+            .map(|r| (r.kind, r.name.as_str()))
+            .collect();
+        assert_eq!(
+            removed,
+            [
+                ("constant", "LIMIT"),
+                ("constant", "UNUSED"),
+                ("struct", "Draft"),
+                ("method", "Story::retract"),
+                ("extern function", "abs"),
+                ("function", "score"),
+                ("function", "helper"),
+            ]
+        );
+        assert_eq!(report.propagated, 2);
+        assert!(report
+            .to_string()
+            .ends_with("7 item(s) removed, 2 constant use(s) propagated\n"));
+
+        // Spans past line 3 are in a library appended after the target
+        let mut report = report;
+        report.attribute(|span| {
+            if span.line > 3 {
+                span.line -= 3;
+                PathBuf::from("src/lib.solo")
+            } else {
+                PathBuf::from("src/main.solo")
+            }
+        });
+        let lines: Vec<String> = report.to_string().lines().map(str::to_string).collect();
+        assert_eq!(lines[0], "removed constant `LIMIT` (src/main.solo:2:14)");
+        assert_eq!(lines[6], "removed function `helper` (src/lib.solo:9:14)");
+    }
+
+    #[test]
+    fn test_fold_intrinsics() {
+        let source = "const LEVEL: u8 = 250;
+             fn main() {
+                 let n = 3;
+                 print(checked_add(LEVEL, 10u8));
+                 print(saturating_add(LEVEL, 10u8));
+                 print(checked_mul(4, 5));
+                 print(popcount(7));
+                 print(checked_add(n, 1));
+             }";
+        let (mut program, info) = crate::analyze(source).unwrap();
+        let report = optimize(&mut program, &info);
+        assert_eq!((report.propagated, report.folded), (2, 4));
+        assert!(report.to_string().ends_with(", 4 call(s) folded\n"));
+        let main = program
+            .items
+            .iter()
+            .find_map(|item| match item {
+                Item::Function(f) if f.name == "main" => Some(f),
+                _ => None,
+            })
+            .unwrap();
+        let printed: Vec<String> = main.body.stmts[1..]
+            .iter()
+            .map(|stmt| program.exprs.display(stmt).to_string())
+            .collect();
+        assert_eq!(
+            printed,
+            [
+                "print(None);",
+                "print(255u8);",
+                "print(Some(20));",
+                "print(3);",
+                "print(checked_add(n, 1));"
+            ]
+        );
+    }
+}
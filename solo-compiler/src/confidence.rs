@@ -0,0 +1,367 @@
+//! Static bounds on belief values
+//!
+//! Belief types may be refined with bounds the type checker must prove:
+//! `Belief<bool, confidence >= 0.9>` bounds the probability of `true`, and
+//! `Belief<f64, std_dev <= 0.5>` or `Belief<f64, interval <= 2.0>` bound the
+//! spread of a numeric belief. `interval` is the width of the 95% credible
+//! interval under a normal approximation, `2 × 1.96 × std_dev`.
+//!
+//! [`Bounds`] over-approximates every value an expression can produce and is
+//! propagated through distribution literals, `update` and `marginalize` with
+//! interval arithmetic.
+
+use crate::ast::{BinaryOp, Bound};
+
+/// z-score of the central 95% interval of a normal distribution
+const Z95: f64 = 1.959_963_984_540_054;
+
+/// What is statically known about a belief
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Bounds {
+    /// The probability of `true` lies in `[lo, hi]`
+    Bool { lo: f64, hi: f64 },
+    /// A normal distribution or a mixture of normals
+    Real(Spread),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Spread {
+    /// Range containing the mean of every component
+    pub mean: (f64, f64),
+    /// Largest standard deviation of any component
+    pub sigma: f64,
+    /// Known to be a single normal distribution rather than a mixture
+    pub single: bool,
+    /// Upper bound on the overall standard deviation
+    pub std_dev: f64,
+}
+
+impl Bounds {
+    /// Bounds of `~name(params)`, where unknown parameters are `None`
+    pub fn distribution(name: &str, params: &[Option<f64>]) -> Option<Bounds> {
+        match (name, params) {
+            ("Bernoulli", [p]) => Some(Bounds::Bool {
+                lo: p.unwrap_or(0.0),
+                hi: p.unwrap_or(1.0),
+            }),
+            ("Normal", [mean, std_dev]) => {
+                let sigma = std_dev.unwrap_or(f64::INFINITY);
+                Some(Bounds::Real(Spread {
+                    mean: mean.map_or((-f64::INFINITY, f64::INFINITY), |m| (m, m)),
+                    sigma,
+                    single: true,
+                    std_dev: sigma,
+                }))
+            }
+            _ => None,
+        }
+    }
+
+    /// What a type annotation guarantees; `None` when it has no bounds
+    pub fn declared(bounds: &[Bound]) -> Option<Bounds> {
+        let first = bounds.first()?;
+        if first.property == "confidence" {
+            let (mut lo, mut hi) = (0.0, 1.0);
+            for bound in bounds {
+                match bound.op {
+                    BinaryOp::Ge => lo = f64::max(lo, bound.value),
+                    _ => hi = f64::min(hi, bound.value),
+                }
+            }
+            return Some(Bounds::Bool { lo, hi });
+        }
+        let std_dev = bounds
+            .iter()
+            .map(|bound| match bound.property.as_str() {
+                "interval" => bound.value / (2.0 * Z95),
+                _ => bound.value,
+            })
+            .fold(f64::INFINITY, f64::min);
+        Some(Bounds::Real(Spread {
+            mean: (-f64::INFINITY, f64::INFINITY),
+            sigma: f64::INFINITY,
+            single: false,
+            std_dev,
+        }))
+    }
+
+    /// Bounds of `update(prior, evidence)`
+    pub fn update(prior: Bounds, evidence: Bounds) -> Option<Bounds> {
+        match (prior, evidence) {
+            // The posterior probability increases with both arguments
+            (Bounds::Bool { lo: p0, hi: p1 }, Bounds::Bool { lo: q0, hi: q1 }) => {
+                Some(Bounds::Bool {
+                    lo: posterior(p0, q0).unwrap_or(0.0),
+                    hi: posterior(p1, q1).unwrap_or(1.0),
+                })
+            }
+            // Each posterior component mean lies between a prior and an
+            // evidence mean, and its variance shrinks
+            (Bounds::Real(a), Bounds::Real(b)) => {
+                let mean = hull(a.mean, b.mean);
+                let sigma = combine(a.sigma, b.sigma);
+                let single = a.single && b.single;
+                let std_dev = if single {
+                    sigma
+                } else {
+                    mixture_std_dev(mean, sigma)
+                };
+                Some(Bounds::Real(Spread {
+                    mean,
+                    sigma,
+                    single,
+                    std_dev,
+                }))
+            }
+            _ => None,
+        }
+    }
+
+    /// Bounds of `marginalize(condition, if_true, if_false)`
+    pub fn marginalize(
+        condition: Option<Bounds>,
+        if_true: Bounds,
+        if_false: Bounds,
+    ) -> Option<Bounds> {
+        let (c0, c1) = match condition {
+            Some(Bounds::Bool { lo, hi }) => (lo, hi),
+            _ => (0.0, 1.0),
+        };
+        match (if_true, if_false) {
+            // Bilinear in the three probabilities, so extremes are at corners
+            (Bounds::Bool { lo: t0, hi: t1 }, Bounds::Bool { lo: f0, hi: f1 }) => {
+                let mut corners = Vec::with_capacity(8);
+                for c in [c0, c1] {
+                    for t in [t0, t1] {
+                        for f in [f0, f1] {
+                            corners.push(c * t + (1.0 - c) * f);
+                        }
+                    }
+                }
+                Some(Bounds::Bool {
+                    lo: corners.iter().copied().fold(1.0, f64::min),
+                    hi: corners.iter().copied().fold(0.0, f64::max),
+                })
+            }
+            // Law of total variance: within-branch variance plus the variance
+            // of the branch means, which is at most a quarter of their range
+            (Bounds::Real(a), Bounds::Real(b)) => {
+                let mean = hull(a.mean, b.mean);
+                let sigma = f64::max(a.sigma, b.sigma);
+                let between = f64::max(a.std_dev, b.std_dev);
+                Some(Bounds::Real(Spread {
+                    mean,
+                    sigma,
+                    single: false,
+                    std_dev: f64::min(mixture_std_dev(mean, sigma), mixture_std_dev(mean, between)),
+                }))
+            }
+            _ => None,
+        }
+    }
+
+    /// Bounds covering both `a` and `b`
+    pub fn join(a: Bounds, b: Bounds) -> Option<Bounds> {
+        match (a, b) {
+            (Bounds::Bool { lo: a0, hi: a1 }, Bounds::Bool { lo: b0, hi: b1 }) => {
+                Some(Bounds::Bool {
+                    lo: f64::min(a0, b0),
+                    hi: f64::max(a1, b1),
+                })
+            }
+            (Bounds::Real(a), Bounds::Real(b)) => Some(Bounds::Real(Spread {
+                mean: hull(a.mean, b.mean),
+                sigma: f64::max(a.sigma, b.sigma),
+                single: a.single && b.single,
+                std_dev: f64::max(a.std_dev, b.std_dev),
+            })),
+            _ => None,
+        }
+    }
+
+    /// Whether every value within these bounds satisfies `bound`; the error
+    /// describes what is known instead
+    pub fn check(&self, bound: &Bound) -> Result<(), String> {
+        match (self, bound.property.as_str()) {
+            (Bounds::Bool { lo, hi }, "confidence") => {
+                let holds = match bound.op {
+                    BinaryOp::Ge => *lo >= bound.value,
+                    _ => *hi <= bound.value,
+                };
+                if holds {
+                    Ok(())
+                } else {
+                    Err(format!(
+                        "its confidence is only known to be between {} and {}",
+                        number(*lo),
+                        number(*hi)
+                    ))
+                }
+            }
+            (Bounds::Real(spread), property @ ("std_dev" | "interval")) => {
+                let scale = if property == "interval" {
+                    2.0 * Z95
+                } else {
+                    1.0
+                };
+                let max = spread.std_dev * scale;
+                // Tolerate rounding when converting between `interval` and `std_dev`
+                if max <= bound.value * (1.0 + 1e-12) {
+                    Ok(())
+                } else if max.is_finite() {
+                    Err(format!(
+                        "its {} is only known to be at most {}",
+                        property,
+                        number(max)
+                    ))
+                } else {
+                    Err(format!("its {} could be unbounded", property))
+                }
+            }
+            // Bounds on the wrong kind of belief are rejected by `validate`
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Check that `bound` is meaningful for a belief about `value_type`
+pub fn validate(bound: &Bound, value_type: &str) -> Result<(), String> {
+    match (bound.property.as_str(), value_type) {
+        ("confidence", "bool") => {
+            if (0.0..=1.0).contains(&bound.value) {
+                Ok(())
+            } else {
+                Err(format!(
+                    "confidence bound must be between 0 and 1, found {}",
+                    bound.value
+                ))
+            }
+        }
+        ("std_dev" | "interval", "f64") => {
+            if bound.op != BinaryOp::Le {
+                Err(format!(
+                    "`{}` can only be bounded above with `<=`",
+                    bound.property
+                ))
+            } else if bound.value <= 0.0 {
+                Err(format!(
+                    "`{}` bound must be positive, found {}",
+                    bound.property, bound.value
+                ))
+            } else {
+                Ok(())
+            }
+        }
+        ("confidence", _) => Err(format!(
+            "`confidence` bounds apply to `Belief<bool>`, not `Belief<{}>`",
+            value_type
+        )),
+        ("std_dev" | "interval", _) => Err(format!(
+            "`{}` bounds apply to `Belief<f64>`, not `Belief<{}>`",
+            bound.property, value_type
+        )),
+        (property, _) => Err(format!(
+            "unknown belief bound `{}`; expected `confidence`, `std_dev` or `interval`",
+            property
+        )),
+    }
+}
+
+/// Posterior probability of `true` after a Bernoulli update; `None` when the
+/// evidence is impossible under the prior
+fn posterior(p: f64, q: f64) -> Option<f64> {
+    let likelihood = p * q + (1.0 - p) * (1.0 - q);
+    (likelihood > 0.0).then(|| p * q / likelihood)
+}
+
+/// Standard deviation of a normal-normal posterior, increasing in both
+fn combine(a: f64, b: f64) -> f64 {
+    if a.is_infinite() {
+        b
+    } else if b.is_infinite() {
+        a
+    } else {
+        a * b / (a * a + b * b).sqrt()
+    }
+}
+
+/// Bound on the standard deviation of any mixture of components with means
+/// in `mean` and standard deviations at most `sigma`
+fn mixture_std_dev(mean: (f64, f64), sigma: f64) -> f64 {
+    let range = mean.1 - mean.0;
+    (sigma * sigma + range * range / 4.0).sqrt()
+}
+
+fn hull(a: (f64, f64), b: (f64, f64)) -> (f64, f64) {
+    (f64::min(a.0, b.0), f64::max(a.1, b.1))
+}
+
+/// Format a bound for an error message without float noise
+fn number(x: f64) -> String {
+    let s = format!("{:.4}", x);
+    s.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Span;
+
+    fn bound(property: &str, op: BinaryOp, value: f64) -> Bound {
+        Bound {
+            property: property.to_string(),
+            op,
+            value,
+            span: Span::default(),
+        }
+    }
+
+    #[test]
+    fn test_propagation() {
+        let prior = Bounds::distribution("Bernoulli", &[Some(0.9)]).unwrap();
+        let evidence = Bounds::distribution("Bernoulli", &[None]).unwrap();
+        let at_least = bound("confidence", BinaryOp::Ge, 0.9);
+        assert!(prior.check(&at_least).is_ok());
+        // Unknown evidence can drive the posterior anywhere
+        assert!(Bounds::update(prior, evidence)
+            .unwrap()
+            .check(&at_least)
+            .is_err());
+
+        let strong = Bounds::distribution("Bernoulli", &[Some(0.8)]).unwrap();
+        let posterior = Bounds::update(prior, strong).unwrap();
+        assert!(posterior
+            .check(&bound("confidence", BinaryOp::Ge, 0.97))
+            .is_ok());
+
+        let wet = Bounds::marginalize(
+            None,
+            Bounds::distribution("Bernoulli", &[Some(0.95)]).unwrap(),
+            Bounds::distribution("Bernoulli", &[Some(0.99)]).unwrap(),
+        )
+        .unwrap();
+        assert!(wet.check(&at_least).is_ok());
+        assert!(wet.check(&bound("confidence", BinaryOp::Ge, 0.96)).is_err());
+
+        let narrow = bound("std_dev", BinaryOp::Le, 1.0);
+        let normal = |m, s| Bounds::distribution("Normal", &[Some(m), Some(s)]).unwrap();
+        let unknown_spread = Bounds::distribution("Normal", &[Some(0.0), None]).unwrap();
+        assert!(unknown_spread.check(&narrow).is_err());
+        // Updating with precise evidence narrows even an unknown prior
+        let posterior = Bounds::update(unknown_spread, normal(1.0, 0.5)).unwrap();
+        assert!(posterior.check(&narrow).is_ok());
+        // Mixing distant means widens the result
+        let mixed = Bounds::marginalize(None, normal(0.0, 0.5), normal(10.0, 0.5)).unwrap();
+        assert!(mixed.check(&narrow).is_err());
+        assert!(mixed.check(&bound("interval", BinaryOp::Le, 25.0)).is_ok());
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(validate(&bound("confidence", BinaryOp::Ge, 0.95), "bool").is_ok());
+        assert!(validate(&bound("confidence", BinaryOp::Ge, 1.5), "bool").is_err());
+        assert!(validate(&bound("confidence", BinaryOp::Ge, 0.95), "f64").is_err());
+        assert!(validate(&bound("std_dev", BinaryOp::Ge, 1.0), "f64").is_err());
+        assert!(validate(&bound("variance", BinaryOp::Le, 1.0), "f64").is_err());
+    }
+}
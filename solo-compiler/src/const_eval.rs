@@ -0,0 +1,1107 @@
+//! Compile-time constant evaluation
+//!
+//! Evaluates `const` items, `const fn` calls, array lengths, and enum
+//! discriminants during type checking. Integer arithmetic is checked: any
+//! overflow, division by zero, or non-const operation is a compile error
+//! reported at the offending expression.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::ast::*;
+use crate::builtins;
+use crate::error::Diagnostic;
+use crate::numeric::{Numeric, Scalar};
+use crate::symbol::Symbol;
+
+/// Maximum number of expressions evaluated for a single constant
+pub const STEP_LIMIT: usize = 1_000_000;
+
+/// Maximum `const fn` call depth
+pub const CALL_DEPTH_LIMIT: usize = 256;
+
+/// A value computed at compile time
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConstValue {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Str(String),
+    Unit,
+    Array(Vec<ConstValue>),
+    /// Enum variant with its discriminant
+    Variant(String, String, i64),
+    /// Struct with fields in declaration order
+    Struct(String, Vec<(String, ConstValue)>),
+    /// `Some` value, or `None`
+    Option(Option<Box<ConstValue>>),
+    /// `start..end`, or `start..=end` when inclusive
+    Range(Option<i64>, Option<i64>, bool),
+}
+
+impl fmt::Display for ConstValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConstValue::Int(n) => write!(f, "{}", n),
+            ConstValue::Float(x) => write!(f, "{:?}", x),
+            ConstValue::Bool(b) => write!(f, "{}", b),
+            ConstValue::Str(s) => write!(f, "{:?}", s),
+            ConstValue::Unit => write!(f, "()"),
+            ConstValue::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            ConstValue::Variant(enum_name, variant, _) => write!(f, "{}::{}", enum_name, variant),
+            ConstValue::Struct(name, fields) => {
+                write!(f, "{} {{ ", name)?;
+                for (i, (field, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", field, value)?;
+                }
+                write!(f, " }}")
+            }
+            ConstValue::Option(Some(value)) => write!(f, "Some({})", value),
+            ConstValue::Option(None) => write!(f, "None"),
+            ConstValue::Range(start, end, inclusive) => {
+                if let Some(start) = start {
+                    write!(f, "{}", start)?;
+                }
+                write!(f, "{}", if *inclusive { "..=" } else { ".." })?;
+                if let Some(end) = end {
+                    write!(f, "{}", end)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+enum Interrupt {
+    Return(ConstValue),
+    Error(Diagnostic),
+}
+
+type EvalResult = Result<ConstValue, Interrupt>;
+
+/// Lazily evaluates constants of a program, caching results
+pub struct ConstEvaluator<'p> {
+    exprs: &'p Exprs,
+    consts: HashMap<&'p str, &'p ConstItem>,
+    /// Free functions and methods by call path (`name`, `Type::name`)
+    functions: HashMap<String, &'p Function>,
+    enums: HashMap<&'p str, &'p EnumDef>,
+    structs: HashMap<&'p str, &'p StructDef>,
+    /// Names of `newtype` items, whose constructors are identities
+    newtypes: HashSet<&'p str>,
+    cache: HashMap<String, ConstValue>,
+    discriminants: HashMap<String, Vec<(String, i64)>>,
+    in_progress: HashSet<String>,
+    /// The sized integer type of each checked or saturating operation that
+    /// has one, by span, as the checker records it
+    numeric: HashMap<Span, Numeric>,
+    steps: usize,
+    depth: usize,
+}
+
+impl<'p> ConstEvaluator<'p> {
+    pub fn new(program: &'p Program) -> Self {
+        let mut consts = HashMap::new();
+        let mut functions = HashMap::new();
+        let mut enums = HashMap::new();
+        let mut structs = HashMap::new();
+        let mut newtypes = HashSet::new();
+        for item in &program.items {
+            match item {
+                Item::Const(c) => {
+                    consts.insert(c.name.as_str(), c);
+                }
+                Item::TypeAlias(alias) if alias.newtype => {
+                    newtypes.insert(alias.name.as_str());
+                }
+                Item::Function(_) | Item::Impl(_) | Item::Extern(_) | Item::TypeAlias(_) => {}
+                Item::Enum(e) => {
+                    enums.insert(e.name.as_str(), e);
+                }
+                Item::Struct(s) => {
+                    structs.insert(s.name.as_str(), s);
+                }
+            }
+        }
+        for (name, _, function) in program.functions() {
+            functions.insert(name, function);
+        }
+        Self {
+            exprs: &program.exprs,
+            consts,
+            functions,
+            enums,
+            structs,
+            newtypes,
+            cache: HashMap::new(),
+            discriminants: HashMap::new(),
+            in_progress: HashSet::new(),
+            numeric: HashMap::new(),
+            steps: 0,
+            depth: 0,
+        }
+    }
+
+    /// Record that the integer operation at `span` is on integers of type
+    /// `numeric`
+    pub fn record_numeric(&mut self, span: Span, numeric: Numeric) {
+        self.numeric.insert(span, numeric);
+    }
+
+    /// Evaluate the global constant `name`
+    pub fn eval_const(&mut self, name: &str) -> Result<ConstValue, Diagnostic> {
+        if let Some(value) = self.cache.get(name) {
+            return Ok(value.clone());
+        }
+        let item = *self
+            .consts
+            .get(name)
+            .ok_or_else(|| Diagnostic::unspanned(format!("cannot find constant `{}`", name)))?;
+        if !self.in_progress.insert(name.to_string()) {
+            return Err(error_at(
+                item.span,
+                &format!("cycle detected when evaluating constant `{}`", name),
+            ));
+        }
+
+        self.steps = 0;
+        let result = self.eval_in(&item.value, &mut vec![HashMap::new()]);
+        self.in_progress.remove(name);
+
+        let value = result?;
+        self.cache.insert(name.to_string(), value.clone());
+        Ok(value)
+    }
+
+    /// Evaluate an expression in const context with the given local constants
+    pub fn eval_expr(
+        &mut self,
+        expr: &Expr,
+        locals: &HashMap<Symbol, ConstValue>,
+    ) -> Result<ConstValue, Diagnostic> {
+        self.steps = 0;
+        self.eval_in(expr, &mut vec![locals.clone()])
+    }
+
+    /// Evaluate an array length or other `usize` constant
+    pub fn eval_usize(
+        &mut self,
+        expr: &Expr,
+        locals: &HashMap<Symbol, ConstValue>,
+    ) -> Result<usize, Diagnostic> {
+        match self.eval_expr(expr, locals)? {
+            ConstValue::Int(n) if n >= 0 => Ok(n as usize),
+            ConstValue::Int(n) => Err(error_at(
+                expr.span,
+                &format!("array length must be non-negative, found {}", n),
+            )),
+            other => Err(error_at(
+                expr.span,
+                &format!("array length must be an integer, found `{}`", other),
+            )),
+        }
+    }
+
+    /// Discriminants of every variant of enum `name`
+    pub fn enum_discriminants(&mut self, name: &str) -> Result<Vec<(String, i64)>, Diagnostic> {
+        if let Some(values) = self.discriminants.get(name) {
+            return Ok(values.clone());
+        }
+        let def = *self
+            .enums
+            .get(name)
+            .ok_or_else(|| Diagnostic::unspanned(format!("cannot find enum `{}`", name)))?;
+        let key = format!("enum {}", name);
+        if !self.in_progress.insert(key.clone()) {
+            return Err(error_at(
+                def.span,
+                &format!("cycle detected when computing discriminants of `{}`", name),
+            ));
+        }
+
+        let mut values: Vec<(String, i64)> = Vec::new();
+        let mut next = 0i64;
+        let mut result = Ok(());
+        for variant in &def.variants {
+            let value = match &variant.discriminant {
+                Some(expr) => match self.eval_expr(expr, &HashMap::new()) {
+                    Ok(ConstValue::Int(n)) => n,
+                    Ok(other) => {
+                        result = Err(error_at(
+                            expr.span,
+                            &format!("enum discriminant must be an integer, found `{}`", other),
+                        ));
+                        break;
+                    }
+                    Err(err) => {
+                        result = Err(err);
+                        break;
+                    }
+                },
+                None => next,
+            };
+            if let Some((other, _)) = values.iter().find(|(_, v)| *v == value) {
+                result = Err(error_at(
+                    variant.span,
+                    &format!(
+                        "discriminant value {} assigned more than once (`{}` and `{}`)",
+                        value, other, variant.name
+                    ),
+                ));
+                break;
+            }
+            values.push((variant.name.to_string(), value));
+            next = match value.checked_add(1) {
+                Some(n) => n,
+                None => {
+                    result = Err(error_at(
+                        variant.span,
+                        "enum discriminant overflowed after this variant",
+                    ));
+                    break;
+                }
+            };
+        }
+        self.in_progress.remove(&key);
+
+        result?;
+        self.discriminants.insert(name.to_string(), values.clone());
+        Ok(values)
+    }
+
+    fn eval_in(
+        &mut self,
+        expr: &Expr,
+        env: &mut Vec<HashMap<Symbol, ConstValue>>,
+    ) -> Result<ConstValue, Diagnostic> {
+        match self.eval(expr, env) {
+            Ok(value) | Err(Interrupt::Return(value)) => Ok(value),
+            Err(Interrupt::Error(msg)) => Err(msg),
+        }
+    }
+
+    fn eval(&mut self, expr: &Expr, env: &mut Vec<HashMap<Symbol, ConstValue>>) -> EvalResult {
+        self.steps += 1;
+        if self.steps > STEP_LIMIT {
+            return fail(
+                expr.span,
+                "constant evaluation exceeded the step limit (possible infinite loop)",
+            );
+        }
+
+        match &expr.kind {
+            ExprKind::Int(n, _) => Ok(ConstValue::Int(*n)),
+            ExprKind::Float(x, suffix) => Ok(ConstValue::Float(
+                suffix.map_or(*x, |suffix| suffix.round(*x)),
+            )),
+            ExprKind::Bool(b) => Ok(ConstValue::Bool(*b)),
+            ExprKind::Str(s) => Ok(ConstValue::Str(s.to_string())),
+            ExprKind::Path(segments) => self.eval_path(segments, expr.span, env),
+            ExprKind::Unary(op, operand) => {
+                let value = self.eval(&self.exprs[*operand], env)?;
+                match (op, value) {
+                    (UnaryOp::Neg, ConstValue::Int(n)) => n
+                        .checked_neg()
+                        .map(ConstValue::Int)
+                        .ok_or_else(|| interrupt(expr.span, "attempt to negate with overflow")),
+                    (UnaryOp::Neg, ConstValue::Float(x)) => Ok(ConstValue::Float(-x)),
+                    (UnaryOp::Not, ConstValue::Bool(b)) => Ok(ConstValue::Bool(!b)),
+                    (UnaryOp::Ref | UnaryOp::RefMut, _) => fail(
+                        expr.span,
+                        "references are not allowed in constant evaluation",
+                    ),
+                    (UnaryOp::Deref, _) => fail(
+                        expr.span,
+                        "raw pointers are not allowed in constant evaluation",
+                    ),
+                    (op, value) => fail(
+                        expr.span,
+                        &format!("cannot apply `{:?}` to `{}` in a constant", op, value),
+                    ),
+                }
+            }
+            ExprKind::Binary(op, lhs, rhs) => {
+                let left = self.eval(&self.exprs[*lhs], env)?;
+                // Short-circuit logical operators
+                match (op, &left) {
+                    (BinaryOp::And, ConstValue::Bool(false)) => return Ok(ConstValue::Bool(false)),
+                    (BinaryOp::Or, ConstValue::Bool(true)) => return Ok(ConstValue::Bool(true)),
+                    _ => {}
+                }
+                let right = self.eval(&self.exprs[*rhs], env)?;
+                binary(*op, left, right).map_err(|msg| interrupt(expr.span, &msg))
+            }
+            ExprKind::Cast(value, ty) => {
+                let value = match self.eval(&self.exprs[*value], env)? {
+                    ConstValue::Int(n) | ConstValue::Variant(_, _, n) => Scalar::Int(n),
+                    ConstValue::Bool(b) => Scalar::Int(i64::from(b)),
+                    ConstValue::Float(x) => Scalar::Float(x),
+                    value => {
+                        return fail(expr.span, &format!("cannot cast `{}` in a constant", value))
+                    }
+                };
+                let target = match ty {
+                    TypeExpr::Named(name, _) => Numeric::parse(name),
+                    _ => None,
+                };
+                match target.map(|target| target.cast(value)) {
+                    Some(Scalar::Int(n)) => Ok(ConstValue::Int(n)),
+                    Some(Scalar::Float(x)) => Ok(ConstValue::Float(x)),
+                    None => fail(
+                        expr.span,
+                        "only casts to numeric types are allowed in constant evaluation",
+                    ),
+                }
+            }
+            ExprKind::Assign(target, value) => {
+                let ExprKind::Path(segments) = &self.exprs[*target].kind else {
+                    return fail(
+                        expr.span,
+                        "only local variables can be assigned in a const fn",
+                    );
+                };
+                let value = self.eval(&self.exprs[*value], env)?;
+                for scope in env.iter_mut().rev() {
+                    if let Some(slot) = scope.get_mut(&segments[0]) {
+                        *slot = value;
+                        return Ok(ConstValue::Unit);
+                    }
+                }
+                fail(
+                    expr.span,
+                    &format!("cannot assign to `{}` in a constant", join_path(segments)),
+                )
+            }
+            ExprKind::Call(callee, args) => {
+                let ExprKind::Path(segments) = &self.exprs[*callee].kind else {
+                    return fail(expr.span, "only `const fn` calls are allowed in constants");
+                };
+                let mut values = Vec::with_capacity(args.len());
+                for arg in args {
+                    values.push(self.eval(arg, env)?);
+                }
+                // A newtype is represented like the value it wraps
+                if let ([name], [_]) = (segments.as_slice(), values.as_slice()) {
+                    if self.newtypes.contains(name.as_str()) {
+                        return Ok(values.remove(0));
+                    }
+                }
+                self.call(&join_path(segments), values, expr.span)
+            }
+            ExprKind::MethodCall(receiver, method, args) => {
+                let receiver = self.eval(&self.exprs[*receiver], env)?;
+                let type_name = match &receiver {
+                    ConstValue::Struct(name, _) | ConstValue::Variant(name, _, _) => name.clone(),
+                    ConstValue::Option(value) => {
+                        let mut values = Vec::with_capacity(args.len());
+                        for arg in args {
+                            values.push(self.eval(arg, env)?);
+                        }
+                        return option_method(value.as_deref(), method, values, expr.span);
+                    }
+                    other => {
+                        return fail(
+                            expr.span,
+                            &format!("no method `{}` on value `{}`", method, other),
+                        )
+                    }
+                };
+                let mut values = vec![receiver];
+                for arg in args {
+                    values.push(self.eval(arg, env)?);
+                }
+                self.call(&format!("{}::{}", type_name, method), values, expr.span)
+            }
+            ExprKind::Index(base, index) => {
+                let base = self.eval(&self.exprs[*base], env)?;
+                let index = self.eval(&self.exprs[*index], env)?;
+                match (base, index) {
+                    (ConstValue::Array(items), ConstValue::Int(i)) => {
+                        let len = items.len();
+                        usize::try_from(i)
+                            .ok()
+                            .and_then(|i| items.into_iter().nth(i))
+                            .ok_or_else(|| {
+                                interrupt(
+                                    expr.span,
+                                    &format!(
+                                        "index out of bounds: the length is {} but the index is {}",
+                                        len, i
+                                    ),
+                                )
+                            })
+                    }
+                    (ConstValue::Array(items), ConstValue::Range(start, end, inclusive)) => {
+                        let (first, last) =
+                            builtins::slice_bounds(start, end, inclusive, items.len())
+                                .map_err(|msg| interrupt(expr.span, &msg))?;
+                        Ok(ConstValue::Array(items[first..last].to_vec()))
+                    }
+                    _ => fail(expr.span, "cannot index this value in a constant"),
+                }
+            }
+            ExprKind::Field(base, field) => match self.eval(&self.exprs[*base], env)? {
+                ConstValue::Struct(name, fields) => fields
+                    .into_iter()
+                    .find(|(f, _)| f == field.as_str())
+                    .map(|(_, value)| value)
+                    .ok_or_else(|| {
+                        interrupt(
+                            expr.span,
+                            &format!("no field `{}` on type `{}`", field, name),
+                        )
+                    }),
+                value if field == "0" => Ok(value),
+                other => fail(
+                    expr.span,
+                    &format!("no field `{}` on value `{}`", field, other),
+                ),
+            },
+            ExprKind::Distribution(..) => {
+                fail(expr.span, "belief values cannot be computed in a constant")
+            }
+            ExprKind::Arena(..) => fail(expr.span, "arenas cannot be used in a constant"),
+            ExprKind::StructLit(name, inits) => {
+                let Some(def) = self.structs.get(name.as_str()).copied() else {
+                    return fail(expr.span, &format!("cannot find struct `{}`", name));
+                };
+                let mut values = HashMap::new();
+                for init in inits {
+                    values.insert(init.name.as_str(), self.eval(&init.value, env)?);
+                }
+                let mut fields = Vec::with_capacity(def.fields.len());
+                for field in &def.fields {
+                    let Some(value) = values.remove(field.name.as_str()) else {
+                        return fail(
+                            expr.span,
+                            &format!(
+                                "missing field `{}` in initializer of `{}`",
+                                field.name, name
+                            ),
+                        );
+                    };
+                    fields.push((field.name.to_string(), value));
+                }
+                Ok(ConstValue::Struct(name.to_string(), fields))
+            }
+            ExprKind::Array(elements) => {
+                let mut items = Vec::with_capacity(elements.len());
+                for element in elements {
+                    items.push(self.eval(element, env)?);
+                }
+                Ok(ConstValue::Array(items))
+            }
+            ExprKind::ArrayRepeat(value, len) => {
+                let value = self.eval(&self.exprs[*value], env)?;
+                match self.eval(&self.exprs[*len], env)? {
+                    ConstValue::Int(n) if n >= 0 => Ok(ConstValue::Array(vec![value; n as usize])),
+                    other => fail(
+                        self.exprs[*len].span,
+                        &format!(
+                            "array length must be a non-negative integer, found `{}`",
+                            other
+                        ),
+                    ),
+                }
+            }
+            ExprKind::If(cond, then_block, else_branch) => {
+                match self.eval(&self.exprs[*cond], env)? {
+                    ConstValue::Bool(true) => self.eval_block(then_block, env),
+                    ConstValue::Bool(false) => match else_branch {
+                        Some(else_expr) => self.eval(&self.exprs[*else_expr], env),
+                        None => Ok(ConstValue::Unit),
+                    },
+                    other => fail(
+                        self.exprs[*cond].span,
+                        &format!("expected `bool` condition, found `{}`", other),
+                    ),
+                }
+            }
+            ExprKind::While(cond, body) => {
+                loop {
+                    match self.eval(&self.exprs[*cond], env)? {
+                        ConstValue::Bool(true) => {
+                            self.eval_block(body, env)?;
+                        }
+                        ConstValue::Bool(false) => break,
+                        other => {
+                            return fail(
+                                self.exprs[*cond].span,
+                                &format!("expected `bool` condition, found `{}`", other),
+                            )
+                        }
+                    }
+                }
+                Ok(ConstValue::Unit)
+            }
+            ExprKind::For(name, iterable, body) => {
+                let items = match self.eval(&self.exprs[*iterable], env)? {
+                    ConstValue::Array(items) => items,
+                    ConstValue::Range(Some(start), end, inclusive) => {
+                        let span = self.exprs[*iterable].span;
+                        return self.eval_range_loop(
+                            *name,
+                            (start, end, inclusive),
+                            body,
+                            span,
+                            env,
+                        );
+                    }
+                    other => {
+                        return fail(
+                            self.exprs[*iterable].span,
+                            &format!(
+                                "only arrays can be iterated over in constants, found `{}`",
+                                other
+                            ),
+                        )
+                    }
+                };
+                for item in items {
+                    env.push(HashMap::from([(*name, item)]));
+                    let result = self.eval_block(body, env);
+                    env.pop();
+                    result?;
+                }
+                Ok(ConstValue::Unit)
+            }
+            ExprKind::Range(start, end, inclusive) => Ok(ConstValue::Range(
+                self.eval_range_end(*start, env)?,
+                self.eval_range_end(*end, env)?,
+                *inclusive,
+            )),
+            ExprKind::Match(scrutinee, arms) => {
+                let value = self.eval(&self.exprs[*scrutinee], env)?;
+                for arm in arms {
+                    let mut bindings = HashMap::new();
+                    if !bind_pattern(&arm.pattern, &value, &mut bindings) {
+                        continue;
+                    }
+                    env.push(bindings);
+                    let result = match arm.guard {
+                        Some(guard) => match self.eval(&self.exprs[guard], env) {
+                            Ok(ConstValue::Bool(true)) => {
+                                Some(self.eval(&self.exprs[arm.value], env))
+                            }
+                            Ok(ConstValue::Bool(false)) => None,
+                            Ok(other) => Some(fail(
+                                self.exprs[guard].span,
+                                &format!("expected `bool` guard, found `{}`", other),
+                            )),
+                            Err(err) => Some(Err(err)),
+                        },
+                        None => Some(self.eval(&self.exprs[arm.value], env)),
+                    };
+                    env.pop();
+                    if let Some(result) = result {
+                        return result;
+                    }
+                }
+                fail(expr.span, &format!("no `match` arm matched `{}`", value))
+            }
+            ExprKind::Block(block) | ExprKind::Unsafe(block) => self.eval_block(block, env),
+            ExprKind::Return(value) => {
+                let value = match value {
+                    Some(value) => self.eval(&self.exprs[*value], env)?,
+                    None => ConstValue::Unit,
+                };
+                Err(Interrupt::Return(value))
+            }
+            // An ordinary call here: `const fn` recursion is bounded anyway
+            ExprKind::Become(call) => {
+                let value = self.eval(&self.exprs[*call], env)?;
+                Err(Interrupt::Return(value))
+            }
+            ExprKind::Asm(..) => fail(expr.span, "inline assembly cannot be used in a constant"),
+        }
+    }
+
+    /// An end of a range, which must be an integer
+    fn eval_range_end(
+        &mut self,
+        end: Option<ExprId>,
+        env: &mut Vec<HashMap<Symbol, ConstValue>>,
+    ) -> Result<Option<i64>, Interrupt> {
+        let Some(end) = end else {
+            return Ok(None);
+        };
+        match self.eval(&self.exprs[end], env)? {
+            ConstValue::Int(n) => Ok(Some(n)),
+            other => Err(interrupt(
+                self.exprs[end].span,
+                &format!("expected an integer end of range, found `{}`", other),
+            )),
+        }
+    }
+
+    /// `for name in start..end { body }` without building the range as an
+    /// array; each item counts as a step, so a range with no end runs into
+    /// the step limit
+    fn eval_range_loop(
+        &mut self,
+        name: Symbol,
+        (start, end, inclusive): (i64, Option<i64>, bool),
+        body: &Block,
+        span: Span,
+        env: &mut Vec<HashMap<Symbol, ConstValue>>,
+    ) -> EvalResult {
+        let mut i = start;
+        while end.is_none_or(|end| i < end || (inclusive && i == end)) {
+            self.steps += 1;
+            if self.steps > STEP_LIMIT {
+                return fail(
+                    span,
+                    "constant evaluation exceeded the step limit (possible infinite loop)",
+                );
+            }
+            env.push(HashMap::from([(name, ConstValue::Int(i))]));
+            let result = self.eval_block(body, env);
+            env.pop();
+            result?;
+            match i.checked_add(1) {
+                Some(next) => i = next,
+                None => break,
+            }
+        }
+        Ok(ConstValue::Unit)
+    }
+
+    fn eval_block(
+        &mut self,
+        block: &Block,
+        env: &mut Vec<HashMap<Symbol, ConstValue>>,
+    ) -> EvalResult {
+        env.push(HashMap::new());
+        let result = self.eval_block_inner(block, env);
+        env.pop();
+        result
+    }
+
+    fn eval_block_inner(
+        &mut self,
+        block: &Block,
+        env: &mut Vec<HashMap<Symbol, ConstValue>>,
+    ) -> EvalResult {
+        for stmt in &block.stmts {
+            match stmt {
+                Stmt::Let(let_stmt) => {
+                    let value = match &let_stmt.value {
+                        Some(value) => self.eval(value, env)?,
+                        None => ConstValue::Unit,
+                    };
+                    if let Some(scope) = env.last_mut() {
+                        scope.insert(let_stmt.name, value);
+                    }
+                }
+                Stmt::Const(item) => {
+                    let value = self.eval(&item.value, env)?;
+                    if let Some(scope) = env.last_mut() {
+                        scope.insert(item.name, value);
+                    }
+                }
+                Stmt::Expr(expr) => {
+                    self.eval(expr, env)?;
+                }
+                Stmt::Defer(expr) => {
+                    return fail(expr.span, "`defer` cannot be used in a constant")
+                }
+            }
+        }
+        match &block.tail {
+            Some(tail) => self.eval(&self.exprs[*tail], env),
+            None => Ok(ConstValue::Unit),
+        }
+    }
+
+    fn eval_path(
+        &mut self,
+        segments: &[Symbol],
+        span: Span,
+        env: &[HashMap<Symbol, ConstValue>],
+    ) -> EvalResult {
+        if let [name] = segments {
+            for scope in env.iter().rev() {
+                if let Some(value) = scope.get(name) {
+                    return Ok(value.clone());
+                }
+            }
+            if self.consts.contains_key(name.as_str()) {
+                let saved_steps = self.steps;
+                let result = self.eval_const(name).map_err(Interrupt::Error);
+                self.steps = saved_steps;
+                return result;
+            }
+            if self.functions.contains_key(name.as_str()) {
+                return fail(
+                    span,
+                    &format!("function `{}` cannot be used as a constant value", name),
+                );
+            }
+            if name == "None" {
+                return Ok(ConstValue::Option(None));
+            }
+            return fail(span, &format!("cannot find value `{}` in this scope", name));
+        }
+
+        if let [enum_name, variant] = segments {
+            if self.enums.contains_key(enum_name.as_str()) {
+                let values = self
+                    .enum_discriminants(enum_name)
+                    .map_err(Interrupt::Error)?;
+                return values
+                    .iter()
+                    .find(|(name, _)| name == variant.as_str())
+                    .map(|(name, value)| {
+                        ConstValue::Variant(enum_name.to_string(), name.clone(), *value)
+                    })
+                    .ok_or_else(|| {
+                        interrupt(
+                            span,
+                            &format!("no variant `{}` in enum `{}`", variant, enum_name),
+                        )
+                    });
+            }
+        }
+
+        fail(
+            span,
+            &format!("cannot find value `{}` in this scope", join_path(segments)),
+        )
+    }
+
+    fn call_intrinsic(&self, name: &str, args: &[ConstValue], span: Span) -> EvalResult {
+        if let ("Some", [value]) = (name, args) {
+            return Ok(ConstValue::Option(Some(Box::new(value.clone()))));
+        }
+        let ints: Option<Vec<i64>> = args
+            .iter()
+            .map(|v| match v {
+                ConstValue::Int(n) => Some(*n),
+                _ => None,
+            })
+            .collect();
+        let floats: Option<Vec<f64>> = args
+            .iter()
+            .map(|v| match v {
+                ConstValue::Float(x) => Some(*x),
+                _ => None,
+            })
+            .collect();
+        if let Some(x) = floats.and_then(|floats| builtins::float_intrinsic(name, &floats)) {
+            return Ok(ConstValue::Float(x));
+        }
+        if builtins::is_int_op(name) {
+            let numeric = self.numeric.get(&span).copied().unwrap_or(Numeric::I64);
+            return match ints.and_then(|ints| builtins::int_op_value(name, &ints, numeric)) {
+                Some(n) if builtins::is_checked(name) => {
+                    Ok(ConstValue::Option(n.map(|n| Box::new(ConstValue::Int(n)))))
+                }
+                Some(Some(n)) => Ok(ConstValue::Int(n)),
+                _ => fail(span, &format!("invalid arguments to `{}`", name)),
+            };
+        }
+        match ints.and_then(|ints| builtins::int_intrinsic(name, &ints)) {
+            Some(n) => Ok(ConstValue::Int(n)),
+            None if builtins::signature(name).is_some() => fail(
+                span,
+                &format!("cannot call builtin `{}` in a constant", name),
+            ),
+            None => fail(span, &format!("cannot find function `{}`", name)),
+        }
+    }
+
+    fn call(&mut self, name: &str, args: Vec<ConstValue>, span: Span) -> EvalResult {
+        let function = match self.functions.get(name) {
+            Some(f) => *f,
+            None => return self.call_intrinsic(name, &args, span),
+        };
+        if !function.is_const {
+            return fail(
+                span,
+                &format!(
+                    "cannot call non-const fn `{}` in a constant; consider declaring it `const fn`",
+                    name
+                ),
+            );
+        }
+        let arity = function.params.len() + usize::from(function.receiver.is_some());
+        if args.len() != arity {
+            return fail(
+                span,
+                &format!(
+                    "function `{}` takes {} arguments but {} were supplied",
+                    name,
+                    arity,
+                    args.len()
+                ),
+            );
+        }
+        if self.depth >= CALL_DEPTH_LIMIT {
+            return fail(
+                span,
+                "const fn call depth limit reached (possible infinite recursion)",
+            );
+        }
+
+        let receiver = function.receiver.map(|_| Symbol::intern("self"));
+        let frame: HashMap<Symbol, ConstValue> = receiver
+            .into_iter()
+            .chain(function.params.iter().map(|p| p.name))
+            .zip(args)
+            .collect();
+        let mut env = vec![frame];
+
+        self.depth += 1;
+        let result = self.eval_block(&function.body, &mut env);
+        self.depth -= 1;
+
+        match result {
+            Ok(value) | Err(Interrupt::Return(value)) => Ok(value),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+fn binary(op: BinaryOp, left: ConstValue, right: ConstValue) -> Result<ConstValue, String> {
+    use ConstValue::*;
+
+    match (left, right) {
+        (Int(a), Int(b)) => {
+            let checked = |result: std::option::Option<i64>, verb: &str| {
+                result
+                    .map(Int)
+                    .ok_or_else(|| format!("attempt to {} with overflow", verb))
+            };
+            match op {
+                BinaryOp::Add => checked(a.checked_add(b), "add"),
+                BinaryOp::Sub => checked(a.checked_sub(b), "subtract"),
+                BinaryOp::Mul => checked(a.checked_mul(b), "multiply"),
+                BinaryOp::Div if b == 0 => Err("attempt to divide by zero".to_string()),
+                BinaryOp::Div => checked(a.checked_div(b), "divide"),
+                BinaryOp::Rem if b == 0 => {
+                    Err("attempt to calculate the remainder with a divisor of zero".to_string())
+                }
+                BinaryOp::Rem => checked(a.checked_rem(b), "calculate the remainder"),
+                _ => compare(op, a.partial_cmp(&b)),
+            }
+        }
+        (Float(a), Float(b)) => match op {
+            BinaryOp::Add => Ok(Float(a + b)),
+            BinaryOp::Sub => Ok(Float(a - b)),
+            BinaryOp::Mul => Ok(Float(a * b)),
+            BinaryOp::Div => Ok(Float(a / b)),
+            BinaryOp::Rem => Ok(Float(a % b)),
+            _ => compare(op, a.partial_cmp(&b)),
+        },
+        (Bool(a), Bool(b)) => match op {
+            BinaryOp::And => Ok(Bool(a && b)),
+            BinaryOp::Or => Ok(Bool(a || b)),
+            BinaryOp::Eq => Ok(Bool(a == b)),
+            BinaryOp::Ne => Ok(Bool(a != b)),
+            _ => Err(format!("cannot apply `{}` to booleans", op.symbol())),
+        },
+        (Str(a), Str(b)) => match op {
+            BinaryOp::Eq => Ok(Bool(a == b)),
+            BinaryOp::Ne => Ok(Bool(a != b)),
+            _ => Err(format!(
+                "cannot apply `{}` to strings in a constant",
+                op.symbol()
+            )),
+        },
+        (Variant(_, _, a), Variant(_, _, b)) => match op {
+            BinaryOp::Eq => Ok(Bool(a == b)),
+            BinaryOp::Ne => Ok(Bool(a != b)),
+            _ => Err(format!("cannot apply `{}` to enum values", op.symbol())),
+        },
+        (left, right) => Err(format!(
+            "mismatched operands for `{}`: `{}` and `{}`",
+            op.symbol(),
+            left,
+            right
+        )),
+    }
+}
+
+/// Call the method `method` of the `Option` holding `value`
+fn option_method(
+    value: Option<&ConstValue>,
+    method: &str,
+    args: Vec<ConstValue>,
+    span: Span,
+) -> EvalResult {
+    match (method, value, args.as_slice()) {
+        ("is_some", _, []) => Ok(ConstValue::Bool(value.is_some())),
+        ("is_none", _, []) => Ok(ConstValue::Bool(value.is_none())),
+        ("unwrap" | "expect" | "unwrap_or", Some(value), _) => Ok(value.clone()),
+        ("unwrap", None, []) => fail(span, "called `Option::unwrap()` on a `None` value"),
+        ("expect", None, [ConstValue::Str(message)]) => fail(span, message),
+        ("unwrap_or", None, [default]) => Ok(default.clone()),
+        _ => {
+            let receiver = ConstValue::Option(value.cloned().map(Box::new));
+            fail(
+                span,
+                &format!("no method `{}` on value `{}`", method, receiver),
+            )
+        }
+    }
+}
+
+fn compare(op: BinaryOp, ordering: Option<std::cmp::Ordering>) -> Result<ConstValue, String> {
+    use std::cmp::Ordering::*;
+
+    let result = match (op, ordering) {
+        (_, None) => op == BinaryOp::Ne,
+        (BinaryOp::Eq, Some(o)) => o == Equal,
+        (BinaryOp::Ne, Some(o)) => o != Equal,
+        (BinaryOp::Lt, Some(o)) => o == Less,
+        (BinaryOp::Le, Some(o)) => o != Greater,
+        (BinaryOp::Gt, Some(o)) => o == Greater,
+        (BinaryOp::Ge, Some(o)) => o != Less,
+        _ => return Err(format!("cannot apply `{}` to numbers", op.symbol())),
+    };
+    Ok(ConstValue::Bool(result))
+}
+
+/// Whether `value` matches `pattern`, adding what it binds to `bindings`
+fn bind_pattern(
+    pattern: &Pattern,
+    value: &ConstValue,
+    bindings: &mut HashMap<Symbol, ConstValue>,
+) -> bool {
+    match (&pattern.kind, value) {
+        (PatternKind::Wildcard, _) => true,
+        (PatternKind::Binding(name), _) => {
+            bindings.insert(*name, value.clone());
+            true
+        }
+        (PatternKind::Or(alternatives), _) => alternatives
+            .iter()
+            .any(|alternative| bind_pattern(alternative, value, bindings)),
+        (PatternKind::Int(..) | PatternKind::Range(..), ConstValue::Int(n)) => {
+            pattern.matches_int(*n)
+        }
+        (PatternKind::Bool(a), ConstValue::Bool(b)) => a == b,
+        (PatternKind::Str(a), ConstValue::Str(b)) => a.as_str() == b.as_str(),
+        (PatternKind::Variant(_, a), ConstValue::Variant(_, b, _)) => a.as_str() == b.as_str(),
+        (PatternKind::Some(pattern), ConstValue::Option(Some(value))) => {
+            bind_pattern(pattern, value, bindings)
+        }
+        (PatternKind::None, ConstValue::Option(None)) => true,
+        _ => false,
+    }
+}
+
+fn error_at(span: Span, msg: &str) -> Diagnostic {
+    Diagnostic::new(span, msg)
+}
+
+fn interrupt(span: Span, msg: &str) -> Interrupt {
+    Interrupt::Error(error_at(span, msg))
+}
+
+fn fail(span: Span, msg: &str) -> EvalResult {
+    Err(interrupt(span, msg))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser;
+    use crate::token::TokenKind;
+
+    fn program(source: &str) -> Program {
+        let mut lexer = Lexer::new(source);
+        let mut tokens = Vec::new();
+        loop {
+            let token = lexer.next_token();
+            if token.kind == TokenKind::Eof {
+                break;
+            }
+            tokens.push(token);
+        }
+        parser::parse(tokens).unwrap()
+    }
+
+    #[test]
+    fn test_const_arithmetic_and_references() {
+        let p = program("const B: i64 = A * 2; const A: i64 = 20 + 1;");
+        let mut eval = ConstEvaluator::new(&p);
+        assert_eq!(eval.eval_const("B").unwrap(), ConstValue::Int(42));
+    }
+
+    #[test]
+    fn test_const_fn() {
+        let source = "
+            const fn fact(n: i64) -> i64 {
+                let mut acc = 1;
+                let mut i = 2;
+                while i <= n { acc = acc * i; i = i + 1; }
+                acc
+            }
+            const F: i64 = fact(10);";
+        let p = program(source);
+        assert_eq!(
+            ConstEvaluator::new(&p).eval_const("F").unwrap(),
+            ConstValue::Int(3_628_800)
+        );
+    }
+
+    #[test]
+    fn test_overflow_is_reported_with_location() {
+        let p = program("const BIG: i64 = 9223372036854775807 + 1;");
+        let err = ConstEvaluator::new(&p)
+            .eval_const("BIG")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("1:38"), "{}", err);
+        assert!(err.contains("attempt to add with overflow"));
+    }
+
+    #[test]
+    fn test_non_const_call_rejected() {
+        let p = program("fn now() -> i64 { 0 } const T: i64 = now();");
+        let err = ConstEvaluator::new(&p)
+            .eval_const("T")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("non-const fn `now`"));
+    }
+
+    #[test]
+    fn test_cycle_detected() {
+        let p = program("const A: i64 = B; const B: i64 = A;");
+        let err = ConstEvaluator::new(&p)
+            .eval_const("A")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("cycle detected"));
+    }
+
+    #[test]
+    fn test_enum_discriminants() {
+        let p = program("const BASE: i64 = 10; enum E { A = BASE, B, C = BASE * 10 }");
+        let values = ConstEvaluator::new(&p).enum_discriminants("E").unwrap();
+        assert_eq!(values[1], ("B".to_string(), 11));
+        assert_eq!(values[2], ("C".to_string(), 100));
+
+        let p = program("enum E { A = 1, B = 0, C }");
+        let err = ConstEvaluator::new(&p)
+            .enum_discriminants("E")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("assigned more than once"));
+    }
+}
@@ -0,0 +1,390 @@
+//! Source coverage for `solo test --coverage`
+//!
+//! [`Coverage::instrument`] finds the regions of a program worth counting:
+//! each function, each line starting a statement, and both outcomes of each
+//! `if` and `while` condition. The interpreter counts them as it runs, and
+//! each test process writes its counters to a profraw-style file
+//! ([`Coverage::to_profraw`]): a header with a checksum of the regions, then
+//! the counters in region order, without names or lines. The report
+//! instruments the source again, checks the checksum still matches, adds up
+//! the counters of every test ([`Coverage::merge_profraw`]) and maps them
+//! back to `.solo` lines, as an lcov tracefile ([`Coverage::to_lcov`]) for
+//! `genhtml` and editors, or a self-contained HTML page
+//! ([`Coverage::to_html`]).
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::ast::{Block, Expr, ExprKind, Exprs, Program, Span};
+use crate::visit::{self, Visit};
+
+/// First bytes of a profraw file
+const MAGIC: &[u8; 8] = b"\xffsoloprf";
+
+/// Version of the profraw layout
+const VERSION: u64 = 1;
+
+/// Execution counts of the regions of a program
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Coverage {
+    /// Calls of each function, with the line it starts on
+    functions: BTreeMap<String, (usize, u64)>,
+    /// Executions of the statements starting on each line
+    lines: BTreeMap<usize, u64>,
+    /// Times each condition, by line and column, was true and false
+    branches: BTreeMap<(usize, usize), [u64; 2]>,
+}
+
+/// Regions covered out of those instrumented
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Totals {
+    pub functions: (usize, usize),
+    pub lines: (usize, usize),
+    pub branches: (usize, usize),
+}
+
+impl fmt::Display for Totals {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts = [
+            ("lines", self.lines),
+            ("functions", self.functions),
+            ("branches", self.branches),
+        ];
+        for (i, (name, (hit, total))) in parts.into_iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{} {}/{} ({})", name, hit, total, percent(hit, total))?;
+        }
+        Ok(())
+    }
+}
+
+impl Coverage {
+    /// The regions of `program`, none executed yet
+    pub fn instrument(program: &Program) -> Self {
+        let mut regions = Regions(&program.exprs, Coverage::default());
+        for (name, _, function) in program.functions() {
+            regions.1.functions.insert(name, (function.span.line, 0));
+            regions.visit_block(&function.body);
+        }
+        regions.1
+    }
+
+    pub fn hit_function(&mut self, name: &str) {
+        if let Some((_, count)) = self.functions.get_mut(name) {
+            *count += 1;
+        }
+    }
+
+    pub fn hit_line(&mut self, line: usize) {
+        if let Some(count) = self.lines.get_mut(&line) {
+            *count += 1;
+        }
+    }
+
+    /// Count the condition of the `if` or `while` at `span` as `taken`
+    pub fn hit_branch(&mut self, span: Span, taken: bool) {
+        if let Some(counts) = self.branches.get_mut(&(span.line, span.column)) {
+            counts[usize::from(!taken)] += 1;
+        }
+    }
+
+    /// Calls of each function, by name
+    pub fn calls(&self) -> impl Iterator<Item = (&str, u64)> {
+        self.functions
+            .iter()
+            .map(|(name, &(_, count))| (name.as_str(), count))
+    }
+
+    /// Times each condition, by line and column, was true and false
+    pub fn branches(&self) -> impl Iterator<Item = ((usize, usize), [u64; 2])> + '_ {
+        self.branches
+            .iter()
+            .map(|(&position, &counts)| (position, counts))
+    }
+
+    pub fn totals(&self) -> Totals {
+        let covered = |counts: Vec<u64>| (counts.iter().filter(|&&n| n > 0).count(), counts.len());
+        Totals {
+            functions: covered(self.functions.values().map(|&(_, n)| n).collect()),
+            lines: covered(self.lines.values().copied().collect()),
+            branches: covered(self.branches.values().flatten().copied().collect()),
+        }
+    }
+
+    /// Hash of the instrumented regions, identifying the source they came
+    /// from (FNV-1a)
+    pub fn checksum(&self) -> u64 {
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        let mut feed = |bytes: &[u8]| {
+            for &byte in bytes {
+                hash = (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3);
+            }
+        };
+        for (name, (line, _)) in &self.functions {
+            feed(name.as_bytes());
+            feed(&line.to_le_bytes());
+        }
+        for line in self.lines.keys() {
+            feed(&line.to_le_bytes());
+        }
+        for (line, column) in self.branches.keys() {
+            feed(&line.to_le_bytes());
+            feed(&column.to_le_bytes());
+        }
+        hash
+    }
+
+    fn counters(&self) -> impl Iterator<Item = &u64> {
+        self.functions
+            .values()
+            .map(|(_, count)| count)
+            .chain(self.lines.values())
+            .chain(self.branches.values().flatten())
+    }
+
+    fn counters_mut(&mut self) -> impl Iterator<Item = &mut u64> {
+        self.functions
+            .values_mut()
+            .map(|(_, count)| count)
+            .chain(self.lines.values_mut())
+            .chain(self.branches.values_mut().flatten())
+    }
+
+    /// The counters as a profraw file: magic, version, checksum and number
+    /// of counters, then the counters, all little-endian `u64`s
+    pub fn to_profraw(&self) -> Vec<u8> {
+        let counters: Vec<u64> = self.counters().copied().collect();
+        let mut out = MAGIC.to_vec();
+        for word in [VERSION, self.checksum(), counters.len() as u64]
+            .into_iter()
+            .chain(counters)
+        {
+            out.extend_from_slice(&word.to_le_bytes());
+        }
+        out
+    }
+
+    /// Add the counters of a profraw file written for the same regions
+    pub fn merge_profraw(&mut self, data: &[u8]) -> Result<(), String> {
+        let Some(words) = data.strip_prefix(MAGIC.as_slice()) else {
+            return Err("not a profraw file".to_string());
+        };
+        if words.len() % 8 != 0 {
+            return Err("truncated profraw file".to_string());
+        }
+        let mut words = words
+            .chunks_exact(8)
+            .map(|word| u64::from_le_bytes(word.try_into().expect("8-byte chunk")));
+        let mut header = || words.next().ok_or("truncated profraw file");
+        let (version, checksum, count) = (header()?, header()?, header()?);
+        if version != VERSION {
+            return Err(format!("unsupported profraw version {}", version));
+        }
+        if checksum != self.checksum() {
+            return Err(
+                "profile data does not match the source, which may have been edited".to_string(),
+            );
+        }
+        let counters: Vec<u64> = words.collect();
+        if counters.len() as u64 != count || counters.len() != self.counters().count() {
+            return Err("truncated profraw file".to_string());
+        }
+        for (total, count) in self.counters_mut().zip(counters) {
+            *total = total.saturating_add(count);
+        }
+        Ok(())
+    }
+
+    /// The counts as an lcov tracefile for the source file `path`
+    pub fn to_lcov(&self, path: &str) -> String {
+        let totals = self.totals();
+        let mut out = format!("TN:\nSF:{}\n", path);
+        for (name, (line, _)) in &self.functions {
+            out.push_str(&format!("FN:{},{}\n", line, name));
+        }
+        for (name, (_, count)) in &self.functions {
+            out.push_str(&format!("FNDA:{},{}\n", count, name));
+        }
+        out.push_str(&format!(
+            "FNF:{}\nFNH:{}\n",
+            totals.functions.1, totals.functions.0
+        ));
+        let mut block = 0;
+        let mut previous = 0;
+        for (&(line, _), counts) in &self.branches {
+            // Blocks are numbered from 0 on each line
+            block = if line == previous { block + 1 } else { 0 };
+            previous = line;
+            for (branch, count) in counts.iter().enumerate() {
+                let taken = if counts == &[0, 0] {
+                    "-".to_string()
+                } else {
+                    count.to_string()
+                };
+                out.push_str(&format!("BRDA:{},{},{},{}\n", line, block, branch, taken));
+            }
+        }
+        out.push_str(&format!(
+            "BRF:{}\nBRH:{}\n",
+            totals.branches.1, totals.branches.0
+        ));
+        for (line, count) in &self.lines {
+            out.push_str(&format!("DA:{},{}\n", line, count));
+        }
+        out.push_str(&format!("LF:{}\nLH:{}\n", totals.lines.1, totals.lines.0));
+        out.push_str("end_of_record\n");
+        out
+    }
+
+    /// The counts as an HTML page showing `source`, read from `path`, with
+    /// each instrumented line marked covered or not
+    pub fn to_html(&self, path: &str, source: &str) -> String {
+        let mut out = String::from("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+        out.push_str(&format!("<title>Coverage of {}</title>\n", escape(path)));
+        out.push_str(
+            "<style>\n\
+             body { font-family: sans-serif; }\n\
+             table { border-collapse: collapse; font-family: monospace; }\n\
+             td { padding: 0 0.5em; white-space: pre; }\n\
+             td.count { text-align: right; color: #666; }\n\
+             tr.hit { background: #dfd; }\n\
+             tr.miss { background: #fdd; }\n\
+             tr.partial { background: #ffd; }\n\
+             </style>\n</head>\n<body>\n",
+        );
+        out.push_str(&format!(
+            "<h1>{}</h1>\n<p>{}</p>\n<table>\n",
+            escape(path),
+            escape(&self.totals().to_string())
+        ));
+        for (i, text) in source.lines().enumerate() {
+            let line = i + 1;
+            let (class, count) = match self.lines.get(&line) {
+                None => ("", String::new()),
+                Some(0) => ("miss", "0".to_string()),
+                Some(&count) => {
+                    // A condition that always went the same way
+                    let partial = self
+                        .branches
+                        .range((line, 0)..(line + 1, 0))
+                        .any(|(_, counts)| counts.contains(&0));
+                    (if partial { "partial" } else { "hit" }, count.to_string())
+                }
+            };
+            out.push_str(&format!(
+                "<tr class=\"{}\"><td class=\"count\">{}</td><td class=\"count\">{}</td><td>{}</td></tr>\n",
+                class,
+                line,
+                count,
+                escape(text)
+            ));
+        }
+        out.push_str("</table>\n</body>\n</html>\n");
+        out
+    }
+}
+
+fn percent(hit: usize, total: usize) -> String {
+    if total == 0 {
+        "-".to_string()
+    } else {
+        format!("{:.1}%", hit as f64 * 100.0 / total as f64)
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Collects the lines and conditions of function bodies
+struct Regions<'a>(&'a Exprs, Coverage);
+
+impl<'a> Visit<'a> for Regions<'a> {
+    fn exprs(&self) -> &'a Exprs {
+        self.0
+    }
+
+    fn visit_block(&mut self, block: &Block) {
+        for stmt in &block.stmts {
+            self.1.lines.insert(stmt.span().line, 0);
+        }
+        if let Some(tail) = block.tail {
+            self.1.lines.insert(self.0[tail].span.line, 0);
+        }
+        visit::walk_block(self, block);
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        if let ExprKind::If(..) | ExprKind::While(..) = expr.kind {
+            self.1
+                .branches
+                .insert((expr.span.line, expr.span.column), [0, 0]);
+        }
+        visit::walk_expr(self, expr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Phase;
+
+    fn instrument(source: &str) -> Coverage {
+        let program = crate::compile_until(source, Phase::Parse)
+            .unwrap()
+            .into_ast()
+            .unwrap();
+        Coverage::instrument(&program)
+    }
+
+    #[test]
+    fn test_lcov() {
+        let source =
+            "fn sign(x: i64) -> i64 {\n    if x < 0 {\n        return -1;\n    }\n    1\n}\n";
+        let mut coverage = instrument(source);
+        coverage.hit_function("sign");
+        coverage.hit_line(2);
+        coverage.hit_branch(Span::new(2, 5), false);
+        coverage.hit_line(5);
+
+        let lcov = coverage.to_lcov("sign.solo");
+        assert!(lcov.starts_with("TN:\nSF:sign.solo\nFN:1,sign\nFNDA:1,sign\n"));
+        assert!(lcov.contains("BRDA:2,0,0,0\nBRDA:2,0,1,1\nBRF:2\nBRH:1\n"));
+        assert!(lcov.contains("DA:2,1\nDA:3,0\nDA:5,1\nLF:3\nLH:2\n"));
+        assert_eq!(
+            coverage.totals().to_string(),
+            "lines 2/3 (66.7%), functions 1/1 (100.0%), branches 1/2 (50.0%)"
+        );
+        let html = coverage.to_html("sign.solo", source);
+        assert!(html.contains("<tr class=\"partial\"><td class=\"count\">2</td>"));
+        assert!(html.contains("<td>        return -1;</td>"));
+    }
+
+    #[test]
+    fn test_profraw() {
+        let source =
+            "fn main() {\n    let mut i = 0;\n    while i < 3 {\n        i = i + 1;\n    }\n}\n";
+        let mut run = instrument(source);
+        run.hit_line(4);
+        run.hit_branch(Span::new(3, 5), true);
+        let profraw = run.to_profraw();
+
+        let mut merged = instrument(source);
+        merged.merge_profraw(&profraw).unwrap();
+        merged.merge_profraw(&profraw).unwrap();
+        assert_eq!(merged.lines[&4], 2);
+        assert_eq!(merged.branches[&(3, 5)], [2, 0]);
+
+        let edited = instrument("fn main() {\n    print(1);\n}\n")
+            .merge_profraw(&profraw)
+            .unwrap_err();
+        assert!(edited.contains("does not match the source"));
+        assert!(merged.merge_profraw(&profraw[..20]).is_err());
+        assert!(merged.merge_profraw(b"junk").is_err());
+    }
+}
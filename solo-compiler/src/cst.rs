@@ -0,0 +1,645 @@
+//! Lossless concrete syntax tree for Solo dialect
+//!
+//! The AST drops comments and whitespace; the CST keeps every character of
+//! the source as tokens in a tree, so printing a tree gives back exactly the
+//! source it was parsed from. Formatters, refactoring tools and the language
+//! server edit code through it without losing comments.
+//!
+//! As in rowan, nodes have a kind and children, and the leaves are tokens
+//! holding their source text, comments and whitespace included. Nodes do not
+//! store positions, so a subtree can be moved or replaced without fixing up
+//! the rest of the tree. The tree records items, attributes and delimited
+//! groups (`(...)`, `[...]`, `{...}`); the AST has the finer structure.
+//!
+//! ```text
+//! Root
+//!   Whitespace "\n"
+//!   Function
+//!     Comment "// Entry point"
+//!     Whitespace "\n"
+//!     Fn "fn"
+//!     ...
+//! ```
+//!
+//! Parsing never fails: tokens outside any item end up in `Error` nodes.
+//!
+//! After an edit, [`Node::reparse`] lexes and parses again only the items
+//! around it and keeps the rest of the tree, so the language server keeps
+//! up with typing in large files.
+
+use std::fmt;
+
+use crate::lexer::Lexer;
+use crate::token::{TokenKind, Trivia};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeKind {
+    Root,
+    /// `#[...]` before an item, or `#![...]` at the top of the file
+    Attribute,
+    Function,
+    Const,
+    Struct,
+    Enum,
+    Impl,
+    Trait,
+    /// `type` or `newtype` alias
+    TypeAlias,
+    /// `extern` block
+    Extern,
+    Macro,
+    /// `(...)`
+    Parens,
+    /// `[...]`
+    Brackets,
+    /// `{...}`
+    Block,
+    /// Tokens that do not start an item
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyntaxKind {
+    Trivia(Trivia),
+    Token(TokenKind),
+}
+
+/// A leaf of the tree
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxToken {
+    pub kind: SyntaxKind,
+    /// Source text, exactly as written
+    pub text: String,
+}
+
+impl SyntaxToken {
+    pub fn is_trivia(&self) -> bool {
+        matches!(self.kind, SyntaxKind::Trivia(_))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Element {
+    Node(Node),
+    Token(SyntaxToken),
+}
+
+impl Element {
+    /// Length of the source text in bytes
+    pub fn text_len(&self) -> usize {
+        match self {
+            Element::Node(node) => node.text_len(),
+            Element::Token(token) => token.text.len(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Node {
+    pub kind: NodeKind,
+    pub children: Vec<Element>,
+}
+
+impl Node {
+    fn new(kind: NodeKind) -> Self {
+        Self {
+            kind,
+            children: Vec::new(),
+        }
+    }
+
+    /// Length of the source text in bytes
+    pub fn text_len(&self) -> usize {
+        self.children.iter().map(Element::text_len).sum()
+    }
+
+    /// Child nodes, such as the items of the root
+    pub fn nodes(&self) -> impl Iterator<Item = &Node> {
+        self.children.iter().filter_map(|child| match child {
+            Element::Node(node) => Some(node),
+            Element::Token(_) => None,
+        })
+    }
+
+    /// Every token of the subtree in source order, trivia included
+    pub fn tokens(&self) -> Vec<&SyntaxToken> {
+        let mut tokens = Vec::new();
+        self.collect_tokens(&mut tokens);
+        tokens
+    }
+
+    fn collect_tokens<'a>(&'a self, tokens: &mut Vec<&'a SyntaxToken>) {
+        for child in &self.children {
+            match child {
+                Element::Node(node) => node.collect_tokens(tokens),
+                Element::Token(token) => tokens.push(token),
+            }
+        }
+    }
+
+    /// Name of an item: its first identifier outside attributes and groups
+    pub fn name(&self) -> Option<&str> {
+        self.children.iter().find_map(|child| match child {
+            Element::Token(SyntaxToken {
+                kind: SyntaxKind::Token(TokenKind::Identifier(name)),
+                ..
+            }) => Some(name.as_str()),
+            _ => None,
+        })
+    }
+
+    /// The token covering byte `offset` of the node's text, with the offset
+    /// it starts at
+    pub fn token_at(&self, offset: usize) -> Option<(usize, &SyntaxToken)> {
+        let mut start = 0;
+        for child in &self.children {
+            let len = child.text_len();
+            if offset < start + len {
+                return match child {
+                    Element::Node(node) => node
+                        .token_at(offset - start)
+                        .map(|(inner, token)| (start + inner, token)),
+                    Element::Token(token) => Some((start, token)),
+                };
+            }
+            start += len;
+        }
+        None
+    }
+}
+
+/// A change to the text of a file: bytes `start..end` replaced with `text`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Edit {
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+}
+
+impl Edit {
+    /// Make the old text the new one
+    pub fn apply(&self, source: &mut String) {
+        source.replace_range(self.start..self.end, &self.text);
+    }
+}
+
+impl Node {
+    /// Update the tree of a file for `edit`, after which its text is
+    /// `source`, to the tree [`parse`] gives for `source`
+    ///
+    /// Items closed by their own `;`, `}` or `]` are complete: the text
+    /// after them cannot change them, and parsing goes on after them as
+    /// from the start of a file. Lexing and parsing restart after the last
+    /// complete item before the edit and stop at the end of a complete item
+    /// after it, once the new text parses into an item that ends there too;
+    /// the items after that are kept. An edit opening a comment, a string or
+    /// a group that nothing closes parses the rest of the file.
+    pub fn reparse(&mut self, source: &str, edit: &Edit) {
+        let mut ends = Vec::with_capacity(self.children.len());
+        let mut end = 0;
+        for child in &self.children {
+            end += child.text_len();
+            ends.push(end);
+        }
+        let first = (0..ends.len())
+            .rev()
+            .find(|&i| ends[i] <= edit.start && is_complete(&self.children[i]))
+            .map_or(0, |i| i + 1);
+        let start = first.checked_sub(1).map_or(0, |i| ends[i]);
+        // Where the text after the edit, unchanged, now starts
+        let shift = |old: usize| old - edit.end + edit.start + edit.text.len();
+        let syncs: Vec<usize> = (first..ends.len())
+            .filter(|&i| ends[i] >= edit.end && is_complete(&self.children[i]))
+            .collect();
+
+        let mut sync = 0;
+        loop {
+            let (last, stop) = match syncs.get(sync) {
+                Some(&i) => (i + 1, shift(ends[i])),
+                None => (ends.len(), source.len()),
+            };
+            let mut builder = Builder {
+                tokens: lex(&source[start..stop]),
+                pos: 0,
+            };
+            let items = builder.root().children;
+            if stop == source.len() || items.last().is_none_or(is_complete) {
+                self.children.splice(first..last, items);
+                return;
+            }
+            // Try at least twice as much text next, so that the work stays
+            // proportional to the text parsed in the end
+            let size = 2 * (stop - start);
+            sync += 1;
+            while syncs
+                .get(sync)
+                .is_some_and(|&i| shift(ends[i]) - start < size)
+            {
+                sync += 1;
+            }
+        }
+    }
+}
+
+/// Whether an element is an item closed by its own `;`, `}` or `]`
+fn is_complete(element: &Element) -> bool {
+    let Element::Node(node) = element else {
+        return false;
+    };
+    let closes = |element: &Element, close: TokenKind| match element {
+        Element::Token(token) => token.kind == SyntaxKind::Token(close),
+        Element::Node(_) => false,
+    };
+    match (node.kind, node.children.last()) {
+        (NodeKind::Error, _) | (_, None) => false,
+        (NodeKind::Attribute, Some(Element::Node(group))) => {
+            group.kind == NodeKind::Brackets
+                && group
+                    .children
+                    .last()
+                    .is_some_and(|last| closes(last, TokenKind::RBracket))
+        }
+        // `const` items go on past blocks, up to their `;`
+        (NodeKind::Const, Some(Element::Node(_))) => false,
+        (_, Some(Element::Node(group))) => {
+            group.kind == NodeKind::Block
+                && group.children.len() > 1
+                && group
+                    .children
+                    .last()
+                    .is_some_and(|last| closes(last, TokenKind::RBrace))
+        }
+        (_, Some(last)) => closes(last, TokenKind::Semicolon),
+    }
+}
+
+impl fmt::Display for Node {
+    /// The source text of the subtree
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for token in self.tokens() {
+            f.write_str(&token.text)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse `source` into a tree whose text is `source`
+pub fn parse(source: &str) -> Node {
+    let mut builder = Builder {
+        tokens: lex(source),
+        pos: 0,
+    };
+    builder.root()
+}
+
+/// Tokens and trivia of `source`, which together cover all of it
+fn lex(source: &str) -> Vec<SyntaxToken> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut lexer = Lexer::new(source);
+    let mut tokens = Vec::new();
+    loop {
+        let start = lexer.position();
+        let kind = match lexer.skip_trivia() {
+            Some(trivia) => SyntaxKind::Trivia(trivia),
+            None => match lexer.next_token().kind {
+                TokenKind::Eof => break,
+                kind => SyntaxKind::Token(kind),
+            },
+        };
+        let text = chars[start..lexer.position()].iter().collect();
+        tokens.push(SyntaxToken { kind, text });
+    }
+    tokens
+}
+
+struct Builder {
+    tokens: Vec<SyntaxToken>,
+    pos: usize,
+}
+
+impl Builder {
+    fn root(&mut self) -> Node {
+        let mut root = Node::new(NodeKind::Root);
+        while self.pos < self.tokens.len() {
+            if self.tokens[self.pos].is_trivia() {
+                self.bump(&mut root);
+            } else if self.at_attribute(true) {
+                let attribute = self.attribute();
+                root.children.push(Element::Node(attribute));
+            } else {
+                let leading = Self::leading_comments(&mut root);
+                let item = self.item(leading);
+                root.children.push(Element::Node(item));
+            }
+        }
+        root
+    }
+
+    /// Comments directly above an item, which move with it, and the
+    /// whitespace after them
+    fn leading_comments(parent: &mut Node) -> Vec<Element> {
+        let mut start = parent.children.len();
+        for (i, child) in parent.children.iter().enumerate().rev() {
+            match child {
+                Element::Token(SyntaxToken {
+                    kind: SyntaxKind::Trivia(Trivia::Comment),
+                    ..
+                }) => start = i,
+                // A blank line separates comments from the item
+                Element::Token(SyntaxToken {
+                    kind: SyntaxKind::Trivia(Trivia::Whitespace),
+                    text,
+                }) if text.matches('\n').count() < 2 => {}
+                _ => break,
+            }
+        }
+        parent.children.split_off(start)
+    }
+
+    fn item(&mut self, leading: Vec<Element>) -> Node {
+        let mut item = Node {
+            kind: NodeKind::Error,
+            children: leading,
+        };
+        while self.at_attribute(false) {
+            let attribute = self.attribute();
+            item.children.push(Element::Node(attribute));
+            self.trivia(&mut item);
+        }
+        item.kind = self.item_kind();
+        if item.kind == NodeKind::Error {
+            // Up to the next item, or at least one token (none are left
+            // after a dangling attribute)
+            while self.pos < self.tokens.len() {
+                self.element(&mut item);
+                if self.at_item_start() {
+                    break;
+                }
+            }
+            return item;
+        }
+        while self.pos < self.tokens.len() {
+            let kind = self.peek().cloned();
+            self.element(&mut item);
+            match kind {
+                Some(TokenKind::Semicolon) => break,
+                Some(TokenKind::LBrace) if item.kind != NodeKind::Const => break,
+                _ => {}
+            }
+        }
+        item
+    }
+
+    /// Kind of the item starting at the current token
+    fn item_kind(&self) -> NodeKind {
+        let mut kinds = self.tokens[self.pos..]
+            .iter()
+            .filter_map(|token| match &token.kind {
+                SyntaxKind::Token(kind) => Some(kind),
+                SyntaxKind::Trivia(_) => None,
+            })
+            .peekable();
+        if kinds.peek() == Some(&&TokenKind::Pub) {
+            kinds.next();
+            // `(crate)`
+            if kinds.peek() == Some(&&TokenKind::LParen) {
+                kinds.nth(2);
+            }
+        }
+        match kinds.next() {
+            Some(TokenKind::Fn) => NodeKind::Function,
+            Some(TokenKind::Const) if kinds.next() == Some(&TokenKind::Fn) => NodeKind::Function,
+            Some(TokenKind::Const) => NodeKind::Const,
+            Some(TokenKind::Extern) => {
+                if matches!(kinds.peek(), Some(TokenKind::String(_))) {
+                    kinds.next();
+                }
+                if kinds.next() == Some(&TokenKind::Fn) {
+                    NodeKind::Function
+                } else {
+                    NodeKind::Extern
+                }
+            }
+            Some(TokenKind::Struct) => NodeKind::Struct,
+            Some(TokenKind::Enum) => NodeKind::Enum,
+            Some(TokenKind::Impl) => NodeKind::Impl,
+            Some(TokenKind::Trait) => NodeKind::Trait,
+            Some(TokenKind::Type | TokenKind::Newtype) => NodeKind::TypeAlias,
+            Some(TokenKind::Macro) => NodeKind::Macro,
+            _ => NodeKind::Error,
+        }
+    }
+
+    fn at_item_start(&self) -> bool {
+        matches!(
+            self.peek(),
+            Some(
+                TokenKind::Pound
+                    | TokenKind::Pub
+                    | TokenKind::Fn
+                    | TokenKind::Const
+                    | TokenKind::Extern
+                    | TokenKind::Struct
+                    | TokenKind::Enum
+                    | TokenKind::Impl
+                    | TokenKind::Trait
+                    | TokenKind::Type
+                    | TokenKind::Newtype
+                    | TokenKind::Macro
+            )
+        )
+    }
+
+    /// `#![` with `inner`, or `#[`
+    fn at_attribute(&self, inner: bool) -> bool {
+        let mut kinds = self.tokens[self.pos..]
+            .iter()
+            .filter(|token| !token.is_trivia())
+            .map(|token| &token.kind);
+        if kinds.next() != Some(&SyntaxKind::Token(TokenKind::Pound)) {
+            return false;
+        }
+        if inner && kinds.next() != Some(&SyntaxKind::Token(TokenKind::Not)) {
+            return false;
+        }
+        kinds.next() == Some(&SyntaxKind::Token(TokenKind::LBracket))
+    }
+
+    fn attribute(&mut self) -> Node {
+        let mut attribute = Node::new(NodeKind::Attribute);
+        loop {
+            self.trivia(&mut attribute);
+            let bracket = self.peek() == Some(&TokenKind::LBracket);
+            self.element(&mut attribute);
+            if bracket {
+                return attribute;
+            }
+        }
+    }
+
+    /// The next token or, at an opening delimiter, the group it opens
+    fn element(&mut self, parent: &mut Node) {
+        let (kind, close) = match self.peek() {
+            Some(TokenKind::LParen) => (NodeKind::Parens, TokenKind::RParen),
+            Some(TokenKind::LBracket) => (NodeKind::Brackets, TokenKind::RBracket),
+            Some(TokenKind::LBrace) => (NodeKind::Block, TokenKind::RBrace),
+            _ => return self.bump(parent),
+        };
+        let mut group = Node::new(kind);
+        self.bump(&mut group);
+        while self.pos < self.tokens.len() {
+            let closed = self.peek() == Some(&close);
+            self.element(&mut group);
+            if closed {
+                break;
+            }
+        }
+        parent.children.push(Element::Node(group));
+    }
+
+    fn trivia(&mut self, parent: &mut Node) {
+        while self.pos < self.tokens.len() && self.tokens[self.pos].is_trivia() {
+            self.bump(parent);
+        }
+    }
+
+    fn bump(&mut self, parent: &mut Node) {
+        parent
+            .children
+            .push(Element::Token(self.tokens[self.pos].clone()));
+        self.pos += 1;
+    }
+
+    /// Kind of the current token, `None` at trivia or the end
+    fn peek(&self) -> Option<&TokenKind> {
+        match self.tokens.get(self.pos).map(|token| &token.kind) {
+            Some(SyntaxKind::Token(kind)) => Some(kind),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_parse() {
+        let source = "#![feature(extern_c)]\n\n\
+                      // Entry point\n\
+                      fn main() {\n    print(1); /* one */\n}\n\n\
+                      const N: i64 = 2;\n";
+        let root = parse(source);
+        assert_eq!(root.to_string(), source);
+        let kinds: Vec<NodeKind> = root.nodes().map(|node| node.kind).collect();
+        assert_eq!(
+            kinds,
+            [NodeKind::Attribute, NodeKind::Function, NodeKind::Const]
+        );
+
+        let main = root.nodes().nth(1).unwrap();
+        assert_eq!(main.name(), Some("main"));
+        assert!(main.to_string().starts_with("// Entry point\nfn main()"));
+        let body = main.nodes().last().unwrap();
+        assert_eq!(body.kind, NodeKind::Block);
+
+        let offset = source.find("/* one */").unwrap();
+        let (start, token) = root.token_at(offset + 3).unwrap();
+        assert_eq!(start, offset);
+        assert_eq!(token.kind, SyntaxKind::Trivia(Trivia::Comment));
+    }
+
+    /// Apply `text` over `start..end` of `source` to its tree
+    fn edit(source: &str, start: usize, end: usize, text: &str) -> (String, Node) {
+        let mut tree = parse(source);
+        let edit = Edit {
+            start,
+            end,
+            text: text.to_string(),
+        };
+        let mut edited = source.to_string();
+        edit.apply(&mut edited);
+        tree.reparse(&edited, &edit);
+        (edited, tree)
+    }
+
+    #[test]
+    fn test_reparse() {
+        let source =
+            "fn a() {}\n\n// Entry point\nfn main() {\n    print(1);\n}\nconst N: i64 = 2;\n";
+        let at = source.find("print").unwrap();
+        let (edited, tree) = edit(source, at, at + 5, "panic");
+        assert_eq!(tree, parse(&edited));
+
+        // An open comment swallows the items after it, and closing it
+        // brings them back
+        let (edited, tree) = edit(source, at, at, "/* ");
+        assert_eq!(tree, parse(&edited));
+        assert_eq!(tree.nodes().count(), 2);
+        let end = edited.len();
+        let (edited, tree) = edit(&edited, end, end, "*/");
+        assert_eq!(tree, parse(&edited));
+
+        // A comment above an item moves into it
+        let at = source.find("\n\n").unwrap();
+        let (edited, tree) = edit(source, at, at + 2, "\n");
+        assert_eq!(tree, parse(&edited));
+        assert!(tree
+            .nodes()
+            .nth(1)
+            .unwrap()
+            .to_string()
+            .starts_with("// Entry"));
+    }
+
+    /// Pieces of Solo that start, end and break items
+    const PIECES: &[&str] = &[
+        "fn f() { g(); }",
+        "struct S;",
+        "const C: i64 = S { x: 1 };",
+        "impl S {",
+        "}",
+        "{",
+        "(",
+        "]",
+        "#[test]",
+        "#![feature(x)]",
+        "// c\n",
+        "/*",
+        "*/",
+        "\"",
+        "\n",
+        "\n\n",
+        " ",
+        "pub ",
+        "x",
+        ";",
+        "1.",
+        "é",
+    ];
+
+    fn pieces() -> impl Strategy<Value = String> {
+        proptest::collection::vec(proptest::sample::select(PIECES), 0..12).prop_map(|p| p.concat())
+    }
+
+    proptest! {
+        #[test]
+        fn test_parse_is_lossless(source in "\\PC*") {
+            prop_assert_eq!(parse(&source).to_string(), source);
+        }
+
+        #[test]
+        fn test_reparse_is_parse(
+            source in pieces(),
+            a in any::<proptest::sample::Index>(),
+            b in any::<proptest::sample::Index>(),
+            text in pieces(),
+        ) {
+            let bounds: Vec<usize> = (0..=source.len()).filter(|&i| source.is_char_boundary(i)).collect();
+            let (a, b) = (*a.get(&bounds), *b.get(&bounds));
+            let (edited, tree) = edit(&source, a.min(b), a.max(b), &text);
+            prop_assert_eq!(tree, parse(&edited));
+        }
+    }
+}
@@ -0,0 +1,231 @@
+//! Unused code
+//!
+//! The `dead_code` lint follows calls and uses from the roots of a program:
+//! `main`, `#[test]` functions, `pub extern "C"` functions and every `pub`
+//! item, which other code may use. In a package the library is appended to
+//! each target (see [`package`](crate::package)), so library code that no
+//! target and no public item reaches is found as well. It warns on
+//!
+//! - functions and methods no root reaches,
+//! - structs and enums that reachable code never builds (with a literal or
+//!   a variant), and
+//! - fields of the structs built that reachable code never reads.
+//!
+//! Reachability is that of [`closed_world`]: a method call reaches every
+//! method of that name, and fields are matched by name, so an unused method
+//! or field sharing its name with a used one goes unreported. Structs with
+//! `#[derive(...)]` are built and read by serialization, and the fields of
+//! `#[repr(C)]` ones by C code. `#[allow(dead_code)]` on a function, method,
+//! struct or enum silences the lint for it (and a struct's fields).
+
+use std::collections::HashSet;
+
+use crate::ast::*;
+use crate::closed_world;
+use crate::error::Diagnostic;
+use crate::lint::Warning;
+use crate::visit::{self, Visit};
+
+/// Name of the lint reporting unused code
+pub const LINT: &str = "dead_code";
+
+/// Report the unused functions, types and fields of a type-checked program
+pub fn check(program: &Program) -> Vec<Warning> {
+    let live = closed_world::reachable(program, &roots(program));
+    let mut uses = Uses {
+        exprs: &program.exprs,
+        built: HashSet::new(),
+        read: HashSet::new(),
+    };
+    for (name, _, function) in program.functions() {
+        if live.contains(&name) {
+            uses.visit_function(function);
+        }
+    }
+    for item in &program.items {
+        if let Item::Const(c) = item {
+            if live.contains(c.name.as_str()) {
+                uses.visit_expr(&c.value);
+            }
+        }
+    }
+
+    let mut warnings = Vec::new();
+    let mut warn = |span: Span, message: String| {
+        warnings.push(Warning {
+            diagnostic: Diagnostic::new(span, message),
+            lint: LINT,
+        })
+    };
+    for (name, self_ty, function) in program.functions() {
+        if !live.contains(&name) && !allows(&function.attrs, LINT) {
+            let kind = if self_ty.is_some() {
+                "method"
+            } else {
+                "function"
+            };
+            warn(function.span, format!("{} `{}` is never used", kind, name));
+        }
+    }
+    for item in &program.items {
+        match item {
+            Item::Struct(def) if !allows(&def.attrs, LINT) => {
+                let derived = def.derives().next().is_some();
+                if !def.vis.is_public() && !derived && !uses.built.contains(def.name.as_str()) {
+                    warn(
+                        def.span,
+                        format!("struct `{}` is never constructed", def.name),
+                    );
+                    continue;
+                }
+                let c = def.repr_hints().any(|hint| hint == "C");
+                if derived || c {
+                    continue;
+                }
+                for field in &def.fields {
+                    let public = def.vis.is_public() && field.vis.is_public();
+                    if !public && !uses.read.contains(field.name.as_str()) {
+                        warn(
+                            field.span,
+                            format!(
+                                "field `{}` of struct `{}` is never read",
+                                field.name, def.name
+                            ),
+                        );
+                    }
+                }
+            }
+            Item::Enum(def)
+                if !allows(&def.attrs, LINT)
+                    && !def.vis.is_public()
+                    && !uses.built.contains(def.name.as_str()) =>
+            {
+                warn(
+                    def.span,
+                    format!("no variant of enum `{}` is ever constructed", def.name),
+                );
+            }
+            _ => {}
+        }
+    }
+    warnings
+}
+
+/// `main`, tests, exports and `pub` items
+fn roots(program: &Program) -> Vec<String> {
+    let mut roots = vec!["main".to_string()];
+    for (name, _, function) in program.functions() {
+        if function.vis.is_public() || function.exported || function.is_test() {
+            roots.push(name);
+        }
+    }
+    for item in &program.items {
+        let (vis, name) = match item {
+            Item::Const(c) => (c.vis, &c.name),
+            Item::Struct(def) => (def.vis, &def.name),
+            Item::Enum(def) => (def.vis, &def.name),
+            Item::TypeAlias(alias) => (alias.vis, &alias.name),
+            Item::Function(_) | Item::Impl(_) | Item::Extern(_) => continue,
+        };
+        if vis.is_public() {
+            roots.push(name.to_string());
+        }
+    }
+    roots
+}
+
+/// Types built and fields read by the code visited
+struct Uses<'a> {
+    exprs: &'a Exprs,
+    /// Structs built with a literal and enums with a variant
+    built: HashSet<String>,
+    read: HashSet<String>,
+}
+
+impl<'a> Visit<'a> for Uses<'a> {
+    fn exprs(&self) -> &'a Exprs {
+        self.exprs
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::StructLit(name, _) => {
+                self.built.insert(name.to_string());
+            }
+            ExprKind::Path(segments) if segments.len() == 2 => {
+                self.built.insert(segments[0].to_string());
+            }
+            ExprKind::Field(_, name) => {
+                self.read.insert(name.to_string());
+            }
+            _ => {}
+        }
+        visit::walk_expr(self, expr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dead_code(source: &str) -> Vec<String> {
+        let (program, _) = crate::analyze(source).unwrap();
+        check(&program)
+            .into_iter()
+            .map(|w| w.diagnostic.message)
+            .collect()
+    }
+
+    #[test]
+    fn test_dead_code() {
+        let warnings = dead_code(
+            "struct Story { words: i64, draft: bool }
+             struct Draft { text: str }
+             #[allow(dead_code)]
+             struct Scratch { text: str }
+             enum Desk { News, Sport }
+             enum Beat { Courts }
+             impl Story {
+                 fn words(&self) -> i64 { self.words }
+                 fn publish(&self) {}
+             }
+             fn main() {
+                 let s = Story { words: 3, draft: false };
+                 print(s.words());
+                 let d = Desk::News;
+             }
+             fn unused() -> Draft { helper(); Draft { text: \"x\" } }
+             fn helper() {}
+             #[allow(dead_code)]
+             fn kept() { print(Beat::Courts); }
+             pub fn api() {}
+             #[test]
+             fn test_words() { helper(); }",
+        );
+        assert_eq!(
+            warnings,
+            [
+                "method `Story::publish` is never used",
+                "function `unused` is never used",
+                "field `draft` of struct `Story` is never read",
+                "struct `Draft` is never constructed",
+                "no variant of enum `Beat` is ever constructed",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_library_roots() {
+        // `pub` library items are used by other code; private ones only
+        // through them
+        let warnings = dead_code(
+            "fn main() { print(triple(2)); }
+             #![library]
+             pub fn triple(x: i64) -> i64 { x * 3 }
+             pub fn double(x: i64) -> i64 { twice(x) }
+             fn twice(x: i64) -> i64 { x + x }
+             fn stale(x: i64) -> i64 { x }",
+        );
+        assert_eq!(warnings, ["function `stale` is never used"]);
+    }
+}
@@ -0,0 +1,453 @@
+//! Compiler errors
+//!
+//! Pipeline stages report [`Diagnostic`]s; the library entry points return a
+//! [`CompileError`] naming the stage that failed, so callers can match on the
+//! failure category and still get at every individual message.
+//!
+//! Diagnostics are reported in source order (file, then position) and each
+//! has a [fingerprint](Diagnostic::fingerprint) that survives unrelated edits,
+//! so CI tooling can tell new errors from pre-existing ones. Some carry a
+//! [`Fix`] that tools can apply without asking, and some an [`ErrorCode`]
+//! that the documentation explains.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::ast::Span;
+use crate::interp::LimitExceeded;
+use crate::json::Json;
+
+/// A single error message, located in the source when possible
+///
+/// Diagnostics order by file, then position, then message.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Diagnostic {
+    pub file: Option<PathBuf>,
+    pub span: Option<Span>,
+    pub message: String,
+    pub fix: Option<Box<Fix>>,
+    pub code: Option<ErrorCode>,
+    /// Another place the message is about, kept out of it so that moving
+    /// it does not change the [fingerprint](Diagnostic::fingerprint)
+    pub related: Option<Box<Related>>,
+}
+
+impl Diagnostic {
+    pub fn new(span: Span, message: impl Into<String>) -> Self {
+        Self {
+            file: None,
+            span: Some(span),
+            message: message.into(),
+            fix: None,
+            code: None,
+            related: None,
+        }
+    }
+
+    /// A diagnostic with no meaningful source position
+    pub fn unspanned(message: impl Into<String>) -> Self {
+        Self {
+            file: None,
+            span: None,
+            message: message.into(),
+            fix: None,
+            code: None,
+            related: None,
+        }
+    }
+
+    pub fn with_code(mut self, code: ErrorCode) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    pub fn with_fix(mut self, fix: Fix) -> Self {
+        self.fix = Some(Box::new(fix));
+        self
+    }
+
+    /// Point at `span` too, with a note saying what is there
+    pub fn with_related(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.related = Some(Box::new(Related {
+            file: None,
+            span,
+            message: message.into(),
+        }));
+        self
+    }
+
+    /// Stable identifier of this diagnostic within `source`
+    ///
+    /// Hashes the message and the text of the offending line rather than its
+    /// number, so edits elsewhere in the file do not change it.
+    pub fn fingerprint(&self, source: &str) -> String {
+        let line = self
+            .span
+            .and_then(|span| source.lines().nth(span.line.checked_sub(1)?))
+            .unwrap_or("");
+        let mut hash = FNV_OFFSET;
+        for part in [self.message.as_str(), line.trim()] {
+            for byte in part.bytes().chain([0]) {
+                hash = (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME);
+            }
+        }
+        format!("{:016x}", hash)
+    }
+}
+
+/// A kind of error that has a stable code, shown as `error[E0001]`, and a
+/// longer explanation in the documentation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ErrorCode {
+    /// A C function called, or a raw pointer dereferenced or offset,
+    /// outside an `unsafe` block
+    UnsafeOperation,
+    /// `observe`, or a call to a `#[model]` function, outside an `expect`
+    /// argument and `#[model]` functions
+    OutsideModel,
+}
+
+impl ErrorCode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorCode::UnsafeOperation => "E0001",
+            ErrorCode::OutsideModel => "E0002",
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A place a diagnostic refers to besides its own, such as the earlier
+/// declaration a binding shadows, shown as a note
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Related {
+    /// `None` for the diagnostic's own file
+    pub file: Option<PathBuf>,
+    pub span: Span,
+    pub message: String,
+}
+
+impl Related {
+    pub fn to_json(&self) -> Json {
+        let file = self
+            .file
+            .as_ref()
+            .map_or(Json::Null, |f| Json::Str(f.display().to_string()));
+        Json::object([
+            ("file", file),
+            ("line", Json::Int(self.span.line as i64)),
+            ("column", Json::Int(self.span.column as i64)),
+            ("message", Json::str(self.message.as_str())),
+        ])
+    }
+}
+
+/// An edit resolving a diagnostic: the text from `start` up to `end`
+/// replaced by `replacement`, possibly in another file than the diagnostic
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Fix {
+    pub file: Option<PathBuf>,
+    pub start: Span,
+    /// Equal to `start` for an insertion
+    pub end: Span,
+    pub replacement: String,
+}
+
+impl Fix {
+    pub fn new(start: Span, end: Span, replacement: impl Into<String>) -> Self {
+        Self {
+            file: None,
+            start,
+            end,
+            replacement: replacement.into(),
+        }
+    }
+
+    fn to_json(&self) -> Json {
+        let file = self
+            .file
+            .as_ref()
+            .map_or(Json::Null, |f| Json::Str(f.display().to_string()));
+        Json::object([
+            ("file", file),
+            ("line", Json::Int(self.start.line as i64)),
+            ("column", Json::Int(self.start.column as i64)),
+            ("end_line", Json::Int(self.end.line as i64)),
+            ("end_column", Json::Int(self.end.column as i64)),
+            ("replacement", Json::str(self.replacement.as_str())),
+        ])
+    }
+}
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{}:", file.display())?;
+        }
+        match self.span {
+            Some(span) => write!(f, "{}:{}: {}", span.line, span.column, self.message)?,
+            None if self.file.is_some() => write!(f, " {}", self.message)?,
+            None => write!(f, "{}", self.message)?,
+        }
+        if let Some(related) = &self.related {
+            write!(f, "\n  note: ")?;
+            if let Some(file) = related.file.as_ref().or(self.file.as_ref()) {
+                write!(f, "{}:", file.display())?;
+            }
+            write!(
+                f,
+                "{}:{}: {}",
+                related.span.line, related.span.column, related.message
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Why compiling (or running) a program failed
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum CompileError {
+    /// A source file could not be read
+    Io { path: PathBuf, source: io::Error },
+    /// Invalid characters or malformed literals
+    Lex(Vec<Diagnostic>),
+    /// Syntax errors, including failed macro expansion
+    Parse(Vec<Diagnostic>),
+    /// Type, move checking and constant evaluation errors
+    Type(Vec<Diagnostic>),
+    /// Code generation failed
+    Codegen(String),
+    /// Constructs without an equivalent in the dialect translated to
+    Translate(Vec<Diagnostic>),
+    /// Linking the final executable or library failed
+    Link(String),
+    /// The interpreted program panicked
+    Runtime(String),
+    /// The interpreted program was stopped by a resource limit
+    LimitExceeded(LimitExceeded),
+}
+
+impl CompileError {
+    /// Diagnostics carried by the error; empty for non-source failures
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        match self {
+            CompileError::Lex(diagnostics)
+            | CompileError::Parse(diagnostics)
+            | CompileError::Type(diagnostics)
+            | CompileError::Translate(diagnostics) => diagnostics,
+            _ => &[],
+        }
+    }
+
+    /// Short machine-readable name of the failing stage
+    pub fn stage(&self) -> &'static str {
+        match self {
+            CompileError::Io { .. } => "io",
+            CompileError::Lex(_) => "lex",
+            CompileError::Parse(_) => "parse",
+            CompileError::Type(_) => "type",
+            CompileError::Codegen(_) => "codegen",
+            CompileError::Translate(_) => "translate",
+            CompileError::Link(_) => "link",
+            CompileError::Runtime(_) => "runtime",
+            CompileError::LimitExceeded(_) => "limit",
+        }
+    }
+
+    pub fn diagnostics_mut(&mut self) -> &mut [Diagnostic] {
+        match self {
+            CompileError::Lex(diagnostics)
+            | CompileError::Parse(diagnostics)
+            | CompileError::Type(diagnostics)
+            | CompileError::Translate(diagnostics) => diagnostics,
+            _ => &mut [],
+        }
+    }
+
+    /// Attribute every diagnostic and fix not yet placed in a file to `path`
+    pub fn in_file(mut self, path: &Path) -> Self {
+        let diagnostics = self.diagnostics_mut();
+        for diagnostic in diagnostics.iter_mut() {
+            if diagnostic.file.is_none() {
+                diagnostic.file = Some(path.to_path_buf());
+            }
+            if let Some(fix) = diagnostic.fix.as_mut().filter(|fix| fix.file.is_none()) {
+                fix.file = Some(path.to_path_buf());
+            }
+            if let Some(related) = diagnostic.related.as_mut().filter(|r| r.file.is_none()) {
+                related.file = Some(path.to_path_buf());
+            }
+        }
+        diagnostics.sort();
+        self
+    }
+
+    /// One JSON object per diagnostic, fingerprinted against `source`
+    pub fn to_json(&self, source: &str) -> Vec<Json> {
+        let stage = Json::str(self.stage());
+        if let CompileError::LimitExceeded(exceeded) = self {
+            return vec![Json::object([
+                ("stage", stage),
+                ("limit", Json::str(exceeded.limit.name())),
+                ("line", Json::Int(exceeded.location.line as i64)),
+                ("column", Json::Int(exceeded.location.column as i64)),
+                ("steps", Json::Int(exceeded.steps as i64)),
+                ("message", Json::Str(self.to_string())),
+            ])];
+        }
+        if self.diagnostics().is_empty() {
+            return vec![Json::object([
+                ("stage", stage),
+                ("message", Json::Str(self.to_string())),
+            ])];
+        }
+        self.diagnostics()
+            .iter()
+            .map(|d| {
+                let file = d
+                    .file
+                    .as_ref()
+                    .map_or(Json::Null, |f| Json::Str(f.display().to_string()));
+                let (line, column) = d.span.map_or((Json::Null, Json::Null), |s| {
+                    (Json::Int(s.line as i64), Json::Int(s.column as i64))
+                });
+                let mut fields = vec![
+                    ("stage", stage.clone()),
+                    ("file", file),
+                    ("line", line),
+                    ("column", column),
+                    ("message", Json::str(d.message.as_str())),
+                    ("fingerprint", Json::Str(d.fingerprint(source))),
+                ];
+                if let Some(code) = d.code {
+                    fields.push(("code", Json::str(code.as_str())));
+                }
+                if let Some(fix) = &d.fix {
+                    fields.push(("fix", fix.to_json()));
+                }
+                if let Some(related) = &d.related {
+                    fields.push(("related", related.to_json()));
+                }
+                Json::object(fields)
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (stage, diagnostics) = match self {
+            CompileError::Io { path, source } => {
+                return write!(f, "I/O error reading '{}': {}", path.display(), source)
+            }
+            CompileError::Codegen(msg) => return write!(f, "Codegen error: {}", msg),
+            CompileError::Link(msg) => return write!(f, "Link error: {}", msg),
+            CompileError::Runtime(msg) => return write!(f, "{}", msg),
+            CompileError::LimitExceeded(exceeded) => return write!(f, "{}", exceeded),
+            CompileError::Lex(diagnostics) => ("Lexer", diagnostics),
+            CompileError::Parse(diagnostics) => ("Parse", diagnostics),
+            CompileError::Type(diagnostics) => ("Type", diagnostics),
+            CompileError::Translate(diagnostics) => ("Translation", diagnostics),
+        };
+        for (i, diagnostic) in diagnostics.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{} error", stage)?;
+            if let Some(code) = diagnostic.code {
+                write!(f, "[{}]", code)?;
+            }
+            match diagnostic.span {
+                Some(_) => write!(f, " at {}", diagnostic)?,
+                None => write!(f, ": {}", diagnostic)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Error for CompileError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CompileError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let err = CompileError::Type(vec![
+            Diagnostic::new(Span::new(2, 5), "mismatched types"),
+            Diagnostic::unspanned("no `main` function"),
+        ]);
+        assert_eq!(
+            err.to_string(),
+            "Type error at 2:5: mismatched types\nType error: no `main` function"
+        );
+        assert_eq!(err.diagnostics().len(), 2);
+        let coded = CompileError::Type(vec![Diagnostic::new(
+            Span::new(4, 9),
+            "call to C function `abs` is unsafe",
+        )
+        .with_code(ErrorCode::UnsafeOperation)]);
+        assert_eq!(
+            coded.to_string(),
+            "Type error[E0001] at 4:9: call to C function `abs` is unsafe"
+        );
+        assert!(CompileError::Link("ld failed".into())
+            .diagnostics()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_order_and_fingerprints() {
+        let err = CompileError::Type(vec![
+            Diagnostic::new(Span::new(3, 1), "b"),
+            Diagnostic::new(Span::new(1, 9), "a"),
+        ])
+        .in_file(Path::new("main.solo"));
+        assert_eq!(
+            err.to_string(),
+            "Type error at main.solo:1:9: a\nType error at main.solo:3:1: b"
+        );
+
+        // Moving the offending line does not change the fingerprint
+        let before = "fn f() {}\nlet x = y;";
+        let after = "// comment\nfn f() {}\n\nlet x = y;";
+        let at = |line| Diagnostic::new(Span::new(line, 9), "cannot find value `y`");
+        assert_eq!(at(2).fingerprint(before), at(4).fingerprint(after));
+        assert_ne!(at(2).fingerprint(before), at(1).fingerprint(before));
+
+        // So does moving a related place, which is shown as a note
+        let shadowing = |line| {
+            Diagnostic::new(Span::new(3, 5), "`x` shadows an earlier binding")
+                .with_related(Span::new(line, 5), "`x` first declared here")
+        };
+        assert_eq!(
+            shadowing(1).fingerprint(before),
+            shadowing(2).fingerprint(before)
+        );
+        let err = CompileError::Type(vec![shadowing(1)]).in_file(Path::new("main.solo"));
+        assert_eq!(
+            err.to_string(),
+            "Type error at main.solo:3:5: `x` shadows an earlier binding\n  \
+             note: main.solo:1:5: `x` first declared here"
+        );
+    }
+}
@@ -0,0 +1,300 @@
+//! Arena escape analysis
+//!
+//! Everything allocated inside an `arena { ... }` lives until the arena ends,
+//! even when the value is dead long before: a struct built on every pass of a
+//! loop piles up in the enclosing arena. This pass finds `let` bindings of
+//! freshly allocated values (struct literals, arrays and distributions) that
+//! never escape a block nested inside the arena, and suggests giving that
+//! block an arena of its own.
+//!
+//! The analysis is lexical and conservative: a value escapes when it is
+//! returned, is the value of a block, is moved or copied into another
+//! binding, struct, array or call, or is borrowed. Reading its fields or
+//! elements, assigning to them and comparing it do not count.
+
+use crate::ast::*;
+use crate::error::Diagnostic;
+use crate::lint::Warning;
+
+/// Name of the lint reporting scoped arena suggestions
+pub const LINT: &str = "scoped_arena";
+
+/// Suggest scoped arenas for the functions of a type-checked program
+pub fn check(program: &Program) -> Vec<Warning> {
+    let mut analysis = Analysis {
+        exprs: &program.exprs,
+        blocks: Vec::new(),
+        candidates: Vec::new(),
+        warnings: Vec::new(),
+    };
+    for (_, _, function) in program.functions() {
+        analysis.block(&function.body, false);
+    }
+    analysis.warnings
+}
+
+/// A `let` bound allocation that may be confined to its block
+struct Candidate {
+    name: String,
+    /// Span of the allocating expression
+    span: Span,
+    /// Index of the block declaring it in [`Analysis::blocks`]
+    block: usize,
+    escaped: bool,
+}
+
+struct Analysis<'a> {
+    exprs: &'a Exprs,
+    /// Blocks being visited, outermost first, and whether each is an arena
+    blocks: Vec<(Span, bool)>,
+    candidates: Vec<Candidate>,
+    warnings: Vec<Warning>,
+}
+
+impl Analysis<'_> {
+    fn block(&mut self, block: &Block, is_arena: bool) {
+        self.blocks.push((block.span, is_arena));
+        let depth = self.blocks.len() - 1;
+        for stmt in &block.stmts {
+            match stmt {
+                Stmt::Let(let_stmt) => {
+                    if let Some(value) = &let_stmt.value {
+                        self.expr(value, true);
+                        self.declare(&let_stmt.name, value, depth);
+                    }
+                }
+                Stmt::Const(item) => self.expr(&item.value, true),
+                Stmt::Expr(expr) | Stmt::Defer(expr) => self.expr(expr, false),
+            }
+        }
+        if let Some(tail) = &block.tail {
+            self.expr(&self.exprs[*tail], true);
+        }
+
+        let start = self
+            .candidates
+            .iter()
+            .position(|c| c.block == depth)
+            .unwrap_or(self.candidates.len());
+        for candidate in self.candidates.split_off(start) {
+            if !candidate.escaped {
+                let message = format!(
+                    "`{}` never escapes its block; consider a scoped `arena` for that block",
+                    candidate.name
+                );
+                self.warnings.push(Warning {
+                    diagnostic: Diagnostic::new(candidate.span, message)
+                        .with_related(block.span, "the block it stays in"),
+                    lint: LINT,
+                });
+            }
+        }
+        self.blocks.pop();
+    }
+
+    /// Record `name = value` if the value is allocated in an enclosing arena
+    /// that outlives the declaring block
+    fn declare(&mut self, name: &str, value: &Expr, depth: usize) {
+        let allocates = matches!(
+            value.kind,
+            ExprKind::StructLit(..)
+                | ExprKind::Array(_)
+                | ExprKind::ArrayRepeat(..)
+                | ExprKind::Distribution(..)
+        );
+        let arena = self.blocks.iter().rposition(|&(_, is_arena)| is_arena);
+        if allocates && arena.is_some_and(|arena| arena < depth) {
+            self.candidates.push(Candidate {
+                name: name.to_string(),
+                span: value.span,
+                block: depth,
+                escaped: false,
+            });
+        } else if let Some(shadowed) = self.candidates.iter().rposition(|c| c.name == name) {
+            // A new binding hides the candidate for the rest of its scope;
+            // treating the candidate as escaped keeps the analysis sound
+            self.candidates[shadowed].escaped = true;
+        }
+    }
+
+    /// Visit `expr`; `escapes` is whether its value can outlive the
+    /// expression
+    fn expr(&mut self, expr: &Expr, escapes: bool) {
+        match &expr.kind {
+            ExprKind::Int(..) | ExprKind::Float(..) | ExprKind::Str(_) | ExprKind::Bool(_) => {}
+            ExprKind::Path(segments) => {
+                if let [name] = segments.as_slice() {
+                    if escapes {
+                        self.escape(name);
+                    }
+                }
+            }
+            ExprKind::Unary(op, operand) => {
+                let borrows = matches!(op, UnaryOp::Ref | UnaryOp::RefMut);
+                self.expr(&self.exprs[*operand], borrows);
+            }
+            ExprKind::Cast(value, _) => self.expr(&self.exprs[*value], false),
+            ExprKind::Binary(_, lhs, rhs) => {
+                self.expr(&self.exprs[*lhs], false);
+                self.expr(&self.exprs[*rhs], false);
+            }
+            ExprKind::Assign(target, value) => {
+                self.expr(&self.exprs[*target], false);
+                self.expr(&self.exprs[*value], true);
+            }
+            ExprKind::Field(base, _) => self.expr(&self.exprs[*base], false),
+            ExprKind::Index(base, index) => {
+                self.expr(&self.exprs[*base], false);
+                self.expr(&self.exprs[*index], false);
+            }
+            ExprKind::Range(start, end, _) => {
+                for end in [start, end].into_iter().flatten() {
+                    self.expr(&self.exprs[*end], false);
+                }
+            }
+            ExprKind::Call(callee, args) => {
+                self.expr(&self.exprs[*callee], false);
+                args.iter().for_each(|arg| self.expr(arg, true));
+            }
+            ExprKind::MethodCall(receiver, _, args) => {
+                // The receiver may be taken by value
+                self.expr(&self.exprs[*receiver], true);
+                args.iter().for_each(|arg| self.expr(arg, true));
+            }
+            ExprKind::StructLit(_, inits) => {
+                inits.iter().for_each(|init| self.expr(&init.value, true));
+            }
+            ExprKind::Distribution(_, args) | ExprKind::Array(args) => {
+                args.iter().for_each(|arg| self.expr(arg, true));
+            }
+            ExprKind::ArrayRepeat(value, len) => {
+                self.expr(&self.exprs[*value], true);
+                self.expr(&self.exprs[*len], false);
+            }
+            ExprKind::If(cond, then_block, else_expr) => {
+                self.expr(&self.exprs[*cond], false);
+                self.block(then_block, false);
+                if let Some(else_expr) = else_expr {
+                    self.expr(&self.exprs[*else_expr], escapes);
+                }
+            }
+            ExprKind::While(cond, body) => {
+                self.expr(&self.exprs[*cond], false);
+                self.block(body, false);
+            }
+            // The items are moved out of the iterable into the loop
+            ExprKind::For(_, iterable, body) => {
+                self.expr(&self.exprs[*iterable], true);
+                self.block(body, false);
+            }
+            // Bindings may move the scrutinee out into an arm
+            ExprKind::Match(scrutinee, arms) => {
+                self.expr(&self.exprs[*scrutinee], true);
+                for arm in arms {
+                    if let Some(guard) = arm.guard {
+                        self.expr(&self.exprs[guard], false);
+                    }
+                    self.expr(&self.exprs[arm.value], escapes);
+                }
+            }
+            ExprKind::Block(block) | ExprKind::Unsafe(block) => self.block(block, false),
+            ExprKind::Arena(_, allocator, block) => {
+                if let Some(allocator) = allocator {
+                    self.expr(&self.exprs[*allocator], false);
+                }
+                self.block(block, true)
+            }
+            ExprKind::Return(value) => {
+                if let Some(value) = value {
+                    self.expr(&self.exprs[*value], true);
+                }
+            }
+            ExprKind::Become(call) => self.expr(&self.exprs[*call], true),
+            // Operands are integers and floats in registers, never references
+            ExprKind::Asm(_, operands) => {
+                for operand in operands {
+                    self.expr(&operand.expr, false);
+                }
+            }
+        }
+    }
+
+    fn escape(&mut self, name: &str) {
+        if let Some(candidate) = self.candidates.iter_mut().rev().find(|c| c.name == name) {
+            candidate.escaped = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn suggestions(source: &str) -> Vec<String> {
+        crate::lint(source)
+            .expect("program should compile")
+            .into_iter()
+            .filter(|w| w.lint == LINT)
+            .map(|w| w.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_scoped_arena_suggestions() {
+        let source = "
+            struct Point { x: i64, y: i64 }
+            fn main() {
+                arena {
+                    let mut total = 0;
+                    let mut i = 0;
+                    while i < 10 {
+                        let p = Point { x: i, y: 2 };
+                        let kept = [i, i];
+                        let origin = Point { x: 1, y: 1 };
+                        total = total + p.x * p.y + kept[0];
+                        if i == 9 { print(kept[1]); print(origin.x); }
+                        i = i + 1;
+                    }
+                    let outer = Point { x: total, y: 0 };
+                    print(outer.x);
+                }
+                let free = Point { x: 0, y: 0 };
+                print(free.x);
+            }";
+        assert_eq!(
+            suggestions(source),
+            [
+                "warning[scoped_arena] at 8:33: `p` never escapes its block; consider a \
+                 scoped `arena` for that block\n  note: 7:34: the block it stays in",
+                "warning[scoped_arena] at 9:36: `kept` never escapes its block; consider a \
+                 scoped `arena` for that block\n  note: 7:34: the block it stays in",
+                "warning[scoped_arena] at 10:38: `origin` never escapes its block; consider a \
+                 scoped `arena` for that block\n  note: 7:34: the block it stays in",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_escaping_values_are_not_reported() {
+        let source = "
+            struct Point { x: i64, y: i64 }
+            fn keep(p: Point) -> i64 { p.x }
+            fn main() {
+                arena {
+                    let mut last = Point { x: 0, y: 0 };
+                    let mut i = 0;
+                    while i < 3 {
+                        let moved = Point { x: i, y: i };
+                        last = moved;
+                        let passed = Point { x: i, y: i };
+                        print(keep(passed));
+                        let tail = { let inner = [i]; inner };
+                        print(tail[0]);
+                        i = i + 1;
+                    }
+                    print(last.y);
+                }
+            }";
+        assert!(suggestions(source).is_empty(), "{:?}", suggestions(source));
+    }
+}
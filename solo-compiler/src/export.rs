@@ -0,0 +1,157 @@
+//! Symbols exported by libraries
+//!
+//! `solo build --lib` produces a static and a shared library whose
+//! interface is the program's `pub extern "C"` functions. Each is exported
+//! under a symbol made of the crate name and the function name, joined by
+//! an underscore (`score` of crate `news-feed` is `news_feed_score`), so
+//! that two Solo libraries linked into one program do not clash.
+//! `#[no_mangle]` exports a function under its own name instead. The scheme
+//! is stable: a symbol only changes when the crate or the function is
+//! renamed.
+//!
+//! [`header`] writes the C declarations of the exports, for C callers and
+//! for binding generators such as `bindgen` or Python's `cffi`.
+
+use std::fmt::Write;
+
+use crate::ast::{Item, Program};
+use crate::error::{CompileError, Diagnostic};
+use crate::ffi::Signature;
+use crate::typeck::TypeInfo;
+
+/// A function exported from a library
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Export {
+    /// Name of the function in Solo
+    pub function: String,
+    /// Name of the symbol in the library
+    pub symbol: String,
+    pub signature: Signature,
+}
+
+/// `name` made into a C identifier: characters other than ASCII letters,
+/// digits and `_` become `_`, and a leading digit gets a `_` before it
+pub fn crate_name(name: &str) -> String {
+    let mut ident: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if !ident.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        ident.insert(0, '_');
+    }
+    ident
+}
+
+/// The symbol of `function` in crate `crate_name`
+pub fn symbol(crate_name: &str, function: &str, no_mangle: bool) -> String {
+    if no_mangle {
+        function.to_string()
+    } else {
+        format!("{}_{}", self::crate_name(crate_name), function)
+    }
+}
+
+/// The `pub extern "C"` functions of a checked program, sorted by symbol
+pub fn exports(
+    crate_name: &str,
+    program: &Program,
+    info: &TypeInfo,
+) -> Result<Vec<Export>, CompileError> {
+    let mut exports: Vec<(Export, _)> = program
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            Item::Function(function) if function.exported => Some(function),
+            _ => None,
+        })
+        .filter_map(|function| {
+            let export = Export {
+                function: function.name.to_string(),
+                symbol: symbol(crate_name, &function.name, function.is_no_mangle()),
+                signature: info.exports.get(function.name.as_str())?.clone(),
+            };
+            Some((export, function.span))
+        })
+        .collect();
+    exports.sort_by(|(a, _), (b, _)| a.symbol.cmp(&b.symbol));
+
+    let errors: Vec<Diagnostic> = exports
+        .windows(2)
+        .filter(|pair| pair[0].0.symbol == pair[1].0.symbol)
+        .map(|pair| {
+            Diagnostic::new(
+                pair[1].1,
+                format!(
+                    "`{}` and `{}` are both exported as the symbol `{}`",
+                    pair[0].0.function, pair[1].0.function, pair[1].0.symbol
+                ),
+            )
+        })
+        .collect();
+    if errors.is_empty() {
+        Ok(exports.into_iter().map(|(export, _)| export).collect())
+    } else {
+        Err(CompileError::Type(errors))
+    }
+}
+
+/// A C header declaring `exports`
+pub fn header(crate_name: &str, exports: &[Export]) -> String {
+    let guard = format!("{}_H", self::crate_name(crate_name).to_ascii_uppercase());
+    let mut out = String::new();
+    writeln!(out, "/* Generated by solo from crate `{}` */", crate_name).unwrap();
+    writeln!(out, "#ifndef {}\n#define {}\n", guard, guard).unwrap();
+    out.push_str("#include <stdbool.h>\n#include <stdint.h>\n\n");
+    out.push_str("#ifdef __cplusplus\nextern \"C\" {\n#endif\n\n");
+    for export in exports {
+        let Signature { params, ret } = &export.signature;
+        let params: Vec<String> = params.iter().map(|p| p.to_string()).collect();
+        let params = if params.is_empty() {
+            "void".to_string()
+        } else {
+            params.join(", ")
+        };
+        writeln!(out, "{} {}({});", ret, export.symbol, params).unwrap();
+    }
+    out.push_str("\n#ifdef __cplusplus\n}\n#endif\n\n");
+    writeln!(out, "#endif /* {} */", guard).unwrap();
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exports_and_header() {
+        let source = "#![feature(extern_c)]
+             pub extern \"C\" fn score(words: i64, sources: i32) -> f64 { 1.0 }
+             #[no_mangle]
+             pub extern \"C\" fn newsroom_version() -> i32 { 3 }
+             pub extern \"C\" fn log(message: &str) {}
+             fn helper() {}";
+        let (program, info) = crate::analyze(source).unwrap();
+        let found = exports("news-feed", &program, &info).unwrap();
+        let symbols: Vec<&str> = found.iter().map(|e| e.symbol.as_str()).collect();
+        assert_eq!(
+            symbols,
+            ["news_feed_log", "news_feed_score", "newsroom_version"]
+        );
+        let text = header("news-feed", &found);
+        assert!(text.contains("#ifndef NEWS_FEED_H"), "{}", text);
+        assert!(text.contains(
+            "void news_feed_log(const char *);\n\
+             double news_feed_score(int64_t, int32_t);\n\
+             int32_t newsroom_version(void);\n"
+        ));
+        assert_eq!(crate_name("2d-plot"), "_2d_plot");
+
+        let clash = "#![feature(extern_c)]
+             #[no_mangle]
+             pub extern \"C\" fn a_b() {}
+             pub extern \"C\" fn b() {}";
+        let (program, info) = crate::analyze(clash).unwrap();
+        let err = exports("a", &program, &info).unwrap_err().to_string();
+        assert!(err.contains("`a_b` and `b` are both exported as the symbol `a_b`"));
+    }
+}
@@ -0,0 +1,677 @@
+//! Feature gates for unstable language features
+//!
+//! Experimental parts of the language have to be enabled with
+//! `#![feature(name)]` at the top of the file before they can be used, so
+//! they can still change without breaking programs that did not opt in.
+//! The `solo` command line only accepts `#![feature]` with `--unstable`;
+//! without it a program is limited to the stable language.
+//!
+//! The registry also lists planned features that have no implementation
+//! yet, so that writing their syntax (`async`, `trait`, `fn f<T>`) reports
+//! what is missing and when it is due instead of a generic syntax error.
+
+use std::collections::HashSet;
+
+use crate::ast::*;
+use crate::error::{CompileError, Diagnostic};
+use crate::stdlib::{atomic, simd};
+use crate::visit::{self, Visit};
+
+/// A language feature that must be enabled before use
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Feature {
+    pub name: &'static str,
+    /// What the feature covers, for diagnostics
+    pub description: &'static str,
+    pub status: Status,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// Implemented, but may still change
+    Unstable,
+    /// Not implemented yet
+    Planned {
+        /// Keyword starting the feature's syntax
+        keyword: Option<&'static str>,
+        /// Release expected to bring it, as in the specification
+        target: Option<&'static str>,
+    },
+}
+
+impl Feature {
+    /// Message for using the syntax of a planned feature
+    pub fn not_implemented(&self) -> String {
+        let target = match self.status {
+            Status::Planned {
+                target: Some(target),
+                ..
+            } => format!("; planned for {}", target),
+            _ => String::new(),
+        };
+        format!(
+            "{} are not implemented yet{} (feature `{}`)",
+            self.description, target, self.name
+        )
+    }
+}
+
+/// Every gated feature
+pub const FEATURES: &[Feature] = &[
+    Feature {
+        name: "any_type",
+        description: "the `any` type",
+        status: Status::Unstable,
+    },
+    Feature {
+        name: "arena_allocators",
+        description: "arenas backed by an allocator, `arena in buffer { ... }`",
+        status: Status::Unstable,
+    },
+    Feature {
+        name: "arena_handles",
+        description: "named arenas and `Arena` parameters",
+        status: Status::Unstable,
+    },
+    Feature {
+        name: "async_functions",
+        description: "`async` functions",
+        status: Status::Planned {
+            keyword: Some("async"),
+            target: None,
+        },
+    },
+    Feature {
+        name: "atomics",
+        description: "atomic integers and `atomic::fence`",
+        status: Status::Unstable,
+    },
+    Feature {
+        name: "belief_conditioning",
+        description: "`observe`, `expect` and `#[model]` functions",
+        status: Status::Unstable,
+    },
+    Feature {
+        name: "belief_declarations",
+        description: "`belief` declarations",
+        status: Status::Planned {
+            keyword: Some("belief"),
+            target: None,
+        },
+    },
+    Feature {
+        name: "belief_inference",
+        description: "`update` and `marginalize` on beliefs",
+        status: Status::Unstable,
+    },
+    Feature {
+        name: "collections",
+        description: "`Vec`, `Map` and `StringBuf`",
+        status: Status::Unstable,
+    },
+    Feature {
+        name: "defer_statements",
+        description: "`defer` statements",
+        status: Status::Unstable,
+    },
+    Feature {
+        name: "extern_c",
+        description: "`extern` blocks of C functions",
+        status: Status::Unstable,
+    },
+    Feature {
+        name: "for_loops",
+        description: "`for` loops and iterator adapters",
+        status: Status::Unstable,
+    },
+    Feature {
+        name: "generics",
+        description: "generic parameters",
+        status: Status::Planned {
+            keyword: None,
+            target: Some("v0.3.0"),
+        },
+    },
+    Feature {
+        name: "inline_asm",
+        description: "inline assembly with `asm!`",
+        status: Status::Unstable,
+    },
+    Feature {
+        name: "locks",
+        description: "`Mutex`, `RwLock` and their guards",
+        status: Status::Unstable,
+    },
+    Feature {
+        name: "never_type",
+        description: "the `!` return type",
+        status: Status::Unstable,
+    },
+    Feature {
+        name: "pattern_matching",
+        description: "`match` expressions",
+        status: Status::Unstable,
+    },
+    Feature {
+        name: "ranges",
+        description: "`..` and `..=` ranges, slicing and range patterns",
+        status: Status::Unstable,
+    },
+    Feature {
+        name: "raw_pointers",
+        description: "raw pointers, their dereference and arithmetic",
+        status: Status::Unstable,
+    },
+    Feature {
+        name: "simd",
+        description: "vector types such as `f64x4`",
+        status: Status::Unstable,
+    },
+    Feature {
+        name: "tail_calls",
+        description: "`become` tail calls",
+        status: Status::Unstable,
+    },
+    Feature {
+        name: "threads",
+        description: "`thread::spawn`, thread handles and channels",
+        status: Status::Unstable,
+    },
+    Feature {
+        name: "traits",
+        description: "traits",
+        status: Status::Planned {
+            keyword: Some("trait"),
+            target: Some("v0.4.0"),
+        },
+    },
+];
+
+/// The feature called `name`
+pub fn lookup(name: &str) -> Option<&'static Feature> {
+    FEATURES.iter().find(|f| f.name == name)
+}
+
+/// The planned feature whose syntax starts with `keyword`
+pub fn planned_syntax(keyword: &str) -> Option<&'static Feature> {
+    FEATURES
+        .iter()
+        .find(|f| matches!(f.status, Status::Planned { keyword: Some(k), .. } if k == keyword))
+}
+
+/// Check the inner attributes of `program` and that it enables every
+/// gated feature it uses
+pub fn check(program: &Program) -> Result<(), CompileError> {
+    let mut gates = Gates {
+        exprs: &program.exprs,
+        enabled: HashSet::new(),
+        reported: HashSet::new(),
+        functions: program
+            .functions()
+            .into_iter()
+            .map(|(name, _, _)| name)
+            .collect(),
+        errors: Vec::new(),
+    };
+    for attr in &program.attrs {
+        if attr.name != "feature" {
+            gates.errors.push(Diagnostic::new(
+                attr.span,
+                format!(
+                    "unknown inner attribute `{}`; only `#![feature(...)]` is supported",
+                    attr.name
+                ),
+            ));
+        }
+    }
+    for (name, span) in program.features() {
+        match lookup(name) {
+            Some(feature) if feature.status == Status::Unstable => {
+                gates.enabled.insert(feature.name);
+            }
+            Some(feature) => gates
+                .errors
+                .push(Diagnostic::new(span, feature.not_implemented())),
+            None => gates
+                .errors
+                .push(Diagnostic::new(span, format!("unknown feature `{}`", name))),
+        }
+    }
+
+    for item in &program.items {
+        match item {
+            Item::Function(function) => {
+                if function.exported {
+                    gates.use_feature("extern_c", function.span);
+                }
+                gates.function(function);
+            }
+            Item::Impl(block) => block.methods.iter().for_each(|m| gates.function(m)),
+            Item::Const(item) => gates.expr(&item.value),
+            Item::Extern(block) => {
+                gates.use_feature("extern_c", block.span);
+                for function in &block.functions {
+                    gates.return_type(&function.return_type);
+                }
+            }
+            Item::Enum(_) | Item::Struct(_) | Item::TypeAlias(_) => {}
+        }
+    }
+
+    if let Some(&span) = any_types(program).first() {
+        gates.use_feature("any_type", span);
+    }
+
+    if gates.errors.is_empty() {
+        Ok(())
+    } else {
+        gates.errors.sort();
+        Err(CompileError::Type(gates.errors))
+    }
+}
+
+/// Reject `#![feature]` attributes, as the stable command line does
+pub fn check_stable(program: &Program) -> Result<(), CompileError> {
+    let errors: Vec<Diagnostic> = program
+        .attrs
+        .iter()
+        .filter(|a| a.name == "feature")
+        .map(|a| {
+            Diagnostic::new(
+                a.span,
+                "`#![feature]` may not be used on the stable channel; \
+                 pass `--unstable` to allow unstable features",
+            )
+        })
+        .collect();
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(CompileError::Parse(errors))
+    }
+}
+
+/// Reject the `any` type, as `--deny-any` asks for
+pub fn deny_any(program: &Program) -> Result<(), CompileError> {
+    let errors: Vec<Diagnostic> = any_types(program)
+        .into_iter()
+        .map(|span| {
+            Diagnostic::new(
+                span,
+                "the `any` type is denied by `--deny-any`; give the value a type of its own",
+            )
+        })
+        .collect();
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(CompileError::Type(errors))
+    }
+}
+
+/// Where `program` names the `any` type, in source order
+fn any_types(program: &Program) -> Vec<Span> {
+    struct AnyTypes<'a> {
+        exprs: &'a Exprs,
+        spans: Vec<Span>,
+    }
+
+    impl<'a> Visit<'a> for AnyTypes<'a> {
+        fn exprs(&self) -> &'a Exprs {
+            self.exprs
+        }
+
+        fn visit_type(&mut self, ty: &TypeExpr) {
+            if let TypeExpr::Named(name, span) = ty {
+                if name == "any" {
+                    self.spans.push(*span);
+                }
+            }
+            visit::walk_type(self, ty);
+        }
+    }
+
+    let mut types = AnyTypes {
+        exprs: &program.exprs,
+        spans: Vec::new(),
+    };
+    types.visit_program(program);
+    types.spans.sort();
+    types.spans
+}
+
+struct Gates<'a> {
+    exprs: &'a Exprs,
+    enabled: HashSet<&'static str>,
+    /// Features already reported, so each is reported at its first use only
+    reported: HashSet<&'static str>,
+    /// User functions, which hide builtins of the same name
+    functions: HashSet<String>,
+    errors: Vec<Diagnostic>,
+}
+
+impl Gates<'_> {
+    fn use_feature(&mut self, name: &'static str, span: Span) {
+        if self.enabled.contains(name) || !self.reported.insert(name) {
+            return;
+        }
+        let feature = lookup(name).expect("gated features are registered");
+        self.errors.push(Diagnostic::new(
+            span,
+            format!(
+                "use of unstable feature `{}` ({}); add `#![feature({})]` at the top of the file",
+                name, feature.description, name
+            ),
+        ));
+    }
+
+    fn function(&mut self, function: &Function) {
+        for attr in &function.attrs {
+            if attr.name == "model" {
+                self.use_feature("belief_conditioning", attr.span);
+            }
+        }
+        for param in &function.params {
+            if matches!(&param.ty, TypeExpr::Named(name, _) if name == "Arena") {
+                self.use_feature("arena_handles", param.span);
+            }
+        }
+        self.return_type(&function.return_type);
+        self.block(&function.body);
+    }
+
+    fn return_type(&mut self, ty: &Option<TypeExpr>) {
+        if let Some(TypeExpr::Never(span)) = ty {
+            self.use_feature("never_type", *span);
+        }
+    }
+
+    fn block(&mut self, block: &Block) {
+        for stmt in &block.stmts {
+            match stmt {
+                Stmt::Let(let_stmt) => {
+                    if let Some(value) = &let_stmt.value {
+                        self.expr(value);
+                    }
+                }
+                Stmt::Const(item) => self.expr(&item.value),
+                Stmt::Expr(expr) => self.expr(expr),
+                Stmt::Defer(expr) => {
+                    self.use_feature("defer_statements", expr.span);
+                    self.expr(expr);
+                }
+            }
+        }
+        if let Some(tail) = &block.tail {
+            self.expr(&self.exprs[*tail]);
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Int(..)
+            | ExprKind::Float(..)
+            | ExprKind::Str(_)
+            | ExprKind::Bool(_)
+            | ExprKind::Path(_) => {}
+            ExprKind::Unary(UnaryOp::Deref, operand) => {
+                self.use_feature("raw_pointers", expr.span);
+                self.expr(&self.exprs[*operand]);
+            }
+            ExprKind::Unary(_, operand)
+            | ExprKind::Field(operand, _)
+            | ExprKind::Cast(operand, _) => self.expr(&self.exprs[*operand]),
+            ExprKind::Binary(_, lhs, rhs)
+            | ExprKind::Assign(lhs, rhs)
+            | ExprKind::Index(lhs, rhs)
+            | ExprKind::ArrayRepeat(lhs, rhs) => {
+                self.expr(&self.exprs[*lhs]);
+                self.expr(&self.exprs[*rhs]);
+            }
+            ExprKind::Call(callee, args) => {
+                if let ExprKind::Path(segments) = &self.exprs[*callee].kind {
+                    let name = join_path(segments);
+                    if !self.functions.contains(&name) {
+                        match name.as_str() {
+                            "update" | "marginalize" => {
+                                self.use_feature("belief_inference", expr.span)
+                            }
+                            "observe" | "expect" => {
+                                self.use_feature("belief_conditioning", expr.span)
+                            }
+                            "Vec::new"
+                            | "Vec::with_capacity"
+                            | "Map::new"
+                            | "StringBuf::new"
+                            | "StringBuf::from"
+                            | "StringBuf::from_utf8" => self.use_feature("collections", expr.span),
+                            "thread::spawn" | "channel::unbounded" | "channel::bounded" => {
+                                self.use_feature("threads", expr.span)
+                            }
+                            "atomic::fence" => self.use_feature("atomics", expr.span),
+                            "Mutex::new" | "RwLock::new" => self.use_feature("locks", expr.span),
+                            "ptr::from_ref" | "ptr::from_mut" => {
+                                self.use_feature("raw_pointers", expr.span)
+                            }
+                            _ if is_vector_constructor(&name) => {
+                                self.use_feature("simd", expr.span)
+                            }
+                            _ if is_atomic_constructor(&name) => {
+                                self.use_feature("atomics", expr.span)
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                self.expr(&self.exprs[*callee]);
+                args.iter().for_each(|arg| self.expr(arg));
+            }
+            ExprKind::MethodCall(receiver, method, args) => {
+                // Unless the program defines methods of that name
+                let defined = self
+                    .functions
+                    .iter()
+                    .any(|name| name.rsplit("::").next() == Some(method.as_str()));
+                if matches!(method.as_str(), "as_ptr" | "as_mut_ptr") && !defined {
+                    self.use_feature("raw_pointers", expr.span);
+                }
+                self.expr(&self.exprs[*receiver]);
+                args.iter().for_each(|arg| self.expr(arg));
+            }
+            ExprKind::StructLit(_, inits) => inits.iter().for_each(|init| self.expr(&init.value)),
+            ExprKind::Distribution(_, args) | ExprKind::Array(args) => {
+                args.iter().for_each(|arg| self.expr(arg));
+            }
+            ExprKind::If(cond, then_block, else_expr) => {
+                self.expr(&self.exprs[*cond]);
+                self.block(then_block);
+                if let Some(else_expr) = else_expr {
+                    self.expr(&self.exprs[*else_expr]);
+                }
+            }
+            ExprKind::While(cond, body) => {
+                self.expr(&self.exprs[*cond]);
+                self.block(body);
+            }
+            ExprKind::For(_, iterable, body) => {
+                self.use_feature("for_loops", expr.span);
+                self.expr(&self.exprs[*iterable]);
+                self.block(body);
+            }
+            ExprKind::Match(scrutinee, arms) => {
+                self.use_feature("pattern_matching", expr.span);
+                self.expr(&self.exprs[*scrutinee]);
+                for arm in arms {
+                    if has_range(&arm.pattern) {
+                        self.use_feature("ranges", arm.pattern.span);
+                    }
+                    if let Some(guard) = arm.guard {
+                        self.expr(&self.exprs[guard]);
+                    }
+                    self.expr(&self.exprs[arm.value]);
+                }
+            }
+            ExprKind::Range(start, end, _) => {
+                self.use_feature("ranges", expr.span);
+                for end in [start, end].into_iter().flatten() {
+                    self.expr(&self.exprs[*end]);
+                }
+            }
+            ExprKind::Block(block) | ExprKind::Unsafe(block) => self.block(block),
+            ExprKind::Arena(name, allocator, block) => {
+                if name.is_some() {
+                    self.use_feature("arena_handles", expr.span);
+                }
+                if let Some(allocator) = allocator {
+                    self.use_feature("arena_allocators", expr.span);
+                    self.expr(&self.exprs[*allocator]);
+                }
+                self.block(block);
+            }
+            ExprKind::Return(value) => {
+                if let Some(value) = value {
+                    self.expr(&self.exprs[*value]);
+                }
+            }
+            ExprKind::Become(call) => {
+                self.use_feature("tail_calls", expr.span);
+                self.expr(&self.exprs[*call]);
+            }
+            ExprKind::Asm(_, operands) => {
+                self.use_feature("inline_asm", expr.span);
+                operands.iter().for_each(|operand| self.expr(&operand.expr));
+            }
+        }
+    }
+}
+
+/// Whether `pattern` has a range pattern in it
+fn has_range(pattern: &Pattern) -> bool {
+    match &pattern.kind {
+        PatternKind::Range(..) => true,
+        PatternKind::Some(inner) => has_range(inner),
+        PatternKind::Or(alternatives) => alternatives.iter().any(has_range),
+        _ => false,
+    }
+}
+
+/// `f64x4::splat` and the like
+fn is_vector_constructor(name: &str) -> bool {
+    name.split_once("::")
+        .is_some_and(|(ty, _)| simd::vector_type(ty).is_some())
+}
+
+/// `AtomicI64::new` and the like
+fn is_atomic_constructor(name: &str) -> bool {
+    name.split_once("::")
+        .is_some_and(|(ty, _)| atomic::atomic_type(ty).is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gate_errors(source: &str) -> Vec<String> {
+        match crate::compile_until(source, crate::Phase::TypeCheck) {
+            Ok(_) => Vec::new(),
+            Err(err) => err.diagnostics().iter().map(|d| d.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_feature_gates() {
+        let source = "
+            fn main() {
+                defer print(1);
+                let b = update(~Bernoulli(0.5), ~Bernoulli(0.9));
+                arena scratch { print(2); }
+                for x in [3] { print(x); }
+                arena a { let v: Vec<i64> = Vec::new(a); }
+                defer print(4);
+                print(match 5 { n if n > 0 => n, _ => 0 });
+                print([6, 7][0..1]);
+            }
+            fn fail() -> ! { panic(\"stop\") }
+            fn again() { become again(); }
+            struct Pool { next: i64 }
+            impl Pool {
+                fn allocate(&mut self, size: i64, align: i64) -> Option<i64> { None }
+                fn reset(&mut self) {}
+            }
+            fn pooled(pool: &mut Pool) { arena in pool { print(8); } }";
+        assert_eq!(
+            gate_errors(source),
+            [
+                "3:28: use of unstable feature `defer_statements` (`defer` statements); \
+                 add `#![feature(defer_statements)]` at the top of the file",
+                "4:31: use of unstable feature `belief_inference` (`update` and \
+                 `marginalize` on beliefs); add `#![feature(belief_inference)]` at the \
+                 top of the file",
+                "5:17: use of unstable feature `arena_handles` (named arenas and `Arena` \
+                 parameters); add `#![feature(arena_handles)]` at the top of the file",
+                "6:17: use of unstable feature `for_loops` (`for` loops and iterator \
+                 adapters); add `#![feature(for_loops)]` at the top of the file",
+                "7:53: use of unstable feature `collections` (`Vec`, `Map` and \
+                 `StringBuf`); add `#![feature(collections)]` at the top of the file",
+                "9:23: use of unstable feature `pattern_matching` (`match` expressions); \
+                 add `#![feature(pattern_matching)]` at the top of the file",
+                "10:31: use of unstable feature `ranges` (`..` and `..=` ranges, slicing \
+                 and range patterns); add `#![feature(ranges)]` at the top of the file",
+                "12:26: use of unstable feature `never_type` (the `!` return type); \
+                 add `#![feature(never_type)]` at the top of the file",
+                "13:26: use of unstable feature `tail_calls` (`become` tail calls); \
+                 add `#![feature(tail_calls)]` at the top of the file",
+                "19:42: use of unstable feature `arena_allocators` (arenas backed by an \
+                 allocator, `arena in buffer { ... }`); add `#![feature(arena_allocators)]` \
+                 at the top of the file",
+            ]
+        );
+
+        let enabled = format!(
+            "#![feature(defer_statements, belief_inference)]\n\
+             #![feature(arena_handles, for_loops, collections, pattern_matching, ranges)]\n\
+             #![feature(never_type, tail_calls, arena_allocators)]\n{}",
+            source
+        );
+        assert!(gate_errors(&enabled).is_empty());
+
+        let unknown = "#![feature(async)] #![no_std] fn main() {}";
+        assert_eq!(
+            gate_errors(unknown),
+            [
+                "1:1: unknown feature `async`",
+                "1:20: unknown inner attribute `no_std`; only `#![feature(...)]` is supported",
+            ]
+        );
+        let program = crate::compile_until(unknown, crate::Phase::Parse)
+            .unwrap()
+            .into_ast()
+            .unwrap();
+        assert!(check_stable(&program).is_err());
+    }
+
+    #[test]
+    fn test_planned_features() {
+        assert_eq!(
+            gate_errors("async fn main() {}"),
+            ["1:1: `async` functions are not implemented yet (feature `async_functions`)"]
+        );
+        assert_eq!(
+            gate_errors("fn first<T>(xs: [T; 1]) -> T { xs[0] }"),
+            [
+                "1:9: generic parameters are not implemented yet; planned for v0.3.0 \
+              (feature `generics`)"
+            ]
+        );
+        assert_eq!(
+            gate_errors("#![feature(traits)] fn main() {}"),
+            ["1:1: traits are not implemented yet; planned for v0.4.0 (feature `traits`)"]
+        );
+        // Only syntax errors are explained; `async` is still a valid name
+        assert!(gate_errors("fn main() { let async = 1; print(async); }").is_empty());
+        assert_eq!(
+            gate_errors("fn main() { let x = 1 +; }"),
+            ["1:24: expected expression, found `;`"]
+        );
+    }
+}
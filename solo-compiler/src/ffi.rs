@@ -0,0 +1,524 @@
+//! Calling C functions from the interpreter
+//!
+//! Functions declared in `extern "C" { ... }` blocks are looked up at their
+//! first call in the libraries named by `#[link]` attributes, loaded with
+//! `dlopen`, and only there: a program cannot reach a function of a library
+//! it did not name, even one the `solo` executable has loaded.
+//!
+//! There is no libffi here: a call is made through a function pointer of
+//! one of a fixed set of Rust types. C arguments are passed in integer or
+//! floating-point registers, so a signature is supported when every
+//! argument and the result is an integer, `bool`, string pointer or
+//! `double`, with up to three arguments in any order or four of one kind.
+
+use std::collections::HashMap;
+use std::fmt;
+
+/// A C type an extern function may take or return
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CType {
+    I8,
+    I16,
+    I32,
+    I64,
+    U8,
+    U16,
+    U32,
+    U64,
+    F32,
+    F64,
+    Bool,
+    /// `const char *`, passed a NUL-terminated copy of a `str`
+    Str,
+    Void,
+}
+
+impl CType {
+    /// The C type behind a Solo type name (`str` is the argument type)
+    pub fn from_name(name: &str) -> Option<CType> {
+        Some(match name {
+            "i8" => CType::I8,
+            "i16" => CType::I16,
+            "i32" => CType::I32,
+            "i64" | "isize" => CType::I64,
+            "u8" => CType::U8,
+            "u16" => CType::U16,
+            "u32" => CType::U32,
+            "u64" | "usize" => CType::U64,
+            "f32" => CType::F32,
+            "f64" => CType::F64,
+            "bool" => CType::Bool,
+            "str" => CType::Str,
+            _ => return None,
+        })
+    }
+
+    fn class(self) -> Option<Class> {
+        match self {
+            CType::F64 => Some(Class::Float),
+            CType::F32 => None,
+            CType::Void => Some(Class::Void),
+            _ => Some(Class::Int),
+        }
+    }
+}
+
+impl fmt::Display for CType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            CType::I8 => "int8_t",
+            CType::I16 => "int16_t",
+            CType::I32 => "int32_t",
+            CType::I64 => "int64_t",
+            CType::U8 => "uint8_t",
+            CType::U16 => "uint16_t",
+            CType::U32 => "uint32_t",
+            CType::U64 => "uint64_t",
+            CType::F32 => "float",
+            CType::F64 => "double",
+            CType::Bool => "bool",
+            CType::Str => "const char *",
+            CType::Void => "void",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Parameter and result types of an extern function
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    pub params: Vec<CType>,
+    pub ret: CType,
+}
+
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (", self.ret)?;
+        for (i, param) in self.params.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", param)?;
+        }
+        if self.params.is_empty() {
+            write!(f, "void")?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// A value crossing the boundary
+#[derive(Debug, Clone, PartialEq)]
+pub enum CValue {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Str(String),
+    Void,
+}
+
+/// Register class of an argument or result
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Class {
+    Int,
+    Float,
+    Void,
+}
+
+/// An argument or result as held in a register
+#[derive(Debug, Clone, Copy)]
+enum Word {
+    Int(i64),
+    Float(f64),
+}
+
+/// Libraries named by `#[link]` and the functions found so far
+pub struct Libraries {
+    names: Vec<String>,
+    /// `None` until loading is attempted; then the handle or the error
+    handles: Option<Vec<Result<sys::Handle, String>>>,
+    symbols: HashMap<String, sys::Symbol>,
+}
+
+impl Libraries {
+    pub fn new(names: Vec<String>) -> Self {
+        Self {
+            names,
+            handles: None,
+            symbols: HashMap::new(),
+        }
+    }
+
+    /// Call the C function `name` with arguments matching `signature`
+    pub fn call(
+        &mut self,
+        name: &str,
+        signature: &Signature,
+        args: &[CValue],
+    ) -> Result<CValue, String> {
+        let classes: Option<Vec<Class>> = signature.params.iter().map(|t| t.class()).collect();
+        let (Some(classes), Some(ret)) = (classes, signature.ret.class()) else {
+            return Err(unsupported(name, signature));
+        };
+        if !supported(&classes) {
+            return Err(unsupported(name, signature));
+        }
+        let symbol = self.symbol(name)?;
+
+        // Keeps the strings alive until the call returns
+        let mut strings = Vec::new();
+        let mut words = Vec::with_capacity(args.len());
+        for (value, ty) in args.iter().zip(&signature.params) {
+            words.push(match (value, ty) {
+                (CValue::Float(x), _) => Word::Float(*x),
+                (CValue::Bool(b), _) => Word::Int(*b as i64),
+                (CValue::Int(n), ty) => Word::Int(extend(*n, *ty)),
+                (CValue::Str(s), _) => {
+                    let string = std::ffi::CString::new(s.as_str())
+                        .map_err(|_| format!("string passed to `{}` contains a NUL byte", name))?;
+                    let pointer = string.as_ptr() as i64;
+                    strings.push(string);
+                    Word::Int(pointer)
+                }
+                (CValue::Void, _) => return Err(format!("invalid argument to `{}`", name)),
+            });
+        }
+        // SAFETY: the declaration is trusted to match the C definition, as
+        // it would be by a linker; only register-passed types get here
+        let result =
+            unsafe { invoke(symbol, &words, ret) }.ok_or_else(|| unsupported(name, signature))?;
+        drop(strings);
+
+        Ok(match (result, signature.ret) {
+            (_, CType::Void) => CValue::Void,
+            (Word::Float(x), _) => CValue::Float(x),
+            (Word::Int(n), CType::Bool) => CValue::Bool(n as u8 != 0),
+            (Word::Int(n), ty) => CValue::Int(extend(n, ty)),
+        })
+    }
+
+    fn symbol(&mut self, name: &str) -> Result<sys::Symbol, String> {
+        if let Some(&symbol) = self.symbols.get(name) {
+            return Ok(symbol);
+        }
+        let names = &self.names;
+        let handles = self
+            .handles
+            .get_or_insert_with(|| names.iter().map(|lib| load(lib)).collect());
+        let symbol = handles
+            .iter()
+            .filter_map(|handle| handle.as_ref().ok())
+            .find_map(|&handle| sys::lookup(handle, name));
+        let Some(symbol) = symbol else {
+            let mut msg = format!("cannot find C function `{}`", name);
+            if self.names.is_empty() {
+                msg.push_str("; name its library with `#[link(...)]`");
+            }
+            for err in self
+                .handles
+                .iter()
+                .flatten()
+                .filter_map(|h| h.as_ref().err())
+            {
+                msg.push_str("; ");
+                msg.push_str(err);
+            }
+            return Err(msg);
+        };
+        self.symbols.insert(name.to_string(), symbol);
+        Ok(symbol)
+    }
+}
+
+/// Load the library `lib` named by `#[link(lib)]`
+///
+/// On Linux the C and math libraries are only installed under an unversioned
+/// name with their development files (and `libc.so` is a linker script), so
+/// their versioned names are tried too.
+fn load(lib: &str) -> Result<sys::Handle, String> {
+    let file = format!(
+        "{}{}{}",
+        std::env::consts::DLL_PREFIX,
+        lib,
+        std::env::consts::DLL_SUFFIX
+    );
+    let versioned = match lib {
+        "c" | "m" if cfg!(target_os = "linux") => Some(format!("{}.6", file)),
+        _ => None,
+    };
+    sys::open(&file)
+        .or_else(|err| versioned.as_deref().map_or(Err(err), sys::open))
+        .map_err(|err| format!("cannot load `{}`: {}", file, err))
+}
+
+/// Sign- or zero-extend the low bits of `n` that a `ty` holds
+fn extend(n: i64, ty: CType) -> i64 {
+    match ty {
+        CType::I8 => n as i8 as i64,
+        CType::I16 => n as i16 as i64,
+        CType::I32 => n as i32 as i64,
+        CType::U8 => n as u8 as i64,
+        CType::U16 => n as u16 as i64,
+        CType::U32 => n as u32 as i64,
+        _ => n,
+    }
+}
+
+/// Up to three arguments of any class, or four of one class
+fn supported(classes: &[Class]) -> bool {
+    classes.len() <= 3 || (classes.len() == 4 && classes.iter().all(|c| *c == classes[0]))
+}
+
+fn unsupported(name: &str, signature: &Signature) -> String {
+    format!(
+        "the interpreter cannot call `{}`: `{}` is not a supported C signature",
+        name, signature
+    )
+}
+
+macro_rules! dispatch {
+    ($symbol:expr, $words:expr, $ret:expr; $(($($arg:ident: $class:ident $ty:ty),*)),* $(,)?) => {
+        match $words {
+            $(
+                [$(Word::$class($arg)),*] => Some(match $ret {
+                    Class::Int => Word::Int(std::mem::transmute::<
+                        sys::Symbol,
+                        extern "C" fn($($ty),*) -> i64,
+                    >($symbol)($(*$arg),*)),
+                    Class::Float => Word::Float(std::mem::transmute::<
+                        sys::Symbol,
+                        extern "C" fn($($ty),*) -> f64,
+                    >($symbol)($(*$arg),*)),
+                    Class::Void => {
+                        std::mem::transmute::<sys::Symbol, extern "C" fn($($ty),*)>($symbol)(
+                            $(*$arg),*
+                        );
+                        Word::Int(0)
+                    }
+                }),
+            )*
+            _ => None,
+        }
+    };
+}
+
+/// Call `symbol` with `words` in registers, or `None` if no function pointer
+/// type matches the arguments
+///
+/// # Safety
+///
+/// `symbol` must be a C function taking arguments of these classes and
+/// returning a value of class `ret`.
+unsafe fn invoke(symbol: sys::Symbol, words: &[Word], ret: Class) -> Option<Word> {
+    dispatch!(symbol, words, ret;
+        (),
+        (a: Int i64),
+        (a: Float f64),
+        (a: Int i64, b: Int i64),
+        (a: Int i64, b: Float f64),
+        (a: Float f64, b: Int i64),
+        (a: Float f64, b: Float f64),
+        (a: Int i64, b: Int i64, c: Int i64),
+        (a: Int i64, b: Int i64, c: Float f64),
+        (a: Int i64, b: Float f64, c: Int i64),
+        (a: Int i64, b: Float f64, c: Float f64),
+        (a: Float f64, b: Int i64, c: Int i64),
+        (a: Float f64, b: Int i64, c: Float f64),
+        (a: Float f64, b: Float f64, c: Int i64),
+        (a: Float f64, b: Float f64, c: Float f64),
+        (a: Int i64, b: Int i64, c: Int i64, d: Int i64),
+        (a: Float f64, b: Float f64, c: Float f64, d: Float f64),
+    )
+}
+
+#[cfg(unix)]
+mod sys {
+    use std::ffi::{c_char, c_int, c_void, CStr, CString};
+
+    pub type Handle = *mut c_void;
+    pub type Symbol = *mut c_void;
+
+    const RTLD_NOW: c_int = 2;
+
+    extern "C" {
+        fn dlopen(filename: *const c_char, flags: c_int) -> *mut c_void;
+        fn dlsym(handle: *mut c_void, symbol: *const c_char) -> *mut c_void;
+        fn dlerror() -> *mut c_char;
+    }
+
+    pub fn open(file: &str) -> Result<Handle, String> {
+        let file = CString::new(file).map_err(|err| err.to_string())?;
+        // SAFETY: `file` is NUL-terminated; the handle is never closed
+        let handle = unsafe { dlopen(file.as_ptr(), RTLD_NOW) };
+        if handle.is_null() {
+            Err(last_error())
+        } else {
+            Ok(handle)
+        }
+    }
+
+    /// Find `name` in `library` itself
+    ///
+    /// `dlsym` also searches the libraries `library` depends on, which for
+    /// almost any library includes all of the C library.
+    pub fn lookup(library: Handle, name: &str) -> Option<Symbol> {
+        let name = CString::new(name).ok()?;
+        // SAFETY: `library` came from `dlopen` and `name` is NUL-terminated
+        let symbol = unsafe { dlsym(library, name.as_ptr()) };
+        (!symbol.is_null() && defined_in(library, symbol)).then_some(symbol)
+    }
+
+    /// Whether `symbol` lies in the object `library` loaded rather than in
+    /// one of its dependencies
+    #[cfg(target_os = "linux")]
+    fn defined_in(library: Handle, symbol: Symbol) -> bool {
+        use std::ptr;
+
+        const RTLD_DI_LINKMAP: c_int = 2;
+
+        /// `Dl_info`
+        #[repr(C)]
+        struct Info {
+            file: *const c_char,
+            base: *mut c_void,
+            name: *const c_char,
+            address: *mut c_void,
+        }
+
+        /// The leading fields of `struct link_map`
+        #[repr(C)]
+        struct LinkMap {
+            address: usize,
+            name: *const c_char,
+        }
+
+        extern "C" {
+            fn dladdr(address: *const c_void, info: *mut Info) -> c_int;
+            fn dlinfo(handle: *mut c_void, request: c_int, info: *mut c_void) -> c_int;
+        }
+
+        let mut map: *const LinkMap = ptr::null();
+        let mut info = Info {
+            file: ptr::null(),
+            base: ptr::null_mut(),
+            name: ptr::null(),
+            address: ptr::null_mut(),
+        };
+        // SAFETY: `library` came from `dlopen`, `RTLD_DI_LINKMAP` writes one
+        // pointer to `map`, and `dladdr` fills in `info`; both name strings
+        // belong to the loader and live as long as the library
+        unsafe {
+            if dlinfo(
+                library,
+                RTLD_DI_LINKMAP,
+                (&mut map as *mut *const LinkMap).cast(),
+            ) != 0
+                || map.is_null()
+                || (*map).name.is_null()
+                || dladdr(symbol, &mut info) == 0
+                || info.file.is_null()
+            {
+                return false;
+            }
+            CStr::from_ptr((*map).name) == CStr::from_ptr(info.file)
+        }
+    }
+
+    /// Elsewhere there is no portable way to tell, and dependencies are
+    /// searched too
+    #[cfg(not(target_os = "linux"))]
+    fn defined_in(_library: Handle, _symbol: Symbol) -> bool {
+        true
+    }
+
+    fn last_error() -> String {
+        // SAFETY: `dlerror` returns null or a NUL-terminated message
+        let message = unsafe { dlerror() };
+        if message.is_null() {
+            "unknown error".to_string()
+        } else {
+            unsafe { CStr::from_ptr(message) }
+                .to_string_lossy()
+                .into_owned()
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod sys {
+    pub type Handle = *mut std::ffi::c_void;
+    pub type Symbol = *mut std::ffi::c_void;
+
+    pub fn open(_file: &str) -> Result<Handle, String> {
+        Err("dynamic libraries are not supported on this platform".to_string())
+    }
+
+    pub fn lookup(_library: Handle, _name: &str) -> Option<Symbol> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signature(params: &[CType], ret: CType) -> Signature {
+        Signature {
+            params: params.to_vec(),
+            ret,
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_call_c_functions() {
+        let mut libraries = Libraries::new(vec!["m".to_string(), "c".to_string()]);
+        let pow = signature(&[CType::F64, CType::F64], CType::F64);
+        assert_eq!(
+            libraries.call("pow", &pow, &[CValue::Float(2.0), CValue::Float(10.0)]),
+            Ok(CValue::Float(1024.0))
+        );
+        let abs = signature(&[CType::I32], CType::I32);
+        assert_eq!(
+            libraries.call("abs", &abs, &[CValue::Int(-7)]),
+            Ok(CValue::Int(7))
+        );
+        let strlen = signature(&[CType::Str], CType::U64);
+        assert_eq!(
+            libraries.call("strlen", &strlen, &[CValue::Str("solo".to_string())]),
+            Ok(CValue::Int(4))
+        );
+
+        let err = libraries
+            .call("no_such_function", &abs, &[CValue::Int(1)])
+            .unwrap_err();
+        assert!(
+            err.starts_with("cannot find C function `no_such_function`"),
+            "{}",
+            err
+        );
+        // Only libraries the program names are searched
+        let mut unlinked = Libraries::new(Vec::new());
+        let system = signature(&[CType::Str], CType::I32);
+        let err = unlinked
+            .call("system", &system, &[CValue::Str("true".to_string())])
+            .unwrap_err();
+        assert_eq!(
+            err,
+            "cannot find C function `system`; name its library with `#[link(...)]`"
+        );
+        if cfg!(target_os = "linux") {
+            // Not even through the C library the math library depends on
+            let mut math = Libraries::new(vec!["m".to_string()]);
+            assert!(math
+                .call("system", &system, &[CValue::Str("true".to_string())])
+                .is_err());
+        }
+
+        let sqrtf = signature(&[CType::F32], CType::F32);
+        assert_eq!(
+            libraries.call("sqrtf", &sqrtf, &[CValue::Float(4.0)]),
+            Err("the interpreter cannot call `sqrtf`: `float (float)` is not a supported C signature".to_string())
+        );
+    }
+}
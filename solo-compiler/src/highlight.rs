@@ -0,0 +1,253 @@
+//! Syntax highlighting outside editors
+//!
+//! `solo highlight <file>` prints a file colored for a terminal, or with
+//! `--html` as a `<pre>` block for web pages. Tokens are classified the way
+//! the [language server](crate::lsp) classifies them, so the HTML spans
+//! carry its token types and modifiers as classes:
+//!
+//! ```html
+//! <span class="function declaration">main</span>
+//! ```
+//!
+//! Editors that highlight with tree-sitter instead of LSP (GitHub, Neovim,
+//! Helix) use the grammar in `editors/tree-sitter-solo`, which the compiler
+//! bundles: `solo highlight --tree-sitter <dir>` writes it out. The tests
+//! here check the grammar's keywords, punctuation and literal strings
+//! against the lexer.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::lsp::{self, SemanticToken, TokenType};
+
+/// The tree-sitter grammar, as paths relative to its directory and their
+/// contents
+pub const TREE_SITTER: &[(&str, &str)] = &[
+    (
+        "grammar.js",
+        include_str!("../editors/tree-sitter-solo/grammar.js"),
+    ),
+    (
+        "queries/highlights.scm",
+        include_str!("../editors/tree-sitter-solo/queries/highlights.scm"),
+    ),
+    (
+        "package.json",
+        include_str!("../editors/tree-sitter-solo/package.json"),
+    ),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// ANSI escape sequences for terminals
+    Ansi,
+    /// A `<pre class="solo">` block with a `<span>` per token
+    Html,
+}
+
+/// `source` with every token highlighted
+pub fn highlight(source: &str, format: Format) -> String {
+    let mut out = String::new();
+    if format == Format::Html {
+        out.push_str("<pre class=\"solo\"><code>");
+    }
+    let starts: Vec<usize> = std::iter::once(0)
+        .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+        .collect();
+    let mut at = 0;
+    for token in lsp::semantic_tokens(source) {
+        let line = starts[token.line as usize];
+        let start = line + byte_offset(&source[line..], token.start);
+        let end = line + byte_offset(&source[line..], token.start + token.length);
+        push_text(&mut out, &source[at..start], format);
+        let text = &source[start..end];
+        match format {
+            Format::Ansi => match ansi_style(&token) {
+                Some(style) => out.push_str(&format!("\x1b[{}m{}\x1b[0m", style, text)),
+                None => out.push_str(text),
+            },
+            Format::Html => {
+                let classes: Vec<&str> = std::iter::once(lsp::TOKEN_TYPES[token.ty as usize])
+                    .chain(
+                        lsp::TOKEN_MODIFIERS
+                            .iter()
+                            .enumerate()
+                            .filter(|(bit, _)| token.modifiers & (1 << bit) != 0)
+                            .map(|(_, name)| *name),
+                    )
+                    .collect();
+                out.push_str(&format!("<span class=\"{}\">", classes.join(" ")));
+                push_text(&mut out, text, format);
+                out.push_str("</span>");
+            }
+        }
+        at = end;
+    }
+    push_text(&mut out, &source[at..], format);
+    if format == Format::Html {
+        out.push_str("</code></pre>\n");
+    }
+    out
+}
+
+/// Byte offset of the UTF-16 column `units` of the line starting `text`
+fn byte_offset(text: &str, units: u32) -> usize {
+    let mut count = 0;
+    for (i, c) in text.char_indices() {
+        if count >= units as usize || c == '\n' {
+            return i;
+        }
+        count += c.len_utf16();
+    }
+    text.len()
+}
+
+fn push_text(out: &mut String, text: &str, format: Format) {
+    match format {
+        Format::Ansi => out.push_str(text),
+        Format::Html => {
+            for c in text.chars() {
+                match c {
+                    '&' => out.push_str("&amp;"),
+                    '<' => out.push_str("&lt;"),
+                    '>' => out.push_str("&gt;"),
+                    '"' => out.push_str("&quot;"),
+                    c => out.push(c),
+                }
+            }
+        }
+    }
+}
+
+/// SGR parameters for a token; beliefs are bold and arenas italic
+fn ansi_style(token: &SemanticToken) -> Option<String> {
+    let color = match token.ty {
+        TokenType::Keyword | TokenType::Macro => Some("35"),
+        TokenType::Type | TokenType::Struct | TokenType::Enum | TokenType::EnumMember => Some("36"),
+        TokenType::Function | TokenType::Method => Some("34"),
+        TokenType::Number => Some("33"),
+        TokenType::String => Some("32"),
+        TokenType::Comment => Some("90"),
+        TokenType::Parameter | TokenType::Variable | TokenType::Property | TokenType::Operator => {
+            None
+        }
+    };
+    let mut style: Vec<&str> = color.into_iter().collect();
+    if token.modifiers & lsp::BELIEF != 0 {
+        style.push("1");
+    }
+    if token.modifiers & lsp::ARENA != 0 {
+        style.push("3");
+    }
+    (!style.is_empty()).then(|| style.join(";"))
+}
+
+/// Write the tree-sitter grammar into `dir`
+pub fn export_tree_sitter(dir: &Path) -> io::Result<()> {
+    for (file, contents) in TREE_SITTER {
+        let path = dir.join(file);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, contents)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::token::{TokenKind, KEYWORDS};
+
+    #[test]
+    fn test_highlight() {
+        let source = "// a < b\nfn main() {\n    let b = ~Bernoulli(0.5);\n}\n";
+        assert_eq!(
+            highlight(source, Format::Html),
+            "<pre class=\"solo\"><code><span class=\"comment\">// a &lt; b</span>\n\
+             <span class=\"keyword\">fn</span> <span class=\"function declaration\">main</span>() {\n\
+             \x20   <span class=\"keyword\">let</span> <span class=\"variable declaration belief\">b</span> \
+             <span class=\"operator\">=</span> <span class=\"operator belief\">~</span>\
+             <span class=\"function defaultLibrary belief\">Bernoulli</span>(<span class=\"number\">0.5</span>);\n\
+             }\n</code></pre>\n"
+        );
+        let ansi = highlight(source, Format::Ansi);
+        assert!(
+            ansi.starts_with("\x1b[90m// a < b\x1b[0m\n\x1b[35mfn\x1b[0m \x1b[34mmain\x1b[0m()")
+        );
+        assert!(ansi.contains("\x1b[34;1mBernoulli\x1b[0m"));
+    }
+
+    /// Single-quoted strings of `text` outside comments, other than field
+    /// names
+    fn quoted(text: &str) -> Vec<&str> {
+        let mut strings = Vec::new();
+        for line in text.lines() {
+            if line.trim_start().starts_with(['/', '*']) {
+                continue;
+            }
+            let parts: Vec<&str> = line.split('\'').collect();
+            for i in (1..parts.len()).step_by(2) {
+                if !parts[i - 1].ends_with("field(") {
+                    strings.push(parts[i]);
+                }
+            }
+        }
+        strings
+    }
+
+    /// Contents of `const NAME = [...]` in the grammar
+    fn list<'g>(grammar: &'g str, name: &str) -> Vec<&'g str> {
+        let start = grammar.find(&format!("const {} = [", name)).unwrap();
+        let end = start + grammar[start..].find("];").unwrap();
+        quoted(&grammar[start..end])
+    }
+
+    #[test]
+    fn test_tree_sitter_grammar_matches_lexer() {
+        let grammar = TREE_SITTER[0].1;
+        assert_eq!(list(grammar, "KEYWORDS"), KEYWORDS);
+
+        // Every operator and delimiter, and nothing that lexes otherwise
+        let punctuation = list(grammar, "PUNCTUATION");
+        let single = |text: &str| {
+            let mut lexer = Lexer::new(text);
+            let token = lexer.next_token();
+            let whole = lexer.next_token().kind == TokenKind::Eof;
+            let comment_or_error = matches!(token.kind, TokenKind::Eof | TokenKind::Error(_));
+            (whole && !comment_or_error).then_some(token.kind)
+        };
+        let symbols = "!#$%&*+,-./:;<=>?@^|~\\`'";
+        for a in symbols.chars() {
+            for text in
+                std::iter::once(a.to_string()).chain(symbols.chars().map(|b| format!("{}{}", a, b)))
+            {
+                if single(&text).is_some() {
+                    assert!(
+                        punctuation.contains(&text.as_str()),
+                        "`{}` is missing",
+                        text
+                    );
+                }
+            }
+        }
+        for text in &punctuation {
+            assert!(single(text).is_some(), "`{}` is not one token", text);
+        }
+
+        // Rules only use those, besides the grammar's name, `self`, the
+        // `crate` of `pub(crate)`, brackets and comment delimiters
+        for text in quoted(grammar) {
+            assert!(
+                KEYWORDS.contains(&text)
+                    || punctuation.contains(&text)
+                    || ["solo", "self", "crate", "(", ")", "[", "]", "{", "}", "//", "/*"]
+                        .contains(&text),
+                "`{}` is not a token of the lexer",
+                text
+            );
+        }
+    }
+}
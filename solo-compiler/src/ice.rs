@@ -0,0 +1,280 @@
+//! Internal compiler errors
+//!
+//! A panic in the compiler is a bug in the compiler, not in the program it
+//! was given. The `solo` binary [`install`]s a panic hook that reports one
+//! as an internal compiler error (ICE), in the manner of rustc:
+//!
+//! ```text
+//! error: internal compiler error: attempt to subtract with overflow
+//!   --> src/typeck.rs:812:17
+//!
+//! note: the compiler unexpectedly panicked. This is a bug.
+//! note: please report it at https://gitlab.com/Hyperpolymath/My-newsroom/-/issues
+//! note: solo 0.1.0 running `solo build main.solo --release`
+//! phase stack during panic:
+//! #0 [typeck]
+//! end of phase stack
+//! ```
+//!
+//! The phases are the passes [`Timings`](crate::timings::Timings) runs,
+//! `interpret` for running a program, and whatever else was run under
+//! [`phase`]. With `SOLO_ICE_BUNDLE=1` the binary then writes a
+//! reproduction bundle ([`write_bundle`]) to the temporary directory: the
+//! report, the arguments, and the program it was compiling, as read and
+//! reduced to what still crashes the compiler. The exit status of an ICE is
+//! 101, as for a panic.
+
+use std::any::Any;
+use std::backtrace::{Backtrace, BacktraceStatus};
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe, PanicHookInfo};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::minimize::{self, Predicate};
+
+/// Where to report compiler bugs
+pub const ISSUES: &str = "https://gitlab.com/Hyperpolymath/My-newsroom/-/issues";
+
+/// The environment variable that asks for a reproduction bundle
+pub const BUNDLE_VAR: &str = "SOLO_ICE_BUNDLE";
+
+thread_local! {
+    /// The phases running on this thread, outermost first
+    static PHASES: RefCell<Vec<&'static str>> = const { RefCell::new(Vec::new()) };
+    /// Whether [`catch`] is running on this thread
+    static CATCHING: Cell<bool> = const { Cell::new(false) };
+    /// The panic [`catch`] caught
+    static CAUGHT: RefCell<Option<Report>> = const { RefCell::new(None) };
+}
+
+/// The program being compiled: its path and the source read from it
+static INPUT: Mutex<Option<(PathBuf, String)>> = Mutex::new(None);
+
+/// The first ICE reported
+static REPORT: Mutex<Option<Report>> = Mutex::new(None);
+
+/// A panic of the compiler
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    pub message: String,
+    /// `file:line:column` of the panic in the compiler's source
+    pub location: Option<String>,
+    /// The phases that were running, innermost first
+    pub phases: Vec<&'static str>,
+    /// The command line, program name first
+    pub args: Vec<String>,
+    /// A backtrace of the compiler, when `RUST_BACKTRACE` asks for one
+    pub backtrace: Option<String>,
+}
+
+/// Run `f` as the phase called `name`, so that an ICE in it names it
+pub fn phase<T>(name: &'static str, f: impl FnOnce() -> T) -> T {
+    /// Leaves the phase however `f` ends, unwinding included
+    struct Leave;
+
+    impl Drop for Leave {
+        fn drop(&mut self) {
+            PHASES.with(|phases| phases.borrow_mut().pop());
+        }
+    }
+
+    PHASES.with(|phases| phases.borrow_mut().push(name));
+    let _leave = Leave;
+    f()
+}
+
+/// The phases running on this thread, innermost first
+pub fn phases() -> Vec<&'static str> {
+    PHASES.with(|phases| phases.borrow().iter().rev().copied().collect())
+}
+
+/// Record the program being compiled, for the reproduction bundle
+pub fn set_input(path: &Path, source: &str) {
+    *INPUT.lock().unwrap_or_else(|e| e.into_inner()) =
+        Some((path.to_path_buf(), source.to_string()));
+}
+
+/// Report every panic as an ICE on standard error, replacing the default
+/// panic message
+pub fn install() {
+    panic::set_hook(Box::new(|info| {
+        if CATCHING.with(Cell::get) {
+            let report = Report::new(info, false);
+            CAUGHT.with(|caught| *caught.borrow_mut() = Some(report));
+            return;
+        }
+        let report = Report::new(info, true);
+        eprint!("{}", report);
+        REPORT
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get_or_insert(report);
+    }));
+}
+
+/// The first ICE reported since [`install`]
+pub fn take_report() -> Option<Report> {
+    REPORT.lock().unwrap_or_else(|e| e.into_inner()).take()
+}
+
+/// Run `f`, returning the report of a panic in it instead of reporting it
+///
+/// Without [`install`], the default panic message is still printed and the
+/// report has no location.
+pub fn catch<T>(f: impl FnOnce() -> T) -> Result<T, Report> {
+    let catching = CATCHING.with(|catching| catching.replace(true));
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    CATCHING.with(|c| c.set(catching));
+    result.map_err(|payload| {
+        CAUGHT
+            .with(|caught| caught.borrow_mut().take())
+            .unwrap_or_else(|| Report {
+                message: message(&*payload),
+                location: None,
+                phases: Vec::new(),
+                args: std::env::args().collect(),
+                backtrace: None,
+            })
+    })
+}
+
+/// The message a panic was started with
+fn message(payload: &(dyn Any + Send)) -> String {
+    match (
+        payload.downcast_ref::<&str>(),
+        payload.downcast_ref::<String>(),
+    ) {
+        (Some(message), _) => message.to_string(),
+        (None, Some(message)) => message.clone(),
+        (None, None) => "Box<dyn Any>".to_string(),
+    }
+}
+
+impl Report {
+    /// The report of the panic `info` describes, with a backtrace if asked
+    /// for and `RUST_BACKTRACE` allows
+    fn new(info: &PanicHookInfo<'_>, backtrace: bool) -> Self {
+        let backtrace = backtrace.then(Backtrace::capture);
+        Report {
+            message: message(info.payload()),
+            location: info.location().map(ToString::to_string),
+            phases: phases(),
+            args: std::env::args().collect(),
+            backtrace: backtrace
+                .filter(|backtrace| backtrace.status() == BacktraceStatus::Captured)
+                .map(|backtrace| backtrace.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "error: internal compiler error: {}", self.message)?;
+        if let Some(location) = &self.location {
+            writeln!(f, "  --> {}", location)?;
+        }
+        writeln!(f)?;
+        writeln!(
+            f,
+            "note: the compiler unexpectedly panicked. This is a bug."
+        )?;
+        writeln!(f, "note: please report it at {}", ISSUES)?;
+        let command = match self.args.split_first() {
+            Some((_, args)) => format!("solo {}", args.join(" ")),
+            None => "solo".to_string(),
+        };
+        writeln!(
+            f,
+            "note: solo {} running `{}`",
+            env!("CARGO_PKG_VERSION"),
+            command.trim_end()
+        )?;
+        if !self.phases.is_empty() {
+            writeln!(f, "phase stack during panic:")?;
+            for (i, phase) in self.phases.iter().enumerate() {
+                writeln!(f, "#{} [{}]", i, phase)?;
+            }
+            writeln!(f, "end of phase stack")?;
+        }
+        if let Some(backtrace) = &self.backtrace {
+            writeln!(f, "compiler backtrace:\n{}", backtrace)?;
+        }
+        Ok(())
+    }
+}
+
+/// Write the reproduction bundle of `report` to `solo-ice-<pid>` in the
+/// temporary directory, returning its path: `report.txt`, `args.txt` with
+/// one argument per line, and the input recorded by [`set_input`] under its
+/// own file name and, if compiling it panics at the same place, reduced by
+/// [`minimize`](crate::minimize) as `reduced.solo`
+pub fn write_bundle(report: &Report) -> std::io::Result<PathBuf> {
+    let dir = std::env::temp_dir().join(format!("solo-ice-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    std::fs::write(dir.join("report.txt"), report.to_string())?;
+    let mut args = report.args.join("\n");
+    args.push('\n');
+    std::fs::write(dir.join("args.txt"), args)?;
+
+    let input = INPUT.lock().unwrap_or_else(|e| e.into_inner()).clone();
+    if let Some((path, source)) = input {
+        let name = path
+            .file_name()
+            .map_or("input.solo".into(), |name| name.to_os_string());
+        std::fs::write(dir.join(name), &source)?;
+        if let Ok(reduced) = minimize::minimize(&source, &Predicate::Ice) {
+            std::fs::write(dir.join("reduced.solo"), reduced.source)?;
+        }
+    }
+    Ok(dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report() {
+        let (inner, outer) = phase("interpret", || (phase("typeck", phases), phases()));
+        assert_eq!(
+            (inner, outer),
+            (vec!["typeck", "interpret"], vec!["interpret"])
+        );
+        let caught = catch(|| phase("lex", || panic!("in lex"))).unwrap_err();
+        assert_eq!(caught.message, "in lex");
+        assert!(phases().is_empty());
+        assert_eq!(catch(|| 1), Ok(1));
+
+        let report = Report {
+            message: "index out of bounds".to_string(),
+            location: Some("src/typeck.rs:812:17".to_string()),
+            phases: vec!["typeck", "interpret"],
+            args: ["solo", "build", "main.solo"].map(String::from).to_vec(),
+            backtrace: None,
+        };
+        let text = report.to_string();
+        assert!(text.starts_with(
+            "error: internal compiler error: index out of bounds\n  --> src/typeck.rs:812:17\n"
+        ));
+        assert!(text.contains(&format!(
+            "note: solo {} running `solo build main.solo`\n",
+            env!("CARGO_PKG_VERSION")
+        )));
+        assert!(text.ends_with("#0 [typeck]\n#1 [interpret]\nend of phase stack\n"));
+
+        set_input(
+            Path::new("examples/main.solo"),
+            "fn main() {\n    print(1);\n}\n",
+        );
+        let dir = write_bundle(&report).unwrap();
+        let read = |name: &str| std::fs::read_to_string(dir.join(name)).unwrap();
+        assert_eq!(read("report.txt"), text);
+        assert_eq!(read("args.txt"), "solo\nbuild\nmain.solo\n");
+        assert_eq!(read("main.solo"), "fn main() {\n    print(1);\n}\n");
+        // It compiles, so there is nothing to reduce
+        assert!(!dir.join("reduced.solo").exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,4312 @@
+//! Tree-walking interpreter for Solo dialect
+//!
+//! Executes a type-checked program directly from the AST. Used by
+//! `solo run` until native code generation lands, and as the reference
+//! semantics for it.
+//!
+//! # Integer overflow
+//!
+//! Arithmetic overflow behaviour depends on the build profile:
+//!
+//! - [`OverflowMode::Trap`] (debug builds): overflow panics with the source
+//!   location of the operation
+//! - [`OverflowMode::Wrap`] (release builds): results wrap in two's complement
+//!
+//! `wrapping_add` and friends always wrap; division by zero always panics.
+//!
+//! # Panics
+//!
+//! A panic (from `panic(msg)`, a failed check or a C call that cannot be
+//! made) unwinds the stack, running the deferred expressions of every block
+//! it leaves, and ends the run with the message and its source location.
+//! With [`PanicStrategy::Abort`] deferred expressions are skipped. With
+//! [`RunOptions::backtrace`] the message lists the Solo functions the panic
+//! left, innermost first, with the line and column each had reached.
+//!
+//! # Beliefs
+//!
+//! Belief operators are computed in closed form. `sample` draws from a
+//! seeded generator, and with [`RunOptions::samples`] `expectation` is a
+//! Monte Carlo estimate instead; a fixed seed replays a run exactly, and a
+//! panic after sampling reports the seed. [`RunOptions::beliefs`] has
+//! `print` show a belief with its statistics and a histogram, or as JSON.
+//!
+//! `expect(value)` is likelihood weighting: it evaluates `value` again for
+//! each sample, `observe` multiplying the weight of the run by the
+//! likelihood of what it observes, and returns the weighted mean.
+//!
+//! # Limits
+//!
+//! Untrusted programs can be run under [`RunOptions::limits`]: a number of
+//! evaluation steps, bytes of arena memory, nested calls and wall-clock
+//! time. A run that exceeds one stops where it is, without unwinding or
+//! running deferred expressions, and ends with
+//! [`CompileError::LimitExceeded`] naming the limit and the location.
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Write;
+use std::rc::{Rc, Weak};
+use std::time::{Duration, Instant};
+
+use crate::arena::{self, ArenaProfile};
+use crate::ast::*;
+use crate::belief::{Belief, Rng};
+use crate::builtins;
+use crate::const_eval::ConstValue;
+use crate::coverage::Coverage;
+use crate::error::CompileError;
+use crate::ffi::{CValue, Libraries, Signature};
+use crate::layout::{self, Layout};
+use crate::numeric::{Numeric, Scalar};
+use crate::parser;
+use crate::pprof::HeapProfile;
+use crate::sanitize::{Quarantine, Sanitizers, Violation};
+use crate::stdlib::alloc;
+use crate::stdlib::atomic::{self, Atomic, Ordering, Rmw};
+use crate::stdlib::channel::Channel;
+use crate::stdlib::collections::{ArenaVec, Key, Table};
+use crate::stdlib::io;
+use crate::stdlib::simd::{self, Lane, LaneOp, Lanes, Reduce, Vector};
+use crate::stdlib::string::{self, ArenaString};
+use crate::stdlib::sync::{Access, GuardKind, Lock};
+use crate::symbol::Symbol;
+use crate::typeck::{Type, TypeInfo};
+
+/// Maximum call depth before reporting a stack overflow
+pub const CALL_DEPTH_LIMIT: usize = 10_000;
+
+/// Stack of the thread `solo run` interprets on, room for
+/// [`CALL_DEPTH_LIMIT`] calls even in an unoptimized build, which uses
+/// tens of KiB per call. Only the pages a run touches are committed.
+pub const STACK_SIZE: usize = 128 * parser::STACK_SIZE;
+
+/// Bytes at the end of its thread's [`RunOptions::stack`] a run leaves for
+/// builtins and what called the interpreter; a run using the rest reports
+/// a stack overflow
+pub const STACK_RESERVE: usize = 1 << 20;
+
+/// Steps between readings of the clock under a time limit
+const CLOCK_INTERVAL: u64 = 1024;
+
+/// Runs `expect` samples without [`RunOptions::samples`]
+pub const EXPECT_SAMPLES: usize = 1000;
+
+/// What happens when integer arithmetic overflows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowMode {
+    /// Panic with the source location (debug profile)
+    Trap,
+    /// Two's complement wrapping (release profile)
+    Wrap,
+}
+
+impl OverflowMode {
+    /// Default overflow behaviour for a build profile
+    pub fn for_profile(release: bool) -> Self {
+        if release {
+            OverflowMode::Wrap
+        } else {
+            OverflowMode::Trap
+        }
+    }
+}
+
+/// What happens when a program panics
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PanicStrategy {
+    /// Unwind the stack, running deferred expressions
+    Unwind,
+    /// Stop at once, skipping deferred expressions
+    Abort,
+}
+
+impl PanicStrategy {
+    /// Parse the value of `--panic=`
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "unwind" => Some(PanicStrategy::Unwind),
+            "abort" => Some(PanicStrategy::Abort),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PanicStrategy::Unwind => "unwind",
+            PanicStrategy::Abort => "abort",
+        }
+    }
+}
+
+/// How `print` shows a belief
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BeliefFormat {
+    /// As a distribution literal, `~Normal(20, 2.5)`
+    Literal,
+    /// With its mean, variance and credible interval, and a histogram
+    Plot,
+    /// As a JSON object on one line
+    Json,
+}
+
+impl BeliefFormat {
+    /// Parse the value of `--output`
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "literal" => Some(BeliefFormat::Literal),
+            "plot" => Some(BeliefFormat::Plot),
+            "json" => Some(BeliefFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+/// How `solo run` executes a program
+#[derive(Debug, Clone)]
+pub struct RunOptions {
+    pub overflow: OverflowMode,
+    pub panic: PanicStrategy,
+    /// Append a backtrace of Solo functions to panic messages
+    pub backtrace: bool,
+    /// Seed of the random number generator used to sample beliefs
+    pub seed: u64,
+    /// Estimate `expectation` from this many samples instead of exactly
+    pub samples: Option<usize>,
+    /// Collect per-arena allocation statistics
+    pub profile_arenas: bool,
+    /// Record the call stack of every allocation for a pprof profile
+    pub profile_heap: bool,
+    /// Command-line arguments for `env::arg`, the program's path first
+    pub args: Vec<String>,
+    /// Memory-safety checks of dereferences of raw pointers
+    pub sanitizers: Sanitizers,
+    /// Count executions of functions, lines and branches
+    pub coverage: bool,
+    /// Resources the program may use before it is stopped
+    pub limits: Limits,
+    /// How `print` shows a belief
+    pub beliefs: BeliefFormat,
+    /// Let the `io` builtins read and write files; the playground's
+    /// sandboxed runs turn this off
+    pub files: bool,
+    /// Let the program call functions of `extern "C"` blocks; off in the
+    /// playground's sandboxed runs too
+    pub ffi: bool,
+    /// Size of the stack of the thread the run is on, [`parser::STACK_SIZE`]
+    /// unless it was started with more (see [`STACK_SIZE`])
+    pub stack: usize,
+}
+
+/// Resource limits of a run; `None` is unlimited
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Limits {
+    /// Expressions evaluated
+    pub steps: Option<u64>,
+    /// Bytes allocated from arenas and not yet freed with them
+    pub memory: Option<u64>,
+    /// Solo function calls in progress at once; deeper recursion than
+    /// [`RunOptions::stack`] holds panics with a stack overflow either way
+    pub depth: Option<usize>,
+    /// Wall-clock time, read every 1024 steps
+    pub time: Option<Duration>,
+}
+
+impl Limits {
+    pub fn is_unlimited(&self) -> bool {
+        *self == Limits::default()
+    }
+}
+
+/// A limit a run exceeded, with its value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    Steps(u64),
+    Memory(u64),
+    Depth(usize),
+    Time(Duration),
+}
+
+impl Limit {
+    /// Short machine-readable name of the limit
+    pub fn name(self) -> &'static str {
+        match self {
+            Limit::Steps(_) => "steps",
+            Limit::Memory(_) => "memory",
+            Limit::Depth(_) => "depth",
+            Limit::Time(_) => "time",
+        }
+    }
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Limit::Steps(max) => write!(f, "ran for more than {} steps", max),
+            Limit::Memory(max) => write!(f, "allocated more than {} bytes", max),
+            Limit::Depth(max) => write!(f, "nested calls more than {} deep", max),
+            Limit::Time(max) => write!(f, "ran for more than {:?}", max),
+        }
+    }
+}
+
+/// A run stopped by one of its [`Limits`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LimitExceeded {
+    pub limit: Limit,
+    /// The expression or call being evaluated when it was stopped
+    pub location: Span,
+    /// Steps evaluated by then
+    pub steps: u64,
+}
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Limit exceeded at {}:{}: the program {}",
+            self.location.line, self.location.column, self.limit
+        )
+    }
+}
+
+impl Default for RunOptions {
+    fn default() -> Self {
+        Self {
+            overflow: OverflowMode::Trap,
+            panic: PanicStrategy::Unwind,
+            backtrace: false,
+            seed: 0,
+            samples: None,
+            profile_arenas: false,
+            profile_heap: false,
+            args: Vec::new(),
+            sanitizers: Sanitizers::default(),
+            coverage: false,
+            limits: Limits::default(),
+            beliefs: BeliefFormat::Literal,
+            files: true,
+            ffi: true,
+            stack: parser::STACK_SIZE,
+        }
+    }
+}
+
+/// A runtime value
+#[derive(Debug, Clone)]
+pub enum Value {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Str(String),
+    Unit,
+    Array(Vec<Value>),
+    Enum(String, String, i64),
+    /// Struct with fields in declaration order
+    Struct(String, Vec<(String, Value)>),
+    /// `Some` value, or `None`
+    Option(Option<Box<Value>>),
+    Fn(String),
+    Belief(Belief),
+    Ref(Rc<RefCell<Value>>),
+    /// Handle to the live arena at this depth
+    Arena(usize),
+    /// Checkpoint of the arena at this depth, by index among its checkpoints
+    Checkpoint(usize, usize),
+    /// `Vec` whose buffers come from the arena at this depth
+    Vec(usize, ArenaVec<Value>),
+    /// `Map` whose buffers come from the arena at this depth
+    Map(usize, Table<Value>),
+    /// `StringBuf` whose buffers come from the arena at this depth
+    StringBuf(usize, ArenaString),
+    /// `f64x4` and the other vector types
+    Simd(Vector),
+    /// Handle to the thread with this index in `Interpreter::threads`
+    Thread(usize),
+    /// Ends of the channel with this index in `Interpreter::channels`
+    Sender(usize),
+    Receiver(usize),
+    /// `AtomicI64` and the other atomic integers, shared by copies
+    Atomic(Rc<Atomic>),
+    /// `Mutex` or `RwLock`, named, and shared by copies
+    Lock(&'static str, Rc<Lock<Value>>),
+    Guard(Rc<Guard>),
+    Ptr(Pointer),
+    /// `start..end`, or `start..=end` when inclusive
+    Range(Option<i64>, Option<i64>, bool),
+}
+
+/// A raw pointer to the value in a slot, or to an element of the array in
+/// it. Arithmetic may take it anywhere; whether it still points into the
+/// value is only checked when it is dereferenced.
+#[derive(Debug, Clone)]
+pub struct Pointer {
+    slot: Rc<RefCell<Value>>,
+    element: bool,
+    offset: i64,
+    /// The arena holding the elements pointed into, and where it was
+    /// entered; a pointer to a variable points to the stack
+    arena: Option<(Weak<()>, Span)>,
+}
+
+impl Pointer {
+    fn to(slot: Rc<RefCell<Value>>, element: bool) -> Self {
+        Self {
+            slot: deepest_slot(slot),
+            element,
+            offset: 0,
+            arena: None,
+        }
+    }
+
+    /// The part of `value`, the value in the slot, that this points to
+    fn target<'a>(&self, value: &'a mut Value, span: Span) -> Result<&'a mut Value, Flow> {
+        let index = usize::try_from(self.offset).ok();
+        let target = if !self.element {
+            Some(value).filter(|_| self.offset == 0)
+        } else if let Value::Array(items) = value {
+            index.and_then(|i| items.get_mut(i))
+        } else if let Value::Vec(_, items) = value {
+            index.and_then(|i| items.items_mut().get_mut(i))
+        } else {
+            None
+        };
+        target.ok_or_else(|| {
+            panic_flow(
+                span,
+                &format!(
+                    "dereference of a pointer {} elements past the start of what it points into",
+                    self.offset
+                ),
+            )
+        })
+    }
+
+    fn read(&self, span: Span) -> ExecResult {
+        Ok(self.target(&mut self.slot.borrow_mut(), span)?.clone())
+    }
+
+    /// Offset and size in bytes of what this points to, given the size
+    /// of a value
+    fn extent(&self, size_of: impl Fn(&Value) -> u64) -> (i64, u64) {
+        let value = self.slot.borrow();
+        let items = match &*value {
+            Value::Array(items) if self.element => items.as_slice(),
+            Value::Vec(_, items) if self.element => items.items(),
+            other => std::slice::from_ref(other),
+        };
+        let element_size = items.first().map_or(0, &size_of);
+        let size = element_size * items.len() as u64;
+        (self.offset.saturating_mul(element_size as i64), size)
+    }
+}
+
+/// A held lock, released when dropped unless `unlock` released it first.
+/// Reading a variable holding a guard moves the guard out (see
+/// [`Interpreter::eval_path`]), so the guard is dropped where the checker
+/// considers it to be, at the end of the scope owning it.
+#[derive(Debug)]
+pub struct Guard {
+    kind: GuardKind,
+    lock: Rc<Lock<Value>>,
+    released: Cell<bool>,
+}
+
+impl Guard {
+    fn unlock(&self) {
+        if !self.released.replace(true) {
+            self.lock.release(self.kind.access());
+        }
+    }
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        self.unlock();
+    }
+}
+
+impl Value {
+    fn deref(&self) -> Value {
+        match self {
+            Value::Ref(slot) => slot.borrow().deref(),
+            other => other.clone(),
+        }
+    }
+
+    /// The key a map stores this value under, if it is hashable
+    fn key(&self) -> Option<Key> {
+        match self.deref() {
+            Value::Int(n) => Some(Key::Int(n)),
+            Value::Bool(b) => Some(Key::Bool(b)),
+            Value::Str(s) => Some(Key::Str(s)),
+            Value::Enum(name, variant, d) => Some(Key::Variant(name, variant, d)),
+            _ => None,
+        }
+    }
+}
+
+impl From<&Key> for Value {
+    fn from(key: &Key) -> Self {
+        match key {
+            Key::Int(n) => Value::Int(*n),
+            Key::Bool(b) => Value::Bool(*b),
+            Key::Str(s) => Value::Str(s.clone()),
+            Key::Variant(name, variant, d) => Value::Enum(name.clone(), variant.clone(), *d),
+        }
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self.deref(), other.deref()) {
+            (Value::Int(a), Value::Int(b)) => a == b,
+            (Value::Float(a), Value::Float(b)) => a == b,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Str(a), Value::Str(b)) => a == b,
+            (Value::Unit, Value::Unit) => true,
+            (Value::Array(a), Value::Array(b)) => a == b,
+            (Value::Enum(e1, _, a), Value::Enum(e2, _, b)) => e1 == e2 && a == b,
+            (Value::Struct(s1, a), Value::Struct(s2, b)) => s1 == s2 && a == b,
+            (Value::Option(a), Value::Option(b)) => a == b,
+            (Value::Fn(a), Value::Fn(b)) => a == b,
+            (Value::Belief(a), Value::Belief(b)) => a == b,
+            (Value::Arena(a), Value::Arena(b)) => a == b,
+            (Value::Checkpoint(a, i), Value::Checkpoint(b, j)) => a == b && i == j,
+            (Value::Vec(_, a), Value::Vec(_, b)) => a.items() == b.items(),
+            (Value::StringBuf(_, a), Value::StringBuf(_, b)) => a.as_str() == b.as_str(),
+            (Value::Simd(a), Value::Simd(b)) => a == b,
+            (Value::Thread(a), Value::Thread(b))
+            | (Value::Sender(a), Value::Sender(b))
+            | (Value::Receiver(a), Value::Receiver(b)) => a == b,
+            (Value::Atomic(a), Value::Atomic(b)) => Rc::ptr_eq(&a, &b),
+            (Value::Lock(_, a), Value::Lock(_, b)) => Rc::ptr_eq(&a, &b),
+            (Value::Ptr(a), Value::Ptr(b)) => Rc::ptr_eq(&a.slot, &b.slot) && a.offset == b.offset,
+            (Value::Range(a, b, c), Value::Range(x, y, z)) => (a, b, c) == (x, y, z),
+            (Value::Map(_, a), Value::Map(_, b)) => {
+                a.len() == b.len() && a.iter().all(|(key, value)| b.get(key) == Some(value))
+            }
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(n) => write!(f, "{}", n),
+            Value::Float(x) => write!(f, "{}", x),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Str(s) => write!(f, "{}", s),
+            Value::StringBuf(_, text) => write!(f, "{}", text.as_str()),
+            Value::Unit => write!(f, "()"),
+            Value::Array(items) => write_list(f, items),
+            Value::Vec(_, items) => write_list(f, items.items()),
+            Value::Simd(vector) => {
+                write!(f, "{}", vector.ty.name)?;
+                write_list(f, &lane_values(vector))
+            }
+            Value::Map(_, table) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in table.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", Value::from(key), value)?;
+                }
+                write!(f, "}}")
+            }
+            Value::Enum(name, variant, _) => write!(f, "{}::{}", name, variant),
+            Value::Struct(name, fields) => {
+                write!(f, "{} {{ ", name)?;
+                for (i, (field, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", field, value)?;
+                }
+                write!(f, " }}")
+            }
+            Value::Option(Some(value)) => write!(f, "Some({})", value),
+            Value::Option(None) => write!(f, "None"),
+            Value::Fn(name) => write!(f, "fn {}", name),
+            Value::Belief(belief) => write!(f, "{}", belief),
+            Value::Ref(slot) => write!(f, "{}", slot.borrow()),
+            Value::Arena(depth) => write!(f, "arena #{}", depth),
+            Value::Checkpoint(depth, index) => {
+                write!(f, "checkpoint #{} of arena #{}", index, depth)
+            }
+            Value::Thread(id) => write!(f, "thread #{}", id),
+            Value::Sender(id) => write!(f, "sender #{}", id),
+            Value::Receiver(id) => write!(f, "receiver #{}", id),
+            Value::Atomic(cell) => write!(f, "{}({})", cell.ty.name, cell.load(Ordering::SeqCst)),
+            Value::Lock(name, lock) => write!(f, "{}({})", name, lock.get()),
+            Value::Guard(guard) => write!(f, "{}({})", guard.kind.type_name(), guard.lock.get()),
+            Value::Ptr(pointer) => write!(f, "pointer +{}", pointer.offset),
+            Value::Range(start, end, inclusive) => {
+                if let Some(start) = start {
+                    write!(f, "{}", start)?;
+                }
+                write!(f, "{}", if *inclusive { "..=" } else { ".." })?;
+                if let Some(end) = end {
+                    write!(f, "{}", end)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// The ordering `value` names, if `operation` can use it (as its failure
+/// ordering with `failure`)
+fn atomic_ordering(
+    value: &Value,
+    operation: &str,
+    failure: bool,
+    span: Span,
+) -> Result<Ordering, Flow> {
+    let Value::Enum(_, variant, _) = value.deref() else {
+        return Err(panic_flow(
+            span,
+            &format!("`{}` is not an `Ordering`", value),
+        ));
+    };
+    let ordering = Ordering::from_name(&variant)
+        .ok_or_else(|| panic_flow(span, &format!("`{}` is not an `Ordering`", value)))?;
+    atomic::check_ordering(operation, failure, ordering).map_err(|msg| panic_flow(span, &msg))?;
+    Ok(ordering)
+}
+
+/// Perform the atomic operation `method` on `cell`
+fn atomic_method(cell: &Atomic, method: &str, args: &[Value], span: Span) -> ExecResult {
+    let int = |i: usize| match args.get(i) {
+        Some(Value::Int(n)) => Ok(*n),
+        _ => Err(panic_flow(
+            span,
+            &format!("invalid arguments to `{}`", method),
+        )),
+    };
+    let ordering = |i: usize, failure: bool| match args.get(i) {
+        Some(value) => atomic_ordering(value, method, failure, span),
+        None => Err(panic_flow(
+            span,
+            &format!("invalid arguments to `{}`", method),
+        )),
+    };
+    match method {
+        "load" => Ok(Value::Int(cell.load(ordering(0, false)?))),
+        "store" => {
+            cell.store(int(0)?, ordering(1, false)?);
+            Ok(Value::Unit)
+        }
+        "compare_exchange" => Ok(Value::Int(cell.compare_exchange(
+            int(0)?,
+            int(1)?,
+            ordering(2, false)?,
+            ordering(3, true)?,
+        ))),
+        _ => match Rmw::from_method(method) {
+            Some(op) => Ok(Value::Int(cell.update(op, int(0)?, ordering(1, false)?))),
+            None => panic_at(
+                span,
+                &format!("no method named `{}` found for `{}`", method, cell.ty.name),
+            ),
+        },
+    }
+}
+
+/// Take `lock` for the guard of `kind`. The interpreter runs one thread
+/// at a time to completion, so whoever holds the lock is this thread or
+/// one below it, waiting for this one: waiting would never end.
+fn lock_method(lock: &Rc<Lock<Value>>, kind: GuardKind, span: Span) -> ExecResult {
+    if !lock.try_acquire(kind.access()) {
+        return panic_at(
+            span,
+            "deadlock: the lock is held by this thread, or by one waiting for it to finish",
+        );
+    }
+    Ok(Value::Guard(Rc::new(Guard {
+        kind,
+        lock: Rc::clone(lock),
+        released: Cell::new(false),
+    })))
+}
+
+/// `get`, `set`, `replace` and `unlock` of a guard
+fn guard_method(guard: &Guard, method: &str, args: Vec<Value>, span: Span) -> ExecResult {
+    let exclusive = guard.kind.access() == Access::Exclusive;
+    match (method, args.into_iter().next()) {
+        ("get", None) => Ok(guard.lock.get()),
+        ("set", Some(value)) if exclusive => {
+            guard.lock.replace(value);
+            Ok(Value::Unit)
+        }
+        ("replace", Some(value)) if exclusive => Ok(guard.lock.replace(value)),
+        ("unlock", None) => {
+            guard.unlock();
+            Ok(Value::Unit)
+        }
+        _ => panic_at(
+            span,
+            &format!(
+                "no method named `{}` found for `{}`",
+                method,
+                guard.kind.type_name()
+            ),
+        ),
+    }
+}
+
+/// A vector of type `ty` made by `constructor` (`splat` or `from_array`)
+/// from `args`, or `None` if they do not fit
+fn vector(ty: &'static simd::VectorType, constructor: &str, args: &[Value]) -> Option<Vector> {
+    let lanes = match (constructor, args.first()?.deref()) {
+        ("splat", lane) => vec![lane; ty.lanes],
+        ("from_array", Value::Array(items)) if items.len() == ty.lanes => items,
+        _ => return None,
+    };
+    let lanes = if ty.float {
+        Lanes::Float(
+            lanes
+                .iter()
+                .map(|lane| match lane.deref() {
+                    Value::Float(x) => Some(x),
+                    _ => None,
+                })
+                .collect::<Option<_>>()?,
+        )
+    } else {
+        Lanes::Int(
+            lanes
+                .iter()
+                .map(|lane| match lane.deref() {
+                    Value::Int(n) => Some(n),
+                    _ => None,
+                })
+                .collect::<Option<_>>()?,
+        )
+    };
+    Some(Vector::new(ty, lanes))
+}
+
+fn lane_values(vector: &Vector) -> Vec<Value> {
+    match &vector.lanes {
+        Lanes::Int(lanes) => lanes.iter().copied().map(Value::Int).collect(),
+        Lanes::Float(lanes) => lanes.iter().copied().map(Value::Float).collect(),
+    }
+}
+
+fn write_list(f: &mut fmt::Formatter<'_>, items: &[Value]) -> fmt::Result {
+    write!(f, "[")?;
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}", item)?;
+    }
+    write!(f, "]")
+}
+
+impl From<&ConstValue> for Value {
+    fn from(value: &ConstValue) -> Self {
+        match value {
+            ConstValue::Int(n) => Value::Int(*n),
+            ConstValue::Float(x) => Value::Float(*x),
+            ConstValue::Bool(b) => Value::Bool(*b),
+            ConstValue::Str(s) => Value::Str(s.clone()),
+            ConstValue::Unit => Value::Unit,
+            ConstValue::Array(items) => Value::Array(items.iter().map(Value::from).collect()),
+            ConstValue::Variant(name, variant, d) => Value::Enum(name.clone(), variant.clone(), *d),
+            ConstValue::Struct(name, fields) => Value::Struct(
+                name.clone(),
+                fields
+                    .iter()
+                    .map(|(field, value)| (field.clone(), Value::from(value)))
+                    .collect(),
+            ),
+            ConstValue::Option(value) => {
+                Value::Option(value.as_deref().map(|value| Box::new(Value::from(value))))
+            }
+            ConstValue::Range(start, end, inclusive) => Value::Range(*start, *end, *inclusive),
+        }
+    }
+}
+
+enum Projection<'a> {
+    Index(i64),
+    Field(&'a str),
+}
+
+enum Flow {
+    Return(Value),
+    /// `become`: leave the function, and call this one in its place
+    Become(String, Vec<Value>),
+    Panic(Box<Panic>),
+    /// Not caught by anything: defers and joining threads pass it on
+    Exceeded(Box<LimitExceeded>),
+}
+
+/// A panic on its way out of the program
+struct Panic {
+    message: String,
+    /// Where execution stopped in the innermost function not yet left
+    location: Span,
+    /// Functions left so far, innermost first, and where each stopped
+    backtrace: Vec<(String, Span)>,
+}
+
+impl Panic {
+    fn report(&self, backtrace: bool) -> String {
+        let mut report = self.message.clone();
+        if backtrace {
+            report.push_str("\nstack backtrace:");
+            for (i, (function, span)) in self.backtrace.iter().enumerate() {
+                report.push_str(&format!(
+                    "\n{:>4}: {}\n             at {}:{}",
+                    i, function, span.line, span.column
+                ));
+            }
+        }
+        report
+    }
+}
+
+/// What a run under [`Limits`] has used of them
+struct Meter {
+    limits: Limits,
+    steps: u64,
+    /// Bytes in live arenas
+    memory: u64,
+    /// When the run started, under a time limit
+    started: Option<Instant>,
+    /// Once a limit is exceeded, every later step stops too
+    exceeded: Option<LimitExceeded>,
+}
+
+impl Meter {
+    fn new(limits: Limits) -> Self {
+        Self {
+            limits,
+            steps: 0,
+            memory: 0,
+            started: limits.time.map(|_| Instant::now()),
+            exceeded: None,
+        }
+    }
+
+    /// Count an evaluation at `span`, stopping the run if it is over a limit
+    fn step(&mut self, span: Span) -> Result<(), Flow> {
+        if self.exceeded.is_none() {
+            let Limits {
+                steps,
+                memory,
+                time,
+                ..
+            } = self.limits;
+            let over = |max: Option<u64>, used: u64| max.filter(|&max| used > max);
+            let late = |max: &Duration| {
+                self.steps.is_multiple_of(CLOCK_INTERVAL)
+                    && self.started.is_some_and(|start| start.elapsed() > *max)
+            };
+            let limit = over(steps, self.steps + 1)
+                .map(Limit::Steps)
+                .or_else(|| over(memory, self.memory).map(Limit::Memory))
+                .or_else(|| time.filter(late).map(Limit::Time));
+            match limit {
+                Some(limit) => self.exceed(limit, span),
+                None => self.steps += 1,
+            }
+        }
+        match &self.exceeded {
+            Some(exceeded) => Err(Flow::Exceeded(Box::new(exceeded.clone()))),
+            None => Ok(()),
+        }
+    }
+
+    /// Stop the run if `bytes` more would not fit under the memory limit,
+    /// before they are allocated at `span`
+    fn reserve(&mut self, bytes: u64, span: Span) -> Result<(), Flow> {
+        match self.limits.memory {
+            Some(max) if self.memory.saturating_add(bytes) > max => {
+                self.exceed(Limit::Memory(max), span);
+                self.step(span)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Stop the run if a call at `span` would nest `depth` calls too deep
+    fn enter(&mut self, depth: usize, span: Span) -> Result<(), Flow> {
+        match self.limits.depth {
+            Some(max) if depth >= max => {
+                self.exceed(Limit::Depth(max), span);
+                self.step(span)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn exceed(&mut self, limit: Limit, location: Span) {
+        self.exceeded.get_or_insert(LimitExceeded {
+            limit,
+            location,
+            steps: self.steps,
+        });
+    }
+}
+
+type ExecResult = Result<Value, Flow>;
+
+type Scope = HashMap<Symbol, Rc<RefCell<Value>>>;
+
+pub struct Interpreter<'p> {
+    exprs: &'p Exprs,
+    /// Functions and methods by call path (`name`, `Type::name`)
+    functions: HashMap<String, &'p Function>,
+    info: &'p TypeInfo,
+    overflow: OverflowMode,
+    panic: PanicStrategy,
+    backtrace: bool,
+    out: &'p mut dyn Write,
+    depth: usize,
+    rng: Rng,
+    samples: Option<usize>,
+    beliefs: BeliefFormat,
+    /// Likelihood of the run `expect` is sampling so far
+    weight: Option<f64>,
+    /// Live arenas, innermost last
+    arenas: Vec<arena::Frame>,
+    /// Arena receiving allocations instead of the innermost one, inside
+    /// `handle.alloc(...)`
+    alloc_target: Option<usize>,
+    /// Set while an arena's allocator runs: what it allocates itself is
+    /// charged to the arena without asking the allocator again
+    allocating: bool,
+    arena_profile: Option<ArenaProfile>,
+    heap_profile: Option<HeapProfile>,
+    /// Where functions of `extern` blocks are found
+    libraries: Libraries,
+    args: Vec<String>,
+    /// Every thread spawned so far, by handle
+    threads: Vec<Thread>,
+    /// Every channel created so far
+    channels: Vec<Channel<Value>>,
+    sanitizers: Sanitizers,
+    /// Variable slots whose scope ended while a pointer pointed into them
+    quarantine: Quarantine<RefCell<Value>>,
+    coverage: Option<Coverage>,
+    /// Resources used, when they are limited
+    meter: Option<Meter>,
+    /// Whether the `io` builtins may touch the file system
+    files: bool,
+    /// Whether C functions may be called
+    ffi: bool,
+    /// Address of the stack where the running function was called from
+    stack_base: usize,
+    /// Bytes of the stack past `stack_base` calls may use
+    stack_limit: usize,
+}
+
+/// A thread of the interpreted program
+///
+/// Threads run one at a time: a spawned thread waits until it is joined,
+/// or until a thread receives from an empty channel, and then runs to
+/// completion on the stack of the thread waiting for it. That is one of
+/// the interleavings a multi-threaded run could produce, and since the
+/// checker only lets threads exchange values they own, every interleaving
+/// computes the same results. A thread that is never needed never runs,
+/// as if the process had exited first.
+///
+/// A thread below others on the stack cannot resume before they finish,
+/// so sends never wait (a full bounded channel takes the message anyway),
+/// and threads waiting on each other through more than one channel can be
+/// reported as deadlocked when native threads would not be.
+enum Thread {
+    /// Not started yet: the function to run and its arguments
+    Pending(String, Vec<Value>),
+    Running,
+    /// Ran to completion, but not joined yet
+    Finished(Result<Value, Flow>),
+    Joined,
+}
+
+impl<'p> Interpreter<'p> {
+    pub fn new(
+        program: &'p Program,
+        info: &'p TypeInfo,
+        options: &RunOptions,
+        out: &'p mut dyn Write,
+    ) -> Self {
+        let functions = program
+            .functions()
+            .into_iter()
+            .map(|(name, _, f)| (name, f))
+            .collect();
+        Self {
+            exprs: &program.exprs,
+            functions,
+            info,
+            overflow: options.overflow,
+            panic: options.panic,
+            backtrace: options.backtrace,
+            out,
+            depth: 0,
+            rng: Rng::new(options.seed),
+            samples: options.samples,
+            beliefs: options.beliefs,
+            weight: None,
+            arenas: Vec::new(),
+            alloc_target: None,
+            allocating: false,
+            arena_profile: options.profile_arenas.then(ArenaProfile::default),
+            heap_profile: options.profile_heap.then(HeapProfile::new),
+            libraries: Libraries::new(info.links.clone()),
+            args: options.args.clone(),
+            threads: Vec::new(),
+            channels: Vec::new(),
+            sanitizers: options.sanitizers,
+            quarantine: Quarantine::default(),
+            coverage: options.coverage.then(|| Coverage::instrument(program)),
+            meter: (!options.limits.is_unlimited()).then(|| Meter::new(options.limits)),
+            files: options.files,
+            ffi: options.ffi,
+            stack_base: 0,
+            stack_limit: options.stack.saturating_sub(STACK_RESERVE),
+        }
+    }
+
+    /// Arena statistics collected so far, if profiling was requested
+    pub fn arena_profile(&self) -> Option<&ArenaProfile> {
+        self.arena_profile.as_ref()
+    }
+
+    /// Allocations recorded so far, if a heap profile was requested
+    pub fn heap_profile(&self) -> Option<&HeapProfile> {
+        self.heap_profile.as_ref()
+    }
+
+    /// Execution counts so far, if coverage was requested
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    /// Run `main`, returning its result or the panic message
+    pub fn run_main(&mut self) -> Result<Value, CompileError> {
+        self.run_function("main")
+    }
+
+    /// Call the argument-less function `name`
+    pub fn run_function(&mut self, name: &str) -> Result<Value, CompileError> {
+        if !self.functions.contains_key(name) {
+            return Err(CompileError::Runtime(format!(
+                "Runtime error: no `{}` function found",
+                name
+            )));
+        }
+        self.stack_base = stack_address();
+        match self.call(name, Vec::new(), Span::new(1, 1)) {
+            Ok(value) | Err(Flow::Return(value)) => Ok(value),
+            Err(Flow::Panic(panic)) => {
+                let mut report = panic.report(self.backtrace);
+                // Without the seed, a failure after sampling may not recur
+                if self.rng.draws() > 0 {
+                    report.push_str(&format!(
+                        "\nnote: beliefs were sampled with seed {}",
+                        self.rng.seed()
+                    ));
+                }
+                Err(CompileError::Runtime(report))
+            }
+            Err(Flow::Exceeded(exceeded)) => Err(CompileError::LimitExceeded(*exceeded)),
+            Err(Flow::Become(..)) => unreachable!("`call` makes tail calls itself"),
+        }
+    }
+
+    fn call(&mut self, name: &str, args: Vec<Value>, span: Span) -> ExecResult {
+        let Some(mut function) = self.functions.get(name).copied() else {
+            if let Some(signature) = self.info.externs.get(name) {
+                return self.call_extern(name, &signature.clone(), args, span);
+            }
+            return self.call_builtin(name, args, span);
+        };
+        if let Some(meter) = &mut self.meter {
+            meter.enter(self.depth, span)?;
+        }
+        if self.depth >= CALL_DEPTH_LIMIT || self.stack_used() > self.stack_limit {
+            return panic_at(span, &format!("stack overflow while calling `{}`", name));
+        }
+
+        let mut name = name.to_string();
+        let mut args = args;
+        let result = loop {
+            let names = function
+                .receiver
+                .map(|_| Symbol::intern("self"))
+                .into_iter()
+                .chain(function.params.iter().map(|p| p.name));
+            let frame: Scope = names
+                .zip(args)
+                .map(|(name, value)| (name, Rc::new(RefCell::new(value))))
+                .collect();
+            let mut env = vec![frame];
+
+            if let Some(coverage) = &mut self.coverage {
+                coverage.hit_function(&name);
+            }
+            if let Some(profile) = &mut self.heap_profile {
+                profile.enter(&name, function.span.line, span.line);
+            }
+            self.depth += 1;
+            let result = self.exec_block(&function.body, &mut env);
+            self.depth -= 1;
+            self.end_scope(env.pop());
+            if let Some(profile) = &mut self.heap_profile {
+                profile.exit();
+            }
+
+            // A tail call runs in the frame just left, at the same depth, as
+            // if called from where this function was; the checker only lets
+            // `become` call Solo functions
+            match result {
+                Err(Flow::Become(next, next_args)) => match self.functions.get(&next) {
+                    Some(next_function) => {
+                        function = next_function;
+                        name = next;
+                        args = next_args;
+                    }
+                    None => return self.call(&next, next_args, span),
+                },
+                result => break result,
+            }
+        };
+
+        match result {
+            Ok(value) | Err(Flow::Return(value)) => Ok(value),
+            Err(Flow::Panic(mut panic)) => {
+                panic.backtrace.push((name, panic.location));
+                panic.location = span;
+                Err(Flow::Panic(panic))
+            }
+            Err(flow) => Err(flow),
+        }
+    }
+
+    /// Wait for the thread `id` to finish and take its result; a panic on
+    /// that thread continues on the joining one, through the `join` call
+    fn join(&mut self, id: usize, span: Span) -> ExecResult {
+        if let Thread::Pending(..) = self.threads[id] {
+            self.run_thread(id, span);
+        }
+        match std::mem::replace(&mut self.threads[id], Thread::Joined) {
+            Thread::Finished(Ok(value)) => Ok(value),
+            Thread::Finished(Err(flow)) => Err(flow),
+            Thread::Running => {
+                self.threads[id] = Thread::Running;
+                panic_at(span, "deadlock: a thread is joining itself")
+            }
+            _ => panic_at(span, "thread already joined"),
+        }
+    }
+
+    /// Run the pending thread `id` to completion, on behalf of a thread
+    /// waiting at `span`
+    fn run_thread(&mut self, id: usize, span: Span) {
+        let Thread::Pending(function, args) =
+            std::mem::replace(&mut self.threads[id], Thread::Running)
+        else {
+            return;
+        };
+        let result = match self.call(&function, args, span) {
+            Ok(value) | Err(Flow::Return(value)) => Ok(value),
+            Err(Flow::Panic(mut panic)) => {
+                panic.message = panic.message.replacen(
+                    "Runtime panic",
+                    &format!("Runtime panic in a thread running `{}`", function),
+                    1,
+                );
+                Err(Flow::Panic(panic))
+            }
+            Err(exceeded) => Err(exceeded),
+        };
+        self.threads[id] = Thread::Finished(result);
+    }
+
+    /// Run the first thread that has not started yet, returning whether
+    /// there was one
+    fn run_pending_thread(&mut self, span: Span) -> bool {
+        let pending = self
+            .threads
+            .iter()
+            .position(|thread| matches!(thread, Thread::Pending(..)));
+        if let Some(id) = pending {
+            self.run_thread(id, span);
+        }
+        pending.is_some()
+    }
+
+    /// `send`, `recv` and `sender` of the ends of a channel, which run
+    /// other threads until the channel has room or a message
+    fn channel_method(
+        &mut self,
+        end: &Value,
+        method: &str,
+        args: Vec<Value>,
+        span: Span,
+    ) -> ExecResult {
+        match (end, method) {
+            (Value::Sender(id), "send") => {
+                let mut message = args.into_iter().next().unwrap_or(Value::Unit);
+                loop {
+                    match self.channels[*id].try_send(message) {
+                        Ok(()) => return Ok(Value::Unit),
+                        Err(full) if self.run_pending_thread(span) => message = full,
+                        Err(full) => {
+                            self.channels[*id].force_send(full);
+                            return Ok(Value::Unit);
+                        }
+                    }
+                }
+            }
+            (Value::Receiver(id), "recv") => loop {
+                if let Some(message) = self.channels[*id].try_recv() {
+                    return Ok(message);
+                }
+                if !self.run_pending_thread(span) {
+                    return panic_at(
+                        span,
+                        "deadlock: receiving from an empty channel, and no other thread can run",
+                    );
+                }
+            },
+            (Value::Receiver(id), "sender") => Ok(Value::Sender(*id)),
+            (other, _) => panic_at(
+                span,
+                &format!("no method named `{}` found for `{}`", method, other),
+            ),
+        }
+    }
+
+    /// Call a C function through the FFI shim
+    fn call_extern(
+        &mut self,
+        name: &str,
+        signature: &Signature,
+        args: Vec<Value>,
+        span: Span,
+    ) -> ExecResult {
+        if !self.ffi {
+            let msg = format!("cannot call C function `{}`: calling C is disabled", name);
+            return panic_at(span, &msg);
+        }
+        let args: Vec<CValue> = args
+            .iter()
+            .map(|arg| match arg.deref() {
+                Value::Int(n) => CValue::Int(n),
+                Value::Float(x) => CValue::Float(x),
+                Value::Bool(b) => CValue::Bool(b),
+                Value::Str(s) => CValue::Str(s),
+                _ => CValue::Void,
+            })
+            .collect();
+        match self.libraries.call(name, signature, &args) {
+            Ok(CValue::Int(n)) => Ok(Value::Int(n)),
+            Ok(CValue::Float(x)) => Ok(Value::Float(x)),
+            Ok(CValue::Bool(b)) => Ok(Value::Bool(b)),
+            Ok(CValue::Str(s)) => Ok(Value::Str(s)),
+            Ok(CValue::Void) => Ok(Value::Unit),
+            Err(msg) => panic_at(span, &msg),
+        }
+    }
+
+    /// `expect(value)`: the mean of `value` evaluated [`EXPECT_SAMPLES`]
+    /// times, or [`RunOptions::samples`] times, each run weighted by the
+    /// likelihood of what it observes (likelihood weighting)
+    fn expect(&mut self, value: &Expr, env: &mut Vec<Scope>, span: Span) -> ExecResult {
+        let samples = self.samples.unwrap_or(EXPECT_SAMPLES);
+        let outer = self.weight.take();
+        let (mut total, mut weights) = (0.0, 0.0);
+        for _ in 0..samples {
+            self.weight = Some(1.0);
+            let result = self.eval(value, env);
+            let weight = self.weight.unwrap_or(0.0);
+            let x = match result.map(|value| value.deref()) {
+                Ok(Value::Float(x)) => x,
+                Ok(Value::Bool(b)) => f64::from(u8::from(b)),
+                Ok(other) => {
+                    self.weight = outer;
+                    return panic_at(span, &format!("cannot take the expectation of `{}`", other));
+                }
+                Err(flow) => {
+                    self.weight = outer;
+                    return Err(flow);
+                }
+            };
+            if weight > 0.0 {
+                total += weight * x;
+                weights += weight;
+            }
+        }
+        self.weight = outer;
+        if weights > 0.0 {
+            Ok(Value::Float(total / weights))
+        } else {
+            panic_at(
+                span,
+                &format!("`observe` rejected all {} samples of `expect`", samples),
+            )
+        }
+    }
+
+    fn call_builtin(&mut self, name: &str, args: Vec<Value>, span: Span) -> ExecResult {
+        if name == "print" {
+            let text = match (args.first().map(Value::deref), self.beliefs) {
+                (Some(Value::Belief(belief)), BeliefFormat::Plot) => belief.plot(),
+                (Some(Value::Belief(belief)), BeliefFormat::Json) => belief.to_json().to_string(),
+                (value, _) => value.map(|v| v.to_string()).unwrap_or_default(),
+            };
+            if writeln!(self.out, "{}", text).is_err() {
+                return panic_at(span, "failed to write to stdout");
+            }
+            return Ok(Value::Unit);
+        }
+        if name == "panic" {
+            let msg = args.first().map(|v| v.to_string()).unwrap_or_default();
+            return panic_at(span, &msg);
+        }
+        if name == "Some" {
+            let value = args.into_iter().next().unwrap_or(Value::Unit);
+            return Ok(Value::Option(Some(Box::new(value))));
+        }
+        if name == "thread::spawn" {
+            let mut args = args.into_iter();
+            return match args.next().map(|f| f.deref()) {
+                Some(Value::Fn(function)) => {
+                    self.threads.push(Thread::Pending(function, args.collect()));
+                    Ok(Value::Thread(self.threads.len() - 1))
+                }
+                _ => panic_at(span, "`thread::spawn` expects a function"),
+            };
+        }
+        if let ("channel::bounded", [capacity]) = (name, args.as_slice()) {
+            return match capacity.deref() {
+                Value::Int(n) if n > 0 => {
+                    self.channels.push(Channel::new(Some(n as usize)));
+                    Ok(Value::Receiver(self.channels.len() - 1))
+                }
+                n => panic_at(
+                    span,
+                    &format!("channel capacity must be positive, but it is {}", n),
+                ),
+            };
+        }
+        if name == "channel::unbounded" {
+            self.channels.push(Channel::new(None));
+            return Ok(Value::Receiver(self.channels.len() - 1));
+        }
+        if name == "env::args_len" {
+            return Ok(Value::Int(self.args.len() as i64));
+        }
+        if let ("env::arg", [index]) = (name, args.as_slice()) {
+            let len = self.args.len();
+            return match index.deref() {
+                Value::Int(i) if (0..len as i64).contains(&i) => {
+                    Ok(Value::Str(self.args[i as usize].clone()))
+                }
+                index => panic_at(
+                    span,
+                    &format!(
+                        "argument index out of range: there are {} arguments but the index is {}",
+                        len, index
+                    ),
+                ),
+            };
+        }
+
+        if let Some(io_name) = name.strip_prefix("io::") {
+            return self.call_io(io_name, &args, span);
+        }
+        match (name, args.as_slice()) {
+            ("Vec::new", [arena]) | ("Map::new", [arena]) | ("Vec::with_capacity", [arena, _]) => {
+                let Value::Arena(depth) = arena.deref() else {
+                    return panic_at(span, &format!("`{}` expects an arena", name));
+                };
+                return Ok(match args.get(1).map(Value::deref) {
+                    None if name == "Map::new" => Value::Map(depth, Table::default()),
+                    None => Value::Vec(depth, ArenaVec::with_capacity(0)),
+                    Some(Value::Int(n)) if n >= 0 => {
+                        Value::Vec(depth, ArenaVec::with_capacity(n as usize))
+                    }
+                    Some(n) => {
+                        return panic_at(span, &format!("capacity overflow: `{}`", n));
+                    }
+                });
+            }
+            ("StringBuf::new" | "StringBuf::from" | "StringBuf::from_utf8", [arena, ..]) => {
+                let Value::Arena(depth) = arena.deref() else {
+                    return panic_at(span, &format!("`{}` expects an arena", name));
+                };
+                let text = match args.get(1).map(Value::deref) {
+                    None => String::new(),
+                    Some(Value::Str(s)) => s,
+                    Some(Value::Vec(_, bytes)) => {
+                        let bytes: Vec<i64> = bytes
+                            .items()
+                            .iter()
+                            .map(|byte| match byte {
+                                Value::Int(n) => *n,
+                                _ => -1,
+                            })
+                            .collect();
+                        string::from_utf8(&bytes).map_err(|msg| panic_flow(span, &msg))?
+                    }
+                    Some(other) => {
+                        return panic_at(
+                            span,
+                            &format!("invalid argument `{}` to `{}`", other, name),
+                        )
+                    }
+                };
+                return self.new_string(depth, &text, span);
+            }
+            _ => {}
+        }
+        if let ("atomic::fence", [ordering]) = (name, args.as_slice()) {
+            atomic::fence(atomic_ordering(ordering, "fence", false, span)?);
+            return Ok(Value::Unit);
+        }
+        if let ("Mutex::new" | "RwLock::new", [value]) = (name, args.as_slice()) {
+            let lock = if name == "Mutex::new" {
+                "Mutex"
+            } else {
+                "RwLock"
+            };
+            return Ok(Value::Lock(lock, Rc::new(Lock::new(value.deref()))));
+        }
+        if let ("ptr::from_ref" | "ptr::from_mut", [value]) = (name, args.as_slice()) {
+            return match value {
+                Value::Ref(slot) => Ok(Value::Ptr(Pointer::to(slot.clone(), false))),
+                other => panic_at(span, &format!("invalid argument `{}` to `{}`", other, name)),
+            };
+        }
+        if let Some((ty, constructor)) = name.split_once("::") {
+            if let (Some(ty), "new", [value]) =
+                (atomic::atomic_type(ty), constructor, args.as_slice())
+            {
+                return match value.deref() {
+                    Value::Int(n) => Ok(Value::Atomic(Rc::new(Atomic::new(ty, n)))),
+                    other => panic_at(span, &format!("invalid argument `{}` to `{}`", other, name)),
+                };
+            }
+            if let Some(ty) = simd::vector_type(ty) {
+                return vector(ty, constructor, &args)
+                    .map(Value::Simd)
+                    .ok_or_else(|| panic_flow(span, &format!("invalid arguments to `{}`", name)));
+            }
+        }
+
+        if name == "observe" {
+            let args: Vec<Value> = args.iter().map(Value::deref).collect();
+            let likelihood = match args.as_slice() {
+                [Value::Bool(condition)] => f64::from(u8::from(*condition)),
+                [Value::Belief(belief), Value::Float(x)] => belief.likelihood(*x),
+                [Value::Belief(belief), Value::Bool(x)] => {
+                    belief.likelihood(f64::from(u8::from(*x)))
+                }
+                _ => return panic_at(span, "invalid arguments to `observe`"),
+            };
+            return match &mut self.weight {
+                Some(weight) => {
+                    *weight *= likelihood;
+                    Ok(Value::Unit)
+                }
+                None => panic_at(span, "`observe` outside `expect`"),
+            };
+        }
+        if builtins::is_belief_op(name) {
+            let beliefs: Vec<Belief> = args
+                .iter()
+                .filter_map(|v| match v.deref() {
+                    Value::Belief(b) => Some(b),
+                    _ => None,
+                })
+                .collect();
+            let result = match (name, beliefs.as_slice()) {
+                ("expectation", [belief]) => {
+                    let mean = match self.samples {
+                        Some(samples) => belief.estimate(&mut self.rng, samples),
+                        None => belief.expectation(),
+                    };
+                    return Ok(Value::Float(mean));
+                }
+                ("sample", [belief]) => {
+                    let x = belief.sample(&mut self.rng);
+                    return Ok(match belief.value_type() {
+                        "bool" => Value::Bool(x != 0.0),
+                        _ => Value::Float(x),
+                    });
+                }
+                ("update", [prior, evidence]) => prior.update(evidence),
+                ("marginalize", [condition, if_true, if_false]) => {
+                    Belief::marginalize(condition, if_true, if_false)
+                }
+                _ => Err(format!("invalid arguments to `{}`", name)),
+            };
+            return result
+                .map(Value::Belief)
+                .or_else(|msg| panic_at(span, &msg));
+        }
+
+        let ints: Option<Vec<i64>> = args
+            .iter()
+            .map(|v| match v.deref() {
+                Value::Int(n) => Some(n),
+                _ => None,
+            })
+            .collect();
+        if builtins::is_int_op(name) {
+            let numeric = self
+                .info
+                .numeric
+                .get(&span)
+                .copied()
+                .unwrap_or(Numeric::I64);
+            return match ints.and_then(|ints| builtins::int_op_value(name, &ints, numeric)) {
+                Some(n) if builtins::is_checked(name) => {
+                    Ok(Value::Option(n.map(|n| Box::new(Value::Int(n)))))
+                }
+                Some(Some(n)) => Ok(Value::Int(n)),
+                _ => panic_at(span, &format!("invalid arguments to `{}`", name)),
+            };
+        }
+        if let Some(result) = ints.and_then(|ints| builtins::int_intrinsic(name, &ints)) {
+            return Ok(Value::Int(result));
+        }
+        let floats: Option<Vec<f64>> = args
+            .iter()
+            .map(|v| match v.deref() {
+                Value::Float(x) => Some(x),
+                _ => None,
+            })
+            .collect();
+        if let Some(result) = floats.and_then(|floats| builtins::float_intrinsic(name, &floats)) {
+            return Ok(Value::Float(result));
+        }
+
+        panic_at(span, &format!("cannot find function `{}`", name))
+    }
+
+    /// Call the `std::io` builtin `io::name`, panicking if it fails
+    fn call_io(&mut self, name: &str, args: &[Value], span: Span) -> ExecResult {
+        let text = |i: usize| {
+            args.get(i)
+                .map(|v| v.deref().to_string())
+                .unwrap_or_default()
+        };
+        let lines = |lines: Result<Vec<String>, String>| {
+            lines.map(|lines| Value::Array(lines.into_iter().map(Value::Str).collect()))
+        };
+        let result = match name {
+            "read_file" | "write_file" | "append_file" | "exists" | "lines" if !self.files => Err(
+                format!("cannot call `io::{}`: file access is disabled", name),
+            ),
+            "read_file" => io::read_file(&text(0)).map(Value::Str),
+            "write_file" => io::write_file(&text(0), &text(1), false).map(|()| Value::Unit),
+            "append_file" => io::write_file(&text(0), &text(1), true).map(|()| Value::Unit),
+            "exists" => Ok(Value::Bool(io::exists(&text(0)))),
+            "lines" => lines(io::Lines::open(&text(0)).and_then(Iterator::collect)),
+            "stdin_lines" => lines(io::Lines::new(std::io::stdin().lock(), "stdin").collect()),
+            "read_line" => io::read_line(&mut std::io::stdin().lock()).map(Value::Str),
+            "print" => write!(self.out, "{}", text(0))
+                .map(|()| Value::Unit)
+                .map_err(|_| "failed to write to stdout".to_string()),
+            "eprint" => write!(std::io::stderr(), "{}", text(0))
+                .map(|()| Value::Unit)
+                .map_err(|_| "failed to write to stderr".to_string()),
+            "flush" => self
+                .out
+                .flush()
+                .map(|()| Value::Unit)
+                .map_err(|_| "failed to flush stdout".to_string()),
+            _ => Err(format!("cannot find function `io::{}`", name)),
+        };
+        result.or_else(|msg| panic_at(span, &msg))
+    }
+
+    /// Charge an allocation of `bytes` aligned to `align` at `span` to the
+    /// targeted or innermost arena, taking the block from the arena's
+    /// allocator if it has one
+    fn allocate(&mut self, bytes: u64, align: u64, span: Span) -> Result<(), Flow> {
+        let frame = match self.alloc_target {
+            Some(depth) => self.arenas.get_mut(depth),
+            None => self.arenas.last_mut(),
+        };
+        if let Some(profile) = &mut self.heap_profile {
+            profile.record(span.line, frame.as_ref().map(|f| f.span), bytes);
+        }
+        let Some(frame) = frame else {
+            return Ok(());
+        };
+        frame.charge(bytes);
+        if let Some(meter) = &mut self.meter {
+            meter.memory += bytes;
+        }
+        let arena = frame.span;
+        match frame.allocator.clone() {
+            Some(allocator) if bytes > 0 && !self.allocating => {
+                self.allocate_from(&allocator, arena, bytes, align, span)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Ask the allocator of the arena at `arena` for a block of `bytes`
+    /// aligned to `align`
+    fn allocate_from(
+        &mut self,
+        allocator: &arena::Allocator,
+        arena: Span,
+        bytes: u64,
+        align: u64,
+        span: Span,
+    ) -> Result<(), Flow> {
+        let method = format!("{}::{}", allocator.ty, alloc::ALLOCATE);
+        let args = vec![
+            Value::Ref(allocator.slot.clone()),
+            Value::Int(bytes as i64),
+            Value::Int(align as i64),
+        ];
+        self.allocating = true;
+        let block = self.call(&method, args, span);
+        self.allocating = false;
+        let problem = match block?.deref() {
+            Value::Option(Some(address)) => match *address {
+                Value::Int(address) => alloc::check_block(address, bytes as i64, align as i64),
+                other => Err(format!("returned `{}` instead of an address", other)),
+            },
+            _ => Err(format!("is out of memory (allocating {} bytes)", bytes)),
+        };
+        problem.map_err(|problem| {
+            let msg = format!(
+                "the allocator of the arena at {}:{} {}",
+                arena.line, arena.column, problem
+            );
+            panic_flow(span, &msg)
+        })
+    }
+
+    /// Bytes `value` occupies inline, using the checker's struct layouts
+    fn value_size(&self, value: &Value) -> u64 {
+        match value {
+            Value::Int(_) | Value::Float(_) => 8,
+            Value::Bool(_) => 1,
+            Value::Unit => 0,
+            Value::Str(_) => Layout::FAT_POINTER.size,
+            Value::Array(items) => items.iter().map(|v| self.value_size(v)).sum(),
+            Value::Enum(name, ..) => self.info.enums.get(name).map_or(8, |variants| {
+                let discriminants: Vec<i64> = variants.iter().map(|(_, d)| *d).collect();
+                layout::enum_layout(&discriminants).size
+            }),
+            Value::Struct(name, _) => self.info.layouts.get(name).map_or(0, |l| l.layout.size),
+            // A tag word and the value
+            Value::Option(value) => 8 + value.as_ref().map_or(0, |value| self.value_size(value)),
+            Value::Fn(_)
+            | Value::Belief(_)
+            | Value::Ref(_)
+            | Value::Arena(_)
+            | Value::Checkpoint(..)
+            | Value::Thread(_)
+            | Value::Sender(_)
+            | Value::Receiver(_)
+            | Value::Atomic(_)
+            | Value::Lock(..)
+            | Value::Guard(_)
+            | Value::Ptr(_) => Layout::POINTER.size,
+            // Buffer pointer, length, capacity and arena
+            Value::Vec(..) | Value::Map(..) | Value::StringBuf(..) => 4 * Layout::POINTER.size,
+            Value::Simd(vector) => vector.ty.size(),
+            // Both ends with their tags, and whether the last is included
+            Value::Range(..) => 40,
+        }
+    }
+
+    /// Alignment `value` needs inline, using the checker's struct layouts
+    fn value_align(&self, value: &Value) -> u64 {
+        match value {
+            Value::Bool(_) | Value::Unit => 1,
+            Value::Array(items) => items.first().map_or(1, |item| self.value_align(item)),
+            Value::Struct(name, _) => self.info.layouts.get(name).map_or(1, |l| l.layout.align),
+            Value::Simd(vector) => vector.ty.size(),
+            _ => Layout::POINTER.align,
+        }
+    }
+
+    fn exec_block(&mut self, block: &Block, env: &mut Vec<Scope>) -> ExecResult {
+        env.push(Scope::new());
+        let result = self.exec_block_inner(block, env);
+        self.end_scope(env.pop());
+        result
+    }
+
+    fn cover_branch(&mut self, span: Span, taken: bool) {
+        if let Some(coverage) = &mut self.coverage {
+            coverage.hit_branch(span, taken);
+        }
+    }
+
+    /// Bytes of the stack in use below where the running function was
+    /// called from
+    fn stack_used(&self) -> usize {
+        self.stack_base.abs_diff(stack_address())
+    }
+
+    /// Free the variables of a scope that ended; under the address
+    /// sanitizer, those still pointed into are poisoned
+    fn end_scope(&mut self, scope: Option<Scope>) {
+        if !self.sanitizers.address {
+            return;
+        }
+        for slot in scope.iter().flat_map(HashMap::values) {
+            if Rc::strong_count(slot) > 1 {
+                self.quarantine.poison(slot);
+            }
+        }
+    }
+
+    /// Whether the sanitizers let `pointer` be dereferenced
+    fn check_pointer(&self, pointer: &Pointer, span: Span) -> Result<(), Flow> {
+        let violation = match &pointer.arena {
+            Some((live, arena)) if self.sanitizers.arena && live.upgrade().is_none() => {
+                Some(Violation::UseAfterReset { arena: *arena })
+            }
+            None if self.sanitizers.address && self.quarantine.is_poisoned(&pointer.slot) => {
+                Some(Violation::UseAfterScope)
+            }
+            _ if self.sanitizers.address
+                && pointer
+                    .target(&mut pointer.slot.borrow_mut(), span)
+                    .is_err() =>
+            {
+                let (offset, size) = pointer.extent(|value| self.value_size(value));
+                Some(Violation::OutOfBounds {
+                    offset,
+                    size,
+                    arena: pointer.arena.is_some(),
+                })
+            }
+            _ => None,
+        };
+        match violation {
+            Some(violation) => Err(panic_flow(span, &violation.to_string())),
+            None => Ok(()),
+        }
+    }
+
+    /// Run the statements of `block`, then whatever it deferred, latest
+    /// first, however the statements ended (unless they panicked and the
+    /// strategy is to abort). Deferred expressions see the variables in
+    /// scope at their `defer`, so later shadowing is invisible to them but
+    /// assignments are not
+    fn exec_block_inner(&mut self, block: &Block, env: &mut Vec<Scope>) -> ExecResult {
+        let mut deferred = Vec::new();
+        let mut result = self.exec_stmts(block, env, &mut deferred);
+        let abort = match result {
+            Err(Flow::Panic(_)) => self.panic == PanicStrategy::Abort,
+            Err(Flow::Exceeded(_)) => true,
+            _ => false,
+        };
+        if abort {
+            return result;
+        }
+        for (expr, mut scopes) in deferred.into_iter().rev() {
+            if let Err(panic @ Flow::Panic(_)) = self.eval(expr, &mut scopes) {
+                // The first panic is the one reported
+                if !matches!(result, Err(Flow::Panic(_))) {
+                    result = Err(panic);
+                }
+            }
+        }
+        result
+    }
+
+    fn exec_stmts<'b>(
+        &mut self,
+        block: &'b Block,
+        env: &mut Vec<Scope>,
+        deferred: &mut Vec<(&'b Expr, Vec<Scope>)>,
+    ) -> ExecResult {
+        for stmt in &block.stmts {
+            if let Some(coverage) = &mut self.coverage {
+                coverage.hit_line(stmt.span().line);
+            }
+            match stmt {
+                Stmt::Let(let_stmt) => {
+                    let value = match &let_stmt.value {
+                        Some(value) => self.eval(value, env)?,
+                        None => Value::Unit,
+                    };
+                    declare(env, let_stmt.name, value);
+                }
+                Stmt::Const(item) => {
+                    let value = self.eval(&item.value, env)?;
+                    declare(env, item.name, value);
+                }
+                Stmt::Expr(expr) => {
+                    self.eval(expr, env)?;
+                }
+                Stmt::Defer(expr) => deferred.push((expr, env.clone())),
+            }
+        }
+        match &block.tail {
+            Some(tail) => {
+                if let Some(coverage) = &mut self.coverage {
+                    coverage.hit_line(self.exprs[*tail].span.line);
+                }
+                self.eval(&self.exprs[*tail], env)
+            }
+            None => Ok(Value::Unit),
+        }
+    }
+
+    fn eval(&mut self, expr: &Expr, env: &mut Vec<Scope>) -> ExecResult {
+        // Nested expressions use the stack too, between calls; they may go
+        // on past the limit of calls into half of what it leaves
+        if self.stack_used() > self.stack_limit + STACK_RESERVE / 2 {
+            return panic_at(expr.span, "stack overflow");
+        }
+        let value = self.eval_kind(expr, env)?;
+        // A value of type `any` converted to a type of its own
+        let casts = &self.info.any_casts;
+        let cast = if casts.is_empty() {
+            None
+        } else {
+            casts.get(&expr.span)
+        };
+        if let Some(ty) = cast {
+            if !self.has_type(&value, ty) {
+                return panic_at(
+                    expr.span,
+                    &format!(
+                        "expected a value of type `{}`, but the `any` holds a `{}`",
+                        ty,
+                        type_of(&value)
+                    ),
+                );
+            }
+        }
+        Ok(value)
+    }
+
+    /// Whether `value` is of type `ty`, as far as it records
+    fn has_type(&self, value: &Value, ty: &Type) -> bool {
+        match (value, ty) {
+            (_, Type::Any | Type::Unknown) => true,
+            (_, Type::Newtype(name)) => self.has_type(value, &self.info.newtypes[name]),
+            (Value::Ref(slot), Type::Ref(_, inner)) => self.has_type(&slot.borrow(), inner),
+            (Value::Array(items), Type::Array(elem, len)) => {
+                items.len() == *len && items.iter().all(|item| self.has_type(item, elem))
+            }
+            (Value::Vec(_, items), Type::Vec(elem)) => {
+                items.items().iter().all(|item| self.has_type(item, elem))
+            }
+            (Value::Option(value), Type::Option(inner)) => value
+                .as_ref()
+                .is_none_or(|value| self.has_type(value, inner)),
+            (Value::Enum(name, ..), Type::Enum(ty))
+            | (Value::Struct(name, _), Type::Struct(ty)) => name == ty,
+            (Value::Int(_), Type::Int)
+            | (Value::Float(_), Type::Float)
+            | (Value::Bool(_), Type::Bool)
+            | (Value::Str(_), Type::Str)
+            | (Value::Unit, Type::Unit)
+            | (Value::Fn(_), Type::Fn(..))
+            | (Value::Belief(_), Type::Belief(_))
+            | (Value::Arena(_), Type::Arena)
+            | (Value::Checkpoint(..), Type::Checkpoint)
+            | (Value::Map(..), Type::Map(..))
+            | (Value::StringBuf(..), Type::StringBuf)
+            | (Value::Simd(_), Type::Simd(_))
+            | (Value::Thread(_), Type::Thread(_))
+            | (Value::Sender(_), Type::Sender(_))
+            | (Value::Receiver(_), Type::Receiver(_))
+            | (Value::Atomic(_), Type::Atomic(_))
+            | (Value::Lock("Mutex", _), Type::Mutex(_))
+            | (Value::Lock("RwLock", _), Type::RwLock(_))
+            | (Value::Guard(_), Type::Guard(..))
+            | (Value::Ptr(_), Type::Ptr(..)) => true,
+            _ => false,
+        }
+    }
+
+    fn eval_kind(&mut self, expr: &Expr, env: &mut Vec<Scope>) -> ExecResult {
+        if let Some(meter) = &mut self.meter {
+            meter.step(expr.span)?;
+        }
+        match &expr.kind {
+            ExprKind::Int(n, _) => Ok(Value::Int(*n)),
+            ExprKind::Float(x, _) => self.narrow(Value::Float(*x), expr.span, "round"),
+            ExprKind::Bool(b) => Ok(Value::Bool(*b)),
+            ExprKind::Str(s) => Ok(Value::Str(s.to_string())),
+            ExprKind::Path(segments) => self.eval_path(segments, expr.span, env),
+            ExprKind::Unary(op, operand) => self.eval_unary(*op, *operand, expr.span, env),
+            ExprKind::Binary(op, lhs, rhs) => {
+                let left = self.eval(&self.exprs[*lhs], env)?.deref();
+                match (op, &left) {
+                    (BinaryOp::And, Value::Bool(false)) => return Ok(Value::Bool(false)),
+                    (BinaryOp::Or, Value::Bool(true)) => return Ok(Value::Bool(true)),
+                    _ => {}
+                }
+                let right = self.eval(&self.exprs[*rhs], env)?.deref();
+                let value = self.binary(*op, left, right, expr.span)?;
+                self.narrow(value, expr.span, overflow_verb(*op))
+            }
+            ExprKind::Cast(value, _) => self.eval_cast(*value, expr.span, env),
+            ExprKind::Assign(target, value) => {
+                let value = self.eval(&self.exprs[*value], env)?;
+                self.assign(&self.exprs[*target], value, env)?;
+                Ok(Value::Unit)
+            }
+            ExprKind::Call(callee, args) => self.eval_call(*callee, args, expr.span, env),
+            ExprKind::MethodCall(receiver, method, args) => {
+                self.eval_method_call(&self.exprs[*receiver], method, args, expr.span, env)
+            }
+            ExprKind::Index(base, index) => self.eval_index(*base, *index, expr.span, env),
+            ExprKind::Field(base, field) => self.eval_field(*base, *field, expr.span, env),
+            ExprKind::StructLit(name, inits) => self.eval_struct_lit(*name, inits, expr.span, env),
+            ExprKind::Distribution(name, args) => {
+                self.eval_distribution(*name, args, expr.span, env)
+            }
+            ExprKind::Array(elements) => {
+                let mut items = Vec::with_capacity(elements.len());
+                for element in elements {
+                    items.push(self.eval(element, env)?.deref());
+                }
+                let array = Value::Array(items);
+                self.allocate(self.value_size(&array), self.value_align(&array), expr.span)?;
+                Ok(array)
+            }
+            ExprKind::ArrayRepeat(value, len) => {
+                self.eval_array_repeat(*value, *len, expr.span, env)
+            }
+            ExprKind::If(cond, then_block, else_branch) => {
+                match self.eval(&self.exprs[*cond], env)?.deref() {
+                    Value::Bool(true) => {
+                        self.cover_branch(expr.span, true);
+                        self.exec_block(then_block, env)
+                    }
+                    Value::Bool(false) => {
+                        self.cover_branch(expr.span, false);
+                        match else_branch {
+                            Some(else_expr) => self.eval(&self.exprs[*else_expr], env),
+                            None => Ok(Value::Unit),
+                        }
+                    }
+                    other => panic_at(
+                        self.exprs[*cond].span,
+                        &format!("expected `bool`, found `{}`", other),
+                    ),
+                }
+            }
+            ExprKind::While(cond, body) => self.eval_while(*cond, body, expr.span, env),
+            ExprKind::For(name, iterable, body) => {
+                self.eval_for(*name, &self.exprs[*iterable], body, env)
+            }
+            ExprKind::Match(scrutinee, arms) => {
+                let value = self.eval(&self.exprs[*scrutinee], env)?;
+                self.eval_match(&value, arms, expr.span, env)
+            }
+            ExprKind::Range(start, end, inclusive) => Ok(Value::Range(
+                self.eval_range_end(*start, env)?,
+                self.eval_range_end(*end, env)?,
+                *inclusive,
+            )),
+            ExprKind::Block(block) | ExprKind::Unsafe(block) => self.exec_block(block, env),
+            ExprKind::Arena(name, allocator, block) => {
+                self.eval_arena(*name, *allocator, block, expr.span, env)
+            }
+            ExprKind::Return(value) => {
+                let value = match value {
+                    Some(value) => self.eval(&self.exprs[*value], env)?,
+                    None => Value::Unit,
+                };
+                Err(Flow::Return(value))
+            }
+            ExprKind::Become(call) => {
+                let ExprKind::Call(callee, args) = &self.exprs[*call].kind else {
+                    return panic_at(expr.span, "`become` needs a function call");
+                };
+                let mut values = Vec::with_capacity(args.len());
+                for arg in args {
+                    values.push(self.eval(arg, env)?);
+                }
+                let name = self.callee(&self.exprs[*callee], expr.span, env)?;
+                Err(Flow::Become(name, values))
+            }
+            // There is no machine to run the instructions on
+            ExprKind::Asm(..) => panic_at(
+                expr.span,
+                "inline assembly cannot run in the interpreter; build the program instead",
+            ),
+        }
+    }
+
+    fn eval_cast(&mut self, value: ExprId, span: Span, env: &mut Vec<Scope>) -> ExecResult {
+        let value = match self.eval(&self.exprs[value], env)?.deref() {
+            Value::Int(n) | Value::Enum(_, _, n) => Scalar::Int(n),
+            Value::Bool(b) => Scalar::Int(i64::from(b)),
+            Value::Float(x) => Scalar::Float(x),
+            other => return panic_at(span, &format!("cannot cast `{}`", other)),
+        };
+        let numeric = self.info.numeric.get(&span);
+        match numeric.map_or(value, |numeric| numeric.cast(value)) {
+            Scalar::Int(n) => Ok(Value::Int(n)),
+            Scalar::Float(x) => Ok(Value::Float(x)),
+        }
+    }
+
+    fn eval_call(
+        &mut self,
+        callee: ExprId,
+        args: &[Expr],
+        span: Span,
+        env: &mut Vec<Scope>,
+    ) -> ExecResult {
+        if let (ExprKind::Path(segments), [value]) = (&self.exprs[callee].kind, args) {
+            if segments.len() == 1
+                && segments[0] == "expect"
+                && lookup(env, segments[0]).is_none()
+                && !self.functions.contains_key("expect")
+            {
+                return self.expect(value, env, span);
+            }
+        }
+        let mut values = Vec::with_capacity(args.len());
+        for arg in args {
+            values.push(self.eval(arg, env)?);
+        }
+        let name = self.callee(&self.exprs[callee], span, env)?;
+        // A newtype is represented like the value it wraps
+        if self.info.newtypes.contains_key(&name) && values.len() == 1 {
+            return Ok(values.remove(0));
+        }
+        self.call(&name, values, span)
+    }
+
+    fn eval_field(
+        &mut self,
+        base: ExprId,
+        field: Symbol,
+        span: Span,
+        env: &mut Vec<Scope>,
+    ) -> ExecResult {
+        match self.eval(&self.exprs[base], env)?.deref() {
+            Value::Struct(_, fields) => fields
+                .into_iter()
+                .find(|(name, _)| *name == field)
+                .map(|(_, value)| value)
+                .ok_or_else(|| panic_flow(span, &format!("no field `{}`", field))),
+            value if field == "0" => Ok(value),
+            other => panic_at(span, &format!("no field `{}` on `{}`", field, other)),
+        }
+    }
+
+    fn eval_while(
+        &mut self,
+        cond: ExprId,
+        body: &Block,
+        span: Span,
+        env: &mut Vec<Scope>,
+    ) -> ExecResult {
+        loop {
+            match self.eval(&self.exprs[cond], env)?.deref() {
+                Value::Bool(true) => {
+                    self.cover_branch(span, true);
+                    self.exec_block(body, env)?;
+                }
+                Value::Bool(false) => {
+                    self.cover_branch(span, false);
+                    break;
+                }
+                other => {
+                    return panic_at(
+                        self.exprs[cond].span,
+                        &format!("expected `bool`, found `{}`", other),
+                    )
+                }
+            }
+        }
+        Ok(Value::Unit)
+    }
+
+    fn eval_unary(
+        &mut self,
+        op: UnaryOp,
+        operand: ExprId,
+        span: Span,
+        env: &mut Vec<Scope>,
+    ) -> ExecResult {
+        match op {
+            UnaryOp::Ref | UnaryOp::RefMut => match self.place(&self.exprs[operand], env) {
+                Some(slot) => Ok(Value::Ref(slot)),
+                None => {
+                    let value = self.eval(&self.exprs[operand], env)?;
+                    Ok(Value::Ref(Rc::new(RefCell::new(value))))
+                }
+            },
+            UnaryOp::Neg => {
+                let value = match self.eval(&self.exprs[operand], env)?.deref() {
+                    Value::Int(n) => match self.overflow {
+                        OverflowMode::Wrap => Value::Int(n.wrapping_neg()),
+                        OverflowMode::Trap => n
+                            .checked_neg()
+                            .map(Value::Int)
+                            .ok_or_else(|| panic_flow(span, "attempt to negate with overflow"))?,
+                    },
+                    Value::Float(x) => Value::Float(-x),
+                    other => return panic_at(span, &format!("cannot negate `{}`", other)),
+                };
+                self.narrow(value, span, "negate")
+            }
+            UnaryOp::Not => match self.eval(&self.exprs[operand], env)?.deref() {
+                Value::Bool(b) => Ok(Value::Bool(!b)),
+                other => panic_at(span, &format!("cannot apply `!` to `{}`", other)),
+            },
+            UnaryOp::Deref => match self.eval(&self.exprs[operand], env)?.deref() {
+                Value::Ptr(pointer) => {
+                    self.check_pointer(&pointer, span)?;
+                    pointer.read(span)
+                }
+                other => panic_at(span, &format!("cannot dereference `{}`", other)),
+            },
+        }
+    }
+
+    fn eval_index(
+        &mut self,
+        base: ExprId,
+        index: ExprId,
+        span: Span,
+        env: &mut Vec<Scope>,
+    ) -> ExecResult {
+        let base = self.eval(&self.exprs[base], env)?.deref();
+        let index = self.eval(&self.exprs[index], env)?.deref();
+        match (base, index) {
+            (Value::Array(items), Value::Int(i)) => index_array(&items, i, span).cloned(),
+            (Value::Vec(_, items), Value::Int(i)) => index_array(items.items(), i, span).cloned(),
+            (Value::Array(items), Value::Range(start, end, inclusive)) => {
+                slice_array(&items, (start, end, inclusive), span)
+            }
+            (Value::Vec(_, items), Value::Range(start, end, inclusive)) => {
+                slice_array(items.items(), (start, end, inclusive), span)
+            }
+            (other, _) => panic_at(span, &format!("cannot index into `{}`", other)),
+        }
+    }
+
+    fn eval_struct_lit(
+        &mut self,
+        name: Symbol,
+        inits: &[FieldInit],
+        span: Span,
+        env: &mut Vec<Scope>,
+    ) -> ExecResult {
+        let mut values = HashMap::new();
+        for init in inits {
+            values.insert(init.name.as_str(), self.eval(&init.value, env)?.deref());
+        }
+        let order = self
+            .info
+            .structs
+            .get(name.as_str())
+            .map(Vec::as_slice)
+            .unwrap_or(&[]);
+        let fields = order
+            .iter()
+            .filter_map(|(field, _)| {
+                let value = values.remove(field.as_str())?;
+                Some((field.clone(), value))
+            })
+            .collect();
+        let size = self
+            .info
+            .layouts
+            .get(name.as_str())
+            .map_or(0, |l| l.layout.size);
+        let align = self
+            .info
+            .layouts
+            .get(name.as_str())
+            .map_or(1, |l| l.layout.align);
+        self.allocate(size, align, span)?;
+        Ok(Value::Struct(name.to_string(), fields))
+    }
+
+    fn eval_distribution(
+        &mut self,
+        name: Symbol,
+        args: &[Expr],
+        span: Span,
+        env: &mut Vec<Scope>,
+    ) -> ExecResult {
+        let mut params = Vec::with_capacity(args.len());
+        for arg in args {
+            match self.eval(arg, env)?.deref() {
+                Value::Float(x) => params.push(x),
+                other => return panic_at(arg.span, &format!("expected `f64`, found `{}`", other)),
+            }
+        }
+        let belief = match (name.as_str(), params.as_slice()) {
+            ("Normal", [mean, std_dev]) => Belief::normal(*mean, *std_dev),
+            ("Bernoulli", [p]) => Belief::bernoulli(*p),
+            _ => Err(format!("unknown distribution `{}`", name)),
+        };
+        let belief = match belief {
+            Ok(belief) => belief,
+            Err(msg) => return panic_at(span, &msg),
+        };
+        self.allocate(belief_size(&belief), 8, span)?;
+        Ok(Value::Belief(belief))
+    }
+
+    fn eval_array_repeat(
+        &mut self,
+        value: ExprId,
+        len: ExprId,
+        span: Span,
+        env: &mut Vec<Scope>,
+    ) -> ExecResult {
+        let value = self.eval(&self.exprs[value], env)?.deref();
+        match self.eval(&self.exprs[len], env)?.deref() {
+            Value::Int(n) if n >= 0 => {
+                let bytes = self.value_size(&value).saturating_mul(n as u64);
+                if let Some(meter) = &mut self.meter {
+                    meter.reserve(bytes, span)?;
+                }
+                let array = Value::Array(vec![value; n as usize]);
+                let align = self.value_align(&array);
+                self.allocate(self.value_size(&array), align, span)?;
+                Ok(array)
+            }
+            other => panic_at(
+                self.exprs[len].span,
+                &format!("invalid array length `{}`", other),
+            ),
+        }
+    }
+
+    fn eval_arena(
+        &mut self,
+        name: Option<Symbol>,
+        allocator: Option<ExprId>,
+        block: &Block,
+        span: Span,
+        env: &mut Vec<Scope>,
+    ) -> ExecResult {
+        let lent = match allocator {
+            Some(allocator) => Some(self.lend(&self.exprs[allocator], span, env)?),
+            None => None,
+        };
+        let timed = self.arena_profile.is_some();
+        let mut frame = arena::Frame::new(span, timed);
+        frame.allocator = lent.as_ref().map(|(allocator, _)| allocator.clone());
+        self.arenas.push(frame);
+        let target = self.alloc_target.take();
+        env.push(Scope::new());
+        if let Some(name) = name {
+            declare(env, name, Value::Arena(self.arenas.len() - 1));
+        }
+        let result = self.exec_block(block, env);
+        self.end_scope(env.pop());
+        self.alloc_target = target;
+        let frame = self.arenas.pop().expect("arena frame pushed above");
+        if let Some(meter) = &mut self.meter {
+            meter.memory -= frame.held;
+        }
+        if let Some(profile) = &mut self.arena_profile {
+            profile.record(frame);
+        }
+        let Some((lender, temporary)) = lent else {
+            return result;
+        };
+        if matches!(result, Err(Flow::Exceeded(_))) {
+            return result;
+        }
+        // The arena's blocks are all dead now, however it ended
+        let method = format!("{}::{}", lender.ty, alloc::RESET);
+        let reset = self.call(&method, vec![Value::Ref(lender.slot.clone())], span);
+        if let (Some(allocator), true) = (allocator, temporary) {
+            let updated = lender.slot.borrow().clone();
+            self.assign(&self.exprs[allocator], updated, env)?;
+        }
+        result.and_then(|value| reset.map(|_| value))
+    }
+
+    /// Lend the allocator `allocator` to the arena at `arena`. Projected
+    /// allocators (`self.buffer`) go through a temporary, which is true in
+    /// the result, written back when the arena ends
+    fn lend(
+        &mut self,
+        allocator: &Expr,
+        arena: Span,
+        env: &mut Vec<Scope>,
+    ) -> Result<(arena::Allocator, bool), Flow> {
+        let Some(ty) = self.info.allocators.get(&arena).cloned() else {
+            return Err(panic_flow(
+                allocator.span,
+                "this arena has no allocator type",
+            ));
+        };
+        let (slot, temporary) = match self.place(allocator, env) {
+            Some(slot) => {
+                let referenced = match &*slot.borrow() {
+                    Value::Ref(referenced) => Some(referenced.clone()),
+                    _ => None,
+                };
+                (referenced.unwrap_or(slot), false)
+            }
+            None => match self.eval(allocator, env)? {
+                Value::Ref(slot) => (slot, false),
+                value => (Rc::new(RefCell::new(value)), true),
+            },
+        };
+        Ok((arena::Allocator { ty, slot }, temporary))
+    }
+
+    /// Name of the function `callee` refers to, when called at `span`
+    fn callee(&mut self, callee: &Expr, span: Span, env: &mut Vec<Scope>) -> Result<String, Flow> {
+        match &callee.kind {
+            ExprKind::Path(segments)
+                if segments.len() == 1 && lookup(env, segments[0]).is_none() =>
+            {
+                Ok(segments[0].to_string())
+            }
+            _ => match self.eval(callee, env)?.deref() {
+                Value::Fn(name) => Ok(name),
+                other => Err(panic_flow(span, &format!("`{}` is not callable", other))),
+            },
+        }
+    }
+
+    fn eval_method_call(
+        &mut self,
+        receiver: &Expr,
+        method: &str,
+        args: &[Expr],
+        span: Span,
+        env: &mut Vec<Scope>,
+    ) -> ExecResult {
+        // Guards are borrowed by every method but `unlock`, instead of
+        // being moved out of their variable
+        let borrowed = match self.place(receiver, env) {
+            Some(slot) if method != "unlock" => {
+                Some(slot.borrow().deref()).filter(|value| matches!(value, Value::Guard(_)))
+            }
+            _ => None,
+        };
+        let value = match borrowed {
+            Some(guard) => guard,
+            None => self.eval(receiver, env)?.deref(),
+        };
+        let name = match &value {
+            Value::Struct(ty, _) | Value::Enum(ty, _, _) => format!("{}::{}", ty, method),
+            Value::Thread(id) if method == "join" => return self.join(*id, span),
+            Value::Sender(_) | Value::Receiver(_) => {
+                let mut values = Vec::with_capacity(args.len());
+                for arg in args {
+                    values.push(self.eval(arg, env)?.deref());
+                }
+                return self.channel_method(&value, method, values, span);
+            }
+            Value::Atomic(cell) => {
+                let mut values = Vec::with_capacity(args.len());
+                for arg in args {
+                    values.push(self.eval(arg, env)?.deref());
+                }
+                return atomic_method(cell, method, &values, span);
+            }
+            Value::Lock(_, lock) => {
+                let kind = match method {
+                    "lock" => GuardKind::Mutex,
+                    "read" => GuardKind::Read,
+                    "write" => GuardKind::Write,
+                    _ => {
+                        return panic_at(
+                            span,
+                            &format!("no method named `{}` found for `{}`", method, value),
+                        )
+                    }
+                };
+                return lock_method(lock, kind, span);
+            }
+            Value::Guard(guard) => {
+                let mut values = Vec::with_capacity(args.len());
+                for arg in args {
+                    values.push(self.eval(arg, env)?.deref());
+                }
+                return guard_method(guard, method, values, span);
+            }
+            // Pointers into an array point into its variable, if it has
+            // one, and pointers into a `Vec` into its arena
+            Value::Array(_) | Value::Vec(..) if matches!(method, "as_ptr" | "as_mut_ptr") => {
+                let slot = self
+                    .place(receiver, env)
+                    .unwrap_or_else(|| Rc::new(RefCell::new(value.clone())));
+                let mut pointer = Pointer::to(slot, true);
+                if let Value::Vec(depth, _) = value {
+                    pointer.arena = self
+                        .arenas
+                        .get(depth)
+                        .map(|frame| (Rc::downgrade(&frame.live), frame.span));
+                }
+                return Ok(Value::Ptr(pointer));
+            }
+            Value::Ptr(pointer) => {
+                let delta = match (method, args) {
+                    ("add" | "sub", [arg]) => match self.eval(arg, env)?.deref() {
+                        Value::Int(n) if method == "add" => n,
+                        Value::Int(n) => n.wrapping_neg(),
+                        other => return panic_at(arg.span, &format!("invalid offset `{}`", other)),
+                    },
+                    _ => {
+                        return panic_at(
+                            span,
+                            &format!("no method named `{}` found for a pointer", method),
+                        )
+                    }
+                };
+                let mut pointer = pointer.clone();
+                pointer.offset = pointer.offset.wrapping_add(delta);
+                return Ok(Value::Ptr(pointer));
+            }
+            Value::Arena(depth) if method == "alloc" => {
+                let target = self.alloc_target.replace(*depth);
+                let result = self.eval(&args[0], env);
+                self.alloc_target = target;
+                return result;
+            }
+            Value::Arena(depth) if method == "checkpoint" => {
+                let index = self.arenas[*depth].checkpoint();
+                return Ok(Value::Checkpoint(*depth, index));
+            }
+            Value::Arena(depth) if method == "reset_to" => {
+                let checkpoint = self.eval(&args[0], env)?.deref();
+                let Value::Checkpoint(of, index) = checkpoint else {
+                    return panic_at(span, &format!("`{}` is not a checkpoint", checkpoint));
+                };
+                if of != *depth {
+                    return panic_at(span, "this checkpoint was taken of another arena");
+                }
+                let Some(freed) = self.arenas[*depth].reset_to(index) else {
+                    return panic_at(
+                        span,
+                        "this checkpoint was discarded by a reset to an earlier one",
+                    );
+                };
+                if let Some(meter) = &mut self.meter {
+                    meter.memory -= freed;
+                }
+                return Ok(Value::Unit);
+            }
+            Value::Vec(..)
+            | Value::Map(..)
+            | Value::Str(_)
+            | Value::StringBuf(..)
+            | Value::Simd(_)
+            | Value::Option(_) => {
+                return self.eval_collection_method(receiver, value, method, args, span, env)
+            }
+            // A slice
+            Value::Array(items) if method == "len" => return Ok(Value::Int(items.len() as i64)),
+            other => {
+                return panic_at(
+                    span,
+                    &format!("no method named `{}` found for `{}`", method, other),
+                )
+            }
+        };
+        let kind = self.functions.get(&name).and_then(|f| f.receiver);
+
+        // By-reference receivers borrow the receiver's slot; projected
+        // receivers (`a.b.method()`) go through a temporary that is written
+        // back after a `&mut self` call
+        let (self_value, temp) = match kind {
+            Some(Receiver::Ref | Receiver::RefMut) => match self.place(receiver, env) {
+                Some(slot) => (Value::Ref(slot), None),
+                None => {
+                    let slot = Rc::new(RefCell::new(value));
+                    (Value::Ref(slot.clone()), Some(slot))
+                }
+            },
+            _ => (value, None),
+        };
+
+        let mut values = vec![self_value];
+        for arg in args {
+            values.push(self.eval(arg, env)?);
+        }
+        let result = self.call(&name, values, span)?;
+
+        if let (Some(Receiver::RefMut), Some(slot)) = (kind, temp) {
+            let updated = slot.borrow().clone();
+            if matches!(receiver.kind, ExprKind::Field(..) | ExprKind::Index(..)) {
+                self.assign(receiver, updated, env)?;
+            }
+        }
+        Ok(result)
+    }
+
+    /// Call a `Vec` or `Map` method. The collection is updated in its
+    /// variable's slot, or in a temporary written back to a projected
+    /// receiver
+    fn eval_collection_method(
+        &mut self,
+        receiver: &Expr,
+        value: Value,
+        method: &str,
+        args: &[Expr],
+        span: Span,
+        env: &mut Vec<Scope>,
+    ) -> ExecResult {
+        let mut values = Vec::with_capacity(args.len());
+        for arg in args {
+            values.push(self.eval(arg, env)?.deref());
+        }
+        let (slot, projected) = match self.place(receiver, env) {
+            Some(slot) => (deepest_slot(slot), false),
+            None => (Rc::new(RefCell::new(value)), true),
+        };
+        let result = self.collection_method(&mut slot.borrow_mut(), method, values, span)?;
+        if projected && matches!(receiver.kind, ExprKind::Field(..) | ExprKind::Index(..)) {
+            let updated = slot.borrow().clone();
+            self.assign(receiver, updated, env)?;
+        }
+        Ok(result)
+    }
+
+    fn collection_method(
+        &mut self,
+        collection: &mut Value,
+        method: &str,
+        args: Vec<Value>,
+        span: Span,
+    ) -> ExecResult {
+        if let Some(value) = self.text_method(collection, method, &args, span)? {
+            return Ok(value);
+        }
+        let key = |i: usize| {
+            args.get(i)
+                .and_then(Value::key)
+                .ok_or_else(|| panic_flow(span, "invalid map key"))
+        };
+        match (collection, method) {
+            (Value::Vec(depth, items), "push") => {
+                let item = args.into_iter().next().unwrap_or(Value::Unit);
+                let (size, align) = (self.value_size(&item), self.value_align(&item));
+                if let Some(capacity) = items.push(item) {
+                    self.allocate_in(*depth, capacity as u64 * size, align, span)?;
+                }
+                Ok(Value::Unit)
+            }
+            (Value::Vec(_, items), "pop") => items
+                .pop()
+                .ok_or_else(|| panic_flow(span, "pop from an empty `Vec`")),
+            (Value::Map(depth, table), "insert") => {
+                let key = key(0)?;
+                let value = args.into_iter().nth(1).unwrap_or(Value::Unit);
+                let size = self.value_size(&Value::from(&key)) + self.value_size(&value);
+                if let (_, Some(capacity)) = table.insert(key, value) {
+                    self.allocate_in(*depth, capacity as u64 * size, 8, span)?;
+                }
+                Ok(Value::Unit)
+            }
+            (Value::Map(_, table), "get") => {
+                let key = key(0)?;
+                match table.get(&key) {
+                    Some(value) => Ok(value.clone()),
+                    None => missing_key(&key, span),
+                }
+            }
+            (Value::Map(_, table), "remove") => {
+                let key = key(0)?;
+                match table.remove(&key) {
+                    Some(value) => Ok(value),
+                    None => missing_key(&key, span),
+                }
+            }
+            (Value::Map(_, table), "contains") => Ok(Value::Bool(table.get(&key(0)?).is_some())),
+            (Value::Option(value), "is_some") => Ok(Value::Bool(value.is_some())),
+            (Value::Option(value), "is_none") => Ok(Value::Bool(value.is_none())),
+            (Value::Option(Some(value)), "unwrap" | "expect" | "unwrap_or") => {
+                Ok((**value).clone())
+            }
+            (Value::Option(None), "unwrap") => {
+                panic_at(span, "called `Option::unwrap()` on a `None` value")
+            }
+            (Value::Option(None), "expect") => {
+                let message = args.first().map(Value::to_string).unwrap_or_default();
+                panic_at(span, &message)
+            }
+            (Value::Option(None), "unwrap_or") => {
+                Ok(args.into_iter().next().unwrap_or(Value::Unit))
+            }
+            (Value::Vec(_, items), "clear") => {
+                items.clear();
+                Ok(Value::Unit)
+            }
+            (Value::Map(_, table), "clear") => {
+                *table = Table::default();
+                Ok(Value::Unit)
+            }
+            (Value::StringBuf(depth, text), "push_str") => {
+                let Some(Value::Str(s)) = args.first() else {
+                    return panic_at(span, "`push_str` expects a `str`");
+                };
+                if let Some(capacity) = text.push_str(s) {
+                    self.allocate_in(*depth, capacity as u64, 1, span)?;
+                }
+                Ok(Value::Unit)
+            }
+            (Value::StringBuf(_, text), "clear") => {
+                text.clear();
+                Ok(Value::Unit)
+            }
+            (Value::StringBuf(_, text), "capacity") => Ok(Value::Int(text.capacity() as i64)),
+            (Value::Vec(_, items), "len") => Ok(Value::Int(items.len() as i64)),
+            (Value::Map(_, table), "len") => Ok(Value::Int(table.len() as i64)),
+            (Value::Vec(_, items), "capacity") => Ok(Value::Int(items.capacity() as i64)),
+            (Value::Map(_, table), "capacity") => Ok(Value::Int(table.capacity() as i64)),
+            (Value::Simd(vector), "to_array") => Ok(Value::Array(lane_values(vector))),
+            (Value::Simd(vector), "reduce_sum" | "reduce_min" | "reduce_max") => {
+                let op = match method {
+                    "reduce_sum" => Reduce::Sum,
+                    "reduce_min" => Reduce::Min,
+                    _ => Reduce::Max,
+                };
+                Ok(match vector.reduce(op) {
+                    Lane::Int(n) => Value::Int(n),
+                    Lane::Float(x) => Value::Float(x),
+                })
+            }
+            (Value::Simd(vector), "mul_add") => match args.as_slice() {
+                [Value::Simd(a), Value::Simd(b)] => Ok(Value::Simd(vector.mul_add(a, b))),
+                _ => panic_at(span, "`mul_add` expects two vectors"),
+            },
+            (other, _) => panic_at(
+                span,
+                &format!("no method named `{}` found for `{}`", method, other),
+            ),
+        }
+    }
+
+    /// Call a method that only reads the text of a `str` or `StringBuf`;
+    /// `None` if `method` is not one
+    fn text_method(
+        &mut self,
+        value: &Value,
+        method: &str,
+        args: &[Value],
+        span: Span,
+    ) -> Result<Option<Value>, Flow> {
+        let (text, arena) = match value {
+            Value::Str(s) => (s.as_str(), None),
+            Value::StringBuf(depth, text) => (text.as_str(), Some(*depth)),
+            _ => return Ok(None),
+        };
+        let int = |i: usize| match args.get(i) {
+            Some(Value::Int(n)) => Ok(*n),
+            _ => Err(panic_flow(span, "invalid byte offset")),
+        };
+        let value = match method {
+            "len" => Value::Int(text.len() as i64),
+            "char_count" => Value::Int(string::char_count(text) as i64),
+            "is_char_boundary" => Value::Bool(string::is_char_boundary(text, int(0)?)),
+            "chars" => Value::Array(text.chars().map(|c| Value::Int(c as i64)).collect()),
+            "bytes" => Value::Array(text.bytes().map(|b| Value::Int(b.into())).collect()),
+            "slice" => {
+                let part =
+                    string::slice(text, int(0)?, int(1)?).map_err(|msg| panic_flow(span, &msg))?;
+                match arena {
+                    Some(depth) => self.new_string(depth, part, span)?,
+                    None => Value::Str(part.to_string()),
+                }
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(value))
+    }
+
+    /// A `StringBuf` holding `text` in the arena at `depth`
+    fn new_string(&mut self, depth: usize, text: &str, span: Span) -> Result<Value, Flow> {
+        let mut buf = ArenaString::with_capacity(text.len());
+        if let Some(capacity) = buf.push_str(text) {
+            self.allocate_in(depth, capacity as u64, 1, span)?;
+        }
+        Ok(Value::StringBuf(depth, buf))
+    }
+
+    /// Charge the buffer a collection grew into to the collection's arena
+    fn allocate_in(
+        &mut self,
+        depth: usize,
+        bytes: u64,
+        align: u64,
+        span: Span,
+    ) -> Result<(), Flow> {
+        let target = self.alloc_target.replace(depth);
+        let result = self.allocate(bytes, align, span);
+        self.alloc_target = target;
+        result
+    }
+
+    /// Run a `for` loop, pulling each item from the source and through the
+    /// adapters before binding it to `name`
+    /// The value of the first arm whose pattern `value` matches and whose
+    /// guard holds, with the bindings of the pattern in scope
+    fn eval_match(
+        &mut self,
+        value: &Value,
+        arms: &[Arm],
+        span: Span,
+        env: &mut Vec<Scope>,
+    ) -> ExecResult {
+        for arm in arms {
+            let mut bindings = Vec::new();
+            if !bind_pattern(&arm.pattern, value, false, &mut bindings) {
+                continue;
+            }
+            env.push(Scope::new());
+            for (name, value) in bindings {
+                declare(env, name, value);
+            }
+            let guard = match arm.guard {
+                Some(guard) => self
+                    .eval(&self.exprs[guard], env)
+                    .map(|guard| guard.deref()),
+                None => Ok(Value::Bool(true)),
+            };
+            let result = match guard {
+                Ok(Value::Bool(true)) => self.eval(&self.exprs[arm.value], env),
+                Ok(Value::Bool(false)) => {
+                    self.end_scope(env.pop());
+                    continue;
+                }
+                Ok(other) => panic_at(
+                    self.exprs[arm.guard.expect("a guard was evaluated")].span,
+                    &format!("expected `bool`, found `{}`", other),
+                ),
+                Err(flow) => Err(flow),
+            };
+            self.end_scope(env.pop());
+            return result;
+        }
+        panic_at(span, &format!("no `match` arm matched `{}`", value))
+    }
+
+    /// An end of a range, which must be an integer
+    fn eval_range_end(
+        &mut self,
+        end: Option<ExprId>,
+        env: &mut Vec<Scope>,
+    ) -> Result<Option<i64>, Flow> {
+        let Some(end) = end else {
+            return Ok(None);
+        };
+        match self.eval(&self.exprs[end], env)?.deref() {
+            Value::Int(n) => Ok(Some(n)),
+            other => Err(panic_flow(
+                self.exprs[end].span,
+                &format!("expected an integer end of range, found `{}`", other),
+            )),
+        }
+    }
+
+    fn eval_for(
+        &mut self,
+        name: Symbol,
+        iterable: &Expr,
+        body: &Block,
+        env: &mut Vec<Scope>,
+    ) -> ExecResult {
+        let (source, adapters) = iterable.iteration(self.exprs);
+        let value = self.eval(source, env)?;
+        let mut items = match (&value, value.deref()) {
+            (_, Value::Array(items)) => Items::Array(items.into_iter()),
+            (_, Value::Vec(_, items)) => Items::Array(items.into_items().into_iter()),
+            (_, Value::Range(Some(start), end, inclusive)) => Items::Range(
+                i128::from(start),
+                end.map(|end| i128::from(end) + i128::from(inclusive)),
+            ),
+            (Value::Ref(slot), Value::Struct(ty, _) | Value::Enum(ty, _, _)) => {
+                Items::Iterator(ty, deepest_slot(slot.clone()))
+            }
+            (_, Value::Struct(ty, _) | Value::Enum(ty, _, _)) => {
+                Items::Iterator(ty, Rc::new(RefCell::new(value)))
+            }
+            (_, other) => return panic_at(source.span, &format!("`{}` is not iterable", other)),
+        };
+        let mut stages = Vec::with_capacity(adapters.len());
+        for adapter in &adapters {
+            let arg = self.eval(&adapter.args[0], env)?.deref();
+            let stage = match (adapter.kind, arg) {
+                (AdapterKind::Map, Value::Fn(f)) => Stage::Map(f),
+                (AdapterKind::Filter, Value::Fn(p)) => Stage::Filter(p),
+                (AdapterKind::Take, Value::Int(n)) => Stage::Take(n, 0),
+                (kind, other) => {
+                    return panic_at(
+                        adapter.span,
+                        &format!("invalid argument `{}` to `{}`", other, kind.name()),
+                    )
+                }
+            };
+            stages.push(stage);
+        }
+
+        // A `take` that is used up ends the loop before anything upstream
+        // of it runs again
+        while !stages
+            .iter()
+            .any(|stage| matches!(stage, Stage::Take(n, taken) if taken >= n))
+        {
+            let mut item = match &mut items {
+                Items::Array(items) => match items.next() {
+                    Some(item) => item,
+                    None => break,
+                },
+                Items::Range(next, end) => {
+                    // A range with no end stops at the largest integer
+                    if *next >= end.unwrap_or(i128::from(i64::MAX) + 1) {
+                        break;
+                    }
+                    // Nothing else may be evaluated, as with `for i in 0.. {}`
+                    if let Some(meter) = &mut self.meter {
+                        meter.step(source.span)?;
+                    }
+                    *next += 1;
+                    Value::Int((*next - 1) as i64)
+                }
+                Items::Iterator(ty, slot) => {
+                    let receiver = || vec![Value::Ref(slot.clone())];
+                    let has_next = format!("{}::has_next", ty);
+                    if self.call(&has_next, receiver(), source.span)?.deref() != Value::Bool(true) {
+                        break;
+                    }
+                    let next = format!("{}::next", ty);
+                    self.call(&next, receiver(), source.span)?.deref()
+                }
+            };
+            let mut keep = true;
+            for (stage, adapter) in stages.iter_mut().zip(&adapters) {
+                match stage {
+                    Stage::Map(f) => item = self.call(f, vec![item], adapter.span)?.deref(),
+                    Stage::Filter(p) => {
+                        keep = self.call(p, vec![item.clone()], adapter.span)?.deref()
+                            == Value::Bool(true);
+                        if !keep {
+                            break;
+                        }
+                    }
+                    Stage::Take(_, taken) => *taken += 1,
+                }
+            }
+            if keep {
+                env.push(Scope::new());
+                declare(env, name, item);
+                let result = self.exec_block(body, env);
+                self.end_scope(env.pop());
+                result?;
+            }
+        }
+        Ok(Value::Unit)
+    }
+
+    fn eval_path(&mut self, segments: &[Symbol], span: Span, env: &[Scope]) -> ExecResult {
+        if let [name] = segments {
+            if let Some(slot) = lookup(env, *name) {
+                // Guards are affine, so any read of one is a move, which
+                // leaves the variable without the guard
+                let value = slot.borrow().clone();
+                if let Value::Guard(_) = value {
+                    slot.replace(Value::Unit);
+                }
+                return Ok(value);
+            }
+            if let Some(value) = self.info.consts.get(name.as_str()) {
+                return Ok(Value::from(value));
+            }
+            if self.functions.contains_key(name.as_str())
+                || self.info.externs.contains_key(name.as_str())
+                || builtins::signature(name).is_some()
+            {
+                return Ok(Value::Fn(name.to_string()));
+            }
+            if name == "None" {
+                return Ok(Value::Option(None));
+            }
+        }
+        let qualified = join_path(segments);
+        if self.functions.contains_key(&qualified)
+            || builtins::signature(&qualified).is_some()
+            || matches!(
+                qualified.as_str(),
+                "thread::spawn" | "Mutex::new" | "RwLock::new" | "ptr::from_ref" | "ptr::from_mut"
+            )
+        {
+            return Ok(Value::Fn(qualified));
+        }
+        if let [enum_name, variant] = segments {
+            if let Some((_, d)) = self
+                .info
+                .enums
+                .get(enum_name.as_str())
+                .and_then(|variants| variants.iter().find(|(v, _)| v == variant))
+            {
+                return Ok(Value::Enum(enum_name.to_string(), variant.to_string(), *d));
+            }
+        }
+        panic_at(
+            span,
+            &format!("cannot find value `{}`", join_path(segments)),
+        )
+    }
+
+    /// The variable slot an expression refers to, if it is a plain variable
+    fn place(&self, expr: &Expr, env: &[Scope]) -> Option<Rc<RefCell<Value>>> {
+        match &expr.kind {
+            ExprKind::Path(segments) if segments.len() == 1 => lookup(env, segments[0]),
+            _ => None,
+        }
+    }
+
+    fn assign(&mut self, target: &Expr, value: Value, env: &mut Vec<Scope>) -> Result<(), Flow> {
+        // Collect `[index]` and `.field` projections, outermost first
+        let mut projections = Vec::new();
+        let mut root = target;
+        loop {
+            match &root.kind {
+                ExprKind::Index(base, index) => {
+                    let i = match self.eval(&self.exprs[*index], env)?.deref() {
+                        Value::Int(i) => i,
+                        other => {
+                            return Err(panic_flow(
+                                self.exprs[*index].span,
+                                &format!("invalid index `{}`", other),
+                            ))
+                        }
+                    };
+                    projections.push((Projection::Index(i), root.span));
+                    root = &self.exprs[*base];
+                }
+                ExprKind::Field(base, field) => {
+                    projections.push((Projection::Field(field), root.span));
+                    root = &self.exprs[*base];
+                }
+                _ => break,
+            }
+        }
+
+        // Writes through a pointer go to what it points to in its slot
+        let (slot, pointer) = match &root.kind {
+            ExprKind::Unary(UnaryOp::Deref, operand) => {
+                match self.eval(&self.exprs[*operand], env)?.deref() {
+                    Value::Ptr(pointer) => {
+                        self.check_pointer(&pointer, root.span)?;
+                        (pointer.slot.clone(), Some(pointer))
+                    }
+                    other => {
+                        return Err(panic_flow(
+                            root.span,
+                            &format!("cannot dereference `{}`", other),
+                        ))
+                    }
+                }
+            }
+            _ => {
+                let slot = self
+                    .place(root, env)
+                    .ok_or_else(|| panic_flow(target.span, "invalid assignment target"))?;
+                (deepest_slot(slot), None)
+            }
+        };
+        let mut current = slot.borrow_mut();
+        let mut cell: &mut Value = &mut current;
+        if let Some(pointer) = &pointer {
+            cell = pointer.target(cell, root.span)?;
+        }
+        for (projection, span) in projections.into_iter().rev() {
+            cell = match (projection, cell) {
+                (Projection::Index(i), Value::Array(items)) => index_array_mut(items, i, span)?,
+                (Projection::Index(i), Value::Vec(_, items)) => {
+                    index_array_mut(items.items_mut(), i, span)?
+                }
+                (Projection::Field(field), Value::Struct(_, fields)) => fields
+                    .iter_mut()
+                    .find(|(name, _)| name == field)
+                    .map(|(_, value)| value)
+                    .ok_or_else(|| panic_flow(span, &format!("no field `{}`", field)))?,
+                (Projection::Field("0"), value) => value,
+                (_, other) => {
+                    return Err(panic_flow(
+                        span,
+                        &format!("invalid assignment target `{}`", other),
+                    ))
+                }
+            };
+        }
+        *cell = value.deref();
+        Ok(())
+    }
+
+    /// `value`, computed by the operation at `span` on `i64` or `f64`,
+    /// brought back into the sized numeric type of the operation, if it has
+    /// one (see [`crate::numeric`])
+    fn narrow(&self, value: Value, span: Span, verb: &str) -> ExecResult {
+        let Some(numeric) = self.info.numeric.get(&span) else {
+            return Ok(value);
+        };
+        match value {
+            Value::Int(n) if !numeric.contains(n.into()) => match self.overflow {
+                OverflowMode::Wrap => Ok(Value::Int(numeric.wrap(n.into()))),
+                OverflowMode::Trap => panic_at(span, &format!("attempt to {} with overflow", verb)),
+            },
+            Value::Float(x) => Ok(Value::Float(numeric.round(x))),
+            value => Ok(value),
+        }
+    }
+
+    fn binary(&self, op: BinaryOp, left: Value, right: Value, span: Span) -> ExecResult {
+        match (left, right) {
+            (Value::Int(a), Value::Int(b)) => self.int_binary(op, a, b, span),
+            (Value::Float(a), Value::Float(b)) => Ok(match op {
+                BinaryOp::Add => Value::Float(a + b),
+                BinaryOp::Sub => Value::Float(a - b),
+                BinaryOp::Mul => Value::Float(a * b),
+                BinaryOp::Div => Value::Float(a / b),
+                BinaryOp::Rem => Value::Float(a % b),
+                BinaryOp::Eq => Value::Bool(a == b),
+                BinaryOp::Ne => Value::Bool(a != b),
+                BinaryOp::Lt => Value::Bool(a < b),
+                BinaryOp::Le => Value::Bool(a <= b),
+                BinaryOp::Gt => Value::Bool(a > b),
+                BinaryOp::Ge => Value::Bool(a >= b),
+                BinaryOp::And | BinaryOp::Or => {
+                    return panic_at(span, "logical operator applied to floats")
+                }
+            }),
+            (Value::Simd(a), Value::Simd(b)) if !op.is_comparison() => {
+                let op = match op {
+                    BinaryOp::Add => LaneOp::Add,
+                    BinaryOp::Sub => LaneOp::Sub,
+                    BinaryOp::Mul => LaneOp::Mul,
+                    BinaryOp::Div => LaneOp::Div,
+                    _ => {
+                        return panic_at(
+                            span,
+                            &format!("cannot apply `{}` to vectors", op.symbol()),
+                        )
+                    }
+                };
+                a.apply(op, &b)
+                    .map(Value::Simd)
+                    .map_err(|msg| panic_flow(span, &msg))
+            }
+            (Value::Bool(a), Value::Bool(b)) => match op {
+                BinaryOp::And => Ok(Value::Bool(a && b)),
+                BinaryOp::Or => Ok(Value::Bool(a || b)),
+                BinaryOp::Eq => Ok(Value::Bool(a == b)),
+                BinaryOp::Ne => Ok(Value::Bool(a != b)),
+                _ => panic_at(span, &format!("cannot apply `{}` to booleans", op.symbol())),
+            },
+            (left, right) => match op {
+                BinaryOp::Eq => Ok(Value::Bool(left == right)),
+                BinaryOp::Ne => Ok(Value::Bool(left != right)),
+                _ => panic_at(
+                    span,
+                    &format!(
+                        "cannot apply `{}` to `{}` and `{}`",
+                        op.symbol(),
+                        left,
+                        right
+                    ),
+                ),
+            },
+        }
+    }
+
+    fn int_binary(&self, op: BinaryOp, a: i64, b: i64, span: Span) -> ExecResult {
+        let arith = |checked: Option<i64>, wrapped: i64| match self.overflow {
+            OverflowMode::Wrap => Ok(Value::Int(wrapped)),
+            OverflowMode::Trap => checked.map(Value::Int).ok_or_else(|| {
+                panic_flow(
+                    span,
+                    &format!("attempt to {} with overflow", overflow_verb(op)),
+                )
+            }),
+        };
+        match op {
+            BinaryOp::Add => arith(a.checked_add(b), a.wrapping_add(b)),
+            BinaryOp::Sub => arith(a.checked_sub(b), a.wrapping_sub(b)),
+            BinaryOp::Mul => arith(a.checked_mul(b), a.wrapping_mul(b)),
+            BinaryOp::Div if b == 0 => panic_at(span, "attempt to divide by zero"),
+            BinaryOp::Div => arith(a.checked_div(b), a.wrapping_div(b)),
+            BinaryOp::Rem if b == 0 => panic_at(
+                span,
+                "attempt to calculate the remainder with a divisor of zero",
+            ),
+            BinaryOp::Rem => arith(a.checked_rem(b), a.wrapping_rem(b)),
+            BinaryOp::Eq => Ok(Value::Bool(a == b)),
+            BinaryOp::Ne => Ok(Value::Bool(a != b)),
+            BinaryOp::Lt => Ok(Value::Bool(a < b)),
+            BinaryOp::Le => Ok(Value::Bool(a <= b)),
+            BinaryOp::Gt => Ok(Value::Bool(a > b)),
+            BinaryOp::Ge => Ok(Value::Bool(a >= b)),
+            BinaryOp::And | BinaryOp::Or => panic_at(span, "logical operator applied to integers"),
+        }
+    }
+}
+
+/// What an arithmetic operator attempts, for overflow messages
+fn overflow_verb(op: BinaryOp) -> &'static str {
+    match op {
+        BinaryOp::Add => "add",
+        BinaryOp::Sub => "subtract",
+        BinaryOp::Mul => "multiply",
+        BinaryOp::Div => "divide",
+        BinaryOp::Rem => "calculate the remainder",
+        _ => "compare",
+    }
+}
+
+/// Where the items of a `for` loop come from
+enum Items {
+    Array(std::vec::IntoIter<Value>),
+    /// The next integer of a range, and the one it stops before if it has
+    /// an end
+    Range(i128, Option<i128>),
+    /// A value of the named type, advanced by its `has_next` and `next`
+    Iterator(String, Rc<RefCell<Value>>),
+}
+
+/// An [`Adapter`] with its argument evaluated
+enum Stage {
+    Map(String),
+    Filter(String),
+    /// Items allowed through and items taken so far
+    Take(i64, i64),
+}
+
+fn declare(env: &mut [Scope], name: Symbol, value: Value) {
+    if let Some(scope) = env.last_mut() {
+        scope.insert(name, Rc::new(RefCell::new(value)));
+    }
+}
+
+/// Whether `value` matches `pattern`, adding what it binds to `bindings`.
+/// Matching through a reference binds references to what is inside it.
+fn bind_pattern(
+    pattern: &Pattern,
+    value: &Value,
+    by_ref: bool,
+    bindings: &mut Vec<(Symbol, Value)>,
+) -> bool {
+    match (&pattern.kind, value) {
+        (PatternKind::Wildcard, _) => true,
+        (PatternKind::Binding(name), _) => {
+            let value = match value {
+                Value::Ref(_) => value.clone(),
+                _ if by_ref => Value::Ref(Rc::new(RefCell::new(value.clone()))),
+                _ => value.clone(),
+            };
+            bindings.push((*name, value));
+            true
+        }
+        (PatternKind::Or(alternatives), _) => alternatives.iter().any(|alternative| {
+            let bound = bindings.len();
+            let matched = bind_pattern(alternative, value, by_ref, bindings);
+            if !matched {
+                bindings.truncate(bound);
+            }
+            matched
+        }),
+        (_, Value::Ref(slot)) => {
+            let inner = slot.borrow().clone();
+            bind_pattern(pattern, &inner, true, bindings)
+        }
+        (PatternKind::Int(..) | PatternKind::Range(..), Value::Int(n)) => pattern.matches_int(*n),
+        (PatternKind::Bool(a), Value::Bool(b)) => a == b,
+        (PatternKind::Str(a), Value::Str(b)) => a.as_str() == b.as_str(),
+        (PatternKind::Variant(_, a), Value::Enum(_, b, _)) => a.as_str() == b.as_str(),
+        (PatternKind::Some(pattern), Value::Option(Some(value))) => {
+            bind_pattern(pattern, value, by_ref, bindings)
+        }
+        (PatternKind::None, Value::Option(None)) => true,
+        _ => false,
+    }
+}
+
+fn lookup(env: &[Scope], name: Symbol) -> Option<Rc<RefCell<Value>>> {
+    env.iter().rev().find_map(|scope| scope.get(&name).cloned())
+}
+
+/// Follow references to the slot that actually holds the value
+fn deepest_slot(slot: Rc<RefCell<Value>>) -> Rc<RefCell<Value>> {
+    let next = match &*slot.borrow() {
+        Value::Ref(inner) => Some(inner.clone()),
+        _ => None,
+    };
+    match next {
+        Some(inner) => deepest_slot(inner),
+        None => slot,
+    }
+}
+
+fn missing_key(key: &Key, span: Span) -> ExecResult {
+    panic_at(
+        span,
+        &format!("key `{}` is not in the `Map`", Value::from(key)),
+    )
+}
+
+fn index_array(items: &[Value], index: i64, span: Span) -> Result<&Value, Flow> {
+    usize::try_from(index)
+        .ok()
+        .and_then(|i| items.get(i))
+        .ok_or_else(|| out_of_bounds(items.len(), index, span))
+}
+
+/// A copy of the elements of `items` in a range
+fn slice_array(
+    items: &[Value],
+    (start, end, inclusive): (Option<i64>, Option<i64>, bool),
+    span: Span,
+) -> ExecResult {
+    let (first, last) = builtins::slice_bounds(start, end, inclusive, items.len())
+        .map_err(|msg| panic_flow(span, &msg))?;
+    Ok(Value::Array(items[first..last].to_vec()))
+}
+
+fn index_array_mut(items: &mut [Value], index: i64, span: Span) -> Result<&mut Value, Flow> {
+    let len = items.len();
+    usize::try_from(index)
+        .ok()
+        .and_then(|i| items.get_mut(i))
+        .ok_or_else(|| out_of_bounds(len, index, span))
+}
+
+fn out_of_bounds(len: usize, index: i64, span: Span) -> Flow {
+    panic_flow(
+        span,
+        &format!(
+            "index out of bounds: the length is {} but the index is {}",
+            len, index
+        ),
+    )
+}
+
+fn panic_flow(span: Span, msg: &str) -> Flow {
+    Flow::Panic(Box::new(Panic {
+        message: format!("Runtime panic at {}:{}: {}", span.line, span.column, msg),
+        location: span,
+        backtrace: Vec::new(),
+    }))
+}
+
+/// Bytes of the parameters a belief stores out of line
+fn belief_size(belief: &Belief) -> u64 {
+    match belief {
+        Belief::Bernoulli(_) => 8,
+        Belief::Normal { .. } => 16,
+        Belief::Mixture(components) => components.iter().map(|(_, c)| 8 + belief_size(c)).sum(),
+    }
+}
+
+/// An address on the stack just below the caller's frame, to measure how
+/// much of the stack is in use
+#[inline(never)]
+fn stack_address() -> usize {
+    let marker = 0u8;
+    std::hint::black_box(std::ptr::addr_of!(marker)) as usize
+}
+
+fn panic_at(span: Span, msg: &str) -> ExecResult {
+    Err(panic_flow(span, msg))
+}
+
+/// The type of a value, as far as it records, for a panic when it is not
+/// the one expected; `_` stands for what it does not
+fn type_of(value: &Value) -> String {
+    match value {
+        Value::Int(_) => "int".to_string(),
+        Value::Float(_) => "float".to_string(),
+        Value::Bool(_) => "bool".to_string(),
+        Value::Str(_) => "str".to_string(),
+        Value::Unit => "()".to_string(),
+        Value::Array(items) => {
+            let mut types = items.iter().map(type_of);
+            let first = types.next().unwrap_or_else(|| "_".to_string());
+            let elem = if types.all(|ty| ty == first) {
+                first
+            } else {
+                "any".to_string()
+            };
+            format!("[{}; {}]", elem, items.len())
+        }
+        Value::Enum(name, ..) | Value::Struct(name, _) => name.clone(),
+        Value::Ref(slot) => format!("&{}", type_of(&slot.borrow())),
+        Value::Lock(name, _) => format!("{}<_>", name),
+        Value::Belief(_) => "Belief<_>".to_string(),
+        Value::Vec(..) => "Vec<_>".to_string(),
+        Value::Option(_) => "Option<_>".to_string(),
+        Value::Map(..) => "Map<_, _>".to_string(),
+        Value::StringBuf(..) => "StringBuf".to_string(),
+        Value::Fn(_) => "fn".to_string(),
+        Value::Ptr(_) => "*const _".to_string(),
+        _ => "_".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json::Json;
+    use crate::lexer::Lexer;
+    use crate::token::TokenKind;
+    use crate::{parser, typeck};
+
+    fn run(source: &str, overflow: OverflowMode) -> (Result<Value, String>, String) {
+        let options = RunOptions {
+            overflow,
+            ..RunOptions::default()
+        };
+        run_with(source, &options)
+    }
+
+    fn run_with(source: &str, options: &RunOptions) -> (Result<Value, String>, String) {
+        let mut lexer = Lexer::new(source);
+        let mut tokens = Vec::new();
+        loop {
+            let token = lexer.next_token();
+            if token.kind == TokenKind::Eof {
+                break;
+            }
+            tokens.push(token);
+        }
+        let program = parser::parse(tokens).unwrap();
+        let info = typeck::check(&program).unwrap();
+        let mut out = Vec::new();
+        let result = Interpreter::new(&program, &info, options, &mut out)
+            .run_main()
+            .map_err(|err| err.to_string());
+        (result, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn test_run_program() {
+        let source = "
+            const LIMIT: i64 = 5;
+            fn fib(n: i64) -> i64 { if n < 2 { n } else { fib(n - 1) + fib(n - 2) } }
+            fn main() -> i64 {
+                let mut xs = [0; LIMIT];
+                let mut i = 0;
+                while i < LIMIT { xs[i] = fib(i + 5); i = i + 1; }
+                print(xs);
+                xs[4]
+            }";
+        let (result, out) = run(source, OverflowMode::Trap);
+        assert_eq!(result.unwrap(), Value::Int(34));
+        assert_eq!(out, "[5, 8, 13, 21, 34]\n");
+    }
+
+    #[test]
+    fn test_overflow_traps_in_debug() {
+        let source = "fn main() -> i64 {\n    let big = 9223372036854775807;\n    big + 1\n}";
+        let (result, _) = run(source, OverflowMode::Trap);
+        assert_eq!(
+            result.unwrap_err(),
+            "Runtime panic at 3:9: attempt to add with overflow"
+        );
+    }
+
+    #[test]
+    fn test_overflow_wraps_in_release() {
+        let source = "fn main() -> i64 { let big = 9223372036854775807; big + 1 }";
+        let (result, _) = run(source, OverflowMode::Wrap);
+        assert_eq!(result.unwrap(), Value::Int(i64::MIN));
+    }
+
+    #[test]
+    fn test_sized_integers() {
+        let source = "fn main() -> i64 {\n    let x: u8 = 250;\n    (x + 10) as i64\n}";
+        let (result, _) = run(source, OverflowMode::Trap);
+        assert_eq!(
+            result.unwrap_err(),
+            "Runtime panic at 3:8: attempt to add with overflow"
+        );
+        let (result, _) = run(source, OverflowMode::Wrap);
+        assert_eq!(result.unwrap(), Value::Int(4));
+
+        let source = "fn main() {
+            print([300 as u8 as i64, -1 as u16 as i64, -3.7 as i64, 1000000.0 as i8 as i64]);
+            let third: f32 = 1.0 / 3.0;
+            print(third as f64);
+            let min: i8 = -128;
+            print(-min);
+        }";
+        let (result, out) = run(source, OverflowMode::Trap);
+        assert_eq!(out, "[44, 65535, -3, 127]\n0.3333333432674408\n");
+        assert_eq!(
+            result.unwrap_err(),
+            "Runtime panic at 6:19: attempt to negate with overflow"
+        );
+    }
+
+    #[test]
+    fn test_wrapping_intrinsics_never_trap() {
+        let source = "fn main() -> i64 { wrapping_mul(9223372036854775807, 2) }";
+        let (result, _) = run(source, OverflowMode::Trap);
+        assert_eq!(result.unwrap(), Value::Int(-2));
+    }
+
+    #[test]
+    fn test_division_by_zero_always_panics() {
+        let source = "fn main() -> i64 { let zero = 0; 1 / zero }";
+        let (result, _) = run(source, OverflowMode::Wrap);
+        assert!(result.unwrap_err().contains("attempt to divide by zero"));
+    }
+
+    #[test]
+    fn test_inline_asm_panics() {
+        let source = "fn main() { let mut x = 1; print(x); asm!(\"inc {0}\", inout(reg) x); }";
+        let (result, out) = run(source, OverflowMode::Trap);
+        assert_eq!(out, "1\n");
+        assert!(result
+            .unwrap_err()
+            .starts_with("Runtime panic at 1:38: inline assembly cannot run in the interpreter"));
+    }
+
+    #[test]
+    fn test_threads() {
+        let source = "
+            struct Job { id: i64, name: String }
+            fn work(job: Job, n: i64) -> i64 { print(job.name); job.id * n }
+            fn fail(n: i64) -> i64 { if n > 0 { panic(\"no work\"); } n }
+            fn main() -> i64 {
+                let a = thread::spawn(work, Job { id: 2, name: \"a\" }, 10);
+                let b = thread::spawn(work, Job { id: 3, name: \"b\" }, 100);
+                let never = thread::spawn(fail, 1);
+                print(\"spawned\");
+                b.join() + a.join()
+            }";
+        let (result, out) = run(source, OverflowMode::Trap);
+        assert_eq!(result.unwrap(), Value::Int(320));
+        assert_eq!(out, "spawned\nb\na\n");
+
+        let source = "
+            fn fail(n: i64) -> i64 { if n > 0 { panic(\"no work\"); } n }
+            fn main() { let t = thread::spawn(fail, 1); t.join(); }";
+        let (result, _) = run(source, OverflowMode::Trap);
+        let err = result.unwrap_err();
+        assert!(
+            err.starts_with("Runtime panic in a thread running `fail` at 2:54: no work"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_channels() {
+        let source = "
+            fn produce(tx: Sender<i64>, from: i64) {
+                let mut i = from;
+                while i < from + 3 { tx.send(i); i = i + 1; }
+            }
+            fn consume(rx: Receiver<i64>, n: i64) -> i64 {
+                let mut total = 0;
+                let mut i = 0;
+                while i < n { total = total * 10 + rx.recv(); i = i + 1; }
+                total
+            }
+            fn main() -> i64 {
+                let rx: Receiver<i64> = channel::bounded(1);
+                let tx = rx.sender();
+                let a = thread::spawn(produce, tx, 1);
+                let b = thread::spawn(produce, tx, 4);
+                print(rx.recv());
+                let c = thread::spawn(consume, rx, 5);
+                c.join()
+            }";
+        let (result, out) = run(source, OverflowMode::Trap);
+        assert_eq!(out, "1\n");
+        // `b` ran while `a` waited for room, and the rest of its messages
+        // went past the capacity
+        assert_eq!(result.unwrap(), Value::Int(45623));
+
+        let source = "
+            fn main() {
+                let rx: Receiver<i64> = channel::unbounded();
+                rx.sender().send(1);
+                rx.recv();
+                rx.recv();
+            }";
+        let (result, _) = run(source, OverflowMode::Trap);
+        let err = result.unwrap_err();
+        assert!(
+            err.starts_with("Runtime panic at 6:19: deadlock: receiving from an empty channel"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_atomics() {
+        let source = "
+            fn count(hits: AtomicU32, n: i64) -> i64 {
+                hits.fetch_sub(n, Ordering::Relaxed)
+            }
+            fn main() -> i64 {
+                let hits = AtomicU32::new(1);
+                let t = thread::spawn(count, hits, 2);
+                print(t.join());
+                print(hits);
+                let order = Ordering::Release;
+                print(hits.compare_exchange(4294967295, 7, Ordering::SeqCst, Ordering::Relaxed));
+                hits.swap(9, Ordering::SeqCst) * 10 + hits.load(order)
+            }";
+        let (result, out) = run(source, OverflowMode::Trap);
+        assert_eq!(out, "1\nAtomicU32(4294967295)\n4294967295\n");
+        let err = result.unwrap_err();
+        assert!(
+            err.starts_with("Runtime panic at 12:59: `load` cannot use `Ordering::Release`"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_locks() {
+        let source = "
+            fn add(total: Mutex<i64>, n: i64) -> i64 {
+                let guard = total.lock();
+                guard.set(guard.get() + n);
+                guard.get()
+            }
+            fn main() {
+                let total = Mutex::new(1);
+                let a = thread::spawn(add, total, 10);
+                let b = thread::spawn(add, total, 100);
+                print(a.join() + b.join());
+                let config = RwLock::new(5);
+                let r1 = config.read();
+                let r2 = config.read();
+                print(r1.get() + r2.get());
+                r1.unlock();
+                r2.unlock();
+                let w = config.write();
+                print(w.replace(7));
+                print(w);
+                let r3 = config.read();
+                let w2 = config.write();
+            }";
+        let (result, out) = run(source, OverflowMode::Trap);
+        assert_eq!(out, "122\n10\n5\nRwLockWriteGuard(7)\n");
+        let err = result.unwrap_err();
+        assert!(
+            err.starts_with("Runtime panic at 22:32: deadlock: the lock is held by this thread"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_sanitizers() {
+        let source = "
+            fn dangling() -> *const i64 {
+                let x = 42;
+                ptr::from_ref(&x)
+            }
+            fn main() {
+                let xs = [1, 2, 3];
+                let mut p = xs.as_ptr();
+                arena a {
+                    let mut v: Vec<i64> = Vec::new(a);
+                    v.push(7);
+                    p = v.as_ptr();
+                }
+                unsafe { print(*p); }
+                unsafe { print(*dangling()); }
+                unsafe { print(*xs.as_ptr().add(3)); }
+            }";
+        // Uninstrumented, the stale values are still there to be read
+        let (_, out) = run(source, OverflowMode::Trap);
+        assert_eq!(out, "7\n42\n");
+
+        let sanitized = |sanitizers| {
+            let options = RunOptions {
+                sanitizers,
+                ..RunOptions::default()
+            };
+            run_with(source, &options).0.unwrap_err()
+        };
+        let arena = sanitized(Sanitizers {
+            arena: true,
+            ..Sanitizers::default()
+        });
+        assert!(
+            arena.starts_with(
+                "Runtime panic at 14:32: ArenaSanitizer: use-after-reset: \
+                 the memory pointed to belonged to the arena at 9:17"
+            ),
+            "{}",
+            arena
+        );
+        let address = sanitized(Sanitizers {
+            address: true,
+            ..Sanitizers::default()
+        });
+        assert!(
+            address.starts_with("Runtime panic at 15:32: AddressSanitizer: stack-use-after-scope"),
+            "{}",
+            address
+        );
+    }
+
+    #[test]
+    fn test_coverage() {
+        let source = "fn half(n: i64) -> i64 {
+    if n % 2 == 1 {
+        panic(\"odd\");
+    }
+    n / 2
+}
+fn main() {
+    let mut n = 8;
+    while n > 0 {
+        n = half(n);
+    }
+}
+";
+        let program = crate::compile_until(source, crate::Phase::Parse)
+            .unwrap()
+            .into_ast()
+            .unwrap();
+        let info = typeck::check(&program).unwrap();
+        let options = RunOptions {
+            coverage: true,
+            ..RunOptions::default()
+        };
+        let mut out = Vec::new();
+        let mut interpreter = Interpreter::new(&program, &info, &options, &mut out);
+        // 8, 4, 2, then 1 is odd
+        assert!(interpreter.run_main().is_err());
+        let lcov = interpreter.coverage().unwrap().to_lcov("half.solo");
+        assert!(lcov.contains("FNDA:4,half\n"), "{}", lcov);
+        assert!(lcov.contains("BRDA:2,0,0,1\nBRDA:2,0,1,3\nBRDA:9,0,0,4\nBRDA:9,0,1,0\n"));
+        assert!(lcov.contains("DA:3,1\nDA:5,3\nDA:8,1\nDA:9,1\nDA:10,4\n"));
+    }
+
+    #[test]
+    fn test_simd() {
+        let source = "
+            fn main() -> i64 {
+                let x = f64x4::from_array([1.0, 2.0, 3.0, 4.0]);
+                let y = x * f64x4::splat(0.5) + x;
+                print(y);
+                print(x.mul_add(y, f64x4::splat(1.0)).reduce_sum());
+                let big = i32x4::splat(2147483647) + i32x4::from_array([1, 2, 3, 4]);
+                print(big.to_array()[0]);
+                print(fma(0.1, 10.0, -1.0) > 0.0);
+                popcount(255) + clz(1) + ctz(8)
+            }";
+        let (result, out) = run(source, OverflowMode::Trap);
+        assert_eq!(result.unwrap(), Value::Int(8 + 63 + 3));
+        assert_eq!(out, "f64x4[1.5, 3, 4.5, 6]\n49\n-2147483648\ntrue\n");
+    }
+
+    #[test]
+    fn test_raw_pointers() {
+        let source = "
+            struct Story { words: i64 }
+            fn main() {
+                let mut scores = [3, 5, 8];
+                let first = scores.as_mut_ptr();
+                let mut story = Story { words: 250 };
+                let p = ptr::from_mut(&mut story);
+                unsafe {
+                    *first.add(2) = 13;
+                    (*p).words = *first.add(1) * 100;
+                    print(*first.add(2).sub(1));
+                }
+                print(scores);
+                print(story.words);
+                unsafe { print(*first.add(3)); }
+            }";
+        let (result, out) = run(source, OverflowMode::Trap);
+        assert_eq!(out, "5\n[3, 5, 13]\n500\n");
+        let err = result.unwrap_err();
+        assert!(
+            err.starts_with("Runtime panic at 15:32: dereference of a pointer 3 elements past"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_struct_fields() {
+        let source = "
+            struct Point { x: i64, y: i64 }
+            struct Line { from: Point, to: Point }
+            fn main() -> i64 {
+                let mut line = Line { to: Point { x: 3, y: 4 }, from: Point { x: 0, y: 0 } };
+                line.from.x = 1;
+                print(line);
+                line.to.x - line.from.x
+            }";
+        let (result, out) = run(source, OverflowMode::Trap);
+        assert_eq!(result.unwrap(), Value::Int(2));
+        assert_eq!(
+            out,
+            "Line { from: Point { x: 1, y: 0 }, to: Point { x: 3, y: 4 } }\n"
+        );
+    }
+
+    #[test]
+    fn test_method_calls() {
+        let source = "
+            struct Counter { n: i64 }
+            struct Pair { left: Counter, right: Counter }
+            impl Counter {
+                fn new() -> Self { Counter { n: 0 } }
+                fn bump(&mut self) { self.n = self.n + 1; }
+                fn get(&self) -> i64 { self.n }
+            }
+            fn main() -> i64 {
+                let mut pair = Pair { left: Counter::new(), right: Counter::new() };
+                pair.left.bump();
+                pair.right.bump();
+                pair.right.bump();
+                let mut c = Counter::new();
+                c.bump();
+                pair.left.get() * 100 + pair.right.get() * 10 + c.get()
+            }";
+        let (result, _) = run(source, OverflowMode::Trap);
+        assert_eq!(result.unwrap(), Value::Int(121));
+    }
+
+    #[test]
+    fn test_program_arguments() {
+        let source = "
+            fn main() -> i64 {
+                let mut i = 1;
+                while i < env::args_len() { print(env::arg(i)); i = i + 1; }
+                print(env::arg(i));
+                0
+            }";
+        let options = RunOptions {
+            args: vec!["prog.solo".to_string(), "a b".to_string(), "c".to_string()],
+            ..RunOptions::default()
+        };
+        let (result, out) = run_with(source, &options);
+        assert_eq!(
+            result.unwrap_err(),
+            "Runtime panic at 5:31: argument index out of range: \
+             there are 3 arguments but the index is 3"
+        );
+        assert_eq!(out, "a b\nc\n");
+    }
+
+    #[test]
+    fn test_beliefs() {
+        let source = "
+            fn main() -> f64 {
+                let rain = update(~Bernoulli(0.5), ~Bernoulli(0.2));
+                print(rain);
+                let temp = marginalize(rain, ~Normal(10.0, 2.0), ~Normal(20.0, 2.0));
+                expectation(temp)
+            }";
+        let (result, out) = run(source, OverflowMode::Trap);
+        assert_eq!(out, "~Bernoulli(0.2)\n");
+        let json = RunOptions {
+            beliefs: BeliefFormat::Json,
+            ..RunOptions::default()
+        };
+        let (_, out) = run_with(source, &json);
+        assert!(out.starts_with("{\"type\":\"bool\",\"distribution\":\"~Bernoulli(0.2)\""));
+        match result.unwrap() {
+            Value::Float(x) => assert!((x - 18.0).abs() < 1e-9, "{}", x),
+            other => panic!("expected a float, found {}", other),
+        }
+
+        let (result, _) = run("fn main() { ~Bernoulli(1.5); }", OverflowMode::Trap);
+        assert!(result.unwrap_err().contains("between 0 and 1"));
+    }
+
+    #[test]
+    fn test_sampling() {
+        let source = "
+            fn main() -> f64 {
+                let temp = marginalize(~Bernoulli(0.2), ~Normal(10.0, 2.0), ~Normal(20.0, 2.0));
+                print(sample(temp));
+                print(sample(~Bernoulli(0.5)));
+                expectation(temp)
+            }";
+        let options = RunOptions {
+            seed: 42,
+            samples: Some(10_000),
+            ..RunOptions::default()
+        };
+        let (first, out) = run_with(source, &options);
+        let (replay, replay_out) = run_with(source, &options);
+        assert_eq!(out, replay_out);
+        let (Ok(Value::Float(x)), Ok(Value::Float(y))) = (first, replay) else {
+            panic!("expected float results");
+        };
+        assert_eq!(x, y);
+        assert!((x - 18.0).abs() < 0.2, "{}", x);
+
+        let reseeded = RunOptions { seed: 7, ..options };
+        assert_ne!(run_with(source, &reseeded).1, out);
+
+        // A failure after sampling names the seed to replay it with
+        let flaky = "fn main() { if sample(~Normal(0.0, 1.0)) > 0.0 { panic(\"high\"); } }";
+        let seeded = |seed| RunOptions {
+            seed,
+            ..RunOptions::default()
+        };
+        let panicked = (0..8)
+            .map(|seed| run_with(flaky, &seeded(seed)).0)
+            .find_map(Result::err)
+            .unwrap();
+        assert!(
+            panicked.contains("high\nnote: beliefs were sampled with seed "),
+            "{}",
+            panicked
+        );
+        let (unsampled, _) = run("fn main() { panic(\"no\"); }", OverflowMode::Trap);
+        assert!(!unsampled.unwrap_err().contains("seed"));
+    }
+
+    #[test]
+    fn test_expect() {
+        // A fair and a biased coin; heads twice makes the biased one likelier
+        let source = "
+            #[model]
+            fn biased() -> bool {
+                let biased = sample(~Bernoulli(0.5));
+                let p = if biased { 0.9 } else { 0.5 };
+                observe(~Bernoulli(p), true);
+                observe(~Bernoulli(p), true);
+                biased
+            }
+            fn main() -> f64 {
+                print(expect(biased()));
+                expect({ let x = sample(~Normal(0.0, 1.0)); observe(~Normal(x, 1.0), 2.0); x })
+            }";
+        let options = RunOptions {
+            samples: Some(20_000),
+            ..RunOptions::default()
+        };
+        let (result, out) = run_with(source, &options);
+        let p: f64 = out.trim().parse().unwrap();
+        assert!((p - 0.81 / 1.06).abs() < 0.02, "{}", p);
+        match result.unwrap() {
+            Value::Float(x) => assert!((x - 1.0).abs() < 0.05, "{}", x),
+            other => panic!("expected a float, found {}", other),
+        }
+
+        let (result, _) = run(
+            "fn main() -> f64 { expect({ observe(false); 1.0 }) }",
+            OverflowMode::Trap,
+        );
+        assert_eq!(
+            result.unwrap_err(),
+            "Runtime panic at 1:26: `observe` rejected all 1000 samples of `expect`"
+        );
+    }
+
+    #[test]
+    fn test_any() {
+        let source = "
+            struct Point { x: i64, y: i64 }
+            fn pick(i: i64) -> any {
+                if i == 0 {
+                    return Point { x: 1, y: 2 };
+                }
+                2.5
+            }
+            fn main() -> i64 {
+                let p: Point = pick(0);
+                let x: any = 40;
+                print(pick(1) * 2.0);
+                p.y + x
+            }";
+        let (result, out) = run(source, OverflowMode::Trap);
+        assert_eq!(result.unwrap().to_string(), "42");
+        assert_eq!(out, "5\n");
+
+        let (result, _) = run(
+            "fn main() { let pair: [any; 2] = [1, 2]; let p: [f64; 2] = pair; }",
+            OverflowMode::Trap,
+        );
+        assert_eq!(
+            result.unwrap_err(),
+            "Runtime panic at 1:60: expected a value of type `[float; 2]`, but the `any` holds \
+             a `[int; 2]`"
+        );
+    }
+
+    #[test]
+    fn test_arena_profile() {
+        let source = "
+            struct Point { x: i64, y: i64 }
+            fn main() {
+                let mut i = 0;
+                while i < 3 {
+                    arena {
+                        let p = Point { x: i, y: 0 };
+                        let a = [p.x; 4];
+                        if i == 2 {
+                            let b = ~Normal(0.0, 1.0);
+                        }
+                    }
+                    i = i + 1;
+                }
+            }";
+        let (program, info) = crate::compile_until(source, crate::Phase::TypeCheck)
+            .unwrap()
+            .into_typed()
+            .unwrap();
+        let options = RunOptions {
+            profile_arenas: true,
+            ..RunOptions::default()
+        };
+        let mut out = Vec::new();
+        let mut interpreter = Interpreter::new(&program, &info, &options, &mut out);
+        interpreter.run_main().unwrap();
+        let profile = interpreter.arena_profile().unwrap();
+        let [stats] = profile.arenas.as_slice() else {
+            panic!("expected one arena, found {:?}", profile.arenas);
+        };
+        assert_eq!(stats.span, Span::new(6, 21));
+        assert_eq!(stats.entries, 3);
+        assert_eq!(stats.allocations, 7);
+        assert_eq!(stats.total_bytes, 3 * (16 + 32) + 16);
+        assert_eq!(stats.peak_bytes, 16 + 32 + 16);
+        assert!(profile.to_string().contains("6:21"), "{}", profile);
+    }
+
+    #[test]
+    fn test_defer_runs_on_every_exit() {
+        let source = "
+            struct File { fd: i64 }
+            fn close(f: File) { print(0 - f.fd); }
+            fn work(n: i64) -> i64 {
+                let a = File { fd: 1 };
+                defer close(a);
+                if n > 0 {
+                    let b = File { fd: 2 };
+                    defer close(b);
+                    return n;
+                }
+                let x = 5;
+                defer print(x);
+                let x = 6;
+                x
+            }
+            fn main() {
+                print(work(1));
+                print(work(0));
+                let mut count = 7;
+                defer print(count);
+                count = 8;
+                print(1 / (count - 8));
+            }";
+        let (result, out) = run(source, OverflowMode::Trap);
+        assert!(result.unwrap_err().contains("divide by zero"));
+        assert_eq!(out, "-2\n-1\n1\n5\n-1\n6\n8\n");
+    }
+
+    #[test]
+    fn test_panic_strategies_and_backtrace() {
+        let source = "
+            fn check(n: i64) -> i64 {
+                if n > 2 { panic(\"too big\"); }
+                n
+            }
+            fn main() -> i64 {
+                defer print(0);
+                let total = check(1) + check(3);
+                total
+            }";
+        let (result, out) = run(source, OverflowMode::Trap);
+        assert_eq!(result.unwrap_err(), "Runtime panic at 3:33: too big");
+        assert_eq!(out, "0\n");
+
+        let options = RunOptions {
+            panic: PanicStrategy::Abort,
+            backtrace: true,
+            ..RunOptions::default()
+        };
+        let (result, out) = run_with(source, &options);
+        assert_eq!(
+            result.unwrap_err(),
+            "Runtime panic at 3:33: too big\nstack backtrace:\n\
+             \x20  0: check\n             at 3:33\n\
+             \x20  1: main\n             at 8:45"
+        );
+        assert_eq!(out, "");
+    }
+
+    #[test]
+    fn test_arena_handles() {
+        let source = "
+            #![feature(arena_handles)]
+            struct Point { x: i64, y: i64 }
+            fn make(a: Arena, x: i64) -> Point { a.alloc(Point { x: x, y: 0 }) }
+            fn main() {
+                arena outer {
+                    let mut kept = Point { x: 0, y: 0 };
+                    let mut i = 0;
+                    while i < 3 {
+                        arena {
+                            let p = Point { x: i, y: 1 };
+                            kept = make(outer, p.x + p.y);
+                        }
+                        i = i + 1;
+                    }
+                    print(kept.x);
+                }
+            }";
+        let (program, info) = crate::compile_until(source, crate::Phase::TypeCheck)
+            .unwrap()
+            .into_typed()
+            .unwrap();
+        let options = RunOptions {
+            profile_arenas: true,
+            ..RunOptions::default()
+        };
+        let mut out = Vec::new();
+        let mut interpreter = Interpreter::new(&program, &info, &options, &mut out);
+        interpreter.run_main().unwrap();
+        let profile = interpreter.arena_profile().unwrap();
+        let [outer, inner] = profile.arenas.as_slice() else {
+            panic!("expected two arenas, found {:?}", profile.arenas);
+        };
+        assert_eq!((outer.entries, outer.allocations), (1, 4));
+        assert_eq!((inner.entries, inner.allocations), (3, 3));
+        drop(interpreter);
+        assert_eq!(String::from_utf8(out).unwrap(), "3\n");
+    }
+
+    #[test]
+    fn test_arena_checkpoints() {
+        let source = "
+            #![feature(arena_handles)]
+            struct Point { x: i64, y: i64 }
+            fn main() {
+                arena frame {
+                    let origin = Point { x: 10, y: 0 };
+                    let start = frame.checkpoint();
+                    let mut i = 0;
+                    while i < 3 {
+                        let p = Point { x: origin.x + i, y: 1 };
+                        let q = [p.x; 2];
+                        print(q[1]);
+                        frame.reset_to(start);
+                        i = i + 1;
+                    }
+                }
+            }";
+        let (program, info) = crate::compile_until(source, crate::Phase::TypeCheck)
+            .unwrap()
+            .into_typed()
+            .unwrap();
+        let options = RunOptions {
+            profile_arenas: true,
+            ..RunOptions::default()
+        };
+        let mut out = Vec::new();
+        let mut interpreter = Interpreter::new(&program, &info, &options, &mut out);
+        interpreter.run_main().unwrap();
+        let profile = interpreter.arena_profile().unwrap();
+        let [stats] = profile.arenas.as_slice() else {
+            panic!("expected one arena, found {:?}", profile.arenas);
+        };
+        assert_eq!(stats.allocations, 7);
+        assert_eq!(stats.total_bytes, 16 + 3 * (16 + 16));
+        // Each iteration reuses the memory of the one before
+        assert_eq!(stats.peak_bytes, 16 + 16 + 16);
+        drop(interpreter);
+        assert_eq!(String::from_utf8(out).unwrap(), "10\n11\n12\n");
+    }
+
+    #[test]
+    fn test_arena_allocators() {
+        let source = "
+            #![feature(arena_allocators)]
+            struct Pool { next: i64, end: i64 }
+            impl Pool {
+                fn allocate(&mut self, size: i64, align: i64) -> Option<i64> {
+                    if self.next + size > self.end { return None; }
+                    self.next = self.next + size;
+                    Some(self.next - size)
+                }
+                fn reset(&mut self) { print(self.next); }
+            }
+            struct Point { x: i64, y: i64 }
+            fn main() {
+                let mut pool = Pool { next: 64, end: 96 };
+                arena in pool {
+                    let p = Point { x: 1, y: 2 };
+                    let q = Point { x: 3, y: 4 };
+                    let r = Point { x: 5, y: 6 };
+                }
+            }";
+        let (result, out) = run(source, OverflowMode::Trap);
+        assert_eq!(
+            result.unwrap_err(),
+            "Runtime panic at 18:29: the allocator of the arena at 15:17 is out of memory \
+             (allocating 16 bytes)"
+        );
+        // `reset` runs however the arena ends
+        assert_eq!(out, "96\n");
+
+        let misaligned = source.replace("next: 64", "next: 68");
+        let (result, _) = run(&misaligned, OverflowMode::Trap);
+        assert_eq!(
+            result.unwrap_err(),
+            "Runtime panic at 16:29: the allocator of the arena at 15:17 returned address \
+             0x44 for 16 bytes, which is not aligned to 8"
+        );
+    }
+
+    #[test]
+    fn test_for_loops() {
+        let source = "
+            struct Countdown { n: i64 }
+            impl Countdown {
+                fn has_next(&self) -> bool { self.n > 0 }
+                fn next(&mut self) -> i64 { self.n = self.n - 1; self.n + 1 }
+            }
+            fn square(x: i64) -> i64 { print(x); x * x }
+            fn odd(x: i64) -> bool { x % 2 == 1 }
+            fn main() -> i64 {
+                for x in [1, 2, 3, 4, 5, 6].map(square).filter(odd).take(2) { print(-x); }
+                let mut countdown = Countdown { n: 5 };
+                for n in (&mut countdown).take(2) { print(n); }
+                let mut sum = 0;
+                for n in countdown { sum = sum + n; }
+                sum
+            }";
+        let (result, out) = run(source, OverflowMode::Trap);
+        assert_eq!(result.unwrap(), Value::Int(6));
+        // `take` ends the loop without pulling a fourth item through `map`
+        assert_eq!(out, "1\n-1\n2\n3\n-9\n5\n4\n");
+    }
+
+    #[test]
+    fn test_collections() {
+        let source = "
+            enum Color { Red, Green }
+            struct Bag { items: Vec<i64> }
+            fn fill(v: &mut Vec<i64>, n: i64) {
+                let mut i = 0;
+                while i < n { v.push(i * i); i = i + 1; }
+            }
+            fn main() -> i64 {
+                arena a {
+                    let mut v: Vec<i64> = Vec::new(a);
+                    fill(&mut v, 5);
+                    v[0] = 7;
+                    print(v);
+                    print(v.pop());
+                    print(v.len() * 100 + v.capacity());
+                    let mut bag = Bag { items: Vec::with_capacity(a, 1) };
+                    bag.items.push(3);
+                    bag.items.push(4);
+                    print(bag.items);
+                    let mut counts: Map<Color, i64> = Map::new(a);
+                    counts.insert(Color::Red, 1);
+                    counts.insert(Color::Green, 2);
+                    counts.insert(Color::Red, counts.get(Color::Red) + 10);
+                    print(counts.remove(Color::Green));
+                    print(counts.contains(Color::Green));
+                    print(counts);
+                    let mut sum = 0;
+                    for x in v { sum = sum + x; }
+                    counts.get(Color::Green) + sum
+                }
+            }";
+        let (result, out) = run(source, OverflowMode::Trap);
+        assert_eq!(
+            out,
+            "[7, 1, 4, 9, 16]\n16\n408\n[3, 4]\n2\nfalse\n{Color::Red: 11}\n"
+        );
+        let err = result.unwrap_err();
+        assert!(
+            err.contains("key `Color::Green` is not in the `Map`"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_io() {
+        let dir = std::env::temp_dir().join(format!("solo-interp-io-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("notes.txt");
+        let source = format!(
+            "fn main() {{
+                let path = {:?};
+                io::write_file(path, \"one\n\");
+                io::append_file(path, \"two\n\");
+                for line in io::lines(path) {{ io::print(line); }}
+                io::print(io::exists(path));
+                io::flush();
+                io::read_file({:?});
+            }}",
+            path.to_str().unwrap(),
+            dir.join("missing.txt").to_str().unwrap()
+        );
+        let (result, out) = run(&source, OverflowMode::Trap);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(out, "onetwotrue");
+        let err = result.unwrap_err();
+        assert!(
+            err.contains("cannot read `") && err.contains("missing.txt"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_strings() {
+        let source = "
+            fn main() {
+                let word = \"héllo\";
+                print(word.len() * 10 + word.char_count());
+                print(word.is_char_boundary(2));
+                for c in word.chars().take(2) { print(c); }
+                arena a {
+                    let mut buf = StringBuf::from(a, \"ab\");
+                    buf.push_str(word);
+                    print(buf.slice(2, 5));
+                    let mut bytes: Vec<i64> = Vec::new(a);
+                    bytes.push(111);
+                    bytes.push(107);
+                    print(StringBuf::from_utf8(a, bytes));
+                    let mut truncated: Vec<i64> = Vec::new(a);
+                    truncated.push(104);
+                    truncated.push(195);
+                    print(StringBuf::from_utf8(a, truncated));
+                }
+            }";
+        let (result, out) = run(source, OverflowMode::Trap);
+        assert_eq!(out, "65\nfalse\n104\n233\nhé\nok\n");
+        let err = result.unwrap_err();
+        assert!(err.contains("invalid UTF-8 sequence at byte 1"), "{}", err);
+        let (result, _) = run(
+            "fn main() { print(\"héllo\".slice(0, 2)); }",
+            OverflowMode::Trap,
+        );
+        let err = result.unwrap_err();
+        assert!(
+            err.contains("byte index 2 is not a char boundary"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_limits() {
+        let source = "
+            fn deep(n: i64) -> i64 { if n == 0 { 0 } else { deep(n - 1) + 1 } }
+            fn main() {
+                print(deep(10));
+                arena a {
+                    let mut v: Vec<i64> = Vec::new(a);
+                    let mut i = 0;
+                    while true { v.push(i); i = i + 1; }
+                }
+            }";
+        let program = crate::compile_until(source, crate::Phase::Parse)
+            .unwrap()
+            .into_ast()
+            .unwrap();
+        let info = typeck::check(&program).unwrap();
+        let limited = |limits| {
+            let options = RunOptions {
+                limits,
+                ..RunOptions::default()
+            };
+            let mut out = Vec::new();
+            let result = Interpreter::new(&program, &info, &options, &mut out).run_main();
+            match result {
+                Err(CompileError::LimitExceeded(exceeded)) => (exceeded, out),
+                other => panic!("not stopped by a limit: {:?}", other),
+            }
+        };
+
+        let (steps, out) = limited(Limits {
+            steps: Some(500),
+            ..Limits::default()
+        });
+        assert_eq!((steps.limit, steps.steps), (Limit::Steps(500), 500));
+        assert_eq!(out, b"10\n");
+        assert_eq!(
+            CompileError::LimitExceeded(steps.clone()).to_string(),
+            format!(
+                "Limit exceeded at {}:{}: the program ran for more than 500 steps",
+                steps.location.line, steps.location.column
+            )
+        );
+
+        let (memory, _) = limited(Limits {
+            memory: Some(4096),
+            ..Limits::default()
+        });
+        assert_eq!(
+            (memory.limit, memory.location.line),
+            (Limit::Memory(4096), 8)
+        );
+        let json = CompileError::LimitExceeded(memory).to_json(source);
+        assert_eq!(json[0].get("stage").and_then(Json::as_str), Some("limit"));
+        assert_eq!(json[0].get("limit").and_then(Json::as_str), Some("memory"));
+
+        let (depth, out) = limited(Limits {
+            depth: Some(5),
+            ..Limits::default()
+        });
+        assert_eq!(depth.limit, Limit::Depth(5));
+        assert_eq!(depth.location.line, 2);
+        assert!(out.is_empty());
+
+        let (time, _) = limited(Limits {
+            time: Some(Duration::from_millis(20)),
+            ..Limits::default()
+        });
+        assert_eq!(time.limit, Limit::Time(Duration::from_millis(20)));
+    }
+
+    #[test]
+    fn test_stack_overflow() {
+        let check = || {
+            let (result, out) = run(
+                "fn down(n: i64) -> i64 { return down(n + 1) + 1; }
+                fn main() { print(1); print(down(0)); }",
+                OverflowMode::Trap,
+            );
+            let err = result.unwrap_err();
+            assert!(
+                err.starts_with("Runtime panic at 1:37: stack overflow while calling `down`"),
+                "{}",
+                err
+            );
+            assert_eq!(out, "1\n");
+        };
+        std::thread::Builder::new()
+            .stack_size(parser::STACK_SIZE)
+            .spawn(check)
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+}
@@ -0,0 +1,329 @@
+//! Memory layout of Solo types
+//!
+//! Sizes and alignments follow the C ABI of the target; only pointers (and
+//! `isize`/`usize`) differ between targets, per [`DataLayout`]. Struct fields are laid out in order of decreasing alignment (ties keep
+//! declaration order), which never needs more padding than declaration
+//! order and is what code generation uses for field offsets. A [`Repr`]
+//! from `#[repr(...)]` keeps declaration order for C, caps field alignment
+//! for packed structs, or raises the struct's alignment.
+
+use std::fmt;
+
+use crate::target::Target;
+
+/// Size and alignment of a type, in bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Layout {
+    pub size: u64,
+    pub align: u64,
+}
+
+impl Layout {
+    pub const fn new(size: u64, align: u64) -> Self {
+        Self { size, align }
+    }
+
+    /// Pointer-sized scalar (references, `usize`) on 64-bit targets
+    pub const POINTER: Layout = Layout::new(8, 8);
+
+    /// Pointer and length (string slices) on 64-bit targets
+    pub const FAT_POINTER: Layout = Layout::new(16, 8);
+
+    /// `[T; len]`, or `None` if the size overflows
+    pub fn array(self, len: u64) -> Option<Layout> {
+        Some(Layout::new(self.size.checked_mul(len)?, self.align))
+    }
+}
+
+/// The target-dependent part of type layouts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DataLayout {
+    pub pointer_size: u64,
+}
+
+impl DataLayout {
+    /// 64-bit targets, and the interpreter
+    pub const LP64: DataLayout = DataLayout { pointer_size: 8 };
+
+    pub fn for_target(target: &Target) -> Self {
+        Self {
+            pointer_size: u64::from(target.pointer_width / 8),
+        }
+    }
+
+    pub fn pointer(&self) -> Layout {
+        Layout::new(self.pointer_size, self.pointer_size)
+    }
+
+    pub fn fat_pointer(&self) -> Layout {
+        Layout::new(2 * self.pointer_size, self.pointer_size)
+    }
+
+    /// Layout of a primitive type by name on this target
+    pub fn primitive(&self, name: &str) -> Option<Layout> {
+        match name {
+            "isize" | "usize" => Some(self.pointer()),
+            "str" | "String" => Some(self.fat_pointer()),
+            _ => primitive(name),
+        }
+    }
+}
+
+/// Layout of a primitive type by name on 64-bit targets
+pub fn primitive(name: &str) -> Option<Layout> {
+    let layout = match name {
+        "i8" | "u8" | "bool" => Layout::new(1, 1),
+        "i16" | "u16" => Layout::new(2, 2),
+        "i32" | "u32" | "f32" | "char" => Layout::new(4, 4),
+        "i64" | "u64" | "f64" | "isize" | "usize" => Layout::new(8, 8),
+        "str" | "String" => Layout::FAT_POINTER,
+        _ => return None,
+    };
+    Some(layout)
+}
+
+/// Smallest integer able to hold every discriminant of a field-less enum
+pub fn enum_layout(discriminants: &[i64]) -> Layout {
+    let min = discriminants.iter().copied().min().unwrap_or(0);
+    let max = discriminants.iter().copied().max().unwrap_or(0);
+    let fits = |lo: i64, hi: i64| min >= lo && max <= hi;
+    if fits(i8::MIN.into(), u8::MAX.into()) && (min >= 0 || max <= i8::MAX.into()) {
+        Layout::new(1, 1)
+    } else if fits(i16::MIN.into(), u16::MAX.into()) && (min >= 0 || max <= i16::MAX.into()) {
+        Layout::new(2, 2)
+    } else if fits(i32::MIN.into(), u32::MAX.into()) && (min >= 0 || max <= i32::MAX.into()) {
+        Layout::new(4, 4)
+    } else {
+        Layout::new(8, 8)
+    }
+}
+
+/// `Option<T>` of a `T` laid out as `value`: a one-byte tag, padded to the
+/// alignment of the value that follows it; `None` if the size overflows
+pub fn option_layout(value: Layout) -> Option<Layout> {
+    let align = value.align.max(1);
+    Some(Layout::new(value.size.checked_add(align)?, align))
+}
+
+/// Representation hints of a struct, from its `#[repr(...)]` attributes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Repr {
+    /// `C`: fields in declaration order, as a C compiler lays them out
+    pub c: bool,
+    /// `packed` or `packed(N)`: fields aligned to at most N bytes (1)
+    pub packed: Option<u64>,
+    /// `align(N)`: the struct aligned to at least N bytes
+    pub align: Option<u64>,
+}
+
+impl Repr {
+    /// Largest alignment `packed(N)` and `align(N)` accept
+    pub const MAX_ALIGN: u64 = 1 << 29;
+
+    /// Combine hints such as `C`, `packed`, `packed(2)` and `align(8)`
+    pub fn parse<'a>(hints: impl IntoIterator<Item = &'a str>) -> Result<Repr, String> {
+        let mut repr = Repr::default();
+        for hint in hints {
+            let (name, arg) = match hint.split_once('(') {
+                Some((name, rest)) => (name, Some(rest.trim_end_matches(')'))),
+                None => (hint, None),
+            };
+            let bytes = |arg: &str| match arg.parse::<u64>() {
+                Ok(n) if n.is_power_of_two() && n <= Repr::MAX_ALIGN => Ok(n),
+                _ => Err(format!(
+                    "`{}` takes a power of two no larger than 2^29, not `{}`",
+                    name, arg
+                )),
+            };
+            match (name, arg) {
+                ("C", None) => repr.c = true,
+                ("packed", None) => repr.packed = Some(1),
+                ("packed", Some(arg)) => repr.packed = Some(bytes(arg)?),
+                ("align", Some(arg)) => repr.align = Some(bytes(arg)?),
+                _ => {
+                    return Err(format!(
+                        "unrecognized representation hint `{}`; expected `C`, `packed`, \
+                         `packed(N)` or `align(N)`",
+                        hint
+                    ))
+                }
+            }
+        }
+        if repr.packed.is_some() && repr.align.is_some() {
+            return Err("a struct cannot be both `packed` and `align`ed".to_string());
+        }
+        Ok(repr)
+    }
+}
+
+impl fmt::Display for Repr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut hints = Vec::new();
+        if self.c {
+            hints.push("C".to_string());
+        }
+        match self.packed {
+            Some(1) => hints.push("packed".to_string()),
+            Some(n) => hints.push(format!("packed({})", n)),
+            None => {}
+        }
+        if let Some(n) = self.align {
+            hints.push(format!("align({})", n));
+        }
+        write!(f, "repr({})", hints.join(", "))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldLayout {
+    pub name: String,
+    pub offset: u64,
+    pub layout: Layout,
+}
+
+/// Struct layout with fields in declaration order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StructLayout {
+    pub name: String,
+    pub layout: Layout,
+    pub fields: Vec<FieldLayout>,
+    pub repr: Repr,
+}
+
+impl StructLayout {
+    /// Lay out `fields` (given in declaration order) as `repr` asks; `None`
+    /// on size overflow
+    pub fn compute(name: &str, fields: &[(String, Layout)], repr: Repr) -> Option<StructLayout> {
+        let field_align =
+            |layout: Layout| repr.packed.map_or(layout.align, |n| layout.align.min(n));
+        let mut order: Vec<usize> = (0..fields.len()).collect();
+        if !repr.c {
+            order.sort_by_key(|&i| std::cmp::Reverse(field_align(fields[i].1)));
+        }
+
+        let mut offsets = vec![0; fields.len()];
+        let mut offset: u64 = 0;
+        let mut align = repr.align.unwrap_or(1);
+        for i in order {
+            let field = fields[i].1;
+            offset = align_to(offset, field_align(field))?;
+            offsets[i] = offset;
+            offset = offset.checked_add(field.size)?;
+            align = align.max(field_align(field));
+        }
+
+        Some(StructLayout {
+            name: name.to_string(),
+            layout: Layout::new(align_to(offset, align)?, align),
+            repr,
+            fields: fields
+                .iter()
+                .zip(offsets)
+                .map(|((name, layout), offset)| FieldLayout {
+                    name: name.clone(),
+                    offset,
+                    layout: *layout,
+                })
+                .collect(),
+        })
+    }
+}
+
+impl fmt::Display for StructLayout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "struct {}", self.name)?;
+        if self.repr != Repr::default() {
+            write!(f, " ({})", self.repr)?;
+        }
+        write!(
+            f,
+            ": size {}, align {}",
+            self.layout.size, self.layout.align
+        )?;
+        let mut fields: Vec<&FieldLayout> = self.fields.iter().collect();
+        fields.sort_by_key(|field| field.offset);
+        for field in fields {
+            write!(
+                f,
+                "\n    {}: offset {}, size {}, align {}",
+                field.name, field.offset, field.layout.size, field.layout.align
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn align_to(offset: u64, align: u64) -> Option<u64> {
+    Some(offset.checked_add(align - 1)? / align * align)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_struct_layout_reorders_fields() {
+        let fields = vec![
+            ("flag".to_string(), primitive("bool").unwrap()),
+            ("value".to_string(), primitive("i64").unwrap()),
+            ("tag".to_string(), primitive("u16").unwrap()),
+        ];
+        let layout = StructLayout::compute("S", &fields, Repr::default()).unwrap();
+        assert_eq!(layout.layout, Layout::new(16, 8));
+        let offsets: Vec<u64> = layout.fields.iter().map(|f| f.offset).collect();
+        assert_eq!(offsets, [10, 0, 8]);
+
+        let wasm = DataLayout::for_target(&Target::find("wasm32-unknown-unknown").unwrap());
+        let fields = vec![
+            ("name".to_string(), wasm.primitive("str").unwrap()),
+            ("len".to_string(), wasm.primitive("usize").unwrap()),
+            ("id".to_string(), wasm.primitive("i64").unwrap()),
+        ];
+        let layout = StructLayout::compute("S", &fields, Repr::default()).unwrap();
+        assert_eq!(layout.layout, Layout::new(24, 8));
+    }
+
+    #[test]
+    fn test_repr() {
+        let fields = vec![
+            ("flag".to_string(), primitive("bool").unwrap()),
+            ("value".to_string(), primitive("i64").unwrap()),
+            ("tag".to_string(), primitive("u16").unwrap()),
+        ];
+        let layout_with = |hints: &[&str]| {
+            let repr = Repr::parse(hints.iter().copied()).unwrap();
+            let layout = StructLayout::compute("S", &fields, repr).unwrap();
+            let offsets: Vec<u64> = layout.fields.iter().map(|f| f.offset).collect();
+            (layout.layout, offsets)
+        };
+        assert_eq!(layout_with(&["C"]), (Layout::new(24, 8), vec![0, 8, 16]));
+        assert_eq!(
+            layout_with(&["C", "packed"]),
+            (Layout::new(11, 1), vec![0, 1, 9])
+        );
+        assert_eq!(
+            layout_with(&["C", "packed(2)"]),
+            (Layout::new(12, 2), vec![0, 2, 10])
+        );
+        assert_eq!(
+            layout_with(&["align(32)"]),
+            (Layout::new(32, 32), vec![10, 0, 8])
+        );
+        assert_eq!(
+            Repr::parse(["C", "align(16)"]).unwrap().to_string(),
+            "repr(C, align(16))"
+        );
+        assert!(Repr::parse(["align(3)"])
+            .unwrap_err()
+            .contains("power of two"));
+        assert!(Repr::parse(["packed", "align(8)"]).is_err());
+        assert!(Repr::parse(["Rust"]).is_err());
+    }
+
+    #[test]
+    fn test_enum_layout() {
+        assert_eq!(enum_layout(&[0, 1, 255]), Layout::new(1, 1));
+        assert_eq!(enum_layout(&[-1, 200]), Layout::new(2, 2));
+        assert_eq!(enum_layout(&[0, 1 << 40]), Layout::new(8, 8));
+    }
+}
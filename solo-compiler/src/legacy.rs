@@ -0,0 +1,503 @@
+//! Front-end for legacy Me files
+//!
+//! Me, the epistemic dialect of the My Language family, predates Solo. To
+//! let a codebase move over one file at a time, `.my` and `.mylang` files
+//! (or any file given `--dialect legacy`) are lowered to Solo source and
+//! then go through the Solo pipeline like any other file. Lowering keeps
+//! every token on its line, so diagnostics point at the right line of the
+//! Me source; comments are dropped.
+//!
+//! | Me                                       | Solo                                  |
+//! |------------------------------------------|---------------------------------------|
+//! | top-level statements                     | the body of `fn main()`               |
+//! | `belief x: Float where confidence(0.8);` | `let mut x: Belief<f64>;`             |
+//! | `x ~ Normal(0.0, 1.0);`                  | `x =~ Normal(0.0, 1.0);`              |
+//! | `y := e;`                                | `let mut y = e;` (`y = e;` once bound) |
+//! | `confidence(x)`                          | the confidence `x` was declared with  |
+//! | `print(a, b);`                           | `print(a); print(b);`                 |
+//! | `Bool`, `Float`, `Int`, `String`         | `bool`, `f64`, `i64`, `str`           |
+//!
+//! Trust declarations, observations and belief fusion have no Solo
+//! equivalent yet and are reported as errors.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::ast::Span;
+use crate::error::{CompileError, Diagnostic};
+use crate::lexer::Lexer;
+use crate::symbol::Symbol;
+use crate::token::{Token, TokenKind};
+
+/// Me functions without a Solo equivalent
+const UNSUPPORTED: &[&str] = &[
+    "trust",
+    "observe",
+    "fuse",
+    "fuse_all",
+    "weighted_fuse",
+    "source",
+];
+
+/// The syntax a source file is written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Dialect {
+    #[default]
+    Solo,
+    /// Me, lowered by [`lower`]
+    Legacy,
+}
+
+impl Dialect {
+    /// `solo`, or `legacy` (also called `me`)
+    pub fn parse(name: &str) -> Option<Dialect> {
+        match name {
+            "solo" => Some(Dialect::Solo),
+            "legacy" | "me" => Some(Dialect::Legacy),
+            _ => None,
+        }
+    }
+
+    /// The dialect of a file, told by its extension
+    pub fn for_path(path: &Path) -> Dialect {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("my" | "mylang") => Dialect::Legacy,
+            _ => Dialect::Solo,
+        }
+    }
+
+    /// `source` as Solo source
+    pub fn to_solo(self, source: &str) -> Result<String, CompileError> {
+        match self {
+            Dialect::Solo => Ok(source.to_string()),
+            Dialect::Legacy => lower(source),
+        }
+    }
+}
+
+/// Lower Me source to Solo source with the same lines
+pub fn lower(source: &str) -> Result<String, CompileError> {
+    let mut lexer = Lexer::new(&strip_comments(source));
+    let mut tokens = Vec::new();
+    loop {
+        let token = lexer.next_token();
+        match &token.kind {
+            TokenKind::Eof => break,
+            TokenKind::Error(msg) => {
+                let span = Span::new(token.line, token.column);
+                return Err(CompileError::Lex(vec![Diagnostic::new(span, msg.as_str())]));
+            }
+            _ => tokens.push(token),
+        }
+    }
+
+    let mut lowering = Lowering {
+        confidences: HashMap::new(),
+        bound: Vec::new(),
+        errors: Vec::new(),
+    };
+    let body = lowering.block(&tokens);
+    if !lowering.errors.is_empty() {
+        lowering.errors.sort();
+        return Err(CompileError::Parse(lowering.errors));
+    }
+
+    let (line, column) = tokens.first().map_or((1, 1), |t| (t.line, t.column));
+    let at = |kind, lexeme: &str| Token::new(kind, lexeme, line, column);
+    let mut program = vec![
+        at(TokenKind::Fn, "fn"),
+        at(TokenKind::Identifier("main".into()), "main"),
+        at(TokenKind::LParen, "("),
+        at(TokenKind::RParen, ")"),
+        at(TokenKind::LBrace, "{"),
+    ];
+    program.extend(body);
+    let end = program.last().map_or((1, 1), |t| (t.line, t.column + 1));
+    program.push(Token::new(TokenKind::RBrace, "}", end.0, end.1));
+    Ok(render(&program))
+}
+
+/// `#` comments turned into `//` comments, which the Solo lexer skips
+fn strip_comments(source: &str) -> String {
+    let mut out = String::with_capacity(source.len() + 16);
+    let mut in_string = false;
+    let mut in_comment = false;
+    for c in source.chars() {
+        match c {
+            '\n' => {
+                in_comment = false;
+                in_string = false;
+            }
+            '"' if !in_comment => in_string = !in_string,
+            '#' if !in_string && !in_comment => {
+                in_comment = true;
+                out.push_str("//");
+                continue;
+            }
+            _ => {}
+        }
+        out.push(c);
+    }
+    out
+}
+
+struct Lowering {
+    /// Confidence expression of each belief declared with one
+    confidences: HashMap<Symbol, Vec<Token>>,
+    /// Names bound by `belief` or `:=`
+    bound: Vec<Symbol>,
+    errors: Vec<Diagnostic>,
+}
+
+impl Lowering {
+    fn error(&mut self, token: &Token, message: impl Into<String>) {
+        self.errors.push(Diagnostic::new(
+            Span::new(token.line, token.column),
+            message,
+        ));
+    }
+
+    /// Lower the statements of a block
+    fn block(&mut self, tokens: &[Token]) -> Vec<Token> {
+        let mut out = Vec::new();
+        let mut start = 0;
+        let mut depth = 0usize;
+        for (i, token) in tokens.iter().enumerate() {
+            match token.kind {
+                TokenKind::LParen | TokenKind::LBracket | TokenKind::LBrace => depth += 1,
+                TokenKind::RParen | TokenKind::RBracket => depth = depth.saturating_sub(1),
+                TokenKind::RBrace => {
+                    depth = depth.saturating_sub(1);
+                    let continues = tokens.get(i + 1).is_some_and(|t| t.kind == TokenKind::Else);
+                    if depth == 0 && !continues {
+                        self.statement(&tokens[start..=i], &mut out);
+                        start = i + 1;
+                    }
+                }
+                TokenKind::Semicolon if depth == 0 => {
+                    self.statement(&tokens[start..=i], &mut out);
+                    start = i + 1;
+                }
+                _ => {}
+            }
+        }
+        if start < tokens.len() {
+            // A trailing expression, the value of the block
+            self.statement(&tokens[start..], &mut out);
+        }
+        out
+    }
+
+    fn statement(&mut self, stmt: &[Token], out: &mut Vec<Token>) {
+        let name = match stmt.first().map(|t| &t.kind) {
+            Some(&TokenKind::Identifier(name)) => Some(name),
+            _ => None,
+        };
+        let second = stmt.get(1).map(|t| &t.kind);
+        match (&stmt[0].kind, name, second) {
+            (TokenKind::Belief, _, _) => self.belief(stmt, out),
+            // `x ~ D(...)`
+            (_, Some(_), Some(TokenKind::Tilde)) => {
+                out.push(stmt[0].clone());
+                out.push(like(&stmt[1], TokenKind::Eq, "="));
+                self.expression(&stmt[1..], out);
+            }
+            // `x := e`
+            (_, Some(name), Some(TokenKind::Colon))
+                if stmt.get(2).is_some_and(|t| t.kind == TokenKind::Eq) =>
+            {
+                if !self.bound.contains(&name) {
+                    self.bound.push(name);
+                    out.push(like(&stmt[0], TokenKind::Let, "let"));
+                    out.push(like(&stmt[0], TokenKind::Mut, "mut"));
+                }
+                out.push(stmt[0].clone());
+                self.expression(&stmt[2..], out);
+            }
+            (_, Some(name), Some(TokenKind::LParen)) if name == "print" => self.print(stmt, out),
+            _ => self.expression(stmt, out),
+        }
+    }
+
+    /// `belief x: T [where confidence(c)];`
+    fn belief(&mut self, stmt: &[Token], out: &mut Vec<Token>) {
+        let [belief, name_token, colon, type_token, rest @ ..] = stmt else {
+            self.error(&stmt[0], "expected `belief <name>: <Type>;`");
+            return;
+        };
+        let (TokenKind::Identifier(name), TokenKind::Colon, TokenKind::Identifier(ty)) =
+            (&name_token.kind, &colon.kind, &type_token.kind)
+        else {
+            self.error(belief, "expected `belief <name>: <Type>;`");
+            return;
+        };
+        let Some(solo_type) = solo_type(ty) else {
+            self.error(
+                type_token,
+                format!(
+                    "unknown Me type `{}`; expected Bool, Float, Int or String",
+                    ty
+                ),
+            );
+            return;
+        };
+        match rest {
+            [semicolon] if semicolon.kind == TokenKind::Semicolon => {}
+            [where_token, property, open, confidence @ .., close, semicolon]
+                if where_token.kind == TokenKind::Where
+                    && property.kind == TokenKind::Identifier("confidence".into())
+                    && open.kind == TokenKind::LParen
+                    && close.kind == TokenKind::RParen
+                    && semicolon.kind == TokenKind::Semicolon
+                    && !confidence.is_empty() =>
+            {
+                self.confidences.insert(*name, confidence.to_vec());
+            }
+            _ => {
+                self.error(
+                    belief,
+                    "expected `belief <name>: <Type> where confidence(<value>);`",
+                );
+                return;
+            }
+        }
+        self.bound.push(*name);
+
+        out.push(like(belief, TokenKind::Let, "let"));
+        out.push(like(belief, TokenKind::Mut, "mut"));
+        out.push(name_token.clone());
+        out.push(colon.clone());
+        let identifier = |lexeme: &str| TokenKind::Identifier(lexeme.into());
+        out.push(like(type_token, identifier("Belief"), "Belief"));
+        out.push(like(type_token, TokenKind::Lt, "<"));
+        out.push(like(type_token, identifier(solo_type), solo_type));
+        out.push(like(type_token, TokenKind::Gt, ">"));
+        out.push(like(type_token, TokenKind::Semicolon, ";"));
+    }
+
+    /// `print(a, b, ...);` as one `print` per argument
+    fn print(&mut self, stmt: &[Token], out: &mut Vec<Token>) {
+        let close = stmt
+            .iter()
+            .rposition(|t| t.kind == TokenKind::RParen)
+            .unwrap_or(stmt.len());
+        let args = &stmt[2..close];
+        let mut depth = 0usize;
+        let mut start = 0;
+        let mut pieces = Vec::new();
+        for (i, token) in args.iter().enumerate() {
+            match token.kind {
+                TokenKind::LParen | TokenKind::LBracket | TokenKind::LBrace => depth += 1,
+                TokenKind::RParen | TokenKind::RBracket | TokenKind::RBrace => {
+                    depth = depth.saturating_sub(1)
+                }
+                TokenKind::Comma if depth == 0 => {
+                    pieces.push(&args[start..i]);
+                    start = i + 1;
+                }
+                _ => {}
+            }
+        }
+        pieces.push(&args[start..]);
+        if pieces.len() == 1 {
+            return self.expression(stmt, out);
+        }
+        for (i, piece) in pieces.into_iter().filter(|p| !p.is_empty()).enumerate() {
+            // The first `print(` stays where it was, the others go before
+            // their argument
+            if i == 0 {
+                out.extend(stmt[..2].iter().cloned());
+            } else {
+                out.push(like(&piece[0], stmt[0].kind.clone(), "print"));
+                out.push(like(&piece[0], TokenKind::LParen, "("));
+            }
+            self.expression(piece, out);
+            // After what the argument became, which may have been moved
+            let end = out.last().unwrap_or(&piece[piece.len() - 1]).clone();
+            out.push(like(&end, TokenKind::RParen, ")"));
+            out.push(like(&end, TokenKind::Semicolon, ";"));
+        }
+    }
+
+    /// Copy an expression (or statement), replacing `confidence(x)` and
+    /// lowering nested blocks
+    fn expression(&mut self, tokens: &[Token], out: &mut Vec<Token>) {
+        let mut i = 0;
+        while i < tokens.len() {
+            let token = &tokens[i];
+            let called = tokens
+                .get(i + 1)
+                .is_some_and(|t| t.kind == TokenKind::LParen);
+            match &token.kind {
+                TokenKind::Identifier(name) if called && name == "confidence" => {
+                    let belief = match (tokens.get(i + 2), tokens.get(i + 3)) {
+                        (Some(argument), Some(close)) if close.kind == TokenKind::RParen => {
+                            match &argument.kind {
+                                TokenKind::Identifier(belief) => Some(*belief),
+                                _ => None,
+                            }
+                        }
+                        _ => None,
+                    };
+                    let Some(belief) = belief else {
+                        self.error(token, "`confidence` takes the name of a belief");
+                        i += 1;
+                        continue;
+                    };
+                    match self.confidences.get(&belief) {
+                        Some(confidence) => {
+                            out.push(like(token, TokenKind::LParen, "("));
+                            // Moved here, so the rendering keeps them on this line
+                            out.extend(
+                                confidence
+                                    .iter()
+                                    .map(|t| like(token, t.kind.clone(), &t.lexeme)),
+                            );
+                            out.push(like(token, TokenKind::RParen, ")"));
+                        }
+                        None => {
+                            self.error(token, format!("`{}` has no declared confidence", belief))
+                        }
+                    }
+                    i += 4;
+                    continue;
+                }
+                TokenKind::Identifier(name) if called && UNSUPPORTED.contains(&name.as_str()) => {
+                    self.error(
+                        token,
+                        format!(
+                            "`{}` has no Solo equivalent yet and cannot be lowered",
+                            name
+                        ),
+                    );
+                }
+                TokenKind::LBrace => {
+                    let close = matching_brace(tokens, i);
+                    out.push(token.clone());
+                    let body = self.block(&tokens[i + 1..close]);
+                    out.extend(body);
+                    if let Some(close) = tokens.get(close) {
+                        out.push(close.clone());
+                    }
+                    i = close + 1;
+                    continue;
+                }
+                _ => {}
+            }
+            out.push(token.clone());
+            i += 1;
+        }
+    }
+}
+
+/// Index of the `}` closing the `{` at `open`, or the end of `tokens`
+fn matching_brace(tokens: &[Token], open: usize) -> usize {
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate().skip(open) {
+        match token.kind {
+            TokenKind::LBrace => depth += 1,
+            TokenKind::RBrace => {
+                depth -= 1;
+                if depth == 0 {
+                    return i;
+                }
+            }
+            _ => {}
+        }
+    }
+    tokens.len()
+}
+
+fn solo_type(me: &str) -> Option<&'static str> {
+    match me {
+        "Bool" => Some("bool"),
+        "Float" => Some("f64"),
+        "Int" => Some("i64"),
+        "String" => Some("str"),
+        _ => None,
+    }
+}
+
+/// A token generated in place of `at`
+fn like(at: &Token, kind: TokenKind, lexeme: &str) -> Token {
+    Token::new(kind, lexeme, at.line, at.column)
+}
+
+/// Source text with each token on its line, keeping the indentation of
+/// lines and the spaces between tokens that had them
+fn render(tokens: &[Token]) -> String {
+    let mut out = String::new();
+    let mut line = 1;
+    let mut previous: Option<&Token> = None;
+    for token in tokens {
+        let newlines = token.lexeme.matches('\n').count();
+        let start_line = token.line - newlines;
+        match previous {
+            Some(previous) if start_line <= line => {
+                let gap = token.column > previous.column + previous.lexeme.chars().count();
+                if gap || crate::minify::needs_space(&previous.lexeme, &token.lexeme) {
+                    out.push(' ');
+                }
+            }
+            _ => {
+                out.push_str(&"\n".repeat(start_line.saturating_sub(line)));
+                out.push_str(&" ".repeat(token.column - 1));
+                line = line.max(start_line);
+            }
+        }
+        out.push_str(&token.lexeme);
+        line += newlines;
+        previous = Some(token);
+    }
+    out.push('\n');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lower() {
+        let source = "\
+# Newsroom check
+belief claim: Bool where confidence(0.9);
+claim ~ Bernoulli(0.8);  # prior
+threshold := 0.85;
+if confidence(claim) >= threshold {
+    print(\"PUBLISH #1:\", expectation(claim));
+} else {
+    print(\"HOLD\");
+}
+threshold := 0.5;
+";
+        let lowered = lower(source).unwrap();
+        assert_eq!(
+            lowered,
+            "
+fn main(){let mut claim: Belief<bool>;
+claim =~ Bernoulli(0.8);
+let mut threshold = 0.85;
+if (0.9) >= threshold {
+    print(\"PUBLISH #1:\"); print(expectation(claim));
+} else {
+    print(\"HOLD\");
+}
+threshold = 0.5;}
+"
+        );
+        assert!(crate::check(&lowered).is_ok());
+
+        let err = lower("belief x: Real;\ny := fuse(x, x);\nprint(confidence(x));").unwrap_err();
+        let messages: Vec<String> = err.diagnostics().iter().map(|d| d.to_string()).collect();
+        assert_eq!(
+            messages,
+            [
+                "1:11: unknown Me type `Real`; expected Bool, Float, Int or String",
+                "2:6: `fuse` has no Solo equivalent yet and cannot be lowered",
+                "3:7: `x` has no declared confidence",
+            ]
+        );
+        assert_eq!(Dialect::for_path(Path::new("a.mylang")), Dialect::Legacy);
+    }
+}
@@ -2,13 +2,19 @@
 ///
 /// Converts source code into a stream of tokens.
 
-use crate::token::{Token, TokenKind};
+use crate::numeric::Numeric;
+use crate::symbol::Symbol;
+use crate::token::{Token, TokenKind, Trivia};
+use crate::unicode;
 
 pub struct Lexer {
     input: Vec<char>,
     position: usize,
     line: usize,
     column: usize,
+    token_column: usize,
+    /// The text of the token being lexed, reused from token to token
+    scratch: String,
 }
 
 impl Lexer {
@@ -18,18 +24,22 @@ pub fn new(input: &str) -> Self {
             position: 0,
             line: 1,
             column: 1,
+            token_column: 1,
+            scratch: String::new(),
         }
     }
 
     pub fn next_token(&mut self) -> Token {
-        self.skip_whitespace();
+        while self.skip_trivia().is_some() {}
 
         if self.is_at_end() {
+            self.token_column = self.column;
             return self.make_token(TokenKind::Eof, "");
         }
 
         let ch = self.current_char();
         let start_column = self.column;
+        self.token_column = start_column;
 
         match ch {
             // Single-character tokens
@@ -41,13 +51,13 @@ pub fn next_token(&mut self) -> Token {
             ']' => self.single_char_token(TokenKind::RBracket),
             ',' => self.single_char_token(TokenKind::Comma),
             ';' => self.single_char_token(TokenKind::Semicolon),
-            '.' => self.single_char_token(TokenKind::Dot),
             '+' => self.single_char_token(TokenKind::Plus),
             '*' => self.single_char_token(TokenKind::Star),
             '%' => self.single_char_token(TokenKind::Percent),
             '~' => self.single_char_token(TokenKind::Tilde),
-            '&' => self.single_char_token(TokenKind::Ampersand),
-            '|' => self.single_char_token(TokenKind::Pipe),
+            '$' => self.single_char_token(TokenKind::Dollar),
+            '?' => self.single_char_token(TokenKind::Question),
+            '#' => self.single_char_token(TokenKind::Pound),
 
             // Multi-character tokens
             '-' => {
@@ -99,6 +109,38 @@ pub fn next_token(&mut self) -> Token {
                     self.single_char_token(TokenKind::Gt)
                 }
             }
+            '&' => {
+                if self.peek() == '&' {
+                    self.advance();
+                    self.advance();
+                    self.make_token(TokenKind::And, "&&")
+                } else {
+                    self.single_char_token(TokenKind::Ampersand)
+                }
+            }
+            '|' => {
+                if self.peek() == '|' {
+                    self.advance();
+                    self.advance();
+                    self.make_token(TokenKind::Or, "||")
+                } else {
+                    self.single_char_token(TokenKind::Pipe)
+                }
+            }
+            '.' => {
+                if self.peek() != '.' {
+                    self.single_char_token(TokenKind::Dot)
+                } else if self.input.get(self.position + 2) == Some(&'=') {
+                    self.advance();
+                    self.advance();
+                    self.advance();
+                    self.make_token(TokenKind::DotDotEq, "..=")
+                } else {
+                    self.advance();
+                    self.advance();
+                    self.make_token(TokenKind::DotDot, "..")
+                }
+            }
             ':' => {
                 if self.peek() == ':' {
                     self.advance();
@@ -109,18 +151,7 @@ pub fn next_token(&mut self) -> Token {
                 }
             }
 
-            // Comments
-            '/' => {
-                if self.peek() == '/' {
-                    self.skip_line_comment();
-                    return self.next_token();
-                } else if self.peek() == '*' {
-                    self.skip_block_comment();
-                    return self.next_token();
-                } else {
-                    self.single_char_token(TokenKind::Slash)
-                }
-            }
+            '/' => self.single_char_token(TokenKind::Slash),
 
             // String literals
             '"' => self.string_literal(),
@@ -129,14 +160,14 @@ pub fn next_token(&mut self) -> Token {
             '0'..='9' => self.number_literal(),
 
             // Identifiers and keywords
-            'a'..='z' | 'A'..='Z' | '_' => self.identifier(),
+            c if unicode::is_ident_start(c) => self.identifier(),
 
             _ => {
                 let msg = format!("Unexpected character: '{}'", ch);
                 self.advance();
                 Token::new(
                     TokenKind::Error(msg.clone()),
-                    msg,
+                    msg.as_str(),
                     self.line,
                     start_column,
                 )
@@ -144,6 +175,34 @@ pub fn next_token(&mut self) -> Token {
         }
     }
 
+    /// Skip the run of whitespace or the comment at the current position,
+    /// if there is one
+    pub fn skip_trivia(&mut self) -> Option<Trivia> {
+        if self.is_at_end() {
+            return None;
+        }
+        match (self.current_char(), self.peek()) {
+            (' ' | '\r' | '\t' | '\n', _) => {
+                self.skip_whitespace();
+                Some(Trivia::Whitespace)
+            }
+            ('/', '/') => {
+                self.skip_line_comment();
+                Some(Trivia::Comment)
+            }
+            ('/', '*') => {
+                self.skip_block_comment();
+                Some(Trivia::Comment)
+            }
+            _ => None,
+        }
+    }
+
+    /// Index of the next character to lex
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
     fn single_char_token(&mut self, kind: TokenKind) -> Token {
         let ch = self.current_char();
         self.advance();
@@ -158,11 +217,20 @@ fn identifier(&mut self) -> Token {
             self.advance();
         }
 
-        let lexeme: String = self.input[start..self.position].iter().collect();
-
-        let kind = Token::is_keyword(&lexeme).unwrap_or_else(|| TokenKind::Identifier(lexeme.clone()));
+        let raw = self.text(start, self.position);
+        let kind = Token::is_keyword(raw);
+        let lexeme = if raw.is_ascii() {
+            Symbol::intern(raw)
+        } else {
+            Symbol::intern(&unicode::nfc(raw))
+        };
 
-        Token::new(kind, lexeme, self.line, start_column)
+        Token::new(
+            kind.unwrap_or(TokenKind::Identifier(lexeme)),
+            lexeme,
+            self.line,
+            start_column,
+        )
     }
 
     fn number_literal(&mut self) -> Token {
@@ -174,20 +242,42 @@ fn number_literal(&mut self) -> Token {
         }
 
         // Check for float
-        if !self.is_at_end() && self.current_char() == '.' && self.peek().is_ascii_digit() {
+        let point = !self.is_at_end() && self.current_char() == '.' && self.peek().is_ascii_digit();
+        if point {
             self.advance(); // consume '.'
             while !self.is_at_end() && self.current_char().is_ascii_digit() {
                 self.advance();
             }
-
-            let lexeme: String = self.input[start..self.position].iter().collect();
-            let value = lexeme.parse::<f64>().unwrap();
-            Token::new(TokenKind::Float(value), lexeme, self.line, start_column)
-        } else {
-            let lexeme: String = self.input[start..self.position].iter().collect();
-            let value = lexeme.parse::<i64>().unwrap();
-            Token::new(TokenKind::Integer(value), lexeme, self.line, start_column)
         }
+        let digits_end = self.position;
+        while !self.is_at_end()
+            && (self.current_char().is_ascii_alphanumeric() || self.current_char() == '_')
+        {
+            self.advance();
+        }
+
+        let digits = self.text(start, digits_end).to_string();
+        let lexeme = self.text(start, self.position);
+        let suffix = &lexeme[digits.len()..];
+        let kind = match (suffix.is_empty(), Numeric::parse(suffix)) {
+            (false, None) => {
+                TokenKind::Error(format!("invalid suffix `{}` for number literal", suffix))
+            }
+            (_, Some(ty)) if point && !ty.is_float() => TokenKind::Error(format!(
+                "invalid suffix `{}` for float literal; cast it with `as {}`",
+                suffix, suffix
+            )),
+            (_, ty) if point || ty.is_some_and(Numeric::is_float) => {
+                // Digits around a `.` always parse, to infinity if too large
+                TokenKind::Float(digits.parse::<f64>().unwrap_or(f64::INFINITY), ty)
+            }
+            (_, ty) => match digits.parse::<i64>() {
+                Ok(value) => TokenKind::Integer(value, ty),
+                Err(_) => TokenKind::Error(format!("Integer literal too large: {}", lexeme)),
+            },
+        };
+        let lexeme = Symbol::intern(lexeme);
+        Token::new(kind, lexeme, self.line, start_column)
     }
 
     fn string_literal(&mut self) -> Token {
@@ -206,21 +296,18 @@ fn string_literal(&mut self) -> Token {
         if self.is_at_end() {
             return Token::new(
                 TokenKind::Error("Unterminated string".to_string()),
-                "".to_string(),
+                "",
                 self.line,
                 start_column,
             );
         }
 
-        let value: String = self.input[start..self.position].iter().collect();
+        let value = Symbol::intern(self.text(start, self.position));
         self.advance(); // consume closing "
 
-        Token::new(
-            TokenKind::String(value.clone()),
-            format!("\"{}\"", value),
-            self.line,
-            start_column,
-        )
+        // The quotes included
+        let lexeme = Symbol::intern(self.text(start - 1, self.position));
+        Token::new(TokenKind::String(value), lexeme, self.line, start_column)
     }
 
     fn skip_whitespace(&mut self) {
@@ -262,7 +349,7 @@ fn skip_block_comment(&mut self) {
     }
 
     fn is_identifier_char(&self, ch: char) -> bool {
-        ch.is_alphanumeric() || ch == '_'
+        unicode::is_ident_continue(ch)
     }
 
     fn current_char(&self) -> char {
@@ -287,13 +374,125 @@ fn is_at_end(&self) -> bool {
     }
 
     fn make_token(&self, kind: TokenKind, lexeme: &str) -> Token {
-        Token::new(kind, lexeme.to_string(), self.line, self.column)
+        Token::new(kind, lexeme, self.line, self.token_column)
+    }
+
+    /// The input from `start` to `end`
+    fn text(&mut self, start: usize, end: usize) -> &str {
+        self.scratch.clear();
+        self.scratch.extend(&self.input[start..end]);
+        &self.scratch
     }
 }
 
+/// The type the suffix of a number literal names, as `u8` in `5u8`
+pub fn suffix(lexeme: &str) -> Option<Numeric> {
+    Numeric::parse(lexeme.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.'))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::token;
+    use proptest::prelude::*;
+
+    /// Tokens of `source` without `Eof`
+    fn lex(source: &str) -> Vec<Token> {
+        let mut lexer = Lexer::new(source);
+        std::iter::from_fn(|| Some(lexer.next_token()))
+            .take_while(|token| token.kind != TokenKind::Eof)
+            .collect()
+    }
+
+    fn kinds(tokens: &[Token]) -> Vec<TokenKind> {
+        tokens.iter().map(|token| token.kind.clone()).collect()
+    }
+
+    /// Any token the lexer can produce, other than errors
+    fn any_token() -> impl Strategy<Value = TokenKind> {
+        let fixed = [
+            "fn",
+            "let",
+            "mut",
+            "if",
+            "else",
+            "while",
+            "return",
+            "become",
+            "struct",
+            "enum",
+            "impl",
+            "trait",
+            "type",
+            "newtype",
+            "arena",
+            "defer",
+            "extern",
+            "pub",
+            "belief",
+            "where",
+            "macro",
+            "const",
+            "unsafe",
+            "as",
+            "true",
+            "false",
+            "+ - * / % = == != < <= > >= && || ! -> => & | ~",
+            "( ) { } [ ] , ; : :: . $ ? #",
+        ]
+        .iter()
+        .flat_map(|text| lex(text))
+        .map(|token| token.kind)
+        .collect::<Vec<_>>();
+        prop_oneof![
+            proptest::sample::select(fixed),
+            (0..=i64::MAX).prop_map(|n| TokenKind::Integer(n, None)),
+            (0.0..1e300f64).prop_map(|x| TokenKind::Float(x, None)),
+            "[^\"]*".prop_map(|text| TokenKind::String(text.as_str().into())),
+            "[a-zA-Z_\u{e9}\u{3b1}][a-zA-Z0-9_\u{e9}\u{3b1}]*"
+                .prop_filter("keywords are not identifiers", |name| {
+                    Token::is_keyword(name).is_none()
+                })
+                .prop_map(|name| TokenKind::Identifier(name.as_str().into())),
+        ]
+    }
+
+    /// Text of the characters tokens and comments are made of, with string
+    /// literals
+    fn source() -> impl Strategy<Value = String> {
+        let text = r"[ \t\na-zA-Z0-9_.+*/%=<>!&|~(){}\[\],;:$?#\u{e9}-]{1,16}";
+        let fragment = prop_oneof![text, r#""[^"]*""#];
+        prop::collection::vec(fragment, 0..16).prop_map(|fragments| fragments.concat())
+    }
+
+    proptest! {
+        #[test]
+        fn test_printed_tokens_lex_back(expected in prop::collection::vec(any_token(), 0..64)) {
+            let tokens: Vec<Token> = expected
+                .iter()
+                .map(|kind| Token::new(kind.clone(), "", 1, 1))
+                .collect();
+            prop_assert_eq!(kinds(&lex(&token::print(&tokens))), expected);
+        }
+
+        #[test]
+        fn test_printed_source_lexes_the_same(source in source()) {
+            let tokens = lex(&source);
+            let errors = tokens.iter().any(|t| matches!(t.kind, TokenKind::Error(_)));
+            prop_assume!(!errors);
+            prop_assert_eq!(kinds(&lex(&token::print(&tokens))), kinds(&tokens));
+        }
+    }
+
+    #[test]
+    fn test_print() {
+        let source = "fn main() { let x = 1.0; if x > 0.5 { print(\"big\"); } }";
+        assert_eq!(
+            token::print(&lex(source)),
+            "fn main ( ) {\n    let x = 1.0 ;\n    if x > 0.5 {\n        \
+             print ( \"big\" ) ;\n    }\n}\n"
+        );
+    }
 
     #[test]
     fn test_keywords() {
@@ -308,11 +507,46 @@ fn test_keywords() {
     }
 
     #[test]
+    #[allow(clippy::approx_constant)]
     fn test_numbers() {
-        let mut lexer = Lexer::new("42 3.14 0");
-        assert!(matches!(lexer.next_token().kind, TokenKind::Integer(42)));
-        assert!(matches!(lexer.next_token().kind, TokenKind::Float(f) if (f - 3.14).abs() < 1e-6));
-        assert!(matches!(lexer.next_token().kind, TokenKind::Integer(0)));
+        let mut lexer = Lexer::new("42 3.14 0 7u8 1f32 0.5f32");
+        assert!(matches!(
+            lexer.next_token().kind,
+            TokenKind::Integer(42, None)
+        ));
+        assert!(
+            matches!(lexer.next_token().kind, TokenKind::Float(f, None) if (f - 3.14).abs() < 1e-6)
+        );
+        assert!(matches!(
+            lexer.next_token().kind,
+            TokenKind::Integer(0, None)
+        ));
+        assert_eq!(
+            lexer.next_token().kind,
+            TokenKind::Integer(7, Some(Numeric::U8))
+        );
+        assert_eq!(
+            lexer.next_token().kind,
+            TokenKind::Float(1.0, Some(Numeric::F32))
+        );
+        assert_eq!(
+            lexer.next_token().kind,
+            TokenKind::Float(0.5, Some(Numeric::F32))
+        );
+
+        for (source, error) in [
+            ("3px", "invalid suffix `px` for number literal"),
+            (
+                "2.5u8",
+                "invalid suffix `u8` for float literal; cast it with `as u8`",
+            ),
+        ] {
+            let token = Lexer::new(source).next_token();
+            assert_eq!(token.kind, TokenKind::Error(error.to_string()));
+        }
+
+        let token = Lexer::new("9223372036854775808").next_token();
+        assert!(matches!(token.kind, TokenKind::Error(msg) if msg.contains("too large")));
     }
 
     #[test]
@@ -327,7 +561,7 @@ fn test_strings() {
 
     #[test]
     fn test_operators() {
-        let mut lexer = Lexer::new("-> => == != <= >= :: + - * /");
+        let mut lexer = Lexer::new("-> => == != <= >= :: 0..=9 ..");
         assert!(matches!(lexer.next_token().kind, TokenKind::Arrow));
         assert!(matches!(lexer.next_token().kind, TokenKind::FatArrow));
         assert!(matches!(lexer.next_token().kind, TokenKind::EqEq));
@@ -335,6 +569,29 @@ fn test_operators() {
         assert!(matches!(lexer.next_token().kind, TokenKind::Le));
         assert!(matches!(lexer.next_token().kind, TokenKind::Ge));
         assert!(matches!(lexer.next_token().kind, TokenKind::DoubleColon));
+        // A range of integers is not a float
+        assert!(matches!(
+            lexer.next_token().kind,
+            TokenKind::Integer(0, None)
+        ));
+        assert!(matches!(lexer.next_token().kind, TokenKind::DotDotEq));
+        assert!(matches!(
+            lexer.next_token().kind,
+            TokenKind::Integer(9, None)
+        ));
+        assert!(matches!(lexer.next_token().kind, TokenKind::DotDot));
+    }
+
+    #[test]
+    fn test_logical_operators_and_columns() {
+        let mut lexer = Lexer::new("a && b || !c");
+        let kinds: Vec<_> = (0..6).map(|_| lexer.next_token()).collect();
+        assert!(matches!(kinds[1].kind, TokenKind::And));
+        assert_eq!(kinds[1].column, 3);
+        assert!(matches!(kinds[3].kind, TokenKind::Or));
+        assert_eq!(kinds[3].column, 8);
+        assert!(matches!(kinds[4].kind, TokenKind::Not));
+        assert_eq!(kinds[4].column, 11);
     }
 
     #[test]
@@ -343,4 +600,14 @@ fn test_comments() {
         assert!(matches!(lexer.next_token().kind, TokenKind::Fn));
         assert!(matches!(lexer.next_token().kind, TokenKind::Let));
     }
+
+    #[test]
+    fn test_unicode_identifiers_are_normalized() {
+        let mut lexer = Lexer::new("caf\u{00E9} cafe\u{0301} größe");
+        let first = lexer.next_token().kind;
+        let second = lexer.next_token().kind;
+        assert_eq!(first, TokenKind::Identifier("caf\u{00E9}".into()));
+        assert_eq!(first, second);
+        assert!(matches!(lexer.next_token().kind, TokenKind::Identifier(ref s) if s == "größe"));
+    }
 }
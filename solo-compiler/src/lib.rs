@@ -12,43 +12,457 @@
 /// # Architecture
 ///
 /// ```text
-/// Source → Lexer → Parser → Type Checker → Code Generator → Binary
+/// Source → Lexer → Macro Expansion → Parser → Type Checker → Code Generator → Binary
 /// ```
+///
+/// # Stability
+///
+/// [`compile`], [`check`], [`lint()`], [`run`], [`tests`], [`run_test`],
+/// [`read_source`], [`CompileError`] and [`Diagnostic`] are the stable API.
+/// The pipeline stages, their artifacts and every other module are
+/// unstable and may change in any release. The language itself is split the
+/// same way by [`feature`]: gated features need `#![feature(...)]`, which
+/// the command line only accepts with `--unstable`.
 
+pub mod error;
+pub mod ice;
+pub mod symbol;
+pub mod numeric;
 pub mod token;
 pub mod lexer;
+pub mod token_cache;
+pub mod macros;
+pub mod ast;
+pub mod visit;
+pub mod parser;
+pub mod cst;
+pub mod legacy;
+pub mod transpile;
+pub mod feature;
+pub mod const_eval;
+pub mod typeck;
+pub mod arena;
+pub mod pprof;
+pub mod linetable;
+pub mod belief;
+pub mod confidence;
+pub mod region;
+pub mod builtins;
+pub mod ffi;
+pub mod interp;
+pub mod sanitize;
+pub mod coverage;
+pub mod pgo;
+pub mod lint;
+pub mod suggest;
+pub mod minify;
+pub mod minimize;
+pub mod escape;
+pub mod dead_code;
+pub mod type_sizes;
+pub mod plugin;
+pub mod sandbox;
+pub mod package;
+pub mod json;
+pub mod rpc;
+pub mod playground;
+pub mod repl;
+pub mod lsp;
+pub mod highlight;
+pub mod target;
+pub mod link;
+pub mod export;
+pub mod closed_world;
+pub mod lto;
+pub mod bloat;
+pub mod sysroot;
+pub mod print;
+pub mod timings;
+pub mod session;
+pub mod layout;
+pub mod stdlib;
+pub mod unicode;
+pub mod wasm;
 
+pub use error::{CompileError, Diagnostic, ErrorCode};
 pub use token::{Token, TokenKind};
 pub use lexer::Lexer;
+pub use interp::{BeliefFormat, OverflowMode, PanicStrategy, RunOptions};
+pub use sanitize::Sanitizers;
 
-/// Compile Solo source code to executable
-pub fn compile(source: &str) -> Result<(), String> {
-    let mut lexer = Lexer::new(source);
-
-    // Tokenize
-    let mut tokens = Vec::new();
-    loop {
-        let token = lexer.next_token();
-        if matches!(token.kind, TokenKind::Eof) {
-            break;
+use std::path::Path;
+
+/// Read a source file
+pub fn read_source(path: &Path) -> Result<String, CompileError> {
+    std::fs::read_to_string(path).map_err(|source| CompileError::Io {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// Stages of the compilation pipeline, in order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Phase {
+    Lex,
+    /// Declarative macro expansion
+    Expand,
+    Parse,
+    /// Feature gate, type, move and constant checking
+    TypeCheck,
+}
+
+/// What the pipeline produced when it stopped
+#[derive(Debug)]
+pub enum Artifact {
+    /// Tokens without `Eof`, macro-expanded after [`Phase::Expand`]
+    Tokens(Vec<Token>),
+    Ast(ast::Program),
+    /// The program together with the checker's types, constants and layouts
+    Typed(ast::Program, Box<typeck::TypeInfo>),
+}
+
+impl Artifact {
+    pub fn into_tokens(self) -> Option<Vec<Token>> {
+        match self {
+            Artifact::Tokens(tokens) => Some(tokens),
+            _ => None,
         }
-        if let TokenKind::Error(ref msg) = token.kind {
-            return Err(format!("Lexer error at {}:{}: {}", token.line, token.column, msg));
+    }
+
+    pub fn into_ast(self) -> Option<ast::Program> {
+        match self {
+            Artifact::Ast(program) | Artifact::Typed(program, _) => Some(program),
+            _ => None,
         }
-        tokens.push(token);
     }
 
-    // TODO(#parser): Implement recursive descent parser
-    // Tracking: See ROADMAP.adoc "Phase 1: Solo Compiler"
-    // Status: In progress - AST types needed first
+    pub fn into_typed(self) -> Option<(ast::Program, typeck::TypeInfo)> {
+        match self {
+            Artifact::Typed(program, info) => Some((program, *info)),
+            _ => None,
+        }
+    }
+}
+
+/// Run the pipeline up to and including `phase`, returning its artifact
+///
+/// Code generation has no inspectable artifact yet; use [`compile`] for a
+/// full build.
+pub fn compile_until(source: &str, phase: Phase) -> Result<Artifact, CompileError> {
+    pipeline(
+        source,
+        phase,
+        layout::DataLayout::LP64,
+        &mut timings::Timings::default(),
+    )
+}
+
+fn pipeline(
+    source: &str,
+    phase: Phase,
+    data_layout: layout::DataLayout,
+    timings: &mut timings::Timings,
+) -> Result<Artifact, CompileError> {
+    let tokens = timings.time("lex", || token_cache::lex(source).0)?;
+    if phase == Phase::Lex {
+        return Ok(Artifact::Tokens(tokens));
+    }
+
+    let tokens = timings.time("expand", || macros::expand(tokens))?;
+    if phase == Phase::Expand {
+        return Ok(Artifact::Tokens(tokens));
+    }
+
+    let program = timings.time("parse", || parser::parse(tokens))?;
+    if phase == Phase::Parse {
+        return Ok(Artifact::Ast(program));
+    }
+
+    timings.time("features", || feature::check(&program))?;
+    // Type check (evaluates constants)
+    let info = timings.time("typeck", || typeck::check_for(&program, data_layout))?;
+    Ok(Artifact::Typed(program, Box::new(info)))
+}
+
+/// Run the front end: lex, expand macros, parse and type check
+fn analyze(source: &str) -> Result<(ast::Program, typeck::TypeInfo), CompileError> {
+    analyze_for(source, layout::DataLayout::LP64)
+}
+
+fn analyze_for(
+    source: &str,
+    data_layout: layout::DataLayout,
+) -> Result<(ast::Program, typeck::TypeInfo), CompileError> {
+    analyze_timed(source, data_layout, &mut timings::Timings::default())
+}
+
+fn analyze_timed(
+    source: &str,
+    data_layout: layout::DataLayout,
+    timings: &mut timings::Timings,
+) -> Result<(ast::Program, typeck::TypeInfo), CompileError> {
+    let artifact = pipeline(source, Phase::TypeCheck, data_layout, timings)?;
+    Ok(artifact
+        .into_typed()
+        .expect("type checking produces a typed program"))
+}
+
+/// Reject unstable features, as the stable command line does
+///
+/// Fails on every `#![feature]` attribute; other errors are left for
+/// [`check`] and friends to report.
+pub fn check_stable(source: &str) -> Result<(), CompileError> {
+    match compile_until(source, Phase::Parse) {
+        Ok(artifact) => artifact
+            .into_ast()
+            .map_or(Ok(()), |program| feature::check_stable(&program)),
+        Err(_) => Ok(()),
+    }
+}
+
+/// Reject the `any` type, as `--deny-any` asks for
+///
+/// Fails on every use of `any`; other errors are left for [`check`] and
+/// friends to report.
+pub fn deny_any(source: &str) -> Result<(), CompileError> {
+    match compile_until(source, Phase::Parse) {
+        Ok(artifact) => artifact
+            .into_ast()
+            .map_or(Ok(()), |program| feature::deny_any(&program)),
+        Err(_) => Ok(()),
+    }
+}
+
+/// Check Solo source code without generating code
+pub fn check(source: &str) -> Result<typeck::TypeInfo, CompileError> {
+    analyze(source).map(|(_, info)| info)
+}
+
+/// Check Solo source code with the struct layouts of `target`
+pub fn check_for(source: &str, target: &target::Target) -> Result<typeck::TypeInfo, CompileError> {
+    analyze_for(source, layout::DataLayout::for_target(target)).map(|(_, info)| info)
+}
 
-    // TODO(#typeck): Implement type checker with affine types
-    // Tracking: See ROADMAP.adoc "Phase 1: Solo Compiler"
-    // Depends on: Parser completion
+/// Report the sizes of the structs and enums of Solo source code, on
+/// `target` or else a 64-bit one (see [`type_sizes`])
+pub fn print_type_sizes(
+    source: &str,
+    target: Option<&target::Target>,
+) -> Result<String, CompileError> {
+    let data_layout = target.map_or(layout::DataLayout::LP64, layout::DataLayout::for_target);
+    analyze_for(source, data_layout).map(|(program, info)| type_sizes::report(&program, &info))
+}
+
+/// Check Solo source code and run the lints over it
+pub fn lint(source: &str) -> Result<Vec<lint::Warning>, CompileError> {
+    analyze(source).map(|(program, info)| lint::check(&program, &info))
+}
+
+/// Compile Solo source code to executable
+pub fn compile(source: &str) -> Result<(), CompileError> {
+    let options = session::Options::default();
+    compile_timed(source, &options, &mut timings::Timings::default()).map(drop)
+}
+
+/// Compile Solo source code to an executable for `target`
+pub fn compile_for(source: &str, target: &target::Target) -> Result<(), CompileError> {
+    let options = session::Options::new().with_target(*target);
+    compile_timed(source, &options, &mut timings::Timings::default()).map(drop)
+}
 
-    // TODO(#codegen): Implement QBE IR code generation
-    // Tracking: See ROADMAP.adoc "Phase 1: Solo Compiler"
+/// What the optimizations of a build did, for the command line to report
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Optimizations {
+    /// What closed-world optimization removed, when it ran
+    pub closed_world: Option<closed_world::Report>,
+    /// What link-time optimization removed, when it ran without
+    /// closed-world optimization
+    pub lto: Option<lto::Report>,
+    /// The plan of the profile passed with `--profile-use`, if any; nothing applies it yet
+    pub profile: Option<pgo::Plan>,
+}
+
+/// Compile Solo source code, timing each pass in `timings`
+///
+/// Embedders should prefer a [`session::Session`], which also reports the
+/// files built.
+pub fn compile_timed(
+    source: &str,
+    options: &session::Options,
+    timings: &mut timings::Timings,
+) -> Result<Optimizations, CompileError> {
+    let data_layout = options
+        .target
+        .as_ref()
+        .map_or(layout::DataLayout::LP64, layout::DataLayout::for_target);
+    let (mut program, info) = analyze_timed(source, data_layout, timings)?;
+
+    let mut report = Optimizations::default();
+    // Before closed-world optimization changes the program profiled
+    if let Some(path) = &options.profile_use {
+        let data = std::fs::read(path).map_err(|source| CompileError::Io {
+            path: path.clone(),
+            source,
+        })?;
+        let plan = timings.time("profile-use", || {
+            let mut profile = coverage::Coverage::instrument(&program);
+            profile
+                .merge_profraw(&data)
+                .map(|()| pgo::Plan::new(&program, &profile))
+        });
+        report.profile = Some(plan.map_err(|err| {
+            CompileError::Codegen(format!(
+                "cannot use the profile '{}': {}; record it again with --profile-generate",
+                path.display(),
+                err
+            ))
+        })?);
+    }
+    if options.closed_world {
+        let has_main = program
+            .items
+            .iter()
+            .any(|item| matches!(item, ast::Item::Function(f) if f.name == "main"));
+        if !has_main {
+            return Err(CompileError::Type(vec![Diagnostic::unspanned(
+                "a closed-world program needs a `main` function",
+            )]));
+        }
+        if options.crate_type != link::CrateType::Bin {
+            return Err(CompileError::Type(vec![Diagnostic::unspanned(
+                "closed-world builds produce executables; libraries have external callers",
+            )]));
+        }
+        let optimize = || closed_world::optimize(&mut program, &info);
+        report.closed_world = Some(timings.time("closed-world", optimize));
+    } else if options.lto {
+        let optimize = || lto::optimize(&mut program, &info, options.crate_type);
+        report.lto = Some(timings.time("lto", optimize));
+    }
+
+    // TODO(#codegen): Implement QBE IR code generation, timed as "codegen"
+    // Tracking: See ROADMAP.adoc "Phase 1: Solo Compiler", and "Solo
+    // Backend Requirements" for what each language feature needs of it
     // Depends on: Type checker completion
 
-    Ok(())
+    Ok(report)
+}
+
+/// Check and interpret Solo source code, running `main`
+///
+/// Program output goes to stdout, and the arena profile (when requested) to
+/// stderr. Runtime panics are returned as [`CompileError::Runtime`].
+pub fn run(source: &str, options: &RunOptions) -> Result<interp::Value, CompileError> {
+    run_profiled(source, options).0
+}
+
+/// Like [`run`], also returning the allocations recorded when
+/// [`RunOptions::profile_heap`] is set and the execution counts when
+/// [`RunOptions::coverage`] is, even if the program panicked
+pub fn run_profiled(
+    source: &str,
+    options: &RunOptions,
+) -> (
+    Result<interp::Value, CompileError>,
+    Option<pprof::HeapProfile>,
+    Option<coverage::Coverage>,
+) {
+    let (program, info) = match analyze(source) {
+        Ok(analyzed) => analyzed,
+        Err(err) => return (Err(err), None, None),
+    };
+    let mut stdout = std::io::stdout();
+    let mut interpreter = interp::Interpreter::new(&program, &info, options, &mut stdout);
+    let result = ice::phase("interpret", || interpreter.run_main());
+    if let Some(profile) = interpreter.arena_profile() {
+        eprint!("{}", profile);
+    }
+    (
+        result,
+        interpreter.heap_profile().cloned(),
+        interpreter.coverage().cloned(),
+    )
+}
+
+/// Names of the `#[test]` functions in Solo source code, in source order
+pub fn tests(source: &str) -> Result<Vec<String>, CompileError> {
+    let (program, _) = analyze(source)?;
+    Ok(program
+        .functions()
+        .into_iter()
+        .filter(|(_, _, f)| f.is_test())
+        .map(|(name, _, _)| name)
+        .collect())
+}
+
+/// Check Solo source code and run the test function `name` in-process
+///
+/// `solo test` runs each test through [`sandbox`] instead, isolating it
+/// from the others and from the caller's environment.
+pub fn run_test(
+    source: &str,
+    name: &str,
+    options: &RunOptions,
+) -> Result<interp::Value, CompileError> {
+    run_test_covered(source, name, options).0
+}
+
+/// Like [`run_test`], also returning the execution counts when
+/// [`RunOptions::coverage`] is set, even if the test panicked
+pub fn run_test_covered(
+    source: &str,
+    name: &str,
+    options: &RunOptions,
+) -> (
+    Result<interp::Value, CompileError>,
+    Option<coverage::Coverage>,
+) {
+    let (program, info) = match analyze(source) {
+        Ok(analyzed) => analyzed,
+        Err(err) => return (Err(err), None),
+    };
+    let mut stdout = std::io::stdout();
+    let mut interpreter = interp::Interpreter::new(&program, &info, options, &mut stdout);
+    let result = ice::phase("interpret", || interpreter.run_function(name));
+    (result, interpreter.coverage().cloned())
+}
+
+/// The coverage regions of Solo source code, none executed yet
+pub fn instrument(source: &str) -> Result<coverage::Coverage, CompileError> {
+    let (program, _) = analyze(source)?;
+    Ok(coverage::Coverage::instrument(&program))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compile_until() {
+        let source = "macro two { () => { 2 }; } fn main() -> i64 { two!() }";
+        let lexed = compile_until(source, Phase::Lex)
+            .unwrap()
+            .into_tokens()
+            .unwrap();
+        let expanded = compile_until(source, Phase::Expand)
+            .unwrap()
+            .into_tokens()
+            .unwrap();
+        assert!(lexed.iter().any(|t| t.kind == TokenKind::Macro));
+        assert!(expanded.len() < lexed.len());
+
+        let program = compile_until(source, Phase::Parse)
+            .unwrap()
+            .into_ast()
+            .unwrap();
+        assert_eq!(program.items.len(), 1);
+
+        // Later phases are not run, so their errors are not reported
+        let ill_typed = "fn main() -> bool { 1 }";
+        assert!(compile_until(ill_typed, Phase::Parse).is_ok());
+        assert!(matches!(
+            compile_until(ill_typed, Phase::TypeCheck),
+            Err(CompileError::Type(_))
+        ));
+    }
 }
@@ -0,0 +1,242 @@
+//! Line tables for symbolizing backtraces of compiled programs
+//!
+//! A compiled program carries a small table mapping code addresses to Solo
+//! functions and source lines in its [`SECTION`] section, so the runtime
+//! can print a backtrace of Solo frames when it panics or traps without
+//! shipping full DWARF. Each row covers the code from its address up to
+//! the next row's.
+//!
+//! The encoding is the magic bytes `SOLOLT`, a version byte, then LEB128
+//! varints: the file name, the function names (length prefixed), and the
+//! rows, sorted by address with addresses and lines as deltas from the
+//! previous row.
+
+use std::fmt::Write;
+
+/// Name of the section holding the table
+pub const SECTION: &str = ".solo_lines";
+
+const MAGIC: &[u8] = b"SOLOLT";
+const VERSION: u8 = 1;
+
+/// A source position an address maps to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location<'t> {
+    pub function: &'t str,
+    pub file: &'t str,
+    pub line: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Row {
+    address: u64,
+    function: usize,
+    line: u64,
+}
+
+/// Addresses of one source file's code and the lines they came from
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LineTable {
+    file: String,
+    functions: Vec<String>,
+    rows: Vec<Row>,
+}
+
+impl LineTable {
+    pub fn new(file: &str) -> Self {
+        Self {
+            file: file.to_string(),
+            ..Self::default()
+        }
+    }
+
+    /// Map code from `address` on to `line` of `function`; rows must be
+    /// added in address order
+    pub fn add(&mut self, address: u64, function: &str, line: u64) {
+        debug_assert!(self.rows.last().is_none_or(|row| row.address <= address));
+        let function = match self.functions.iter().position(|f| f == function) {
+            Some(index) => index,
+            None => {
+                self.functions.push(function.to_string());
+                self.functions.len() - 1
+            }
+        };
+        self.rows.push(Row {
+            address,
+            function,
+            line,
+        });
+    }
+
+    /// The function and line of the code at `address`
+    pub fn lookup(&self, address: u64) -> Option<Location<'_>> {
+        let index = self.rows.partition_point(|row| row.address <= address);
+        let row = self.rows.get(index.checked_sub(1)?)?;
+        Some(Location {
+            function: &self.functions[row.function],
+            file: &self.file,
+            line: row.line,
+        })
+    }
+
+    /// A backtrace of return addresses, innermost first, in the format of
+    /// interpreter backtraces; addresses outside the table are shown as is
+    pub fn symbolize(&self, addresses: &[u64]) -> String {
+        let mut out = String::from("stack backtrace:");
+        for (i, &address) in addresses.iter().enumerate() {
+            match self.lookup(address) {
+                Some(location) => write!(
+                    out,
+                    "\n{:>4}: {}\n             at {}:{}",
+                    i, location.function, location.file, location.line
+                ),
+                None => write!(out, "\n{:>4}: {:#x}", i, address),
+            }
+            .expect("writing to a String cannot fail");
+        }
+        out
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        write_str(&mut bytes, &self.file);
+        write_varint(&mut bytes, self.functions.len() as u64);
+        for function in &self.functions {
+            write_str(&mut bytes, function);
+        }
+        write_varint(&mut bytes, self.rows.len() as u64);
+        let (mut address, mut line) = (0, 0);
+        for row in &self.rows {
+            write_varint(&mut bytes, row.address - address);
+            write_varint(&mut bytes, row.function as u64);
+            // Lines go back and forth, so their deltas are zigzag encoded
+            let delta = row.line as i64 - line as i64;
+            write_varint(&mut bytes, ((delta << 1) ^ (delta >> 63)) as u64);
+            (address, line) = (row.address, row.line);
+        }
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, String> {
+        let mut reader = Reader { bytes, position: 0 };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err("not a Solo line table".to_string());
+        }
+        let version = reader.take(1)?[0];
+        if version != VERSION {
+            return Err(format!("unsupported line table version {}", version));
+        }
+        let file = reader.string()?;
+        let functions = (0..reader.varint()?)
+            .map(|_| reader.string())
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut rows = Vec::new();
+        let (mut address, mut line) = (0u64, 0u64);
+        for _ in 0..reader.varint()? {
+            address += reader.varint()?;
+            let function = reader.varint()? as usize;
+            if function >= functions.len() {
+                return Err(format!("function index {} out of range", function));
+            }
+            let zigzag = reader.varint()?;
+            let delta = (zigzag >> 1) as i64 ^ -((zigzag & 1) as i64);
+            line = (line as i64 + delta) as u64;
+            rows.push(Row {
+                address,
+                function,
+                line,
+            });
+        }
+        Ok(Self {
+            file,
+            functions,
+            rows,
+        })
+    }
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+fn write_str(bytes: &mut Vec<u8>, s: &str) {
+    write_varint(bytes, s.len() as u64);
+    bytes.extend_from_slice(s.as_bytes());
+}
+
+struct Reader<'b> {
+    bytes: &'b [u8],
+    position: usize,
+}
+
+impl<'b> Reader<'b> {
+    fn take(&mut self, len: usize) -> Result<&'b [u8], String> {
+        let end = self
+            .position
+            .checked_add(len)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or("truncated line table")?;
+        let taken = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(taken)
+    }
+
+    fn varint(&mut self) -> Result<u64, String> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.take(1)?[0];
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte < 0x80 {
+                return Ok(value);
+            }
+        }
+        Err("varint too long in line table".to_string())
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let len = self.varint()? as usize;
+        String::from_utf8(self.take(len)?.to_vec())
+            .map_err(|_| "invalid UTF-8 in line table".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_table_round_trip() {
+        let mut table = LineTable::new("src/main.solo");
+        table.add(0x1000, "check", 2);
+        table.add(0x1010, "check", 3);
+        table.add(0x1040, "main", 7);
+        table.add(0x1048, "main", 5);
+
+        let bytes = table.encode();
+        assert!(bytes.starts_with(b"SOLOLT\x01"));
+        let decoded = LineTable::decode(&bytes).unwrap();
+        assert_eq!(decoded, table);
+        assert_eq!(
+            decoded.lookup(0x1020),
+            Some(Location {
+                function: "check",
+                file: "src/main.solo",
+                line: 3
+            })
+        );
+        assert_eq!(decoded.lookup(0xfff), None);
+        assert_eq!(
+            decoded.symbolize(&[0x1012, 0x104c, 0x20]),
+            "stack backtrace:\n   0: check\n             at src/main.solo:3\n   \
+             1: main\n             at src/main.solo:5\n   2: 0x20"
+        );
+
+        assert!(LineTable::decode(&bytes[..bytes.len() - 1]).is_err());
+        assert!(LineTable::decode(b"ELF").is_err());
+    }
+}
@@ -0,0 +1,316 @@
+//! Linker driver
+//!
+//! The link stage turns the object files of a build into an executable, a
+//! static library or a shared library. It drives one of two kinds of
+//! linker: a C compiler driver (`cc`, `clang`, a cross `gcc`), which adds the
+//! C runtime and system libraries itself, or LLVM's `lld` (`rust-lld`,
+//! `ld.lld`, `wasm-ld`), which links exactly what it is given. Static
+//! libraries are archived with `ar` whatever the linker.
+//!
+//! Library search paths and libraries come from `-L`/`-l` flags, such as
+//! the `flags` of a package's `[link]` table:
+//!
+//! ```toml
+//! [link]
+//! linker = "clang"
+//! flags = "-L native/lib -lm -lsqlite3"
+//! ```
+//!
+//! `wasm32-wasi` executables are linked against wasi-libc, which provides
+//! files, clocks and arguments through WASI. `lld` needs its start file and
+//! library directory, found under [`WASI_SYSROOT_VAR`] as installed by
+//! wasi-sdk.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::error::CompileError;
+use crate::target::Target;
+
+/// What a build produces, as chosen by `--crate-type`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CrateType {
+    #[default]
+    Bin,
+    StaticLib,
+    DyLib,
+}
+
+impl CrateType {
+    pub fn parse(name: &str) -> Option<CrateType> {
+        match name {
+            "bin" => Some(CrateType::Bin),
+            "staticlib" => Some(CrateType::StaticLib),
+            "dylib" => Some(CrateType::DyLib),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CrateType::Bin => "bin",
+            CrateType::StaticLib => "staticlib",
+            CrateType::DyLib => "dylib",
+        }
+    }
+
+    /// File name of the output called `name` on `target`
+    pub fn file_name(self, name: &str, target: &Target) -> String {
+        match (self, target.os, target.family) {
+            (CrateType::Bin, _, "wasm") => format!("{}.wasm", name),
+            (CrateType::Bin, _, _) => name.to_string(),
+            (CrateType::StaticLib, _, _) => format!("lib{}.a", name),
+            (CrateType::DyLib, "macos", _) => format!("lib{}.dylib", name),
+            (CrateType::DyLib, _, _) => format!("lib{}.so", name),
+        }
+    }
+}
+
+impl fmt::Display for CrateType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// How a linker takes its arguments
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flavor {
+    /// A C compiler driver
+    Cc,
+    /// An `lld` linker
+    Lld,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Linker {
+    pub program: String,
+    pub flavor: Flavor,
+}
+
+impl Linker {
+    /// The linker run as `program`, its flavor told by the name
+    pub fn new(program: &str) -> Self {
+        let name = program.rsplit(['/', '\\']).next().unwrap_or(program);
+        let flavor = if name.contains("lld") || name == "wasm-ld" {
+            Flavor::Lld
+        } else {
+            Flavor::Cc
+        };
+        Self {
+            program: program.to_string(),
+            flavor,
+        }
+    }
+
+    /// The default linker for `target` when building on `host`
+    pub fn for_target(target: &Target, host: Option<Target>) -> Self {
+        Self::new(target.linker(host))
+    }
+}
+
+/// Library search paths and libraries to link, from `-L`/`-l` flags
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LinkArgs {
+    pub search_paths: Vec<String>,
+    pub libraries: Vec<String>,
+}
+
+impl LinkArgs {
+    /// Parse whitespace-separated `-L <dir>`/`-L<dir>` and `-l <lib>`/`-l<lib>`
+    /// flags
+    pub fn parse(flags: &str) -> Result<LinkArgs, String> {
+        let mut args = LinkArgs::default();
+        let mut words = flags.split_whitespace();
+        while let Some(word) = words.next() {
+            let (flag, value) = match word.get(..2) {
+                Some(flag @ ("-L" | "-l")) if word.len() > 2 => (flag, &word[2..]),
+                Some(flag @ ("-L" | "-l")) => match words.next() {
+                    Some(value) => (flag, value),
+                    None => return Err(format!("`{}` requires a value", flag)),
+                },
+                _ => {
+                    return Err(format!(
+                        "unsupported link flag `{}`; only `-L` and `-l` are accepted",
+                        word
+                    ))
+                }
+            };
+            match flag {
+                "-L" => args.search_paths.push(value.to_string()),
+                _ => args.libraries.push(value.to_string()),
+            }
+        }
+        Ok(args)
+    }
+
+    /// Add `other`'s paths and libraries that are not listed yet
+    pub fn extend(&mut self, other: &LinkArgs) {
+        for path in &other.search_paths {
+            if !self.search_paths.contains(path) {
+                self.search_paths.push(path.clone());
+            }
+        }
+        for library in &other.libraries {
+            if !self.libraries.contains(library) {
+                self.libraries.push(library.clone());
+            }
+        }
+    }
+}
+
+/// Environment variable naming the wasi-libc sysroot
+pub const WASI_SYSROOT_VAR: &str = "WASI_SYSROOT";
+
+/// Start file and library directory of wasi-libc in `sysroot`, for
+/// executables linked with `lld`
+pub fn wasi_libc(sysroot: &Path) -> (PathBuf, String) {
+    let lib = sysroot.join("lib").join("wasm32-wasi");
+    (
+        lib.join("crt1-command.o"),
+        lib.to_string_lossy().into_owned(),
+    )
+}
+
+/// One invocation of the link stage
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Job {
+    pub crate_type: CrateType,
+    pub objects: Vec<PathBuf>,
+    pub output: PathBuf,
+    pub args: LinkArgs,
+}
+
+impl Job {
+    /// The command producing the output with `linker` for `target`
+    pub fn command(&self, linker: &Linker, target: &Target) -> Command {
+        if self.crate_type == CrateType::StaticLib {
+            let mut command = Command::new("ar");
+            command.arg("crs").arg(&self.output).args(&self.objects);
+            return command;
+        }
+
+        let mut command = Command::new(&linker.program);
+        // `rust-lld` is every flavor of lld in one, so it has to be told
+        if linker.program.ends_with("rust-lld") {
+            let flavor = if target.family == "wasm" {
+                "wasm"
+            } else {
+                "gnu"
+            };
+            command.args(["-flavor", flavor]);
+        }
+        if self.crate_type == CrateType::DyLib {
+            match (linker.flavor, target.os) {
+                (Flavor::Cc, "macos") => command.arg("-dynamiclib"),
+                _ => command.arg("-shared"),
+            };
+        }
+        command.arg("-o").arg(&self.output).args(&self.objects);
+        for path in &self.args.search_paths {
+            command.arg(format!("-L{}", path));
+        }
+        for library in &self.args.libraries {
+            command.arg(format!("-l{}", library));
+        }
+        // A C compiler driver adds libc itself
+        if target.os == "wasi" && linker.flavor == Flavor::Lld {
+            command.arg("-lc");
+        }
+        command
+    }
+
+    /// Run the link stage
+    pub fn run(&self, linker: &Linker, target: &Target) -> Result<(), CompileError> {
+        let mut command = self.command(linker, target);
+        let output = command.output().map_err(|err| {
+            CompileError::Link(format!("cannot run `{}`: {}", command_line(&command), err))
+        })?;
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(CompileError::Link(format!(
+                "`{}` failed ({})\n{}",
+                command_line(&command),
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim_end()
+            )))
+        }
+    }
+}
+
+/// `command` as a shell-like line, for messages and `--print-link-args`
+pub fn command_line(command: &Command) -> String {
+    let mut line = command.get_program().to_string_lossy().into_owned();
+    for arg in command.get_args() {
+        line.push(' ');
+        line.push_str(&arg.to_string_lossy());
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_link_commands() {
+        let linux = Target::find("x86_64-unknown-linux-gnu").unwrap();
+        let args = LinkArgs::parse("-L native/lib -lm -L/opt/lib -l sqlite3").unwrap();
+        assert_eq!(args.search_paths, ["native/lib", "/opt/lib"]);
+        assert_eq!(args.libraries, ["m", "sqlite3"]);
+        assert_eq!(
+            LinkArgs::parse("-lm -static").unwrap_err(),
+            "unsupported link flag `-static`; only `-L` and `-l` are accepted"
+        );
+        assert!(LinkArgs::parse("-l").is_err());
+
+        let mut job = Job {
+            crate_type: CrateType::Bin,
+            objects: vec![PathBuf::from("main.o")],
+            output: PathBuf::from(CrateType::Bin.file_name("demo", &linux)),
+            args,
+        };
+        let line = |job: &Job, linker: &str, target: &Target| {
+            command_line(&job.command(&Linker::new(linker), target))
+        };
+        assert_eq!(
+            line(&job, "cc", &linux),
+            "cc -o demo main.o -Lnative/lib -L/opt/lib -lm -lsqlite3"
+        );
+        assert_eq!(Linker::new("/usr/bin/rust-lld").flavor, Flavor::Lld);
+        assert_eq!(
+            line(&job, "rust-lld", &linux),
+            "rust-lld -flavor gnu -o demo main.o -Lnative/lib -L/opt/lib -lm -lsqlite3"
+        );
+
+        let macos = Target::find("aarch64-apple-darwin").unwrap();
+        job.crate_type = CrateType::DyLib;
+        job.output = PathBuf::from(CrateType::DyLib.file_name("demo", &macos));
+        assert!(line(&job, "clang", &macos).starts_with("clang -dynamiclib -o libdemo.dylib"));
+        job.crate_type = CrateType::StaticLib;
+        job.output = PathBuf::from(CrateType::StaticLib.file_name("demo", &linux));
+        assert_eq!(line(&job, "clang", &linux), "ar crs libdemo.a main.o");
+
+        let wasm = Target::find("wasm32-unknown-unknown").unwrap();
+        assert_eq!(Linker::for_target(&wasm, Some(linux)).flavor, Flavor::Lld);
+        assert_eq!(CrateType::Bin.file_name("demo", &wasm), "demo.wasm");
+
+        let wasi = Target::find("wasm32-wasi").unwrap();
+        let (start, lib) = wasi_libc(Path::new("/opt/wasi-sysroot"));
+        let job = Job {
+            crate_type: CrateType::Bin,
+            objects: vec![start, PathBuf::from("main.o")],
+            output: PathBuf::from(CrateType::Bin.file_name("demo", &wasi)),
+            args: LinkArgs {
+                search_paths: vec![lib],
+                libraries: Vec::new(),
+            },
+        };
+        assert_eq!(
+            line(&job, "wasm-ld", &wasi),
+            "wasm-ld -o demo.wasm /opt/wasi-sysroot/lib/wasm32-wasi/crt1-command.o main.o \
+             -L/opt/wasi-sysroot/lib/wasm32-wasi -lc"
+        );
+    }
+}
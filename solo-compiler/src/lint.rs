@@ -0,0 +1,537 @@
+//! Lints for Solo dialect
+//!
+//! Lints are warnings about programs that compile but are probably not what
+//! the author meant. `solo lint` fails on any of them; a [`Baseline`] records
+//! the warnings a codebase already has (by [fingerprint](Diagnostic::fingerprint))
+//! so that only new ones fail the build. A lint can be turned off for a whole
+//! program with `solo lint --allow <lint>` or a package's `[lints]` table,
+//! and those in [`ITEM_LINTS`] for one item with `#[allow(<lint>)]`.
+
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::ast::*;
+use crate::dead_code;
+use crate::error::Diagnostic;
+use crate::escape;
+use crate::json::Json;
+use crate::type_sizes;
+use crate::typeck::TypeInfo;
+
+/// Name of the lint reporting bindings that hide another of the same name
+pub const SHADOWING: &str = "shadowing";
+
+/// Name of the lint reporting code after an expression of type `!`
+pub const UNREACHABLE_CODE: &str = "unreachable_code";
+
+/// Every lint, by name
+pub const LINTS: [&str; 9] = [
+    "non_upper_case_globals",
+    "non_camel_case_types",
+    "non_snake_case",
+    "unused_variables",
+    SHADOWING,
+    UNREACHABLE_CODE,
+    escape::LINT,
+    type_sizes::LINT,
+    dead_code::LINT,
+];
+
+/// Lints that `#[allow(...)]` turns off for a function, method or type
+pub const ITEM_LINTS: [&str; 2] = [dead_code::LINT, SHADOWING];
+
+/// A lint violation
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Warning {
+    pub diagnostic: Diagnostic,
+    /// Name of the lint, e.g. `unused_variables`
+    pub lint: &'static str,
+}
+
+impl Warning {
+    /// The warning as a JSON object, fingerprinted against `source`
+    pub fn to_json(&self, source: &str) -> Json {
+        self.to_json_with(self.diagnostic.fingerprint(source))
+    }
+
+    /// The warning as a JSON object with a fingerprint taken beforehand,
+    /// such as before placing it in a package's library file
+    pub fn to_json_with(&self, fingerprint: String) -> Json {
+        let d = &self.diagnostic;
+        let file = d
+            .file
+            .as_ref()
+            .map_or(Json::Null, |f| Json::Str(f.display().to_string()));
+        let (line, column) = d.span.map_or((Json::Null, Json::Null), |s| {
+            (Json::Int(s.line as i64), Json::Int(s.column as i64))
+        });
+        let mut fields = vec![
+            ("stage", Json::str("lint")),
+            ("lint", Json::str(self.lint)),
+            ("file", file),
+            ("line", line),
+            ("column", column),
+            ("message", Json::str(d.message.as_str())),
+            ("fingerprint", Json::Str(fingerprint)),
+        ];
+        if let Some(related) = &d.related {
+            fields.push(("related", related.to_json()));
+        }
+        Json::object(fields)
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "warning[{}] at {}", self.lint, self.diagnostic)
+    }
+}
+
+/// Lint a type-checked program; warnings come back in source order
+pub fn check(program: &Program, info: &TypeInfo) -> Vec<Warning> {
+    let mut linter = Linter {
+        exprs: &program.exprs,
+        scopes: Vec::new(),
+        allow_shadowing: false,
+        warnings: Vec::new(),
+    };
+    for item in &program.items {
+        match item {
+            Item::Const(c) if !is_upper_case(&c.name) => linter.warn(
+                "non_upper_case_globals",
+                c.span,
+                format!("constant `{}` should have an upper case name", c.name),
+            ),
+            Item::Struct(StructDef { name, span, .. }) | Item::Enum(EnumDef { name, span, .. })
+                if !is_camel_case(name) =>
+            {
+                linter.warn(
+                    "non_camel_case_types",
+                    *span,
+                    format!("type `{}` should have an upper camel case name", name),
+                )
+            }
+            _ => {}
+        }
+    }
+    for (_, _, function) in program.functions() {
+        linter.function(function);
+    }
+    for &(span, after) in &info.unreachable {
+        linter.warnings.push(Warning {
+            diagnostic: Diagnostic::new(
+                span,
+                "unreachable code after a statement that never finishes",
+            )
+            .with_related(after, "this statement never finishes"),
+            lint: UNREACHABLE_CODE,
+        });
+    }
+    linter.warnings.extend(escape::check(program));
+    linter.warnings.extend(dead_code::check(program));
+    linter.warnings.extend(type_sizes::check(program, info));
+    linter.warnings.sort();
+    linter.warnings.dedup();
+    linter.warnings
+}
+
+struct Linter<'a> {
+    exprs: &'a Exprs,
+    /// Locals in scope: name, declaration and whether it has been used
+    scopes: Vec<Vec<(String, Span, bool)>>,
+    /// `#[allow(shadowing)]` on the function being linted
+    allow_shadowing: bool,
+    warnings: Vec<Warning>,
+}
+
+impl Linter<'_> {
+    fn warn(&mut self, lint: &'static str, span: Span, message: String) {
+        self.warnings.push(Warning {
+            diagnostic: Diagnostic::new(span, message),
+            lint,
+        });
+    }
+
+    fn function(&mut self, function: &Function) {
+        if !is_snake_case(&function.name) {
+            self.warn(
+                "non_snake_case",
+                function.span,
+                format!("function `{}` should have a snake case name", function.name),
+            );
+        }
+        self.allow_shadowing = allows(&function.attrs, SHADOWING);
+        self.scopes.push(Vec::new());
+        for param in &function.params {
+            self.declare(&param.name, param.span);
+        }
+        self.block(&function.body);
+        self.pop_scope();
+    }
+
+    fn declare(&mut self, name: &str, span: Span) {
+        if !is_snake_case(name) {
+            self.warn(
+                "non_snake_case",
+                span,
+                format!("variable `{}` should have a snake case name", name),
+            );
+        }
+        let earlier = self
+            .scopes
+            .iter()
+            .flatten()
+            .rev()
+            .find(|(n, _, _)| n == name);
+        if let Some(&(_, earlier, _)) = earlier {
+            if !self.allow_shadowing && !name.starts_with('_') {
+                let message = format!("`{}` shadows an earlier binding of the same name", name);
+                self.warnings.push(Warning {
+                    diagnostic: Diagnostic::new(span, message)
+                        .with_related(earlier, format!("`{}` first declared here", name)),
+                    lint: SHADOWING,
+                });
+            }
+        }
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.push((name.to_string(), span, false));
+        }
+    }
+
+    fn pop_scope(&mut self) {
+        for (name, span, used) in self.scopes.pop().unwrap_or_default() {
+            if !used && !name.starts_with('_') {
+                self.warn(
+                    "unused_variables",
+                    span,
+                    format!(
+                        "unused variable: `{}` (prefix it with an underscore if intentional)",
+                        name
+                    ),
+                );
+            }
+        }
+    }
+
+    fn use_name(&mut self, name: &str) {
+        let local = self
+            .scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.iter_mut().rev().find(|(n, _, _)| n == name));
+        if let Some((_, _, used)) = local {
+            *used = true;
+        }
+    }
+
+    fn block(&mut self, block: &Block) {
+        self.scopes.push(Vec::new());
+        for stmt in &block.stmts {
+            match stmt {
+                Stmt::Let(let_stmt) => {
+                    if let Some(value) = &let_stmt.value {
+                        self.expr(value);
+                    }
+                    self.declare(&let_stmt.name, let_stmt.span);
+                }
+                Stmt::Const(item) => {
+                    self.expr(&item.value);
+                    if !is_upper_case(&item.name) {
+                        self.warn(
+                            "non_upper_case_globals",
+                            item.span,
+                            format!("constant `{}` should have an upper case name", item.name),
+                        );
+                    }
+                }
+                Stmt::Expr(expr) | Stmt::Defer(expr) => self.expr(expr),
+            }
+        }
+        if let Some(tail) = &block.tail {
+            self.expr(&self.exprs[*tail]);
+        }
+        self.pop_scope();
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Int(..) | ExprKind::Float(..) | ExprKind::Str(_) | ExprKind::Bool(_) => {}
+            ExprKind::Path(segments) => {
+                if let [name] = segments.as_slice() {
+                    self.use_name(name);
+                }
+            }
+            ExprKind::Unary(_, operand)
+            | ExprKind::Field(operand, _)
+            | ExprKind::Cast(operand, _) => self.expr(&self.exprs[*operand]),
+            ExprKind::Binary(_, lhs, rhs)
+            | ExprKind::Assign(lhs, rhs)
+            | ExprKind::Index(lhs, rhs)
+            | ExprKind::ArrayRepeat(lhs, rhs) => {
+                self.expr(&self.exprs[*lhs]);
+                self.expr(&self.exprs[*rhs]);
+            }
+            ExprKind::Call(callee, args) | ExprKind::MethodCall(callee, _, args) => {
+                self.expr(&self.exprs[*callee]);
+                args.iter().for_each(|arg| self.expr(arg));
+            }
+            ExprKind::Distribution(_, args) | ExprKind::Array(args) => {
+                args.iter().for_each(|arg| self.expr(arg));
+            }
+            ExprKind::StructLit(_, inits) => inits.iter().for_each(|init| self.expr(&init.value)),
+            ExprKind::If(cond, then_block, else_expr) => {
+                self.expr(&self.exprs[*cond]);
+                self.block(then_block);
+                if let Some(else_expr) = else_expr {
+                    self.expr(&self.exprs[*else_expr]);
+                }
+            }
+            ExprKind::While(cond, body) => {
+                self.expr(&self.exprs[*cond]);
+                self.block(body);
+            }
+            ExprKind::For(name, iterable, body) => {
+                self.expr(&self.exprs[*iterable]);
+                self.scopes.push(Vec::new());
+                self.declare(name, expr.span);
+                self.block(body);
+                self.pop_scope();
+            }
+            ExprKind::Match(scrutinee, arms) => {
+                self.expr(&self.exprs[*scrutinee]);
+                for arm in arms {
+                    self.scopes.push(Vec::new());
+                    for (name, span) in arm.pattern.bindings() {
+                        self.declare(&name, span);
+                    }
+                    if let Some(guard) = arm.guard {
+                        self.expr(&self.exprs[guard]);
+                    }
+                    self.expr(&self.exprs[arm.value]);
+                    self.pop_scope();
+                }
+            }
+            ExprKind::Range(start, end, _) => {
+                for end in [start, end].into_iter().flatten() {
+                    self.expr(&self.exprs[*end]);
+                }
+            }
+            ExprKind::Block(block) | ExprKind::Unsafe(block) => self.block(block),
+            ExprKind::Arena(name, allocator, block) => {
+                if let Some(allocator) = allocator {
+                    self.expr(&self.exprs[*allocator]);
+                }
+                self.scopes.push(Vec::new());
+                if let Some(name) = name {
+                    self.declare(name, expr.span);
+                }
+                self.block(block);
+                self.pop_scope();
+            }
+            ExprKind::Return(value) => {
+                if let Some(value) = value {
+                    self.expr(&self.exprs[*value]);
+                }
+            }
+            ExprKind::Become(call) => self.expr(&self.exprs[*call]),
+            ExprKind::Asm(_, operands) => {
+                for operand in operands {
+                    self.expr(&operand.expr);
+                }
+            }
+        }
+    }
+}
+
+fn is_snake_case(name: &str) -> bool {
+    !name.chars().any(|c| c.is_uppercase())
+}
+
+fn is_upper_case(name: &str) -> bool {
+    !name.chars().any(|c| c.is_lowercase())
+}
+
+fn is_camel_case(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_uppercase()) && !name.contains('_')
+}
+
+/// Fingerprints of accepted warnings, one per line
+///
+/// Each line starts with a fingerprint; the rest of the line (the lint and
+/// message when written by [`Baseline::render`]) and `#` comment lines are
+/// ignored when reading.
+#[derive(Debug, Clone, Default)]
+pub struct Baseline {
+    fingerprints: HashSet<String>,
+}
+
+impl Baseline {
+    pub fn parse(text: &str) -> Baseline {
+        let fingerprints = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(|line| line.split_whitespace().next())
+            .map(str::to_string)
+            .collect();
+        Baseline { fingerprints }
+    }
+
+    /// Baseline file accepting every warning in `warnings`
+    pub fn render(warnings: &[Warning], source: &str) -> String {
+        let mut out =
+            String::from("# solo lint baseline: warnings accepted when this file was written\n");
+        for warning in warnings {
+            out.push_str(&format!(
+                "{} {}: {}\n",
+                warning.diagnostic.fingerprint(source),
+                warning.lint,
+                warning.diagnostic.message
+            ));
+        }
+        out
+    }
+
+    pub fn contains(&self, warning: &Warning, source: &str) -> bool {
+        self.fingerprints
+            .contains(&warning.diagnostic.fingerprint(source))
+    }
+
+    pub fn len(&self) -> usize {
+        self.fingerprints.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fingerprints.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lint_source(source: &str) -> Vec<Warning> {
+        crate::lint(source).expect("program should compile")
+    }
+
+    #[test]
+    fn test_lints() {
+        let source = "
+            const limit: i64 = 3;
+            struct point_2d { x: i64 }
+            fn Main() {
+                let unused = 1;
+                let _ignored = 2;
+                let used = limit;
+                let p = point_2d { x: used };
+                print(p.x);
+            }";
+        let lints: Vec<_> = lint_source(source).iter().map(|w| w.lint).collect();
+        assert_eq!(
+            lints,
+            [
+                "non_upper_case_globals",
+                "dead_code",
+                "non_camel_case_types",
+                "dead_code",
+                "non_snake_case",
+                "unused_variables"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_shadowing() {
+        let source = "
+            fn main() {
+                let words = 250;
+                {
+                    let words = 300;
+                    print(words);
+                }
+                let words = words + 1;
+                if words > 0 { let draft = 1; print(draft); }
+                if words > 1 { let draft = 2; print(draft); }
+                let _n = 1;
+                { let _n = 2; }
+                print(words);
+            }
+            #[allow(shadowing)]
+            fn quiet(x: i64) -> i64 {
+                let x = x * 2;
+                x
+            }";
+        let messages: Vec<String> = lint_source(source)
+            .into_iter()
+            .filter(|w| w.lint == SHADOWING)
+            .map(|w| w.to_string())
+            .collect();
+        // Sibling blocks do not shadow each other
+        assert_eq!(
+            messages,
+            [
+                "warning[shadowing] at 5:21: `words` shadows an earlier binding of the same \
+                 name\n  note: 3:17: `words` first declared here",
+                "warning[shadowing] at 8:17: `words` shadows an earlier binding of the same \
+                 name\n  note: 3:17: `words` first declared here",
+            ]
+        );
+
+        let err = crate::check("#[allow(shadow)]\nfn main() {}").unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("unknown lint `shadow` in `#[allow]`; `dead_code` and `shadowing` can be allowed on items"));
+    }
+
+    #[test]
+    fn test_unreachable_code() {
+        let source = "
+            fn check(words: i64) -> i64 {
+                if words < 0 {
+                    return 0;
+                    print(words);
+                }
+                let n = if words > 100 { words } else { panic(\"short\") };
+                n
+            }
+            fn spin() {
+                while true {}
+                print(1);
+            }
+            fn main() {
+                print(check(150));
+                spin();
+            }";
+        let messages: Vec<String> = lint_source(source)
+            .into_iter()
+            .filter(|w| w.lint == UNREACHABLE_CODE)
+            .map(|w| w.to_string())
+            .collect();
+        // Only one branch of the `if` diverges, so what follows it is reached
+        assert_eq!(
+            messages,
+            [
+                "warning[unreachable_code] at 5:26: unreachable code after a statement that \
+                 never finishes\n  note: 4:21: this statement never finishes",
+                "warning[unreachable_code] at 12:22: unreachable code after a statement that \
+                 never finishes\n  note: 11:17: this statement never finishes",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_baseline() {
+        let old = "fn main() {\n    let a = 1;\n}";
+        let baseline = Baseline::parse(&Baseline::render(&lint_source(old), old));
+        assert_eq!(baseline.len(), 1);
+
+        // The recorded warning moved, and a new one was added
+        let new = "// header\nfn main() {\n    let a = 1;\n    let b = 2;\n}";
+        let fresh: Vec<_> = lint_source(new)
+            .into_iter()
+            .filter(|w| !baseline.contains(w, new))
+            .collect();
+        assert_eq!(fresh.len(), 1);
+        assert!(fresh[0].diagnostic.message.contains("`b`"), "{}", fresh[0]);
+    }
+}
@@ -0,0 +1,2278 @@
+//! Language server
+//!
+//! `solo lsp` speaks the [Language Server Protocol] on stdin and stdout, so
+//! editors check Solo code as it is typed and render it with more than a
+//! TextMate grammar can see:
+//!
+//! | Request                            | Result                                           |
+//! |------------------------------------|--------------------------------------------------|
+//! | `textDocument/semanticTokens/full` | the kind of every token (see [`TOKEN_TYPES`])    |
+//! | `textDocument/documentSymbol`      | items, with their fields, variants and methods   |
+//! | `textDocument/foldingRange`        | multi-line blocks, groups and comments           |
+//! | `textDocument/completion`          | fields, methods, paths, names in scope, keywords |
+//! | `textDocument/references`          | every use of a symbol, across the package        |
+//! | `textDocument/rename`              | edits renaming it, unless the new name conflicts |
+//!
+//! Documents are synchronized incrementally: each change sends the edited
+//! range, and only the items around it are lexed and parsed again into the
+//! document's [lossless syntax tree](crate::cst) (see
+//! [`Node::reparse`](cst::Node::reparse)). Every change publishes the
+//! document's diagnostics, unless another change to it is already queued.
+//! Highlighting and the outline work from the syntax tree, so they keep up
+//! with code that does not parse yet; when it does parse, locals holding
+//! beliefs or arenas are told apart with the `belief` and `arena`
+//! modifiers.
+//!
+//! Completion type checks the document as edited so far, errors and all,
+//! so it knows the types of locals. A completion request still queued when
+//! the client cancels it or edits the document again is answered with a
+//! `RequestCancelled` error instead.
+//!
+//! References and renames use the names the type checker resolves. A
+//! document in a package is checked with the rest of it, each target
+//! followed by the library as the build puts them together, so a rename in
+//! the library reaches every target. A rename is refused if, after it, any
+//! name in those programs resolves differently than before.
+//!
+//! [Language Server Protocol]: https://microsoft.github.io/language-server-protocol/
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::sync::mpsc;
+use std::thread;
+
+use crate::ast::{
+    join_path, Block, Expr, ExprKind, Exprs, Function, Item, LetStmt, Program, Span, TypeExpr,
+};
+use crate::builtins;
+use crate::cst::{self, Edit, Element, Node, NodeKind, SyntaxKind, SyntaxToken};
+use crate::feature;
+use crate::json::Json;
+use crate::package::Package;
+use crate::rpc::{self, RpcError};
+use crate::token::{Token, TokenKind, Trivia, KEYWORDS};
+use crate::typeck::{self, Type};
+use crate::visit::{self, Visit};
+use crate::Phase;
+
+/// Error code of requests the client cancelled or made stale
+pub const REQUEST_CANCELLED: i64 = -32800;
+/// Error code of valid requests the server refuses, such as conflicting
+/// renames
+pub const REQUEST_FAILED: i64 = -32803;
+
+/// Semantic token types, in the order of [`TokenType`]
+pub const TOKEN_TYPES: &[&str] = &[
+    "keyword",
+    "type",
+    "struct",
+    "enum",
+    "enumMember",
+    "function",
+    "method",
+    "macro",
+    "parameter",
+    "variable",
+    "property",
+    "number",
+    "string",
+    "comment",
+    "operator",
+];
+
+/// Semantic token modifiers, each a bit of [`SemanticToken::modifiers`]
+pub const TOKEN_MODIFIERS: &[&str] = &[
+    "declaration",
+    "readonly",
+    "defaultLibrary",
+    "belief",
+    "arena",
+];
+
+pub const DECLARATION: u32 = 1 << 0;
+pub const READONLY: u32 = 1 << 1;
+pub const DEFAULT_LIBRARY: u32 = 1 << 2;
+/// Holds or produces a belief: `Belief<T>` types, distributions, `~`
+pub const BELIEF: u32 = 1 << 3;
+/// An arena handle or the `Arena` type
+pub const ARENA: u32 = 1 << 4;
+
+/// Types built into the language
+const PRIMITIVES: &[&str] = &[
+    "i8", "i16", "i32", "i64", "u8", "u16", "u32", "u64", "isize", "usize", "f32", "f64", "bool",
+    "str", "String",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenType {
+    Keyword,
+    Type,
+    Struct,
+    Enum,
+    EnumMember,
+    Function,
+    Method,
+    Macro,
+    Parameter,
+    Variable,
+    Property,
+    Number,
+    String,
+    Comment,
+    Operator,
+}
+
+/// A highlighted range within one line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SemanticToken {
+    /// 0-based line
+    pub line: u32,
+    /// 0-based column in UTF-16 code units, as LSP counts them
+    pub start: u32,
+    pub length: u32,
+    pub ty: TokenType,
+    pub modifiers: u32,
+}
+
+/// `SymbolKind` of the LSP specification
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Namespace = 3,
+    Method = 6,
+    Field = 8,
+    Enum = 10,
+    Interface = 11,
+    Function = 12,
+    Constant = 14,
+    Object = 19,
+    EnumMember = 22,
+    Struct = 23,
+    TypeParameter = 26,
+}
+
+/// An entry of the outline: an item, or a field, variant or method of one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// Byte range of the whole declaration, leading comments included
+    pub range: (usize, usize),
+    /// Byte range of its name
+    pub selection: (usize, usize),
+    pub children: Vec<Symbol>,
+}
+
+/// Lines `start..=end` (0-based) that an editor can collapse
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FoldingRange {
+    pub start: u32,
+    pub end: u32,
+    pub comment: bool,
+}
+
+/// Serve one client until it sends `exit` or closes `input`
+///
+/// Messages are read as they arrive, so that a request can be skipped when
+/// the messages queued after it make it pointless (see [`cancelled`]).
+pub fn serve(
+    mut input: impl BufRead + Send + 'static,
+    mut output: impl Write,
+    unstable: bool,
+) -> io::Result<()> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || loop {
+        let message = read_message(&mut input);
+        let last = !matches!(message, Ok(Some(_)));
+        if sender.send(message).is_err() || last {
+            break;
+        }
+    });
+
+    let mut server = Server::new(unstable);
+    let mut pending = VecDeque::new();
+    let mut closed = false;
+    loop {
+        if pending.is_empty() && !closed {
+            closed = enqueue(receiver.recv().unwrap_or(Ok(None)), &mut pending)?;
+        }
+        while !closed {
+            let Ok(message) = receiver.try_recv() else {
+                break;
+            };
+            closed = enqueue(message, &mut pending)?;
+        }
+        let Some(message) = pending.pop_front() else {
+            break;
+        };
+        let replies = match message {
+            Ok(message) if cancelled(&message, pending.iter().flatten()) => {
+                let id = message.get("id").cloned().unwrap_or(Json::Null);
+                let error = (REQUEST_CANCELLED, "request cancelled".to_string());
+                vec![rpc::response(id, Err(error))]
+            }
+            // Diagnostics that would be out of date once published
+            Ok(message) if superseded(&message, pending.iter().flatten()) => {
+                server.update(&message);
+                Vec::new()
+            }
+            Ok(message) => server.handle(&message),
+            Err(err) => vec![rpc::response(Json::Null, Err((rpc::PARSE_ERROR, err)))],
+        };
+        for reply in replies {
+            write_message(&mut output, &reply)?;
+        }
+        if server.exited {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Queue a message read from the client; true once there are no more
+fn enqueue(
+    message: io::Result<Option<String>>,
+    pending: &mut VecDeque<Result<Json, String>>,
+) -> io::Result<bool> {
+    match message? {
+        Some(content) => {
+            pending.push_back(Json::parse(&content));
+            Ok(false)
+        }
+        None => Ok(true),
+    }
+}
+
+/// Whether the messages after a request make answering it pointless: a
+/// `$/cancelRequest` for it or, for completion, a change to its document
+pub fn cancelled<'m>(request: &Json, mut later: impl Iterator<Item = &'m Json>) -> bool {
+    let Some(id) = request.get("id") else {
+        return false;
+    };
+    let method = request.get("method").and_then(Json::as_str);
+    let uri = |message: &Json| {
+        let params = message.get("params")?;
+        params.get("textDocument")?.get("uri").cloned()
+    };
+    later.any(
+        |message| match message.get("method").and_then(Json::as_str) {
+            Some("$/cancelRequest") => message.get("params").and_then(|p| p.get("id")) == Some(id),
+            Some("textDocument/didChange") => {
+                method == Some("textDocument/completion") && uri(message) == uri(request)
+            }
+            _ => false,
+        },
+    )
+}
+
+/// Whether a change to a document is followed by another one to it
+pub fn superseded<'m>(change: &Json, mut later: impl Iterator<Item = &'m Json>) -> bool {
+    let changes = |message: &Json| {
+        message.get("method").and_then(Json::as_str) == Some("textDocument/didChange")
+    };
+    let uri = |message: &Json| {
+        let params = message.get("params")?;
+        params.get("textDocument")?.get("uri").cloned()
+    };
+    changes(change) && later.any(|message| changes(message) && uri(message) == uri(change))
+}
+
+/// The content of the next `Content-Length` framed message
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let Some(length) = length else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "message without a Content-Length header",
+        ));
+    };
+    let mut content = vec![0; length];
+    input.read_exact(&mut content)?;
+    Ok(Some(String::from_utf8_lossy(&content).into_owned()))
+}
+
+fn write_message(output: &mut impl Write, message: &Json) -> io::Result<()> {
+    let content = message.to_string();
+    write!(
+        output,
+        "Content-Length: {}\r\n\r\n{}",
+        content.len(),
+        content
+    )?;
+    output.flush()
+}
+
+/// An open document and its syntax tree, updated edit by edit
+#[derive(Debug, Clone)]
+struct Document {
+    text: String,
+    tree: Node,
+}
+
+impl Document {
+    fn new(text: &str) -> Self {
+        Self {
+            text: text.to_string(),
+            tree: cst::parse(text),
+        }
+    }
+
+    /// Apply one of the `contentChanges` of `didChange`: new `text` for a
+    /// `range`, or for the whole document without one
+    fn change(&mut self, change: &Json) {
+        let Some(text) = change.get("text").and_then(Json::as_str) else {
+            return;
+        };
+        let Some(range) = change.get("range") else {
+            *self = Document::new(text);
+            return;
+        };
+        let index = LineIndex::new(&self.text);
+        let offset = |position: Option<&Json>| {
+            let line = position?.get("line")?.as_int()?;
+            let character = position?.get("character")?.as_int()?;
+            Some(index.offset_at(line as usize, character as usize))
+        };
+        let (Some(start), Some(end)) = (offset(range.get("start")), offset(range.get("end")))
+        else {
+            return;
+        };
+        let edit = Edit {
+            start,
+            end: end.max(start),
+            text: text.to_string(),
+        };
+        edit.apply(&mut self.text);
+        self.tree.reparse(&self.text, &edit);
+    }
+}
+
+/// The documents an editor has open
+#[derive(Debug, Clone, Default)]
+pub struct Server {
+    documents: HashMap<String, Document>,
+    /// Accept `#![feature(...)]`, as `--unstable` does
+    unstable: bool,
+    shut_down: bool,
+    /// The client sent `exit`
+    pub exited: bool,
+}
+
+impl Server {
+    pub fn new(unstable: bool) -> Self {
+        Self {
+            unstable,
+            ..Self::default()
+        }
+    }
+
+    /// Answer one message: a response for a request, and diagnostics to
+    /// publish for notifications changing a document
+    pub fn handle(&mut self, message: &Json) -> Vec<Json> {
+        // Responses to requests of ours, which the server never sends
+        let Some(method) = message.get("method").and_then(Json::as_str) else {
+            return Vec::new();
+        };
+        let params = message.get("params").cloned().unwrap_or(Json::Null);
+        match message.get("id") {
+            Some(id) => vec![rpc::response(id.clone(), self.request(method, &params))],
+            None => self.notify(method, &params),
+        }
+    }
+
+    fn request(&mut self, method: &str, params: &Json) -> Result<Json, RpcError> {
+        if self.shut_down {
+            return Err((
+                rpc::INVALID_REQUEST,
+                "the server is shutting down".to_string(),
+            ));
+        }
+        match method {
+            "initialize" => Ok(Json::object([
+                ("capabilities", capabilities()),
+                (
+                    "serverInfo",
+                    Json::object([
+                        ("name", Json::str("solo")),
+                        ("version", Json::str(env!("CARGO_PKG_VERSION"))),
+                    ]),
+                ),
+            ])),
+            "shutdown" => {
+                self.shut_down = true;
+                Ok(Json::Null)
+            }
+            "textDocument/semanticTokens/full" => {
+                let (_, document) = self.document(params)?;
+                let tokens = semantic_tokens_in(&document.tree, &document.text);
+                Ok(Json::object([("data", encode(&tokens))]))
+            }
+            "textDocument/documentSymbol" => {
+                let (_, document) = self.document(params)?;
+                let index = LineIndex::new(&document.text);
+                let symbols = document_symbols_in(&document.tree);
+                Ok(Json::Array(
+                    symbols.iter().map(|s| symbol_json(&index, s)).collect(),
+                ))
+            }
+            "textDocument/foldingRange" => {
+                let (_, document) = self.document(params)?;
+                let ranges = folding_ranges_in(&document.tree, &document.text)
+                    .into_iter()
+                    .map(|range| {
+                        let mut fields = vec![
+                            ("startLine", Json::Int(range.start.into())),
+                            ("endLine", Json::Int(range.end.into())),
+                        ];
+                        if range.comment {
+                            fields.push(("kind", Json::str("comment")));
+                        }
+                        Json::object(fields)
+                    });
+                Ok(Json::Array(ranges.collect()))
+            }
+            "textDocument/completion" => {
+                let (_, document) = self.document(params)?;
+                let source = &document.text;
+                let offset = position(params, source)?;
+                let items = completions(source, offset);
+                Ok(Json::object([
+                    ("isIncomplete", Json::Bool(false)),
+                    (
+                        "items",
+                        Json::Array(items.iter().map(completion_json).collect()),
+                    ),
+                ]))
+            }
+            "textDocument/references" => {
+                let (uri, document) = self.document(params)?;
+                let source = &document.text;
+                let offset = position(params, source)?;
+                let declarations = params
+                    .get("context")
+                    .and_then(|c| c.get("includeDeclaration"))
+                    == Some(&Json::Bool(true));
+                let workspace = self.workspace(uri);
+                let occurrences = occurrences_of(&workspace.resolve(), 0, offset);
+                let locations = occurrences
+                    .iter()
+                    .filter(|o| declarations || o.at != o.definition)
+                    .map(|o| {
+                        let file = &workspace.files[o.at.0];
+                        let range = LineIndex::new(&file.text).range(o.at.1, o.at.1 + o.name.len());
+                        Json::object([("uri", Json::str(file.uri.as_str())), ("range", range)])
+                    });
+                Ok(Json::Array(locations.collect()))
+            }
+            "textDocument/rename" => {
+                let (uri, document) = self.document(params)?;
+                let source = &document.text;
+                let offset = position(params, source)?;
+                let new_name = params
+                    .get("newName")
+                    .and_then(Json::as_str)
+                    .ok_or_else(|| rpc::invalid_param("newName", "a string"))?;
+                let workspace = self.workspace(uri);
+                let edits = rename(&workspace, 0, offset, new_name)
+                    .map_err(|message| (REQUEST_FAILED, message))?;
+                let changes = edits.iter().map(|(&file, ranges)| {
+                    let file = &workspace.files[file];
+                    let index = LineIndex::new(&file.text);
+                    let edits = ranges.iter().map(|&(start, end)| {
+                        Json::object([
+                            ("range", index.range(start, end)),
+                            ("newText", Json::str(new_name)),
+                        ])
+                    });
+                    (file.uri.clone(), Json::Array(edits.collect()))
+                });
+                Ok(Json::object([("changes", Json::object(changes))]))
+            }
+            _ => Err((
+                rpc::METHOD_NOT_FOUND,
+                format!("unknown method `{}`", method),
+            )),
+        }
+    }
+
+    /// The document `uri`, first, with the other files of its package that
+    /// are checked with it
+    fn workspace(&self, uri: &str) -> Workspace {
+        let text = self
+            .documents
+            .get(uri)
+            .map(|document| document.text.clone())
+            .unwrap_or_default();
+        let mut workspace = Workspace {
+            files: vec![File {
+                uri: uri.to_string(),
+                text,
+            }],
+            units: vec![vec![0]],
+        };
+        let Some(path) = uri.strip_prefix("file://").map(Path::new) else {
+            return workspace;
+        };
+        let Some(package) = path.parent().and_then(|dir| Package::discover(dir).ok()) else {
+            return workspace;
+        };
+        let library = package.library();
+        let targets: Vec<_> = package
+            .targets()
+            .into_iter()
+            .map(|target| target.path)
+            .filter(|path| path.extension().is_some_and(|ext| ext == "solo"))
+            .collect();
+        if library.as_deref() != Some(path) && !targets.iter().any(|t| t == path) {
+            return workspace;
+        }
+        // Files open in the editor may differ from the disk
+        let mut index_of = |file: &Path| {
+            if file == path {
+                return Some(0);
+            }
+            let uri = format!("file://{}", file.display());
+            let text = match self.documents.get(&uri) {
+                Some(document) => document.text.clone(),
+                None => fs::read_to_string(file).ok()?,
+            };
+            workspace.files.push(File { uri, text });
+            Some(workspace.files.len() - 1)
+        };
+        let library = library.and_then(|path| index_of(&path));
+        let mut units = Vec::new();
+        for target in &targets {
+            if let Some(target) = index_of(target) {
+                units.push(std::iter::once(target).chain(library).collect());
+            }
+        }
+        if units.is_empty() {
+            units.extend(library.map(|library| vec![library]));
+        }
+        workspace.units = units;
+        workspace
+    }
+
+    fn notify(&mut self, method: &str, params: &Json) -> Vec<Json> {
+        match self.change(method, params) {
+            Some(uri) => {
+                let diagnostics = self.documents.get(&uri).map(|d| self.diagnostics(d));
+                vec![publish(&uri, diagnostics.unwrap_or_default())]
+            }
+            None => Vec::new(),
+        }
+    }
+
+    /// Apply a notification changing a document without publishing its
+    /// diagnostics
+    pub fn update(&mut self, message: &Json) {
+        let method = message.get("method").and_then(Json::as_str).unwrap_or("");
+        let params = message.get("params").cloned().unwrap_or(Json::Null);
+        self.change(method, &params);
+    }
+
+    /// Open, change or close a document, returning its URI
+    fn change(&mut self, method: &str, params: &Json) -> Option<String> {
+        let document = params.get("textDocument");
+        let Some(uri) = document.and_then(|d| d.get("uri")).and_then(Json::as_str) else {
+            if method == "exit" {
+                self.exited = true;
+            }
+            return None;
+        };
+        match method {
+            "textDocument/didOpen" => {
+                let text = document?.get("text")?.as_str()?;
+                self.documents.insert(uri.to_string(), Document::new(text));
+            }
+            "textDocument/didChange" => {
+                let Some(Json::Array(changes)) = params.get("contentChanges") else {
+                    return None;
+                };
+                let open = self
+                    .documents
+                    .entry(uri.to_string())
+                    .or_insert_with(|| Document::new(""));
+                for change in changes {
+                    open.change(change);
+                }
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+            }
+            _ => return None,
+        }
+        Some(uri.to_string())
+    }
+
+    /// The open document named by `textDocument.uri`
+    fn document<'s>(&'s self, params: &Json) -> Result<(&'s str, &'s Document), RpcError> {
+        let uri = params
+            .get("textDocument")
+            .and_then(|d| d.get("uri"))
+            .and_then(Json::as_str)
+            .ok_or_else(|| rpc::invalid_param("textDocument.uri", "a string"))?;
+        match self.documents.get_key_value(uri) {
+            Some((uri, document)) => Ok((uri, document)),
+            None => Err(rpc::no_document(uri)),
+        }
+    }
+
+    /// LSP diagnostics of checking `source`
+    ///
+    /// Unstable features are rejected first, as the stable command line
+    /// does, from the same parse.
+    fn diagnostics(&self, document: &Document) -> Vec<Json> {
+        let source = &document.text;
+        let result = crate::compile_until(source, Phase::Parse).and_then(|artifact| {
+            let program = artifact.into_ast().expect("parsing produces a program");
+            if !self.unstable {
+                feature::check_stable(&program)?;
+            }
+            feature::check(&program)?;
+            typeck::check(&program).map(drop)
+        });
+        let Err(err) = result else {
+            return Vec::new();
+        };
+        let index = LineIndex::new(source);
+        let tree = &document.tree;
+        err.diagnostics()
+            .iter()
+            .map(|diagnostic| {
+                let (start, end) = match diagnostic.span {
+                    Some(span) => {
+                        let start = index.offset(span);
+                        match tree.token_at(start) {
+                            Some((at, token)) if !token.is_trivia() => {
+                                (start, at + token.text.len())
+                            }
+                            _ => (start, start),
+                        }
+                    }
+                    None => (0, 0),
+                };
+                Json::object([
+                    ("range", index.range(start, end)),
+                    ("severity", Json::Int(1)),
+                    ("source", Json::str("solo")),
+                    ("code", Json::str(err.stage())),
+                    ("message", Json::str(diagnostic.message.as_str())),
+                ])
+            })
+            .collect()
+    }
+}
+
+fn publish(uri: &str, diagnostics: Vec<Json>) -> Json {
+    Json::object([
+        ("jsonrpc", Json::str("2.0")),
+        ("method", Json::str("textDocument/publishDiagnostics")),
+        (
+            "params",
+            Json::object([
+                ("uri", Json::str(uri)),
+                ("diagnostics", Json::Array(diagnostics)),
+            ]),
+        ),
+    ])
+}
+
+fn capabilities() -> Json {
+    let names = |names: &[&str]| Json::Array(names.iter().map(|&n| Json::str(n)).collect());
+    Json::object([
+        // The edited range on every change
+        ("textDocumentSync", Json::Int(2)),
+        (
+            "semanticTokensProvider",
+            Json::object([
+                (
+                    "legend",
+                    Json::object([
+                        ("tokenTypes", names(TOKEN_TYPES)),
+                        ("tokenModifiers", names(TOKEN_MODIFIERS)),
+                    ]),
+                ),
+                ("full", Json::Bool(true)),
+            ]),
+        ),
+        ("documentSymbolProvider", Json::Bool(true)),
+        ("foldingRangeProvider", Json::Bool(true)),
+        (
+            "completionProvider",
+            Json::object([("triggerCharacters", names(&[".", ":"]))]),
+        ),
+        ("referencesProvider", Json::Bool(true)),
+        ("renameProvider", Json::Bool(true)),
+    ])
+}
+
+/// Byte offset in `source` of the request's `position`
+fn position(params: &Json, source: &str) -> Result<usize, RpcError> {
+    let position = params.get("position");
+    let line = position.and_then(|p| p.get("line")).and_then(Json::as_int);
+    let character = position
+        .and_then(|p| p.get("character"))
+        .and_then(Json::as_int);
+    let (Some(line), Some(character)) = (line, character) else {
+        return Err(rpc::invalid_param("position", "a line and character"));
+    };
+    Ok(LineIndex::new(source).offset_at(line as usize, character as usize))
+}
+
+/// Converts byte offsets into LSP positions: 0-based lines and UTF-16
+/// columns
+struct LineIndex<'s> {
+    source: &'s str,
+    /// Byte offset of the start of each line
+    starts: Vec<usize>,
+}
+
+impl<'s> LineIndex<'s> {
+    fn new(source: &'s str) -> Self {
+        let starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self { source, starts }
+    }
+
+    fn position(&self, offset: usize) -> (u32, u32) {
+        let line = self.starts.partition_point(|&start| start <= offset) - 1;
+        let column = self.source[self.starts[line]..offset]
+            .encode_utf16()
+            .count();
+        (line as u32, column as u32)
+    }
+
+    /// Position of `offset` as the compiler reports it
+    fn span(&self, offset: usize) -> Span {
+        let line = self.starts.partition_point(|&start| start <= offset) - 1;
+        let column = self.source[self.starts[line]..offset].chars().count();
+        Span::new(line + 1, column + 1)
+    }
+
+    /// Byte offset of a compiler position, clamped to the source
+    fn offset(&self, span: Span) -> usize {
+        let Some(&start) = self.starts.get(span.line.saturating_sub(1)) else {
+            return self.source.len();
+        };
+        let line = self.source[start..].split('\n').next().unwrap_or("");
+        let column = line
+            .char_indices()
+            .nth(span.column.saturating_sub(1))
+            .map_or(line.len(), |(i, _)| i);
+        start + column
+    }
+
+    /// Byte offset of an LSP position, clamped to its line
+    fn offset_at(&self, line: usize, character: usize) -> usize {
+        let Some(&start) = self.starts.get(line) else {
+            return self.source.len();
+        };
+        let text = self.source[start..].split('\n').next().unwrap_or("");
+        let mut units = 0;
+        for (i, c) in text.char_indices() {
+            if units >= character {
+                return start + i;
+            }
+            units += c.len_utf16();
+        }
+        start + text.len()
+    }
+
+    fn range(&self, start: usize, end: usize) -> Json {
+        let position = |offset| {
+            let (line, character) = self.position(offset);
+            Json::object([
+                ("line", Json::Int(line.into())),
+                ("character", Json::Int(character.into())),
+            ])
+        };
+        Json::object([("start", position(start)), ("end", position(end))])
+    }
+}
+
+/// A token of the syntax tree with where it is
+struct Located<'t> {
+    token: &'t SyntaxToken,
+    offset: usize,
+    /// Kind of the item it belongs to, `Root` outside items
+    item: NodeKind,
+    /// Kind of the innermost node containing it
+    parent: NodeKind,
+}
+
+fn locate<'t>(node: &'t Node, item: NodeKind, offset: &mut usize, out: &mut Vec<Located<'t>>) {
+    for child in &node.children {
+        match child {
+            Element::Node(inner) => {
+                let item = if node.kind == NodeKind::Root {
+                    inner.kind
+                } else {
+                    item
+                };
+                locate(inner, item, offset, out);
+            }
+            Element::Token(token) => {
+                out.push(Located {
+                    token,
+                    offset: *offset,
+                    item,
+                    parent: node.kind,
+                });
+                *offset += token.text.len();
+            }
+        }
+    }
+}
+
+/// Names declared by the items of a file
+#[derive(Default)]
+struct Names<'t> {
+    structs: HashSet<&'t str>,
+    enums: HashSet<&'t str>,
+    aliases: HashSet<&'t str>,
+    consts: HashSet<&'t str>,
+}
+
+impl<'t> Names<'t> {
+    fn new(root: &'t Node) -> Self {
+        let mut names = Self::default();
+        for node in root.nodes() {
+            let Some(name) = node.name() else { continue };
+            match node.kind {
+                NodeKind::Struct => names.structs.insert(name),
+                NodeKind::Enum => names.enums.insert(name),
+                NodeKind::TypeAlias => names.aliases.insert(name),
+                NodeKind::Const => names.consts.insert(name),
+                _ => false,
+            };
+        }
+        names
+    }
+
+    /// A type declared in the file or built in
+    fn ty(&self, name: &str) -> Option<(TokenType, u32)> {
+        if self.structs.contains(name) || name == "Self" {
+            Some((TokenType::Struct, 0))
+        } else if self.enums.contains(name) {
+            Some((TokenType::Enum, 0))
+        } else if self.aliases.contains(name) {
+            Some((TokenType::Type, 0))
+        } else if PRIMITIVES.contains(&name) {
+            Some((TokenType::Type, DEFAULT_LIBRARY))
+        } else if name == "Belief" {
+            Some((TokenType::Type, DEFAULT_LIBRARY | BELIEF))
+        } else if name == crate::region::ARENA_TYPE {
+            Some((TokenType::Type, DEFAULT_LIBRARY | ARENA))
+        } else {
+            None
+        }
+    }
+}
+
+/// Highlighting of `source`, in order, with multi-line tokens split per line
+pub fn semantic_tokens(source: &str) -> Vec<SemanticToken> {
+    semantic_tokens_in(&cst::parse(source), source)
+}
+
+/// [`semantic_tokens`] of `source`, whose syntax tree is `tree`
+fn semantic_tokens_in(tree: &Node, source: &str) -> Vec<SemanticToken> {
+    let index = LineIndex::new(source);
+    let mut located = Vec::new();
+    locate(tree, NodeKind::Root, &mut 0, &mut located);
+    let highlighter = Highlighter {
+        significant: located.iter().filter(|t| !t.token.is_trivia()).collect(),
+        names: Names::new(tree),
+        bindings: Bindings::of(source),
+        index: &index,
+    };
+
+    let mut tokens = Vec::new();
+    let mut significant = 0;
+    for token in &located {
+        let classified = match &token.token.kind {
+            SyntaxKind::Trivia(Trivia::Comment) => Some((TokenType::Comment, 0)),
+            SyntaxKind::Trivia(Trivia::Whitespace) => None,
+            SyntaxKind::Token(_) => {
+                significant += 1;
+                highlighter.classify(significant - 1)
+            }
+        };
+        let Some((ty, modifiers)) = classified else {
+            continue;
+        };
+        let mut offset = token.offset;
+        for line in token.token.text.split_inclusive('\n') {
+            let text = line.trim_end_matches(['\n', '\r']);
+            if !text.is_empty() {
+                let (line, start) = index.position(offset);
+                tokens.push(SemanticToken {
+                    line,
+                    start,
+                    length: text.encode_utf16().count() as u32,
+                    ty,
+                    modifiers,
+                });
+            }
+            offset += line.len();
+        }
+    }
+    tokens
+}
+
+/// Classifies tokens by their kind, the tokens around them and what the
+/// program declares
+struct Highlighter<'t> {
+    /// Tokens other than trivia
+    significant: Vec<&'t Located<'t>>,
+    names: Names<'t>,
+    bindings: HashMap<Span, Binding>,
+    index: &'t LineIndex<'t>,
+}
+
+impl Highlighter<'_> {
+    /// Kind of the `i`th significant token, `None` past either end
+    fn kind(&self, i: Option<usize>) -> Option<&TokenKind> {
+        match &self.significant.get(i?)?.token.kind {
+            SyntaxKind::Token(kind) => Some(kind),
+            SyntaxKind::Trivia(_) => None,
+        }
+    }
+
+    fn classify(&self, i: usize) -> Option<(TokenType, u32)> {
+        use TokenKind::*;
+        match self.kind(Some(i))? {
+            Belief => Some((TokenType::Type, DEFAULT_LIBRARY | BELIEF)),
+            Arena => Some((TokenType::Keyword, ARENA)),
+            Fn | Let | Mut | If | Else | While | For | In | Match | Return | Become | Struct
+            | Enum | Impl | Trait | Type | Newtype | Defer | Extern | Pub | Where | Macro
+            | Const | True | False => Some((TokenType::Keyword, 0)),
+            Integer(..) | Float(..) => Some((TokenType::Number, 0)),
+            String(_) => Some((TokenType::String, 0)),
+            Tilde => Some((TokenType::Operator, BELIEF)),
+            Plus | Minus | Star | Slash | Percent | Eq | EqEq | Ne | Lt | Le | Gt | Ge | And
+            | Or | Not | Arrow | FatArrow | Ampersand | Pipe | Question | DotDot | DotDotEq => {
+                Some((TokenType::Operator, 0))
+            }
+            Identifier(name) => Some(self.identifier(i, name)),
+            _ => None,
+        }
+    }
+
+    fn identifier(&self, i: usize, name: &str) -> (TokenType, u32) {
+        use TokenKind as K;
+        let token = self.significant[i];
+        let prev = |n: usize| self.kind(i.checked_sub(n));
+        let next = |n: usize| self.kind(Some(i + n));
+        let function = match token.item {
+            NodeKind::Impl | NodeKind::Trait => TokenType::Method,
+            _ => TokenType::Function,
+        };
+        // `crate` of `pub(crate)`
+        if name == "self" || (name == "crate" && prev(2) == Some(&K::Pub)) {
+            return (TokenType::Keyword, 0);
+        }
+        match (prev(1), next(1)) {
+            (Some(K::Fn), _) => (function, DECLARATION),
+            (Some(K::Struct), _) => (TokenType::Struct, DECLARATION),
+            (Some(K::Enum), _) => (TokenType::Enum, DECLARATION),
+            (Some(K::Trait | K::Type | K::Newtype), _) => (TokenType::Type, DECLARATION),
+            (Some(K::Const), _) => (TokenType::Variable, DECLARATION | READONLY),
+            (Some(K::Macro), _) => (TokenType::Macro, DECLARATION),
+            // A metavariable of a macro
+            (Some(K::Dollar), _) => (TokenType::Variable, 0),
+            // Locals are found by the position of the keyword declaring them
+            (Some(K::Let), _) => self.local(i - 1, DECLARATION),
+            (Some(K::Mut), _) if prev(2) == Some(&K::Let) => self.local(i - 2, DECLARATION),
+            (Some(K::Arena), _) => (TokenType::Variable, DECLARATION | ARENA),
+            // A distribution
+            (Some(K::Tilde), _) => (TokenType::Function, DEFAULT_LIBRARY | BELIEF),
+            (_, Some(K::Not)) if matches!(next(2), Some(K::LParen | K::LBracket | K::LBrace)) => {
+                (TokenType::Macro, 0)
+            }
+            (Some(K::Dot), Some(K::LParen)) => (TokenType::Method, 0),
+            (Some(K::Dot), _) => (TokenType::Property, 0),
+            (Some(K::DoubleColon), Some(K::LParen)) => (TokenType::Method, 0),
+            (Some(K::DoubleColon), _) => (TokenType::EnumMember, 0),
+            (_, Some(K::DoubleColon)) => self.names.ty(name).unwrap_or((TokenType::Type, 0)),
+            (Some(K::LBrace | K::Comma), _) if token.item == NodeKind::Enum => {
+                (TokenType::EnumMember, DECLARATION)
+            }
+            (_, Some(K::Colon)) => match (token.item, token.parent) {
+                (
+                    NodeKind::Function | NodeKind::Impl | NodeKind::Trait | NodeKind::Extern,
+                    NodeKind::Parens,
+                ) => self.local(i, DECLARATION),
+                (NodeKind::Struct, NodeKind::Block) => (TokenType::Property, DECLARATION),
+                // A field of a struct literal
+                _ => (TokenType::Property, 0),
+            },
+            (_, next) => {
+                if let Some(ty) = self.names.ty(name) {
+                    ty
+                } else if next == Some(&K::LParen) {
+                    (TokenType::Function, 0)
+                } else if self.names.consts.contains(name) {
+                    (TokenType::Variable, READONLY)
+                } else {
+                    self.local(i, 0)
+                }
+            }
+        }
+    }
+
+    /// A local variable or parameter, found by the `at`th significant token
+    fn local(&self, at: usize, modifiers: u32) -> (TokenType, u32) {
+        let span = self.index.span(self.significant[at].offset);
+        let binding = self.bindings.get(&span).copied().unwrap_or_default();
+        let ty = if binding.parameter {
+            TokenType::Parameter
+        } else {
+            TokenType::Variable
+        };
+        (ty, modifiers | binding.modifiers())
+    }
+}
+
+/// Delta-encoded `data` of a `semanticTokens` result
+fn encode(tokens: &[SemanticToken]) -> Json {
+    let mut data = Vec::with_capacity(tokens.len() * 5);
+    let (mut line, mut start) = (0, 0);
+    for token in tokens {
+        if token.line != line {
+            start = 0;
+        }
+        data.extend([
+            token.line - line,
+            token.start - start,
+            token.length,
+            token.ty as u32,
+            token.modifiers,
+        ]);
+        line = token.line;
+        start = token.start;
+    }
+    Json::Array(data.into_iter().map(|n| Json::Int(n.into())).collect())
+}
+
+/// What a local variable holds
+#[derive(Debug, Clone, Copy, Default)]
+struct Binding {
+    parameter: bool,
+    belief: bool,
+    arena: bool,
+}
+
+impl Binding {
+    fn of_type(ty: &TypeExpr) -> Self {
+        let name = match ty {
+            TypeExpr::Named(name, _) | TypeExpr::Generic { name, .. } => name.as_str(),
+            _ => "",
+        };
+        Self {
+            parameter: false,
+            belief: name == "Belief",
+            arena: name == crate::region::ARENA_TYPE,
+        }
+    }
+
+    fn modifiers(self) -> u32 {
+        let mut modifiers = 0;
+        if self.belief {
+            modifiers |= BELIEF;
+        }
+        if self.arena {
+            modifiers |= ARENA;
+        }
+        modifiers
+    }
+}
+
+/// Locals of a program by the position declaring or using them: parameter
+/// names, `let` keywords and paths
+struct Bindings<'a> {
+    exprs: &'a Exprs,
+    scopes: Vec<HashMap<String, Binding>>,
+    /// Functions returning beliefs
+    beliefs: HashSet<String>,
+    found: HashMap<Span, Binding>,
+}
+
+impl<'a> Bindings<'a> {
+    /// Locals of `source`, none when it does not parse
+    fn of(source: &str) -> HashMap<Span, Binding> {
+        let Some(program) = crate::compile_until(source, Phase::Parse)
+            .ok()
+            .and_then(|artifact| artifact.into_ast())
+        else {
+            return HashMap::new();
+        };
+        let mut bindings = Bindings::new(&program);
+        bindings.visit_program(&program);
+        bindings.found
+    }
+
+    fn new(program: &'a Program) -> Self {
+        let beliefs = program
+            .functions()
+            .into_iter()
+            .filter(|(_, _, f)| {
+                f.return_type
+                    .as_ref()
+                    .is_some_and(|t| Binding::of_type(t).belief)
+            })
+            .map(|(name, _, _)| name)
+            .collect();
+        Self {
+            exprs: &program.exprs,
+            scopes: Vec::new(),
+            beliefs,
+            found: HashMap::new(),
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Option<Binding> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).copied())
+    }
+
+    fn declare(&mut self, name: &str, span: Span, binding: Binding) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), binding);
+        }
+        self.found.insert(span, binding);
+    }
+
+    /// Does `expr` evaluate to a belief?
+    fn is_belief(&self, expr: &Expr) -> bool {
+        match &expr.kind {
+            ExprKind::Distribution(..) => true,
+            ExprKind::Path(path) => match path.as_slice() {
+                [name] => self.lookup(name).is_some_and(|b| b.belief),
+                _ => false,
+            },
+            ExprKind::Call(callee, _) => match &self.exprs[*callee].kind {
+                ExprKind::Path(path) => self.beliefs.contains(&join_path(path)),
+                _ => false,
+            },
+            ExprKind::Unary(_, operand) => self.is_belief(&self.exprs[*operand]),
+            ExprKind::Binary(_, left, right) => {
+                self.is_belief(&self.exprs[*left]) || self.is_belief(&self.exprs[*right])
+            }
+            ExprKind::Block(block) => block.tail.is_some_and(|t| self.is_belief(&self.exprs[t])),
+            _ => false,
+        }
+    }
+}
+
+impl<'a> Visit<'a> for Bindings<'a> {
+    fn exprs(&self) -> &'a Exprs {
+        self.exprs
+    }
+
+    fn visit_function(&mut self, function: &Function) {
+        self.scopes.push(HashMap::new());
+        for param in &function.params {
+            let binding = Binding {
+                parameter: true,
+                ..Binding::of_type(&param.ty)
+            };
+            self.declare(&param.name, param.span, binding);
+        }
+        self.visit_block(&function.body);
+        self.scopes.pop();
+    }
+
+    fn visit_block(&mut self, block: &Block) {
+        self.scopes.push(HashMap::new());
+        visit::walk_block(self, block);
+        self.scopes.pop();
+    }
+
+    fn visit_let(&mut self, stmt: &LetStmt) {
+        visit::walk_let(self, stmt);
+        let mut binding = stmt.ty.as_ref().map(Binding::of_type).unwrap_or_default();
+        binding.belief |= stmt.value.as_ref().is_some_and(|v| self.is_belief(v));
+        self.declare(&stmt.name, stmt.span, binding);
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Path(path) if path.len() == 1 => {
+                if let Some(binding) = self.lookup(&path[0]) {
+                    self.found.insert(expr.span, binding);
+                }
+            }
+            ExprKind::Arena(Some(name), allocator, block) => {
+                if let Some(allocator) = allocator {
+                    self.visit_expr(&self.exprs[*allocator]);
+                }
+                self.scopes.push(HashMap::new());
+                let binding = Binding {
+                    arena: true,
+                    ..Binding::default()
+                };
+                self.declare(name, expr.span, binding);
+                self.visit_block(block);
+                self.scopes.pop();
+            }
+            _ => visit::walk_expr(self, expr),
+        }
+    }
+}
+
+/// The outline of `source`: its items, with their fields, variants and
+/// methods as children
+pub fn document_symbols(source: &str) -> Vec<Symbol> {
+    document_symbols_in(&cst::parse(source))
+}
+
+fn document_symbols_in(tree: &Node) -> Vec<Symbol> {
+    let mut symbols = Vec::new();
+    let mut offset = 0;
+    for child in &tree.children {
+        if let Element::Node(node) = child {
+            if let Some(symbol) = item_symbol(node, offset) {
+                symbols.push(symbol);
+            }
+        }
+        offset += child.text_len();
+    }
+    symbols
+}
+
+fn item_symbol(node: &Node, start: usize) -> Option<Symbol> {
+    let (kind, members) = match node.kind {
+        NodeKind::Function | NodeKind::Macro => (SymbolKind::Function, None),
+        NodeKind::Const => (SymbolKind::Constant, None),
+        NodeKind::Struct => (SymbolKind::Struct, Some(SymbolKind::Field)),
+        NodeKind::Enum => (SymbolKind::Enum, Some(SymbolKind::EnumMember)),
+        NodeKind::Impl => (SymbolKind::Object, Some(SymbolKind::Method)),
+        NodeKind::Trait => (SymbolKind::Interface, Some(SymbolKind::Method)),
+        NodeKind::Extern => (SymbolKind::Namespace, Some(SymbolKind::Function)),
+        NodeKind::TypeAlias => (SymbolKind::TypeParameter, None),
+        NodeKind::Root
+        | NodeKind::Attribute
+        | NodeKind::Parens
+        | NodeKind::Brackets
+        | NodeKind::Block
+        | NodeKind::Error => return None,
+    };
+    let children: Vec<(usize, &Element)> = offsets(node, start);
+    let name = children.iter().find_map(|&(at, child)| match child {
+        Element::Token(SyntaxToken {
+            kind: SyntaxKind::Token(TokenKind::Identifier(name)),
+            ..
+        }) => Some((name.to_string(), (at, at + name.len()))),
+        _ => None,
+    });
+    let range = trimmed(&children)?;
+    let (name, selection) = match (node.kind, name) {
+        (NodeKind::Impl, Some((name, selection))) => (format!("impl {}", name), selection),
+        (_, Some(named)) => named,
+        // `extern "C" { ... }`
+        (_, None) => {
+            let keyword = children.iter().find(|(_, child)| !is_trivia(child))?;
+            let (at, _) = *keyword;
+            ("extern".to_string(), (at, at + "extern".len()))
+        }
+    };
+    let mut children_symbols = Vec::new();
+    let body = children.iter().rev().find_map(|&(at, child)| match child {
+        Element::Node(block) if block.kind == NodeKind::Block => Some((at, block)),
+        _ => None,
+    });
+    if let (Some(kind), Some((at, body))) = (members, body) {
+        children_symbols = member_symbols(body, at, kind);
+    }
+    Some(Symbol {
+        name,
+        kind,
+        range,
+        selection,
+        children: children_symbols,
+    })
+}
+
+/// Fields or variants (separated by `,`) or functions (ending with `;` or a
+/// body) declared in the body of an item
+fn member_symbols(body: &Node, start: usize, kind: SymbolKind) -> Vec<Symbol> {
+    let children = offsets(body, start);
+    // Without the braces
+    let inner = children
+        .get(1..children.len().saturating_sub(1))
+        .unwrap_or(&[]);
+    let mut members = Vec::new();
+    let mut member: Vec<(usize, &Element)> = Vec::new();
+    for &(at, child) in inner {
+        let ends_before = matches!(kind, SymbolKind::Field | SymbolKind::EnumMember)
+            && token_kind(child) == Some(&TokenKind::Comma);
+        if !ends_before {
+            member.push((at, child));
+        }
+        let ends = ends_before
+            || token_kind(child) == Some(&TokenKind::Semicolon)
+            || matches!(child, Element::Node(node) if node.kind == NodeKind::Block);
+        if ends {
+            members.extend(member_symbol(&member, kind));
+            member.clear();
+        }
+    }
+    members.extend(member_symbol(&member, kind));
+    members
+}
+
+fn member_symbol(member: &[(usize, &Element)], kind: SymbolKind) -> Option<Symbol> {
+    let significant: Vec<&(usize, &Element)> = member
+        .iter()
+        .filter(|(_, child)| !is_trivia(child))
+        .collect();
+    let name = match kind {
+        SymbolKind::Field | SymbolKind::EnumMember => significant.first(),
+        _ => significant
+            .windows(2)
+            .find(|pair| token_kind(pair[0].1) == Some(&TokenKind::Fn))
+            .map(|pair| &pair[1]),
+    };
+    let &&(at, Element::Token(token)) = name? else {
+        return None;
+    };
+    let SyntaxKind::Token(TokenKind::Identifier(name)) = &token.kind else {
+        return None;
+    };
+    Some(Symbol {
+        name: name.to_string(),
+        kind,
+        range: trimmed(member)?,
+        selection: (at, at + name.len()),
+        children: Vec::new(),
+    })
+}
+
+/// Children of `node` with their byte offsets, `node` starting at `start`
+fn offsets(node: &Node, start: usize) -> Vec<(usize, &Element)> {
+    let mut offset = start;
+    node.children
+        .iter()
+        .map(|child| {
+            let at = offset;
+            offset += child.text_len();
+            (at, child)
+        })
+        .collect()
+}
+
+/// Byte range of `children` without the whitespace around them
+fn trimmed(children: &[(usize, &Element)]) -> Option<(usize, usize)> {
+    let is_space = |child: &Element| {
+        matches!(
+            child,
+            Element::Token(SyntaxToken {
+                kind: SyntaxKind::Trivia(Trivia::Whitespace),
+                ..
+            })
+        )
+    };
+    let &(start, _) = children.iter().find(|(_, child)| !is_space(child))?;
+    let &(at, last) = children.iter().rev().find(|(_, child)| !is_space(child))?;
+    Some((start, at + last.text_len()))
+}
+
+fn is_trivia(element: &Element) -> bool {
+    matches!(element, Element::Token(token) if token.is_trivia())
+}
+
+fn token_kind(element: &Element) -> Option<&TokenKind> {
+    match element {
+        Element::Token(SyntaxToken {
+            kind: SyntaxKind::Token(kind),
+            ..
+        }) => Some(kind),
+        _ => None,
+    }
+}
+
+fn symbol_json(index: &LineIndex, symbol: &Symbol) -> Json {
+    Json::object([
+        ("name", Json::str(symbol.name.as_str())),
+        ("kind", Json::Int(symbol.kind as i64)),
+        ("range", index.range(symbol.range.0, symbol.range.1)),
+        (
+            "selectionRange",
+            index.range(symbol.selection.0, symbol.selection.1),
+        ),
+        (
+            "children",
+            Json::Array(
+                symbol
+                    .children
+                    .iter()
+                    .map(|s| symbol_json(index, s))
+                    .collect(),
+            ),
+        ),
+    ])
+}
+
+/// Ranges of `source` to fold: the inside of groups spanning several lines,
+/// leaving their closing delimiter visible, and runs of comments
+pub fn folding_ranges(source: &str) -> Vec<FoldingRange> {
+    folding_ranges_in(&cst::parse(source), source)
+}
+
+fn folding_ranges_in(tree: &Node, source: &str) -> Vec<FoldingRange> {
+    let index = LineIndex::new(source);
+    let mut ranges = Vec::new();
+    fold_groups(tree, 0, &index, &mut ranges);
+
+    let mut located = Vec::new();
+    locate(tree, NodeKind::Root, &mut 0, &mut located);
+    // Line comments on consecutive lines fold together
+    let mut run: Option<(u32, u32)> = None;
+    for token in &located {
+        let line = index.position(token.offset).0;
+        match &token.token.kind {
+            SyntaxKind::Trivia(Trivia::Comment) if token.token.text.starts_with("//") => {
+                run = match run {
+                    Some((start, end)) if line == end + 1 || line == end => Some((start, line)),
+                    _ => {
+                        fold_comments(run, &mut ranges);
+                        Some((line, line))
+                    }
+                };
+            }
+            SyntaxKind::Trivia(Trivia::Comment) => {
+                let end = index.position(token.offset + token.token.text.len()).0;
+                fold_comments(Some((line, end)), &mut ranges);
+            }
+            SyntaxKind::Trivia(Trivia::Whitespace)
+                if token.token.text.matches('\n').count() < 2 => {}
+            _ => {
+                fold_comments(run.take(), &mut ranges);
+            }
+        }
+    }
+    fold_comments(run, &mut ranges);
+    ranges.sort_by_key(|range| (range.start, range.end));
+    ranges
+}
+
+fn fold_groups(node: &Node, start: usize, index: &LineIndex, ranges: &mut Vec<FoldingRange>) {
+    for (at, child) in offsets(node, start) {
+        let Element::Node(inner) = child else {
+            continue;
+        };
+        if matches!(
+            inner.kind,
+            NodeKind::Block | NodeKind::Parens | NodeKind::Brackets
+        ) {
+            let first = index.position(at).0;
+            let last = index.position(at + inner.text_len().saturating_sub(1)).0;
+            if last > first + 1 {
+                ranges.push(FoldingRange {
+                    start: first,
+                    end: last - 1,
+                    comment: false,
+                });
+            }
+        }
+        fold_groups(inner, at, index, ranges);
+    }
+}
+
+fn fold_comments(run: Option<(u32, u32)>, ranges: &mut Vec<FoldingRange>) {
+    if let Some((start, end)) = run.filter(|(start, end)| end > start) {
+        ranges.push(FoldingRange {
+            start,
+            end,
+            comment: true,
+        });
+    }
+}
+
+/// Identifier put in place of the one being completed, so that the code
+/// around it parses
+const PLACEHOLDER: &str = "__solo_completion";
+
+/// `CompletionItemKind` of the LSP specification
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompletionKind {
+    Method = 2,
+    Function = 3,
+    Field = 5,
+    Variable = 6,
+    Enum = 13,
+    Keyword = 14,
+    EnumMember = 20,
+    Constant = 21,
+    Struct = 22,
+    TypeParameter = 25,
+}
+
+/// A suggestion for the identifier at the cursor
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completion {
+    pub label: String,
+    pub kind: CompletionKind,
+    /// Type or signature
+    pub detail: Option<String>,
+}
+
+impl Completion {
+    fn new(label: &str, kind: CompletionKind, detail: Option<String>) -> Self {
+        Self {
+            label: label.to_string(),
+            kind,
+            detail,
+        }
+    }
+}
+
+/// What is being completed, found in the program parsed with the
+/// placeholder
+enum Site {
+    /// A field or method of the expression at the span
+    Member(Span),
+    /// The last segment of a path after these segments
+    Path(Vec<String>),
+    /// A name visible at the span
+    Scope(Span),
+}
+
+struct FindSite<'a>(&'a Exprs, Option<Site>);
+
+impl<'a> Visit<'a> for FindSite<'a> {
+    fn exprs(&self) -> &'a Exprs {
+        self.0
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Field(base, name) | ExprKind::MethodCall(base, name, _)
+                if name == PLACEHOLDER =>
+            {
+                self.1 = Some(Site::Member(self.0[*base].span));
+            }
+            ExprKind::Path(path) if path.last().is_some_and(|s| s == PLACEHOLDER) => {
+                self.1 = Some(match path.split_last() {
+                    Some((_, [])) | None => Site::Scope(expr.span),
+                    Some((_, init)) => Site::Path(init.iter().map(|s| s.to_string()).collect()),
+                });
+            }
+            _ => {}
+        }
+        visit::walk_expr(self, expr);
+    }
+}
+
+/// Completions for the identifier ending at byte `offset` of `source`:
+/// fields and methods after `.`, variants and associated functions after
+/// `::`, and otherwise the names in scope; keywords are offered anywhere
+/// else in code
+pub fn completions(source: &str, offset: usize) -> Vec<Completion> {
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+    let before = &source[..offset];
+    let start = before.trim_end_matches(is_ident).len();
+    let end =
+        offset + (source[offset..].len() - source[offset..].trim_start_matches(is_ident).len());
+    let prefix = &source[start..offset];
+    if in_comment_or_string(source, start) {
+        return Vec::new();
+    }
+
+    // The placeholder alone, or ending a statement cut short
+    let site = ["", ";"].iter().find_map(|end_text| {
+        let patched = format!(
+            "{}{}{}{}",
+            &source[..start],
+            PLACEHOLDER,
+            end_text,
+            &source[end..]
+        );
+        let program = crate::compile_until(&patched, Phase::Parse)
+            .ok()?
+            .into_ast()?;
+        let mut find = FindSite(&program.exprs, None);
+        find.visit_program(&program);
+        let site = find.1?;
+        Some((program, site))
+    });
+    let mut completions = match &site {
+        Some((program, Site::Member(span))) => {
+            let (info, probe) = typeck::probe(program, *span);
+            members(program, &info, probe.ty.as_ref())
+        }
+        Some((program, Site::Path(path))) => path_members(program, &path.join("::")),
+        Some((program, Site::Scope(span))) => {
+            let (_, probe) = typeck::probe(program, *span);
+            let mut completions: Vec<Completion> = probe
+                .locals
+                .iter()
+                .map(|(name, ty)| {
+                    Completion::new(name, CompletionKind::Variable, Some(ty.to_string()))
+                })
+                .collect();
+            completions.extend(globals(program));
+            completions.extend(keywords());
+            completions
+        }
+        None if before[..start].trim_end().ends_with(['.', ':']) => Vec::new(),
+        None => keywords().collect(),
+    };
+    completions.retain(|c| c.label.starts_with(prefix) && c.label != PLACEHOLDER);
+    completions
+}
+
+/// Keywords, completed everywhere
+fn keywords() -> impl Iterator<Item = Completion> {
+    KEYWORDS
+        .iter()
+        .map(|keyword| Completion::new(keyword, CompletionKind::Keyword, None))
+}
+
+/// Whether byte `offset` is inside a comment or a string literal
+fn in_comment_or_string(source: &str, offset: usize) -> bool {
+    let Some(before) = offset.checked_sub(1) else {
+        return false;
+    };
+    match cst::parse(source).token_at(before) {
+        Some((at, token)) => match &token.kind {
+            // Line comments run to the end of the line, which they leave out
+            SyntaxKind::Trivia(Trivia::Comment) => {
+                token.text.starts_with("//") || at + token.text.len() > offset
+            }
+            SyntaxKind::Token(TokenKind::String(_)) => at + token.text.len() > offset,
+            // An unterminated string runs to the end of the file
+            SyntaxKind::Token(TokenKind::Error(_)) => token.text.starts_with('"'),
+            _ => false,
+        },
+        None => false,
+    }
+}
+
+/// Fields and methods of values of type `ty`
+fn members(program: &Program, info: &typeck::TypeInfo, ty: Option<&Type>) -> Vec<Completion> {
+    let mut ty = ty;
+    while let Some(Type::Ref(_, inner)) = ty {
+        ty = Some(inner);
+    }
+    let name = match ty {
+        Some(Type::Struct(name) | Type::Enum(name)) => name,
+        Some(Type::Arena) => {
+            return [
+                ("alloc", "fn<T>(value: T) -> T"),
+                ("checkpoint", "fn() -> Checkpoint"),
+                ("reset_to", "fn(checkpoint: Checkpoint)"),
+            ]
+            .into_iter()
+            .map(|(name, detail)| {
+                Completion::new(name, CompletionKind::Method, Some(detail.to_string()))
+            })
+            .collect();
+        }
+        Some(Type::Thread(result)) => {
+            let detail = format!("fn(self) -> {}", result);
+            return vec![Completion::new(
+                "join",
+                CompletionKind::Method,
+                Some(detail),
+            )];
+        }
+        Some(Type::Guard(..)) => {
+            return vec![Completion::new(
+                "unlock",
+                CompletionKind::Method,
+                Some("fn(self)".to_string()),
+            )];
+        }
+        _ => return Vec::new(),
+    };
+    let fields = info.structs.get(name).into_iter().flatten();
+    let mut completions: Vec<Completion> = fields
+        .map(|(field, ty)| Completion::new(field, CompletionKind::Field, Some(ty.to_string())))
+        .collect();
+    for (_, self_ty, function) in program.functions() {
+        if self_ty == Some(name.as_str()) && function.receiver.is_some() {
+            completions.push(Completion::new(
+                &function.name,
+                CompletionKind::Method,
+                Some(signature(&program.exprs, function)),
+            ));
+        }
+    }
+    completions
+}
+
+/// Names reached through `path::`: variants, associated functions and
+/// qualified builtins
+fn path_members(program: &Program, path: &str) -> Vec<Completion> {
+    let mut completions = Vec::new();
+    for item in &program.items {
+        if let Item::Enum(def) = item {
+            if def.name == path {
+                completions.extend(def.variants.iter().map(|variant| {
+                    Completion::new(
+                        &variant.name,
+                        CompletionKind::EnumMember,
+                        Some(def.name.to_string()),
+                    )
+                }));
+            }
+        }
+    }
+    for (name, self_ty, function) in program.functions() {
+        if self_ty == Some(path) {
+            let kind = match function.receiver {
+                Some(_) => CompletionKind::Method,
+                None => CompletionKind::Function,
+            };
+            completions.push(Completion::new(
+                &function.name,
+                kind,
+                Some(signature(&program.exprs, function)),
+            ));
+        } else if let Some(name) = name.strip_prefix(path).and_then(|n| n.strip_prefix("::")) {
+            completions.push(Completion::new(
+                name,
+                CompletionKind::Function,
+                Some(signature(&program.exprs, function)),
+            ));
+        }
+    }
+    for builtin in builtins::FUNCTIONS {
+        if let Some(name) = builtin
+            .strip_prefix(path)
+            .and_then(|n| n.strip_prefix("::"))
+        {
+            completions.push(builtin_completion(name, builtin));
+        }
+    }
+    completions
+}
+
+/// Items of the program and builtins, reached by a plain name
+fn globals(program: &Program) -> Vec<Completion> {
+    let mut completions = Vec::new();
+    for item in &program.items {
+        let completion = match item {
+            Item::Function(function) => Completion::new(
+                &function.name,
+                CompletionKind::Function,
+                Some(signature(&program.exprs, function)),
+            ),
+            Item::Const(item) => Completion::new(
+                &item.name,
+                CompletionKind::Constant,
+                Some(program.exprs.display(&item.ty).to_string()),
+            ),
+            Item::Struct(def) => Completion::new(&def.name, CompletionKind::Struct, None),
+            Item::Enum(def) => Completion::new(&def.name, CompletionKind::Enum, None),
+            Item::TypeAlias(alias) => Completion::new(
+                &alias.name,
+                CompletionKind::TypeParameter,
+                Some(program.exprs.display(&alias.ty).to_string()),
+            ),
+            Item::Extern(block) => {
+                completions.extend(block.functions.iter().map(|function| {
+                    Completion::new(&function.name, CompletionKind::Function, None)
+                }));
+                continue;
+            }
+            Item::Impl(_) => continue,
+        };
+        completions.push(completion);
+    }
+    for builtin in builtins::FUNCTIONS {
+        if !builtin.contains("::") {
+            completions.push(builtin_completion(builtin, builtin));
+        }
+    }
+    for op in builtins::BELIEF_OPS {
+        completions.push(Completion::new(
+            op,
+            CompletionKind::Function,
+            Some("belief operator".to_string()),
+        ));
+    }
+    completions
+}
+
+fn builtin_completion(label: &str, builtin: &str) -> Completion {
+    let detail = builtins::signature(builtin)
+        .map(|(params, ret)| Type::Fn(params, Box::new(ret)).to_string());
+    Completion::new(label, CompletionKind::Function, detail)
+}
+
+/// `fn(name: Type, ...) -> Type`
+fn signature(exprs: &Exprs, function: &Function) -> String {
+    let params: Vec<String> = function
+        .params
+        .iter()
+        .map(|param| format!("{}: {}", param.name, exprs.display(&param.ty)))
+        .collect();
+    match &function.return_type {
+        Some(ty) => format!("fn({}) -> {}", params.join(", "), exprs.display(ty)),
+        None => format!("fn({})", params.join(", ")),
+    }
+}
+
+fn completion_json(completion: &Completion) -> Json {
+    let mut fields = vec![
+        ("label", Json::str(completion.label.as_str())),
+        ("kind", Json::Int(completion.kind as i64)),
+    ];
+    if let Some(detail) = &completion.detail {
+        fields.push(("detail", Json::str(detail.as_str())));
+    }
+    Json::object(fields)
+}
+
+/// A file taking part in a workspace query, as open in the editor or else
+/// as on disk
+#[derive(Debug, Clone)]
+struct File {
+    uri: String,
+    text: String,
+}
+
+/// The files a query about one document must look at, and the programs
+/// they form: each target of its package followed by the library code, as
+/// [`Package::source`] puts them together, or the document alone
+#[derive(Debug, Clone)]
+struct Workspace {
+    files: Vec<File>,
+    /// Indices in `files` of the files of each program, in order
+    units: Vec<Vec<usize>>,
+}
+
+/// A name and the definition it resolves to, each as a file index and byte
+/// offset
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Occurrence {
+    at: (usize, usize),
+    definition: (usize, usize),
+    name: String,
+}
+
+impl Workspace {
+    /// Every name each program resolves, or `None` for programs that do not
+    /// parse
+    fn resolve(&self) -> Vec<Option<Vec<Occurrence>>> {
+        self.units
+            .iter()
+            .map(|unit| self.resolve_unit(unit))
+            .collect()
+    }
+
+    fn resolve_unit(&self, unit: &[usize]) -> Option<Vec<Occurrence>> {
+        let mut text = String::new();
+        let mut starts = Vec::new();
+        for &file in unit {
+            if !text.is_empty() && !text.ends_with('\n') {
+                text.push('\n');
+            }
+            starts.push((text.len(), file));
+            text.push_str(&self.files[file].text);
+        }
+        let program = crate::compile_until(&text, Phase::Parse).ok()?.into_ast()?;
+        let info = typeck::infer(&program);
+
+        let index = LineIndex::new(&text);
+        let tree = cst::parse(&text);
+        let mut tokens = Vec::new();
+        locate(&tree, NodeKind::Root, &mut 0, &mut tokens);
+        let identifiers: Vec<(usize, &str)> = tokens
+            .iter()
+            .filter_map(|located| match &located.token.kind {
+                SyntaxKind::Token(TokenKind::Identifier(name)) => {
+                    Some((located.offset, name.as_str()))
+                }
+                _ => None,
+            })
+            .collect();
+        // Spans point at the name or at the syntax leading to it
+        let find = |span: Span, name: &str| {
+            let anchor = index.offset(span);
+            let first = identifiers.partition_point(|&(offset, _)| offset < anchor);
+            let &(offset, _) = identifiers[first..].iter().find(|(_, n)| *n == name)?;
+            let &(start, file) = starts.iter().rev().find(|(start, _)| *start <= offset)?;
+            Some((file, offset - start))
+        };
+        let occurrences = info.references.iter().filter_map(|reference| {
+            Some(Occurrence {
+                at: find(reference.span, &reference.name)?,
+                definition: find(reference.definition, &reference.name)?,
+                name: reference.name.clone(),
+            })
+        });
+        Some(occurrences.collect())
+    }
+}
+
+/// The name at `offset` of `file` and every occurrence of what it refers
+/// to, across the programs that include the file
+fn occurrences_of(
+    resolved: &[Option<Vec<Occurrence>>],
+    file: usize,
+    offset: usize,
+) -> Vec<Occurrence> {
+    let all: BTreeSet<&Occurrence> = resolved.iter().flatten().flatten().collect();
+    let Some(target) = all
+        .iter()
+        .find(|o| o.at.0 == file && o.at.1 <= offset && offset <= o.at.1 + o.name.len())
+    else {
+        return Vec::new();
+    };
+    let mut found: Vec<Occurrence> = all
+        .iter()
+        .filter(|o| o.definition == target.definition && o.name == target.name)
+        .map(|&o| o.clone())
+        .collect();
+    found.dedup_by_key(|o| o.at);
+    found
+}
+
+/// Whether `name` can name a local or an item
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+        && Token::is_keyword(name).is_none()
+        && name != "self"
+}
+
+/// Edits renaming every occurrence of the symbol at `offset` of `file` to
+/// `new_name`, by file; refused if that would make any name in the
+/// workspace refer to something else or break its syntax
+fn rename(
+    workspace: &Workspace,
+    file: usize,
+    offset: usize,
+    new_name: &str,
+) -> Result<BTreeMap<usize, Vec<(usize, usize)>>, String> {
+    if !is_identifier(new_name) {
+        return Err(format!("`{}` is not a valid name", new_name));
+    }
+    let before = workspace.resolve();
+    let occurrences = occurrences_of(&before, file, offset);
+    let Some(old_name) = occurrences.first().map(|o| o.name.clone()) else {
+        return Err("there is no symbol to rename here".to_string());
+    };
+    let mut edits: BTreeMap<usize, Vec<(usize, usize)>> = BTreeMap::new();
+    for occurrence in &occurrences {
+        let (file, start) = occurrence.at;
+        edits
+            .entry(file)
+            .or_default()
+            .push((start, start + old_name.len()));
+    }
+
+    let mut renamed = workspace.clone();
+    for (&file, ranges) in &edits {
+        let text = &mut renamed.files[file].text;
+        for &(start, end) in ranges.iter().rev() {
+            text.replace_range(start..end, new_name);
+        }
+    }
+    // Where an offset of the original text ends up after the edits
+    let growth = new_name.len() as isize - old_name.len() as isize;
+    let shift = |(file, offset): (usize, usize)| {
+        let before = edits
+            .get(&file)
+            .map_or(0, |ranges| ranges.iter().filter(|r| r.0 < offset).count());
+        (file, offset.saturating_add_signed(before as isize * growth))
+    };
+    let refusal = |(file, offset): (usize, usize)| {
+        let (line, _) = LineIndex::new(&renamed.files[file].text).position(offset);
+        let uri = &renamed.files[file].uri;
+        format!(
+            "renaming `{}` to `{}` would change what the name on line {} of {} refers to",
+            old_name,
+            new_name,
+            line + 1,
+            uri.rsplit('/').next().unwrap_or(uri)
+        )
+    };
+    for (unit, (before, after)) in before.iter().zip(renamed.resolve()).enumerate() {
+        let Some(before) = before else {
+            continue;
+        };
+        let Some(after) = after else {
+            let file = renamed.units[unit][0];
+            return Err(format!(
+                "renaming `{}` to `{}` would break the syntax of {}",
+                old_name, new_name, renamed.files[file].uri
+            ));
+        };
+        let expected: BTreeSet<_> = before
+            .iter()
+            .map(|o| (shift(o.at), shift(o.definition)))
+            .collect();
+        let actual: BTreeSet<_> = after.iter().map(|o| (o.at, o.definition)).collect();
+        if let Some(&(at, _)) = actual.symmetric_difference(&expected).next() {
+            return Err(refusal(at));
+        }
+    }
+    Ok(edits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_semantic_tokens() {
+        let source = "struct P { x: f64 }\n\
+                      fn guess(p: P, a: Arena) -> Belief<bool> {\n\
+                      \x20   let b = ~Bernoulli(p.x);\n\
+                      \x20   arena tmp { b }\n\
+                      }\n";
+        let tokens: Vec<(u32, u32, TokenType, u32)> = semantic_tokens(source)
+            .iter()
+            .map(|t| (t.line, t.start, t.ty, t.modifiers))
+            .collect();
+        let at = |line, start| {
+            tokens
+                .iter()
+                .find(|t| (t.0, t.1) == (line, start))
+                .map(|t| (t.2, t.3))
+        };
+        assert_eq!(at(0, 7), Some((TokenType::Struct, DECLARATION)));
+        assert_eq!(at(0, 11), Some((TokenType::Property, DECLARATION)));
+        assert_eq!(at(0, 14), Some((TokenType::Type, DEFAULT_LIBRARY)));
+        assert_eq!(at(1, 3), Some((TokenType::Function, DECLARATION)));
+        assert_eq!(at(1, 9), Some((TokenType::Parameter, DECLARATION)));
+        assert_eq!(at(1, 12), Some((TokenType::Struct, 0)));
+        assert_eq!(at(1, 15), Some((TokenType::Parameter, DECLARATION | ARENA)));
+        assert_eq!(at(1, 18), Some((TokenType::Type, DEFAULT_LIBRARY | ARENA)));
+        assert_eq!(at(1, 28), Some((TokenType::Type, DEFAULT_LIBRARY | BELIEF)));
+        assert_eq!(at(2, 8), Some((TokenType::Variable, DECLARATION | BELIEF)));
+        assert_eq!(
+            at(2, 13),
+            Some((TokenType::Function, DEFAULT_LIBRARY | BELIEF))
+        );
+        assert_eq!(at(2, 23), Some((TokenType::Parameter, 0)));
+        assert_eq!(at(2, 25), Some((TokenType::Property, 0)));
+        assert_eq!(at(3, 10), Some((TokenType::Variable, DECLARATION | ARENA)));
+        assert_eq!(at(3, 16), Some((TokenType::Variable, BELIEF)));
+
+        assert_eq!(
+            encode(&semantic_tokens("// a\nlet")).to_string(),
+            "[0,0,4,13,0,1,0,3,0,0]"
+        );
+    }
+
+    #[test]
+    fn test_completions() {
+        let source = "struct P { x: f64, y: f64 }\n\
+                      enum Mood { Calm, Tense }\n\
+                      impl P {\n\
+                      \x20   fn norm(&self) -> f64 { self.x }\n\
+                      \x20   fn origin() -> P { P { x: 0.0, y: 0.0 } }\n\
+                      }\n\
+                      fn main() {\n\
+                      \x20   let p = P::origin();\n\
+                      \x20   let prior = ~Normal(0.0, 1.0); // p\n\
+                      \x20   CURSOR\n\
+                      }\n";
+        let complete = |text: &str| {
+            let source = source.replace("CURSOR", text);
+            completions(&source, source.rfind(text).unwrap() + text.len())
+        };
+        let labels =
+            |text: &str| -> Vec<String> { complete(text).into_iter().map(|c| c.label).collect() };
+        assert_eq!(labels("p."), ["x", "y", "norm"]);
+        assert_eq!(labels("p.n"), ["norm"]);
+        assert_eq!(labels("Mood::"), ["Calm", "Tense"]);
+        assert_eq!(labels("P::o"), ["origin"]);
+        assert_eq!(labels("env::"), ["args_len", "arg"]);
+        assert_eq!(
+            complete("pr"),
+            [
+                Completion::new(
+                    "prior",
+                    CompletionKind::Variable,
+                    Some("Belief<float>".into())
+                ),
+                Completion::new(
+                    "print",
+                    CompletionKind::Function,
+                    Some("fn({unknown}) -> ()".into())
+                ),
+            ]
+        );
+        assert_eq!(labels("wh"), ["while", "where"]);
+        // Inside a comment
+        let offset = source.find("// p").unwrap() + 4;
+        assert!(completions(source, offset).is_empty());
+
+        let request = Json::parse(r#"{"jsonrpc":"2.0","id":7,"method":"textDocument/completion","params":{"textDocument":{"uri":"a"},"position":{"line":0,"character":1}}}"#).unwrap();
+        let cancel =
+            Json::parse(r#"{"jsonrpc":"2.0","method":"$/cancelRequest","params":{"id":7}}"#)
+                .unwrap();
+        let change = Json::parse(r#"{"jsonrpc":"2.0","method":"textDocument/didChange","params":{"textDocument":{"uri":"a","version":2},"contentChanges":[{"text":"x"}]}}"#).unwrap();
+        assert!(cancelled(&request, [&cancel].into_iter()));
+        assert!(cancelled(&request, [&change].into_iter()));
+        assert!(!cancelled(&request, std::iter::empty()));
+        assert!(superseded(&change, [&change].into_iter()));
+        assert!(!superseded(&change, [&request].into_iter()));
+        assert!(!superseded(&request, [&change].into_iter()));
+    }
+
+    #[test]
+    fn test_server() {
+        let mut server = Server::new(false);
+        let mut send = |message: &str| {
+            let replies = server.handle(&Json::parse(message).unwrap());
+            replies.iter().map(Json::to_string).collect::<Vec<_>>()
+        };
+        let open = r#"{"jsonrpc":"2.0","method":"textDocument/didOpen","params":{"textDocument":{"uri":"file:///a.solo","languageId":"solo","version":1,"text":"// Points\n// in space\nstruct P {\n    x: i64,\n    y: i64,\n}\n\nfn main() {\n    print(z);\n}\n"}}}"#;
+        assert_eq!(
+            send(open),
+            [
+                r#"{"jsonrpc":"2.0","method":"textDocument/publishDiagnostics","params":{"uri":"file:///a.solo","diagnostics":[{"range":{"start":{"line":8,"character":10},"end":{"line":8,"character":11}},"severity":1,"source":"solo","code":"type","message":"cannot find value `z` in this scope"}]}}"#
+            ]
+        );
+
+        let symbols = send(
+            r#"{"jsonrpc":"2.0","id":1,"method":"textDocument/documentSymbol","params":{"textDocument":{"uri":"file:///a.solo"}}}"#,
+        );
+        assert!(symbols[0].starts_with(r#"{"jsonrpc":"2.0","id":1,"result":[{"name":"P","kind":23,"range":{"start":{"line":0,"character":0},"end":{"line":5,"character":1}},"selectionRange":{"start":{"line":2,"character":7},"end":{"line":2,"character":8}},"children":[{"name":"x","kind":8,"range":{"start":{"line":3,"character":4},"end":{"line":3,"character":10}}"#));
+        assert!(symbols[0].contains(r#"{"name":"main","kind":12,"#));
+
+        assert_eq!(
+            send(
+                r#"{"jsonrpc":"2.0","id":2,"method":"textDocument/foldingRange","params":{"textDocument":{"uri":"file:///a.solo"}}}"#
+            ),
+            [
+                r#"{"jsonrpc":"2.0","id":2,"result":[{"startLine":0,"endLine":1,"kind":"comment"},{"startLine":2,"endLine":4},{"startLine":7,"endLine":8}]}"#
+            ]
+        );
+
+        // `z` becomes `1`, then `1 + 1`
+        let change = r#"{"jsonrpc":"2.0","method":"textDocument/didChange","params":{"textDocument":{"uri":"file:///a.solo","version":2},"contentChanges":[{"range":{"start":{"line":8,"character":10},"end":{"line":8,"character":11}},"text":"1"},{"range":{"start":{"line":8,"character":11},"end":{"line":8,"character":11}},"text":" + 1"}]}}"#;
+        assert_eq!(
+            send(change),
+            [
+                r#"{"jsonrpc":"2.0","method":"textDocument/publishDiagnostics","params":{"uri":"file:///a.solo","diagnostics":[]}}"#
+            ]
+        );
+        let document = &server.documents["file:///a.solo"];
+        assert!(document.text.contains("    print(1 + 1);\n"));
+        assert_eq!(document.tree, cst::parse(&document.text));
+        let mut send = |message: &str| {
+            let replies = server.handle(&Json::parse(message).unwrap());
+            replies.iter().map(Json::to_string).collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            send(r#"{"jsonrpc":"2.0","id":3,"method":"shutdown"}"#),
+            [r#"{"jsonrpc":"2.0","id":3,"result":null}"#]
+        );
+        assert!(send(r#"{"jsonrpc":"2.0","method":"exit"}"#).is_empty());
+        assert!(server.exited);
+    }
+    #[test]
+    fn test_references_and_rename() {
+        let root = std::env::temp_dir().join(format!("solo-lsp-{}", std::process::id()));
+        let write = |file: &str, text: &str| {
+            let path = root.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, text).unwrap();
+        };
+        write(crate::package::MANIFEST, "[package]\nname = \"demo\"\n");
+        write("src/lib.solo", "fn helper(n: i64) -> i64 {\n    n\n}\n");
+        write("src/main.solo", "fn main() {}\n");
+        write(
+            "src/bin/other.solo",
+            "fn main() {\n    print(helper(2));\n}\n",
+        );
+
+        let mut server = Server::new(false);
+        let uri = format!("file://{}", root.join("src/main.solo").display());
+        let text = "fn main() {\n    let count = 1;\n    print(helper(count));\n}\n";
+        server.handle(&Json::object([
+            ("method", Json::str("textDocument/didOpen")),
+            (
+                "params",
+                Json::object([(
+                    "textDocument",
+                    Json::object([("uri", Json::str(uri.as_str())), ("text", Json::str(text))]),
+                )]),
+            ),
+        ]));
+        let mut request = |method: &str, character: i64, extra: (&str, Json)| {
+            let params = Json::object([
+                (
+                    "textDocument",
+                    Json::object([("uri", Json::str(uri.as_str()))]),
+                ),
+                (
+                    "position",
+                    Json::object([("line", Json::Int(2)), ("character", Json::Int(character))]),
+                ),
+                extra,
+            ]);
+            let message = Json::object([
+                ("id", Json::Int(1)),
+                ("method", Json::str(method)),
+                ("params", params),
+            ]);
+            server.handle(&message).remove(0)
+        };
+
+        // Every target of the package calls into the library
+        let context = Json::object([("includeDeclaration", Json::Bool(true))]);
+        let references = request("textDocument/references", 12, ("context", context));
+        let Some(Json::Array(locations)) = references.get("result") else {
+            panic!("{}", references);
+        };
+        let mut files: Vec<&str> = locations
+            .iter()
+            .filter_map(|l| l.get("uri")?.as_str()?.rsplit('/').next())
+            .collect();
+        files.sort();
+        assert_eq!(files, ["lib.solo", "main.solo", "other.solo"]);
+
+        let renamed = request("textDocument/rename", 12, ("newName", Json::str("assist")));
+        let Some(Json::Object(changes)) = renamed.get("result").and_then(|r| r.get("changes"))
+        else {
+            panic!("{}", renamed);
+        };
+        assert_eq!(changes.len(), 3);
+
+        // The local would shadow the function it is passed to
+        let refused = request("textDocument/rename", 17, ("newName", Json::str("helper")));
+        assert_eq!(
+            refused.get("error").and_then(|e| e.get("message")),
+            Some(&Json::str(
+                "renaming `count` to `helper` would change what the name on line 3 of main.solo refers to"
+            ))
+        );
+        let refused = request("textDocument/rename", 17, ("newName", Json::str("fn")));
+        assert_eq!(
+            refused.get("error").and_then(|e| e.get("code")),
+            Some(&Json::Int(REQUEST_FAILED))
+        );
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,160 @@
+//! Link-time optimization
+//!
+//! A package target is compiled with the package library appended (see
+//! [`package`](crate::package)), and each would otherwise be a codegen unit
+//! of its own, optimized without seeing the other. `solo build --lto`
+//! optimizes them as one program: constants of either unit are propagated
+//! into the other, and whatever no entry point can reach is stripped, in
+//! the library as in the target.
+//!
+//! Unlike `--closed-world` (see [`closed_world`]), which implies it, LTO
+//! keeps every entry point a linker can see: `main` for an executable, and
+//! each `pub extern "C"` function, which C code or the dynamic loader may
+//! look up. It therefore also applies to libraries.
+//!
+//! The [`Report`] measures the program in functions and expressions before
+//! and after, the units code generation works in.
+
+use std::fmt;
+
+use crate::ast::{Expr, Exprs, Function, Item, Program};
+use crate::closed_world;
+use crate::link::CrateType;
+use crate::typeck::TypeInfo;
+use crate::visit::{self, Visit};
+
+/// How much code a program holds
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Size {
+    /// Functions and methods
+    pub functions: usize,
+    pub expressions: usize,
+}
+
+impl Size {
+    pub fn of(program: &Program) -> Self {
+        let mut counter = Counter(&program.exprs, Size::default());
+        counter.visit_program(program);
+        counter.1
+    }
+}
+
+/// Counts the functions and expressions it visits
+struct Counter<'a>(&'a Exprs, Size);
+
+impl<'a> Visit<'a> for Counter<'a> {
+    fn exprs(&self) -> &'a Exprs {
+        self.0
+    }
+
+    fn visit_function(&mut self, function: &Function) {
+        self.1.functions += 1;
+        visit::walk_function(self, function);
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        self.1.expressions += 1;
+        visit::walk_expr(self, expr);
+    }
+}
+
+impl fmt::Display for Size {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} function(s), {} expression(s)",
+            self.functions, self.expressions
+        )
+    }
+}
+
+/// What link-time optimization did to a program
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+    /// Items stripped and constant uses propagated
+    pub optimized: closed_world::Report,
+    pub before: Size,
+    pub after: Size,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.optimized)?;
+        let change = |before: usize, after: usize| {
+            if before == 0 {
+                0.0
+            } else {
+                (after as f64 - before as f64) * 100.0 / before as f64
+            }
+        };
+        writeln!(
+            f,
+            "lto: {} -> {} ({:+.1}% expressions)",
+            self.before,
+            self.after,
+            change(self.before.expressions, self.after.expressions)
+        )
+    }
+}
+
+/// Optimize a checked program built as `crate_type` as a whole
+pub fn optimize(program: &mut Program, info: &TypeInfo, crate_type: CrateType) -> Report {
+    let before = Size::of(program);
+    let main = (crate_type == CrateType::Bin).then(|| "main".to_string());
+    let exported = program.items.iter().filter_map(|item| match item {
+        Item::Function(function) if function.exported => Some(function.name.to_string()),
+        _ => None,
+    });
+    let roots: Vec<String> = main.into_iter().chain(exported).collect();
+    let optimized = closed_world::optimize_from(program, info, &roots);
+    Report {
+        optimized,
+        before,
+        after: Size::of(program),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_optimize() {
+        let source = "#![feature(extern_c)]
+             const SCALE: i64 = 3;
+             fn main() {
+                 print(triple(2));
+             }
+             #![library]
+             pub fn triple(x: i64) -> i64 { x * SCALE }
+             pub fn unused(x: i64) -> i64 { x + 1 }
+             pub extern \"C\" fn exported() -> i64 { SCALE }";
+        let (mut program, info) = crate::analyze(source).unwrap();
+        let report = optimize(&mut program, &info, CrateType::Bin);
+        let removed: Vec<&str> = report
+            .optimized
+            .removed
+            .iter()
+            .map(|r| r.name.as_str())
+            .collect();
+        // Constants are propagated across units; exports stay
+        assert_eq!(removed, ["SCALE", "unused"]);
+        assert_eq!(report.optimized.propagated, 2);
+        assert_eq!(
+            report.before,
+            Size {
+                functions: 4,
+                expressions: 13
+            }
+        );
+        assert_eq!(report.after.functions, 3);
+        assert!(report
+            .to_string()
+            .ends_with("lto: 4 function(s), 13 expression(s) -> 3 function(s), 9 expression(s) (-30.8% expressions)\n"));
+
+        // A library has no `main`; only its exports are entry points
+        let (mut program, info) = crate::analyze(source).unwrap();
+        let report = optimize(&mut program, &info, CrateType::StaticLib);
+        assert_eq!(report.after.functions, 1);
+    }
+}
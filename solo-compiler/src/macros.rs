@@ -0,0 +1,837 @@
+//! Declarative macros for Solo dialect (macro by example)
+//!
+//! Macros are expanded on the token stream before parsing, so a macro can
+//! produce any syntax the rest of the pipeline accepts.
+//!
+//! ```text
+//! macro square {
+//!     ($x:expr) => { $x * $x };
+//! }
+//!
+//! let nine = square!(3);
+//! ```
+//!
+//! Fragment specifiers: `expr`, `ident`, `literal`, `tt`.
+//! Repetitions: `$( ... ) sep *`, `$( ... ) sep +`, `$( ... ) ?`.
+//!
+//! Identifiers bound with `let` inside a macro body are renamed on every
+//! expansion (hygiene), so they never capture or shadow names at the call site.
+
+use std::collections::HashMap;
+
+use crate::ast::Span;
+use crate::error::{CompileError, Diagnostic};
+use crate::symbol::Symbol;
+use crate::token::{Token, TokenKind};
+
+/// Maximum nesting depth of macro expansions before giving up
+pub const RECURSION_LIMIT: usize = 64;
+
+/// Maximum number of tokens all expansions of a program may produce, which
+/// stops macros that grow their input on every step long before the
+/// recursion limit would
+pub const EXPANSION_LIMIT: usize = 1 << 18;
+
+/// Macros built into the compiler, whose invocations are left for the
+/// parser unless a macro of the same name is defined
+pub const BUILTIN_MACROS: &[&str] = &["asm"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Fragment {
+    Expr,
+    Ident,
+    Literal,
+    Tt,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RepeatKind {
+    ZeroOrMore,
+    OneOrMore,
+    ZeroOrOne,
+}
+
+#[derive(Debug, Clone)]
+enum Matcher {
+    Token(TokenKind),
+    Var(Symbol, Fragment),
+    Repeat(Vec<Matcher>, Option<TokenKind>, RepeatKind),
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+    pattern: Vec<Matcher>,
+    body: Vec<Token>,
+}
+
+#[derive(Debug, Clone)]
+struct MacroDef {
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug, Clone)]
+enum Binding {
+    Single(Vec<Token>, Fragment),
+    Repeated(Vec<Bindings>),
+}
+
+type Bindings = HashMap<Symbol, Binding>;
+
+/// Expand all macro definitions and invocations in a token stream
+///
+/// Definitions are removed from the output; invocations are replaced by
+/// their (recursively expanded) transcriptions.
+pub fn expand(tokens: Vec<Token>) -> Result<Vec<Token>, CompileError> {
+    // Most programs neither define nor invoke macros
+    let macros =
+        (0..tokens.len()).any(|i| tokens[i].kind == TokenKind::Macro || is_invocation(&tokens, i));
+    if !macros {
+        return Ok(tokens);
+    }
+    let mut expander = Expander {
+        macros: HashMap::new(),
+        hygiene_counter: 0,
+        produced: 0,
+    };
+    expander
+        .collect_definitions(tokens)
+        .and_then(|tokens| expander.expand_tokens(tokens, 0))
+        .map_err(|err| CompileError::Parse(vec![err]))
+}
+
+struct Expander {
+    macros: HashMap<Symbol, MacroDef>,
+    hygiene_counter: usize,
+    /// Tokens transcribed so far, bounded by [`EXPANSION_LIMIT`]
+    produced: usize,
+}
+
+impl Expander {
+    fn collect_definitions(&mut self, tokens: Vec<Token>) -> Result<Vec<Token>, Diagnostic> {
+        let mut output = Vec::new();
+        let mut i = 0;
+
+        while i < tokens.len() {
+            if tokens[i].kind != TokenKind::Macro {
+                output.push(tokens[i].clone());
+                i += 1;
+                continue;
+            }
+
+            let name = match tokens.get(i + 1).map(|t| &t.kind) {
+                Some(TokenKind::Identifier(name)) => *name,
+                _ => return Err(error_at(&tokens[i], "expected macro name after `macro`")),
+            };
+            let open = i + 2;
+            if tokens.get(open).map(|t| &t.kind) != Some(&TokenKind::LBrace) {
+                return Err(error_at(&tokens[i], "expected `{` after macro name"));
+            }
+            let close = group_end(&tokens, open)
+                .ok_or_else(|| error_at(&tokens[open], "unclosed macro definition"))?;
+
+            let rules = parse_rules(&tokens[open + 1..close], &tokens[i])?;
+            if self.macros.insert(name, MacroDef { rules }).is_some() {
+                return Err(error_at(
+                    &tokens[i],
+                    &format!("macro `{}` is defined multiple times", name),
+                ));
+            }
+            i = close + 1;
+        }
+
+        Ok(output)
+    }
+
+    fn expand_tokens(
+        &mut self,
+        tokens: Vec<Token>,
+        depth: usize,
+    ) -> Result<Vec<Token>, Diagnostic> {
+        let mut output = Vec::new();
+        let mut i = 0;
+
+        while i < tokens.len() {
+            if !is_invocation(&tokens, i) {
+                output.push(tokens[i].clone());
+                i += 1;
+                continue;
+            }
+
+            let call_site = &tokens[i];
+            let name = match &call_site.kind {
+                TokenKind::Identifier(name) => *name,
+                _ => unreachable!(),
+            };
+            if depth >= RECURSION_LIMIT {
+                return Err(error_at(
+                    call_site,
+                    &format!(
+                        "recursion limit ({}) reached while expanding `{}!`",
+                        RECURSION_LIMIT, name
+                    ),
+                ));
+            }
+
+            let open = i + 2;
+            let close = group_end(&tokens, open)
+                .ok_or_else(|| error_at(&tokens[open], "unclosed macro invocation"))?;
+            let args = &tokens[open + 1..close];
+
+            if BUILTIN_MACROS.contains(&name.as_str()) && !self.macros.contains_key(&name) {
+                output.extend_from_slice(&tokens[i..=open]);
+                output.extend(self.expand_tokens(args.to_vec(), depth + 1)?);
+                output.push(tokens[close].clone());
+                i = close + 1;
+                continue;
+            }
+
+            let transcribed = self.invoke(name, args, call_site)?;
+            output.extend(self.expand_tokens(transcribed, depth + 1)?);
+            i = close + 1;
+        }
+
+        Ok(output)
+    }
+
+    fn invoke(
+        &mut self,
+        name: Symbol,
+        args: &[Token],
+        call_site: &Token,
+    ) -> Result<Vec<Token>, Diagnostic> {
+        let def = self
+            .macros
+            .get(&name)
+            .cloned()
+            .ok_or_else(|| error_at(call_site, &format!("cannot find macro `{}!`", name)))?;
+
+        for rule in &def.rules {
+            let mut bindings = Bindings::new();
+            if let Some(consumed) = match_seq(&rule.pattern, args, &mut bindings) {
+                if consumed == args.len() {
+                    self.hygiene_counter += 1;
+                    let body = hygienic_body(&rule.body, self.hygiene_counter);
+                    let mut output = Vec::new();
+                    transcribe(&body, &bindings, call_site, &mut output)?;
+                    self.produced += output.len();
+                    if self.produced > EXPANSION_LIMIT {
+                        return Err(error_at(
+                            call_site,
+                            &format!(
+                                "expansion limit ({} tokens) reached while expanding `{}!`",
+                                EXPANSION_LIMIT, name
+                            ),
+                        ));
+                    }
+                    return Ok(output);
+                }
+            }
+        }
+
+        Err(error_at(
+            call_site,
+            &format!("no rules of macro `{}!` matched this invocation", name),
+        ))
+    }
+}
+
+fn parse_rules(tokens: &[Token], def_site: &Token) -> Result<Vec<Rule>, Diagnostic> {
+    let mut rules = Vec::new();
+    let mut i = 0;
+
+    while i < tokens.len() {
+        if !is_open(&tokens[i].kind) {
+            return Err(error_at(&tokens[i], "expected macro rule pattern"));
+        }
+        let pattern_end =
+            group_end(tokens, i).ok_or_else(|| error_at(&tokens[i], "unclosed macro pattern"))?;
+        let pattern = parse_pattern(&tokens[i + 1..pattern_end])?;
+
+        let arrow = pattern_end + 1;
+        if tokens.get(arrow).map(|t| &t.kind) != Some(&TokenKind::FatArrow) {
+            return Err(error_at(
+                &tokens[pattern_end],
+                "expected `=>` after macro pattern",
+            ));
+        }
+
+        let body_start = arrow + 1;
+        if !tokens.get(body_start).is_some_and(|t| is_open(&t.kind)) {
+            return Err(error_at(&tokens[arrow], "expected macro body after `=>`"));
+        }
+        let body_end = group_end(tokens, body_start)
+            .ok_or_else(|| error_at(&tokens[body_start], "unclosed macro body"))?;
+
+        rules.push(Rule {
+            pattern,
+            body: tokens[body_start + 1..body_end].to_vec(),
+        });
+
+        i = body_end + 1;
+        if tokens.get(i).map(|t| &t.kind) == Some(&TokenKind::Semicolon) {
+            i += 1;
+        }
+    }
+
+    if rules.is_empty() {
+        return Err(error_at(def_site, "macro must have at least one rule"));
+    }
+    Ok(rules)
+}
+
+fn parse_pattern(tokens: &[Token]) -> Result<Vec<Matcher>, Diagnostic> {
+    let mut matchers = Vec::new();
+    let mut i = 0;
+
+    while i < tokens.len() {
+        if tokens[i].kind != TokenKind::Dollar {
+            matchers.push(Matcher::Token(tokens[i].kind.clone()));
+            i += 1;
+            continue;
+        }
+
+        match tokens.get(i + 1).map(|t| &t.kind) {
+            Some(TokenKind::Identifier(name)) => {
+                if tokens.get(i + 2).map(|t| &t.kind) != Some(&TokenKind::Colon) {
+                    return Err(error_at(
+                        &tokens[i],
+                        &format!("missing fragment specifier for `${}`", name),
+                    ));
+                }
+                let fragment = match tokens.get(i + 3).map(|t| &t.kind) {
+                    Some(TokenKind::Identifier(frag)) => match frag.as_str() {
+                        "expr" => Fragment::Expr,
+                        "ident" => Fragment::Ident,
+                        "literal" => Fragment::Literal,
+                        "tt" => Fragment::Tt,
+                        other => {
+                            return Err(error_at(
+                                &tokens[i],
+                                &format!("invalid fragment specifier `{}`", other),
+                            ))
+                        }
+                    },
+                    _ => return Err(error_at(&tokens[i], "expected fragment specifier")),
+                };
+                matchers.push(Matcher::Var(*name, fragment));
+                i += 4;
+            }
+            Some(TokenKind::LParen) => {
+                let close = group_end(tokens, i + 1)
+                    .ok_or_else(|| error_at(&tokens[i], "unclosed repetition in macro pattern"))?;
+                let inner = parse_pattern(&tokens[i + 2..close])?;
+                let (separator, kind, next) =
+                    parse_repeat_suffix(tokens, close + 1).ok_or_else(|| {
+                        error_at(&tokens[i], "expected `*`, `+` or `?` after repetition")
+                    })?;
+                matchers.push(Matcher::Repeat(inner, separator.map(|t| t.kind), kind));
+                i = next;
+            }
+            _ => {
+                return Err(error_at(
+                    &tokens[i],
+                    "expected metavariable or repetition after `$`",
+                ))
+            }
+        }
+    }
+
+    Ok(matchers)
+}
+
+/// Parse `sep? op` following a repetition group, returning the position after it
+fn parse_repeat_suffix(tokens: &[Token], at: usize) -> Option<(Option<Token>, RepeatKind, usize)> {
+    let op = |kind: &TokenKind| match kind {
+        TokenKind::Star => Some(RepeatKind::ZeroOrMore),
+        TokenKind::Plus => Some(RepeatKind::OneOrMore),
+        TokenKind::Question => Some(RepeatKind::ZeroOrOne),
+        _ => None,
+    };
+
+    let first = tokens.get(at)?;
+    if let Some(kind) = op(&first.kind) {
+        return Some((None, kind, at + 1));
+    }
+    let second = tokens.get(at + 1)?;
+    op(&second.kind).map(|kind| (Some(first.clone()), kind, at + 2))
+}
+
+/// Match a matcher sequence against a prefix of `tokens`, returning tokens consumed
+fn match_seq(pattern: &[Matcher], tokens: &[Token], bindings: &mut Bindings) -> Option<usize> {
+    let mut pos = 0;
+
+    for (idx, matcher) in pattern.iter().enumerate() {
+        match matcher {
+            Matcher::Token(kind) => {
+                if tokens.get(pos).map(|t| &t.kind) != Some(kind) {
+                    return None;
+                }
+                pos += 1;
+            }
+            Matcher::Var(name, fragment) => {
+                let len = match_fragment(*fragment, &tokens[pos..], follow_token(pattern, idx))?;
+                bindings.insert(
+                    *name,
+                    Binding::Single(tokens[pos..pos + len].to_vec(), *fragment),
+                );
+                pos += len;
+            }
+            Matcher::Repeat(inner, separator, kind) => {
+                let mut iterations = Vec::new();
+                loop {
+                    let mut start = pos;
+                    if !iterations.is_empty() {
+                        if let Some(sep) = separator {
+                            if tokens.get(pos).map(|t| &t.kind) != Some(sep) {
+                                break;
+                            }
+                            start += 1;
+                        }
+                    }
+                    let mut iteration = Bindings::new();
+                    match match_seq(inner, &tokens[start..], &mut iteration) {
+                        Some(len) if len > 0 || inner.is_empty() => {
+                            iterations.push(iteration);
+                            pos = start + len;
+                        }
+                        _ => break,
+                    }
+                    if *kind == RepeatKind::ZeroOrOne || inner.is_empty() {
+                        break;
+                    }
+                }
+                if *kind == RepeatKind::OneOrMore && iterations.is_empty() {
+                    return None;
+                }
+                for name in pattern_vars(inner) {
+                    bindings.insert(name, Binding::Repeated(iterations.clone()));
+                }
+            }
+        }
+    }
+
+    Some(pos)
+}
+
+/// The literal token that terminates an `expr` fragment, if the pattern has one
+fn follow_token(pattern: &[Matcher], idx: usize) -> Option<&TokenKind> {
+    match pattern.get(idx + 1) {
+        Some(Matcher::Token(kind)) => Some(kind),
+        _ => None,
+    }
+}
+
+fn match_fragment(
+    fragment: Fragment,
+    tokens: &[Token],
+    follow: Option<&TokenKind>,
+) -> Option<usize> {
+    let first = tokens.first()?;
+    match fragment {
+        Fragment::Ident => matches!(first.kind, TokenKind::Identifier(_)).then_some(1),
+        Fragment::Literal => matches!(
+            first.kind,
+            TokenKind::Integer(..)
+                | TokenKind::Float(..)
+                | TokenKind::String(_)
+                | TokenKind::True
+                | TokenKind::False
+        )
+        .then_some(1),
+        Fragment::Tt => token_tree_len(tokens, 0),
+        Fragment::Expr => {
+            let mut pos = 0;
+            while pos < tokens.len() {
+                let kind = &tokens[pos].kind;
+                if Some(kind) == follow
+                    || matches!(
+                        kind,
+                        TokenKind::Comma | TokenKind::Semicolon | TokenKind::FatArrow
+                    )
+                {
+                    break;
+                }
+                pos += token_tree_len(tokens, pos)?;
+            }
+            (pos > 0).then_some(pos)
+        }
+    }
+}
+
+fn pattern_vars(pattern: &[Matcher]) -> Vec<Symbol> {
+    let mut names = Vec::new();
+    for matcher in pattern {
+        match matcher {
+            Matcher::Token(_) => {}
+            Matcher::Var(name, _) => names.push(*name),
+            Matcher::Repeat(inner, _, _) => names.extend(pattern_vars(inner)),
+        }
+    }
+    names
+}
+
+/// Rename identifiers introduced by `let` in a macro body
+///
+/// The `#` suffix cannot appear in user identifiers, so renamed bindings
+/// never collide with names at the call site.
+fn hygienic_body(body: &[Token], counter: usize) -> Vec<Token> {
+    let mut introduced = Vec::new();
+    for (i, token) in body.iter().enumerate() {
+        if token.kind != TokenKind::Let {
+            continue;
+        }
+        let mut j = i + 1;
+        if body.get(j).map(|t| &t.kind) == Some(&TokenKind::Mut) {
+            j += 1;
+        }
+        let preceded_by_dollar =
+            j > 0 && body.get(j - 1).map(|t| &t.kind) == Some(&TokenKind::Dollar);
+        if let Some(TokenKind::Identifier(name)) = body.get(j).map(|t| &t.kind) {
+            if !preceded_by_dollar {
+                introduced.push(*name);
+            }
+        }
+    }
+
+    let mut output = Vec::with_capacity(body.len());
+    for (i, token) in body.iter().enumerate() {
+        let after_dollar = i > 0 && body[i - 1].kind == TokenKind::Dollar;
+        match &token.kind {
+            TokenKind::Identifier(name) if !after_dollar && introduced.contains(name) => {
+                let renamed = Symbol::intern(&format!("{}#{}", name, counter));
+                output.push(Token::new(
+                    TokenKind::Identifier(renamed),
+                    renamed,
+                    token.line,
+                    token.column,
+                ));
+            }
+            _ => output.push(token.clone()),
+        }
+    }
+    output
+}
+
+fn transcribe(
+    body: &[Token],
+    bindings: &Bindings,
+    call_site: &Token,
+    output: &mut Vec<Token>,
+) -> Result<(), Diagnostic> {
+    let mut i = 0;
+
+    while i < body.len() {
+        if body[i].kind != TokenKind::Dollar {
+            output.push(Token::new(
+                body[i].kind.clone(),
+                body[i].lexeme,
+                call_site.line,
+                call_site.column,
+            ));
+            i += 1;
+            continue;
+        }
+
+        match body.get(i + 1).map(|t| &t.kind) {
+            Some(TokenKind::Identifier(name)) => {
+                match bindings.get(name) {
+                    Some(Binding::Single(tokens, fragment)) => {
+                        let wrap = *fragment == Fragment::Expr && tokens.len() > 1;
+                        if wrap {
+                            output.push(Token::new(
+                                TokenKind::LParen,
+                                "(",
+                                call_site.line,
+                                call_site.column,
+                            ));
+                        }
+                        output.extend(tokens.iter().cloned());
+                        if wrap {
+                            output.push(Token::new(
+                                TokenKind::RParen,
+                                ")",
+                                call_site.line,
+                                call_site.column,
+                            ));
+                        }
+                    }
+                    Some(Binding::Repeated(_)) => {
+                        return Err(error_at(
+                            &body[i],
+                            &format!("variable `${}` is still repeating at this depth", name),
+                        ));
+                    }
+                    None => {
+                        return Err(error_at(
+                            &body[i],
+                            &format!("unknown macro variable `${}`", name),
+                        ))
+                    }
+                }
+                i += 2;
+            }
+            Some(TokenKind::LParen) => {
+                let close = group_end(body, i + 1)
+                    .ok_or_else(|| error_at(&body[i], "unclosed repetition in macro body"))?;
+                let inner = &body[i + 2..close];
+                let (separator, _, next) =
+                    parse_repeat_suffix(body, close + 1).ok_or_else(|| {
+                        error_at(&body[i], "expected `*`, `+` or `?` after repetition")
+                    })?;
+
+                let iterations = repetition_count(inner, bindings).ok_or_else(|| {
+                    error_at(
+                        &body[i],
+                        "repetition in macro body uses no repeating variables",
+                    )
+                })?;
+                for n in 0..iterations {
+                    if n > 0 {
+                        if let Some(sep) = &separator {
+                            output.push(Token::new(
+                                sep.kind.clone(),
+                                sep.lexeme,
+                                call_site.line,
+                                call_site.column,
+                            ));
+                        }
+                    }
+                    // Only the variables the repetition uses, so that an
+                    // iteration does not copy every other iteration too
+                    let mut scoped = Bindings::new();
+                    for (name, binding) in bindings {
+                        if !body_uses(inner, *name) {
+                            continue;
+                        }
+                        match binding {
+                            Binding::Repeated(items) => scoped.extend(items[n].clone()),
+                            single => {
+                                scoped.insert(*name, single.clone());
+                            }
+                        }
+                    }
+                    transcribe(inner, &scoped, call_site, output)?;
+                }
+                i = next;
+            }
+            _ => {
+                output.push(body[i].clone());
+                i += 1;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn repetition_count(inner: &[Token], bindings: &Bindings) -> Option<usize> {
+    bindings
+        .iter()
+        .filter(|(name, _)| body_uses(inner, **name))
+        .find_map(|(_, binding)| match binding {
+            Binding::Repeated(items) => Some(items.len()),
+            Binding::Single(..) => None,
+        })
+}
+
+fn body_uses(body: &[Token], name: Symbol) -> bool {
+    body.windows(2).any(|w| {
+        w[0].kind == TokenKind::Dollar
+            && matches!(&w[1].kind, TokenKind::Identifier(n) if *n == name)
+    })
+}
+
+fn is_open(kind: &TokenKind) -> bool {
+    matches!(
+        kind,
+        TokenKind::LParen | TokenKind::LBracket | TokenKind::LBrace
+    )
+}
+
+fn is_close(kind: &TokenKind) -> bool {
+    matches!(
+        kind,
+        TokenKind::RParen | TokenKind::RBracket | TokenKind::RBrace
+    )
+}
+
+/// Whether `name!(`, `name![` or `name!{` starts at token `i`
+fn is_invocation(tokens: &[Token], i: usize) -> bool {
+    matches!(tokens[i].kind, TokenKind::Identifier(_))
+        && tokens.get(i + 1).map(|t| &t.kind) == Some(&TokenKind::Not)
+        && tokens.get(i + 2).is_some_and(|t| is_open(&t.kind))
+}
+
+/// Index of the delimiter closing the group opened at `open`
+fn group_end(tokens: &[Token], open: usize) -> Option<usize> {
+    let mut depth = 0usize;
+    for (i, token) in tokens.iter().enumerate().skip(open) {
+        if is_open(&token.kind) {
+            depth += 1;
+        } else if is_close(&token.kind) {
+            depth = depth.checked_sub(1)?;
+            if depth == 0 {
+                return Some(i);
+            }
+        }
+    }
+    None
+}
+
+/// Number of tokens in the token tree starting at `pos`
+fn token_tree_len(tokens: &[Token], pos: usize) -> Option<usize> {
+    let token = tokens.get(pos)?;
+    if is_open(&token.kind) {
+        group_end(tokens, pos).map(|end| end - pos + 1)
+    } else if is_close(&token.kind) {
+        None
+    } else {
+        Some(1)
+    }
+}
+
+fn error_at(token: &Token, msg: &str) -> Diagnostic {
+    Diagnostic::new(
+        Span::new(token.line, token.column),
+        format!("macro expansion failed: {}", msg),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    fn lex(source: &str) -> Vec<Token> {
+        let mut lexer = Lexer::new(source);
+        let mut tokens = Vec::new();
+        loop {
+            let token = lexer.next_token();
+            if token.kind == TokenKind::Eof {
+                break;
+            }
+            tokens.push(token);
+        }
+        tokens
+    }
+
+    fn kinds(source: &str) -> Vec<TokenKind> {
+        expand(lex(source))
+            .unwrap()
+            .into_iter()
+            .map(|t| t.kind)
+            .collect()
+    }
+
+    #[test]
+    fn test_simple_expansion() {
+        let expanded = kinds("macro square { ($x:expr) => { $x * $x }; } square!(3)");
+        assert_eq!(
+            expanded,
+            vec![
+                TokenKind::Integer(3, None),
+                TokenKind::Star,
+                TokenKind::Integer(3, None)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_expr_fragments_are_parenthesized() {
+        let expanded = kinds("macro double { ($x:expr) => { $x * 2 }; } double!(1 + 1)");
+        assert_eq!(expanded[0], TokenKind::LParen);
+        assert_eq!(expanded[4], TokenKind::RParen);
+    }
+
+    #[test]
+    fn test_repetition() {
+        let expanded = kinds("macro sum { ($($x:expr),*) => { 0 $(+ $x)* }; } sum!(1, 2, 3)");
+        assert_eq!(
+            expanded,
+            vec![
+                TokenKind::Integer(0, None),
+                TokenKind::Plus,
+                TokenKind::Integer(1, None),
+                TokenKind::Plus,
+                TokenKind::Integer(2, None),
+                TokenKind::Plus,
+                TokenKind::Integer(3, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_hygiene_renames_introduced_bindings() {
+        let expanded = kinds("macro tmp { ($e:expr) => { let t = $e; t }; } let t = 1; tmp!(t)");
+        let idents: Vec<_> = expanded
+            .iter()
+            .filter_map(|k| match k {
+                TokenKind::Identifier(name) => Some(name.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(idents, vec!["t", "t#1", "t", "t#1"]);
+    }
+
+    #[test]
+    fn test_recursive_macro() {
+        let source = "macro count { () => { 0 }; ($x:tt $($rest:tt)*) => { 1 + count!($($rest)*) }; } count!(a b c)";
+        let expanded = kinds(source);
+        assert_eq!(
+            expanded
+                .iter()
+                .filter(|k| **k == TokenKind::Integer(1, None))
+                .count(),
+            3
+        );
+    }
+
+    #[test]
+    fn test_recursion_limit() {
+        let err = expand(lex("macro forever { () => { forever!() }; } forever!()"))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("recursion limit"));
+    }
+
+    #[test]
+    fn test_expansion_limit() {
+        let args = "x ".repeat(40);
+        let source = format!(
+            "macro dup {{ ($($t:tt)*) => {{ dup!($($t)* $($t)*) }}; }} dup!({})",
+            args
+        );
+        let err = expand(lex(&source)).unwrap_err().to_string();
+        assert!(
+            err.contains("expansion limit (262144 tokens) reached while expanding `dup!`"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_builtin_asm() {
+        let expanded = kinds("macro one { () => { 1 }; } asm!(\"nop\", in(reg) one!())");
+        assert_eq!(
+            expanded[..3],
+            [
+                TokenKind::Identifier("asm".into()),
+                TokenKind::Not,
+                TokenKind::LParen
+            ]
+        );
+        assert!(expanded.contains(&TokenKind::Integer(1, None)));
+        assert_eq!(
+            kinds("macro asm { () => { 2 }; } asm!()"),
+            [TokenKind::Integer(2, None)]
+        );
+    }
+
+    #[test]
+    fn test_no_matching_rule() {
+        let err = expand(lex("macro one { ($x:ident) => { $x }; } one!(1)"))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("no rules"));
+    }
+}
@@ -1,15 +1,79 @@
 /// Solo Compiler CLI
 ///
 /// Usage:
-///   solo build <file.solo>
-///   solo run <file.solo>
-///   solo check <file.solo>
+///   solo build <file.solo> | --bin <name> | --example <name> [--target <triple>]
+///              [--crate-type bin|staticlib|dylib | --lib [--emit-header <file>]]
+///              [--linker <program>] [--print-link-args] [--timings[=json]] [--closed-world]
+///              [--lto] [--profile-generate[=<file>] | --profile-use=<file>]
+///   solo run <file.solo> | --bin <name> | --example <name> [--samples <n>] [--seed <n|random>]
+///            [--profile-arenas] [--profile-heap <file>] [--profile-generate[=<file>]]
+///            [--max-steps <n>] [--max-memory <MiB>] [--max-depth <n>] [--time-limit <secs>]
+///            [--output literal|plot|json]
+///            [-- <args>...]
+///   solo check <file.solo> | --bin <name> | --example <name> | --all-targets [--print-layouts]
+///              [--print-type-sizes]
+///              [--emit expanded]
+///   solo lint <file.solo> | --bin <name> | --example <name> [--baseline <file>]
+///             [--write-baseline <file>] [--allow <lint>]
+///   solo test <file.solo> [filter] [--timeout <secs>] [--seed <n|random>] [--coverage[=<dir>]]
+///   solo minify <file.solo>
+///   solo minimize <file.solo> --ice | --error <code|text> | --command <program> [args...]
+///   solo bloat <binary> [file.solo] [-n <count>]
+///   solo transpile --to solo|me <file> [--dialect solo|legacy]
+///   solo serve --api | --playground [--listen <addr>] [--unstable]
+///              [--timeout <secs>] [--memory <MiB>]
+///   solo lsp [--unstable]
+///   solo repl [--unstable] [--output literal|plot|json] [file.solo...]
+///   solo highlight <file.solo> [--html] | --tree-sitter <dir>
+///   solo <cmd> [args...]    (runs `solo-<cmd>` from PATH)
+///
+/// Options:
+///   --release                  Release profile (integer overflow wraps)
+///   --overflow-checks[=on|off] Override overflow trapping for the profile
+///   --panic=unwind|abort       Run deferred cleanup on panic, or skip it
+///   --sanitize=address,arena   Check accesses through raw pointers at runtime
+///   --message-format=json      Errors as JSON lines (with stable fingerprints)
+///   --unstable                 Allow `#![feature(...)]` (build, run, check, lint, test)
+///   --deny-any                 Reject the `any` type (build, run, check)
+///   --dialect solo|legacy      Read the input as Solo or as legacy Me (build, run, check,
+///                              lint); `.my` and `.mylang` files are Me by default
+///
+/// Environment:
+///   SOLO_CACHE_DIR             Keep lexed token streams here between builds
+///   SOLO_ICE_BUNDLE=1          On a compiler crash, write a reproduction bundle
+///
+/// Introspection:
+///   solo --print targets|sysroot|target-libdir|cfg|version [--json] [--sysroot <dir>]
+///                [--target <triple>]
 
 use std::env;
-use std::fs;
+use std::path::{Path, PathBuf};
 use std::process;
+use std::time::Duration;
 
+/// Report a panic as an internal compiler error, with exit status 101
 fn main() {
+    solo::ice::install();
+    if std::panic::catch_unwind(run).is_ok() {
+        return;
+    }
+    if let Some(report) = solo::ice::take_report() {
+        if env::var_os(solo::ice::BUNDLE_VAR).is_some_and(|v| v != "0") {
+            match solo::ice::write_bundle(&report) {
+                Ok(dir) => eprintln!("note: wrote a reproduction bundle to {}", dir.display()),
+                Err(err) => eprintln!("warning: cannot write a reproduction bundle: {}", err),
+            }
+        } else {
+            eprintln!(
+                "note: run with `{}=1` to write a reproduction bundle (source and flags)",
+                solo::ice::BUNDLE_VAR
+            );
+        }
+    }
+    process::exit(101);
+}
+
+fn run() {
     let args: Vec<String> = env::args().collect();
 
     if args.len() < 2 {
@@ -17,13 +81,145 @@ fn main() {
         eprintln!();
         eprintln!("Usage:");
         eprintln!("  solo build <file.solo>  - Compile to executable");
+        eprintln!(
+            "                            (--crate-type bin|staticlib|dylib, --linker <program>)"
+        );
+        eprintln!("                            (--print-link-args: show the link command)");
+        eprintln!(
+            "                            (--lib: static and shared library of pub extern fns)"
+        );
+        eprintln!("                            (--emit-header <file>: C header for --lib)");
+        eprintln!(
+            "                            (--timings: time and memory per pass, =json: Chrome trace)"
+        );
+        eprintln!(
+            "                            (--closed-world: strip what main cannot reach, report it)"
+        );
+        eprintln!(
+            "                            (--lto: optimize the target and library as one, report it)"
+        );
+        eprintln!(
+            "                            (--profile-use=<file>: plan for a recorded profile)"
+        );
         eprintln!("  solo run <file.solo>    - Compile and run");
-        eprintln!("  solo check <file.solo>  - Type check only");
+        eprintln!(
+            "                            (-- <args>...: arguments for the program, exit status from main)"
+        );
+        eprintln!(
+            "                            (--samples <n>: Monte Carlo beliefs, --seed <n|random>: replay)"
+        );
+        eprintln!(
+            "                            (--profile-arenas: report arena allocation statistics)"
+        );
+        eprintln!(
+            "                            (--profile-heap <file>: write allocations as pprof)"
+        );
+        eprintln!(
+            "                            (--profile-generate[=<file>]: add calls and branches to a profile)"
+        );
+        eprintln!(
+            "                            (--max-steps/--max-memory <MiB>/--max-depth/--time-limit <secs>:"
+        );
+        eprintln!("                             stop untrusted programs, reporting the limit)");
+        eprintln!(
+            "                            (--output plot|json: print beliefs with a histogram, as JSON)"
+        );
+        eprintln!("  solo check <file.solo>  - Type check only (--print-layouts: struct layouts)");
+        eprintln!(
+            "                            (--print-type-sizes: sizes, padding and unread fields)"
+        );
+        eprintln!("                            (--all-targets: every target of the package)");
+        eprintln!("                            (--emit expanded: print the source after macros)");
+        eprintln!("  solo lint <file.solo>   - Fail on lint warnings not in --baseline <file>");
+        eprintln!("                            (--write-baseline <file> records the current ones)");
+        eprintln!(
+            "                            (--bin/--example: a package target with its library)"
+        );
+        eprintln!("                            (--allow <lint>: turn a lint off, e.g. shadowing)");
+        eprintln!("  solo test <file.solo>   - Run #[test] functions, each in a sandbox");
+        eprintln!("                            ([filter] substring, --timeout <secs> per test)");
+        eprintln!("                            (--seed <n|random>: seed of belief sampling)");
+        eprintln!(
+            "                            (--coverage[=<dir>]: lcov and HTML report, default coverage/)"
+        );
+        eprintln!(
+            "  solo minify <file.solo> - Print the program minified (short names, no comments)"
+        );
+        eprintln!("  solo minimize <file.solo> - Reduce the program to a minimal reproducer");
+        eprintln!(
+            "                            (--ice, --error <code|text>, --command <program> [args...])"
+        );
+        eprintln!(
+            "  solo bloat <binary>     - Attribute binary size to functions and std components"
+        );
+        eprintln!("                            ([file.solo]: its functions; -n <count>: rows, default 20)");
+        eprintln!("  solo transpile --to <d> - Print the file translated to Solo or Me");
+        eprintln!("  solo serve --api        - Answer JSON-RPC requests on a local socket");
+        eprintln!(
+            "                            (--listen <addr>: loopback address, default {})",
+            solo::rpc::DEFAULT_ADDRESS
+        );
+        eprintln!("  solo serve --playground - Check and run POSTed source over HTTP, sandboxed");
+        eprintln!("                            (--timeout <secs>, --memory <MiB>: limits per run)");
+        eprintln!("  solo lsp                - Language server for editors, on stdin and stdout");
+        eprintln!(
+            "  solo repl [file.solo...] - Evaluate items, statements and expressions interactively"
+        );
+        eprintln!(
+            "                            (:load <file>, :reload, :save <file>; files reload on change)"
+        );
+        eprintln!(
+            "                            (--output literal|json: beliefs as literals or JSON, not plots)"
+        );
+        eprintln!("  solo highlight <file>   - Print the file highlighted for a terminal (--html)");
+        eprintln!(
+            "                            (--tree-sitter <dir>: write the tree-sitter grammar)"
+        );
         eprintln!("  solo version            - Show version");
+        eprintln!();
+        eprintln!("Options:");
+        eprintln!("  --release                   - Release profile (overflow wraps)");
+        eprintln!("  --overflow-checks[=on|off]  - Trap on integer overflow");
+        eprintln!("  --panic=unwind|abort        - Unwind on panic (default) or abort at once");
+        eprintln!(
+            "  --sanitize=address,arena    - Catch out-of-bounds and use-after-reset accesses"
+        );
+        eprintln!("  --message-format=json       - Report errors as JSON lines with fingerprints");
+        eprintln!("  --unstable                  - Allow unstable features via #![feature(...)]");
+        eprintln!("  --deny-any                  - Reject the `any` type, for production code");
+        eprintln!(
+            "  --dialect solo|legacy       - Read the input as Solo or legacy Me (.my, .mylang)"
+        );
+        eprintln!(
+            "  --bin <name>                - Use the named executable of the current package"
+        );
+        eprintln!(
+            "  --example <name>            - Use examples/<name>.solo of the current package"
+        );
+        eprintln!(
+            "  --print <query> [--json]    - Print targets, sysroot, target-libdir, cfg or version"
+        );
+        eprintln!("  --sysroot <dir>             - Use an alternative sysroot (or SOLO_SYSROOT)");
+        eprintln!("  --target <triple>           - Build or check for another target (see --print targets)");
+        eprintln!();
+        eprintln!("Environment:");
+        eprintln!("  SOLO_CACHE_DIR              - Keep lexed token streams here between builds");
+        eprintln!(
+            "  SOLO_ICE_BUNDLE=1           - On a compiler crash, write a reproduction bundle"
+        );
+        let plugins = solo::plugin::list();
+        if !plugins.is_empty() {
+            eprintln!();
+            eprintln!("Installed plugins:");
+            for name in plugins {
+                eprintln!("  solo {}", name);
+            }
+        }
         process::exit(1);
     }
 
     let command = &args[1];
+    solo::token_cache::set_dir(env::var_os("SOLO_CACHE_DIR").map(Into::into));
 
     match command.as_str() {
         "version" => {
@@ -31,36 +227,1640 @@ fn main() {
             println!("Part of the My Language family");
         }
         "build" | "run" | "check" => {
-            if args.len() < 3 {
+            let mut profile = Profile::default();
+            let mut options = solo::RunOptions::default();
+            let mut print_layouts = false;
+            let mut print_type_sizes = false;
+            let mut emit_expanded = false;
+            let mut all_targets = false;
+            let mut json = false;
+            let mut unstable = false;
+            let mut deny_any = false;
+            let mut input = None;
+            let mut target = None;
+            let mut heap_profile = None;
+            let mut cross = None;
+            let mut program_args = Vec::new();
+            let mut crate_types = vec![solo::link::CrateType::default()];
+            let mut lib = false;
+            let mut header = None;
+            let mut linker = None;
+            let mut print_link_args = false;
+            let mut dialect = None;
+            let mut timings = None;
+            let mut closed_world = false;
+            let mut lto = false;
+            let mut profile_generate = None;
+            let mut profile_use = None;
+            let mut rest = args[2..].iter().map(String::as_str);
+            while let Some(arg) = rest.next() {
+                match arg {
+                    "--" if command == "run" => {
+                        program_args.extend(rest.by_ref().map(String::from));
+                    }
+                    "--print-layouts" if command == "check" => print_layouts = true,
+                    "--print-type-sizes" if command == "check" => print_type_sizes = true,
+                    "--all-targets" if command == "check" => all_targets = true,
+                    "--emit" if command == "check" => match rest.next() {
+                        Some("expanded") => emit_expanded = true,
+                        _ => {
+                            eprintln!("Error: --emit must be expanded");
+                            process::exit(1);
+                        }
+                    },
+                    "--bin" | "--example" => match rest.next() {
+                        Some(name) => target = Some((arg, name)),
+                        None => {
+                            eprintln!("Error: {} requires a name", arg);
+                            process::exit(1);
+                        }
+                    },
+                    "--target" if command == "run" => {
+                        eprintln!(
+                            "Error: --target cannot be used with run, which runs on the host"
+                        );
+                        process::exit(1);
+                    }
+                    "--target" => cross = Some(find_target(rest.next())),
+                    "--crate-type" if command == "build" => {
+                        let value = rest.next().and_then(solo::link::CrateType::parse);
+                        let Some(value) = value else {
+                            eprintln!("Error: --crate-type must be bin, staticlib or dylib");
+                            process::exit(1);
+                        };
+                        crate_types = vec![value];
+                    }
+                    "--lib" if command == "build" => {
+                        lib = true;
+                        crate_types = vec![
+                            solo::link::CrateType::StaticLib,
+                            solo::link::CrateType::DyLib,
+                        ];
+                    }
+                    "--emit-header" if command == "build" => match rest.next() {
+                        Some(file) => header = Some(file),
+                        None => {
+                            eprintln!("Error: --emit-header requires a file");
+                            process::exit(1);
+                        }
+                    },
+                    "--linker" if command == "build" => match rest.next() {
+                        Some(program) => linker = Some(solo::link::Linker::new(program)),
+                        None => {
+                            eprintln!("Error: --linker requires a program");
+                            process::exit(1);
+                        }
+                    },
+                    "--print-link-args" if command == "build" => print_link_args = true,
+                    "--timings" | "--timings=json" if command != "run" => {
+                        timings = Some(arg == "--timings=json");
+                    }
+                    "--closed-world" if command == "build" => closed_world = true,
+                    "--lto" if command == "build" => lto = true,
+                    "--profile-generate" if command != "check" => {
+                        profile_generate = Some("default.profraw");
+                    }
+                    flag if command != "check" && flag.starts_with("--profile-generate=") => {
+                        profile_generate = Some(&flag["--profile-generate=".len()..]);
+                    }
+                    flag if command == "build" && flag.starts_with("--profile-use=") => {
+                        profile_use = Some(&flag["--profile-use=".len()..]);
+                    }
+                    "--profile-arenas" if command == "run" => options.profile_arenas = true,
+                    "--profile-heap" if command == "run" => match rest.next() {
+                        Some(file) => heap_profile = Some(file),
+                        None => {
+                            eprintln!("Error: --profile-heap requires a file");
+                            process::exit(1);
+                        }
+                    },
+                    "--max-steps" | "--max-memory" | "--max-depth" if command == "run" => {
+                        let value = rest.next().and_then(|v| v.parse::<u64>().ok());
+                        let limits = &mut options.limits;
+                        match (arg, value) {
+                            ("--max-steps", Some(n)) if n > 0 => limits.steps = Some(n),
+                            ("--max-memory", Some(mib)) if mib > 0 => {
+                                limits.memory = Some(mib.saturating_mul(1024 * 1024));
+                            }
+                            ("--max-depth", Some(n)) if n > 0 => limits.depth = Some(n as usize),
+                            _ => {
+                                eprintln!("Error: {} requires a positive integer", arg);
+                                process::exit(1);
+                            }
+                        }
+                    }
+                    "--time-limit" if command == "run" => {
+                        match rest.next().and_then(|v| v.parse::<f64>().ok()) {
+                            Some(secs) if secs > 0.0 => {
+                                options.limits.time = Some(Duration::from_secs_f64(secs));
+                            }
+                            _ => {
+                                eprintln!(
+                                    "Error: --time-limit requires a positive number of seconds"
+                                );
+                                process::exit(1);
+                            }
+                        }
+                    }
+                    "--samples" if command == "run" => {
+                        match rest.next().and_then(|v| v.parse::<usize>().ok()) {
+                            Some(n) if n > 0 => options.samples = Some(n),
+                            _ => {
+                                eprintln!("Error: --samples requires a positive integer");
+                                process::exit(1);
+                            }
+                        }
+                    }
+                    "--seed" if command == "run" => options.seed = parse_seed(rest.next()),
+                    "--output" if command == "run" => {
+                        options.beliefs = parse_belief_format(rest.next())
+                    }
+                    "--message-format=json" => json = true,
+                    "--message-format=human" => json = false,
+                    "--unstable" => unstable = true,
+                    "--deny-any" => deny_any = true,
+                    "--dialect" => dialect = Some(parse_dialect(rest.next())),
+                    flag if profile.parse_flag(flag) => {}
+                    flag if flag.starts_with("--") => {
+                        eprintln!("Error: Unknown option '{}'", flag);
+                        process::exit(1);
+                    }
+                    file => input = Some(file),
+                }
+            }
+
+            let external = crate_types.iter().any(|&t| t != solo::link::CrateType::Bin);
+            if closed_world && external {
+                eprintln!(
+                    "Error: --closed-world builds an executable; libraries have external callers"
+                );
+                process::exit(1);
+            }
+            if header.is_some() && !lib {
+                eprintln!("Error: --emit-header requires --lib");
+                process::exit(1);
+            }
+            if input.is_some() && (target.is_some() || all_targets) {
+                eprintln!(
+                    "Error: --bin, --example and --all-targets select files from the package"
+                );
+                process::exit(1);
+            }
+            if all_targets {
+                check_targets(&current_package(), json, unstable, deny_any);
+                return;
+            }
+            let (path, unit, package) = match (input, target) {
+                (Some(filename), _) => (PathBuf::from(filename), None, None),
+                (None, Some((flag, name))) => {
+                    let package = current_package();
+                    let target = match flag {
+                        "--bin" => package.bin(name),
+                        _ => package.example(name),
+                    }
+                    .unwrap_or_else(|err| {
+                        eprintln!("Error: {}", err);
+                        process::exit(1);
+                    });
+                    let unit = package.source(&target).unwrap_or_else(|err| {
+                        report(err, &target.path, "", json);
+                    });
+                    (target.path, Some(unit), Some(package))
+                }
+                (None, None) => {
+                    eprintln!("Error: Missing input file");
+                    eprintln!(
+                        "Usage: solo {} <file.solo> | --bin <name> | --example <name>",
+                        command
+                    );
+                    process::exit(1);
+                }
+            };
+            let path = path.as_path();
+            let source = match &unit {
+                Some(unit) => {
+                    solo::ice::set_input(path, &unit.text);
+                    unit.text.clone()
+                }
+                None => read_program(path, dialect, json),
+            };
+            // Place errors in library code in `src/lib.solo`
+            let attribute = |err: solo::CompileError| match &unit {
+                Some(unit) => unit.attribute(err),
+                None => err,
+            };
+            // From here, so the lex cache counts the stability check
+            let mut passes = match timings {
+                Some(_) => solo::timings::Timings::new(),
+                None => solo::timings::Timings::default(),
+            };
+            if !unstable {
+                if let Err(err) = solo::check_stable(&source) {
+                    report(attribute(err), path, &source, json);
+                }
+            }
+            if deny_any {
+                if let Err(err) = solo::deny_any(&source) {
+                    report(attribute(err), path, &source, json);
+                }
+            }
+
+            if command == "run" {
+                // Interpreted until native code generation lands
+                options.overflow = profile.overflow();
+                options.panic = profile.panic;
+                options.sanitizers = profile.sanitizers;
+                options.backtrace = env::var_os("SOLO_BACKTRACE").is_some_and(|v| v != "0");
+                options.profile_heap = heap_profile.is_some();
+                options.coverage = profile_generate.is_some();
+                options.args = std::iter::once(path.display().to_string())
+                    .chain(program_args)
+                    .collect();
+                options.stack = solo::interp::STACK_SIZE;
+                on_stack(options.stack, || {
+                    let (result, heap, counts) = solo::run_profiled(&source, &options);
+                    if let (Some(file), Some(counts)) = (profile_generate, counts) {
+                        record_profile(file, counts);
+                    }
+                    if let (Some(file), Some(heap)) = (heap_profile, heap) {
+                        let bytes = heap.encode(&path.display().to_string());
+                        if let Err(err) = std::fs::write(file, bytes) {
+                            eprintln!("Error: cannot write '{}': {}", file, err);
+                            process::exit(1);
+                        }
+                        let (count, bytes) = heap.totals();
+                        eprintln!(
+                            "Wrote {} allocation(s), {} bytes, to {}",
+                            count, bytes, file
+                        );
+                    }
+                    if let Err(err) = result {
+                        let panicked = matches!(&err, solo::CompileError::Runtime(msg)
+                            if msg.starts_with("Runtime panic"));
+                        print_error(attribute(err), path, &source, json);
+                        if panicked && !options.backtrace && !json {
+                            eprintln!("note: run with `SOLO_BACKTRACE=1` to display a backtrace");
+                        }
+                        if panicked && options.panic == solo::PanicStrategy::Abort {
+                            process::abort();
+                        }
+                        process::exit(1);
+                    }
+                    // An integer returned by `main` is the exit status
+                    if let Ok(solo::interp::Value::Int(code)) = result {
+                        process::exit(code as i32);
+                    }
+                });
+                return;
+            }
+
+            if emit_expanded {
+                let program = solo::compile_until(&source, solo::Phase::Parse)
+                    .map(|artifact| artifact.into_ast().expect("parsing ran"))
+                    .unwrap_or_else(|err| {
+                        report(attribute(err), path, &source, json);
+                    });
+                print!("{}", program);
+                return;
+            }
+
+            if print_type_sizes {
+                match solo::print_type_sizes(&source, cross.as_ref()) {
+                    Ok(sizes) => print!("{}", sizes),
+                    Err(err) => report(attribute(err), path, &source, json),
+                }
+                return;
+            }
+
+            if print_layouts {
+                let info = match &cross {
+                    Some(target) => solo::check_for(&source, target),
+                    None => solo::check(&source),
+                };
+                let info = info.unwrap_or_else(|err| {
+                    report(attribute(err), path, &source, json);
+                });
+                let mut layouts: Vec<_> = info.layouts.values().collect();
+                layouts.sort_by(|a, b| a.name.cmp(&b.name));
+                for layout in layouts {
+                    println!("{}", layout);
+                }
+                return;
+            }
+
+            let opt_level = if profile.release {
+                solo::session::OptLevel::Release
+            } else {
+                solo::session::OptLevel::Debug
+            };
+            let mut compile = solo::session::Options::new()
+                .with_opt_level(opt_level)
+                .with_crate_type(crate_types[0])
+                .with_closed_world(closed_world)
+                .with_lto(lto)
+                .with_sanitizers(profile.sanitizers)
+                .with_profile_generate(profile_generate.is_some());
+            if let Some(file) = profile_use {
+                compile = compile.with_profile_use(file);
+            }
+            compile.target = cross;
+            let compiled = solo::compile_timed(&source, &compile, &mut passes);
+            match timings {
+                Some(false) => eprint!("{}", passes),
+                Some(true) => {
+                    let file = "solo-timings.json";
+                    if let Err(err) = std::fs::write(file, passes.to_trace().to_string()) {
+                        eprintln!("Error: cannot write '{}': {}", file, err);
+                        process::exit(1);
+                    }
+                    eprintln!(
+                        "Wrote a trace of {} passes to {}",
+                        passes.passes().len(),
+                        file
+                    );
+                }
+                None => {}
+            }
+            match compiled {
+                Ok(optimizations) => {
+                    if let Some(plan) = optimizations.profile {
+                        eprint!("{}", plan);
+                    }
+                    // Removed items are reported in the target or library
+                    // file, relative to the current directory
+                    let cwd = env::current_dir().unwrap_or_default();
+                    let place = |span: &mut solo::ast::Span| {
+                        let file = match &unit {
+                            Some(unit) => unit.locate(span),
+                            None => path.to_path_buf(),
+                        };
+                        match file.strip_prefix(&cwd) {
+                            Ok(relative) => relative.to_path_buf(),
+                            Err(_) => file,
+                        }
+                    };
+                    if let Some(mut removed) = optimizations.closed_world {
+                        removed.attribute(place);
+                        eprint!("{}", removed);
+                    }
+                    if let Some(mut report) = optimizations.lto {
+                        report.optimized.attribute(place);
+                        eprint!("{}", report);
+                    }
+                }
+                Err(err) => report(attribute(err), path, &source, json),
+            }
+
+            if lib {
+                let crate_name = path
+                    .file_stem()
+                    .map_or("main".into(), |s| s.to_string_lossy());
+                let exports = solo::compile_until(&source, solo::Phase::TypeCheck)
+                    .map(|artifact| artifact.into_typed().expect("type checking ran"))
+                    .and_then(|(program, info)| solo::export::exports(&crate_name, &program, &info))
+                    .unwrap_or_else(|err| {
+                        report(attribute(err), path, &source, json);
+                    });
+                if exports.is_empty() {
+                    eprintln!(
+                        "Error: a library must export at least one `pub extern \"C\"` function"
+                    );
+                    process::exit(1);
+                }
+                if let Some(file) = header {
+                    let text = solo::export::header(&crate_name, &exports);
+                    if let Err(err) = std::fs::write(file, text) {
+                        eprintln!("Error: cannot write '{}': {}", file, err);
+                        process::exit(1);
+                    }
+                }
+            }
+
+            let host = solo::target::Target::host();
+            if let Some(target) = cross.or(host) {
+                let linker = linker
+                    .or_else(|| {
+                        let program = package.as_ref()?.linker()?;
+                        Some(solo::link::Linker::new(program))
+                    })
+                    .unwrap_or_else(|| solo::link::Linker::for_target(&target, host));
+                for &crate_type in &crate_types {
+                    let job = link_job(path, &source, crate_type, &target, package.as_ref());
+                    // Run once code generation writes the object file
+                    if print_link_args {
+                        println!(
+                            "{}",
+                            solo::link::command_line(&job.command(&linker, &target))
+                        );
+                    }
+                }
+            }
+
+            match cross {
+                Some(target) => println!("✓ Compilation successful ({})", target.triple),
+                None => println!("✓ Compilation successful"),
+            }
+        }
+        "lint" => {
+            let mut json = false;
+            let mut unstable = false;
+            let mut input = None;
+            let mut baseline = None;
+            let mut write_baseline = None;
+            let mut dialect = None;
+            let mut target = None;
+            let mut allowed = Vec::new();
+            let mut rest = args[2..].iter().map(String::as_str);
+            while let Some(arg) = rest.next() {
+                match arg {
+                    "--bin" | "--example" => match rest.next() {
+                        Some(name) => target = Some((arg, name)),
+                        None => {
+                            eprintln!("Error: {} requires a name", arg);
+                            process::exit(1);
+                        }
+                    },
+                    "--allow" => match rest.next() {
+                        Some(lint) if solo::lint::LINTS.contains(&lint) => {
+                            allowed.push(lint.to_string())
+                        }
+                        _ => {
+                            eprintln!(
+                                "Error: --allow expects a lint: {}",
+                                solo::lint::LINTS.join(", ")
+                            );
+                            process::exit(1);
+                        }
+                    },
+                    "--baseline" | "--write-baseline" => {
+                        let Some(file) = rest.next() else {
+                            eprintln!("Error: {} requires a file", arg);
+                            process::exit(1);
+                        };
+                        if arg == "--baseline" {
+                            baseline = Some(file);
+                        } else {
+                            write_baseline = Some(file);
+                        }
+                    }
+                    "--message-format=json" => json = true,
+                    "--message-format=human" => json = false,
+                    "--unstable" => unstable = true,
+                    "--dialect" => dialect = Some(parse_dialect(rest.next())),
+                    flag if flag.starts_with("--") => {
+                        eprintln!("Error: Unknown option '{}'", flag);
+                        process::exit(1);
+                    }
+                    file => input = Some(file),
+                }
+            }
+
+            // A package target is linted with the library appended, so
+            // that library code no target uses is found too
+            let (path, source, unit) = match (input, target) {
+                (Some(filename), None) => {
+                    let path = PathBuf::from(filename);
+                    let source = read_program(&path, dialect, json);
+                    (path, source, None)
+                }
+                (None, Some((flag, name))) => {
+                    let package = current_package();
+                    let target = match flag {
+                        "--bin" => package.bin(name),
+                        _ => package.example(name),
+                    }
+                    .unwrap_or_else(|err| {
+                        eprintln!("Error: {}", err);
+                        process::exit(1);
+                    });
+                    let unit = package.source(&target).unwrap_or_else(|err| {
+                        report(err, &target.path, "", json);
+                    });
+                    allowed.extend_from_slice(package.allowed_lints());
+                    (target.path, unit.text.clone(), Some(unit))
+                }
+                _ => {
+                    eprintln!("Error: Expected an input file or one of --bin and --example");
+                    eprintln!(
+                        "Usage: solo lint <file.solo> | --bin <name> | --example <name> [--baseline <file>]"
+                    );
+                    process::exit(1);
+                }
+            };
+            let path = path.as_path();
+            let attribute = |err: solo::CompileError| match &unit {
+                Some(unit) => unit.attribute(err),
+                None => err,
+            };
+            if !unstable {
+                if let Err(err) = solo::check_stable(&source) {
+                    report(attribute(err), path, &source, json);
+                }
+            }
+            let mut warnings = solo::lint(&source).unwrap_or_else(|err| {
+                report(attribute(err), path, &source, json);
+            });
+            warnings.retain(|warning| !allowed.iter().any(|lint| lint == warning.lint));
+
+            if let Some(file) = write_baseline {
+                let text = solo::lint::Baseline::render(&warnings, &source);
+                if let Err(err) = std::fs::write(file, text) {
+                    eprintln!("Error: cannot write '{}': {}", file, err);
+                    process::exit(1);
+                }
+                println!("Recorded {} warning(s) in {}", warnings.len(), file);
+                return;
+            }
+
+            let baseline = match baseline {
+                Some(file) => {
+                    let text = solo::read_source(Path::new(file)).unwrap_or_else(|err| {
+                        report(err, Path::new(file), "", json);
+                    });
+                    solo::lint::Baseline::parse(&text)
+                }
+                None => solo::lint::Baseline::default(),
+            };
+            let (known, new): (Vec<_>, Vec<_>) = warnings
+                .into_iter()
+                .partition(|w| baseline.contains(w, &source));
+            for mut warning in new.iter().cloned() {
+                let fingerprint = warning.diagnostic.fingerprint(&source);
+                match &unit {
+                    Some(unit) => unit.attribute_diagnostic(&mut warning.diagnostic),
+                    None => warning.diagnostic.file = Some(path.to_path_buf()),
+                }
+                if json {
+                    println!("{}", warning.to_json_with(fingerprint));
+                } else {
+                    eprintln!("{}", warning);
+                }
+            }
+            if !new.is_empty() {
+                process::exit(1);
+            }
+            if !json {
+                match known.len() {
+                    0 => println!("✓ No lint warnings"),
+                    n => println!("✓ No new lint warnings ({} in baseline)", n),
+                }
+            }
+        }
+        "minify" => {
+            let Some(filename) = args.get(2) else {
                 eprintln!("Error: Missing input file");
-                eprintln!("Usage: solo {} <file.solo>", command);
+                eprintln!("Usage: solo minify <file.solo>");
+                process::exit(1);
+            };
+            let path = Path::new(filename);
+            let source = solo::read_source(path).unwrap_or_else(|err| {
+                report(err, path, "", false);
+            });
+            match solo::minify::minify(&source) {
+                Ok(minified) => println!("{}", minified),
+                Err(err) => report(err, path, &source, false),
+            }
+        }
+        "minimize" => {
+            let usage = "Usage: solo minimize <file.solo> --ice | --error <code|text> \
+                         | --command <program> [args...]";
+            let mut file = None;
+            let mut predicate = None;
+            let mut rest = args[2..].iter();
+            while let Some(arg) = rest.next() {
+                match arg.as_str() {
+                    "--ice" => predicate = Some(solo::minimize::Predicate::Ice),
+                    "--error" => match rest.next() {
+                        Some(text) => {
+                            predicate = Some(solo::minimize::Predicate::Error(text.clone()))
+                        }
+                        None => {
+                            eprintln!("Error: --error expects an error code or text");
+                            process::exit(1);
+                        }
+                    },
+                    "--command" => {
+                        let command: Vec<String> = rest.by_ref().cloned().collect();
+                        if command.is_empty() {
+                            eprintln!("Error: --command expects a program to run");
+                            process::exit(1);
+                        }
+                        predicate = Some(solo::minimize::Predicate::Command(command));
+                    }
+                    flag if flag.starts_with('-') => {
+                        eprintln!("Error: Unknown option '{}'", flag);
+                        process::exit(1);
+                    }
+                    _ => file = Some(PathBuf::from(arg)),
+                }
+            }
+            let (Some(path), Some(predicate)) = (file, predicate) else {
+                eprintln!("Error: Missing input file or predicate");
+                eprintln!("{}", usage);
                 process::exit(1);
+            };
+            let source = solo::read_source(&path).unwrap_or_else(|err| {
+                report(err, &path, "", false);
+            });
+            match solo::minimize::minimize_file(&source, &path, &predicate) {
+                Ok(minimized) => {
+                    print!("{}", minimized.source);
+                    eprintln!(
+                        "Reduced {} lines to {} in {} tests",
+                        source.lines().count(),
+                        minimized.source.lines().count(),
+                        minimized.tests
+                    );
+                }
+                Err(err) => {
+                    eprintln!("Error: {}", err);
+                    process::exit(1);
+                }
+            }
+        }
+        "bloat" => {
+            let mut files = Vec::new();
+            let mut limit = 20;
+            let mut rest = args[2..].iter().map(String::as_str);
+            while let Some(arg) = rest.next() {
+                match arg {
+                    "-n" => match rest.next().and_then(|n| n.parse().ok()) {
+                        Some(n) => limit = n,
+                        None => {
+                            eprintln!("Error: -n expects a number of rows");
+                            process::exit(1);
+                        }
+                    },
+                    flag if flag.starts_with('-') => {
+                        eprintln!("Error: Unknown option '{}'", flag);
+                        process::exit(1);
+                    }
+                    file => files.push(file),
+                }
             }
+            let ([binary] | [binary, _]) = files[..] else {
+                eprintln!("Error: Expected a binary and at most one source file");
+                eprintln!("Usage: solo bloat <binary> [file.solo] [-n <count>]");
+                process::exit(1);
+            };
+            let data = std::fs::read(binary).unwrap_or_else(|err| {
+                eprintln!("Error: cannot read '{}': {}", binary, err);
+                process::exit(1);
+            });
+            let binary = solo::bloat::read_elf(&data).unwrap_or_else(|err| {
+                eprintln!("Error: '{}': {}", binary, err);
+                process::exit(1);
+            });
+            let (crate_name, functions) = match files.get(1) {
+                Some(filename) => {
+                    let path = Path::new(filename);
+                    let source = solo::read_source(path).unwrap_or_else(|err| {
+                        report(err, path, "", false);
+                    });
+                    let program = solo::compile_until(&source, solo::Phase::Parse)
+                        .map(|artifact| artifact.into_ast().expect("parsing ran"))
+                        .unwrap_or_else(|err| report(err, path, &source, false));
+                    let crate_name = path
+                        .file_stem()
+                        .map_or("main".into(), |s| s.to_string_lossy())
+                        .into_owned();
+                    let functions = solo::bloat::solo_symbols(&crate_name, &program);
+                    (crate_name, functions)
+                }
+                None => (String::new(), Default::default()),
+            };
+            let report = solo::bloat::analyze(&binary, &crate_name, &functions);
+            print!("{}", report.render(limit));
+        }
+        "transpile" => {
+            let mut input = None;
+            let mut to = None;
+            let mut dialect = None;
+            let mut json = false;
+            let mut rest = args[2..].iter().map(String::as_str);
+            while let Some(arg) = rest.next() {
+                match arg {
+                    "--to" => match rest.next().and_then(solo::legacy::Dialect::parse) {
+                        Some(value) => to = Some(value),
+                        None => {
+                            eprintln!("Error: --to must be solo or me");
+                            process::exit(1);
+                        }
+                    },
+                    "--dialect" => dialect = Some(parse_dialect(rest.next())),
+                    "--message-format=json" => json = true,
+                    "--message-format=human" => json = false,
+                    flag if flag.starts_with("--") => {
+                        eprintln!("Error: Unknown option '{}'", flag);
+                        process::exit(1);
+                    }
+                    file => input = Some(file),
+                }
+            }
+            let (Some(filename), Some(to)) = (input, to) else {
+                eprintln!("Error: Missing input file or target dialect");
+                eprintln!("Usage: solo transpile --to solo|me <file>");
+                process::exit(1);
+            };
+            let path = Path::new(filename);
+            let source = solo::read_source(path).unwrap_or_else(|err| {
+                report(err, path, "", json);
+            });
+            let from = dialect.unwrap_or_else(|| solo::legacy::Dialect::for_path(path));
+            match solo::transpile::transpile(&source, from, to) {
+                Ok(output) => print!("{}", output),
+                Err(err) => report(err, path, &source, json),
+            }
+        }
+        "serve" => {
+            let mut api = false;
+            let mut playground = None;
+            let mut timeout = None;
+            let mut memory = None;
+            let mut unstable = false;
+            let mut address = None;
+            let mut rest = args[2..].iter().map(String::as_str);
+            while let Some(arg) = rest.next() {
+                match arg {
+                    "--api" => api = true,
+                    "--playground" => {
+                        let exe = env::current_exe().unwrap_or_else(|err| {
+                            eprintln!("Error: cannot locate the solo executable: {}", err);
+                            process::exit(1);
+                        });
+                        playground = Some(solo::playground::Playground::new(exe));
+                    }
+                    "--unstable" => unstable = true,
+                    "--listen" => {
+                        let Some(value) = rest.next() else {
+                            eprintln!("Error: --listen requires an address");
+                            process::exit(1);
+                        };
+                        address = Some(value);
+                    }
+                    "--timeout" => match rest.next().and_then(|v| v.parse::<f64>().ok()) {
+                        Some(secs) if secs > 0.0 => timeout = Some(Duration::from_secs_f64(secs)),
+                        _ => {
+                            eprintln!("Error: --timeout requires a positive number of seconds");
+                            process::exit(1);
+                        }
+                    },
+                    "--memory" => match rest.next().and_then(|v| v.parse::<u64>().ok()) {
+                        Some(mib) if mib > 0 => memory = Some(mib.saturating_mul(1024 * 1024)),
+                        _ => {
+                            eprintln!("Error: --memory requires a positive number of MiB");
+                            process::exit(1);
+                        }
+                    },
+                    other => {
+                        eprintln!("Error: Unknown option '{}'", other);
+                        process::exit(1);
+                    }
+                }
+            }
+            if api == playground.is_some() {
+                eprintln!("Error: Expected exactly one server mode");
+                eprintln!("Usage: solo serve --api | --playground [--listen <addr>]");
+                process::exit(1);
+            }
+            if api && (timeout.is_some() || memory.is_some()) {
+                eprintln!("Error: --timeout and --memory only apply to --playground");
+                process::exit(1);
+            }
+            let address = address.unwrap_or(if api {
+                solo::rpc::DEFAULT_ADDRESS
+            } else {
+                solo::playground::DEFAULT_ADDRESS
+            });
 
-            let filename = &args[2];
-            let source = fs::read_to_string(filename).unwrap_or_else(|err| {
-                eprintln!("Error reading file '{}': {}", filename, err);
+            // Requests are not authenticated, so never listen beyond this host
+            let listener = match address.parse::<std::net::SocketAddr>() {
+                Ok(addr) if addr.ip().is_loopback() => std::net::TcpListener::bind(addr),
+                Ok(_) => {
+                    eprintln!("Error: '{}' is not a loopback address", address);
+                    process::exit(1);
+                }
+                Err(err) => {
+                    eprintln!("Error: invalid address '{}': {}", address, err);
+                    process::exit(1);
+                }
+            };
+            let listener = listener.unwrap_or_else(|err| {
+                eprintln!("Error: cannot listen on {}: {}", address, err);
                 process::exit(1);
             });
+            let served = match playground {
+                Some(mut playground) => {
+                    playground.timeout = timeout.unwrap_or(playground.timeout);
+                    playground.memory = memory.or(playground.memory);
+                    playground.unstable = unstable;
+                    if let Ok(addr) = listener.local_addr() {
+                        eprintln!("Listening for playground requests on http://{}", addr);
+                    }
+                    solo::playground::serve(listener, playground)
+                }
+                None => {
+                    if let Ok(addr) = listener.local_addr() {
+                        eprintln!("Listening for JSON-RPC requests on {}", addr);
+                    }
+                    solo::rpc::serve(listener, unstable)
+                }
+            };
+            if let Err(err) = served {
+                eprintln!("Error: {}", err);
+                process::exit(1);
+            }
+        }
+        "lsp" => {
+            let mut unstable = false;
+            for arg in &args[2..] {
+                match arg.as_str() {
+                    "--unstable" => unstable = true,
+                    other => {
+                        eprintln!("Error: Unknown option '{}'", other);
+                        process::exit(1);
+                    }
+                }
+            }
+            let stdin = std::io::BufReader::new(std::io::stdin());
+            if let Err(err) = solo::lsp::serve(stdin, std::io::stdout().lock(), unstable) {
+                eprintln!("Error: {}", err);
+                process::exit(1);
+            }
+        }
+        "repl" => {
+            let mut unstable = false;
+            let mut files = Vec::new();
+            let mut options = solo::RunOptions {
+                beliefs: solo::BeliefFormat::Plot,
+                ..solo::RunOptions::default()
+            };
+            let mut rest = args[2..].iter().map(String::as_str);
+            while let Some(arg) = rest.next() {
+                match arg {
+                    "--unstable" => unstable = true,
+                    "--output" => options.beliefs = parse_belief_format(rest.next()),
+                    flag if flag.starts_with('-') => {
+                        eprintln!("Error: Unknown option '{}'", flag);
+                        process::exit(1);
+                    }
+                    file => files.push(PathBuf::from(file)),
+                }
+            }
+            repl(solo::repl::Session::new(options, unstable), &files);
+        }
+        "highlight" => {
+            let mut input = None;
+            let mut format = solo::highlight::Format::Ansi;
+            let mut tree_sitter = None;
+            let mut rest = args[2..].iter().map(String::as_str);
+            while let Some(arg) = rest.next() {
+                match arg {
+                    "--html" => format = solo::highlight::Format::Html,
+                    "--tree-sitter" => match rest.next() {
+                        Some(dir) => tree_sitter = Some(dir),
+                        None => {
+                            eprintln!("Error: --tree-sitter requires a directory");
+                            process::exit(1);
+                        }
+                    },
+                    flag if flag.starts_with("--") => {
+                        eprintln!("Error: Unknown option '{}'", flag);
+                        process::exit(1);
+                    }
+                    file => input = Some(file),
+                }
+            }
+            if let Some(dir) = tree_sitter {
+                if let Err(err) = solo::highlight::export_tree_sitter(Path::new(dir)) {
+                    eprintln!("Error: cannot write the grammar to '{}': {}", dir, err);
+                    process::exit(1);
+                }
+                println!("✓ Wrote the tree-sitter grammar to {}", dir);
+                return;
+            }
+            let Some(filename) = input else {
+                eprintln!("Error: Missing input file");
+                eprintln!("Usage: solo highlight <file.solo> [--html]");
+                process::exit(1);
+            };
+            let path = Path::new(filename);
+            let source = solo::read_source(path).unwrap_or_else(|err| {
+                report(err, path, "", false);
+            });
+            print!("{}", solo::highlight::highlight(&source, format));
+        }
+        "test" => {
+            let mut input = None;
+            let mut filter = None;
+            let mut unstable = false;
+            let mut timeout = solo::sandbox::DEFAULT_TIMEOUT;
+            let mut coverage_dir = None;
+            let mut seed = 0;
+            let mut random_seed = false;
+            let mut rest = args[2..].iter().map(String::as_str);
+            while let Some(arg) = rest.next() {
+                match arg {
+                    "--timeout" => match rest.next().and_then(|v| v.parse::<f64>().ok()) {
+                        Some(secs) if secs > 0.0 => timeout = Duration::from_secs_f64(secs),
+                        _ => {
+                            eprintln!("Error: --timeout requires a positive number of seconds");
+                            process::exit(1);
+                        }
+                    },
+                    "--unstable" => unstable = true,
+                    "--seed" => {
+                        let value = rest.next();
+                        random_seed = value == Some("random");
+                        seed = parse_seed(value);
+                    }
+                    "--coverage" => coverage_dir = Some(PathBuf::from("coverage")),
+                    flag if flag.starts_with("--coverage=") => {
+                        coverage_dir = Some(PathBuf::from(&flag["--coverage=".len()..]));
+                    }
+                    flag if flag.starts_with("--") => {
+                        eprintln!("Error: Unknown option '{}'", flag);
+                        process::exit(1);
+                    }
+                    file if input.is_none() => input = Some(file),
+                    pattern => filter = Some(pattern),
+                }
+            }
 
-            if let Err(err) = solo::compile(&source) {
-                eprintln!("Compilation error: {}", err);
+            let Some(filename) = input else {
+                eprintln!("Error: Missing input file");
+                eprintln!(
+                    "Usage: solo test <file.solo> [filter] [--timeout <secs>] [--seed <n|random>]"
+                );
+                eprintln!("                 [--coverage[=<dir>]]");
                 process::exit(1);
+            };
+            let path = Path::new(filename);
+            let source = read_program(path, None, false);
+            if !unstable {
+                require_stable(&source, path, false);
             }
+            let tests = solo::tests(&source).unwrap_or_else(|err| {
+                report(err, path, &source, false);
+            });
+            let exe = env::current_exe().unwrap_or_else(|err| {
+                eprintln!("Error: cannot locate the solo executable: {}", err);
+                process::exit(1);
+            });
 
-            println!("✓ Compilation successful");
+            let selected: Vec<&String> = tests
+                .iter()
+                .filter(|name| filter.is_none_or(|f| name.contains(f)))
+                .collect();
+            if let Some(dir) = &coverage_dir {
+                if let Err(err) = std::fs::create_dir_all(dir) {
+                    eprintln!("Error: cannot create '{}': {}", dir.display(), err);
+                    process::exit(1);
+                }
+            }
+            // One profraw file per test, none left over from an earlier run
+            let profraw = |name: &str| {
+                let file = profraw_path(coverage_dir.as_ref()?, name);
+                let _ = std::fs::remove_file(&file);
+                Some(file)
+            };
+            let plural = if selected.len() == 1 { "" } else { "s" };
+            if random_seed {
+                println!(
+                    "running {} test{} with seed {}",
+                    selected.len(),
+                    plural,
+                    seed
+                );
+            } else {
+                println!("running {} test{}", selected.len(), plural);
+            }
+            let mut failures = Vec::new();
+            for name in &selected {
+                let run = solo::sandbox::run_test(
+                    &exe,
+                    path,
+                    name,
+                    timeout,
+                    seed,
+                    profraw(name).as_deref(),
+                )
+                .unwrap_or_else(|err| {
+                    eprintln!("Error: cannot run test `{}`: {}", name, err);
+                    process::exit(1);
+                });
+                let status = match run.outcome {
+                    solo::sandbox::Outcome::Passed => "ok",
+                    solo::sandbox::Outcome::Failed(_) => "FAILED",
+                    solo::sandbox::Outcome::TimedOut => "TIMED OUT",
+                };
+                println!("test {} ... {} ({:.2?})", name, status, run.duration);
+                match run.outcome {
+                    solo::sandbox::Outcome::Passed => {}
+                    solo::sandbox::Outcome::TimedOut => failures.push((
+                        name,
+                        format!("{}killed after the {:?} timeout\n", run.output, timeout),
+                    )),
+                    solo::sandbox::Outcome::Failed(_) => failures.push((name, run.output)),
+                }
+            }
 
-            if command == "run" {
-                // TODO(#runtime): Execute compiled binary
-                // Tracking: See ROADMAP.adoc "Phase 1: Solo Compiler"
-                // Depends on: Code generation completion
-                println!("Execution not yet implemented (see ROADMAP.adoc)");
+            for (name, output) in &failures {
+                println!();
+                println!("---- {} output ----", name);
+                print!("{}", output);
+            }
+            println!();
+            println!(
+                "test result: {}. {} passed; {} failed; {} filtered out",
+                if failures.is_empty() { "ok" } else { "FAILED" },
+                selected.len() - failures.len(),
+                failures.len(),
+                tests.len() - selected.len()
+            );
+            if random_seed && !failures.is_empty() {
+                println!("note: rerun with `--seed {}` to reproduce", seed);
+            }
+            if let Some(dir) = &coverage_dir {
+                report_coverage(dir, path, &source, &selected);
+            }
+            if !failures.is_empty() {
+                process::exit(1);
+            }
+        }
+        solo::sandbox::RUN_TEST_COMMAND => {
+            let usage = || -> ! {
+                eprintln!(
+                    "Usage: solo {} <file.solo> <test> [--seed <n>] [--coverage <file>]",
+                    command
+                );
+                process::exit(1);
+            };
+            let [file, name, flags @ ..] = &args[2..] else {
+                usage();
+            };
+            let mut options = solo::RunOptions::default();
+            let mut profraw = None;
+            let mut rest = flags.iter().map(String::as_str);
+            while let Some(flag) = rest.next() {
+                match (flag, rest.next()) {
+                    ("--seed", Some(seed)) => options.seed = parse_seed(Some(seed)),
+                    ("--coverage", Some(file)) => profraw = Some(file),
+                    _ => usage(),
+                }
+            }
+            options.coverage = profraw.is_some();
+            let path = Path::new(file);
+            let source = read_program(path, None, false);
+            let (result, coverage) = solo::run_test_covered(&source, name, &options);
+            // Counts up to a panic are written too
+            if let (Some(file), Some(coverage)) = (profraw, coverage) {
+                if let Err(err) = std::fs::write(file, coverage.to_profraw()) {
+                    eprintln!("Error: cannot write '{}': {}", file, err);
+                    process::exit(1);
+                }
+            }
+            if let Err(err) = result {
+                report(err, path, &source, false);
+            }
+        }
+        solo::sandbox::RUN_PROGRAM_COMMAND => {
+            let mut unstable = false;
+            let mut stack = solo::interp::STACK_SIZE;
+            let mut rest = args[2..].iter().map(String::as_str);
+            while let Some(arg) = rest.next() {
+                match arg {
+                    "--unstable" => unstable = true,
+                    "--memory" => {
+                        let bytes = rest.next().and_then(|v| v.parse::<u64>().ok());
+                        let limited = bytes.map(solo::sandbox::limit_memory);
+                        if let Some(Err(err)) = limited {
+                            eprintln!("Error: cannot limit memory: {}", err);
+                            process::exit(1);
+                        }
+                        // The whole stack counts against the limit, used or
+                        // not, so it gets a quarter of it
+                        if let Some(bytes) = bytes {
+                            let quarter = usize::try_from(bytes / 4).unwrap_or(usize::MAX);
+                            stack = stack.min(quarter).max(solo::parser::STACK_SIZE);
+                        }
+                    }
+                    _ => {
+                        eprintln!(
+                            "Usage: solo {} [--memory <bytes>] [--unstable] < <file.solo>",
+                            command
+                        );
+                        process::exit(1);
+                    }
+                }
+            }
+            let mut source = String::new();
+            if let Err(err) = std::io::Read::read_to_string(&mut std::io::stdin(), &mut source) {
+                eprintln!("Error: cannot read the program: {}", err);
+                process::exit(1);
+            }
+            let file = solo::playground::FILE_NAME;
+            // Untrusted programs get no file system beyond their output,
+            // and no C functions that could reach it
+            let options = solo::RunOptions {
+                args: vec![file.to_string()],
+                files: false,
+                ffi: false,
+                stack,
+                ..Default::default()
+            };
+            let stable = if unstable {
+                Ok(())
+            } else {
+                solo::check_stable(&source)
+            };
+            on_stack(options.stack, || {
+                match stable.and_then(|()| solo::run(&source, &options)) {
+                    // Diagnostics go to stderr, keeping stdout for the program
+                    Err(err) => {
+                        for diagnostic in err.in_file(Path::new(file)).to_json(&source) {
+                            eprintln!("{}", diagnostic);
+                        }
+                        process::exit(1);
+                    }
+                    Ok(solo::interp::Value::Int(code)) => process::exit(code as i32),
+                    Ok(_) => {}
+                }
+            });
+        }
+        flag if flag == "--print" || flag.starts_with("--print=") => {
+            let mut rest = args[2..].iter().map(String::as_str);
+            let name = match flag.strip_prefix("--print=") {
+                Some(name) => Some(name),
+                None => rest.next(),
+            };
+            let Some(name) = name else {
+                eprintln!("Error: Missing print request");
+                eprintln!("Usage: solo --print <query> [--json] [--sysroot <dir>]");
+                process::exit(1);
+            };
+
+            let mut profile = Profile::default();
+            let mut options = solo::print::PrintOptions::default();
+            while let Some(arg) = rest.next() {
+                match arg {
+                    "--json" => options.json = true,
+                    "--sysroot" => match rest.next() {
+                        Some(dir) => options.sysroot = Some(dir.into()),
+                        None => {
+                            eprintln!("Error: --sysroot requires a directory");
+                            process::exit(1);
+                        }
+                    },
+                    flag if flag.starts_with("--sysroot=") => {
+                        options.sysroot = Some(flag["--sysroot=".len()..].into());
+                    }
+                    "--target" => options.target = Some(find_target(rest.next())),
+                    flag if profile.parse_flag(flag) => {}
+                    other => {
+                        eprintln!("Error: Unknown option '{}'", other);
+                        process::exit(1);
+                    }
+                }
+            }
+            options.overflow = profile.overflow();
+            options.panic = profile.panic;
+
+            let output = solo::print::Query::parse(name)
+                .and_then(|query| solo::print::render(query, &options));
+            match output {
+                Ok(output) => println!("{}", output),
+                Err(err) => {
+                    eprintln!("Error: {}", err);
+                    process::exit(1);
+                }
             }
         }
         _ => {
+            if let Some(program) = solo::plugin::find(command) {
+                match solo::plugin::run(&program, command, &args[2..]) {
+                    Ok(code) => process::exit(code),
+                    Err(err) => {
+                        eprintln!("Error: {}", err);
+                        process::exit(1);
+                    }
+                }
+            }
             eprintln!("Unknown command: {}", command);
             eprintln!("Run 'solo' without arguments for help");
             process::exit(1);
         }
     }
 }
+
+/// The target named by `--target`, or exit
+/// Read, evaluate and print inputs until end of input or `:quit`,
+/// reloading the loaded files that changed before each
+fn repl(mut session: solo::repl::Session, files: &[PathBuf]) {
+    use std::io::{BufRead, Write};
+
+    for file in files {
+        match session.load(file) {
+            Ok(output) => print!("{}", output),
+            Err(err) => eprintln!("{}", err),
+        }
+    }
+    eprintln!(
+        "Solo {} REPL; :help for commands",
+        env!("CARGO_PKG_VERSION")
+    );
+    let stdin = std::io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        eprint!("solo> ");
+        let _ = std::io::stderr().flush();
+        let Some(Ok(mut input)) = lines.next() else {
+            break;
+        };
+        // An input goes on until its brackets close
+        while !solo::repl::is_complete(&input) {
+            eprint!("  ... ");
+            let _ = std::io::stderr().flush();
+            match lines.next() {
+                Some(Ok(line)) => {
+                    input.push('\n');
+                    input.push_str(&line);
+                }
+                _ => break,
+            }
+        }
+        match session.reload(false) {
+            Ok(reloaded) => {
+                for path in reloaded {
+                    eprintln!("reloaded {}", path.display());
+                }
+            }
+            Err(err) => eprintln!("{}", err),
+        }
+
+        let input = input.trim();
+        let (command, argument) = input.split_once(char::is_whitespace).unwrap_or((input, ""));
+        let argument = argument.trim();
+        let result = match command {
+            ":quit" | ":q" => break,
+            ":help" | ":h" => {
+                eprintln!(":load <file>   Add the file's items to the session");
+                eprintln!(":reload        Read every loaded file again");
+                eprintln!(":save <file>   Write the session as a program, statements in main");
+                eprintln!(":quit          Leave; so does end of input");
+                eprintln!("Loaded files are also reloaded whenever they change.");
+                continue;
+            }
+            ":load" | ":l" if !argument.is_empty() => session.load(Path::new(argument)),
+            ":reload" | ":r" => session.reload(true).map(|reloaded| {
+                for path in reloaded {
+                    eprintln!("reloaded {}", path.display());
+                }
+                String::new()
+            }),
+            ":save" if !argument.is_empty() => {
+                match session.save(Path::new(argument)) {
+                    Ok(()) => eprintln!("saved the session to {}", argument),
+                    Err(err) => eprintln!("Error: cannot write '{}': {}", argument, err),
+                }
+                continue;
+            }
+            ":load" | ":l" | ":save" => {
+                eprintln!("Error: {} expects a file", command);
+                continue;
+            }
+            command if command.starts_with(':') => {
+                eprintln!("Error: Unknown command '{}'; :help lists them", input);
+                continue;
+            }
+            _ => session.eval(input),
+        };
+        match result {
+            Ok(output) => {
+                print!("{}", output);
+                let _ = std::io::stdout().flush();
+            }
+            Err(err) => eprintln!("{}", err),
+        }
+    }
+}
+
+fn find_target(triple: Option<&str>) -> solo::target::Target {
+    let Some(triple) = triple else {
+        eprintln!("Error: --target requires a target triple");
+        process::exit(1);
+    };
+    solo::target::Target::find(triple).unwrap_or_else(|| {
+        eprintln!(
+            "Error: unknown target '{}' (see `solo --print targets`)",
+            triple
+        );
+        process::exit(1);
+    })
+}
+
+/// The link stage of building `path`: its object file, the output named
+/// after it, and the libraries of the package and of `#[link]` attributes
+fn link_job(
+    path: &Path,
+    source: &str,
+    crate_type: solo::link::CrateType,
+    target: &solo::target::Target,
+    package: Option<&solo::package::Package>,
+) -> solo::link::Job {
+    let stem = path
+        .file_stem()
+        .map_or("main".into(), |s| s.to_string_lossy());
+    let mut args = package.map(|p| p.link_args().clone()).unwrap_or_default();
+    let program = solo::compile_until(source, solo::Phase::Parse)
+        .ok()
+        .and_then(solo::Artifact::into_ast);
+    for item in program.iter().flat_map(|program| &program.items) {
+        if let solo::ast::Item::Extern(block) = item {
+            let libraries = block.links().map(String::from).collect();
+            args.extend(&solo::link::LinkArgs {
+                search_paths: Vec::new(),
+                libraries,
+            });
+        }
+    }
+    let mut objects = vec![PathBuf::from(format!("{}.o", stem))];
+    if target.os == "wasi" && crate_type == solo::link::CrateType::Bin {
+        if let Some(sysroot) = env::var_os(solo::link::WASI_SYSROOT_VAR) {
+            let (start, lib) = solo::link::wasi_libc(Path::new(&sysroot));
+            objects.insert(0, start);
+            args.search_paths.push(lib);
+        }
+    }
+    solo::link::Job {
+        crate_type,
+        objects,
+        output: PathBuf::from(crate_type.file_name(&stem, target)),
+        args,
+    }
+}
+
+/// The package containing the current directory, or exit
+fn current_package() -> solo::package::Package {
+    env::current_dir()
+        .map_err(|err| err.to_string())
+        .and_then(|dir| solo::package::Package::discover(&dir))
+        .unwrap_or_else(|err| {
+            eprintln!("Error: {}", err);
+            process::exit(1);
+        })
+}
+
+/// Check every target of `package`, reporting all failures before exiting
+fn check_targets(package: &solo::package::Package, json: bool, unstable: bool, deny_any: bool) {
+    let targets = package.targets();
+    let mut failed = 0;
+    for target in &targets {
+        let result = match package.source(target) {
+            Err(err) => Err((err, String::new())),
+            Ok(unit) => {
+                let checked = if unstable {
+                    Ok(())
+                } else {
+                    solo::check_stable(&unit.text)
+                };
+                checked
+                    .and_then(|()| {
+                        if deny_any {
+                            solo::deny_any(&unit.text)
+                        } else {
+                            Ok(())
+                        }
+                    })
+                    .and_then(|()| solo::compile(&unit.text))
+                    .map_err(|err| (unit.attribute(err), unit.text))
+            }
+        };
+        if let Err((err, source)) = result {
+            print_error(err, &target.path, &source, json);
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        eprintln!(
+            "Error: {} of {} target(s) of `{}` failed to check",
+            failed,
+            targets.len(),
+            package.name()
+        );
+        process::exit(1);
+    }
+    if !json {
+        println!(
+            "✓ Checked {} target(s) of `{}`",
+            targets.len(),
+            package.name()
+        );
+    }
+}
+
+/// Without `--unstable`, fail on `#![feature]` before reporting anything else
+/// The value of `--seed`: an integer, or `random` for a fresh one
+fn parse_seed(value: Option<&str>) -> u64 {
+    match value {
+        Some("random") => solo::belief::random_seed(),
+        Some(value) => value.parse().unwrap_or_else(|_| {
+            eprintln!("Error: --seed must be an integer or random");
+            process::exit(1);
+        }),
+        None => {
+            eprintln!("Error: --seed requires an integer or random");
+            process::exit(1);
+        }
+    }
+}
+
+/// Parse the value of `--output`
+fn parse_belief_format(value: Option<&str>) -> solo::BeliefFormat {
+    match value.and_then(solo::BeliefFormat::parse) {
+        Some(format) => format,
+        None => {
+            eprintln!("Error: --output requires literal, plot or json");
+            process::exit(1);
+        }
+    }
+}
+
+fn parse_dialect(name: Option<&str>) -> solo::legacy::Dialect {
+    name.and_then(solo::legacy::Dialect::parse)
+        .unwrap_or_else(|| {
+            eprintln!("Error: --dialect must be solo or legacy");
+            process::exit(1);
+        })
+}
+
+/// Solo source of the file at `path`, lowered from Me when `dialect` (or,
+/// without one, the file's extension) says it is legacy code
+fn read_program(path: &Path, dialect: Option<solo::legacy::Dialect>, json: bool) -> String {
+    let source = solo::read_source(path).unwrap_or_else(|err| {
+        report(err, path, "", json);
+    });
+    let dialect = dialect.unwrap_or_else(|| solo::legacy::Dialect::for_path(path));
+    let source = dialect.to_solo(&source).unwrap_or_else(|err| {
+        report(err, path, &source, json);
+    });
+    solo::ice::set_input(path, &source);
+    source
+}
+
+fn require_stable(source: &str, path: &Path, json: bool) {
+    if let Err(err) = solo::check_stable(source) {
+        report(err, path, source, json);
+    }
+}
+
+/// Print a failure (one JSON object per diagnostic with `json`) and exit
+/// Run `f` on a thread with `size` bytes of stack, so an interpreted
+/// program may recurse as deep as [`solo::RunOptions::stack`] lets it
+fn on_stack(size: usize, f: impl FnOnce() + Send) {
+    std::thread::scope(|scope| {
+        let thread = std::thread::Builder::new()
+            .stack_size(size)
+            .spawn_scoped(scope, f)
+            .unwrap_or_else(|err| {
+                eprintln!("Error: cannot start the interpreter: {}", err);
+                process::exit(1);
+            });
+        // A panic is an internal compiler error, reported by `main`
+        if let Err(panic) = thread.join() {
+            std::panic::resume_unwind(panic);
+        }
+    });
+}
+
+fn report(err: solo::CompileError, path: &Path, source: &str, json: bool) -> ! {
+    print_error(err, path, source, json);
+    process::exit(1);
+}
+
+/// Add the counts of a run to the profile in `file`, replacing one
+/// recorded for another version of the program
+fn record_profile(file: &str, mut counts: solo::coverage::Coverage) {
+    if let Ok(earlier) = std::fs::read(file) {
+        if let Err(err) = counts.merge_profraw(&earlier) {
+            eprintln!("warning: replacing the profile '{}': {}", file, err);
+        }
+    }
+    if let Err(err) = std::fs::write(file, counts.to_profraw()) {
+        eprintln!("Error: cannot write '{}': {}", file, err);
+        process::exit(1);
+    }
+}
+
+fn profraw_path(dir: &Path, test: &str) -> PathBuf {
+    dir.join(format!("{}.profraw", test.replace("::", "-")))
+}
+
+/// Merge the profraw files the tests wrote into `dir` and write the report
+/// next to them, as `lcov.info` and `index.html`
+fn report_coverage(dir: &Path, path: &Path, source: &str, tests: &[&String]) {
+    let mut coverage = solo::instrument(source).unwrap_or_else(|err| {
+        report(err, path, source, false);
+    });
+    for test in tests {
+        let file = profraw_path(dir, test);
+        // A test killed by its timeout wrote nothing
+        let Ok(data) = std::fs::read(&file) else {
+            continue;
+        };
+        if let Err(err) = coverage.merge_profraw(&data) {
+            eprintln!("Error: cannot read '{}': {}", file.display(), err);
+            process::exit(1);
+        }
+    }
+
+    let name = path.display().to_string();
+    let lcov = dir.join("lcov.info");
+    let html = dir.join("index.html");
+    let outputs = [
+        (&lcov, coverage.to_lcov(&name)),
+        (&html, coverage.to_html(&name, source)),
+    ];
+    for (file, contents) in outputs {
+        if let Err(err) = std::fs::write(file, contents) {
+            eprintln!("Error: cannot write '{}': {}", file.display(), err);
+            process::exit(1);
+        }
+    }
+    println!("coverage: {}", coverage.totals());
+    println!("wrote {} and {}", lcov.display(), html.display());
+}
+
+/// Print a failure (one JSON object per diagnostic with `json`)
+fn print_error(err: solo::CompileError, path: &Path, source: &str, json: bool) {
+    let err = err.in_file(path);
+    if json {
+        for diagnostic in err.to_json(source) {
+            println!("{}", diagnostic);
+        }
+    } else if matches!(
+        err,
+        solo::CompileError::Io { .. }
+            | solo::CompileError::Runtime(_)
+            | solo::CompileError::LimitExceeded(_)
+    ) {
+        eprintln!("{}", err);
+    } else {
+        eprintln!("Compilation error: {}", err);
+    }
+}
+
+/// Build profile selected on the command line
+struct Profile {
+    release: bool,
+    overflow_checks: Option<bool>,
+    panic: solo::PanicStrategy,
+    sanitizers: solo::Sanitizers,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self {
+            release: false,
+            overflow_checks: None,
+            panic: solo::PanicStrategy::Unwind,
+            sanitizers: solo::Sanitizers::default(),
+        }
+    }
+}
+
+impl Profile {
+    /// Record a profile flag; returns false if `arg` is not one
+    fn parse_flag(&mut self, arg: &str) -> bool {
+        match arg {
+            "--release" => self.release = true,
+            "--overflow-checks" | "--overflow-checks=on" => self.overflow_checks = Some(true),
+            "--overflow-checks=off" => self.overflow_checks = Some(false),
+            _ if arg.starts_with("--sanitize=") => {
+                let list = &arg["--sanitize=".len()..];
+                self.sanitizers = solo::Sanitizers::parse(list).unwrap_or_else(|msg| {
+                    eprintln!("Error: --sanitize: {}", msg);
+                    process::exit(1);
+                })
+            }
+            _ => match arg.strip_prefix("--panic=") {
+                Some(name) => {
+                    self.panic = solo::PanicStrategy::parse(name).unwrap_or_else(|| {
+                        eprintln!("Error: --panic must be `unwind` or `abort`, not `{}`", name);
+                        process::exit(1);
+                    })
+                }
+                None => return false,
+            },
+        }
+        true
+    }
+
+    fn overflow(&self) -> solo::OverflowMode {
+        match self.overflow_checks {
+            Some(true) => solo::OverflowMode::Trap,
+            Some(false) => solo::OverflowMode::Wrap,
+            None => solo::OverflowMode::for_profile(self.release),
+        }
+    }
+}
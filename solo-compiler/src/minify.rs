@@ -0,0 +1,319 @@
+//! Minification of Solo source code
+//!
+//! `solo minify` re-emits a program in as few bytes as it can without
+//! changing what it does: comments are dropped, whitespace is kept only
+//! where two tokens would otherwise run together, and identifiers are
+//! renamed to the shortest free names, the most frequent first.
+//!
+//! Names seen from outside the file keep their spelling: `main` and
+//! `#[test]` functions, which `solo run` and `solo test` look up, functions
+//! of `extern` blocks and `pub extern` functions, which are FFI symbols,
+//! structs with `#[derive(...)]` and their fields, whose record schemas name
+//! them, and everything declared `pub`, which a package's library offers
+//! its targets. These make up the whole interface of a file. Names
+//! the compiler knows (builtins, primitive types, `self`) are kept too, and
+//! attributes are copied unchanged. Printing a struct or enum value shows
+//! the new names.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::ast::{Item, Program};
+use crate::error::CompileError;
+use crate::lexer::Lexer;
+use crate::stdlib::{atomic, simd};
+use crate::symbol::Symbol;
+use crate::token::{Token, TokenKind};
+use crate::{builtins, feature, layout, unicode, Phase};
+
+/// Names the compiler gives a meaning to besides builtin functions and
+/// primitive types
+const RESERVED: &[&str] = &[
+    "main",
+    "self",
+    "Self",
+    // `pub(crate)`
+    "crate",
+    "Arena",
+    "Belief",
+    "Vec",
+    "Map",
+    "StringBuf",
+    "alloc",
+    "checkpoint",
+    "reset_to",
+    "Option",
+    "Some",
+    "None",
+    // The wildcard pattern
+    "_",
+    // The iterator protocol and the adapters of `for` loops
+    "has_next",
+    "next",
+    "map",
+    "filter",
+    "take",
+    // The allocator protocol of `arena in buffer { ... }`
+    "allocate",
+    "reset",
+    // Properties of refinement bounds
+    "confidence",
+    "std_dev",
+    "interval",
+    // `thread::spawn`, thread handles and channels
+    "thread",
+    "spawn",
+    "Thread",
+    "join",
+    "Sender",
+    "Receiver",
+    // Atomics: `atomic::fence` and the orderings
+    "atomic",
+    "fence",
+    "Ordering",
+    "Relaxed",
+    "Release",
+    "Acquire",
+    "AcqRel",
+    "SeqCst",
+    // Locks and their guards
+    "Mutex",
+    "RwLock",
+    "MutexGuard",
+    "RwLockReadGuard",
+    "RwLockWriteGuard",
+    // Raw pointers
+    "ptr",
+    "from_ref",
+    "from_mut",
+    // Inline assembly: the macro, operand directions and register classes
+    "asm",
+    "out",
+    "inout",
+    "reg",
+    "freg",
+    // Macro fragment specifiers
+    "expr",
+    "ident",
+    "literal",
+    "tt",
+];
+
+/// Minify Solo source code, which must parse
+pub fn minify(source: &str) -> Result<String, CompileError> {
+    let tokens = crate::compile_until(source, Phase::Lex)?
+        .into_tokens()
+        .expect("lexing produces tokens");
+    let program = crate::compile_until(source, Phase::Parse)?
+        .into_ast()
+        .expect("parsing produces a program");
+
+    let kept = kept_names(&program, &tokens);
+    let names = short_names(&tokens, &kept);
+
+    let mut out = String::new();
+    let mut previous: Option<&str> = None;
+    for (token, in_attribute) in tokens.iter().zip(in_attributes(&tokens)) {
+        let text = match &token.kind {
+            TokenKind::Identifier(name) if !in_attribute => {
+                names.get(name).map_or(name.as_str(), String::as_str)
+            }
+            _ => token.lexeme.as_str(),
+        };
+        if previous.is_some_and(|previous| needs_space(previous, text)) {
+            out.push(' ');
+        }
+        out.push_str(text);
+        previous = Some(text);
+    }
+    Ok(out)
+}
+
+/// Names that must keep their spelling
+fn kept_names(program: &Program, tokens: &[Token]) -> HashSet<Symbol> {
+    let mut kept = HashSet::new();
+    for item in &program.items {
+        match item {
+            Item::Function(function) if function.is_test() || function.vis.is_public() => {
+                kept.insert(function.name);
+            }
+            Item::Extern(block) => {
+                kept.extend(block.functions.iter().map(|f| f.name));
+            }
+            Item::Struct(def) if def.derives().next().is_some() => {
+                kept.insert(def.name);
+                kept.extend(def.fields.iter().map(|f| f.name));
+            }
+            Item::Struct(def) => {
+                if def.vis.is_public() {
+                    kept.insert(def.name);
+                }
+                let public = def.fields.iter().filter(|f| f.vis.is_public());
+                kept.extend(public.map(|f| f.name));
+            }
+            Item::Enum(def) if def.vis.is_public() => {
+                kept.insert(def.name);
+                kept.extend(def.variants.iter().map(|v| v.name));
+            }
+            Item::Const(item) if item.vis.is_public() => {
+                kept.insert(item.name);
+            }
+            Item::TypeAlias(alias) if alias.vis.is_public() => {
+                kept.insert(alias.name);
+            }
+            Item::Impl(block) => {
+                let public = block.methods.iter().filter(|m| m.vis.is_public());
+                kept.extend(public.map(|m| m.name));
+            }
+            _ => {}
+        }
+    }
+    // Segments of qualified builtins such as `env::arg`
+    for window in tokens.windows(3) {
+        if let [TokenKind::Identifier(a), TokenKind::DoubleColon, TokenKind::Identifier(b)] =
+            [&window[0].kind, &window[1].kind, &window[2].kind]
+        {
+            if builtins::signature(&format!("{}::{}", a, b)).is_some() {
+                kept.insert(*a);
+                kept.insert(*b);
+            }
+        }
+    }
+    kept
+}
+
+fn is_reserved(name: &str) -> bool {
+    RESERVED.contains(&name)
+        || builtins::signature(name).is_some()
+        || builtins::distribution(name).is_some()
+        || builtins::is_belief_op(name)
+        || builtins::is_int_op(name)
+        || builtins::COLLECTION_METHODS.contains(&name)
+        || layout::primitive(name).is_some()
+        || simd::vector_type(name).is_some()
+        || atomic::atomic_type(name).is_some()
+}
+
+/// New names for the identifiers of `tokens` outside attributes, shortest
+/// for the most frequent
+fn short_names(tokens: &[Token], kept: &HashSet<Symbol>) -> HashMap<Symbol, String> {
+    let mut counts: HashMap<Symbol, (usize, usize)> = HashMap::new();
+    for (i, (token, in_attribute)) in tokens.iter().zip(in_attributes(tokens)).enumerate() {
+        if let TokenKind::Identifier(name) = &token.kind {
+            if !in_attribute {
+                let count = counts.entry(*name).or_insert((0, i));
+                count.0 += 1;
+            }
+        }
+    }
+    let mut renamed: Vec<(Symbol, (usize, usize))> = counts
+        .into_iter()
+        .filter(|&(name, _)| !kept.contains(&name) && !is_reserved(&name))
+        .collect();
+    renamed.sort_by_key(|&(_, (count, first))| (std::cmp::Reverse(count), first));
+
+    let mut candidates = (1..).map(nth_name).filter(|name| {
+        Token::is_keyword(name).is_none()
+            && feature::planned_syntax(name).is_none()
+            && !kept.contains(&Symbol::intern(name))
+            && !is_reserved(name)
+    });
+    renamed
+        .into_iter()
+        .map(|(name, _)| (name, candidates.next().expect("names never run out")))
+        .collect()
+}
+
+/// Whether each token is part of an attribute, `#[...]` or `#![...]`
+fn in_attributes(tokens: &[Token]) -> Vec<bool> {
+    let mut opened = false;
+    let mut depth = 0;
+    let mut mask = Vec::with_capacity(tokens.len());
+    for token in tokens {
+        mask.push(opened || depth > 0 || token.kind == TokenKind::Pound);
+        match token.kind {
+            TokenKind::Pound => opened = true,
+            TokenKind::LBracket if opened || depth > 0 => {
+                opened = false;
+                depth += 1;
+            }
+            TokenKind::RBracket if depth > 0 => depth -= 1,
+            _ => {}
+        }
+    }
+    mask
+}
+
+/// The `n`th name of `a`, `b`, ..., `z`, `aa`, `ab`, ...
+fn nth_name(mut n: usize) -> String {
+    let mut name = Vec::new();
+    while n > 0 {
+        n -= 1;
+        name.push(b'a' + (n % 26) as u8);
+        n /= 26;
+    }
+    name.reverse();
+    String::from_utf8(name).expect("names are ASCII")
+}
+
+/// Whether `left` and `right` would lex differently without a space
+/// between them
+pub fn needs_space(left: &str, right: &str) -> bool {
+    let word = |c: Option<char>| c.is_some_and(unicode::is_ident_continue);
+    if word(left.chars().last()) && word(right.chars().next()) {
+        return true;
+    }
+    let joined = format!("{}{}", left, right);
+    let mut lexer = Lexer::new(&joined);
+    lexer.next_token().lexeme != left || lexer.next_token().lexeme != right
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_minify() {
+        let source = r#"
+            // Totals of a `Point`
+            struct Point { x: i64, y: i64 }
+
+            #[derive(Csv)]
+            struct Row { total: i64 }
+
+            fn total(point: Point) -> i64 {
+                /* both coordinates */
+                let sum = point.x + point.y;
+                sum - -1
+            }
+
+            #[test]
+            fn test_total() {
+                let point = Point { x: 1, y: 2 };
+                print(total(point));
+            }
+
+            fn main() -> i64 {
+                let point = Point { x: 3, y: 4 };
+                let row = Row { total: total(point) };
+                print("a // b");
+                print(env::args_len());
+                row.total
+            }
+        "#;
+        let minified = minify(source).unwrap();
+        assert_eq!(
+            minified,
+            "struct b{c:i64,d:i64}#[derive(Csv)]struct Row{total:i64}\
+             fn total(a:b)->i64{let e=a.c+a.d;e--1}\
+             #[test]fn test_total(){let a=b{c:1,d:2};print(total(a));}\
+             fn main()->i64{let a=b{c:3,d:4};let f=Row{total:total(a)};\
+             print(\"a // b\");print(env::args_len());f.total}"
+        );
+        assert!(crate::check(&minified).is_ok());
+        assert_eq!(minify(&minified).unwrap(), minified);
+
+        assert_eq!(nth_name(1), "a");
+        assert_eq!(nth_name(27), "aa");
+        assert!(minify("fn main() {").is_err());
+    }
+}
@@ -0,0 +1,336 @@
+//! Reduction of programs to minimal reproducers
+//!
+//! `solo minimize` shrinks a program while a predicate still holds of it,
+//! for a bug report that shows only what matters: the compiler still
+//! crashes at the same place (`--ice`), still reports an error with a given
+//! code or text (`--error E0001`), or a command still succeeds on it
+//! (`--command`, as for C-Reduce). It runs delta debugging (Zeller's
+//! ddmin) over the lines of the program and then over its tokens, and again
+//! until neither removes anything. A line or token goes only if the
+//! program without it keeps the predicate, so the result is 1-minimal:
+//! removing any one more unit loses it.
+
+use std::fmt;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::error::CompileError;
+use crate::ice;
+use crate::lexer::Lexer;
+use crate::minify::needs_space;
+use crate::sandbox::TempDir;
+use crate::token::TokenKind;
+
+/// What a reduced program must keep doing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Predicate {
+    /// Compiling it panics where compiling the original does
+    Ice,
+    /// Compiling it fails with a diagnostic of this code, or an error whose
+    /// message contains this text
+    Error(String),
+    /// The program and arguments exit successfully when given the path of
+    /// a file holding it, named as the original
+    Command(Vec<String>),
+}
+
+/// A program reduced by [`minimize`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Minimized {
+    pub source: String,
+    /// Candidates the predicate was tested on
+    pub tests: usize,
+}
+
+/// The original program does not have the property to keep
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Uninteresting(pub Predicate);
+
+impl fmt::Display for Uninteresting {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.0 {
+            Predicate::Ice => write!(f, "compiling the program does not crash the compiler"),
+            Predicate::Error(text) => {
+                write!(
+                    f,
+                    "compiling the program reports no error matching \"{}\"",
+                    text
+                )
+            }
+            Predicate::Command(command) => {
+                write!(f, "`{}` fails on the program", command.join(" "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for Uninteresting {}
+
+/// The smallest program the reduction finds for which `predicate` holds as
+/// it does of `source`
+///
+/// A [`Command`](Predicate::Command) is run on files in a fresh temporary
+/// directory, under the name of `path`; for [`Ice`](Predicate::Ice) the
+/// panic location is only known when [`ice::install`] has been called, and
+/// without it any panic will do.
+pub fn minimize(source: &str, predicate: &Predicate) -> Result<Minimized, Uninteresting> {
+    minimize_file(source, Path::new("input.solo"), predicate)
+}
+
+/// Like [`minimize`], naming the candidate files as `path`
+pub fn minimize_file(
+    source: &str,
+    path: &Path,
+    predicate: &Predicate,
+) -> Result<Minimized, Uninteresting> {
+    let uninteresting = || Uninteresting(predicate.clone());
+    let mut test = Test::new(source, path, predicate).ok_or_else(uninteresting)?;
+    let mut source = source.to_string();
+    loop {
+        let before = source.len();
+        let lines: Vec<&str> = source.lines().collect();
+        let kept = ddmin(lines, |lines| test.holds(&render_lines(lines)));
+        source = render_lines(&kept);
+        if let Some(tokens) = tokens(&source) {
+            let kept = ddmin(tokens, |tokens| test.holds(&render_tokens(tokens)));
+            source = render_tokens(&kept);
+        }
+        if source.len() >= before {
+            break;
+        }
+    }
+    Ok(Minimized {
+        source,
+        tests: test.count,
+    })
+}
+
+/// A predicate ready to test candidates against
+struct Test<'a> {
+    predicate: &'a Predicate,
+    /// Where compiling the original panicked
+    location: Option<String>,
+    /// Where candidates are written for a command
+    dir: Option<TempDir>,
+    path: &'a Path,
+    count: usize,
+}
+
+impl<'a> Test<'a> {
+    /// `None` if the predicate does not hold of `source`
+    fn new(source: &str, path: &'a Path, predicate: &'a Predicate) -> Option<Self> {
+        let location = match predicate {
+            Predicate::Ice => ice::catch(|| crate::compile(source)).err()?.location,
+            _ => None,
+        };
+        let dir = match predicate {
+            Predicate::Command(_) => Some(TempDir::new("minimize").ok()?),
+            _ => None,
+        };
+        let mut test = Test {
+            predicate,
+            location,
+            dir,
+            path,
+            count: 0,
+        };
+        test.holds(source).then_some(test)
+    }
+
+    fn holds(&mut self, candidate: &str) -> bool {
+        self.count += 1;
+        match self.predicate {
+            Predicate::Ice => match ice::catch(|| crate::compile(candidate)) {
+                Ok(_) => false,
+                Err(report) => report.location == self.location,
+            },
+            Predicate::Error(text) => match ice::catch(|| crate::compile(candidate)) {
+                Ok(Err(err)) => reports(&err, text),
+                _ => false,
+            },
+            Predicate::Command(command) => {
+                let Some((program, args)) = command.split_first() else {
+                    return false;
+                };
+                let dir = self.dir.as_ref().expect("a directory for commands");
+                let name = self.path.file_name().unwrap_or("input.solo".as_ref());
+                let file = dir.path.join(name);
+                std::fs::write(&file, candidate).is_ok()
+                    && Command::new(program)
+                        .args(args)
+                        .arg(&file)
+                        .stdin(Stdio::null())
+                        .stdout(Stdio::null())
+                        .stderr(Stdio::null())
+                        .status()
+                        .is_ok_and(|status| status.success())
+            }
+        }
+    }
+}
+
+/// Whether `err` is an error of code `text`, or mentions it
+fn reports(err: &CompileError, text: &str) -> bool {
+    err.diagnostics()
+        .iter()
+        .any(|d| d.code.is_some_and(|code| code.as_str() == text))
+        || err.to_string().contains(text)
+}
+
+/// Zeller's ddmin: a subsequence of `units` for which `holds` is true, with
+/// no single unit removable; `holds(&units)` is assumed
+pub fn ddmin<T: Clone>(mut units: Vec<T>, mut holds: impl FnMut(&[T]) -> bool) -> Vec<T> {
+    let mut parts = 2;
+    while units.len() >= 2 {
+        let size = units.len().div_ceil(parts);
+        let chunks: Vec<_> = (0..units.len())
+            .step_by(size)
+            .map(|start| start..(start + size).min(units.len()))
+            .collect();
+        // A part alone, then everything but a part; of two parts, each is
+        // what the other leaves, so only the second kind is tried
+        let reduced = chunks
+            .iter()
+            .map(|chunk| (units[chunk.clone()].to_vec(), 2))
+            .chain(chunks.iter().map(|chunk| {
+                let mut rest = units[..chunk.start].to_vec();
+                rest.extend_from_slice(&units[chunk.end..]);
+                (rest, (parts - 1).max(2))
+            }))
+            .skip(if parts == 2 { 2 } else { 0 })
+            .find(|(candidate, _)| holds(candidate));
+        match reduced {
+            Some((candidate, next)) => {
+                units = candidate;
+                parts = next;
+            }
+            None if parts >= units.len() => break,
+            None => parts = (parts * 2).min(units.len()),
+        }
+    }
+    if units.len() == 1 && holds(&[]) {
+        units.clear();
+    }
+    units
+}
+
+fn render_lines(lines: &[&str]) -> String {
+    lines.iter().map(|line| format!("{}\n", line)).collect()
+}
+
+/// A token on its line
+#[derive(Debug, Clone, Copy)]
+struct Unit<'a> {
+    line: usize,
+    /// The indentation of the line
+    indent: &'a str,
+    text: &'a str,
+    /// Whether whitespace came before it on the line
+    spaced: bool,
+}
+
+/// The tokens of `source` by line, or `None` if it does not lex
+fn tokens(source: &str) -> Option<Vec<Unit<'_>>> {
+    let lines: Vec<&str> = source.lines().collect();
+    let mut lexer = Lexer::new(source);
+    let mut units: Vec<Unit> = Vec::new();
+    let mut end = 0;
+    loop {
+        let token = lexer.next_token();
+        match token.kind {
+            TokenKind::Eof => return Some(units),
+            TokenKind::Error(_) => return None,
+            _ => {
+                let line = lines.get(token.line.checked_sub(1)?)?;
+                let text = token.lexeme.as_str();
+                let same_line = units.last().is_some_and(|last| last.line == token.line);
+                units.push(Unit {
+                    line: token.line,
+                    indent: &line[..line.len() - line.trim_start().len()],
+                    text,
+                    spaced: same_line && token.column > end,
+                });
+                end = token.column + text.chars().count();
+            }
+        }
+    }
+}
+
+/// The tokens on their lines, each line indented as it was and each token
+/// spaced from the one before as it was, or as it must be
+fn render_tokens(tokens: &[Unit<'_>]) -> String {
+    let mut out = String::new();
+    let mut last: Option<(usize, &str)> = None;
+    for &Unit {
+        line,
+        indent,
+        text,
+        spaced,
+    } in tokens
+    {
+        match last {
+            Some((previous, left)) if previous == line => {
+                if spaced || needs_space(left, text) {
+                    out.push(' ');
+                }
+            }
+            Some(_) => {
+                out.push('\n');
+                out.push_str(indent);
+            }
+            None => out.push_str(indent),
+        }
+        out.push_str(text);
+        last = Some((line, text));
+    }
+    if last.is_some() {
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ddmin() {
+        // Holds while 3 and 7 are both kept
+        let mut tests = 0;
+        let kept = ddmin((0..16).collect(), |units: &[i32]| {
+            tests += 1;
+            units.contains(&3) && units.contains(&7)
+        });
+        assert_eq!(kept, [3, 7]);
+        assert!(tests < 64, "{} tests", tests);
+        assert_eq!(ddmin(vec![1, 2], |_: &[i32]| true), Vec::<i32>::new());
+    }
+
+    #[test]
+    fn test_minimize_error() {
+        let source = "// unrelated
+fn helper(x: i64) -> i64 {
+    x * 2
+}
+
+fn main() {
+    let a = 1;
+    let b: bool = helper(a);
+    print(a);
+}
+";
+        let predicate = Predicate::Error("expected `bool`".to_string());
+        let minimized = minimize(source, &predicate).unwrap();
+        assert_eq!(
+            minimized.source,
+            "fn helper() {\n}\nfn main() {\n    let b: bool = helper;\n}\n"
+        );
+        assert!(minimized.tests > 0);
+
+        let err = minimize("fn main() {}", &predicate).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "compiling the program reports no error matching \"expected `bool`\""
+        );
+    }
+}
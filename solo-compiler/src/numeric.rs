@@ -0,0 +1,181 @@
+//! Sized numeric types and `as` casts
+//!
+//! `i64` and `f64` are the default integer and float types: an unsuffixed
+//! literal has one of them unless it is written where another numeric type
+//! is expected, and the standard library takes and returns them. The other
+//! widths (`i8` to `i32`, `u8` to `u64` and `f32`) are distinct types that
+//! mix with no other in arithmetic and convert only with `as`. `isize` and
+//! `usize` are `i64`, the type of lengths and indices.
+//!
+//! The interpreter holds every integer in an `i64` and every float in an
+//! `f64`. The result of arithmetic on a narrower type is brought back into
+//! its range, trapping or wrapping as overflow of `i64` does, and an `f32`
+//! result is rounded to single precision. Until native code generation
+//! lands, `u64` holds `0..=i64::MAX`: it is 63 bits wide.
+
+use std::fmt;
+
+/// A numeric type, by width
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Numeric {
+    I8,
+    I16,
+    I32,
+    I64,
+    U8,
+    U16,
+    U32,
+    U64,
+    F32,
+    F64,
+}
+
+/// A number to cast
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scalar {
+    Int(i64),
+    Float(f64),
+}
+
+impl Numeric {
+    /// The type a name or literal suffix stands for
+    pub fn parse(name: &str) -> Option<Numeric> {
+        Some(match name {
+            "i8" => Numeric::I8,
+            "i16" => Numeric::I16,
+            "i32" => Numeric::I32,
+            "i64" | "isize" | "usize" => Numeric::I64,
+            "u8" => Numeric::U8,
+            "u16" => Numeric::U16,
+            "u32" => Numeric::U32,
+            "u64" => Numeric::U64,
+            "f32" => Numeric::F32,
+            "f64" => Numeric::F64,
+            _ => return None,
+        })
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Numeric::I8 => "i8",
+            Numeric::I16 => "i16",
+            Numeric::I32 => "i32",
+            Numeric::I64 => "i64",
+            Numeric::U8 => "u8",
+            Numeric::U16 => "u16",
+            Numeric::U32 => "u32",
+            Numeric::U64 => "u64",
+            Numeric::F32 => "f32",
+            Numeric::F64 => "f64",
+        }
+    }
+
+    pub fn is_float(self) -> bool {
+        matches!(self, Numeric::F32 | Numeric::F64)
+    }
+
+    pub fn is_signed(self) -> bool {
+        matches!(
+            self,
+            Numeric::I8 | Numeric::I16 | Numeric::I32 | Numeric::I64
+        )
+    }
+
+    /// Bits an integer of this type holds in the interpreter
+    fn bits(self) -> u32 {
+        match self {
+            Numeric::I8 | Numeric::U8 => 8,
+            Numeric::I16 | Numeric::U16 => 16,
+            Numeric::I32 | Numeric::U32 | Numeric::F32 => 32,
+            Numeric::U64 => 63,
+            Numeric::I64 | Numeric::F64 => 64,
+        }
+    }
+
+    /// Smallest and largest value of an integer type
+    pub fn range(self) -> (i64, i64) {
+        let bits = self.bits();
+        match self {
+            Numeric::I64 | Numeric::F32 | Numeric::F64 => (i64::MIN, i64::MAX),
+            Numeric::U64 => (0, i64::MAX),
+            _ if self.is_signed() => (-1 << (bits - 1), (1 << (bits - 1)) - 1),
+            _ => (0, (1 << bits) - 1),
+        }
+    }
+
+    /// Whether an integer type holds `value`
+    pub fn contains(self, value: i128) -> bool {
+        let (min, max) = self.range();
+        (i128::from(min)..=i128::from(max)).contains(&value)
+    }
+
+    /// `value` truncated to the width of an integer type, as two's
+    /// complement
+    pub fn wrap(self, value: i128) -> i64 {
+        let modulus = 1i128 << self.bits();
+        let value = value.rem_euclid(modulus);
+        if self.is_signed() && value >= modulus / 2 {
+            (value - modulus) as i64
+        } else {
+            value as i64
+        }
+    }
+
+    /// `value` rounded to the precision of a float type
+    pub fn round(self, value: f64) -> f64 {
+        match self {
+            Numeric::F32 => value as f32 as f64,
+            _ => value,
+        }
+    }
+
+    /// `value as self`: integers are truncated to the width of an integer
+    /// type, floats are rounded towards zero and saturate at its bounds
+    /// (`NaN` becomes 0), and a float type rounds to its nearest value
+    pub fn cast(self, value: Scalar) -> Scalar {
+        match (value, self.is_float()) {
+            (Scalar::Int(n), false) => Scalar::Int(self.wrap(n.into())),
+            (Scalar::Float(x), false) => {
+                let (min, max) = self.range();
+                Scalar::Int((x as i64).clamp(min, max))
+            }
+            (Scalar::Int(n), true) => Scalar::Float(self.round(n as f64)),
+            (Scalar::Float(x), true) => Scalar::Float(self.round(x)),
+        }
+    }
+}
+
+impl fmt::Display for Numeric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_casts() {
+        assert_eq!(Numeric::I8.range(), (-128, 127));
+        assert_eq!(Numeric::U64.range(), (0, i64::MAX));
+        assert_eq!(Numeric::U8.wrap(300), 44);
+        assert_eq!(Numeric::U8.wrap(-1), 255);
+        assert_eq!(Numeric::I8.wrap(200), -56);
+        assert_eq!(Numeric::I64.wrap(i128::from(i64::MAX) + 1), i64::MIN);
+        assert_eq!(Numeric::U64.wrap(-1), i64::MAX);
+        assert!(Numeric::I16.contains(-32768) && !Numeric::I16.contains(32768));
+
+        assert_eq!(Numeric::U8.cast(Scalar::Float(-3.7)), Scalar::Int(0));
+        assert_eq!(Numeric::I32.cast(Scalar::Float(-3.7)), Scalar::Int(-3));
+        assert_eq!(Numeric::I8.cast(Scalar::Float(1e10)), Scalar::Int(127));
+        assert_eq!(Numeric::I64.cast(Scalar::Float(f64::NAN)), Scalar::Int(0));
+        assert_eq!(
+            Numeric::F32.cast(Scalar::Float(0.1)),
+            Scalar::Float(0.1f32 as f64)
+        );
+        assert_eq!(Numeric::F64.cast(Scalar::Int(3)), Scalar::Float(3.0));
+        assert_eq!(Numeric::parse("usize"), Some(Numeric::I64));
+        assert_eq!(Numeric::parse("char"), None);
+    }
+}
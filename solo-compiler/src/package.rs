@@ -0,0 +1,538 @@
+//! Packages and their build targets
+//!
+//! A package is a directory holding a `solo.toml` manifest:
+//!
+//! ```text
+//! <package>/
+//!   solo.toml               [package] name = "..."
+//!   src/lib.solo            library code shared by every target
+//!   src/main.solo           executable named after the package
+//!   src/bin/<name>.solo     more executables, run with `solo run --bin <name>`
+//!   examples/<name>.solo    example programs, run with `solo run --example <name>`
+//! ```
+//!
+//! Executables can also be declared in the manifest, with a `path` relative
+//! to the package root (by default `src/bin/<name>.solo`):
+//!
+//! ```toml
+//! [[bin]]
+//! name = "server"
+//! path = "tools/server.solo"
+//! ```
+//!
+//! A `[link]` table picks the linker and passes `-L`/`-l` flags to it (see
+//! [`link`](crate::link)):
+//!
+//! ```toml
+//! [link]
+//! linker = "clang"
+//! flags = "-L native/lib -lm"
+//! ```
+//!
+//! A `[lints]` table turns lints off for every target (see
+//! [`lint`](crate::lint)); `"warn"`, the default, keeps them on:
+//!
+//! ```toml
+//! [lints]
+//! shadowing = "allow"
+//! ```
+//!
+//! Targets in `src/bin` and `examples` may also be legacy Me files (`.my`,
+//! `.mylang`), which are lowered to Solo (see [`legacy`](crate::legacy)) so a
+//! package can move over one file at a time.
+//!
+//! Commands given `--bin`, `--example` or `--all-targets` instead of a file
+//! locate the package by searching the current directory and its parents for
+//! the manifest, the way Cargo does.
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::ast::Span;
+use crate::error::{CompileError, Diagnostic};
+use crate::legacy::Dialect;
+use crate::link::LinkArgs;
+
+/// File name of the package manifest
+pub const MANIFEST: &str = "solo.toml";
+
+/// What a target is built for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TargetKind {
+    Bin,
+    Example,
+}
+
+impl fmt::Display for TargetKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TargetKind::Bin => "bin",
+            TargetKind::Example => "example",
+        })
+    }
+}
+
+/// A program of the package that can be built and run on its own
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Target {
+    pub kind: TargetKind,
+    pub name: String,
+    pub path: PathBuf,
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} `{}`", self.kind, self.name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Package {
+    root: PathBuf,
+    name: String,
+    /// Executables declared with `[[bin]]`
+    declared: Vec<Target>,
+    /// `linker` of the `[link]` table
+    linker: Option<String>,
+    link_args: LinkArgs,
+    /// Lints set to `"allow"` in the `[lints]` table
+    allowed_lints: Vec<String>,
+}
+
+impl Package {
+    /// Find the package containing `dir`
+    pub fn discover(dir: &Path) -> Result<Package, String> {
+        for candidate in dir.ancestors() {
+            if candidate.join(MANIFEST).is_file() {
+                return Package::load(candidate);
+            }
+        }
+        Err(format!(
+            "could not find `{}` in `{}` or any parent directory",
+            MANIFEST,
+            dir.display()
+        ))
+    }
+
+    /// Load the package whose manifest is in `root`
+    pub fn load(root: &Path) -> Result<Package, String> {
+        let path = root.join(MANIFEST);
+        let text = fs::read_to_string(&path)
+            .map_err(|err| format!("cannot read '{}': {}", path.display(), err))?;
+        let manifest =
+            Manifest::parse(&text).map_err(|err| format!("{}:{}", path.display(), err))?;
+        let name = manifest
+            .get("package", "name")
+            .ok_or_else(|| format!("{}: missing `[package]` `name`", path.display()))?;
+        let mut declared = Vec::new();
+        for table in manifest.tables.iter().filter(|t| t.name == "bin") {
+            let name = table
+                .get("name")
+                .ok_or_else(|| format!("{}: `[[bin]]` without a `name`", path.display()))?;
+            let file = match table.get("path") {
+                Some(file) => root.join(file),
+                None => root.join("src").join("bin").join(format!("{}.solo", name)),
+            };
+            declared.push(Target {
+                kind: TargetKind::Bin,
+                name: name.to_string(),
+                path: file,
+            });
+        }
+        let link_args = LinkArgs::parse(manifest.get("link", "flags").unwrap_or(""))
+            .map_err(|err| format!("{}: `[link]` `flags`: {}", path.display(), err))?;
+        let mut allowed_lints = Vec::new();
+        for table in manifest.tables.iter().filter(|t| t.name == "lints") {
+            for (lint, level) in &table.entries {
+                if !crate::lint::LINTS.contains(&lint.as_str()) {
+                    return Err(format!(
+                        "{}: `[lints]`: unknown lint `{}`",
+                        path.display(),
+                        lint
+                    ));
+                }
+                match level.as_str() {
+                    "allow" => allowed_lints.push(lint.clone()),
+                    "warn" => {}
+                    _ => {
+                        return Err(format!(
+                            "{}: `[lints]` `{}`: expected \"allow\" or \"warn\"",
+                            path.display(),
+                            lint
+                        ))
+                    }
+                }
+            }
+        }
+        Ok(Package {
+            root: root.to_path_buf(),
+            name: name.to_string(),
+            declared,
+            linker: manifest.get("link", "linker").map(String::from),
+            link_args,
+            allowed_lints,
+        })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The linker named in the manifest, if any
+    pub fn linker(&self) -> Option<&str> {
+        self.linker.as_deref()
+    }
+
+    /// Lints turned off by the `[lints]` table
+    pub fn allowed_lints(&self) -> &[String] {
+        &self.allowed_lints
+    }
+
+    /// Search paths and libraries from the manifest's link flags
+    pub fn link_args(&self) -> &LinkArgs {
+        &self.link_args
+    }
+
+    /// Every target of the package: executables, then examples
+    pub fn targets(&self) -> Vec<Target> {
+        let mut targets = self.bins();
+        targets.extend(self.examples());
+        targets
+    }
+
+    /// The executables, sorted by name: `src/main.solo`, those declared in
+    /// the manifest and the `src/bin/*.solo` files not already declared
+    pub fn bins(&self) -> Vec<Target> {
+        let mut bins = self.declared.clone();
+        let main = self.root.join("src").join("main.solo");
+        let discovered = main
+            .is_file()
+            .then(|| Target {
+                kind: TargetKind::Bin,
+                name: self.name.clone(),
+                path: main,
+            })
+            .into_iter()
+            .chain(scan(&self.root.join("src").join("bin"), TargetKind::Bin));
+        for target in discovered {
+            if !bins
+                .iter()
+                .any(|b| b.name == target.name || b.path == target.path)
+            {
+                bins.push(target);
+            }
+        }
+        bins.sort_by(|a, b| a.name.cmp(&b.name));
+        bins
+    }
+
+    /// The `examples/*.solo` programs, sorted by name
+    pub fn examples(&self) -> Vec<Target> {
+        scan(&self.root.join("examples"), TargetKind::Example)
+    }
+
+    pub fn bin(&self, name: &str) -> Result<Target, String> {
+        find(self.bins(), TargetKind::Bin, name)
+    }
+
+    pub fn example(&self, name: &str) -> Result<Target, String> {
+        find(self.examples(), TargetKind::Example, name)
+    }
+
+    /// `src/lib.solo`, if the package has library code
+    pub fn library(&self) -> Option<PathBuf> {
+        Some(self.root.join("src").join("lib.solo")).filter(|path| path.is_file())
+    }
+
+    /// Source of `target` followed by the package's library code, which
+    /// `#![library]` sets apart so the target only uses what it makes `pub`
+    pub fn source(&self, target: &Target) -> Result<Source, CompileError> {
+        let text = crate::read_source(&target.path)?;
+        let mut text = Dialect::for_path(&target.path).to_solo(&text)?;
+        let mut library = None;
+        if let Some(path) = self.library() {
+            if !text.is_empty() && !text.ends_with('\n') {
+                text.push('\n');
+            }
+            text.push_str("#![library]\n");
+            let offset = text.lines().count();
+            text.push_str(&crate::read_source(&path)?);
+            library = Some((path, offset));
+        }
+        Ok(Source {
+            text,
+            path: target.path.clone(),
+            library,
+        })
+    }
+}
+
+/// The `*.solo` files (and legacy `.my`/`.mylang` files) of `dir` as
+/// targets, sorted by name
+fn scan(dir: &Path, kind: TargetKind) -> Vec<Target> {
+    let Ok(entries) = dir.read_dir() else {
+        return Vec::new();
+    };
+    let mut targets: Vec<Target> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.is_file()
+                && path
+                    .extension()
+                    .is_some_and(|ext| ext == "solo" || Dialect::for_path(path) == Dialect::Legacy)
+        })
+        .filter_map(|path| {
+            let name = path.file_stem()?.to_str()?.to_string();
+            Some(Target { kind, name, path })
+        })
+        .collect();
+    targets.sort_by(|a, b| a.name.cmp(&b.name));
+    targets
+}
+
+fn find(targets: Vec<Target>, kind: TargetKind, name: &str) -> Result<Target, String> {
+    if let Some(target) = targets.iter().find(|t| t.name == name) {
+        return Ok(target.clone());
+    }
+    let available: Vec<&str> = targets.iter().map(|t| t.name.as_str()).collect();
+    Err(if available.is_empty() {
+        format!("no {} target named `{}` (the package has none)", kind, name)
+    } else {
+        format!(
+            "no {} target named `{}`; available: {}",
+            kind,
+            name,
+            available.join(", ")
+        )
+    })
+}
+
+/// A target's source with the library code appended
+#[derive(Debug, Clone)]
+pub struct Source {
+    pub text: String,
+    path: PathBuf,
+    /// The library file and the number of target lines before it
+    library: Option<(PathBuf, usize)>,
+}
+
+impl Source {
+    /// Place the diagnostics of `err`, and their fixes, in the target or
+    /// the library file
+    pub fn attribute(&self, mut err: CompileError) -> CompileError {
+        for diagnostic in err.diagnostics_mut() {
+            self.attribute_diagnostic(diagnostic);
+        }
+        err.in_file(&self.path)
+    }
+
+    /// Place one diagnostic, such as a lint warning's, its fix and the
+    /// places it refers to
+    pub fn attribute_diagnostic(&self, diagnostic: &mut Diagnostic) {
+        diagnostic.file = Some(self.place(diagnostic.span.as_mut()));
+        if let Some(fix) = &mut diagnostic.fix {
+            let file = self.place(Some(&mut fix.start));
+            self.place(Some(&mut fix.end));
+            fix.file = Some(file);
+        }
+        if let Some(related) = &mut diagnostic.related {
+            related.file = Some(self.place(Some(&mut related.span)));
+        }
+    }
+
+    /// The file `span` is in, making it relative to that file, such as for
+    /// the items a closed-world build removes
+    pub fn locate(&self, span: &mut Span) -> PathBuf {
+        self.place(Some(span))
+    }
+
+    fn place(&self, span: Option<&mut Span>) -> PathBuf {
+        match (&self.library, span) {
+            (Some((library, offset)), Some(span)) if span.line > *offset => {
+                span.line -= offset;
+                library.clone()
+            }
+            _ => self.path.clone(),
+        }
+    }
+}
+
+/// The subset of TOML used by manifests: `[table]` headers, `[[array]]`
+/// headers and `key = "string"` entries
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Manifest {
+    pub tables: Vec<Table>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Table {
+    pub name: String,
+    /// Written as `[[name]]`, one element of an array of tables
+    pub array: bool,
+    pub entries: Vec<(String, String)>,
+}
+
+impl Table {
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+impl Manifest {
+    /// Parse a manifest; errors are prefixed with the line number
+    pub fn parse(text: &str) -> Result<Manifest, String> {
+        let mut manifest = Manifest::default();
+        for (index, line) in text.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = |message: &str| format!("{}: {}", index + 1, message);
+            if let Some(header) = line.strip_prefix('[') {
+                let (name, array) = match header.strip_prefix('[') {
+                    Some(rest) => (rest.strip_suffix("]]"), true),
+                    None => (header.strip_suffix(']'), false),
+                };
+                let name = name
+                    .map(str::trim)
+                    .filter(|name| !name.is_empty())
+                    .ok_or_else(|| error("malformed table header"))?;
+                manifest.tables.push(Table {
+                    name: name.to_string(),
+                    array,
+                    entries: Vec::new(),
+                });
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| error("expected `key = \"value\"`"))?;
+            let value = value
+                .trim()
+                .strip_prefix('"')
+                .and_then(|v| v.strip_suffix('"'))
+                .ok_or_else(|| error("values must be double-quoted strings"))?;
+            let table = manifest
+                .tables
+                .last_mut()
+                .ok_or_else(|| error("key outside of a table"))?;
+            table
+                .entries
+                .push((key.trim().to_string(), value.to_string()));
+        }
+        Ok(manifest)
+    }
+
+    /// Value of `key` in the first `[table]`
+    pub fn get(&self, table: &str, key: &str) -> Option<&str> {
+        self.tables
+            .iter()
+            .find(|t| t.name == table && !t.array)
+            .and_then(|t| t.get(key))
+    }
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifest() {
+        let manifest = Manifest::parse(
+            "# demo\n[package]\nname = \"demo\" # the name\n\n[[bin]]\nname = \"a#b\"\n",
+        )
+        .unwrap();
+        assert_eq!(manifest.get("package", "name"), Some("demo"));
+        assert_eq!(manifest.tables[1].get("name"), Some("a#b"));
+        assert!(manifest.tables[1].array);
+        assert_eq!(
+            Manifest::parse("[package]\nname = demo").unwrap_err(),
+            "2: values must be double-quoted strings"
+        );
+    }
+
+    #[test]
+    fn test_targets_and_library() {
+        let root = std::env::temp_dir().join(format!("solo-package-{}", std::process::id()));
+        let write = |file: &str, text: &str| {
+            let path = root.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, text).unwrap();
+        };
+        write(
+            MANIFEST,
+            "[package]\nname = \"demo\"\n[[bin]]\nname = \"tool\"\npath = \"tools/t.solo\"\n\
+             [link]\nlinker = \"clang\"\nflags = \"-L native -lm\"\n\
+             [lints]\nshadowing = \"allow\"\ndead_code = \"warn\"\n",
+        );
+        write("src/main.solo", "fn main() { print(helper()); }");
+        write("src/bin/extra.solo", "fn main() {}\n");
+        write("src/lib.solo", "fn helper() -> i64 {\n    missing\n}\n");
+        write("tools/t.solo", "fn main() {}\n");
+        write("examples/hello.solo", "fn main() {}\n");
+        write("examples/old.mylang", "# Me\nx := 1;\n");
+
+        let package = Package::discover(&root.join("examples")).unwrap();
+        assert_eq!(package.linker(), Some("clang"));
+        assert_eq!(package.link_args().libraries, ["m"]);
+        assert_eq!(package.allowed_lints(), ["shadowing"]);
+        let targets: Vec<String> = package.targets().iter().map(|t| t.to_string()).collect();
+        assert_eq!(
+            targets,
+            [
+                "bin `demo`",
+                "bin `extra`",
+                "bin `tool`",
+                "example `hello`",
+                "example `old`"
+            ]
+        );
+        let old = package.source(&package.example("old").unwrap()).unwrap();
+        assert!(old
+            .text
+            .starts_with("\nfn main(){let mut x = 1;}\n#![library]\nfn helper()"));
+
+        let main = package.bin("demo").unwrap();
+        let source = package.source(&main).unwrap();
+        let err = source.attribute(crate::check(&source.text).unwrap_err());
+        let diagnostic = &err.diagnostics()[0];
+        assert_eq!(diagnostic.file, Some(root.join("src").join("lib.solo")));
+        assert_eq!(diagnostic.span.map(|s| s.line), Some(2));
+        // `helper` is not `pub`; the fix goes in the library
+        let private = &err.diagnostics()[1];
+        assert_eq!(private.file, Some(main.path.clone()));
+        assert!(private.message.contains("private to the library"));
+        let fix = private.fix.as_ref().unwrap();
+        assert_eq!(fix.file, Some(root.join("src").join("lib.solo")));
+        assert_eq!(
+            (fix.start, fix.replacement.as_str()),
+            (Span::new(1, 1), "pub ")
+        );
+        assert!(package
+            .bin("nope")
+            .unwrap_err()
+            .contains("available: demo, extra, tool"));
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,1566 @@
+//! Recursive descent parser for Solo dialect
+//!
+//! Consumes a (macro-expanded) token stream and produces an AST.
+//! Expression precedence, lowest to highest:
+//!
+//! ```text
+//! =        (right associative)
+//! ||
+//! &&
+//! == != < <= > >=
+//! + -
+//! * / %
+//! - ! & &mut   (prefix)
+//! call() index[] .field  (postfix)
+//! ```
+//!
+//! As in Rust, struct literals are not allowed directly in `if` and `while`
+//! conditions, where `{` starts the body instead.
+
+use crate::ast::*;
+use crate::error::{CompileError, Diagnostic};
+use crate::feature;
+use crate::symbol::Symbol;
+use crate::token::{Token, TokenKind};
+
+/// Maximum nesting depth of expressions and types, which keeps the passes
+/// recursing over the tree within [`STACK_SIZE`]
+pub const NESTING_LIMIT: usize = 256;
+
+/// Stack that compiling a program nested up to [`NESTING_LIMIT`] fits in,
+/// unoptimized builds included: that of the main thread on Linux. Threads
+/// running the compiler are spawned with it.
+pub const STACK_SIZE: usize = 8 << 20;
+
+/// Remove `#![library]` from `attrs`, returning its span
+fn take_library(attrs: &mut Vec<Attribute>) -> Option<Span> {
+    let index = attrs
+        .iter()
+        .position(|a| a.name == "library" && a.args.is_empty())?;
+    Some(attrs.remove(index).span)
+}
+
+/// Parse a token stream (without `Eof`) into a program
+pub fn parse(tokens: Vec<Token>) -> Result<Program, CompileError> {
+    Parser::new(tokens)
+        .parse_program()
+        .map_err(|err| CompileError::Parse(vec![err]))
+}
+
+pub struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    /// Set while parsing a condition, where `Name {` is not a struct literal
+    no_struct_literal: bool,
+    /// Type of the enclosing `impl` block, which `Self` stands for
+    impl_type: Option<Symbol>,
+    /// Nesting depth of the expression or type being parsed
+    depth: usize,
+    /// Where the nested expressions parsed so far are allocated
+    exprs: Exprs,
+}
+
+impl Parser {
+    pub fn new(tokens: Vec<Token>) -> Self {
+        Self {
+            tokens,
+            position: 0,
+            no_struct_literal: false,
+            impl_type: None,
+            depth: 0,
+            exprs: Exprs::default(),
+        }
+    }
+
+    pub fn parse_program(&mut self) -> Result<Program, Diagnostic> {
+        let mut attrs = self.parse_attributes(true)?;
+        let mut library = take_library(&mut attrs);
+        let mut items = Vec::new();
+        while !self.is_at_end() {
+            if self.at_inner_attribute() {
+                // Except for the one starting the library
+                let misplaced = self.error("inner attributes must come before the first item");
+                let mut inner = self.parse_attributes(true)?;
+                match take_library(&mut inner) {
+                    Some(span) if inner.is_empty() && library.is_none() => library = Some(span),
+                    _ => return Err(misplaced),
+                }
+                continue;
+            }
+            items.push(self.parse_item()?);
+        }
+        Ok(Program {
+            attrs,
+            items,
+            library,
+            exprs: std::mem::take(&mut self.exprs),
+        })
+    }
+
+    /// Move an expression nested in another into the arena
+    fn alloc(&mut self, expr: Expr) -> ExprId {
+        self.exprs.alloc(expr)
+    }
+
+    fn at_inner_attribute(&self) -> bool {
+        self.check(&TokenKind::Pound) && self.peek_kind_at(1) == Some(&TokenKind::Not)
+    }
+
+    fn parse_item(&mut self) -> Result<Item, Diagnostic> {
+        let attrs = self.parse_attributes(false)?;
+        let vis = self.parse_visibility()?;
+        let attributed = [
+            TokenKind::Struct,
+            TokenKind::Enum,
+            TokenKind::Fn,
+            TokenKind::Extern,
+        ];
+        if !attrs.is_empty() && !attributed.iter().any(|kind| self.check(kind)) {
+            return Err(self.error(
+                "attributes are only supported on `struct`, `enum`, `fn` and `extern` items",
+            ));
+        }
+
+        match self.peek_kind() {
+            Some(TokenKind::Fn) => Ok(Item::Function(Function {
+                vis,
+                ..self.parse_function(false, attrs)?
+            })),
+            Some(TokenKind::Impl) if vis.is_private() => Ok(Item::Impl(self.parse_impl()?)),
+            Some(TokenKind::Impl) => Err(self.error(
+                "`impl` blocks have no visibility; mark the methods that should be `pub` instead",
+            )),
+            Some(TokenKind::Const) => {
+                if self.peek_kind_at(1) == Some(&TokenKind::Fn) {
+                    self.advance();
+                    Ok(Item::Function(Function {
+                        vis,
+                        ..self.parse_function(true, Vec::new())?
+                    }))
+                } else {
+                    Ok(Item::Const(ConstItem {
+                        vis,
+                        ..self.parse_const()?
+                    }))
+                }
+            }
+            Some(TokenKind::Enum) => Ok(Item::Enum(EnumDef {
+                vis,
+                ..self.parse_enum(attrs)?
+            })),
+            Some(TokenKind::Struct) => Ok(Item::Struct(StructDef {
+                vis,
+                ..self.parse_struct(attrs)?
+            })),
+            Some(TokenKind::Extern) => match vis {
+                Visibility::Private { .. } => Ok(Item::Extern(self.parse_extern(attrs)?)),
+                Visibility::Public { .. } => Ok(Item::Function(self.parse_exported(attrs, vis)?)),
+                Visibility::Crate { .. } => {
+                    Err(self.error("`extern \"C\"` functions are exported with `pub`"))
+                }
+            },
+            Some(TokenKind::Type | TokenKind::Newtype) => Ok(Item::TypeAlias(TypeAlias {
+                vis,
+                ..self.parse_type_alias()?
+            })),
+            _ => Err(self.error(
+                "expected item (`fn`, `const`, `enum`, `struct`, `impl`, `extern`, `type` or `newtype`)",
+            )),
+        }
+    }
+
+    /// `pub`, `pub(crate)` or nothing
+    fn parse_visibility(&mut self) -> Result<Visibility, Diagnostic> {
+        let at = self.current_span();
+        if !self.matches(&TokenKind::Pub) {
+            return Ok(Visibility::Private { at });
+        }
+        if !self.matches(&TokenKind::LParen) {
+            return Ok(Visibility::Public { at });
+        }
+        if !matches!(self.peek_kind(), Some(TokenKind::Identifier(name)) if name == "crate") {
+            return Err(self.error("expected `crate`, the only restricted visibility"));
+        }
+        self.advance();
+        let close = self.expect(TokenKind::RParen, "`)`")?;
+        Ok(Visibility::Crate {
+            start: at,
+            end: Span::new(close.line, close.column + 1),
+        })
+    }
+
+    /// Outer attributes (`#[...]`), or inner ones (`#![...]`) with `inner`
+    fn parse_attributes(&mut self, inner: bool) -> Result<Vec<Attribute>, Diagnostic> {
+        let mut attrs = Vec::new();
+        while self.check(&TokenKind::Pound) && self.at_inner_attribute() == inner {
+            let span = self.current_span();
+            self.advance();
+            if inner {
+                self.advance();
+            }
+            self.expect(TokenKind::LBracket, "`[` after `#`")?;
+            let name = self.expect_identifier()?;
+            let mut args = Vec::new();
+            if self.matches(&TokenKind::LParen) {
+                while !self.check(&TokenKind::RParen) {
+                    let mut arg = self.expect_identifier()?.to_string();
+                    if self.matches(&TokenKind::LParen) {
+                        match self.peek_kind() {
+                            Some(TokenKind::Integer(n, None)) => {
+                                arg = format!("{}({})", arg, n);
+                                self.advance();
+                            }
+                            _ => return Err(self.error("expected an integer")),
+                        }
+                        self.expect(TokenKind::RParen, "`)`")?;
+                    }
+                    args.push(arg);
+                    if !self.check(&TokenKind::RParen) {
+                        self.expect(TokenKind::Comma, "`,` or `)`")?;
+                    }
+                }
+                self.expect(TokenKind::RParen, "`)`")?;
+            }
+            self.expect(TokenKind::RBracket, "`]`")?;
+            attrs.push(Attribute {
+                name: name.to_string(),
+                args,
+                span,
+            });
+        }
+        Ok(attrs)
+    }
+
+    fn parse_function(
+        &mut self,
+        is_const: bool,
+        attrs: Vec<Attribute>,
+    ) -> Result<Function, Diagnostic> {
+        let span = self.expect(TokenKind::Fn, "`fn`")?;
+        let name = self.expect_identifier()?;
+        self.reject_generics()?;
+
+        self.expect(TokenKind::LParen, "`(`")?;
+        let receiver = self.parse_receiver()?;
+        let params = self.parse_params()?;
+        let return_type = self.parse_return_type()?;
+
+        let body = self.parse_block()?;
+        Ok(Function {
+            vis: Visibility::Private { at: span },
+            name,
+            params,
+            return_type,
+            body,
+            is_const,
+            exported: false,
+            receiver,
+            attrs,
+            span,
+        })
+    }
+
+    /// `extern "C" fn name(params) -> ret { ... }` after `pub`
+    fn parse_exported(
+        &mut self,
+        attrs: Vec<Attribute>,
+        vis: Visibility,
+    ) -> Result<Function, Diagnostic> {
+        self.expect(TokenKind::Extern, "`extern`")?;
+        self.parse_abi()?;
+        let mut function = self.parse_function(false, attrs)?;
+        function.vis = vis;
+        function.exported = true;
+        Ok(function)
+    }
+
+    /// The `"C"` after `extern`, the only ABI there is
+    fn parse_abi(&mut self) -> Result<(), Diagnostic> {
+        match self.peek_kind() {
+            Some(TokenKind::String(abi)) if abi == "C" => {
+                self.advance();
+                Ok(())
+            }
+            Some(TokenKind::String(abi)) => {
+                let msg = format!("unsupported ABI `{}`; only \"C\" is supported", abi);
+                Err(self.error(&msg))
+            }
+            _ => Err(self.error("expected ABI string after `extern`, e.g. `extern \"C\"`")),
+        }
+    }
+
+    /// Parameters up to and including the closing `)`
+    fn parse_params(&mut self) -> Result<Vec<Param>, Diagnostic> {
+        let mut params = Vec::new();
+        while !self.check(&TokenKind::RParen) {
+            let param_span = self.current_span();
+            let param_name = self.expect_identifier()?;
+            self.expect(TokenKind::Colon, "`:`")?;
+            let ty = self.parse_type()?;
+            params.push(Param {
+                name: param_name,
+                ty,
+                span: param_span,
+            });
+            if !self.check(&TokenKind::RParen) {
+                self.expect(TokenKind::Comma, "`,` or `)`")?;
+            }
+        }
+        self.expect(TokenKind::RParen, "`)`")?;
+        Ok(params)
+    }
+
+    /// `-> T` after the parameters, where `!` may stand for the type
+    fn parse_return_type(&mut self) -> Result<Option<TypeExpr>, Diagnostic> {
+        if !self.matches(&TokenKind::Arrow) {
+            return Ok(None);
+        }
+        let span = self.current_span();
+        if self.matches(&TokenKind::Not) {
+            Ok(Some(TypeExpr::Never(span)))
+        } else {
+            Ok(Some(self.parse_type()?))
+        }
+    }
+
+    /// Optional `self`, `mut self`, `&self` or `&mut self` first parameter
+    fn parse_receiver(&mut self) -> Result<Option<Receiver>, Diagnostic> {
+        let is_self = |kind: Option<&TokenKind>| matches!(kind, Some(TokenKind::Identifier(name)) if name == "self");
+        let (receiver, len) = match (self.peek_kind(), self.peek_kind_at(1)) {
+            (Some(TokenKind::Ampersand), Some(TokenKind::Mut)) if is_self(self.peek_kind_at(2)) => {
+                (Receiver::RefMut, 3)
+            }
+            (Some(TokenKind::Ampersand), next) if is_self(next) => (Receiver::Ref, 2),
+            (Some(TokenKind::Mut), next) if is_self(next) => (Receiver::MutValue, 2),
+            (current, _) if is_self(current) => (Receiver::Value, 1),
+            _ => return Ok(None),
+        };
+        if self.impl_type.is_none() {
+            return Err(self.error("`self` parameter is only allowed in methods"));
+        }
+        for _ in 0..len {
+            self.advance();
+        }
+        if !self.check(&TokenKind::RParen) {
+            self.expect(TokenKind::Comma, "`,` or `)`")?;
+        }
+        Ok(Some(receiver))
+    }
+
+    /// `extern "C" { fn name(params) -> ret; ... }`
+    fn parse_extern(&mut self, attrs: Vec<Attribute>) -> Result<ExternBlock, Diagnostic> {
+        let span = self.expect(TokenKind::Extern, "`extern`")?;
+        self.parse_abi()?;
+        self.expect(TokenKind::LBrace, "`{`")?;
+
+        let mut functions = Vec::new();
+        while !self.matches(&TokenKind::RBrace) {
+            if self.is_at_end() {
+                return Err(self.error("unclosed `extern` block, expected `}`"));
+            }
+            let fn_span = self.expect(TokenKind::Fn, "`fn`")?;
+            let name = self.expect_identifier()?;
+            self.expect(TokenKind::LParen, "`(`")?;
+            let params = self.parse_params()?;
+            let return_type = self.parse_return_type()?;
+            self.expect(
+                TokenKind::Semicolon,
+                "`;` after extern function declaration",
+            )?;
+            functions.push(ExternFn {
+                name,
+                params,
+                return_type,
+                span: fn_span,
+            });
+        }
+
+        Ok(ExternBlock {
+            functions,
+            attrs,
+            span,
+        })
+    }
+
+    fn parse_impl(&mut self) -> Result<ImplBlock, Diagnostic> {
+        let span = self.expect(TokenKind::Impl, "`impl`")?;
+        let self_ty = self.expect_identifier()?;
+        self.expect(TokenKind::LBrace, "`{`")?;
+
+        self.impl_type = Some(self_ty);
+        let mut methods = Vec::new();
+        let result = loop {
+            if self.check(&TokenKind::RBrace) || self.is_at_end() {
+                break self.expect(TokenKind::RBrace, "`}`").map(|_| ());
+            }
+            let attrs = match self.parse_attributes(false) {
+                Ok(attrs) => attrs,
+                Err(err) => break Err(err),
+            };
+            let vis = match self.parse_visibility() {
+                Ok(vis) => vis,
+                Err(err) => break Err(err),
+            };
+            let is_const = self.matches(&TokenKind::Const);
+            match self.parse_function(is_const, attrs) {
+                Ok(method) => methods.push(Function { vis, ..method }),
+                Err(err) => break Err(err),
+            }
+        };
+        self.impl_type = None;
+        result?;
+
+        Ok(ImplBlock {
+            self_ty,
+            methods,
+            span,
+        })
+    }
+
+    fn parse_const(&mut self) -> Result<ConstItem, Diagnostic> {
+        let span = self.expect(TokenKind::Const, "`const`")?;
+        let name = self.expect_identifier()?;
+        self.expect(
+            TokenKind::Colon,
+            "`:` (constants require a type annotation)",
+        )?;
+        let ty = self.parse_type()?;
+        self.expect(TokenKind::Eq, "`=`")?;
+        let value = self.parse_expr()?;
+        self.expect(TokenKind::Semicolon, "`;`")?;
+        Ok(ConstItem {
+            vis: Visibility::Private { at: span },
+            name,
+            ty,
+            value,
+            span,
+        })
+    }
+
+    /// `type Name = Type;` or `newtype Name = Type;`
+    fn parse_type_alias(&mut self) -> Result<TypeAlias, Diagnostic> {
+        let span = self.current_span();
+        let newtype = self.matches(&TokenKind::Newtype);
+        if !newtype {
+            self.expect(TokenKind::Type, "`type`")?;
+        }
+        let name = self.expect_identifier()?;
+        self.expect(TokenKind::Eq, "`=`")?;
+        let ty = self.parse_type()?;
+        self.expect(TokenKind::Semicolon, "`;`")?;
+        Ok(TypeAlias {
+            vis: Visibility::Private { at: span },
+            name,
+            ty,
+            newtype,
+            span,
+        })
+    }
+
+    fn parse_enum(&mut self, attrs: Vec<Attribute>) -> Result<EnumDef, Diagnostic> {
+        let span = self.expect(TokenKind::Enum, "`enum`")?;
+        let name = self.expect_identifier()?;
+        self.expect(TokenKind::LBrace, "`{`")?;
+
+        let mut variants = Vec::new();
+        while !self.check(&TokenKind::RBrace) {
+            let variant_span = self.current_span();
+            let variant_name = self.expect_identifier()?;
+            let discriminant = if self.matches(&TokenKind::Eq) {
+                Some(self.parse_expr()?)
+            } else {
+                None
+            };
+            variants.push(Variant {
+                name: variant_name,
+                discriminant,
+                span: variant_span,
+            });
+            if !self.check(&TokenKind::RBrace) {
+                self.expect(TokenKind::Comma, "`,` or `}`")?;
+            }
+        }
+        self.expect(TokenKind::RBrace, "`}`")?;
+
+        Ok(EnumDef {
+            vis: Visibility::Private { at: span },
+            name,
+            variants,
+            attrs,
+            span,
+        })
+    }
+
+    fn parse_struct(&mut self, attrs: Vec<Attribute>) -> Result<StructDef, Diagnostic> {
+        let span = self.expect(TokenKind::Struct, "`struct`")?;
+        let name = self.expect_identifier()?;
+        self.reject_generics()?;
+        self.expect(TokenKind::LBrace, "`{`")?;
+
+        let mut fields = Vec::new();
+        while !self.check(&TokenKind::RBrace) {
+            let vis = self.parse_visibility()?;
+            let field_span = self.current_span();
+            let field_name = self.expect_identifier()?;
+            self.expect(TokenKind::Colon, "`:`")?;
+            let ty = self.parse_type()?;
+            fields.push(FieldDef {
+                vis,
+                name: field_name,
+                ty,
+                span: field_span,
+            });
+            if !self.check(&TokenKind::RBrace) {
+                self.expect(TokenKind::Comma, "`,` or `}`")?;
+            }
+        }
+        self.expect(TokenKind::RBrace, "`}`")?;
+
+        Ok(StructDef {
+            vis: Visibility::Private { at: span },
+            name,
+            fields,
+            attrs,
+            span,
+        })
+    }
+
+    fn parse_type(&mut self) -> Result<TypeExpr, Diagnostic> {
+        let span = self.current_span();
+        match self.peek_kind() {
+            Some(TokenKind::Ampersand) => {
+                self.advance();
+                let mutable = self.matches(&TokenKind::Mut);
+                let inner = Box::new(self.nested(Self::parse_type)?);
+                Ok(TypeExpr::Ref { mutable, inner })
+            }
+            Some(TokenKind::Star) => {
+                self.advance();
+                let mutable = self.matches(&TokenKind::Mut);
+                if !mutable {
+                    self.expect(
+                        TokenKind::Const,
+                        "`const` or `mut` after `*` in a pointer type",
+                    )?;
+                }
+                let inner = Box::new(self.nested(Self::parse_type)?);
+                Ok(TypeExpr::Ptr { mutable, inner })
+            }
+            Some(TokenKind::LBracket) => {
+                self.advance();
+                let elem = Box::new(self.nested(Self::parse_type)?);
+                if self.matches(&TokenKind::RBracket) {
+                    return Ok(TypeExpr::Slice { elem });
+                }
+                self.expect(TokenKind::Semicolon, "`;` in array type")?;
+                let len = Box::new(self.parse_expr()?);
+                self.expect(TokenKind::RBracket, "`]`")?;
+                Ok(TypeExpr::Array { elem, len })
+            }
+            Some(TokenKind::LParen) => {
+                self.advance();
+                self.expect(TokenKind::RParen, "`)`")?;
+                Ok(TypeExpr::Unit)
+            }
+            Some(TokenKind::Identifier(_) | TokenKind::Belief) => {
+                let name = if self.matches(&TokenKind::Belief) {
+                    Symbol::intern("Belief")
+                } else {
+                    self.expect_identifier()?
+                };
+                if !self.matches(&TokenKind::Lt) {
+                    return Ok(TypeExpr::Named(self.resolve_self(name), span));
+                }
+                let mut args = Vec::new();
+                let mut bounds = Vec::new();
+                loop {
+                    let is_bound = matches!(self.peek_kind(), Some(TokenKind::Identifier(_)))
+                        && matches!(self.peek_kind_at(1), Some(TokenKind::Ge | TokenKind::Le));
+                    if is_bound {
+                        bounds.push(self.parse_bound()?);
+                    } else if bounds.is_empty() {
+                        args.push(self.nested(Self::parse_type)?);
+                    } else {
+                        return Err(self.error("type arguments must come before bounds"));
+                    }
+                    if !self.matches(&TokenKind::Comma) {
+                        break;
+                    }
+                }
+                self.expect(TokenKind::Gt, "`>`")?;
+                Ok(TypeExpr::Generic {
+                    name,
+                    args,
+                    bounds,
+                    span,
+                })
+            }
+            _ => Err(self.error("expected type")),
+        }
+    }
+
+    /// `property >= value` or `property <= value` inside a type argument list
+    fn parse_bound(&mut self) -> Result<Bound, Diagnostic> {
+        let span = self.current_span();
+        let property = self.expect_identifier()?;
+        let op = match self.peek_kind() {
+            Some(TokenKind::Ge) => BinaryOp::Ge,
+            Some(TokenKind::Le) => BinaryOp::Le,
+            _ => return Err(self.error("expected `>=` or `<=` in bound")),
+        };
+        self.advance();
+        let value = match self.peek_kind() {
+            Some(TokenKind::Float(f, None)) => *f,
+            Some(TokenKind::Integer(n, None)) => *n as f64,
+            _ => return Err(self.error("expected number in bound")),
+        };
+        self.advance();
+        Ok(Bound {
+            property: property.to_string(),
+            op,
+            value,
+            span,
+        })
+    }
+
+    fn parse_block(&mut self) -> Result<Block, Diagnostic> {
+        let restriction = std::mem::replace(&mut self.no_struct_literal, false);
+        let block = self.parse_block_inner();
+        self.no_struct_literal = restriction;
+        block
+    }
+
+    fn parse_block_inner(&mut self) -> Result<Block, Diagnostic> {
+        let span = self.expect(TokenKind::LBrace, "`{`")?;
+        let mut stmts = Vec::new();
+        let mut tail = None;
+
+        while !self.check(&TokenKind::RBrace) {
+            if self.is_at_end() {
+                return Err(self.error("unclosed block, expected `}`"));
+            }
+            match self.peek_kind() {
+                Some(TokenKind::Let) => stmts.push(Stmt::Let(self.parse_let()?)),
+                Some(TokenKind::Const) => stmts.push(Stmt::Const(self.parse_const()?)),
+                Some(TokenKind::Defer) => stmts.push(self.parse_defer()?),
+                Some(TokenKind::Semicolon) => {
+                    self.advance();
+                }
+                _ => {
+                    let expr = self.parse_expr()?;
+                    if self.matches(&TokenKind::Semicolon) {
+                        stmts.push(Stmt::Expr(expr));
+                    } else if self.check(&TokenKind::RBrace) {
+                        tail = Some(self.alloc(expr));
+                    } else if expr.is_block_like() {
+                        stmts.push(Stmt::Expr(expr));
+                    } else {
+                        return Err(self.error("expected `;` or `}` after expression"));
+                    }
+                }
+            }
+        }
+        self.expect(TokenKind::RBrace, "`}`")?;
+
+        Ok(Block { stmts, tail, span })
+    }
+
+    /// `defer expr;`, where the `;` is optional after a block-like expression
+    fn parse_defer(&mut self) -> Result<Stmt, Diagnostic> {
+        self.expect(TokenKind::Defer, "`defer`")?;
+        let expr = self.parse_expr()?;
+        if !self.matches(&TokenKind::Semicolon) && !expr.is_block_like() {
+            return Err(self.error("expected `;` after deferred expression"));
+        }
+        Ok(Stmt::Defer(expr))
+    }
+
+    fn parse_let(&mut self) -> Result<LetStmt, Diagnostic> {
+        let span = self.expect(TokenKind::Let, "`let`")?;
+        let mutable = self.matches(&TokenKind::Mut);
+        let name = self.expect_identifier()?;
+        let ty = if self.matches(&TokenKind::Colon) {
+            Some(self.parse_type()?)
+        } else {
+            None
+        };
+        let value = if self.matches(&TokenKind::Eq) {
+            Some(self.parse_expr()?)
+        } else {
+            None
+        };
+        self.expect(TokenKind::Semicolon, "`;` after let binding")?;
+        Ok(LetStmt {
+            name,
+            mutable,
+            ty,
+            value,
+            span,
+        })
+    }
+
+    pub fn parse_expr(&mut self) -> Result<Expr, Diagnostic> {
+        self.nested(Self::parse_assignment)
+    }
+
+    fn parse_assignment(&mut self) -> Result<Expr, Diagnostic> {
+        let target = self.parse_range()?;
+        if self.check(&TokenKind::Eq) {
+            let span = self.current_span();
+            self.advance();
+            let value = self.nested(Self::parse_assignment)?;
+            return Ok(Expr::new(
+                ExprKind::Assign(self.alloc(target), self.alloc(value)),
+                span,
+            ));
+        }
+        Ok(target)
+    }
+
+    /// `start..end` and `start..=end`, binding looser than any binary
+    /// operator; `..` may leave out either end
+    fn parse_range(&mut self) -> Result<Expr, Diagnostic> {
+        let start = if self.check(&TokenKind::DotDot) || self.check(&TokenKind::DotDotEq) {
+            None
+        } else {
+            let start = self.parse_binary(0)?;
+            if !self.check(&TokenKind::DotDot) && !self.check(&TokenKind::DotDotEq) {
+                return Ok(start);
+            }
+            Some(start)
+        };
+        let span = self.current_span();
+        let inclusive = self.check(&TokenKind::DotDotEq);
+        self.advance();
+        let end = if self.ends_range() {
+            if inclusive {
+                return Err(self.error("expected the end of an inclusive range"));
+            }
+            None
+        } else {
+            Some(self.nested(|p| p.parse_binary(0))?)
+        };
+        let start = start.map(|start| self.alloc(start));
+        let end = end.map(|end| self.alloc(end));
+        Ok(Expr::new(ExprKind::Range(start, end, inclusive), span))
+    }
+
+    /// Whether the next token ends a range with no end, as in `xs[1..]` or
+    /// `for i in 0.. {`
+    fn ends_range(&self) -> bool {
+        match self.peek_kind() {
+            None => true,
+            Some(kind) => {
+                matches!(
+                    kind,
+                    TokenKind::RBracket
+                        | TokenKind::RParen
+                        | TokenKind::RBrace
+                        | TokenKind::Comma
+                        | TokenKind::Semicolon
+                        | TokenKind::FatArrow
+                        | TokenKind::Eq
+                        | TokenKind::Eof
+                ) || (*kind == TokenKind::LBrace && self.no_struct_literal)
+            }
+        }
+    }
+
+    fn parse_binary(&mut self, min_precedence: u8) -> Result<Expr, Diagnostic> {
+        let mut left = self.parse_cast()?;
+
+        while let Some((op, precedence)) = self.peek_kind().and_then(binary_op) {
+            if precedence < min_precedence {
+                break;
+            }
+            let span = self.current_span();
+            self.advance();
+            self.deeper()?;
+            let right = self.nested(|p| p.parse_binary(precedence + 1))?;
+            left = Expr::new(
+                ExprKind::Binary(op, self.alloc(left), self.alloc(right)),
+                span,
+            );
+        }
+
+        Ok(left)
+    }
+
+    /// `value as ty`, binding tighter than binary operators and looser than
+    /// unary ones; the type is a name, so that `x as i64 < y` is a
+    /// comparison
+    fn parse_cast(&mut self) -> Result<Expr, Diagnostic> {
+        let mut value = self.parse_unary()?;
+        while self.peek_kind() == Some(&TokenKind::As) {
+            let span = self.current_span();
+            self.advance();
+            let type_span = self.current_span();
+            let name = self.expect_identifier()?;
+            value = Expr::new(
+                ExprKind::Cast(self.alloc(value), TypeExpr::Named(name, type_span)),
+                span,
+            );
+        }
+        Ok(value)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, Diagnostic> {
+        let span = self.current_span();
+        let op = match self.peek_kind() {
+            Some(TokenKind::Minus) => UnaryOp::Neg,
+            Some(TokenKind::Not) => UnaryOp::Not,
+            Some(TokenKind::Star) => UnaryOp::Deref,
+            Some(TokenKind::Ampersand) => {
+                if self.peek_kind_at(1) == Some(&TokenKind::Mut) {
+                    self.advance();
+                    UnaryOp::RefMut
+                } else {
+                    UnaryOp::Ref
+                }
+            }
+            _ => return self.parse_postfix(),
+        };
+        self.advance();
+        let operand = self.nested(Self::parse_unary)?;
+        Ok(Expr::new(ExprKind::Unary(op, self.alloc(operand)), span))
+    }
+
+    fn parse_postfix(&mut self) -> Result<Expr, Diagnostic> {
+        let mut expr = self.parse_primary()?;
+
+        loop {
+            let span = self.current_span();
+            if matches!(
+                self.peek_kind(),
+                Some(TokenKind::LParen | TokenKind::LBracket | TokenKind::Dot)
+            ) {
+                self.deeper()?;
+            }
+            if self.matches(&TokenKind::LParen) {
+                let args = self.parse_call_args()?;
+                expr = Expr::new(ExprKind::Call(self.alloc(expr), args), span);
+            } else if self.matches(&TokenKind::LBracket) {
+                let index = self.parse_unrestricted_expr()?;
+                self.expect(TokenKind::RBracket, "`]`")?;
+                expr = Expr::new(ExprKind::Index(self.alloc(expr), self.alloc(index)), span);
+            } else if self.matches(&TokenKind::Dot) {
+                // `.0` is the value inside a newtype
+                let name = if self.check(&TokenKind::Integer(0, None)) {
+                    self.advance();
+                    Symbol::intern("0")
+                } else {
+                    self.expect_identifier()?
+                };
+                if self.matches(&TokenKind::LParen) {
+                    let args = self.parse_call_args()?;
+                    expr = Expr::new(ExprKind::MethodCall(self.alloc(expr), name, args), span);
+                } else {
+                    expr = Expr::new(ExprKind::Field(self.alloc(expr), name), span);
+                }
+            } else {
+                break;
+            }
+        }
+
+        Ok(expr)
+    }
+
+    /// Arguments after the opening `(`, consuming the closing `)`
+    fn parse_call_args(&mut self) -> Result<Vec<Expr>, Diagnostic> {
+        let mut args = Vec::new();
+        while !self.check(&TokenKind::RParen) {
+            args.push(self.parse_unrestricted_expr()?);
+            if !self.check(&TokenKind::RParen) {
+                self.expect(TokenKind::Comma, "`,` or `)`")?;
+            }
+        }
+        self.expect(TokenKind::RParen, "`)`")?;
+        Ok(args)
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, Diagnostic> {
+        let span = self.current_span();
+        let kind = match self.peek_kind() {
+            Some(kind) => kind.clone(),
+            None => return Err(self.error("expected expression")),
+        };
+
+        match kind {
+            TokenKind::Integer(n, suffix) => {
+                self.advance();
+                Ok(Expr::new(ExprKind::Int(n, suffix), span))
+            }
+            TokenKind::Float(f, suffix) => {
+                self.advance();
+                Ok(Expr::new(ExprKind::Float(f, suffix), span))
+            }
+            TokenKind::String(s) => {
+                self.advance();
+                Ok(Expr::new(ExprKind::Str(s), span))
+            }
+            TokenKind::Tilde => {
+                self.advance();
+                let name = self.expect_identifier()?;
+                self.expect(TokenKind::LParen, "`(` after distribution name")?;
+                let args = self.parse_call_args()?;
+                Ok(Expr::new(ExprKind::Distribution(name, args), span))
+            }
+            TokenKind::True | TokenKind::False => {
+                self.advance();
+                Ok(Expr::new(ExprKind::Bool(kind == TokenKind::True), span))
+            }
+            TokenKind::Identifier(name)
+                if name == "asm" && self.peek_kind_at(1) == Some(&TokenKind::Not) =>
+            {
+                self.parse_asm()
+            }
+            TokenKind::Identifier(name) => {
+                self.advance();
+                let mut segments = vec![self.resolve_self(name)];
+                while self.matches(&TokenKind::DoubleColon) {
+                    segments.push(self.expect_identifier()?);
+                }
+                if segments.len() == 1 && self.check(&TokenKind::LBrace) && !self.no_struct_literal
+                {
+                    return self.parse_struct_literal(segments[0], span);
+                }
+                Ok(Expr::new(ExprKind::Path(segments), span))
+            }
+            TokenKind::LParen => {
+                self.advance();
+                let expr = self.parse_unrestricted_expr()?;
+                self.expect(TokenKind::RParen, "`)`")?;
+                Ok(expr)
+            }
+            TokenKind::LBracket => {
+                self.advance();
+                if self.matches(&TokenKind::RBracket) {
+                    return Ok(Expr::new(ExprKind::Array(Vec::new()), span));
+                }
+                let first = self.parse_unrestricted_expr()?;
+                if self.matches(&TokenKind::Semicolon) {
+                    let len = self.parse_unrestricted_expr()?;
+                    self.expect(TokenKind::RBracket, "`]`")?;
+                    return Ok(Expr::new(
+                        ExprKind::ArrayRepeat(self.alloc(first), self.alloc(len)),
+                        span,
+                    ));
+                }
+                let mut elements = vec![first];
+                while self.matches(&TokenKind::Comma) {
+                    if self.check(&TokenKind::RBracket) {
+                        break;
+                    }
+                    elements.push(self.parse_unrestricted_expr()?);
+                }
+                self.expect(TokenKind::RBracket, "`]`")?;
+                Ok(Expr::new(ExprKind::Array(elements), span))
+            }
+            TokenKind::LBrace => Ok(Expr::new(ExprKind::Block(self.parse_block()?), span)),
+            TokenKind::Arena => {
+                self.advance();
+                let name = match self.peek_kind() {
+                    Some(TokenKind::Identifier(_)) => Some(self.expect_identifier()?),
+                    _ => None,
+                };
+                let allocator = if self.check(&TokenKind::In) {
+                    self.advance();
+                    let allocator = self.parse_condition()?;
+                    Some(self.alloc(allocator))
+                } else {
+                    None
+                };
+                let block = self.parse_block()?;
+                Ok(Expr::new(ExprKind::Arena(name, allocator, block), span))
+            }
+            TokenKind::Unsafe => {
+                self.advance();
+                Ok(Expr::new(ExprKind::Unsafe(self.parse_block()?), span))
+            }
+            TokenKind::If => self.parse_if(),
+            TokenKind::While => {
+                self.advance();
+                let cond = self.parse_condition()?;
+                let body = self.parse_block()?;
+                Ok(Expr::new(ExprKind::While(self.alloc(cond), body), span))
+            }
+            TokenKind::For => {
+                self.advance();
+                let name = self.expect_identifier()?;
+                self.expect(TokenKind::In, "`in`")?;
+                let iterable = self.parse_condition()?;
+                let body = self.parse_block()?;
+                Ok(Expr::new(
+                    ExprKind::For(name, self.alloc(iterable), body),
+                    span,
+                ))
+            }
+            TokenKind::Match => self.parse_match(),
+            TokenKind::Return => {
+                self.advance();
+                // `return` ends at a `,` too, as the value of a `match` arm
+                let value = if self.check(&TokenKind::Semicolon)
+                    || self.check(&TokenKind::RBrace)
+                    || self.check(&TokenKind::Comma)
+                {
+                    None
+                } else {
+                    let value = self.parse_expr()?;
+                    Some(self.alloc(value))
+                };
+                Ok(Expr::new(ExprKind::Return(value), span))
+            }
+            TokenKind::Become => {
+                self.advance();
+                let call = self.parse_expr()?;
+                if !matches!(call.kind, ExprKind::Call(..)) {
+                    return Err(Diagnostic::new(
+                        call.span,
+                        "expected a function call after `become`",
+                    ));
+                }
+                Ok(Expr::new(ExprKind::Become(self.alloc(call)), span))
+            }
+            _ => Err(self.error("expected expression")),
+        }
+    }
+
+    fn parse_if(&mut self) -> Result<Expr, Diagnostic> {
+        let span = self.expect(TokenKind::If, "`if`")?;
+        let cond = self.parse_condition()?;
+        let then_block = self.parse_block()?;
+        let else_branch = if self.matches(&TokenKind::Else) {
+            if self.check(&TokenKind::If) {
+                let else_if = self.nested(Self::parse_if)?;
+                Some(self.alloc(else_if))
+            } else {
+                let else_span = self.current_span();
+                let block = self.parse_block()?;
+                Some(self.alloc(Expr::new(ExprKind::Block(block), else_span)))
+            }
+        } else {
+            None
+        };
+        Ok(Expr::new(
+            ExprKind::If(self.alloc(cond), then_block, else_branch),
+            span,
+        ))
+    }
+
+    /// `match scrutinee { pattern if guard => value, ... }`, where the `,`
+    /// after an arm may be left out when its value is block-like
+    fn parse_match(&mut self) -> Result<Expr, Diagnostic> {
+        let span = self.expect(TokenKind::Match, "`match`")?;
+        let scrutinee = self.parse_condition()?;
+        self.expect(TokenKind::LBrace, "`{`")?;
+        let mut arms = Vec::new();
+        while !self.check(&TokenKind::RBrace) {
+            if self.is_at_end() {
+                return Err(self.error("unclosed `match`, expected `}`"));
+            }
+            let arm_span = self.current_span();
+            let pattern = self.parse_pattern()?;
+            let guard = if self.matches(&TokenKind::If) {
+                let guard = self.parse_unrestricted_expr()?;
+                Some(self.alloc(guard))
+            } else {
+                None
+            };
+            self.expect(TokenKind::FatArrow, "`=>`")?;
+            let value = self.parse_unrestricted_expr()?;
+            let block_like = value.is_block_like();
+            arms.push(Arm {
+                pattern,
+                guard,
+                value: self.alloc(value),
+                span: arm_span,
+            });
+            if !self.matches(&TokenKind::Comma) && !block_like && !self.check(&TokenKind::RBrace) {
+                return Err(self.error("expected `,` or `}` after `match` arm"));
+            }
+        }
+        self.expect(TokenKind::RBrace, "`}`")?;
+        Ok(Expr::new(
+            ExprKind::Match(self.alloc(scrutinee), arms),
+            span,
+        ))
+    }
+
+    /// Alternatives separated by `|`
+    fn parse_pattern(&mut self) -> Result<Pattern, Diagnostic> {
+        let span = self.current_span();
+        let first = self.nested(Self::parse_simple_pattern)?;
+        if !self.check(&TokenKind::Pipe) {
+            return Ok(first);
+        }
+        let mut alternatives = vec![first];
+        while self.matches(&TokenKind::Pipe) {
+            alternatives.push(self.nested(Self::parse_simple_pattern)?);
+        }
+        Ok(Pattern {
+            kind: PatternKind::Or(alternatives),
+            span,
+        })
+    }
+
+    fn parse_simple_pattern(&mut self) -> Result<Pattern, Diagnostic> {
+        let span = self.current_span();
+        let kind = match self.peek_kind().cloned() {
+            Some(TokenKind::Integer(..) | TokenKind::Minus) => {
+                let start = self.parse_int_pattern()?;
+                let inclusive = self.check(&TokenKind::DotDotEq);
+                if !inclusive && !self.check(&TokenKind::DotDot) {
+                    return Ok(start);
+                }
+                self.advance();
+                // `start..` has no end; `start..=` must have one
+                let end = match self.peek_kind() {
+                    Some(TokenKind::Integer(..) | TokenKind::Minus) => {
+                        Some(Box::new(self.parse_int_pattern()?))
+                    }
+                    _ if inclusive => {
+                        return Err(self.error("expected the end of an inclusive range"))
+                    }
+                    _ => None,
+                };
+                PatternKind::Range(Some(Box::new(start)), end, inclusive)
+            }
+            Some(TokenKind::DotDot | TokenKind::DotDotEq) => {
+                let inclusive = self.check(&TokenKind::DotDotEq);
+                self.advance();
+                let end = self.parse_int_pattern()?;
+                PatternKind::Range(None, Some(Box::new(end)), inclusive)
+            }
+            Some(TokenKind::True | TokenKind::False) => {
+                let value = self.check(&TokenKind::True);
+                self.advance();
+                PatternKind::Bool(value)
+            }
+            Some(TokenKind::String(s)) => {
+                self.advance();
+                PatternKind::Str(s)
+            }
+            Some(TokenKind::Identifier(name)) => {
+                self.advance();
+                if self.matches(&TokenKind::DoubleColon) {
+                    let variant = self.expect_identifier()?;
+                    PatternKind::Variant(self.resolve_self(name), variant)
+                } else if name == "Some" && self.matches(&TokenKind::LParen) {
+                    let inner = self.parse_pattern()?;
+                    self.expect(TokenKind::RParen, "`)`")?;
+                    PatternKind::Some(Box::new(inner))
+                } else if name == "None" {
+                    PatternKind::None
+                } else if name == "_" {
+                    PatternKind::Wildcard
+                } else {
+                    PatternKind::Binding(name)
+                }
+            }
+            _ => return Err(self.error("expected pattern")),
+        };
+        Ok(Pattern { kind, span })
+    }
+
+    /// An integer pattern, possibly negative
+    fn parse_int_pattern(&mut self) -> Result<Pattern, Diagnostic> {
+        let span = self.current_span();
+        let negative = self.matches(&TokenKind::Minus);
+        match self.peek_kind() {
+            Some(&TokenKind::Integer(n, suffix)) => {
+                self.advance();
+                let n = if negative { -n } else { n };
+                Ok(Pattern {
+                    kind: PatternKind::Int(n, suffix),
+                    span,
+                })
+            }
+            _ if negative => Err(self.error("expected an integer after `-` in a pattern")),
+            _ => Err(self.error("expected an integer")),
+        }
+    }
+
+    /// `asm!("template", in(reg) a, out(reg) b, inout(freg) c)`, left in
+    /// place by macro expansion
+    fn parse_asm(&mut self) -> Result<Expr, Diagnostic> {
+        let span = self.current_span();
+        self.advance();
+        self.expect(TokenKind::Not, "`!`")?;
+        self.expect(TokenKind::LParen, "`(` after `asm!`")?;
+        let template = match self.peek_kind() {
+            Some(TokenKind::String(template)) => template.to_string(),
+            _ => return Err(self.error("expected an assembly template string")),
+        };
+        self.advance();
+        let mut operands = Vec::new();
+        while self.matches(&TokenKind::Comma) {
+            if self.check(&TokenKind::RParen) {
+                break;
+            }
+            let operand_span = self.current_span();
+            let dir = match self.peek_kind() {
+                Some(TokenKind::In) => AsmDir::In,
+                Some(TokenKind::Identifier(name)) if name == "out" => AsmDir::Out,
+                Some(TokenKind::Identifier(name)) if name == "inout" => AsmDir::InOut,
+                _ => return Err(self.error("expected `in`, `out` or `inout`")),
+            };
+            self.advance();
+            self.expect(TokenKind::LParen, "`(` before the register class")?;
+            let class = self.expect_identifier()?;
+            self.expect(TokenKind::RParen, "`)`")?;
+            let expr = self.parse_unrestricted_expr()?;
+            operands.push(AsmOperand {
+                dir,
+                class: class.to_string(),
+                expr,
+                span: operand_span,
+            });
+        }
+        self.expect(TokenKind::RParen, "`,` or `)`")?;
+        Ok(Expr::new(ExprKind::Asm(template, operands), span))
+    }
+
+    fn parse_struct_literal(&mut self, name: Symbol, span: Span) -> Result<Expr, Diagnostic> {
+        self.expect(TokenKind::LBrace, "`{`")?;
+        let mut fields = Vec::new();
+        while !self.check(&TokenKind::RBrace) {
+            let field_span = self.current_span();
+            let field_name = self.expect_identifier()?;
+            self.expect(TokenKind::Colon, "`:`")?;
+            let value = self.parse_unrestricted_expr()?;
+            fields.push(FieldInit {
+                name: field_name,
+                value,
+                span: field_span,
+            });
+            if !self.check(&TokenKind::RBrace) {
+                self.expect(TokenKind::Comma, "`,` or `}`")?;
+            }
+        }
+        self.expect(TokenKind::RBrace, "`}`")?;
+        Ok(Expr::new(ExprKind::StructLit(name, fields), span))
+    }
+
+    /// `if`/`while` condition, `for` iterable or arena allocator: struct
+    /// literals need
+    /// parentheses here
+    fn parse_condition(&mut self) -> Result<Expr, Diagnostic> {
+        let restriction = std::mem::replace(&mut self.no_struct_literal, true);
+        let cond = self.parse_expr();
+        self.no_struct_literal = restriction;
+        cond
+    }
+
+    /// Expression inside delimiters, where struct literals are always allowed
+    fn parse_unrestricted_expr(&mut self) -> Result<Expr, Diagnostic> {
+        let restriction = std::mem::replace(&mut self.no_struct_literal, false);
+        let expr = self.parse_expr();
+        self.no_struct_literal = restriction;
+        expr
+    }
+
+    /// Run `parse` one level of nesting deeper; levels added by [`deeper`]
+    /// inside it end with it
+    ///
+    /// [`deeper`]: Self::deeper
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, Diagnostic>,
+    ) -> Result<T, Diagnostic> {
+        let depth = self.depth;
+        self.deeper()?;
+        let result = parse(self);
+        self.depth = depth;
+        result
+    }
+
+    /// Go one level of nesting deeper, for a node wrapping the one before
+    fn deeper(&mut self) -> Result<(), Diagnostic> {
+        if self.depth >= NESTING_LIMIT {
+            return Err(Diagnostic::new(
+                self.current_span(),
+                format!("nesting depth limit ({}) reached", NESTING_LIMIT),
+            ));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    /// Replace `Self` with the type of the enclosing `impl` block
+    fn resolve_self(&self, name: Symbol) -> Symbol {
+        match self.impl_type {
+            Some(impl_type) if name == "Self" => impl_type,
+            _ => name,
+        }
+    }
+
+    // Token helpers
+
+    fn peek_kind(&self) -> Option<&TokenKind> {
+        self.tokens.get(self.position).map(|t| &t.kind)
+    }
+
+    fn peek_kind_at(&self, offset: usize) -> Option<&TokenKind> {
+        self.tokens.get(self.position + offset).map(|t| &t.kind)
+    }
+
+    fn check(&self, kind: &TokenKind) -> bool {
+        self.peek_kind() == Some(kind)
+    }
+
+    fn matches(&mut self, kind: &TokenKind) -> bool {
+        if self.check(kind) {
+            self.advance();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn advance(&mut self) {
+        if self.position < self.tokens.len() {
+            self.position += 1;
+        }
+    }
+
+    fn is_at_end(&self) -> bool {
+        self.position >= self.tokens.len()
+    }
+
+    fn current_span(&self) -> Span {
+        match self.tokens.get(self.position).or(self.tokens.last()) {
+            Some(token) => Span::new(token.line, token.column),
+            None => Span::new(1, 1),
+        }
+    }
+
+    fn expect(&mut self, kind: TokenKind, what: &str) -> Result<Span, Diagnostic> {
+        let span = self.current_span();
+        if self.matches(&kind) {
+            Ok(span)
+        } else {
+            Err(self.error(&format!("expected {}", what)))
+        }
+    }
+
+    fn expect_identifier(&mut self) -> Result<Symbol, Diagnostic> {
+        match self.peek_kind() {
+            Some(&TokenKind::Identifier(name)) => {
+                self.advance();
+                Ok(name)
+            }
+            _ => Err(self.error("expected identifier")),
+        }
+    }
+
+    /// Generic parameters are planned but not parsed yet
+    fn reject_generics(&self) -> Result<(), Diagnostic> {
+        if !self.check(&TokenKind::Lt) {
+            return Ok(());
+        }
+        let generics = feature::lookup("generics").expect("generics are registered");
+        Err(Diagnostic::new(
+            self.current_span(),
+            generics.not_implemented(),
+        ))
+    }
+
+    /// A syntax error, or what is missing when the offending token or the
+    /// one before it starts the syntax of a planned feature
+    fn error(&self, msg: &str) -> Diagnostic {
+        let start = self.position.saturating_sub(1);
+        let planned = self.tokens[start..]
+            .iter()
+            .take(2)
+            .find_map(|token| Some((token, feature::planned_syntax(&token.lexeme)?)));
+        if let Some((token, feature)) = planned {
+            return Diagnostic::new(
+                Span::new(token.line, token.column),
+                feature.not_implemented(),
+            );
+        }
+        let found = match self.tokens.get(self.position) {
+            Some(token) => format!("`{}`", token.lexeme),
+            None => "end of input".to_string(),
+        };
+        Diagnostic::new(self.current_span(), format!("{}, found {}", msg, found))
+    }
+}
+
+/// Binary operator and precedence for a token
+fn binary_op(kind: &TokenKind) -> Option<(BinaryOp, u8)> {
+    let op = match kind {
+        TokenKind::Or => BinaryOp::Or,
+        TokenKind::And => BinaryOp::And,
+        TokenKind::EqEq => BinaryOp::Eq,
+        TokenKind::Ne => BinaryOp::Ne,
+        TokenKind::Lt => BinaryOp::Lt,
+        TokenKind::Le => BinaryOp::Le,
+        TokenKind::Gt => BinaryOp::Gt,
+        TokenKind::Ge => BinaryOp::Ge,
+        TokenKind::Plus => BinaryOp::Add,
+        TokenKind::Minus => BinaryOp::Sub,
+        TokenKind::Star => BinaryOp::Mul,
+        TokenKind::Slash => BinaryOp::Div,
+        TokenKind::Percent => BinaryOp::Rem,
+        _ => return None,
+    };
+    Some((op, op.precedence()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    fn parse_source(source: &str) -> Result<Program, String> {
+        let mut lexer = Lexer::new(source);
+        let mut tokens = Vec::new();
+        loop {
+            let token = lexer.next_token();
+            if token.kind == TokenKind::Eof {
+                break;
+            }
+            tokens.push(token);
+        }
+        parse(tokens).map_err(|err| err.to_string())
+    }
+
+    #[test]
+    fn test_function() {
+        let program = parse_source("fn add(a: i32, b: i32) -> i32 { a + b }").unwrap();
+        match &program.items[0] {
+            Item::Function(f) => {
+                assert_eq!(f.name, "add");
+                assert_eq!(f.params.len(), 2);
+                assert!(f.body.tail.is_some());
+            }
+            _ => panic!("expected function"),
+        }
+    }
+
+    #[test]
+    fn test_precedence() {
+        let program = parse_source("const X: i64 = 1 + 2 * 3;").unwrap();
+        let Item::Const(c) = &program.items[0] else {
+            panic!("expected const");
+        };
+        match &c.value.kind {
+            ExprKind::Binary(BinaryOp::Add, _, rhs) => {
+                assert!(matches!(
+                    program[*rhs].kind,
+                    ExprKind::Binary(BinaryOp::Mul, _, _)
+                ));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_statements() {
+        let source =
+            "fn main() { let mut x = 0; while x < 10 { x = x + 1; } if x == 10 { return; } }";
+        let program = parse_source(source).unwrap();
+        let Item::Function(f) = &program.items[0] else {
+            panic!("expected function");
+        };
+        assert_eq!(f.body.stmts.len(), 2);
+        assert!(matches!(
+            f.body.tail.map(|e| &program[e].kind),
+            Some(ExprKind::If(..))
+        ));
+    }
+
+    #[test]
+    fn test_enum_and_array_type() {
+        let source = "enum Color { Red = 1, Green, Blue = 8 } fn f(xs: [i64; 4]) {}";
+        let program = parse_source(source).unwrap();
+        let Item::Enum(e) = &program.items[0] else {
+            panic!("expected enum");
+        };
+        assert_eq!(e.variants.len(), 3);
+        assert!(e.variants[1].discriminant.is_none());
+    }
+
+    #[test]
+    fn test_struct_literal_and_field_access() {
+        let source = "#[derive(Csv)] struct P { x: i64, y: i64 }
+            fn main() { let p = P { x: 1, y: 2 }; if p.x == (P { x: 0, y: 0 }).x { } }";
+        let program = parse_source(source).unwrap();
+        let Item::Struct(s) = &program.items[0] else {
+            panic!("expected struct");
+        };
+        assert_eq!(s.fields.len(), 2);
+        assert_eq!(
+            s.derives().map(|(name, _)| name).collect::<Vec<_>>(),
+            ["Csv"]
+        );
+        let Item::Function(f) = &program.items[1] else {
+            panic!("expected function");
+        };
+        let Some(ExprKind::If(cond, ..)) = f.body.tail.map(|e| &program[e].kind) else {
+            panic!("expected if");
+        };
+        assert!(matches!(
+            program[*cond].kind,
+            ExprKind::Binary(BinaryOp::Eq, _, _)
+        ));
+    }
+
+    #[test]
+    fn test_impl_methods() {
+        let source = "impl Counter {
+                fn new() -> Self { Self { n: 0 } }
+                fn bump(&mut self, by: i64) { self.n = self.n + by; }
+            }
+            fn main() { let mut c = Counter::new(); c.bump(2); }";
+        let program = parse_source(source).unwrap();
+        let Item::Impl(block) = &program.items[0] else {
+            panic!("expected impl");
+        };
+        assert_eq!(block.methods[0].receiver, None);
+        assert!(
+            matches!(&block.methods[0].return_type, Some(TypeExpr::Named(n, _)) if n == "Counter")
+        );
+        assert_eq!(block.methods[1].receiver, Some(Receiver::RefMut));
+        assert_eq!(block.methods[1].params.len(), 1);
+        assert_eq!(program.functions()[1].0, "Counter::bump");
+
+        let err = parse_source("fn f(&self) {}").unwrap_err();
+        assert!(
+            err.contains("`self` parameter is only allowed in methods"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_error_location() {
+        let err = parse_source("fn main() {\n    let x = ;\n}").unwrap_err();
+        assert!(err.starts_with("Parse error at 2:13"), "{}", err);
+    }
+
+    #[test]
+    fn test_nesting_limit() {
+        let nested = |depth| {
+            format!(
+                "const X: i64 = {}1{};",
+                "(".repeat(depth),
+                ")".repeat(depth)
+            )
+        };
+        let check = move || {
+            assert!(crate::check(&nested(250)).is_ok());
+            let err = parse_source(&nested(300)).unwrap_err();
+            assert!(err.contains("nesting depth limit (256) reached"), "{}", err);
+            let chain = format!("const X: i64 = 1{};", " + 1".repeat(10_000));
+            assert!(parse_source(&chain).is_err());
+        };
+        std::thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn(check)
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+}
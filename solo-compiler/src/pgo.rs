@@ -0,0 +1,207 @@
+//! Profile-guided optimization
+//!
+//! `solo run --profile-generate` counts how often each function is called
+//! and which way each `if` and `while` condition goes, adding the counts to
+//! a profraw file (the format of [`crate::coverage`]) so that several
+//! representative runs build up one profile. `solo build --profile-use`
+//! turns the profile into a [`Plan`] for code generation:
+//!
+//! - hot functions, taking at least [`HOT_SHARE`] of all calls, are to be
+//!   inlined at their call sites, unless larger than [`INLINE_LIMIT`]
+//!   expressions or recursive
+//! - functions the profile never saw called are cold, to be placed apart
+//!   from the rest so hot code shares fewer cache lines with them
+//! - a condition that went one way at least [`BIAS`] times as often as the
+//!   other is to have its likely successor laid out as the fall-through
+//!   block
+//!
+//! There is no code generation to apply a plan yet, so `solo build` only
+//! reports it.
+//!
+//! A profile is only used with the source it was recorded for: its
+//! checksum covers every function, line and condition.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::ast::{Expr, ExprKind, Exprs, Function, Program};
+use crate::coverage::Coverage;
+use crate::visit::{self, Visit};
+
+/// Share of all calls, in percent, that makes a function hot
+pub const HOT_SHARE: u64 = 10;
+
+/// Largest hot function inlined, in expressions
+pub const INLINE_LIMIT: usize = 40;
+
+/// How many times more often one outcome of a condition must occur than
+/// the other for it to be laid out as likely
+pub const BIAS: u64 = 4;
+
+/// What a profile would tell code generation
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Plan {
+    /// Functions to inline at every call site, with their calls
+    pub inline: Vec<(String, u64)>,
+    /// Functions never called
+    pub cold: Vec<String>,
+    /// Whether the likely outcome of each biased condition, by line and
+    /// column, is `true`
+    pub likely: BTreeMap<(usize, usize), bool>,
+    /// Conditions evaluated at least once
+    pub profiled_branches: usize,
+}
+
+impl Plan {
+    /// The plan for `program` given the counts of `profile`, which must
+    /// have been instrumented from the same program
+    pub fn new(program: &Program, profile: &Coverage) -> Self {
+        let functions: BTreeMap<String, &Function> = program
+            .functions()
+            .into_iter()
+            .map(|(name, _, function)| (name, function))
+            .collect();
+        let total: u64 = profile.calls().map(|(_, calls)| calls).sum();
+        let mut plan = Plan::default();
+        for (name, calls) in profile.calls() {
+            // `main` and tests have no call sites to inline into
+            let Some(function) = functions
+                .get(name)
+                .filter(|f| f.name != "main" && !f.is_test())
+            else {
+                continue;
+            };
+            if calls == 0 {
+                plan.cold.push(name.to_string());
+            } else if calls.saturating_mul(100) >= total.saturating_mul(HOT_SHARE) {
+                let mut body = Body {
+                    exprs: &program.exprs,
+                    name: &function.name,
+                    size: 0,
+                    recursive: false,
+                };
+                body.visit_block(&function.body);
+                if body.size <= INLINE_LIMIT && !body.recursive {
+                    plan.inline.push((name.to_string(), calls));
+                }
+            }
+        }
+        for (position, [taken, not_taken]) in profile.branches() {
+            if taken + not_taken == 0 {
+                continue;
+            }
+            plan.profiled_branches += 1;
+            if taken >= not_taken.saturating_mul(BIAS) {
+                plan.likely.insert(position, true);
+            } else if not_taken >= taken.saturating_mul(BIAS) {
+                plan.likely.insert(position, false);
+            }
+        }
+        plan
+    }
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, calls) in &self.inline {
+            writeln!(f, "would inline hot function `{}` ({} calls)", name, calls)?;
+        }
+        for name in &self.cold {
+            writeln!(f, "would place cold function `{}` apart", name)?;
+        }
+        writeln!(
+            f,
+            "{} function(s) to inline, {} cold, {} of {} profiled branch(es) biased; \
+             not applied until code generation lands",
+            self.inline.len(),
+            self.cold.len(),
+            self.likely.len(),
+            self.profiled_branches
+        )
+    }
+}
+
+/// Measures a function body and finds calls of the function itself
+struct Body<'a> {
+    exprs: &'a Exprs,
+    name: &'a str,
+    size: usize,
+    recursive: bool,
+}
+
+impl<'a> Visit<'a> for Body<'a> {
+    fn exprs(&self) -> &'a Exprs {
+        self.exprs
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        self.size += 1;
+        match &expr.kind {
+            ExprKind::Call(callee, _) => {
+                if let ExprKind::Path(path) = &self.exprs[*callee].kind {
+                    self.recursive |= path.last().is_some_and(|last| last == self.name);
+                }
+            }
+            ExprKind::MethodCall(_, method, _) => self.recursive |= method == self.name,
+            _ => {}
+        }
+        visit::walk_expr(self, expr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Span;
+    use crate::Phase;
+
+    #[test]
+    fn test_plan() {
+        let source = "fn small(x: i64) -> i64 {
+    if x > 0 {
+        x
+    } else {
+        0 - x
+    }
+}
+fn fact(n: i64) -> i64 {
+    if n == 0 {
+        return 1;
+    }
+    n * fact(n - 1)
+}
+fn never() {}
+fn main() {
+    print(small(1) + fact(3));
+}
+";
+        let program = crate::compile_until(source, Phase::Parse)
+            .unwrap()
+            .into_ast()
+            .unwrap();
+        let mut profile = Coverage::instrument(&program);
+        profile.hit_function("main");
+        for _ in 0..10 {
+            profile.hit_function("small");
+            profile.hit_branch(Span::new(2, 5), true);
+        }
+        for n in 0..4 {
+            profile.hit_function("fact");
+            profile.hit_branch(Span::new(9, 5), n == 3);
+        }
+
+        let plan = Plan::new(&program, &profile);
+        // `fact` is hot too, but recursive
+        assert_eq!(plan.inline, [("small".to_string(), 10)]);
+        assert_eq!(plan.cold, ["never"]);
+        // 3 to 1 is not biased enough
+        assert_eq!(plan.likely, BTreeMap::from([((2, 5), true)]));
+        assert_eq!(
+            plan.to_string().lines().last(),
+            Some(
+                "1 function(s) to inline, 1 cold, 1 of 2 profiled branch(es) biased; \
+                 not applied until code generation lands"
+            )
+        );
+    }
+}
@@ -0,0 +1,343 @@
+//! HTTP backend for an online playground
+//!
+//! `solo serve --playground` answers plain HTTP/1.1 requests whose body is
+//! Solo source, so a web page (behind a reverse proxy) can check and run
+//! programs without a JSON-RPC client:
+//!
+//! | Request       | Response fields                                                                  |
+//! |---------------|----------------------------------------------------------------------------------|
+//! | `POST /check` | `ok`, `diagnostics`                                                              |
+//! | `POST /run`   | `ok`, `diagnostics`, `output`, `stderr`, `exit_code`, `timed_out`, `duration_ms` |
+//!
+//! Diagnostics are the objects of `--message-format=json`, in a file named
+//! [`FILE_NAME`]. `/check` runs in the server process; `/run` compiles and
+//! runs the program in a [`sandbox::run_program`] child, killed after the
+//! time limit and (on Linux) denied memory beyond the memory limit. Every
+//! connection carries a single request and is closed after the response.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::json::Json;
+use crate::parser;
+use crate::sandbox::{self, Outcome};
+
+/// Address `solo serve --playground` listens on by default
+pub const DEFAULT_ADDRESS: &str = "127.0.0.1:7700";
+
+/// Name programs are compiled under, as seen in diagnostics
+pub const FILE_NAME: &str = "main.solo";
+
+/// Largest request body accepted, in bytes
+pub const MAX_BODY: usize = 64 * 1024;
+
+/// How long a client may take to send its request
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How the playground runs programs
+#[derive(Debug, Clone)]
+pub struct Playground {
+    /// The `solo` executable spawned for each run
+    pub exe: PathBuf,
+    /// Wall-clock limit of one run
+    pub timeout: Duration,
+    /// Address space limit of one run, in bytes
+    pub memory: Option<u64>,
+    /// Accept `#![feature(...)]`, as `--unstable` does
+    pub unstable: bool,
+}
+
+impl Playground {
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+    pub const DEFAULT_MEMORY: u64 = 256 * 1024 * 1024;
+
+    pub fn new(exe: PathBuf) -> Self {
+        Self {
+            exe,
+            timeout: Self::DEFAULT_TIMEOUT,
+            memory: Some(Self::DEFAULT_MEMORY),
+            unstable: false,
+        }
+    }
+
+    /// Answer one request
+    pub fn handle(&self, request: &Request) -> Response {
+        let run = match request.path.as_str() {
+            "/check" => false,
+            "/run" => true,
+            _ => return Response::error(404, "no such endpoint; use /check or /run"),
+        };
+        if request.method != "POST" {
+            return Response::error(405, "send the source with POST");
+        }
+        let Ok(source) = std::str::from_utf8(&request.body) else {
+            return Response::error(400, "the source is not UTF-8");
+        };
+        if run {
+            self.run(source)
+        } else {
+            self.check(source)
+        }
+    }
+
+    fn check(&self, source: &str) -> Response {
+        let result = if self.unstable {
+            Ok(())
+        } else {
+            crate::check_stable(source)
+        };
+        let diagnostics = match result.and_then(|()| crate::check(source).map(drop)) {
+            Ok(()) => Vec::new(),
+            Err(err) => err.in_file(Path::new(FILE_NAME)).to_json(source),
+        };
+        Response::ok(Json::object([
+            ("ok", Json::Bool(diagnostics.is_empty())),
+            ("diagnostics", Json::Array(diagnostics)),
+        ]))
+    }
+
+    fn run(&self, source: &str) -> Response {
+        let result =
+            sandbox::run_program(&self.exe, source, self.timeout, self.memory, self.unstable);
+        let run = match result {
+            Ok(run) => run,
+            Err(err) => return Response::error(500, &format!("cannot start the sandbox: {}", err)),
+        };
+
+        // The child reports diagnostics as JSON lines; anything else on
+        // stderr (such as the runtime aborting on a failed allocation) is
+        // passed through as text
+        let mut diagnostics = Vec::new();
+        let mut stderr = String::new();
+        for line in run.stderr.lines() {
+            match Json::parse(line) {
+                Ok(diagnostic @ Json::Object(_)) => diagnostics.push(diagnostic),
+                _ => {
+                    stderr.push_str(line);
+                    stderr.push('\n');
+                }
+            }
+        }
+        let exit_code = match run.outcome {
+            Outcome::Passed => Json::Int(0),
+            Outcome::Failed(Some(code)) => Json::Int(code.into()),
+            Outcome::Failed(None) | Outcome::TimedOut => Json::Null,
+        };
+        Response::ok(Json::object([
+            ("ok", Json::Bool(run.outcome == Outcome::Passed)),
+            ("diagnostics", Json::Array(diagnostics)),
+            ("output", Json::Str(run.stdout)),
+            ("stderr", Json::Str(stderr)),
+            ("exit_code", exit_code),
+            ("timed_out", Json::Bool(run.outcome == Outcome::TimedOut)),
+            ("duration_ms", Json::Int(run.duration.as_millis() as i64)),
+        ]))
+    }
+}
+
+/// A parsed HTTP request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub body: Vec<u8>,
+}
+
+/// An HTTP response with a JSON body
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    pub body: Json,
+}
+
+impl Response {
+    pub fn ok(body: Json) -> Self {
+        Self { status: 200, body }
+    }
+
+    /// A failed request, explained by `{"error": message}`
+    pub fn error(status: u16, message: &str) -> Self {
+        Self {
+            status,
+            body: Json::object([("error", Json::str(message))]),
+        }
+    }
+
+    /// Write the response with the headers a browser needs
+    pub fn write_to(&self, writer: &mut impl Write) -> io::Result<()> {
+        let body = self.body.to_string();
+        write!(
+            writer,
+            "HTTP/1.1 {} {}\r\n\
+             Content-Type: application/json\r\n\
+             Content-Length: {}\r\n\
+             Connection: close\r\n\
+             \r\n\
+             {}",
+            self.status,
+            reason(self.status),
+            body.len(),
+            body
+        )?;
+        writer.flush()
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        411 => "Length Required",
+        413 => "Payload Too Large",
+        _ => "Internal Server Error",
+    }
+}
+
+/// Read one request: the request line, headers, and a body of at most
+/// [`MAX_BODY`] bytes sized by `Content-Length`
+pub fn read_request(reader: &mut impl BufRead) -> Result<Request, Response> {
+    let bad = |_| Response::error(400, "malformed request");
+    let mut line = String::new();
+    reader.read_line(&mut line).map_err(bad)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target), Some(version)) = (parts.next(), parts.next(), parts.next())
+    else {
+        return Err(Response::error(400, "malformed request line"));
+    };
+    if !version.starts_with("HTTP/1.") {
+        return Err(Response::error(400, "only HTTP/1.x is supported"));
+    }
+    // The query string, if any, is ignored
+    let path = target.split('?').next().unwrap_or(target).to_string();
+    let method = method.to_string();
+
+    let mut length = None;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).map_err(bad)? == 0 {
+            return Err(Response::error(400, "headers end early"));
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        let Some((name, value)) = header.split_once(':') else {
+            return Err(Response::error(400, "malformed header"));
+        };
+        if name.eq_ignore_ascii_case("content-length") {
+            let value = value.trim().parse::<usize>();
+            length = Some(value.map_err(|_| Response::error(400, "bad Content-Length"))?);
+        } else if name.eq_ignore_ascii_case("transfer-encoding") {
+            return Err(Response::error(411, "send a Content-Length, not chunks"));
+        }
+    }
+
+    let body = match length {
+        Some(length) if length > MAX_BODY => {
+            return Err(Response::error(413, "the source is too large"));
+        }
+        Some(length) => {
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).map_err(bad)?;
+            body
+        }
+        None if method == "POST" => {
+            return Err(Response::error(411, "a Content-Length is required"));
+        }
+        None => Vec::new(),
+    };
+    Ok(Request { method, path, body })
+}
+
+/// Serve every connection to `listener` on its own thread
+pub fn serve(listener: TcpListener, playground: Playground) -> io::Result<()> {
+    let playground = Arc::new(playground);
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let playground = Arc::clone(&playground);
+        thread::Builder::new()
+            .stack_size(parser::STACK_SIZE)
+            .spawn(move || {
+                // A client going away mid-request is not the server's problem
+                let _ = handle_connection(stream, &playground);
+            })?;
+    }
+    Ok(())
+}
+
+fn handle_connection(stream: TcpStream, playground: &Playground) -> io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut writer = stream.try_clone()?;
+    let response = match read_request(&mut BufReader::new(stream)) {
+        Ok(request) => playground.handle(&request),
+        Err(response) => response,
+    };
+    response.write_to(&mut writer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(text: &str) -> Result<Request, Response> {
+        read_request(&mut text.as_bytes())
+    }
+
+    #[test]
+    fn test_read_request() {
+        let parsed =
+            request("POST /run?v=1 HTTP/1.1\r\nHost: x\r\nContent-Length: 5\r\n\r\nhello").unwrap();
+        assert_eq!(parsed.method, "POST");
+        assert_eq!(parsed.path, "/run");
+        assert_eq!(parsed.body, b"hello");
+
+        let status = |text: &str| request(text).unwrap_err().status;
+        assert_eq!(status("POST /run HTTP/1.1\r\n\r\n"), 411);
+        assert_eq!(status("nonsense\r\n\r\n"), 400);
+        let large = format!(
+            "POST /run HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            MAX_BODY + 1
+        );
+        assert_eq!(status(&large), 413);
+    }
+
+    #[test]
+    fn test_check_and_routes() {
+        let playground = Playground::new(PathBuf::from("solo"));
+        let post = |path: &str, body: &str| Request {
+            method: "POST".to_string(),
+            path: path.to_string(),
+            body: body.as_bytes().to_vec(),
+        };
+
+        let response = playground.handle(&post("/check", "fn main() { print(1); }"));
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body.get("ok"), Some(&Json::Bool(true)));
+
+        let response = playground.handle(&post("/check", "fn main() { let x: i32 = true; }"));
+        assert_eq!(response.body.get("ok"), Some(&Json::Bool(false)));
+        let Some(Json::Array(diagnostics)) = response.body.get("diagnostics") else {
+            panic!("no diagnostics in {}", response.body);
+        };
+        assert!(diagnostics[0].to_string().contains(FILE_NAME));
+
+        assert_eq!(playground.handle(&post("/missing", "")).status, 404);
+        let get = Request {
+            method: "GET".to_string(),
+            ..post("/run", "")
+        };
+        assert_eq!(playground.handle(&get).status, 405);
+
+        let mut written = Vec::new();
+        response.write_to(&mut written).unwrap();
+        let written = String::from_utf8(written).unwrap();
+        assert!(written.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(written.ends_with(&response.body.to_string()));
+    }
+}
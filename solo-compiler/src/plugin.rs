@@ -0,0 +1,134 @@
+//! External subcommand discovery
+//!
+//! `solo foo args...` runs the first executable named `solo-foo` on `PATH`,
+//! passing `args...` through unchanged. The plugin receives a JSON context
+//! blob in the `SOLO_CONTEXT` environment variable describing the invoking
+//! compiler:
+//!
+//! ```json
+//! {"version":"0.1.0","solo":"/usr/local/bin/solo","cwd":"/home/me/project","command":"foo"}
+//! ```
+
+use std::env;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::json::Json;
+
+/// Prefix of plugin executable names
+pub const PREFIX: &str = "solo-";
+
+/// Environment variable carrying the JSON context blob
+pub const CONTEXT_VAR: &str = "SOLO_CONTEXT";
+
+/// Find the plugin executable for `command` on `PATH`
+pub fn find(command: &str) -> Option<PathBuf> {
+    find_in(command, &env::var_os("PATH")?)
+}
+
+/// Find the plugin executable for `command` in a `PATH`-style list
+pub fn find_in(command: &str, path: &OsStr) -> Option<PathBuf> {
+    if command.is_empty() || command.contains(['/', '\\']) {
+        return None;
+    }
+    let name = format!("{}{}{}", PREFIX, command, env::consts::EXE_SUFFIX);
+    env::split_paths(path)
+        .map(|dir| dir.join(&name))
+        .find(|candidate| is_executable(candidate))
+}
+
+/// Names of all plugins on `PATH`, sorted and deduplicated
+pub fn list() -> Vec<String> {
+    let Some(path) = env::var_os("PATH") else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = env::split_paths(&path)
+        .filter_map(|dir| dir.read_dir().ok())
+        .flatten()
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let file_name = entry.file_name().into_string().ok()?;
+            let stem = file_name.strip_suffix(env::consts::EXE_SUFFIX)?;
+            let command = stem.strip_prefix(PREFIX)?;
+            (!command.is_empty() && is_executable(&entry.path())).then(|| command.to_string())
+        })
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// JSON context passed to plugins
+pub fn context_json(command: &str) -> String {
+    let solo = env::current_exe()
+        .map(|p| p.display().to_string())
+        .unwrap_or_default();
+    let cwd = env::current_dir()
+        .map(|p| p.display().to_string())
+        .unwrap_or_default();
+    Json::object([
+        ("version", Json::str(env!("CARGO_PKG_VERSION"))),
+        ("solo", Json::Str(solo)),
+        ("cwd", Json::Str(cwd)),
+        ("command", Json::str(command)),
+    ])
+    .to_string()
+}
+
+/// Run a plugin, returning its exit code
+pub fn run(program: &Path, command: &str, args: &[String]) -> Result<i32, String> {
+    let status = Command::new(program)
+        .args(args)
+        .env(CONTEXT_VAR, context_json(command))
+        .status()
+        .map_err(|err| format!("failed to run '{}': {}", program.display(), err))?;
+    // Terminated by a signal: report failure like a shell would
+    Ok(status.code().unwrap_or(1))
+}
+
+fn is_executable(path: &Path) -> bool {
+    let Ok(metadata) = path.metadata() else {
+        return false;
+    };
+    if !metadata.is_file() {
+        return false;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        metadata.permissions().mode() & 0o111 != 0
+    }
+    #[cfg(not(unix))]
+    {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_find_in_path() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = env::temp_dir().join(format!("solo-plugin-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let plugin = dir.join("solo-fmt");
+        fs::write(&plugin, "#!/bin/sh\n").unwrap();
+        fs::set_permissions(&plugin, fs::Permissions::from_mode(0o755)).unwrap();
+        let not_executable = dir.join("solo-doc");
+        fs::write(&not_executable, "").unwrap();
+        fs::set_permissions(&not_executable, fs::Permissions::from_mode(0o644)).unwrap();
+
+        let path = env::join_paths([Path::new("/nonexistent"), &dir]).unwrap();
+        assert_eq!(find_in("fmt", &path), Some(plugin));
+        assert_eq!(find_in("doc", &path), None);
+        assert_eq!(find_in("../fmt", &path), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,274 @@
+//! Allocation profiles in pprof format
+//!
+//! With `solo run --profile-heap <file>` the interpreter records the call
+//! stack of every struct, array and belief allocation, and writes them as a
+//! [pprof](https://github.com/google/pprof/blob/main/proto/profile.proto)
+//! profile for `go tool pprof`, speedscope and similar tools. Samples carry
+//! the number of allocations and bytes, and an `arena` label naming the
+//! arena charged (`8:5`), or `heap` outside arenas.
+//!
+//! The profile is an uncompressed protocol buffer, which pprof tools accept
+//! as well as gzipped ones.
+
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::ast::Span;
+
+/// A function in a call stack and the line executing in it
+type Frame = (String, usize);
+
+/// Allocations by call stack, collected by the interpreter
+#[derive(Debug, Clone, Default)]
+pub struct HeapProfile {
+    /// Calls in progress, outermost first, with the line each was made from
+    calls: Vec<(String, usize)>,
+    /// First line of every function seen
+    functions: BTreeMap<String, usize>,
+    /// Allocations and bytes by call stack (innermost first) and arena
+    samples: BTreeMap<(Vec<Frame>, Option<Span>), (i64, i64)>,
+    started: Option<SystemTime>,
+}
+
+impl HeapProfile {
+    pub fn new() -> Self {
+        Self {
+            started: Some(SystemTime::now()),
+            ..Self::default()
+        }
+    }
+
+    /// A call to `function` (defined at `line`) made from `call_line`
+    pub fn enter(&mut self, function: &str, line: usize, call_line: usize) {
+        self.functions.entry(function.to_string()).or_insert(line);
+        self.calls.push((function.to_string(), call_line));
+    }
+
+    pub fn exit(&mut self) {
+        self.calls.pop();
+    }
+
+    /// An allocation of `bytes` at `line` of the current function, charged
+    /// to the arena at `arena` (`None` for the heap)
+    pub fn record(&mut self, line: usize, arena: Option<Span>, bytes: u64) {
+        let mut stack = Vec::with_capacity(self.calls.len());
+        let mut line = line;
+        for (function, call_line) in self.calls.iter().rev() {
+            stack.push((function.clone(), line));
+            line = *call_line;
+        }
+        let sample = self.samples.entry((stack, arena)).or_default();
+        sample.0 += 1;
+        sample.1 += bytes as i64;
+    }
+
+    /// Total allocations and bytes recorded
+    pub fn totals(&self) -> (i64, i64) {
+        self.samples
+            .values()
+            .fold((0, 0), |(count, bytes), s| (count + s.0, bytes + s.1))
+    }
+
+    /// Encode as a pprof `Profile` message, naming `file` as the source
+    pub fn encode(&self, file: &str) -> Vec<u8> {
+        let mut strings = Strings::default();
+        let mut profile = Message::default();
+
+        for (kind, unit) in [("alloc_objects", "count"), ("alloc_space", "bytes")] {
+            let mut value_type = Message::default();
+            value_type.int(1, strings.index(kind));
+            value_type.int(2, strings.index(unit));
+            profile.message(1, &value_type);
+        }
+
+        // Locations are (function, line) pairs, numbered from 1
+        let mut locations: BTreeMap<&Frame, u64> = BTreeMap::new();
+        let arena_key = strings.index("arena");
+        for ((stack, arena), (count, bytes)) in &self.samples {
+            let ids: Vec<u64> = stack
+                .iter()
+                .map(|frame| {
+                    let next = locations.len() as u64 + 1;
+                    *locations.entry(frame).or_insert(next)
+                })
+                .collect();
+            let mut sample = Message::default();
+            sample.packed(1, &ids);
+            sample.packed(2, &[*count as u64, *bytes as u64]);
+            let mut label = Message::default();
+            label.int(1, arena_key);
+            let name = match arena {
+                Some(span) => format!("{}:{}", span.line, span.column),
+                None => "heap".to_string(),
+            };
+            label.int(2, strings.index(&name));
+            sample.message(3, &label);
+            profile.message(2, &sample);
+        }
+
+        let function_ids: BTreeMap<&str, u64> = self
+            .functions
+            .keys()
+            .enumerate()
+            .map(|(i, name)| (name.as_str(), i as u64 + 1))
+            .collect();
+        let mut by_id: Vec<(&Frame, u64)> = locations.into_iter().collect();
+        by_id.sort_by_key(|&(_, id)| id);
+        for ((function, line), id) in by_id {
+            let mut line_message = Message::default();
+            line_message.uint(1, function_ids[function.as_str()]);
+            line_message.int(2, *line as i64);
+            let mut location = Message::default();
+            location.uint(1, id);
+            location.message(4, &line_message);
+            profile.message(4, &location);
+        }
+
+        let file = strings.index(file);
+        for (name, line) in &self.functions {
+            let mut function = Message::default();
+            function.uint(1, function_ids[name.as_str()]);
+            let name = strings.index(name);
+            function.int(2, name);
+            function.int(3, name);
+            function.int(4, file);
+            function.int(5, *line as i64);
+            profile.message(5, &function);
+        }
+
+        let mut period_type = Message::default();
+        period_type.int(1, strings.index("space"));
+        period_type.int(2, strings.index("bytes"));
+
+        for string in &strings.table {
+            profile.bytes(6, string.as_bytes());
+        }
+        if let Some(started) = self.started {
+            let since_epoch = started.duration_since(UNIX_EPOCH).unwrap_or_default();
+            profile.int(9, since_epoch.as_nanos() as i64);
+            profile.int(10, started.elapsed().unwrap_or_default().as_nanos() as i64);
+        }
+        profile.message(11, &period_type);
+        profile.int(12, 1);
+        profile.bytes
+    }
+}
+
+/// The string table; index 0 is always the empty string
+struct Strings {
+    table: Vec<String>,
+    indices: BTreeMap<String, i64>,
+}
+
+impl Default for Strings {
+    fn default() -> Self {
+        Self {
+            table: vec![String::new()],
+            indices: BTreeMap::from([(String::new(), 0)]),
+        }
+    }
+}
+
+impl Strings {
+    fn index(&mut self, s: &str) -> i64 {
+        if let Some(&index) = self.indices.get(s) {
+            return index;
+        }
+        let index = self.table.len() as i64;
+        self.table.push(s.to_string());
+        self.indices.insert(s.to_string(), index);
+        index
+    }
+}
+
+/// A protocol buffer message being encoded
+#[derive(Default)]
+struct Message {
+    bytes: Vec<u8>,
+}
+
+impl Message {
+    const VARINT: u64 = 0;
+    const LENGTH_DELIMITED: u64 = 2;
+
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.bytes.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        self.bytes.push(value as u8);
+    }
+
+    fn key(&mut self, field: u64, wire_type: u64) {
+        self.varint(field << 3 | wire_type);
+    }
+
+    /// A scalar field, omitted when zero as in proto3
+    fn uint(&mut self, field: u64, value: u64) {
+        if value != 0 {
+            self.key(field, Self::VARINT);
+            self.varint(value);
+        }
+    }
+
+    fn int(&mut self, field: u64, value: i64) {
+        self.uint(field, value as u64);
+    }
+
+    fn bytes(&mut self, field: u64, data: &[u8]) {
+        self.key(field, Self::LENGTH_DELIMITED);
+        self.varint(data.len() as u64);
+        self.bytes.extend_from_slice(data);
+    }
+
+    fn message(&mut self, field: u64, message: &Message) {
+        self.bytes(field, &message.bytes);
+    }
+
+    /// A repeated scalar field in packed encoding
+    fn packed(&mut self, field: u64, values: &[u64]) {
+        let mut packed = Message::default();
+        values.iter().for_each(|&value| packed.varint(value));
+        self.bytes(field, &packed.bytes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_profile() {
+        let mut profile = HeapProfile::default();
+        profile.enter("main", 3, 1);
+        profile.record(4, None, 16);
+        profile.enter("make", 1, 5);
+        profile.record(2, Some(Span::new(5, 9)), 16);
+        profile.record(2, Some(Span::new(5, 9)), 16);
+        profile.exit();
+        assert_eq!(profile.totals(), (3, 48));
+        assert_eq!(
+            profile
+                .samples
+                .keys()
+                .map(|(stack, _)| stack.clone())
+                .collect::<Vec<_>>(),
+            [
+                vec![("main".to_string(), 4)],
+                vec![("make".to_string(), 2), ("main".to_string(), 5)],
+            ]
+        );
+
+        let bytes = profile.encode("prog.solo");
+        // sample_type { type: 1 ("alloc_objects"), unit: 2 ("count") }
+        assert_eq!(bytes[..6], [0x0a, 0x04, 0x08, 0x01, 0x10, 0x02]);
+        let text = String::from_utf8_lossy(&bytes);
+        for string in ["alloc_space", "arena", "heap", "5:9", "make", "prog.solo"] {
+            assert!(text.contains(string), "missing {:?}", string);
+        }
+
+        let mut message = Message::default();
+        message.varint(300);
+        assert_eq!(message.bytes, [0xac, 0x02]);
+    }
+}
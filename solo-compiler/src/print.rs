@@ -0,0 +1,221 @@
+//! `solo --print <query>` introspection for build systems and editors
+//!
+//! Queries print plain text by default, or a single JSON document with
+//! `--json`.
+
+use std::path::PathBuf;
+
+use crate::interp::{OverflowMode, PanicStrategy};
+use crate::json::Json;
+use crate::sysroot::Sysroot;
+use crate::target::{Target, TARGETS};
+
+/// Information that can be queried with `--print`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Query {
+    Targets,
+    Sysroot,
+    TargetLibdir,
+    Cfg,
+    Version,
+}
+
+impl Query {
+    pub const ALL: &'static [(&'static str, Query)] = &[
+        ("targets", Query::Targets),
+        ("sysroot", Query::Sysroot),
+        ("target-libdir", Query::TargetLibdir),
+        ("cfg", Query::Cfg),
+        ("version", Query::Version),
+    ];
+
+    pub fn parse(name: &str) -> Result<Query, String> {
+        Query::ALL
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, q)| *q)
+            .ok_or_else(|| {
+                let names: Vec<&str> = Query::ALL.iter().map(|(n, _)| *n).collect();
+                format!(
+                    "unknown print request `{}` (expected one of: {})",
+                    name,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+/// Command-line settings that affect query results
+#[derive(Debug, Clone)]
+pub struct PrintOptions {
+    pub json: bool,
+    pub overflow: OverflowMode,
+    pub panic: PanicStrategy,
+    /// Explicit `--sysroot`
+    pub sysroot: Option<PathBuf>,
+    /// `--target` to describe instead of the host
+    pub target: Option<Target>,
+}
+
+impl Default for PrintOptions {
+    fn default() -> Self {
+        Self {
+            json: false,
+            overflow: OverflowMode::Trap,
+            panic: PanicStrategy::Unwind,
+            sysroot: None,
+            target: None,
+        }
+    }
+}
+
+/// Render a query result as text or JSON
+pub fn render(query: Query, options: &PrintOptions) -> Result<String, String> {
+    let json = options.json;
+    let output = match query {
+        Query::Targets if json => {
+            Json::Array(TARGETS.iter().map(target_json).collect()).to_string()
+        }
+        Query::Targets => lines(TARGETS.iter().map(|t| t.triple.to_string())),
+        Query::Sysroot => {
+            let sysroot = sysroot(options)?;
+            let root = sysroot.root().display().to_string();
+            if json {
+                Json::object([
+                    ("sysroot", Json::Str(root)),
+                    ("std", Json::Str(sysroot.std_dir().display().to_string())),
+                    (
+                        "installed_targets",
+                        Json::Array(
+                            sysroot
+                                .installed_targets()
+                                .into_iter()
+                                .map(Json::Str)
+                                .collect(),
+                        ),
+                    ),
+                ])
+                .to_string()
+            } else {
+                root
+            }
+        }
+        Query::TargetLibdir => {
+            let dir = sysroot(options)?
+                .target_libdir(target(options)?.triple)
+                .display()
+                .to_string();
+            if json {
+                Json::object([("target_libdir", Json::Str(dir))]).to_string()
+            } else {
+                dir
+            }
+        }
+        Query::Cfg => {
+            let target = target(options)?;
+            let checks = options.overflow == OverflowMode::Trap;
+            if json {
+                let mut fields: Vec<(String, Json)> = target
+                    .cfg()
+                    .into_iter()
+                    .map(|(k, v)| (k.to_string(), Json::Str(v)))
+                    .collect();
+                fields.push(("overflow_checks".to_string(), Json::Bool(checks)));
+                fields.push(("panic".to_string(), Json::str(options.panic.name())));
+                Json::Object(fields).to_string()
+            } else {
+                let mut out: Vec<String> = target
+                    .cfg()
+                    .into_iter()
+                    .map(|(k, v)| format!("{}=\"{}\"", k, v))
+                    .collect();
+                if checks {
+                    out.push("overflow_checks".to_string());
+                }
+                out.push(format!("panic=\"{}\"", options.panic.name()));
+                lines(out)
+            }
+        }
+        Query::Version if json => Json::object([
+            ("name", Json::str(env!("CARGO_PKG_NAME"))),
+            ("version", Json::str(env!("CARGO_PKG_VERSION"))),
+            (
+                "host",
+                Target::host().map_or(Json::Null, |t| Json::str(t.triple)),
+            ),
+        ])
+        .to_string(),
+        Query::Version => format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+    };
+    Ok(output)
+}
+
+fn sysroot(options: &PrintOptions) -> Result<Sysroot, String> {
+    Sysroot::resolve(options.sysroot.as_deref())
+        .ok_or_else(|| "could not determine sysroot".to_string())
+}
+
+/// The `--target`, or else the host
+fn target(options: &PrintOptions) -> Result<Target, String> {
+    options.target.or_else(Target::host).ok_or_else(|| {
+        format!(
+            "host platform {}-{} is not a supported target",
+            std::env::consts::ARCH,
+            std::env::consts::OS
+        )
+    })
+}
+
+fn target_json(target: &Target) -> Json {
+    Json::object([
+        ("triple", Json::str(target.triple)),
+        ("arch", Json::str(target.arch)),
+        ("os", Json::str(target.os)),
+        ("family", Json::str(target.family)),
+        ("pointer_width", Json::Int(target.pointer_width.into())),
+        ("endian", Json::str(target.endian)),
+    ])
+}
+
+fn lines(items: impl IntoIterator<Item = String>) -> String {
+    items.into_iter().collect::<Vec<_>>().join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_queries() {
+        assert_eq!(Query::parse("cfg"), Ok(Query::Cfg));
+        assert!(Query::parse("libs")
+            .unwrap_err()
+            .contains("targets, sysroot"));
+
+        let options = PrintOptions::default();
+        let targets = render(Query::Targets, &options).unwrap();
+        assert!(targets.lines().any(|l| l == "x86_64-unknown-linux-gnu"));
+
+        let options = PrintOptions {
+            json: true,
+            sysroot: Some(PathBuf::from("/opt/solo")),
+            ..PrintOptions::default()
+        };
+        let sysroot = render(Query::Sysroot, &options).unwrap();
+        assert!(sysroot.starts_with(r#"{"sysroot":"/opt/solo","std":"/opt/solo/lib/solo/std""#));
+
+        let options = PrintOptions {
+            target: Target::find("wasm32-unknown-unknown"),
+            ..PrintOptions::default()
+        };
+        let cfg = render(Query::Cfg, &options).unwrap();
+        assert!(cfg.contains("target_pointer_width=\"32\""), "{}", cfg);
+
+        let options = PrintOptions {
+            json: true,
+            ..PrintOptions::default()
+        };
+        let version = render(Query::Version, &options).unwrap();
+        assert!(version.starts_with(r#"{"name":"solo","version":"#));
+    }
+}
@@ -0,0 +1,301 @@
+//! Regions for arena checking
+//!
+//! Every struct, array, belief and reference lives in a region: static
+//! memory, the caller's data, an arena handed in as a parameter, the frame
+//! of the current function, or one of the arenas it opens. Regions are
+//! ordered by how long they live, and the type checker only lets a value be
+//! stored where it outlives the place receiving it:
+//!
+//! ```text
+//! static  ⊒  caller, arena parameters  ⊒  function frame  ⊒  outer arena  ⊒  inner arena
+//! ```
+//!
+//! Data from an outer arena can therefore be referenced from an inner
+//! scope, but data from an inner arena cannot be stored in a variable of an
+//! outer scope, returned, or be the value of its `arena` block.
+//!
+//! `handle.checkpoint()` splits an arena: what it allocates from then on,
+//! until the end of the block, lives in a region of its own inside the
+//! arena's, which `handle.reset_to(checkpoint)` frees.
+
+use crate::ast::Span;
+
+/// Name of the arena handle type
+pub const ARENA_TYPE: &str = "Arena";
+
+/// Name of the type of `handle.checkpoint()`
+pub const CHECKPOINT_TYPE: &str = "Checkpoint";
+
+/// Where a value lives
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Region {
+    /// Heap and static data, outliving every arena
+    Static,
+    /// Data the caller passed in
+    Caller,
+    /// The arena behind the `Arena` parameter with this index
+    Param(usize),
+    /// The frame of the function being checked (its local variables)
+    Local,
+    /// An arena opened by the function being checked, or the part of one
+    /// after a checkpoint
+    Arena(usize),
+}
+
+#[derive(Debug, Clone)]
+struct Scope {
+    parent: Option<usize>,
+    name: Option<String>,
+    span: Span,
+    /// For a checkpoint, the arena it was taken of
+    checkpoint_of: Option<usize>,
+}
+
+/// The arenas of the function being checked
+#[derive(Debug, Clone, Default)]
+pub struct Regions {
+    arenas: Vec<Scope>,
+    current: Option<usize>,
+    /// Parameter names, to describe [`Region::Param`]
+    params: Vec<String>,
+}
+
+impl Regions {
+    /// Start checking a function with the given parameter names
+    pub fn reset(&mut self, params: Vec<String>) {
+        *self = Regions {
+            params,
+            ..Regions::default()
+        };
+    }
+
+    /// Open an arena inside the current one
+    pub fn enter(&mut self, name: Option<&str>, span: Span) -> Region {
+        self.arenas.push(Scope {
+            parent: self.current,
+            name: name.map(str::to_string),
+            span,
+            checkpoint_of: None,
+        });
+        let index = self.arenas.len() - 1;
+        self.current = Some(index);
+        Region::Arena(index)
+    }
+
+    pub fn exit(&mut self) {
+        self.current = self.current.and_then(|index| self.arenas[index].parent);
+    }
+
+    /// Take a checkpoint at `span` of `arena`, which must be the innermost
+    /// one; `None` otherwise. Allocations after it live in the region
+    /// returned, until [`Regions::restore`] ends it with its block
+    pub fn checkpoint(&mut self, arena: Region, span: Span) -> Option<Region> {
+        let Region::Arena(index) = arena else {
+            return None;
+        };
+        if self.allocation_in(arena) != self.allocation() {
+            return None;
+        }
+        self.arenas.push(Scope {
+            parent: self.current,
+            name: None,
+            span,
+            checkpoint_of: Some(self.arena_of(index)),
+        });
+        let index = self.arenas.len() - 1;
+        self.current = Some(index);
+        Some(Region::Arena(index))
+    }
+
+    /// The current region, to [`Regions::restore`] at the end of a block
+    pub fn mark(&self) -> Option<usize> {
+        self.current
+    }
+
+    /// End the checkpoints taken since `mark`
+    pub fn restore(&mut self, mark: Option<usize>) {
+        self.current = mark;
+    }
+
+    /// The arena a region belongs to: itself, or the arena a checkpoint
+    /// was taken of
+    fn arena_of(&self, index: usize) -> usize {
+        self.arenas[index].checkpoint_of.unwrap_or(index)
+    }
+
+    /// The arena `checkpoint` was taken of, if it is a checkpoint's region
+    pub fn checkpoint_of(&self, checkpoint: Region) -> Option<Region> {
+        match checkpoint {
+            Region::Arena(index) => self.arenas[index].checkpoint_of.map(Region::Arena),
+            _ => None,
+        }
+    }
+
+    /// Whether `region` is the current region or encloses it
+    pub fn is_active(&self, region: Region) -> bool {
+        let Region::Arena(target) = region else {
+            return true;
+        };
+        let mut scope = self.current;
+        while let Some(index) = scope {
+            if index == target {
+                return true;
+            }
+            scope = self.arenas[index].parent;
+        }
+        false
+    }
+
+    /// Where the arena or checkpoint of `region` was opened
+    pub fn span(&self, region: Region) -> Option<Span> {
+        match region {
+            Region::Arena(index) => Some(self.arenas[index].span),
+            _ => None,
+        }
+    }
+
+    /// Region of values allocated here
+    pub fn allocation(&self) -> Region {
+        self.current.map_or(Region::Static, Region::Arena)
+    }
+
+    /// Region of values allocated here in `arena` through its handle: the
+    /// part after its latest checkpoint that is still open, if any
+    pub fn allocation_in(&self, arena: Region) -> Region {
+        let Region::Arena(target) = arena else {
+            return arena;
+        };
+        let target = self.arena_of(target);
+        let mut scope = self.current;
+        while let Some(index) = scope {
+            if index == target || self.arenas[index].checkpoint_of == Some(target) {
+                return Region::Arena(index);
+            }
+            scope = self.arenas[index].parent;
+        }
+        arena
+    }
+
+    /// Region of variables declared here
+    pub fn frame(&self) -> Region {
+        self.current.map_or(Region::Local, Region::Arena)
+    }
+
+    /// Whether data in `a` lives at least as long as data in `b`
+    pub fn outlives(&self, a: Region, b: Region) -> bool {
+        match (a, b) {
+            _ if a == b => true,
+            (Region::Static, _) => true,
+            (Region::Caller | Region::Param(_), Region::Local | Region::Arena(_)) => true,
+            (Region::Local, Region::Arena(_)) => true,
+            (Region::Arena(outer), Region::Arena(inner)) => {
+                let mut scope = Some(inner);
+                while let Some(index) = scope {
+                    if index == outer {
+                        return true;
+                    }
+                    scope = self.arenas[index].parent;
+                }
+                false
+            }
+            _ => false,
+        }
+    }
+
+    /// The longest-lived region outlived by both `a` and `b`
+    pub fn meet(&self, a: Region, b: Region) -> Region {
+        if self.outlives(a, b) {
+            b
+        } else if self.outlives(b, a) {
+            a
+        } else {
+            Region::Local
+        }
+    }
+
+    pub fn describe(&self, region: Region) -> String {
+        match region {
+            Region::Static => "static memory".to_string(),
+            Region::Caller => "the caller's data".to_string(),
+            Region::Param(index) => match self.params.get(index) {
+                Some(name) => format!("arena `{}`", name),
+                None => "a parameter's arena".to_string(),
+            },
+            Region::Local => "a local variable".to_string(),
+            Region::Arena(index) => match &self.arenas[index] {
+                Scope {
+                    checkpoint_of: Some(arena),
+                    span,
+                    ..
+                } => format!(
+                    "{} after the checkpoint at {}:{}",
+                    self.describe(Region::Arena(*arena)),
+                    span.line,
+                    span.column
+                ),
+                Scope {
+                    name: Some(name), ..
+                } => format!("arena `{}`", name),
+                Scope { span, .. } => format!("the arena at {}:{}", span.line, span.column),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_region_order() {
+        let mut regions = Regions::default();
+        regions.reset(vec!["scratch".to_string()]);
+        assert_eq!(regions.allocation(), Region::Static);
+        let outer = regions.enter(Some("outer"), Span::new(2, 5));
+        let inner = regions.enter(None, Span::new(3, 9));
+        regions.exit();
+        let sibling = regions.enter(None, Span::new(5, 9));
+
+        assert!(regions.outlives(outer, inner));
+        assert!(!regions.outlives(inner, outer));
+        assert!(!regions.outlives(inner, sibling));
+        assert!(regions.outlives(Region::Param(0), inner));
+        assert!(regions.outlives(Region::Local, outer));
+        assert!(!regions.outlives(Region::Caller, Region::Param(0)));
+        assert_eq!(
+            regions.meet(Region::Caller, Region::Param(0)),
+            Region::Local
+        );
+        assert_eq!(regions.meet(inner, Region::Static), inner);
+        assert_eq!(regions.frame(), sibling);
+        assert_eq!(regions.describe(Region::Param(0)), "arena `scratch`");
+        assert_eq!(regions.describe(inner), "the arena at 3:9");
+    }
+
+    #[test]
+    fn test_checkpoints() {
+        let mut regions = Regions::default();
+        regions.reset(Vec::new());
+        let frame = regions.enter(Some("frame"), Span::new(2, 5));
+        let mark = regions.mark();
+        let start = regions.checkpoint(frame, Span::new(3, 27)).unwrap();
+        assert_eq!(regions.allocation(), start);
+        assert_eq!(regions.allocation_in(frame), start);
+        assert!(regions.outlives(frame, start));
+        assert_eq!(regions.checkpoint_of(start), Some(frame));
+        assert_eq!(
+            regions.describe(start),
+            "arena `frame` after the checkpoint at 3:27"
+        );
+
+        let inner = regions.enter(None, Span::new(4, 9));
+        assert_eq!(regions.allocation_in(frame), start);
+        assert_eq!(regions.checkpoint(frame, Span::new(5, 13)), None);
+        regions.exit();
+
+        regions.restore(mark);
+        assert_eq!(regions.allocation(), frame);
+        assert!(!regions.is_active(start));
+        assert!(regions.outlives(start, inner));
+    }
+}
@@ -0,0 +1,405 @@
+//! Interactive sessions for `solo repl`
+//!
+//! A session is a program built up one input at a time. Items (functions,
+//! structs, enums, constants, ...) become items of the program; statements
+//! become the body of a function that runs after each input, replaying the
+//! ones before it. Programs are deterministic, bar what they read and write
+//! outside, so a replay prints what it printed before, and only the output
+//! beyond that is shown. An expression prints its value. An input that does
+//! not compile, or panics, is dropped from the session.
+//!
+//! Files given to [`Session::load`] come first in the program, with their
+//! own `main` if they have one. [`Session::reload`] reads them again, all
+//! or only those changed on disk since; the REPL does the latter before
+//! every input, so a file edited alongside it stays live.
+//! [`Session::save`] writes the session as a program of its own: the
+//! loaded files, each after a `// :load` comment, the items typed, and a
+//! `main` running the statements typed. It runs as the session did, unless
+//! a loaded file has a `main` of its own.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::ast::Span;
+use crate::error::CompileError;
+use crate::interp::{Interpreter, RunOptions};
+use crate::lexer::Lexer;
+use crate::token::TokenKind;
+
+/// The function running the statements of a session
+const BODY: &str = "__repl";
+
+/// A program being written interactively
+pub struct Session {
+    options: RunOptions,
+    /// Whether `#![feature(...)]` is allowed
+    unstable: bool,
+    files: Vec<Loaded>,
+    items: Vec<String>,
+    stmts: Vec<String>,
+    /// Bytes the statements so far print
+    printed: usize,
+}
+
+/// A file loaded into a session
+struct Loaded {
+    path: PathBuf,
+    source: String,
+    modified: Option<SystemTime>,
+}
+
+/// Where a line of the program of a session came from
+#[derive(Clone, Copy)]
+enum Origin<'a> {
+    File(&'a Path),
+    Session,
+    Input,
+}
+
+impl Session {
+    pub fn new(options: RunOptions, unstable: bool) -> Self {
+        Self {
+            options,
+            unstable,
+            files: Vec::new(),
+            items: Vec::new(),
+            stmts: Vec::new(),
+            printed: 0,
+        }
+    }
+
+    /// Evaluate `input`, returning what it printed
+    pub fn eval(&mut self, input: &str) -> Result<String, CompileError> {
+        let input = input.trim();
+        if input.is_empty() {
+            return Ok(String::new());
+        }
+        let (item, stmt) = match classify(input) {
+            Input::Item => (Some(input.to_string()), None),
+            Input::Stmt => (None, Some(input.to_string())),
+            Input::Let if input.ends_with(';') => (None, Some(input.to_string())),
+            Input::Let => (None, Some(format!("{};", input))),
+            // A unit value is not worth printing
+            Input::Expr
+                if self
+                    .run(None, Some(&format!("let _: () = {};", input)))
+                    .is_ok() =>
+            {
+                (None, Some(format!("{};", input)))
+            }
+            Input::Expr => (None, Some(format!("print({});", input))),
+        };
+        let output = self.run(item.as_deref(), stmt.as_deref())?;
+        let printed = String::from_utf8_lossy(&output[self.printed.min(output.len())..]);
+        let printed = printed.into_owned();
+        self.items.extend(item);
+        self.stmts.extend(stmt);
+        self.printed = output.len();
+        Ok(printed)
+    }
+
+    /// Add the file at `path` to the session, returning what the
+    /// statements of the session print with it
+    pub fn load(&mut self, path: &Path) -> Result<String, CompileError> {
+        if self.files.iter().any(|file| file.path == path) {
+            self.reload(true)?;
+            return Ok(String::new());
+        }
+        self.files.push(Loaded::read(path)?);
+        self.replay().inspect_err(|_| drop(self.files.pop()))
+    }
+
+    /// Read the loaded files again, all of them or only those modified
+    /// since they were read, returning the paths of those read
+    ///
+    /// If the session no longer compiles with them, it keeps the old
+    /// sources, but not their modification times, so a file is not read
+    /// again until it changes again.
+    pub fn reload(&mut self, all: bool) -> Result<Vec<PathBuf>, CompileError> {
+        let mut reloaded = Vec::new();
+        let mut previous = Vec::new();
+        for (i, file) in self.files.iter_mut().enumerate() {
+            if all || file.modified != modified(&file.path) {
+                let read = Loaded::read(&file.path)?;
+                previous.push((i, std::mem::replace(file, read)));
+                reloaded.push(file.path.clone());
+            }
+        }
+        if reloaded.is_empty() {
+            return Ok(reloaded);
+        }
+        match self.replay() {
+            Ok(_) => Ok(reloaded),
+            Err(err) => {
+                for (i, old) in previous {
+                    let modified = self.files[i].modified;
+                    self.files[i] = Loaded { modified, ..old };
+                }
+                Err(err)
+            }
+        }
+    }
+
+    /// The files loaded, in order
+    pub fn files(&self) -> impl Iterator<Item = &Path> {
+        self.files.iter().map(|file| file.path.as_path())
+    }
+
+    /// The session as a program: its inner attributes, the loaded files, the
+    /// items typed, then a `main` running the statements typed
+    pub fn to_program(&self) -> String {
+        let mut out = String::new();
+        let (attrs, items): (Vec<&String>, Vec<&String>) =
+            self.items.iter().partition(|item| item.starts_with("#!["));
+        for attr in attrs {
+            out.push_str(attr);
+            out.push('\n');
+        }
+        for file in &self.files {
+            out.push_str(&format!("// :load {}\n", file.path.display()));
+            out.push_str(&file.source);
+            if !file.source.ends_with('\n') {
+                out.push('\n');
+            }
+        }
+        for item in items {
+            out.push_str(item);
+            out.push('\n');
+        }
+        out.push_str("fn main() {\n");
+        for stmt in &self.stmts {
+            for line in stmt.lines() {
+                out.push_str(&format!("    {}\n", line));
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    /// Write [`to_program`](Session::to_program) to `path`
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_program())
+    }
+
+    /// Run the session again, returning what its statements print; the
+    /// output already shown is kept as shown
+    fn replay(&mut self) -> Result<String, CompileError> {
+        let output = self.run(None, None)?;
+        self.printed = output.len();
+        Ok(String::new())
+    }
+
+    /// Run the session with `item` and `stmt` added, returning everything
+    /// its statements print
+    fn run(&self, item: Option<&str>, stmt: Option<&str>) -> Result<Vec<u8>, CompileError> {
+        let (source, origins) = self.program(item, stmt);
+        let result = (|| -> Result<Vec<u8>, CompileError> {
+            if !self.unstable {
+                crate::check_stable(&source)?;
+            }
+            let (program, info) = crate::analyze(&source)?;
+            let mut output = Vec::new();
+            Interpreter::new(&program, &info, &self.options, &mut output).run_function(BODY)?;
+            Ok(output)
+        })();
+        result.map_err(|mut err| {
+            for diagnostic in err.diagnostics_mut() {
+                let Some(span) = diagnostic.span else {
+                    continue;
+                };
+                let (origin, line) = locate(&origins, span.line);
+                diagnostic.span = Some(Span::new(line, span.column));
+                diagnostic.file = Some(match origin {
+                    Origin::File(path) => path.to_path_buf(),
+                    Origin::Session => PathBuf::from("<session>"),
+                    Origin::Input => PathBuf::from("<input>"),
+                });
+            }
+            err
+        })
+    }
+
+    /// The program of the session with `item` and `stmt` added, and where
+    /// each of its pieces starts
+    fn program(
+        &self,
+        item: Option<&str>,
+        stmt: Option<&str>,
+    ) -> (String, Vec<(usize, Origin<'_>)>) {
+        let mut source = String::new();
+        let mut origins = Vec::new();
+        let mut push = |text: &str, origin| {
+            origins.push((source.lines().count() + 1, origin));
+            source.push_str(text);
+            source.push('\n');
+        };
+        // Inner attributes come before every item
+        let attrs = self.items.iter().map(String::as_str).chain(item);
+        for attr in attrs.filter(|item| item.starts_with("#![")) {
+            push(attr, Origin::Session);
+        }
+        for file in &self.files {
+            push(&file.source, Origin::File(&file.path));
+        }
+        for item in self.items.iter().filter(|item| !item.starts_with("#![")) {
+            push(item, Origin::Session);
+        }
+        if let Some(item) = item.filter(|item| !item.starts_with("#![")) {
+            push(item, Origin::Input);
+        }
+        push(&format!("fn {}() {{", BODY), Origin::Session);
+        for stmt in &self.stmts {
+            push(stmt, Origin::Session);
+        }
+        if let Some(stmt) = stmt {
+            push(stmt, Origin::Input);
+        }
+        push("}", Origin::Session);
+        (source, origins)
+    }
+}
+
+impl Loaded {
+    fn read(path: &Path) -> Result<Self, CompileError> {
+        Ok(Self {
+            path: path.to_path_buf(),
+            source: crate::read_source(path)?,
+            modified: modified(path),
+        })
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+/// The piece line `line` of a program is in, and its line in that piece
+fn locate<'a>(origins: &[(usize, Origin<'a>)], line: usize) -> (Origin<'a>, usize) {
+    let (start, origin) = origins
+        .iter()
+        .rev()
+        .find(|(start, _)| *start <= line)
+        .copied()
+        .unwrap_or((1, Origin::Session));
+    (origin, line - start + 1)
+}
+
+/// What an input to a session is
+#[derive(Debug, PartialEq, Eq)]
+enum Input {
+    Item,
+    Let,
+    /// A statement that needs no `;`
+    Stmt,
+    Expr,
+}
+
+fn classify(input: &str) -> Input {
+    match Lexer::new(input).next_token().kind {
+        TokenKind::Fn
+        | TokenKind::Struct
+        | TokenKind::Enum
+        | TokenKind::Impl
+        | TokenKind::Type
+        | TokenKind::Newtype
+        | TokenKind::Extern
+        | TokenKind::Pub
+        | TokenKind::Macro
+        | TokenKind::Const
+        | TokenKind::Pound => Input::Item,
+        TokenKind::Let => Input::Let,
+        _ if input.ends_with([';', '}']) => Input::Stmt,
+        _ => Input::Expr,
+    }
+}
+
+/// Whether `input` has closed every bracket it opened, so that it can be
+/// evaluated rather than continued on the next line
+pub fn is_complete(input: &str) -> bool {
+    let mut lexer = Lexer::new(input);
+    let mut depth = 0i64;
+    loop {
+        match lexer.next_token().kind {
+            TokenKind::Eof => return depth <= 0,
+            TokenKind::LBrace | TokenKind::LParen | TokenKind::LBracket => depth += 1,
+            TokenKind::RBrace | TokenKind::RParen | TokenKind::RBracket => depth -= 1,
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session() {
+        let mut session = Session::new(RunOptions::default(), false);
+        assert_eq!(
+            session.eval("fn double(n: i64) -> i64 { n * 2 }").unwrap(),
+            ""
+        );
+        session.eval("struct Point {\n    x: i64,\n}").unwrap();
+        assert_eq!(session.eval("let x = double(21)").unwrap(), "");
+        assert_eq!(session.eval("let p = Point { x: 1 }").unwrap(), "");
+        assert_eq!(session.eval("print(1);").unwrap(), "1\n");
+        // Only the value is new output, not the `print` replayed
+        assert_eq!(session.eval("x").unwrap(), "42\n");
+        assert_eq!(session.eval("print(2)").unwrap(), "2\n");
+
+        let err = session.eval("let y: bool = x;").unwrap_err();
+        assert!(err.to_string().contains("<input>:1:"), "{}", err);
+        assert!(session.eval("panic(\"no\");").is_err());
+        assert!(is_complete("fn f() { 1 }") && !is_complete("fn f() {"));
+        assert_eq!(
+            session.to_program(),
+            "fn double(n: i64) -> i64 { n * 2 }
+struct Point {
+    x: i64,
+}
+fn main() {
+    let x = double(21);
+    let p = Point { x: 1 };
+    print(1);
+    print(x);
+    print(2);
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_load() {
+        let dir = std::env::temp_dir().join(format!("solo-repl-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("lib.solo");
+        fs::write(&file, "fn answer() -> i64 { 42 }\n").unwrap();
+
+        let mut session = Session::new(RunOptions::default(), false);
+        session.load(&file).unwrap();
+        assert_eq!(session.eval("answer()").unwrap(), "42\n");
+        assert!(session.reload(false).unwrap().is_empty());
+
+        fs::write(&file, "fn answer() -> i64 { 43 }\n").unwrap();
+        assert_eq!(session.reload(true).unwrap(), [file.as_path()]);
+        assert_eq!(session.eval("answer()").unwrap(), "43\n");
+
+        // A file that breaks the session is not taken
+        fs::write(&file, "fn question() -> i64 { 0 }\n").unwrap();
+        let err = session.reload(true).unwrap_err();
+        assert!(err.to_string().contains("<session>"), "{}", err);
+        assert_eq!(session.eval("answer()").unwrap(), "43\n");
+        let program = session.to_program();
+        assert!(program.starts_with("// :load "), "{}", program);
+        let main =
+            "fn main() {\n    print(answer());\n    print(answer());\n    print(answer());\n}\n";
+        assert!(
+            program.ends_with(&format!("{{ 43 }}\n{}", main)),
+            "{}",
+            program
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,322 @@
+//! Compiler as a service over JSON-RPC
+//!
+//! `solo serve --api` listens on a loopback TCP address and answers
+//! [JSON-RPC 2.0](https://www.jsonrpc.org/specification) requests, one JSON
+//! object per line, so tools not written in Rust (CI bots, web backends,
+//! editors without an LSP client) can drive the compiler without spawning a
+//! process per request. Each connection is a [`Session`] holding the
+//! documents it opened; requests refer to a document by `name` or pass its
+//! text as `source`.
+//!
+//! | Method    | Params                               | Result                                 |
+//! |-----------|--------------------------------------|----------------------------------------|
+//! | `open`    | `name`, `source`                     | `null`                                 |
+//! | `close`   | `name`                               | `null`                                 |
+//! | `check`   | `name` or `source`, `target`?        | `ok`, `diagnostics`                    |
+//! | `compile` | `name` or `source`, `target`?        | `ok`, `diagnostics`                    |
+//! | `eval`    | `name` or `source`, `args`?, `seed`? | `ok`, `diagnostics`, `value`, `output` |
+//! | `minify`  | `name` or `source`                   | `ok`, `diagnostics`, `source`          |
+//! | `format`  | `name` or `source`                   | not implemented yet (always an error)  |
+//!
+//! Diagnostics are the objects of `--message-format=json`. Compile errors
+//! are results with `ok: false`; JSON-RPC errors are reserved for malformed
+//! requests. `eval` runs `main` in the server process and has no time
+//! limit.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::thread;
+
+use crate::error::CompileError;
+use crate::interp::{Interpreter, RunOptions};
+use crate::json::Json;
+use crate::parser;
+use crate::target::Target;
+
+/// Address `solo serve --api` listens on by default
+pub const DEFAULT_ADDRESS: &str = "127.0.0.1:7600";
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+
+/// Serve every connection to `listener` on its own thread, each with a
+/// fresh session
+pub fn serve(listener: TcpListener, unstable: bool) -> io::Result<()> {
+    for stream in listener.incoming() {
+        let stream = stream?;
+        thread::Builder::new()
+            .stack_size(parser::STACK_SIZE)
+            .spawn(move || {
+                // A client going away mid-request is not the server's problem
+                let _ = handle_connection(stream, Session::new(unstable));
+            })?;
+    }
+    Ok(())
+}
+
+fn handle_connection(stream: TcpStream, mut session: Session) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(response) = session.handle(&line) {
+            writeln!(writer, "{}", response)?;
+        }
+    }
+    Ok(())
+}
+
+/// The documents opened on one connection
+#[derive(Debug, Clone, Default)]
+pub struct Session {
+    documents: HashMap<String, String>,
+    /// Accept `#![feature(...)]`, as `--unstable` does
+    unstable: bool,
+}
+
+/// A JSON-RPC error: its code and message
+pub type RpcError = (i64, String);
+
+impl Session {
+    pub fn new(unstable: bool) -> Self {
+        Self {
+            documents: HashMap::new(),
+            unstable,
+        }
+    }
+
+    /// Answer one request line; notifications (requests without an `id`)
+    /// get no response
+    pub fn handle(&mut self, line: &str) -> Option<Json> {
+        let request = match Json::parse(line) {
+            Ok(request) => request,
+            Err(err) => return Some(response(Json::Null, Err((PARSE_ERROR, err)))),
+        };
+        let id = request.get("id").cloned();
+        let result = match request.get("method").and_then(Json::as_str) {
+            Some(method) if request.get("jsonrpc").and_then(Json::as_str) == Some("2.0") => {
+                let params = request.get("params").cloned().unwrap_or(Json::Null);
+                self.call(method, &params)
+            }
+            _ => Err((
+                INVALID_REQUEST,
+                "expected a JSON-RPC 2.0 request with a `method`".to_string(),
+            )),
+        };
+        id.map(|id| response(id, result))
+    }
+
+    fn call(&mut self, method: &str, params: &Json) -> Result<Json, RpcError> {
+        match method {
+            "open" => {
+                let name = string_param(params, "name")?;
+                let source = string_param(params, "source")?;
+                self.documents.insert(name.to_string(), source.to_string());
+                Ok(Json::Null)
+            }
+            "close" => {
+                let name = string_param(params, "name")?;
+                match self.documents.remove(name) {
+                    Some(_) => Ok(Json::Null),
+                    None => Err(no_document(name)),
+                }
+            }
+            "check" | "compile" => {
+                let (name, source) = self.document(params)?;
+                let target = match params.get("target") {
+                    None | Some(Json::Null) => None,
+                    Some(_) => {
+                        let triple = string_param(params, "target")?;
+                        let target = Target::find(triple).ok_or_else(|| {
+                            (INVALID_PARAMS, format!("unknown target `{}`", triple))
+                        })?;
+                        Some(target)
+                    }
+                };
+                let result = self.stable(source).and_then(|()| match (method, target) {
+                    ("check", None) => crate::check(source).map(drop),
+                    ("check", Some(target)) => crate::check_for(source, &target).map(drop),
+                    (_, None) => crate::compile(source),
+                    (_, Some(target)) => crate::compile_for(source, &target),
+                });
+                Ok(outcome(name, source, result, Vec::new()))
+            }
+            "eval" => {
+                let (name, source) = self.document(params)?;
+                let mut options = RunOptions::default();
+                options.args.push(name.unwrap_or("-").to_string());
+                if let Some(args) = params.get("args") {
+                    let Json::Array(args) = args else {
+                        return Err(invalid_param("args", "an array of strings"));
+                    };
+                    for arg in args {
+                        let arg = arg
+                            .as_str()
+                            .ok_or_else(|| invalid_param("args", "an array of strings"))?;
+                        options.args.push(arg.to_string());
+                    }
+                }
+                if let Some(seed) = params.get("seed") {
+                    options.seed = seed
+                        .as_int()
+                        .ok_or_else(|| invalid_param("seed", "an integer"))?
+                        as u64;
+                }
+                let mut output = Vec::new();
+                let result = self.stable(source).and_then(|()| {
+                    let (program, info) = crate::analyze(source)?;
+                    Interpreter::new(&program, &info, &options, &mut output).run_main()
+                });
+                let value = match &result {
+                    Ok(value) => Json::Str(value.to_string()),
+                    Err(_) => Json::Null,
+                };
+                let output = Json::Str(String::from_utf8_lossy(&output).into_owned());
+                Ok(outcome(
+                    name,
+                    source,
+                    result.map(drop),
+                    vec![("value", value), ("output", output)],
+                ))
+            }
+            "format" => Err((
+                METHOD_NOT_FOUND,
+                "formatting is not implemented yet".to_string(),
+            )),
+            "minify" => {
+                let (name, source) = self.document(params)?;
+                let result = crate::minify::minify(source);
+                let minified = result
+                    .as_ref()
+                    .map_or(Json::Null, |s| Json::str(s.as_str()));
+                Ok(outcome(
+                    name,
+                    source,
+                    result.map(drop),
+                    vec![("source", minified)],
+                ))
+            }
+            _ => Err((METHOD_NOT_FOUND, format!("unknown method `{}`", method))),
+        }
+    }
+
+    /// The document named by `name`, or the text of `source`
+    fn document<'s>(&'s self, params: &'s Json) -> Result<(Option<&'s str>, &'s str), RpcError> {
+        if params.get("source").is_some() {
+            return Ok((None, string_param(params, "source")?));
+        }
+        let name = string_param(params, "name")?;
+        match self.documents.get(name) {
+            Some(source) => Ok((Some(name), source)),
+            None => Err(no_document(name)),
+        }
+    }
+
+    fn stable(&self, source: &str) -> Result<(), CompileError> {
+        if self.unstable {
+            Ok(())
+        } else {
+            crate::check_stable(source)
+        }
+    }
+}
+
+fn string_param<'p>(params: &'p Json, key: &str) -> Result<&'p str, RpcError> {
+    params
+        .get(key)
+        .and_then(Json::as_str)
+        .ok_or_else(|| invalid_param(key, "a string"))
+}
+
+pub fn invalid_param(key: &str, expected: &str) -> RpcError {
+    (
+        INVALID_PARAMS,
+        format!("parameter `{}` must be {}", key, expected),
+    )
+}
+
+pub fn no_document(name: &str) -> RpcError {
+    (INVALID_PARAMS, format!("no open document `{}`", name))
+}
+
+/// A result object reporting `result`'s diagnostics, plus `extra` fields
+fn outcome(
+    name: Option<&str>,
+    source: &str,
+    result: Result<(), CompileError>,
+    extra: Vec<(&str, Json)>,
+) -> Json {
+    let diagnostics = match result {
+        Ok(()) => Vec::new(),
+        Err(err) => match name {
+            Some(name) => err.in_file(Path::new(name)).to_json(source),
+            None => err.to_json(source),
+        },
+    };
+    let mut fields = vec![
+        ("ok", Json::Bool(diagnostics.is_empty())),
+        ("diagnostics", Json::Array(diagnostics)),
+    ];
+    fields.extend(extra);
+    Json::object(fields)
+}
+
+/// The response to request `id`
+pub fn response(id: Json, result: Result<Json, RpcError>) -> Json {
+    let outcome = match result {
+        Ok(result) => ("result", result),
+        Err((code, message)) => (
+            "error",
+            Json::object([("code", Json::Int(code)), ("message", Json::Str(message))]),
+        ),
+    };
+    Json::object([("jsonrpc", Json::str("2.0")), ("id", id), outcome])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session() {
+        let mut session = Session::new(false);
+        let mut call = |request: &str| session.handle(request).map(|r| r.to_string());
+
+        let open = r#"{"jsonrpc":"2.0","method":"open","params":{"name":"a.solo","source":"fn main() -> i64 { print(env::arg(1)); 7 }"}}"#;
+        assert_eq!(call(open), None);
+        assert_eq!(
+            call(r#"{"jsonrpc":"2.0","id":1,"method":"eval","params":{"name":"a.solo","args":["hi"]}}"#)
+                .unwrap(),
+            r#"{"jsonrpc":"2.0","id":1,"result":{"ok":true,"diagnostics":[],"value":"7","output":"hi\n"}}"#
+        );
+        let check = call(
+            r#"{"jsonrpc":"2.0","id":"c","method":"check","params":{"source":"fn main() { x }"}}"#,
+        )
+        .unwrap();
+        assert!(check.starts_with(r#"{"jsonrpc":"2.0","id":"c","result":{"ok":false,"diagnostics":[{"stage":"type","file":null,"line":1,"column":13,"message":"cannot find value `x` in this scope""#));
+        assert_eq!(
+            call(r#"{"jsonrpc":"2.0","id":2,"method":"compile","params":{"name":"a.solo","target":"wasm32-unknown-unknown"}}"#)
+                .unwrap(),
+            r#"{"jsonrpc":"2.0","id":2,"result":{"ok":true,"diagnostics":[]}}"#
+        );
+
+        assert_eq!(
+            call(r#"{"jsonrpc":"2.0","id":3,"method":"close","params":{"name":"b.solo"}}"#)
+                .unwrap(),
+            r#"{"jsonrpc":"2.0","id":3,"error":{"code":-32602,"message":"no open document `b.solo`"}}"#
+        );
+        assert_eq!(
+            call(r#"{"jsonrpc":"2.0","id":4,"method":"run"}"#).unwrap(),
+            r#"{"jsonrpc":"2.0","id":4,"error":{"code":-32601,"message":"unknown method `run`"}}"#
+        );
+        assert!(call("{")
+            .unwrap()
+            .contains(r#""id":null,"error":{"code":-32700"#));
+    }
+}
@@ -0,0 +1,298 @@
+//! Hermetic test execution for `solo test`
+//!
+//! Each test runs in its own child process (the `solo` executable invoked
+//! with [`RUN_TEST_COMMAND`]) so tests cannot interfere with each other:
+//!
+//! - the working directory is a fresh temporary directory, removed afterwards
+//! - the environment is cleared; only `HOME`, `TMPDIR` (both the temporary
+//!   directory) and [`TEST_VAR`] (the test name) are set
+//! - a test that runs longer than its timeout is killed
+//! - stdout and stderr are captured and returned, to be shown on failure
+//!
+//! With coverage on, the child also writes its execution counts to a
+//! profraw file (see [`crate::coverage`]) at a path outside the sandbox.
+//!
+//! [`run_program`] runs untrusted source the same way for the playground
+//! server (`solo serve --playground`), passing it on stdin to the
+//! [`RUN_PROGRAM_COMMAND`] child, which can also cap its own memory and
+//! runs the program without file access.
+
+use std::env;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Hidden subcommand running a single test in the current process
+pub const RUN_TEST_COMMAND: &str = "__run-test";
+
+/// Hidden subcommand compiling and running the program read from stdin
+pub const RUN_PROGRAM_COMMAND: &str = "__run-program";
+
+/// Environment variable naming the running test
+pub const TEST_VAR: &str = "SOLO_TEST";
+
+/// Default per-test timeout
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How a sandboxed test ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Passed,
+    /// Exited unsuccessfully (`None` when killed by a signal)
+    Failed(Option<i32>),
+    TimedOut,
+}
+
+/// Result of one sandboxed test
+#[derive(Debug, Clone)]
+pub struct TestRun {
+    pub outcome: Outcome,
+    /// Captured stdout followed by stderr
+    pub output: String,
+    pub duration: Duration,
+}
+
+/// Run test `name` of the source file `file` with the `solo` executable
+/// `exe`, sampling beliefs from `seed` and writing its execution counts to
+/// `profraw` if given
+pub fn run_test(
+    exe: &Path,
+    file: &Path,
+    name: &str,
+    timeout: Duration,
+    seed: u64,
+    profraw: Option<&Path>,
+) -> io::Result<TestRun> {
+    let file = file.canonicalize()?;
+    let dir = TempDir::new(name)?;
+    let mut command = Command::new(exe);
+    command.arg(RUN_TEST_COMMAND).arg(&file).arg(name);
+    if seed != 0 {
+        command.arg("--seed").arg(seed.to_string());
+    }
+    if let Some(profraw) = profraw {
+        // The child runs in the sandbox directory
+        command.arg("--coverage").arg(std::path::absolute(profraw)?);
+    }
+    let start = Instant::now();
+    let mut child = command
+        .current_dir(&dir.path)
+        .env_clear()
+        .env("HOME", &dir.path)
+        .env("TMPDIR", &dir.path)
+        .env(TEST_VAR, name)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    // Drain the pipes while waiting so a chatty test cannot block on them
+    let stdout = capture(child.stdout.take());
+    let stderr = capture(child.stderr.take());
+    let outcome = wait(&mut child, timeout)?;
+    let duration = start.elapsed();
+
+    let mut output = stdout.join().unwrap_or_default();
+    output.push_str(&stderr.join().unwrap_or_default());
+    Ok(TestRun {
+        outcome,
+        output,
+        duration,
+    })
+}
+
+/// Result of one sandboxed program, with its output streams kept apart
+#[derive(Debug, Clone)]
+pub struct ProgramRun {
+    pub outcome: Outcome,
+    pub stdout: String,
+    pub stderr: String,
+    pub duration: Duration,
+}
+
+/// Compile and run `source` in a child of the `solo` executable `exe`,
+/// killed after `timeout` and limited to `memory` bytes of address space
+/// (see [`limit_memory`])
+///
+/// The child writes program output to stdout and `--message-format=json`
+/// diagnostics to stderr, one per line.
+pub fn run_program(
+    exe: &Path,
+    source: &str,
+    timeout: Duration,
+    memory: Option<u64>,
+    unstable: bool,
+) -> io::Result<ProgramRun> {
+    let dir = TempDir::new("program")?;
+    let mut command = Command::new(exe);
+    command.arg(RUN_PROGRAM_COMMAND);
+    if let Some(bytes) = memory {
+        command.arg("--memory").arg(bytes.to_string());
+    }
+    if unstable {
+        command.arg("--unstable");
+    }
+    let start = Instant::now();
+    let mut child = command
+        .current_dir(&dir.path)
+        .env_clear()
+        .env("HOME", &dir.path)
+        .env("TMPDIR", &dir.path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    // Feed stdin from its own thread too: the child may stop reading early
+    let mut stdin = child.stdin.take();
+    let source = source.to_string();
+    let writer = thread::spawn(move || {
+        if let Some(stdin) = &mut stdin {
+            let _ = stdin.write_all(source.as_bytes());
+        }
+    });
+    let stdout = capture(child.stdout.take());
+    let stderr = capture(child.stderr.take());
+    let outcome = wait(&mut child, timeout)?;
+    let duration = start.elapsed();
+    let _ = writer.join();
+
+    Ok(ProgramRun {
+        outcome,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+        duration,
+    })
+}
+
+/// Cap the address space of the current process at `bytes`, so an
+/// allocation beyond it fails instead of exhausting the machine
+///
+/// Only Linux enforces the limit; elsewhere this does nothing.
+pub fn limit_memory(bytes: u64) -> io::Result<()> {
+    sys::limit_memory(bytes)
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use std::io;
+    use std::os::raw::{c_int, c_ulong};
+
+    const RLIMIT_AS: c_int = 9;
+
+    #[repr(C)]
+    struct RLimit {
+        current: c_ulong,
+        maximum: c_ulong,
+    }
+
+    extern "C" {
+        fn setrlimit(resource: c_int, limit: *const RLimit) -> c_int;
+    }
+
+    pub fn limit_memory(bytes: u64) -> io::Result<()> {
+        let bytes = c_ulong::try_from(bytes).unwrap_or(c_ulong::MAX);
+        let limit = RLimit {
+            current: bytes,
+            maximum: bytes,
+        };
+        // SAFETY: `limit` is a valid `struct rlimit` for the call's duration
+        if unsafe { setrlimit(RLIMIT_AS, &limit) } == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod sys {
+    pub fn limit_memory(_bytes: u64) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+fn capture(pipe: Option<impl Read + Send + 'static>) -> thread::JoinHandle<String> {
+    thread::spawn(move || {
+        let mut bytes = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut bytes);
+        }
+        String::from_utf8_lossy(&bytes).into_owned()
+    })
+}
+
+fn wait(child: &mut Child, timeout: Duration) -> io::Result<Outcome> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(if status.success() {
+                Outcome::Passed
+            } else {
+                Outcome::Failed(status.code())
+            });
+        }
+        if Instant::now() >= deadline {
+            child.kill()?;
+            child.wait()?;
+            return Ok(Outcome::TimedOut);
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+/// A uniquely named temporary directory, removed on drop
+///
+/// A directory of the same name that already exists is never reused.
+pub(crate) struct TempDir {
+    pub(crate) path: PathBuf,
+}
+
+impl TempDir {
+    pub(crate) fn new(test: &str) -> io::Result<TempDir> {
+        let stem: String = test
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        let base = env::temp_dir();
+        for attempt in 0u32.. {
+            let path = base.join(format!(
+                "solo-test-{}-{}-{}",
+                std::process::id(),
+                stem,
+                attempt
+            ));
+            match fs::create_dir(&path) {
+                Ok(()) => return Ok(TempDir { path }),
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(err),
+            }
+        }
+        unreachable!("ran out of temporary directory names")
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_temp_dirs_are_unique_and_removed() {
+        let a = TempDir::new("a::b").unwrap();
+        let b = TempDir::new("a::b").unwrap();
+        assert_ne!(a.path, b.path);
+        assert!(a.path.is_dir());
+        let path = a.path.clone();
+        drop(a);
+        assert!(!path.exists());
+    }
+}
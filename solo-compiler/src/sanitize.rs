@@ -0,0 +1,180 @@
+//! Sanitizers: memory-safety checks of accesses through raw pointers
+//!
+//! `--sanitize=address,arena` instruments a program so that `unsafe` code
+//! misusing a pointer stops with a report instead of reading or writing
+//! memory it does not own:
+//!
+//! - `address` surrounds each allocation with [`REDZONE`] bytes of
+//!   poisoned memory, catching accesses that run off its ends, and poisons
+//!   a variable's slot when its scope ends
+//! - `arena` poisons an arena's memory when the arena is reset, catching
+//!   accesses through pointers that outlived it
+//!
+//! The interpreter performs the same checks on each dereference. Slots
+//! freed while a pointer still points into them are kept in a
+//! [`Quarantine`], as AddressSanitizer does, so that no later slot is
+//! mistaken for one of them.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::{Rc, Weak};
+
+use crate::ast::Span;
+
+/// Bytes of poisoned memory around each allocation
+pub const REDZONE: u64 = 32;
+
+/// The sanitizers a program is instrumented with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Sanitizers {
+    pub address: bool,
+    pub arena: bool,
+}
+
+impl Sanitizers {
+    /// Parse a comma-separated list such as `address,arena`
+    pub fn parse(list: &str) -> Result<Sanitizers, String> {
+        let mut sanitizers = Sanitizers::default();
+        for name in list.split(',') {
+            match name.trim() {
+                "address" => sanitizers.address = true,
+                "arena" => sanitizers.arena = true,
+                other => {
+                    return Err(format!(
+                        "unknown sanitizer `{}`; expected `address` or `arena`",
+                        other
+                    ))
+                }
+            }
+        }
+        Ok(sanitizers)
+    }
+
+    pub fn is_empty(self) -> bool {
+        !self.address && !self.arena
+    }
+}
+
+impl fmt::Display for Sanitizers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<&str> = [(self.address, "address"), (self.arena, "arena")]
+            .into_iter()
+            .filter_map(|(on, name)| on.then_some(name))
+            .collect();
+        f.write_str(&names.join(","))
+    }
+}
+
+/// A bad access a sanitizer caught
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    /// An access `offset` bytes from the start of an allocation of `size`
+    /// bytes, in an arena or not
+    OutOfBounds { offset: i64, size: u64, arena: bool },
+    /// An access to a variable whose scope has ended
+    UseAfterScope,
+    /// An access to memory of the arena entered at `arena`, since reset
+    UseAfterReset { arena: Span },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::OutOfBounds {
+                offset,
+                size,
+                arena,
+            } => {
+                let kind = if *arena { "arena" } else { "stack" };
+                let end = i64::try_from(*size).unwrap_or(i64::MAX);
+                let distance = if *offset < 0 {
+                    offset.unsigned_abs()
+                } else {
+                    (offset - end).unsigned_abs() + 1
+                };
+                let whence = if distance <= REDZONE {
+                    "in its redzone"
+                } else {
+                    "far outside it"
+                };
+                write!(
+                    f,
+                    "AddressSanitizer: {}-buffer-overflow: access at offset {} of a {}-byte allocation, {}",
+                    kind, offset, size, whence
+                )
+            }
+            Violation::UseAfterScope => write!(
+                f,
+                "AddressSanitizer: stack-use-after-scope: the variable pointed to has gone out of scope"
+            ),
+            Violation::UseAfterReset { arena } => write!(
+                f,
+                "ArenaSanitizer: use-after-reset: the memory pointed to belonged to the arena at {}:{}, which has been reset",
+                arena.line, arena.column
+            ),
+        }
+    }
+}
+
+/// Slots that were freed while something still pointed into them. Each is
+/// kept allocated, though unreachable, for as long as it is poisoned.
+#[derive(Debug)]
+pub struct Quarantine<T> {
+    slots: HashMap<*const T, Weak<T>>,
+}
+
+impl<T> Default for Quarantine<T> {
+    fn default() -> Self {
+        Self {
+            slots: HashMap::new(),
+        }
+    }
+}
+
+impl<T> Quarantine<T> {
+    pub fn poison(&mut self, slot: &Rc<T>) {
+        self.slots.insert(Rc::as_ptr(slot), Rc::downgrade(slot));
+    }
+
+    pub fn is_poisoned(&self, slot: &Rc<T>) -> bool {
+        self.slots.contains_key(&Rc::as_ptr(slot))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let both = Sanitizers::parse("address,arena").unwrap();
+        assert!(both.address && both.arena);
+        assert_eq!(both.to_string(), "address,arena");
+        assert!(Sanitizers::parse("thread")
+            .unwrap_err()
+            .contains("unknown sanitizer `thread`"));
+        assert!(Sanitizers::default().is_empty());
+    }
+
+    #[test]
+    fn test_quarantine() {
+        let mut quarantine = Quarantine::default();
+        let freed = Rc::new(1);
+        let live = Rc::new(2);
+        quarantine.poison(&freed);
+        assert!(quarantine.is_poisoned(&freed));
+        assert!(!quarantine.is_poisoned(&live));
+        // The address stays taken after the last strong reference is gone
+        drop(freed);
+        assert!(!quarantine.is_poisoned(&Rc::new(3)));
+
+        let overflow = Violation::OutOfBounds {
+            offset: 24,
+            size: 24,
+            arena: false,
+        };
+        assert!(overflow
+            .to_string()
+            .ends_with("24-byte allocation, in its redzone"));
+    }
+}
@@ -0,0 +1,300 @@
+//! Embedding the compiler
+//!
+//! A [`Session`] compiles programs with one set of [`Options`], built up
+//! like the command line of `solo build`:
+//!
+//! ```
+//! use solo::link::CrateType;
+//! use solo::session::{OptLevel, Options, Session};
+//!
+//! let session = Session::new(
+//!     Options::new()
+//!         .with_opt_level(OptLevel::Release)
+//!         .with_crate_type(CrateType::StaticLib),
+//! );
+//! let result = session.compile("fn main() { print(1); }");
+//! assert!(result.is_ok());
+//! assert!(result.diagnostics().is_empty());
+//! ```
+//!
+//! Every build returns a [`CompileResult`] holding the files it wrote, what
+//! its optimizations did and, when it failed, the error with all its
+//! diagnostics, so tools do not have to parse the compiler's messages.
+//!
+//! Two parts wait on the rest of the compiler: no backend writes files yet,
+//! so [`CompileResult::artifacts`] is empty, and the language has no
+//! `#[cfg]` attribute yet, so [`Options::cfgs`] are only reported back by
+//! [`Session::cfg`].
+
+use std::path::PathBuf;
+
+use crate::error::{CompileError, Diagnostic};
+use crate::interp::{OverflowMode, RunOptions, Value};
+use crate::link::CrateType;
+use crate::sanitize::Sanitizers;
+use crate::target::Target;
+use crate::{closed_world, lto, pgo, timings};
+
+/// How much a build optimizes, as chosen by `--release`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OptLevel {
+    /// Fast builds; integer overflow traps
+    #[default]
+    Debug,
+    /// Optimized builds; integer overflow wraps
+    Release,
+}
+
+impl OptLevel {
+    pub fn overflow(self) -> OverflowMode {
+        OverflowMode::for_profile(self == OptLevel::Release)
+    }
+}
+
+/// What a [`Session`] builds and how
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Target to compile for, the host when `None`
+    pub target: Option<Target>,
+    pub opt_level: OptLevel,
+    /// `cfg` key/value pairs set in addition to the target's
+    pub cfgs: Vec<(String, String)>,
+    pub crate_type: CrateType,
+    /// Accept `#![feature(...)]`, as `--unstable` does
+    pub unstable: bool,
+    /// Optimize assuming nothing outside the program calls into it (see
+    /// [`closed_world`])
+    pub closed_world: bool,
+    /// Runtime memory-safety checks to instrument the program with
+    pub sanitizers: Sanitizers,
+    /// Count calls and branches at run time for `profile_use` (see [`pgo`])
+    pub profile_generate: bool,
+    /// Profraw file of representative runs to optimize for
+    pub profile_use: Option<PathBuf>,
+    /// Optimize the target and the package library as one program (see
+    /// [`lto`]); implied by `closed_world`
+    pub lto: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            target: None,
+            opt_level: OptLevel::Debug,
+            cfgs: Vec::new(),
+            crate_type: CrateType::Bin,
+            unstable: false,
+            closed_world: false,
+            sanitizers: Sanitizers::default(),
+            profile_generate: false,
+            profile_use: None,
+            lto: false,
+        }
+    }
+}
+
+impl Options {
+    /// The options of a plain `solo build`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_target(mut self, target: Target) -> Self {
+        self.target = Some(target);
+        self
+    }
+
+    pub fn with_opt_level(mut self, opt_level: OptLevel) -> Self {
+        self.opt_level = opt_level;
+        self
+    }
+
+    /// Set `key = "value"` in the `cfg` of the build
+    pub fn with_cfg(mut self, key: &str, value: &str) -> Self {
+        self.cfgs.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn with_crate_type(mut self, crate_type: CrateType) -> Self {
+        self.crate_type = crate_type;
+        self
+    }
+
+    pub fn with_unstable(mut self, unstable: bool) -> Self {
+        self.unstable = unstable;
+        self
+    }
+
+    pub fn with_closed_world(mut self, closed_world: bool) -> Self {
+        self.closed_world = closed_world;
+        self
+    }
+
+    pub fn with_lto(mut self, lto: bool) -> Self {
+        self.lto = lto;
+        self
+    }
+
+    pub fn with_sanitizers(mut self, sanitizers: Sanitizers) -> Self {
+        self.sanitizers = sanitizers;
+        self
+    }
+
+    pub fn with_profile_generate(mut self, profile_generate: bool) -> Self {
+        self.profile_generate = profile_generate;
+        self
+    }
+
+    pub fn with_profile_use(mut self, profile: impl Into<PathBuf>) -> Self {
+        self.profile_use = Some(profile.into());
+        self
+    }
+}
+
+/// A file a build wrote
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Artifact {
+    pub crate_type: CrateType,
+    pub path: PathBuf,
+}
+
+/// The outcome of a build
+#[derive(Debug, Default)]
+pub struct CompileResult {
+    /// Files written, none until code generation lands
+    pub artifacts: Vec<Artifact>,
+    /// Why the build failed
+    pub error: Option<CompileError>,
+    /// What closed-world optimization removed, when it ran
+    pub closed_world: Option<closed_world::Report>,
+    /// What link-time optimization removed, when it ran
+    pub lto: Option<lto::Report>,
+    /// What the profile of [`Options::profile_use`] decided
+    pub profile: Option<pgo::Plan>,
+}
+
+impl CompileResult {
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+
+    /// Diagnostics of the failed build, in source order
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        self.error.as_ref().map_or(&[], CompileError::diagnostics)
+    }
+}
+
+/// A compiler configured once for any number of builds
+#[derive(Debug, Clone, Default)]
+pub struct Session {
+    options: Options,
+}
+
+impl Session {
+    pub fn new(options: Options) -> Self {
+        Self { options }
+    }
+
+    pub fn options(&self) -> &Options {
+        &self.options
+    }
+
+    /// The target built for: the one chosen, or else the host
+    pub fn target(&self) -> Option<Target> {
+        self.options.target.or_else(Target::host)
+    }
+
+    /// `cfg` key/value pairs of the build, the target's first
+    pub fn cfg(&self) -> Vec<(String, String)> {
+        let target = self.target().map(|target| target.cfg()).unwrap_or_default();
+        let target = target
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value));
+        target.chain(self.options.cfgs.iter().cloned()).collect()
+    }
+
+    /// Compile `source` as the crate type
+    pub fn compile(&self, source: &str) -> CompileResult {
+        let mut timings = timings::Timings::default();
+        self.compile_timed(source, &mut timings)
+    }
+
+    /// Like [`compile`](Self::compile), timing each pass in `timings`
+    pub fn compile_timed(&self, source: &str, timings: &mut timings::Timings) -> CompileResult {
+        let compiled = if self.options.unstable {
+            Ok(())
+        } else {
+            crate::check_stable(source)
+        }
+        .and_then(|()| crate::compile_timed(source, &self.options, timings));
+        match compiled {
+            Ok(optimizations) => CompileResult {
+                artifacts: Vec::new(),
+                error: None,
+                closed_world: optimizations.closed_world,
+                lto: optimizations.lto,
+                profile: optimizations.profile,
+            },
+            Err(err) => err.into(),
+        }
+    }
+
+    /// Check and interpret `source`, overflowing as the opt level does and
+    /// with the sanitizers of the build
+    pub fn run(&self, source: &str) -> Result<Value, CompileError> {
+        if !self.options.unstable {
+            crate::check_stable(source)?;
+        }
+        let options = RunOptions {
+            overflow: self.options.opt_level.overflow(),
+            sanitizers: self.options.sanitizers,
+            ..RunOptions::default()
+        };
+        crate::run(source, &options)
+    }
+}
+
+impl From<CompileError> for CompileResult {
+    fn from(error: CompileError) -> Self {
+        Self {
+            error: Some(error),
+            ..Self::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session() {
+        let target = Target::find("x86_64-unknown-linux-gnu").unwrap();
+        let session = Session::new(
+            Options::new()
+                .with_target(target)
+                .with_crate_type(CrateType::DyLib)
+                .with_cfg("feature", "ranking"),
+        );
+        let result = session.compile("fn main() { print(1); }");
+        assert!(result.is_ok());
+        assert!(result.artifacts.is_empty());
+        assert_eq!(session.target(), Some(target));
+        let cfg = session.cfg();
+        assert!(cfg.contains(&("target_os".to_string(), "linux".to_string())));
+        assert_eq!(cfg.last().unwrap(), &("feature".into(), "ranking".into()));
+
+        let result = session.compile("fn main() { let x: i64 = true; }");
+        assert!(!result.is_ok());
+        assert_eq!(result.error.as_ref().unwrap().stage(), "type");
+        assert_eq!(
+            result.diagnostics()[0].span,
+            Some(crate::ast::Span::new(1, 26))
+        );
+
+        let gated = "#![feature(extern_c)]\nfn main() {}";
+        assert!(!session.compile(gated).is_ok());
+        let unstable = Session::new(session.options().clone().with_unstable(true));
+        assert!(unstable.compile(gated).is_ok());
+    }
+}
@@ -0,0 +1,60 @@
+//! `std::alloc` - the allocator protocol behind `arena in buffer { ... }`
+//!
+//! An arena normally bumps a pointer through chunks it gets from the
+//! system allocator, and frees them all when it ends. An arena opened with
+//! `in` gets its memory from a program-supplied allocator instead: a
+//! fixed buffer for targets without a heap, pages from `mmap`, or a pool
+//! shared between frames. Until traits land, an allocator is any struct or
+//! enum with the two methods below, in the same way an iterator is any type
+//! with `has_next` and `next`:
+//!
+//! - `allocate(&mut self, size: i64, align: i64) -> Option<i64>` returns the
+//!   address of `size` free bytes aligned to `align`, or `None` when the
+//!   allocator is out of memory
+//! - `reset(&mut self)` is called once the arena ends, when every block it
+//!   handed out is dead
+
+/// Name of the method that hands out a block
+pub const ALLOCATE: &str = "allocate";
+
+/// Name of the method called when the arena ends
+pub const RESET: &str = "reset";
+
+/// Check a block an allocator returned for `size` bytes aligned to `align`
+pub fn check_block(address: i64, size: i64, align: i64) -> Result<(), String> {
+    if address <= 0 {
+        return Err(format!(
+            "returned address {} for {} bytes, which is not a valid address",
+            address, size
+        ));
+    }
+    if address % align != 0 {
+        return Err(format!(
+            "returned address {:#x} for {} bytes, which is not aligned to {}",
+            address, size, align
+        ));
+    }
+    if address.checked_add(size).is_none() {
+        return Err(format!(
+            "returned address {:#x} for {} bytes, which runs past the end of memory",
+            address, size
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_block() {
+        assert_eq!(check_block(0x1000, 24, 8), Ok(()));
+        assert_eq!(
+            check_block(0x1004, 24, 8).unwrap_err(),
+            "returned address 0x1004 for 24 bytes, which is not aligned to 8"
+        );
+        assert!(check_block(0, 8, 8).is_err());
+        assert!(check_block(i64::MAX - 7, 16, 8).is_err());
+    }
+}
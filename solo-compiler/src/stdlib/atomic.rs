@@ -0,0 +1,313 @@
+//! `std::atomic` - atomic integers and memory orderings
+//!
+//! An atomic is a cell that threads may read and write at the same time.
+//! Every operation names an [`Ordering`], with the meaning it has in C11
+//! and Rust: `Relaxed` only makes the operation itself atomic, `Acquire`
+//! loads and `Release` stores order the memory accesses around them, and
+//! `SeqCst` operations additionally happen in one order that all threads
+//! agree on. The cells here are backed by Rust's atomics, so the same
+//! orderings reach the hardware on native targets.
+
+use std::sync::atomic::{self, AtomicI64};
+
+/// Name of the enum of orderings, which every program can use
+pub const ORDERING: &str = "Ordering";
+
+/// An atomic integer type such as `AtomicI64`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AtomicType {
+    pub name: &'static str,
+    /// Width of the integer, with `isize` and `usize` taken as 64 bits
+    pub bits: u32,
+    pub signed: bool,
+}
+
+const fn atomic(name: &'static str, bits: u32, signed: bool) -> AtomicType {
+    AtomicType { name, bits, signed }
+}
+
+pub const ATOMIC_TYPES: &[AtomicType] = &[
+    atomic("AtomicI32", 32, true),
+    atomic("AtomicI64", 64, true),
+    atomic("AtomicIsize", 64, true),
+    atomic("AtomicU32", 32, false),
+    atomic("AtomicU64", 64, false),
+    atomic("AtomicUsize", 64, false),
+];
+
+pub fn atomic_type(name: &str) -> Option<&'static AtomicType> {
+    ATOMIC_TYPES.iter().find(|ty| ty.name == name)
+}
+
+impl AtomicType {
+    /// `n` wrapped to the width and signedness of the type
+    fn wrap(&self, n: i64) -> i64 {
+        match (self.bits, self.signed) {
+            (32, true) => n as i32 as i64,
+            (32, false) => n as u32 as i64,
+            _ => n,
+        }
+    }
+
+    fn max(&self, a: i64, b: i64) -> i64 {
+        if self.signed {
+            a.max(b)
+        } else {
+            (a as u64).max(b as u64) as i64
+        }
+    }
+
+    fn min(&self, a: i64, b: i64) -> i64 {
+        if self.signed {
+            a.min(b)
+        } else {
+            (a as u64).min(b as u64) as i64
+        }
+    }
+}
+
+/// Memory ordering of an atomic operation or fence
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ordering {
+    Relaxed,
+    Release,
+    Acquire,
+    AcqRel,
+    SeqCst,
+}
+
+impl Ordering {
+    /// Every ordering, in the order of the variants of the Solo enum
+    pub const ALL: [Ordering; 5] = [
+        Ordering::Relaxed,
+        Ordering::Release,
+        Ordering::Acquire,
+        Ordering::AcqRel,
+        Ordering::SeqCst,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Ordering::Relaxed => "Relaxed",
+            Ordering::Release => "Release",
+            Ordering::Acquire => "Acquire",
+            Ordering::AcqRel => "AcqRel",
+            Ordering::SeqCst => "SeqCst",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|ordering| ordering.name() == name)
+    }
+
+    /// The C11 `memory_order` of the ordering, as taken by the `__atomic`
+    /// builtins that native code calls
+    pub fn c11(self) -> &'static str {
+        match self {
+            Ordering::Relaxed => "memory_order_relaxed",
+            Ordering::Release => "memory_order_release",
+            Ordering::Acquire => "memory_order_acquire",
+            Ordering::AcqRel => "memory_order_acq_rel",
+            Ordering::SeqCst => "memory_order_seq_cst",
+        }
+    }
+
+    fn to_std(self) -> atomic::Ordering {
+        match self {
+            Ordering::Relaxed => atomic::Ordering::Relaxed,
+            Ordering::Release => atomic::Ordering::Release,
+            Ordering::Acquire => atomic::Ordering::Acquire,
+            Ordering::AcqRel => atomic::Ordering::AcqRel,
+            Ordering::SeqCst => atomic::Ordering::SeqCst,
+        }
+    }
+
+    /// The strongest ordering a load may use in place of this one, for
+    /// the load half of a read-modify-write
+    fn for_load(self) -> Self {
+        match self {
+            Ordering::Release => Ordering::Relaxed,
+            Ordering::AcqRel => Ordering::Acquire,
+            other => other,
+        }
+    }
+}
+
+/// Check that `ordering` may be used by the atomic operation `operation`
+/// (a method name, or `fence`); `failure` selects the ordering
+/// `compare_exchange` uses when the comparison fails
+pub fn check_ordering(operation: &str, failure: bool, ordering: Ordering) -> Result<(), String> {
+    let invalid = match operation {
+        // A load has no writes to release, and a failed exchange is a load
+        "load" => matches!(ordering, Ordering::Release | Ordering::AcqRel),
+        "compare_exchange" if failure => matches!(ordering, Ordering::Release | Ordering::AcqRel),
+        // A store has no reads to acquire
+        "store" => matches!(ordering, Ordering::Acquire | Ordering::AcqRel),
+        // A relaxed fence orders nothing
+        "fence" => ordering == Ordering::Relaxed,
+        _ => false,
+    };
+    if invalid {
+        let what = if failure {
+            "the failure ordering of `compare_exchange`".to_string()
+        } else {
+            format!("`{}`", operation)
+        };
+        Err(format!(
+            "{} cannot use `Ordering::{}`",
+            what,
+            ordering.name()
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Read-modify-write operation, named like the method performing it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rmw {
+    Swap,
+    Add,
+    Sub,
+    And,
+    Or,
+    Xor,
+    Max,
+    Min,
+}
+
+impl Rmw {
+    pub fn from_method(name: &str) -> Option<Self> {
+        Some(match name {
+            "swap" => Rmw::Swap,
+            "fetch_add" => Rmw::Add,
+            "fetch_sub" => Rmw::Sub,
+            "fetch_and" => Rmw::And,
+            "fetch_or" => Rmw::Or,
+            "fetch_xor" => Rmw::Xor,
+            "fetch_max" => Rmw::Max,
+            "fetch_min" => Rmw::Min,
+            _ => return None,
+        })
+    }
+}
+
+/// An atomic integer cell
+#[derive(Debug)]
+pub struct Atomic {
+    pub ty: &'static AtomicType,
+    cell: AtomicI64,
+}
+
+impl Atomic {
+    pub fn new(ty: &'static AtomicType, value: i64) -> Self {
+        Self {
+            ty,
+            cell: AtomicI64::new(ty.wrap(value)),
+        }
+    }
+
+    /// `ordering` must be valid for `load` (see [`check_ordering`])
+    pub fn load(&self, ordering: Ordering) -> i64 {
+        self.cell.load(ordering.to_std())
+    }
+
+    /// `ordering` must be valid for `store`
+    pub fn store(&self, value: i64, ordering: Ordering) {
+        self.cell.store(self.ty.wrap(value), ordering.to_std());
+    }
+
+    /// Apply `op` with `operand`, wrapping at the type's width, and return
+    /// the previous value
+    pub fn update(&self, op: Rmw, operand: i64, ordering: Ordering) -> i64 {
+        let apply = |old: i64| {
+            let new = match op {
+                Rmw::Swap => operand,
+                Rmw::Add => old.wrapping_add(operand),
+                Rmw::Sub => old.wrapping_sub(operand),
+                Rmw::And => old & operand,
+                Rmw::Or => old | operand,
+                Rmw::Xor => old ^ operand,
+                Rmw::Max => self.ty.max(old, operand),
+                Rmw::Min => self.ty.min(old, operand),
+            };
+            Some(self.ty.wrap(new))
+        };
+        match self
+            .cell
+            .fetch_update(ordering.to_std(), ordering.for_load().to_std(), apply)
+        {
+            Ok(old) | Err(old) => old,
+        }
+    }
+
+    /// Replace the value with `new` if it is `current`, returning the
+    /// value found: the exchange happened if that is `current`
+    pub fn compare_exchange(
+        &self,
+        current: i64,
+        new: i64,
+        success: Ordering,
+        failure: Ordering,
+    ) -> i64 {
+        match self.cell.compare_exchange(
+            self.ty.wrap(current),
+            self.ty.wrap(new),
+            success.to_std(),
+            failure.to_std(),
+        ) {
+            Ok(old) | Err(old) => old,
+        }
+    }
+}
+
+/// A memory fence, ordering the accesses around it without accessing
+/// anything itself
+pub fn fence(ordering: Ordering) {
+    atomic::fence(ordering.to_std());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_atomics() {
+        let counter = Atomic::new(atomic_type("AtomicU32").unwrap(), 0);
+        assert_eq!(counter.update(Rmw::Sub, 1, Ordering::AcqRel), 0);
+        assert_eq!(counter.load(Ordering::Acquire), i64::from(u32::MAX));
+        assert_eq!(counter.update(Rmw::Max, 7, Ordering::Relaxed), 4294967295);
+        assert_eq!(
+            counter.compare_exchange(1, 2, Ordering::SeqCst, Ordering::Relaxed),
+            4294967295
+        );
+        assert_eq!(
+            counter.compare_exchange(4294967295, 2, Ordering::SeqCst, Ordering::Relaxed),
+            4294967295
+        );
+        assert_eq!(counter.load(Ordering::SeqCst), 2);
+
+        let signed = Atomic::new(atomic_type("AtomicI32").unwrap(), i64::from(i32::MAX));
+        signed.update(Rmw::Add, 1, Ordering::SeqCst);
+        assert_eq!(signed.load(Ordering::Relaxed), i64::from(i32::MIN));
+    }
+
+    #[test]
+    fn test_orderings() {
+        assert!(check_ordering("load", false, Ordering::Acquire).is_ok());
+        assert_eq!(
+            check_ordering("load", false, Ordering::Release).unwrap_err(),
+            "`load` cannot use `Ordering::Release`"
+        );
+        assert!(check_ordering("store", false, Ordering::AcqRel).is_err());
+        assert!(check_ordering("compare_exchange", false, Ordering::AcqRel).is_ok());
+        assert!(check_ordering("compare_exchange", true, Ordering::AcqRel).is_err());
+        assert!(check_ordering("fence", false, Ordering::Relaxed).is_err());
+        assert_eq!(
+            Ordering::from_name("SeqCst").map(Ordering::c11),
+            Some("memory_order_seq_cst")
+        );
+    }
+}
@@ -0,0 +1,213 @@
+//! `std::binary` - compact binary serialization
+//!
+//! Integers are LEB128 varints (signed values are zigzag-encoded first),
+//! floats are 8 bytes little-endian, and strings and byte buffers are
+//! length-prefixed with a varint. Records are the concatenation of their
+//! fields in schema order, with no field names or tags on the wire.
+
+use super::record::{FieldType, FieldValue, Schema};
+
+/// Appends encoded values to an in-memory buffer
+#[derive(Debug, Default)]
+pub struct Encoder {
+    buf: Vec<u8>,
+}
+
+impl Encoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn write_varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.buf.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+        self.buf.push(value as u8);
+    }
+
+    pub fn write_signed(&mut self, value: i64) {
+        self.write_varint(((value << 1) ^ (value >> 63)) as u64);
+    }
+
+    pub fn write_f64(&mut self, value: f64) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.buf.push(value as u8);
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.write_varint(bytes.len() as u64);
+        self.buf.extend_from_slice(bytes);
+    }
+
+    pub fn write_str(&mut self, s: &str) {
+        self.write_bytes(s.as_bytes());
+    }
+
+    pub fn write_record(&mut self, schema: &Schema, values: &[FieldValue]) -> Result<(), String> {
+        schema.validate(values)?;
+        for value in values {
+            match value {
+                FieldValue::Int(n) => self.write_signed(*n),
+                FieldValue::Float(f) => self.write_f64(*f),
+                FieldValue::Bool(b) => self.write_bool(*b),
+                FieldValue::Str(s) => self.write_str(s),
+                FieldValue::Bytes(bytes) => self.write_bytes(bytes),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+/// Reads encoded values from a byte slice
+pub struct Decoder<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    pub fn is_at_end(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    pub fn read_varint(&mut self) -> Result<u64, String> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.read_byte()?;
+            value |= u64::from(byte & 0x7F) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(format!("varint at offset {} exceeds 64 bits", self.pos))
+    }
+
+    pub fn read_signed(&mut self) -> Result<i64, String> {
+        let raw = self.read_varint()?;
+        Ok((raw >> 1) as i64 ^ -((raw & 1) as i64))
+    }
+
+    pub fn read_f64(&mut self) -> Result<f64, String> {
+        let bytes = self.take(8)?;
+        let mut array = [0u8; 8];
+        array.copy_from_slice(bytes);
+        Ok(f64::from_le_bytes(array))
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, String> {
+        match self.read_byte()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            other => Err(format!(
+                "invalid bool byte {} at offset {}",
+                other,
+                self.pos - 1
+            )),
+        }
+    }
+
+    pub fn read_bytes(&mut self) -> Result<Vec<u8>, String> {
+        let len = self.read_varint()?;
+        let len = usize::try_from(len).map_err(|_| format!("length {} too large", len))?;
+        Ok(self.take(len)?.to_vec())
+    }
+
+    pub fn read_str(&mut self) -> Result<String, String> {
+        let start = self.pos;
+        String::from_utf8(self.read_bytes()?)
+            .map_err(|_| format!("invalid UTF-8 in string at offset {}", start))
+    }
+
+    pub fn read_record(&mut self, schema: &Schema) -> Result<Vec<FieldValue>, String> {
+        schema
+            .fields
+            .iter()
+            .map(|(_, ty)| {
+                Ok(match ty {
+                    FieldType::Int => FieldValue::Int(self.read_signed()?),
+                    FieldType::Float => FieldValue::Float(self.read_f64()?),
+                    FieldType::Bool => FieldValue::Bool(self.read_bool()?),
+                    FieldType::Str => FieldValue::Str(self.read_str()?),
+                    FieldType::Bytes => FieldValue::Bytes(self.read_bytes()?),
+                })
+            })
+            .collect()
+    }
+
+    fn read_byte(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|&end| end <= self.data.len())
+            .ok_or_else(|| format!("unexpected end of input at offset {}", self.pos))?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_varint_encoding() {
+        let mut encoder = Encoder::new();
+        encoder.write_varint(300);
+        assert_eq!(encoder.finish(), vec![0xAC, 0x02]);
+    }
+
+    #[test]
+    fn test_zigzag_round_trip() {
+        let mut encoder = Encoder::new();
+        for n in [0, -1, 1, i64::MIN, i64::MAX] {
+            encoder.write_signed(n);
+        }
+        let bytes = encoder.finish();
+        let mut decoder = Decoder::new(&bytes);
+        for n in [0, -1, 1, i64::MIN, i64::MAX] {
+            assert_eq!(decoder.read_signed().unwrap(), n);
+        }
+        assert!(decoder.is_at_end());
+    }
+
+    #[test]
+    fn test_record_round_trip() {
+        let schema = Schema::new()
+            .field("id", FieldType::Int)
+            .field("name", FieldType::Str)
+            .field("weight", FieldType::Float)
+            .field("active", FieldType::Bool);
+        let values = vec![
+            FieldValue::Int(-7),
+            FieldValue::Str("ünïcode".to_string()),
+            FieldValue::Float(0.25),
+            FieldValue::Bool(true),
+        ];
+        let mut encoder = Encoder::new();
+        encoder.write_record(&schema, &values).unwrap();
+        let bytes = encoder.finish();
+        assert_eq!(Decoder::new(&bytes).read_record(&schema).unwrap(), values);
+    }
+
+    #[test]
+    fn test_truncated_input() {
+        let mut decoder = Decoder::new(&[0x05, b'a']);
+        assert!(decoder.read_str().unwrap_err().contains("unexpected end"));
+    }
+}
@@ -0,0 +1,153 @@
+//! `std::channel` - message passing between threads
+//!
+//! A channel is a first-in first-out queue of messages, optionally bounded.
+//! Senders wait while a bounded channel is full and the receiver waits
+//! while it is empty. Sending moves the message into the channel, so a
+//! value is owned by one thread at a time and never shared.
+//!
+//! The blocking operations are for native threads; a scheduler that runs
+//! threads one at a time uses the `try_` operations and decides itself what
+//! to run while a thread cannot make progress.
+
+use std::collections::VecDeque;
+use std::sync::{Condvar, Mutex, MutexGuard};
+
+pub struct Channel<T> {
+    state: Mutex<State<T>>,
+    /// Signalled whenever a message is added or taken
+    changed: Condvar,
+}
+
+struct State<T> {
+    queue: VecDeque<T>,
+    /// Most messages the channel holds, or `None` if unbounded
+    capacity: Option<usize>,
+}
+
+impl<T> Channel<T> {
+    /// A channel holding at most `capacity` messages, or any number with
+    /// `None`; a capacity of zero is treated as one
+    pub fn new(capacity: Option<usize>) -> Self {
+        Self {
+            state: Mutex::new(State {
+                queue: VecDeque::new(),
+                capacity: capacity.map(|n| n.max(1)),
+            }),
+            changed: Condvar::new(),
+        }
+    }
+
+    pub fn capacity(&self) -> Option<usize> {
+        self.lock().capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Add `message`, waiting while the channel is full
+    pub fn send(&self, message: T) {
+        let mut state = self.lock();
+        while state.is_full() {
+            state = self.changed.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+        state.queue.push_back(message);
+        self.changed.notify_all();
+    }
+
+    /// Take the oldest message, waiting while the channel is empty
+    pub fn recv(&self) -> T {
+        let mut state = self.lock();
+        loop {
+            if let Some(message) = state.queue.pop_front() {
+                self.changed.notify_all();
+                return message;
+            }
+            state = self.changed.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    /// Add `message` unless the channel is full, in which case it is
+    /// handed back
+    pub fn try_send(&self, message: T) -> Result<(), T> {
+        let mut state = self.lock();
+        if state.is_full() {
+            return Err(message);
+        }
+        state.queue.push_back(message);
+        self.changed.notify_all();
+        Ok(())
+    }
+
+    /// Add `message` even if the channel is full, for a scheduler that
+    /// cannot make the sender wait
+    pub fn force_send(&self, message: T) {
+        self.lock().queue.push_back(message);
+        self.changed.notify_all();
+    }
+
+    /// Take the oldest message, if there is one
+    pub fn try_recv(&self) -> Option<T> {
+        let message = self.lock().queue.pop_front();
+        if message.is_some() {
+            self.changed.notify_all();
+        }
+        message
+    }
+
+    /// A panic while the lock was held cannot leave the queue half
+    /// updated, so a poisoned lock is still usable
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl<T> State<T> {
+    fn is_full(&self) -> bool {
+        self.capacity.is_some_and(|n| self.queue.len() >= n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_bounded() {
+        let channel = Channel::new(Some(2));
+        assert_eq!(channel.try_send('a'), Ok(()));
+        assert_eq!(channel.try_send('b'), Ok(()));
+        assert_eq!(channel.try_send('c'), Err('c'));
+        channel.force_send('c');
+        assert_eq!(channel.len(), 3);
+        assert_eq!(channel.try_recv(), Some('a'));
+        assert_eq!(channel.try_recv(), Some('b'));
+        assert_eq!(channel.try_recv(), Some('c'));
+        assert_eq!(channel.try_recv(), None);
+        assert_eq!(Channel::<()>::new(Some(0)).capacity(), Some(1));
+    }
+
+    #[test]
+    fn test_native_threads() {
+        let channel = Arc::new(Channel::new(Some(1)));
+        let producers: Vec<_> = (0..4)
+            .map(|p| {
+                let channel = Arc::clone(&channel);
+                thread::spawn(move || (0..100).for_each(|i| channel.send(p * 100 + i)))
+            })
+            .collect();
+        let mut received: Vec<i32> = (0..400).map(|_| channel.recv()).collect();
+        producers
+            .into_iter()
+            .for_each(|producer| producer.join().unwrap());
+        received.sort_unstable();
+        assert_eq!(received, (0..400).collect::<Vec<_>>());
+        assert!(channel.is_empty());
+    }
+}
@@ -0,0 +1,258 @@
+//! `Vec<T>` and `Map<K, V>` - growable collections in an arena
+//!
+//! A collection is created from an arena handle and every buffer it ever
+//! uses comes from that arena. Growing allocates a buffer twice as large in
+//! the same arena, whichever arena is innermost at the time, and the old
+//! buffer is released with the arena rather than on its own; a collection
+//! therefore never outlives its arena, and never holds data of a shorter
+//! lived one. Buffers are allocated lazily, when the first item arrives.
+//! These types only track contents and capacity; what a buffer costs is up
+//! to the caller.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+/// Capacity of the first buffer of a collection created empty
+pub const MIN_CAPACITY: usize = 4;
+
+/// Capacity to reallocate to when a buffer of `capacity` is full
+pub fn grow(capacity: usize) -> usize {
+    capacity.saturating_mul(2).max(MIN_CAPACITY)
+}
+
+/// A growable array with an explicit capacity
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArenaVec<T> {
+    items: Vec<T>,
+    capacity: usize,
+    /// Whether a buffer of `capacity` has been allocated yet
+    allocated: bool,
+}
+
+impl<T> ArenaVec<T> {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            items: Vec::with_capacity(capacity),
+            capacity,
+            allocated: false,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Append `item`, returning the capacity of the buffer allocated for
+    /// it, if one was
+    pub fn push(&mut self, item: T) -> Option<usize> {
+        let full = self.items.len() == self.capacity;
+        if full {
+            self.capacity = grow(self.capacity);
+        }
+        let allocated = (full || !self.allocated).then_some(self.capacity);
+        self.allocated = true;
+        self.items.push(item);
+        allocated
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        self.items.pop()
+    }
+
+    /// Remove every item, keeping the buffer
+    pub fn clear(&mut self) {
+        self.items.clear();
+    }
+
+    pub fn items(&self) -> &[T] {
+        &self.items
+    }
+
+    pub fn items_mut(&mut self) -> &mut [T] {
+        &mut self.items
+    }
+
+    pub fn into_items(self) -> Vec<T> {
+        self.items
+    }
+}
+
+/// Key of a [`Table`]: the hashable values of Solo
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Key {
+    Int(i64),
+    Bool(bool),
+    Str(String),
+    /// Enum variant: enum name, variant name and discriminant
+    Variant(String, String, i64),
+}
+
+/// A hash table with open addressing and linear probing
+///
+/// The table grows before it is three quarters full. Hashing is
+/// deterministic, so iteration order only depends on what was inserted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Table<V> {
+    slots: Vec<Option<(Key, V)>>,
+    len: usize,
+}
+
+impl<V> Default for Table<V> {
+    fn default() -> Self {
+        Self {
+            slots: Vec::new(),
+            len: 0,
+        }
+    }
+}
+
+impl<V> Table<V> {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of slots
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Insert `value` under `key`, returning the value it replaces and the
+    /// new capacity if the table had to grow
+    pub fn insert(&mut self, key: Key, value: V) -> (Option<V>, Option<usize>) {
+        let mut grown = None;
+        if (self.len + 1) * 4 > self.slots.len() * 3 {
+            let capacity = grow(self.slots.len());
+            self.resize(capacity);
+            grown = Some(capacity);
+        }
+        let i = self.probe(&key);
+        match &mut self.slots[i] {
+            Some((_, old)) => (Some(std::mem::replace(old, value)), grown),
+            empty => {
+                *empty = Some((key, value));
+                self.len += 1;
+                (None, grown)
+            }
+        }
+    }
+
+    pub fn get(&self, key: &Key) -> Option<&V> {
+        if self.slots.is_empty() {
+            return None;
+        }
+        self.slots[self.probe(key)].as_ref().map(|(_, value)| value)
+    }
+
+    pub fn remove(&mut self, key: &Key) -> Option<V> {
+        if self.slots.is_empty() {
+            return None;
+        }
+        let mut hole = self.probe(key);
+        let (_, value) = self.slots[hole].take()?;
+        self.len -= 1;
+        // Shift later entries of the probe sequence back into the hole, so
+        // that lookups never stop early at it
+        let mask = self.slots.len() - 1;
+        let mut i = hole;
+        loop {
+            i = (i + 1) & mask;
+            let Some((key, _)) = &self.slots[i] else {
+                break;
+            };
+            let home = slot_of(key, mask);
+            let distance = |from: usize, to: usize| to.wrapping_sub(from) & mask;
+            if distance(home, hole) < distance(home, i) {
+                self.slots[hole] = self.slots[i].take();
+                hole = i;
+            }
+        }
+        Some(value)
+    }
+
+    /// Entries in slot order
+    pub fn iter(&self) -> impl Iterator<Item = (&Key, &V)> {
+        self.slots.iter().flatten().map(|(key, value)| (key, value))
+    }
+
+    /// Slot holding `key`, or the empty slot where it belongs
+    fn probe(&self, key: &Key) -> usize {
+        let mask = self.slots.len() - 1;
+        let mut i = slot_of(key, mask);
+        while let Some((k, _)) = &self.slots[i] {
+            if k == key {
+                break;
+            }
+            i = (i + 1) & mask;
+        }
+        i
+    }
+
+    fn resize(&mut self, capacity: usize) {
+        let old = std::mem::take(&mut self.slots);
+        self.slots.resize_with(capacity, || None);
+        for (key, value) in old.into_iter().flatten() {
+            let i = self.probe(&key);
+            self.slots[i] = Some((key, value));
+        }
+    }
+}
+
+/// Home slot of `key` in a table of `mask + 1` slots (a power of two)
+fn slot_of(key: &Key, mask: usize) -> usize {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish() as usize & mask
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vec_growth() {
+        let mut v = ArenaVec::with_capacity(0);
+        let grown: Vec<Option<usize>> = (0..9).map(|i| v.push(i)).collect();
+        assert_eq!(grown[0], Some(4));
+        assert_eq!(grown[4], Some(8));
+        assert_eq!(grown[8], Some(16));
+        assert_eq!(grown.iter().flatten().count(), 3);
+        assert_eq!((v.len(), v.capacity()), (9, 16));
+        assert_eq!(v.pop(), Some(8));
+
+        let mut reserved = ArenaVec::with_capacity(2);
+        assert_eq!(reserved.push('a'), Some(2));
+        assert_eq!(reserved.push('b'), None);
+        reserved.clear();
+        assert_eq!(reserved.push('c'), None);
+    }
+
+    #[test]
+    fn test_table() {
+        let mut table = Table::default();
+        for i in 0..100 {
+            assert_eq!(table.insert(Key::Int(i), i * 10).0, None);
+        }
+        assert_eq!(table.insert(Key::Int(7), 0).0, Some(70));
+        assert_eq!((table.len(), table.capacity()), (100, 256));
+        for i in (0..100).step_by(3) {
+            assert_eq!(table.remove(&Key::Int(i)), Some(i * 10));
+        }
+        for i in 0..100 {
+            let expected = (i % 3 != 0).then_some(if i == 7 { 0 } else { i * 10 });
+            assert_eq!(table.get(&Key::Int(i)).copied(), expected);
+        }
+        assert_eq!(table.get(&Key::Str("7".to_string())), None);
+    }
+}
@@ -0,0 +1,250 @@
+//! `std::csv` - streaming CSV reading and writing
+//!
+//! Follows RFC 4180: fields containing the delimiter, quotes, or line breaks
+//! are quoted, and quotes inside quoted fields are doubled. Readers yield one
+//! record at a time so arbitrarily large inputs can be processed in a fixed
+//! amount of memory.
+
+use std::io::{BufRead, Write};
+
+use super::record::{FieldType, FieldValue, Schema};
+
+/// Streaming CSV reader over any buffered input
+pub struct CsvReader<R: BufRead> {
+    input: R,
+    delimiter: char,
+    line: usize,
+}
+
+impl<R: BufRead> CsvReader<R> {
+    pub fn new(input: R) -> Self {
+        Self {
+            input,
+            delimiter: ',',
+            line: 0,
+        }
+    }
+
+    pub fn with_delimiter(mut self, delimiter: char) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Read the next record, or `None` at end of input
+    pub fn next_record(&mut self) -> Option<Result<Vec<String>, String>> {
+        let mut fields = Vec::new();
+        let mut field = String::new();
+        let mut in_quotes = false;
+        let mut started = false;
+
+        loop {
+            let mut line = String::new();
+            match self.input.read_line(&mut line) {
+                Ok(0) => {
+                    if in_quotes {
+                        return Some(Err(format!(
+                            "line {}: unterminated quoted field",
+                            self.line
+                        )));
+                    }
+                    if !started {
+                        return None;
+                    }
+                    fields.push(field);
+                    return Some(Ok(fields));
+                }
+                Ok(_) => {}
+                Err(err) => return Some(Err(format!("line {}: {}", self.line + 1, err))),
+            }
+            self.line += 1;
+            started = true;
+
+            let mut chars = line.chars().peekable();
+            while let Some(ch) = chars.next() {
+                if in_quotes {
+                    if ch == '"' {
+                        if chars.peek() == Some(&'"') {
+                            chars.next();
+                            field.push('"');
+                        } else {
+                            in_quotes = false;
+                        }
+                    } else {
+                        field.push(ch);
+                    }
+                } else if ch == '"' && field.is_empty() {
+                    in_quotes = true;
+                } else if ch == self.delimiter {
+                    fields.push(std::mem::take(&mut field));
+                } else if ch == '\n' || (ch == '\r' && chars.peek() == Some(&'\n')) {
+                    break;
+                } else {
+                    field.push(ch);
+                }
+            }
+
+            if !in_quotes {
+                fields.push(field);
+                return Some(Ok(fields));
+            }
+        }
+    }
+
+    /// Read the next record and convert it according to `schema`
+    pub fn next_typed(&mut self, schema: &Schema) -> Option<Result<Vec<FieldValue>, String>> {
+        let line = self.line + 1;
+        let record = match self.next_record()? {
+            Ok(record) => record,
+            Err(err) => return Some(Err(err)),
+        };
+        Some(parse_record(schema, &record).map_err(|err| format!("line {}: {}", line, err)))
+    }
+}
+
+impl<R: BufRead> Iterator for CsvReader<R> {
+    type Item = Result<Vec<String>, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_record()
+    }
+}
+
+fn parse_record(schema: &Schema, record: &[String]) -> Result<Vec<FieldValue>, String> {
+    if record.len() != schema.fields.len() {
+        return Err(format!(
+            "expected {} fields, found {}",
+            schema.fields.len(),
+            record.len()
+        ));
+    }
+    schema
+        .fields
+        .iter()
+        .zip(record)
+        .map(|((name, ty), raw)| {
+            let invalid = || format!("field `{}`: cannot parse {:?} as {:?}", name, raw, ty);
+            Ok(match ty {
+                FieldType::Int => FieldValue::Int(raw.trim().parse().map_err(|_| invalid())?),
+                FieldType::Float => FieldValue::Float(raw.trim().parse().map_err(|_| invalid())?),
+                FieldType::Bool => FieldValue::Bool(raw.trim().parse().map_err(|_| invalid())?),
+                FieldType::Str => FieldValue::Str(raw.clone()),
+                FieldType::Bytes => FieldValue::Bytes(raw.as_bytes().to_vec()),
+            })
+        })
+        .collect()
+}
+
+/// Streaming CSV writer over any output
+pub struct CsvWriter<W: Write> {
+    output: W,
+    delimiter: char,
+}
+
+impl<W: Write> CsvWriter<W> {
+    pub fn new(output: W) -> Self {
+        Self {
+            output,
+            delimiter: ',',
+        }
+    }
+
+    pub fn with_delimiter(mut self, delimiter: char) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    pub fn write_record<S: AsRef<str>>(&mut self, fields: &[S]) -> Result<(), String> {
+        let mut line = String::new();
+        for (i, field) in fields.iter().enumerate() {
+            if i > 0 {
+                line.push(self.delimiter);
+            }
+            line.push_str(&self.quote(field.as_ref()));
+        }
+        line.push('\n');
+        self.output
+            .write_all(line.as_bytes())
+            .map_err(|err| err.to_string())
+    }
+
+    /// Write the field names of `schema` as a header row
+    pub fn write_header(&mut self, schema: &Schema) -> Result<(), String> {
+        self.write_record(&schema.names())
+    }
+
+    pub fn write_typed(&mut self, schema: &Schema, values: &[FieldValue]) -> Result<(), String> {
+        schema.validate(values)?;
+        let fields: Vec<String> = values
+            .iter()
+            .map(|value| match value {
+                FieldValue::Int(n) => n.to_string(),
+                FieldValue::Float(f) => f.to_string(),
+                FieldValue::Bool(b) => b.to_string(),
+                FieldValue::Str(s) => s.clone(),
+                FieldValue::Bytes(bytes) => String::from_utf8_lossy(bytes).into_owned(),
+            })
+            .collect();
+        self.write_record(&fields)
+    }
+
+    pub fn flush(&mut self) -> Result<(), String> {
+        self.output.flush().map_err(|err| err.to_string())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.output
+    }
+
+    fn quote(&self, field: &str) -> String {
+        let needs_quotes = field
+            .chars()
+            .any(|c| c == self.delimiter || c == '"' || c == '\n' || c == '\r');
+        if needs_quotes {
+            format!("\"{}\"", field.replace('"', "\"\""))
+        } else {
+            field.to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_quoted_fields() {
+        let input = "name,quote\nada,\"says \"\"hi\"\", then\nleaves\"\nbob,plain\n";
+        let records: Vec<_> = CsvReader::new(input.as_bytes())
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[1][1], "says \"hi\", then\nleaves");
+        assert_eq!(records[2], vec!["bob", "plain"]);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut writer = CsvWriter::new(Vec::new());
+        writer.write_record(&["a,b", "c\"d", "e"]).unwrap();
+        let bytes = writer.into_inner();
+        let record = CsvReader::new(bytes.as_slice())
+            .next_record()
+            .unwrap()
+            .unwrap();
+        assert_eq!(record, vec!["a,b", "c\"d", "e"]);
+    }
+
+    #[test]
+    fn test_typed_records() {
+        let schema = Schema::new()
+            .field("id", FieldType::Int)
+            .field("score", FieldType::Float);
+        let mut reader = CsvReader::new("1,0.5\nx,2\n".as_bytes());
+        assert_eq!(
+            reader.next_typed(&schema).unwrap().unwrap(),
+            vec![FieldValue::Int(1), FieldValue::Float(0.5)]
+        );
+        let err = reader.next_typed(&schema).unwrap().unwrap_err();
+        assert!(err.starts_with("line 2"));
+    }
+}
@@ -0,0 +1,118 @@
+//! `std::io` - files and the standard streams
+//!
+//! Errors are returned as messages naming the file, for the caller to
+//! report (the interpreter panics with them). Everything goes through
+//! Rust's `std`, which calls libc on native targets and WASI on
+//! `wasm32-wasip1`; on `wasm32-unknown-unknown` there is no filesystem and
+//! file operations fail.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+
+/// The whole contents of the file at `path`
+pub fn read_file(path: &str) -> Result<String, String> {
+    fs::read_to_string(path).map_err(|err| format!("cannot read `{}`: {}", path, err))
+}
+
+/// Replace the contents of the file at `path`, creating it if needed, or
+/// add to the end of them with `append`
+pub fn write_file(path: &str, contents: &str, append: bool) -> Result<(), String> {
+    OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(path)
+        .and_then(|mut file| file.write_all(contents.as_bytes()))
+        .map_err(|err| format!("cannot write `{}`: {}", path, err))
+}
+
+pub fn exists(path: &str) -> bool {
+    Path::new(path).exists()
+}
+
+/// The next line of `input` with its terminator, or `""` at end of input
+pub fn read_line(input: &mut impl BufRead) -> Result<String, String> {
+    let mut line = String::new();
+    input
+        .read_line(&mut line)
+        .map_err(|err| format!("cannot read a line: {}", err))?;
+    Ok(line)
+}
+
+/// Lines of a buffered input without their terminators, read one at a time
+pub struct Lines<R: BufRead> {
+    input: R,
+    /// Where the input came from, for error messages
+    name: String,
+}
+
+impl<R: BufRead> Lines<R> {
+    pub fn new(input: R, name: &str) -> Self {
+        Self {
+            input,
+            name: name.to_string(),
+        }
+    }
+}
+
+impl Lines<BufReader<File>> {
+    /// Lines of the file at `path`
+    pub fn open(path: &str) -> Result<Self, String> {
+        File::open(path)
+            .map(|file| Lines::new(BufReader::new(file), path))
+            .map_err(|err| format!("cannot read `{}`: {}", path, err))
+    }
+}
+
+impl<R: BufRead> Iterator for Lines<R> {
+    type Item = Result<String, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut line = String::new();
+        match self.input.read_line(&mut line) {
+            Ok(0) => None,
+            Ok(_) => {
+                if line.ends_with('\n') {
+                    line.pop();
+                    if line.ends_with('\r') {
+                        line.pop();
+                    }
+                }
+                Some(Ok(line))
+            }
+            Err(err) => Some(Err(format!("cannot read `{}`: {}", self.name, err))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lines() {
+        let input = "one\r\ntwo\n\nthree".as_bytes();
+        let lines: Result<Vec<String>, String> = Lines::new(input, "input").collect();
+        assert_eq!(lines.unwrap(), ["one", "two", "", "three"]);
+
+        let mut input = "a\nb".as_bytes();
+        assert_eq!(read_line(&mut input).unwrap(), "a\n");
+        assert_eq!(read_line(&mut input).unwrap(), "b");
+        assert_eq!(read_line(&mut input).unwrap(), "");
+    }
+
+    #[test]
+    fn test_files() {
+        let path = std::env::temp_dir().join(format!("solo-io-{}.txt", std::process::id()));
+        let path = path.to_str().unwrap();
+        write_file(path, "x\n", false).unwrap();
+        write_file(path, "y\n", true).unwrap();
+        assert_eq!(read_file(path).unwrap(), "x\ny\n");
+        assert_eq!(Lines::open(path).unwrap().count(), 2);
+        fs::remove_file(path).unwrap();
+        assert!(!exists(path));
+        assert!(read_file(path).unwrap_err().starts_with("cannot read `"));
+    }
+}
@@ -0,0 +1,17 @@
+//! Solo standard library (host implementations)
+//!
+//! Functions in this module back the `std::*` modules available to Solo
+//! programs. They operate on plain Rust values so both the interpreter and
+//! native runtime shims can share them.
+
+pub mod alloc;
+pub mod atomic;
+pub mod binary;
+pub mod channel;
+pub mod collections;
+pub mod csv;
+pub mod io;
+pub mod record;
+pub mod simd;
+pub mod string;
+pub mod sync;
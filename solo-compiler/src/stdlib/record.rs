@@ -0,0 +1,81 @@
+//! Flat records shared by the serialization modules
+//!
+//! A [`Schema`] describes the fields of a struct-like value. The compiler
+//! derives one for every type annotated with `#[derive(Csv)]` or
+//! `#[derive(Binary)]`; `std::csv` and `std::binary` only ever see schemas
+//! and values, never Solo types directly.
+
+/// Primitive field types supported by the serialization formats
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FieldType {
+    Int,
+    Float,
+    Bool,
+    Str,
+    Bytes,
+}
+
+/// A single field value
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldValue {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Str(String),
+    Bytes(Vec<u8>),
+}
+
+impl FieldValue {
+    pub fn field_type(&self) -> FieldType {
+        match self {
+            FieldValue::Int(_) => FieldType::Int,
+            FieldValue::Float(_) => FieldType::Float,
+            FieldValue::Bool(_) => FieldType::Bool,
+            FieldValue::Str(_) => FieldType::Str,
+            FieldValue::Bytes(_) => FieldType::Bytes,
+        }
+    }
+}
+
+/// Ordered field names and types of a record
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Schema {
+    pub fields: Vec<(String, FieldType)>,
+}
+
+impl Schema {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn field(mut self, name: &str, ty: FieldType) -> Self {
+        self.fields.push((name.to_string(), ty));
+        self
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.fields.iter().map(|(name, _)| name.as_str()).collect()
+    }
+
+    /// Check that `values` has the shape this schema describes
+    pub fn validate(&self, values: &[FieldValue]) -> Result<(), String> {
+        if values.len() != self.fields.len() {
+            return Err(format!(
+                "expected {} fields, found {}",
+                self.fields.len(),
+                values.len()
+            ));
+        }
+        for ((name, ty), value) in self.fields.iter().zip(values) {
+            if value.field_type() != *ty {
+                return Err(format!(
+                    "field `{}` expected {:?}, found {:?}",
+                    name,
+                    ty,
+                    value.field_type()
+                ));
+            }
+        }
+        Ok(())
+    }
+}
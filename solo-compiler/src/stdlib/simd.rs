@@ -0,0 +1,235 @@
+//! `std::simd` - fixed-width vector types
+//!
+//! A vector holds a fixed number of lanes of one primitive type, such as
+//! the four `f64`s of an `f64x4`, and arithmetic applies to every lane at
+//! once. Lanes behave like the hardware's: integer lanes wrap at their own
+//! width instead of trapping, and `f32` lanes are rounded to `f32` after
+//! every operation.
+
+/// A vector type such as `f64x4`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VectorType {
+    pub name: &'static str,
+    /// Whether the lanes are floats
+    pub float: bool,
+    /// Width of one lane
+    pub bits: u32,
+    pub lanes: usize,
+}
+
+impl VectorType {
+    /// Size of the whole vector in bytes, which is also its alignment
+    pub fn size(&self) -> u64 {
+        u64::from(self.bits / 8) * self.lanes as u64
+    }
+
+    /// Name of the lane type
+    pub fn elem(&self) -> &'static str {
+        match (self.float, self.bits) {
+            (true, 32) => "f32",
+            (true, _) => "f64",
+            (false, 32) => "i32",
+            (false, _) => "i64",
+        }
+    }
+}
+
+const fn vector(name: &'static str, float: bool, bits: u32, lanes: usize) -> VectorType {
+    VectorType {
+        name,
+        float,
+        bits,
+        lanes,
+    }
+}
+
+/// Every vector type, 128 and 256 bits wide
+pub const VECTOR_TYPES: &[VectorType] = &[
+    vector("f32x4", true, 32, 4),
+    vector("f32x8", true, 32, 8),
+    vector("f64x2", true, 64, 2),
+    vector("f64x4", true, 64, 4),
+    vector("i32x4", false, 32, 4),
+    vector("i32x8", false, 32, 8),
+    vector("i64x2", false, 64, 2),
+    vector("i64x4", false, 64, 4),
+];
+
+pub fn vector_type(name: &str) -> Option<&'static VectorType> {
+    VECTOR_TYPES.iter().find(|ty| ty.name == name)
+}
+
+/// The lanes of a vector
+#[derive(Debug, Clone, PartialEq)]
+pub enum Lanes {
+    Int(Vec<i64>),
+    Float(Vec<f64>),
+}
+
+/// One lane of a vector, or the result of reducing one
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Lane {
+    Int(i64),
+    Float(f64),
+}
+
+/// Lane-wise operation of two vectors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LaneOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+/// Operation folding the lanes of a vector into one value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reduce {
+    Sum,
+    Min,
+    Max,
+}
+
+/// A vector value
+#[derive(Debug, Clone, PartialEq)]
+pub struct Vector {
+    pub ty: &'static VectorType,
+    pub lanes: Lanes,
+}
+
+impl Vector {
+    /// A vector of exactly `ty.lanes` lanes, narrowed to the lane type
+    pub fn new(ty: &'static VectorType, lanes: Lanes) -> Self {
+        let lanes = match lanes {
+            Lanes::Int(lanes) => Lanes::Int(lanes.into_iter().map(|n| wrap(n, ty.bits)).collect()),
+            Lanes::Float(lanes) => {
+                Lanes::Float(lanes.into_iter().map(|x| round(x, ty.bits)).collect())
+            }
+        };
+        Self { ty, lanes }
+    }
+
+    /// Apply `op` to each pair of lanes, failing on integer division by
+    /// zero
+    pub fn apply(&self, op: LaneOp, other: &Vector) -> Result<Vector, String> {
+        let lanes = match (&self.lanes, &other.lanes) {
+            (Lanes::Int(a), Lanes::Int(b)) => {
+                let lane = |(a, b): (&i64, &i64)| match op {
+                    LaneOp::Add => Ok(a.wrapping_add(*b)),
+                    LaneOp::Sub => Ok(a.wrapping_sub(*b)),
+                    LaneOp::Mul => Ok(a.wrapping_mul(*b)),
+                    LaneOp::Div if *b == 0 => Err("attempt to divide by zero".to_string()),
+                    LaneOp::Div => Ok(a.wrapping_div(*b)),
+                };
+                Lanes::Int(a.iter().zip(b).map(lane).collect::<Result<_, _>>()?)
+            }
+            (Lanes::Float(a), Lanes::Float(b)) => Lanes::Float(
+                a.iter()
+                    .zip(b)
+                    .map(|(a, b)| match op {
+                        LaneOp::Add => a + b,
+                        LaneOp::Sub => a - b,
+                        LaneOp::Mul => a * b,
+                        LaneOp::Div => a / b,
+                    })
+                    .collect(),
+            ),
+            _ => return Err("mismatched vector lanes".to_string()),
+        };
+        Ok(Vector::new(self.ty, lanes))
+    }
+
+    /// Fold the lanes with `op`; sums wrap and round like the lanes do
+    pub fn reduce(&self, op: Reduce) -> Lane {
+        match &self.lanes {
+            Lanes::Int(lanes) => {
+                let fold = |a: i64, b: &i64| match op {
+                    Reduce::Sum => wrap(a.wrapping_add(*b), self.ty.bits),
+                    Reduce::Min => a.min(*b),
+                    Reduce::Max => a.max(*b),
+                };
+                Lane::Int(lanes[1..].iter().fold(lanes[0], fold))
+            }
+            Lanes::Float(lanes) => {
+                let fold = |a: f64, b: &f64| match op {
+                    Reduce::Sum => round(a + b, self.ty.bits),
+                    Reduce::Min => a.min(*b),
+                    Reduce::Max => a.max(*b),
+                };
+                Lane::Float(lanes[1..].iter().fold(lanes[0], fold))
+            }
+        }
+    }
+
+    /// `self * a + b` in every lane with a single rounding
+    pub fn mul_add(&self, a: &Vector, b: &Vector) -> Vector {
+        let lanes = match (&self.lanes, &a.lanes, &b.lanes) {
+            (Lanes::Float(x), Lanes::Float(a), Lanes::Float(b)) => Lanes::Float(
+                x.iter()
+                    .zip(a)
+                    .zip(b)
+                    .map(|((x, a), b)| x.mul_add(*a, *b))
+                    .collect(),
+            ),
+            (Lanes::Int(x), Lanes::Int(a), Lanes::Int(b)) => Lanes::Int(
+                x.iter()
+                    .zip(a)
+                    .zip(b)
+                    .map(|((x, a), b)| x.wrapping_mul(*a).wrapping_add(*b))
+                    .collect(),
+            ),
+            _ => self.lanes.clone(),
+        };
+        Vector::new(self.ty, lanes)
+    }
+}
+
+/// `n` wrapped to a signed integer of `bits`
+fn wrap(n: i64, bits: u32) -> i64 {
+    let shift = 64 - bits;
+    (n << shift) >> shift
+}
+
+/// `x` rounded to a float of `bits`
+fn round(x: f64, bits: u32) -> f64 {
+    if bits == 32 {
+        x as f32 as f64
+    } else {
+        x
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lane_arithmetic() {
+        let i32x4 = vector_type("i32x4").unwrap();
+        let max = Vector::new(i32x4, Lanes::Int(vec![i64::from(i32::MAX); 4]));
+        let one = Vector::new(i32x4, Lanes::Int(vec![1, 2, 3, 4]));
+        assert_eq!(
+            max.apply(LaneOp::Add, &one).unwrap().lanes,
+            Lanes::Int(vec![-2147483648, -2147483647, -2147483646, -2147483645])
+        );
+        let zero = Vector::new(i32x4, Lanes::Int(vec![0; 4]));
+        assert!(one.apply(LaneOp::Div, &zero).is_err());
+
+        let f32x4 = vector_type("f32x4").unwrap();
+        let third = Vector::new(f32x4, Lanes::Float(vec![1.0 / 3.0; 4]));
+        assert_eq!(third.lanes, Lanes::Float(vec![f64::from(1.0f32 / 3.0); 4]));
+        let f64x2 = vector_type("f64x2").unwrap();
+        let x = Vector::new(f64x2, Lanes::Float(vec![0.1, 2.0]));
+        assert_eq!(
+            x.mul_add(&x, &Vector::new(f64x2, Lanes::Float(vec![-0.01; 2])))
+                .lanes,
+            Lanes::Float(vec![0.1f64.mul_add(0.1, -0.01), 3.99])
+        );
+        assert_eq!(x.reduce(Reduce::Max), Lane::Float(2.0));
+        assert_eq!(max.reduce(Reduce::Sum), Lane::Int(-4));
+        assert_eq!(
+            (f64x2.size(), vector_type("i32x8").unwrap().size()),
+            (16, 32)
+        );
+    }
+}
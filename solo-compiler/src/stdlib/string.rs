@@ -0,0 +1,219 @@
+//! `std::string` - Unicode-aware string operations
+//!
+//! Solo strings are UTF-8. Byte length, char iteration, case conversion and
+//! normalization are always available; grapheme clusters require the
+//! `graphemes` feature. Text built at run time lives in an [`ArenaString`],
+//! which is only ever created from valid UTF-8 and sliced at char
+//! boundaries, so it stays valid.
+
+use super::collections::grow;
+use crate::unicode;
+
+/// A growable UTF-8 string with an explicit capacity in bytes, allocated
+/// like the buffers of [`super::collections`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArenaString {
+    text: String,
+    capacity: usize,
+    /// Whether a buffer of `capacity` has been allocated yet
+    allocated: bool,
+}
+
+impl ArenaString {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            text: String::with_capacity(capacity),
+            capacity,
+            allocated: false,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    pub fn len(&self) -> usize {
+        self.text.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Append `s`, returning the capacity of the buffer allocated for it,
+    /// if one was
+    pub fn push_str(&mut self, s: &str) -> Option<usize> {
+        if s.is_empty() {
+            return None;
+        }
+        let needed = self.text.len() + s.len();
+        let full = needed > self.capacity;
+        while self.capacity < needed {
+            self.capacity = grow(self.capacity);
+        }
+        let allocated = (full || !self.allocated).then_some(self.capacity);
+        self.allocated = true;
+        self.text.push_str(s);
+        allocated
+    }
+
+    /// Remove the text, keeping the buffer
+    pub fn clear(&mut self) {
+        self.text.clear();
+    }
+}
+
+/// Decode `bytes`, which must each be in `0..=255` and form valid UTF-8
+pub fn from_utf8(bytes: &[i64]) -> Result<String, String> {
+    let bytes = bytes
+        .iter()
+        .enumerate()
+        .map(|(i, &b)| {
+            u8::try_from(b).map_err(|_| format!("byte {} at index {} is out of range", b, i))
+        })
+        .collect::<Result<Vec<u8>, String>>()?;
+    String::from_utf8(bytes).map_err(|err| {
+        format!(
+            "invalid UTF-8 sequence at byte {}",
+            err.utf8_error().valid_up_to()
+        )
+    })
+}
+
+/// Whether byte offset `index` starts a char or is the end of `s`
+pub fn is_char_boundary(s: &str, index: i64) -> bool {
+    usize::try_from(index).is_ok_and(|i| s.is_char_boundary(i))
+}
+
+/// The bytes `start..end` of `s`, which must be in bounds and fall on char
+/// boundaries
+pub fn slice(s: &str, start: i64, end: i64) -> Result<&str, String> {
+    let in_bounds = 0 <= start && start <= end && end <= s.len() as i64;
+    if !in_bounds {
+        return Err(format!(
+            "byte range {}..{} is out of bounds of a string of {} bytes",
+            start,
+            end,
+            s.len()
+        ));
+    }
+    for index in [start, end] {
+        if !is_char_boundary(s, index) {
+            return Err(format!("byte index {} is not a char boundary", index));
+        }
+    }
+    Ok(&s[start as usize..end as usize])
+}
+
+/// Length in bytes
+pub fn byte_len(s: &str) -> usize {
+    s.len()
+}
+
+/// Number of Unicode scalar values
+pub fn char_count(s: &str) -> usize {
+    s.chars().count()
+}
+
+/// Unicode scalar values in order
+pub fn chars(s: &str) -> Vec<char> {
+    s.chars().collect()
+}
+
+/// Uppercase using full Unicode case mapping (`ß` → `SS`)
+pub fn to_upper(s: &str) -> String {
+    s.to_uppercase()
+}
+
+/// Lowercase using full Unicode case mapping
+pub fn to_lower(s: &str) -> String {
+    s.to_lowercase()
+}
+
+/// Case-fold for caseless matching
+pub fn case_fold(s: &str) -> String {
+    unicode::case_fold(s)
+}
+
+/// Normalize to NFC
+pub fn normalize_nfc(s: &str) -> String {
+    unicode::nfc(s)
+}
+
+/// Normalize to NFD
+pub fn normalize_nfd(s: &str) -> String {
+    unicode::nfd(s)
+}
+
+/// Is the string already in NFC?
+pub fn is_nfc(s: &str) -> bool {
+    unicode::is_nfc(s)
+}
+
+/// Compare two strings ignoring case and normalization form
+pub fn eq_ignore_case(a: &str, b: &str) -> bool {
+    unicode::nfc(&unicode::case_fold(a)) == unicode::nfc(&unicode::case_fold(b))
+}
+
+/// Extended grapheme clusters (user-perceived characters)
+#[cfg(feature = "graphemes")]
+pub fn graphemes(s: &str) -> Vec<&str> {
+    unicode::graphemes(s)
+}
+
+/// Number of extended grapheme clusters
+#[cfg(feature = "graphemes")]
+pub fn grapheme_count(s: &str) -> usize {
+    unicode::graphemes(s).len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lengths() {
+        assert_eq!(byte_len("héllo"), 6);
+        assert_eq!(char_count("héllo"), 5);
+        assert_eq!(char_count("he\u{0301}llo"), 6);
+    }
+
+    #[test]
+    fn test_validation_and_slicing() {
+        assert_eq!(from_utf8(&[104, 195, 169]), Ok("hé".to_string()));
+        assert_eq!(
+            from_utf8(&[104, 195]),
+            Err("invalid UTF-8 sequence at byte 1".to_string())
+        );
+        assert!(from_utf8(&[256]).is_err());
+        assert_eq!(slice("héllo", 1, 3), Ok("é"));
+        assert_eq!(
+            slice("héllo", 0, 2),
+            Err("byte index 2 is not a char boundary".to_string())
+        );
+        assert!(slice("héllo", 3, 9).is_err());
+
+        let mut text = ArenaString::with_capacity(0);
+        assert_eq!(text.push_str(""), None);
+        assert_eq!(text.push_str("héllo"), Some(8));
+        assert_eq!(text.push_str(", world"), Some(16));
+        assert_eq!(text.as_str(), "héllo, world");
+    }
+
+    #[test]
+    fn test_case_conversion() {
+        assert_eq!(to_upper("straße"), "STRASSE");
+        assert_eq!(to_lower("ÀÉÎ"), "àéî");
+    }
+
+    #[test]
+    fn test_eq_ignore_case() {
+        assert!(eq_ignore_case("CAFE\u{0301}", "café"));
+        assert!(eq_ignore_case("Straße", "STRASSE"));
+        assert!(!eq_ignore_case("cafe", "café"));
+    }
+}
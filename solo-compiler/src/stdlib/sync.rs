@@ -0,0 +1,193 @@
+//! `std::sync` - locks guarding a value
+//!
+//! A [`Lock`] is either held exclusively, by one `Mutex` guard or `RwLock`
+//! writer, or shared by any number of `RwLock` readers. Whoever holds it
+//! may read the value, and an exclusive holder may replace it. Solo's lock
+//! guards are affine: releasing one consumes it, so a lock cannot be
+//! released twice or used after its release.
+//!
+//! Like [`super::channel`], the blocking operations are for native
+//! threads, and a scheduler running threads one at a time uses
+//! [`Lock::try_acquire`].
+
+use std::sync::{Condvar, Mutex, MutexGuard};
+
+/// How a lock is held
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    /// By a `Mutex` guard or an `RwLock` writer
+    Exclusive,
+    /// By an `RwLock` reader
+    Shared,
+}
+
+/// The kind of a lock guard, by its Solo type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GuardKind {
+    Mutex,
+    Read,
+    Write,
+}
+
+impl GuardKind {
+    pub fn type_name(self) -> &'static str {
+        match self {
+            GuardKind::Mutex => "MutexGuard",
+            GuardKind::Read => "RwLockReadGuard",
+            GuardKind::Write => "RwLockWriteGuard",
+        }
+    }
+
+    pub fn from_type_name(name: &str) -> Option<Self> {
+        [GuardKind::Mutex, GuardKind::Read, GuardKind::Write]
+            .into_iter()
+            .find(|kind| kind.type_name() == name)
+    }
+
+    pub fn access(self) -> Access {
+        match self {
+            GuardKind::Read => Access::Shared,
+            GuardKind::Mutex | GuardKind::Write => Access::Exclusive,
+        }
+    }
+}
+
+/// A value and the lock guarding it
+#[derive(Debug)]
+pub struct Lock<T> {
+    inner: Mutex<Inner<T>>,
+    /// Signalled whenever the lock is released
+    released: Condvar,
+}
+
+#[derive(Debug)]
+struct Inner<T> {
+    value: T,
+    writer: bool,
+    readers: usize,
+}
+
+impl<T> Inner<T> {
+    fn can_acquire(&self, access: Access) -> bool {
+        match access {
+            Access::Exclusive => !self.writer && self.readers == 0,
+            Access::Shared => !self.writer,
+        }
+    }
+
+    fn acquire(&mut self, access: Access) {
+        match access {
+            Access::Exclusive => self.writer = true,
+            Access::Shared => self.readers += 1,
+        }
+    }
+}
+
+impl<T> Lock<T> {
+    pub fn new(value: T) -> Self {
+        Self {
+            inner: Mutex::new(Inner {
+                value,
+                writer: false,
+                readers: 0,
+            }),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Take the lock, waiting until it is free enough for `access`
+    pub fn acquire(&self, access: Access) {
+        let mut inner = self.lock();
+        while !inner.can_acquire(access) {
+            inner = self.released.wait(inner).unwrap_or_else(|e| e.into_inner());
+        }
+        inner.acquire(access);
+    }
+
+    /// Take the lock if that needs no waiting, returning whether it did
+    pub fn try_acquire(&self, access: Access) -> bool {
+        let mut inner = self.lock();
+        let free = inner.can_acquire(access);
+        if free {
+            inner.acquire(access);
+        }
+        free
+    }
+
+    /// Give back the lock taken for `access`
+    pub fn release(&self, access: Access) {
+        let mut inner = self.lock();
+        match access {
+            Access::Exclusive => inner.writer = false,
+            Access::Shared => inner.readers = inner.readers.saturating_sub(1),
+        }
+        self.released.notify_all();
+    }
+
+    /// The value, for a holder of the lock
+    pub fn get(&self) -> T
+    where
+        T: Clone,
+    {
+        self.lock().value.clone()
+    }
+
+    /// Replace the value, for the exclusive holder of the lock
+    pub fn replace(&self, value: T) -> T {
+        std::mem::replace(&mut self.lock().value, value)
+    }
+
+    /// The internal mutex only protects the fields for the moment they are
+    /// accessed, so a panic cannot leave them inconsistent
+    fn lock(&self) -> MutexGuard<'_, Inner<T>> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_access() {
+        let lock = Lock::new(1);
+        assert!(lock.try_acquire(Access::Shared));
+        assert!(lock.try_acquire(Access::Shared));
+        assert!(!lock.try_acquire(Access::Exclusive));
+        lock.release(Access::Shared);
+        lock.release(Access::Shared);
+        assert!(lock.try_acquire(Access::Exclusive));
+        assert!(!lock.try_acquire(Access::Shared));
+        assert_eq!(lock.replace(2), 1);
+        lock.release(Access::Exclusive);
+        assert_eq!(lock.get(), 2);
+        assert_eq!(
+            GuardKind::from_type_name("RwLockWriteGuard").map(GuardKind::access),
+            Some(Access::Exclusive)
+        );
+    }
+
+    #[test]
+    fn test_native_threads() {
+        let lock = Arc::new(Lock::new(0));
+        let workers: Vec<_> = (0..8)
+            .map(|_| {
+                let lock = Arc::clone(&lock);
+                thread::spawn(move || {
+                    for _ in 0..100 {
+                        lock.acquire(Access::Exclusive);
+                        let n = lock.get();
+                        lock.replace(n + 1);
+                        lock.release(Access::Exclusive);
+                    }
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .for_each(|worker| worker.join().unwrap());
+        assert_eq!(lock.get(), 800);
+    }
+}
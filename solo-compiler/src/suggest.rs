@@ -0,0 +1,91 @@
+//! Suggestions for names that fail to resolve
+//!
+//! Solo has no `use` declarations: items of other namespaces (`env::arg`,
+//! `Story::new`, `Desk::News`) are always written with their path. A name
+//! that resolves nowhere is most often one of
+//!
+//! - such an item written without its path (`arg` for `env::arg`) or under
+//!   the wrong one (`io::arg`): the one path ending in the same name is
+//!   suggested, or
+//! - a typo: the name within a third of its length in edits (at least one;
+//!   swapping two adjacent characters is one edit), or differing only in
+//!   case, is suggested, the closest first.
+
+/// The name `written` most likely means among `candidates`
+pub fn suggest<'a>(written: &str, candidates: &'a [String]) -> Option<&'a str> {
+    let last = written.rsplit("::").next().unwrap_or(written);
+    let mut elsewhere = candidates
+        .iter()
+        .filter(|c| c.as_str() != written && c.rsplit("::").next() == Some(last));
+    if let (Some(path), None) = (elsewhere.next(), elsewhere.next()) {
+        return Some(path);
+    }
+    closest(written, candidates.iter().map(String::as_str))
+}
+
+/// The candidate closest to `name` in edits, if close enough to be a typo;
+/// ties go to the first in alphabetical order
+pub fn closest<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let threshold = (name.chars().count() / 3).max(1);
+    candidates
+        .into_iter()
+        .filter(|c| *c != name)
+        .filter_map(|c| {
+            let d = if c.eq_ignore_ascii_case(name) {
+                0
+            } else {
+                distance(name, c)
+            };
+            (d <= threshold).then_some((d, c))
+        })
+        .min()
+        .map(|(_, c)| c)
+}
+
+/// Edit distance between two strings, in characters: insertions, deletions,
+/// substitutions and swaps of adjacent characters count one each
+pub fn distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    d[0] = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_suggest() {
+        let names: Vec<String> = [
+            "words", "draft", "env::arg", "Vec::new", "Map::new", "Story",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        assert_eq!(distance("wrods", "words"), 1);
+        assert_eq!(distance("draft", "drafts"), 1);
+        assert_eq!(suggest("wrods", &names), Some("words"));
+        assert_eq!(suggest("story", &names), Some("Story"));
+        assert_eq!(suggest("arg", &names), Some("env::arg"));
+        assert_eq!(suggest("io::arg", &names), Some("env::arg"));
+        // `new` exists under two paths, and is no typo of either
+        assert_eq!(suggest("new", &names), None);
+        assert_eq!(suggest("headline", &names), None);
+    }
+}
@@ -0,0 +1,168 @@
+//! Interned strings
+//!
+//! The text of every token, the names and string literals tokens carry, and
+//! the names of the AST are interned: each distinct string is stored once,
+//! and a [`Symbol`] for it is a 4-byte handle that copies, hashes and
+//! compares as an integer. A thousand uses of `total` in a file share one
+//! string instead of allocating a thousand, and looking a name up in a
+//! scope hashes four bytes.
+//!
+//! The table is global, so symbols from different files and compilations are
+//! equal when their strings are. Each thread keeps a copy of the part it
+//! has seen, so reading a symbol or interning a string seen before takes no
+//! lock. The table never shrinks: a long-running process such as the
+//! language server keeps every distinct string it has lexed.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Deref;
+use std::sync::{Mutex, OnceLock};
+
+/// A string in the global table
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symbol(u32);
+
+#[derive(Default)]
+struct Interner {
+    symbols: HashMap<&'static str, Symbol>,
+    strings: Vec<&'static str>,
+}
+
+fn global() -> &'static Mutex<Interner> {
+    static INTERNER: OnceLock<Mutex<Interner>> = OnceLock::new();
+    INTERNER.get_or_init(Mutex::default)
+}
+
+thread_local! {
+    /// The strings and symbols this thread has seen, a prefix of the table
+    static LOCAL: RefCell<Interner> = RefCell::default();
+}
+
+impl Symbol {
+    /// The symbol for `string`, adding it to the table the first time
+    pub fn intern(string: &str) -> Symbol {
+        LOCAL.with(|local| {
+            let mut local = local.borrow_mut();
+            if let Some(&symbol) = local.symbols.get(string) {
+                return symbol;
+            }
+            let mut global = global().lock().unwrap_or_else(|e| e.into_inner());
+            let symbol = match global.symbols.get(string) {
+                Some(&symbol) => symbol,
+                None => {
+                    let symbol = Symbol(global.strings.len() as u32);
+                    let string: &'static str = Box::leak(string.into());
+                    global.strings.push(string);
+                    global.symbols.insert(string, symbol);
+                    symbol
+                }
+            };
+            local.catch_up(&global);
+            symbol
+        })
+    }
+
+    pub fn as_str(self) -> &'static str {
+        LOCAL.with(|local| {
+            let mut local = local.borrow_mut();
+            if let Some(&string) = local.strings.get(self.0 as usize) {
+                return string;
+            }
+            local.catch_up(&global().lock().unwrap_or_else(|e| e.into_inner()));
+            local.strings[self.0 as usize]
+        })
+    }
+}
+
+impl Interner {
+    /// Copy what the global table has that this one does not
+    fn catch_up(&mut self, global: &Interner) {
+        for (i, &string) in global.strings.iter().enumerate().skip(self.strings.len()) {
+            self.strings.push(string);
+            self.symbols.insert(string, Symbol(i as u32));
+        }
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(string: &str) -> Self {
+        Symbol::intern(string)
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl PartialEq<Symbol> for str {
+    fn eq(&self, other: &Symbol) -> bool {
+        self == other.as_str()
+    }
+}
+
+impl PartialEq<Symbol> for &str {
+    fn eq(&self, other: &Symbol) -> bool {
+        *self == other.as_str()
+    }
+}
+
+impl PartialEq<Symbol> for String {
+    fn eq(&self, other: &Symbol) -> bool {
+        self == other.as_str()
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// As the string, so tokens and trees print as before interning
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intern() {
+        let total = Symbol::intern("total");
+        assert_eq!(Symbol::intern(&String::from("total")), total);
+        assert_ne!(Symbol::intern("totals"), total);
+        assert_eq!(total.as_str(), "total");
+        assert_eq!(total, "total");
+        assert!(total.starts_with("tot"));
+        assert_eq!(format!("{} {:?}", total, total), "total \"total\"");
+
+        // Other threads see the same table
+        let threads: Vec<_> = (0..4)
+            .map(|_| std::thread::spawn(move || (Symbol::intern("größe"), total.as_str())))
+            .collect();
+        let grosse = Symbol::intern("größe");
+        for thread in threads {
+            assert_eq!(thread.join().unwrap(), (grosse, "total"));
+        }
+    }
+}
@@ -0,0 +1,135 @@
+//! Sysroot location and layout
+//!
+//! The sysroot is the installation prefix holding the compiler, the standard
+//! library and per-target runtime files:
+//!
+//! ```text
+//! <sysroot>/
+//!   bin/solo
+//!   lib/solo/std/                    standard library sources (*.solo)
+//!   lib/solo/targets/<triple>.json   target description
+//!   lib/solo/<triple>/lib/           runtime libraries (libsolort.a)
+//! ```
+//!
+//! It is taken from `--sysroot`, then `SOLO_SYSROOT`, and otherwise derived
+//! from the running executable. Pinning a toolchain or cross-compiling only
+//! requires pointing at a different sysroot.
+
+use std::env;
+use std::path::{Path, PathBuf};
+
+/// Environment variable overriding the sysroot
+pub const SYSROOT_VAR: &str = "SOLO_SYSROOT";
+
+/// File name of the runtime library inside a target's library directory
+pub const RUNTIME_LIB: &str = "libsolort.a";
+
+/// An installed (or to-be-installed) sysroot
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sysroot {
+    root: PathBuf,
+}
+
+impl Sysroot {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// Locate the sysroot; an explicit `--sysroot` wins over the environment
+    pub fn resolve(explicit: Option<&Path>) -> Option<Sysroot> {
+        if let Some(dir) = explicit {
+            return Some(Sysroot::new(dir));
+        }
+        if let Some(dir) = env::var_os(SYSROOT_VAR).filter(|v| !v.is_empty()) {
+            return Some(Sysroot::new(dir));
+        }
+        let exe = env::current_exe().ok()?;
+        from_executable(&exe.canonicalize().unwrap_or(exe)).map(Sysroot::new)
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// `lib/solo`, the root of everything the compiler loads
+    pub fn lib_dir(&self) -> PathBuf {
+        self.root.join("lib").join("solo")
+    }
+
+    pub fn std_dir(&self) -> PathBuf {
+        self.lib_dir().join("std")
+    }
+
+    pub fn target_spec(&self, triple: &str) -> PathBuf {
+        self.lib_dir()
+            .join("targets")
+            .join(format!("{}.json", triple))
+    }
+
+    /// Directory holding the runtime libraries for `triple`
+    pub fn target_libdir(&self, triple: &str) -> PathBuf {
+        self.lib_dir().join(triple).join("lib")
+    }
+
+    pub fn runtime_lib(&self, triple: &str) -> PathBuf {
+        self.target_libdir(triple).join(RUNTIME_LIB)
+    }
+
+    /// Targets with an installed runtime library, sorted
+    pub fn installed_targets(&self) -> Vec<String> {
+        let Ok(entries) = self.lib_dir().read_dir() else {
+            return Vec::new();
+        };
+        let mut triples: Vec<String> = entries
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .filter(|name| self.runtime_lib(name).is_file())
+            .collect();
+        triples.sort();
+        triples
+    }
+
+    /// Files the layout requires for `triple` that are not present
+    pub fn missing(&self, triple: &str) -> Vec<PathBuf> {
+        let required = [self.std_dir(), self.runtime_lib(triple)];
+        required.into_iter().filter(|p| !p.exists()).collect()
+    }
+}
+
+/// The sysroot for a compiler installed at `exe` (`<sysroot>/bin/solo`)
+pub fn from_executable(exe: &Path) -> Option<PathBuf> {
+    exe.parent()?.parent().map(Path::to_path_buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_from_executable() {
+        assert_eq!(
+            from_executable(Path::new("/opt/solo/bin/solo")),
+            Some(PathBuf::from("/opt/solo"))
+        );
+    }
+
+    #[test]
+    fn test_layout() {
+        let root = env::temp_dir().join(format!("solo-sysroot-test-{}", std::process::id()));
+        let sysroot = Sysroot::resolve(Some(&root)).unwrap();
+        let triple = "x86_64-unknown-linux-gnu";
+        assert_eq!(sysroot.missing(triple).len(), 2);
+
+        fs::create_dir_all(sysroot.std_dir()).unwrap();
+        fs::create_dir_all(sysroot.target_libdir(triple)).unwrap();
+        fs::write(sysroot.runtime_lib(triple), b"").unwrap();
+        assert!(sysroot.missing(triple).is_empty());
+        assert_eq!(sysroot.installed_targets(), [triple]);
+        assert_eq!(
+            sysroot.target_spec(triple),
+            root.join("lib/solo/targets/x86_64-unknown-linux-gnu.json")
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
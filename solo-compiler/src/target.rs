@@ -0,0 +1,168 @@
+//! Compilation targets
+//!
+//! Each native target corresponds to a QBE backend (`amd64_sysv`, `arm64`,
+//! `rv64`) plus the operating system conventions the linker needs.
+//! `wasm32-unknown-unknown` and `wasm32-wasi` have 32-bit pointers and no
+//! QBE backend; `wasm32-wasi` modules are to get files, clocks and
+//! arguments from their host through WASI, once code is generated for it
+//! (only its layout and link plan exist so far).
+
+/// A supported target triple and its `cfg` properties
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Target {
+    pub triple: &'static str,
+    pub arch: &'static str,
+    pub os: &'static str,
+    pub family: &'static str,
+    pub pointer_width: u32,
+    pub endian: &'static str,
+    /// QBE backend generating code for the target, if any
+    pub qbe: Option<&'static str>,
+    /// Linker used when cross-compiling to the target
+    pub cross_linker: &'static str,
+}
+
+const fn target(
+    triple: &'static str,
+    arch: &'static str,
+    os: &'static str,
+    qbe: &'static str,
+    cross_linker: &'static str,
+) -> Target {
+    Target {
+        triple,
+        arch,
+        os,
+        family: "unix",
+        pointer_width: 64,
+        endian: "little",
+        qbe: Some(qbe),
+        cross_linker,
+    }
+}
+
+/// All targets this compiler can generate code for
+pub const TARGETS: &[Target] = &[
+    target(
+        "aarch64-apple-darwin",
+        "aarch64",
+        "macos",
+        "arm64_apple",
+        "clang",
+    ),
+    target(
+        "aarch64-unknown-linux-gnu",
+        "aarch64",
+        "linux",
+        "arm64",
+        "aarch64-linux-gnu-gcc",
+    ),
+    target(
+        "riscv64gc-unknown-linux-gnu",
+        "riscv64",
+        "linux",
+        "rv64",
+        "riscv64-linux-gnu-gcc",
+    ),
+    Target {
+        triple: "wasm32-unknown-unknown",
+        arch: "wasm32",
+        os: "unknown",
+        family: "wasm",
+        pointer_width: 32,
+        endian: "little",
+        qbe: None,
+        cross_linker: "wasm-ld",
+    },
+    Target {
+        triple: "wasm32-wasi",
+        arch: "wasm32",
+        os: "wasi",
+        family: "wasm",
+        pointer_width: 32,
+        endian: "little",
+        qbe: None,
+        cross_linker: "wasm-ld",
+    },
+    target(
+        "x86_64-apple-darwin",
+        "x86_64",
+        "macos",
+        "amd64_apple",
+        "clang",
+    ),
+    target(
+        "x86_64-unknown-freebsd",
+        "x86_64",
+        "freebsd",
+        "amd64_sysv",
+        "cc",
+    ),
+    target(
+        "x86_64-unknown-linux-gnu",
+        "x86_64",
+        "linux",
+        "amd64_sysv",
+        "x86_64-linux-gnu-gcc",
+    ),
+];
+
+impl Target {
+    /// Look up a target by triple
+    pub fn find(triple: &str) -> Option<Target> {
+        TARGETS.iter().copied().find(|t| t.triple == triple)
+    }
+
+    /// The target matching the machine the compiler runs on, if supported
+    pub fn host() -> Option<Target> {
+        TARGETS
+            .iter()
+            .copied()
+            .find(|t| t.arch == std::env::consts::ARCH && t.os == std::env::consts::OS)
+    }
+
+    /// Linker command for building on `host`: the system `cc` for native
+    /// builds, and the target's cross linker otherwise
+    pub fn linker(&self, host: Option<Target>) -> &'static str {
+        if host == Some(*self) {
+            "cc"
+        } else {
+            self.cross_linker
+        }
+    }
+
+    /// `cfg` key/value pairs visible to programs built for this target
+    pub fn cfg(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("target_arch", self.arch.to_string()),
+            ("target_os", self.os.to_string()),
+            ("target_family", self.family.to_string()),
+            ("target_pointer_width", self.pointer_width.to_string()),
+            ("target_endian", self.endian.to_string()),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_target() {
+        let target = Target::find("x86_64-unknown-linux-gnu").unwrap();
+        assert_eq!(target.arch, "x86_64");
+        assert!(Target::find("sparc-sun-solaris").is_none());
+        assert!(TARGETS.windows(2).all(|w| w[0].triple < w[1].triple));
+
+        let aarch64 = Target::find("aarch64-unknown-linux-gnu").unwrap();
+        assert_eq!(aarch64.linker(Some(target)), "aarch64-linux-gnu-gcc");
+        assert_eq!(aarch64.linker(Some(aarch64)), "cc");
+        let wasm = Target::find("wasm32-unknown-unknown").unwrap();
+        assert_eq!((wasm.pointer_width, wasm.qbe), (32, None));
+        let wasi = Target::find("wasm32-wasi").unwrap();
+        assert_eq!(
+            (wasi.os, wasi.family, wasi.pointer_width),
+            ("wasi", "wasm", 32)
+        );
+    }
+}
@@ -0,0 +1,217 @@
+//! Self-profiling of the compiler
+//!
+//! `solo build --timings` reports how long each pass of the pipeline took
+//! and the compiler's resident memory after it, in the manner of rustc's
+//! `-Z time-passes`:
+//!
+//! ```text
+//! time:   0.000412s  rss:     3MB ->     3MB (    +0MB)  lex
+//! time:   0.001871s  rss:     3MB ->     5MB (    +2MB)  parse
+//! ```
+//!
+//! `--timings=json` writes the same passes in the Chrome trace event format
+//! for `chrome://tracing`, Perfetto or speedscope. Memory is read from
+//! `/proc/self/status`, so it is only reported on Linux.
+//!
+//! Both end with how often lexing was answered from the [token
+//! cache](crate::token_cache):
+//!
+//! ```text
+//! lex cache: 1 hit(s), 0 from disk, 0 miss(es)
+//! ```
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::ice;
+use crate::json::Json;
+use crate::token_cache::{self, Stats};
+
+/// One pass of the pipeline
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pass {
+    pub name: &'static str,
+    /// Start, from the creation of the [`Timings`]
+    pub start: Duration,
+    pub duration: Duration,
+    /// Resident memory in bytes before and after the pass
+    pub rss: Option<(u64, u64)>,
+}
+
+/// Passes timed so far; a disabled `Timings` (the default) records nothing
+#[derive(Debug, Clone, Default)]
+pub struct Timings {
+    /// When recording started; `None` when disabled, so the clock is never
+    /// read where there is none (such as `wasm32-unknown-unknown`)
+    created: Option<Instant>,
+    passes: Vec<Pass>,
+    /// Lookups of the token cache before recording started
+    lex_cache: Stats,
+}
+
+impl Timings {
+    /// Timings that record every pass
+    pub fn new() -> Self {
+        Self {
+            created: Some(Instant::now()),
+            passes: Vec::new(),
+            lex_cache: token_cache::stats(),
+        }
+    }
+
+    /// Run `pass` as the pass called `name`, which an ICE in it reports as
+    /// its phase
+    pub fn time<T>(&mut self, name: &'static str, pass: impl FnOnce() -> T) -> T {
+        let Some(created) = self.created else {
+            return ice::phase(name, pass);
+        };
+        let before = resident_memory();
+        let started = Instant::now();
+        let result = ice::phase(name, pass);
+        let duration = started.elapsed();
+        let rss = before.zip(resident_memory());
+        self.passes.push(Pass {
+            name,
+            start: started - created,
+            duration,
+            rss,
+        });
+        result
+    }
+
+    pub fn passes(&self) -> &[Pass] {
+        &self.passes
+    }
+
+    /// Lookups of the token cache, by any thread, since recording started
+    pub fn lex_cache(&self) -> Stats {
+        match self.created {
+            Some(_) => token_cache::stats().since(self.lex_cache),
+            None => Stats::default(),
+        }
+    }
+
+    /// The passes as a Chrome trace: one complete (`X`) event each, with
+    /// times in microseconds, then a counter (`C`) event of the token cache
+    pub fn to_trace(&self) -> Json {
+        let events = self.passes.iter().map(|pass| {
+            let mut args = Vec::new();
+            if let Some((before, after)) = pass.rss {
+                args.push(("rss_before", Json::Int(before as i64)));
+                args.push(("rss_after", Json::Int(after as i64)));
+            }
+            Json::object([
+                ("name", Json::str(pass.name)),
+                ("cat", Json::str("pass")),
+                ("ph", Json::str("X")),
+                ("ts", Json::Int(pass.start.as_micros() as i64)),
+                ("dur", Json::Int(pass.duration.as_micros() as i64)),
+                ("pid", Json::Int(std::process::id() as i64)),
+                ("tid", Json::Int(1)),
+                ("args", Json::object(args)),
+            ])
+        });
+        let mut events: Vec<Json> = events.collect();
+        let cache = self.lex_cache();
+        if cache.lookups() > 0 {
+            let end = self
+                .passes
+                .last()
+                .map_or(Duration::ZERO, |pass| pass.start + pass.duration);
+            events.push(Json::object([
+                ("name", Json::str("lex cache")),
+                ("ph", Json::str("C")),
+                ("ts", Json::Int(end.as_micros() as i64)),
+                ("pid", Json::Int(std::process::id() as i64)),
+                ("tid", Json::Int(1)),
+                (
+                    "args",
+                    Json::object([
+                        ("hits", Json::Int(cache.hits as i64)),
+                        ("disk_hits", Json::Int(cache.disk_hits as i64)),
+                        ("misses", Json::Int(cache.misses as i64)),
+                    ]),
+                ),
+            ]));
+        }
+        Json::object([
+            ("traceEvents", Json::Array(events)),
+            ("displayTimeUnit", Json::str("ms")),
+        ])
+    }
+}
+
+impl fmt::Display for Timings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const MB: u64 = 1 << 20;
+        for pass in &self.passes {
+            write!(f, "time: {:>10.6}s", pass.duration.as_secs_f64())?;
+            if let Some((before, after)) = pass.rss {
+                let delta = after as i64 - before as i64;
+                write!(
+                    f,
+                    "  rss: {:>5}MB -> {:>5}MB ({:>+6}MB)",
+                    before / MB,
+                    after / MB,
+                    delta / MB as i64
+                )?;
+            }
+            writeln!(f, "  {}", pass.name)?;
+        }
+        let cache = self.lex_cache();
+        if cache.lookups() > 0 {
+            writeln!(
+                f,
+                "lex cache: {} hit(s), {} from disk, {} miss(es)",
+                cache.hits, cache.disk_hits, cache.misses
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Resident set size of this process, where the platform tells
+fn resident_memory() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kilobytes: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kilobytes * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timings() {
+        let mut disabled = Timings::default();
+        assert_eq!(disabled.time("lex", || 1), 1);
+        assert!(disabled.passes().is_empty());
+
+        let mut timings = Timings::new();
+        let source = "fn main() { print(1); }";
+        crate::compile_timed(source, &Default::default(), &mut timings).unwrap();
+        let names: Vec<&str> = timings.passes().iter().map(|p| p.name).collect();
+        assert_eq!(names, ["lex", "expand", "parse", "features", "typeck"]);
+        assert!(timings
+            .to_string()
+            .lines()
+            .nth(2)
+            .unwrap()
+            .ends_with("  parse"));
+
+        let trace = timings.to_trace().to_string();
+        assert!(trace.starts_with(r#"{"traceEvents":[{"name":"lex","cat":"pass","ph":"X","ts":"#));
+        assert!(Json::parse(&trace).is_ok());
+
+        // Compiling the same source again lexes it from the cache
+        crate::compile_timed(source, &Default::default(), &mut timings).unwrap();
+        assert!(timings.lex_cache().lookups() >= 2);
+        assert!(timings.lex_cache().hits >= 1);
+        assert!(timings.to_string().contains("\nlex cache: "));
+        assert!(timings
+            .to_trace()
+            .to_string()
+            .contains(r#""name":"lex cache","ph":"C""#));
+    }
+}
@@ -1,3 +1,8 @@
+use std::fmt;
+
+use crate::numeric::Numeric;
+use crate::symbol::Symbol;
+
 /// Token types for Solo dialect lexer
 ///
 /// Solo syntax is Rust-inspired with affine types and arena allocation.
@@ -11,25 +16,37 @@ pub enum TokenKind {
     If,
     Else,
     While,
+    For,
+    In,
+    Match,
     Return,
+    Become, // Guaranteed tail calls
     Struct,
     Enum,
     Impl,
     Trait,
     Type,
+    Newtype, // Distinct type aliases
     Arena,
+    Defer,
+    Extern,
+    Pub,
     Belief,  // Epistemic extension
     Where,   // Type constraints
+    Macro,   // Declarative macros
+    Const,
+    Unsafe,
+    As,
 
-    // Literals
-    Integer(i64),
-    Float(f64),
-    String(String),
+    // Literals, with the type of their suffix (`5u8`, `0.5f32`)
+    Integer(i64, Option<Numeric>),
+    Float(f64, Option<Numeric>),
+    String(Symbol),
     True,
     False,
 
     // Identifiers
-    Identifier(String),
+    Identifier(Symbol),
 
     // Operators
     Plus,
@@ -65,25 +82,44 @@ pub enum TokenKind {
     Colon,
     DoubleColon,  // ::
     Dot,
+    DotDot,       // ..
+    DotDotEq,     // ..=
+    Dollar,       // $ (macro metavariables)
+    Question,     // ?
+    Pound,        // # (attributes)
 
     // Special
     Eof,
     Error(String),
 }
 
+/// Source text between tokens, which the lexer skips
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trivia {
+    Whitespace,
+    Comment,
+}
+
+/// Every word `Token::is_keyword` reserves
+pub const KEYWORDS: &[&str] = &[
+    "fn", "let", "mut", "if", "else", "while", "for", "in", "match", "return", "become", "struct",
+    "enum", "impl", "trait", "type", "newtype", "arena", "defer", "extern", "pub", "belief",
+    "where", "macro", "const", "unsafe", "as", "true", "false",
+];
+
 #[derive(Debug, Clone)]
 pub struct Token {
     pub kind: TokenKind,
-    pub lexeme: String,
+    pub lexeme: Symbol,
     pub line: usize,
     pub column: usize,
 }
 
 impl Token {
-    pub fn new(kind: TokenKind, lexeme: String, line: usize, column: usize) -> Self {
+    pub fn new(kind: TokenKind, lexeme: impl Into<Symbol>, line: usize, column: usize) -> Self {
         Self {
             kind,
-            lexeme,
+            lexeme: lexeme.into(),
             line,
             column,
         }
@@ -97,18 +133,148 @@ pub fn is_keyword(ident: &str) -> Option<TokenKind> {
             "if" => Some(TokenKind::If),
             "else" => Some(TokenKind::Else),
             "while" => Some(TokenKind::While),
+            "for" => Some(TokenKind::For),
+            "in" => Some(TokenKind::In),
+            "match" => Some(TokenKind::Match),
             "return" => Some(TokenKind::Return),
+            "become" => Some(TokenKind::Become),
             "struct" => Some(TokenKind::Struct),
             "enum" => Some(TokenKind::Enum),
             "impl" => Some(TokenKind::Impl),
             "trait" => Some(TokenKind::Trait),
             "type" => Some(TokenKind::Type),
+            "newtype" => Some(TokenKind::Newtype),
             "arena" => Some(TokenKind::Arena),
+            "defer" => Some(TokenKind::Defer),
+            "extern" => Some(TokenKind::Extern),
+            "pub" => Some(TokenKind::Pub),
             "belief" => Some(TokenKind::Belief),
             "where" => Some(TokenKind::Where),
+            "macro" => Some(TokenKind::Macro),
+            "const" => Some(TokenKind::Const),
+            "unsafe" => Some(TokenKind::Unsafe),
+            "as" => Some(TokenKind::As),
             "true" => Some(TokenKind::True),
             "false" => Some(TokenKind::False),
             _ => None,
         }
     }
 }
+
+impl fmt::Display for TokenKind {
+    /// Source text that lexes back to this token; error tokens show their
+    /// message
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            TokenKind::Fn => "fn",
+            TokenKind::Let => "let",
+            TokenKind::Mut => "mut",
+            TokenKind::If => "if",
+            TokenKind::Else => "else",
+            TokenKind::While => "while",
+            TokenKind::For => "for",
+            TokenKind::In => "in",
+            TokenKind::Match => "match",
+            TokenKind::Return => "return",
+            TokenKind::Become => "become",
+            TokenKind::Struct => "struct",
+            TokenKind::Enum => "enum",
+            TokenKind::Impl => "impl",
+            TokenKind::Trait => "trait",
+            TokenKind::Type => "type",
+            TokenKind::Newtype => "newtype",
+            TokenKind::Arena => "arena",
+            TokenKind::Defer => "defer",
+            TokenKind::Extern => "extern",
+            TokenKind::Pub => "pub",
+            TokenKind::Belief => "belief",
+            TokenKind::Where => "where",
+            TokenKind::Macro => "macro",
+            TokenKind::Const => "const",
+            TokenKind::Unsafe => "unsafe",
+            TokenKind::As => "as",
+            TokenKind::Integer(n, suffix) => {
+                return write!(f, "{}{}", n, suffix.map_or("", Numeric::name))
+            }
+            // Always with a `.`, which `Display` for `f64` leaves out of
+            // whole numbers
+            TokenKind::Float(x, suffix) => {
+                let point = if x.fract() == 0.0 { ".0" } else { "" };
+                return write!(f, "{}{}{}", x, point, suffix.map_or("", Numeric::name));
+            }
+            TokenKind::String(s) => return write!(f, "\"{}\"", s),
+            TokenKind::True => "true",
+            TokenKind::False => "false",
+            TokenKind::Identifier(name) => name.as_str(),
+            TokenKind::Error(message) => message,
+            TokenKind::Plus => "+",
+            TokenKind::Minus => "-",
+            TokenKind::Star => "*",
+            TokenKind::Slash => "/",
+            TokenKind::Percent => "%",
+            TokenKind::Eq => "=",
+            TokenKind::EqEq => "==",
+            TokenKind::Ne => "!=",
+            TokenKind::Lt => "<",
+            TokenKind::Le => "<=",
+            TokenKind::Gt => ">",
+            TokenKind::Ge => ">=",
+            TokenKind::And => "&&",
+            TokenKind::Or => "||",
+            TokenKind::Not => "!",
+            TokenKind::Arrow => "->",
+            TokenKind::FatArrow => "=>",
+            TokenKind::Ampersand => "&",
+            TokenKind::Pipe => "|",
+            TokenKind::Tilde => "~",
+            TokenKind::LParen => "(",
+            TokenKind::RParen => ")",
+            TokenKind::LBrace => "{",
+            TokenKind::RBrace => "}",
+            TokenKind::LBracket => "[",
+            TokenKind::RBracket => "]",
+            TokenKind::Comma => ",",
+            TokenKind::Semicolon => ";",
+            TokenKind::Colon => ":",
+            TokenKind::DoubleColon => "::",
+            TokenKind::Dot => ".",
+            TokenKind::DotDot => "..",
+            TokenKind::DotDotEq => "..=",
+            TokenKind::Dollar => "$",
+            TokenKind::Question => "?",
+            TokenKind::Pound => "#",
+            TokenKind::Eof => "",
+        };
+        f.write_str(text)
+    }
+}
+
+/// Source text of a token stream, one statement per line and indented by
+/// braces, that lexes back to the same tokens
+pub fn print(tokens: &[Token]) -> String {
+    let mut out = String::new();
+    let mut depth = 0usize;
+    let mut line_start = true;
+    for token in tokens {
+        if token.kind == TokenKind::RBrace {
+            depth = depth.saturating_sub(1);
+        }
+        if line_start {
+            out.push_str(&"    ".repeat(depth));
+        } else {
+            out.push(' ');
+        }
+        out.push_str(&token.kind.to_string());
+        line_start = matches!(
+            token.kind,
+            TokenKind::LBrace | TokenKind::RBrace | TokenKind::Semicolon
+        );
+        if token.kind == TokenKind::LBrace {
+            depth += 1;
+        }
+        if line_start {
+            out.push('\n');
+        }
+    }
+    out
+}
@@ -0,0 +1,470 @@
+//! Lexed token streams, cached by source hash
+//!
+//! Lexing is memoized per source text, so a text is lexed once however many
+//! times it is compiled: `solo build` checks stability and then builds, the
+//! language server answers every request on an unchanged document, and
+//! `solo check` on a package lexes each target twice. The key is a 128-bit
+//! FNV-1a hash of the text and the compiler version, and every stream is
+//! kept with the text it was lexed from, which a hit must equal. Only
+//! streams that lexed without error are kept, and only the most recent
+//! [`CAPACITY`], so a language server does not keep every version of a
+//! document. The symbols of a cached stream stay valid, as there is one
+//! interner per process and it never frees a string.
+//!
+//! With a cache directory ([`set_dir`]; the `solo` binary uses
+//! `$SOLO_CACHE_DIR`), streams are also written to `<dir>/tokens/<hash>` and
+//! read back by later builds. A file that cannot be written or read back is
+//! a miss, never an error. `--timings` reports the lookups of each build.
+
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use crate::ast::Span;
+use crate::error::{CompileError, Diagnostic};
+use crate::lexer::{self, Lexer};
+use crate::symbol::Symbol;
+use crate::token::{Token, TokenKind};
+
+/// Token streams kept in memory
+pub const CAPACITY: usize = 32;
+
+/// First bytes of a cached token stream
+const MAGIC: &[u8; 8] = b"\xffsolotok";
+
+/// Version of the cached token stream layout
+const VERSION: u64 = 6;
+
+/// Where a token stream came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lookup {
+    Memory,
+    Disk,
+    Miss,
+}
+
+/// Lookups of the cache, counted by outcome
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Streams found in memory
+    pub hits: u64,
+    /// Streams read back from the cache directory
+    pub disk_hits: u64,
+    /// Texts lexed
+    pub misses: u64,
+}
+
+impl Stats {
+    pub fn record(&mut self, lookup: Lookup) {
+        match lookup {
+            Lookup::Memory => self.hits += 1,
+            Lookup::Disk => self.disk_hits += 1,
+            Lookup::Miss => self.misses += 1,
+        }
+    }
+
+    pub fn lookups(&self) -> u64 {
+        self.hits + self.disk_hits + self.misses
+    }
+
+    /// The lookups since `earlier` stats were taken
+    pub fn since(self, earlier: Stats) -> Stats {
+        Stats {
+            hits: self.hits - earlier.hits,
+            disk_hits: self.disk_hits - earlier.disk_hits,
+            misses: self.misses - earlier.misses,
+        }
+    }
+}
+
+/// A token stream and the text it was lexed from
+struct Stream {
+    source: Box<str>,
+    tokens: Arc<[Token]>,
+}
+
+#[derive(Default)]
+struct Cache {
+    streams: HashMap<u128, Stream>,
+    /// Keys of `streams`, oldest first
+    order: VecDeque<u128>,
+    dir: Option<PathBuf>,
+    stats: Stats,
+}
+
+impl Cache {
+    fn insert(&mut self, key: u128, source: &str, tokens: Arc<[Token]>) {
+        let stream = Stream {
+            source: source.into(),
+            tokens,
+        };
+        if self.streams.insert(key, stream).is_none() {
+            self.order.push_back(key);
+        }
+        while self.order.len() > CAPACITY {
+            let oldest = self.order.pop_front().expect("over capacity");
+            self.streams.remove(&oldest);
+        }
+    }
+}
+
+fn global() -> &'static Mutex<Cache> {
+    static CACHE: OnceLock<Mutex<Cache>> = OnceLock::new();
+    CACHE.get_or_init(Mutex::default)
+}
+
+/// Keep token streams in `dir` as well as in memory, or only in memory
+pub fn set_dir(dir: Option<PathBuf>) {
+    global().lock().unwrap_or_else(|e| e.into_inner()).dir = dir;
+}
+
+/// The lookups of this process so far
+pub fn stats() -> Stats {
+    global().lock().unwrap_or_else(|e| e.into_inner()).stats
+}
+
+/// The tokens of `source` without the end of input, or the first lex
+/// error, and where they came from
+pub fn lex(source: &str) -> (Result<Vec<Token>, CompileError>, Lookup) {
+    let key = hash(source);
+    let dir = {
+        let mut cache = global().lock().unwrap_or_else(|e| e.into_inner());
+        let stream = cache.streams.get(&key).filter(|s| *s.source == *source);
+        if let Some(tokens) = stream.map(|s| s.tokens.clone()) {
+            cache.stats.record(Lookup::Memory);
+            return (Ok(tokens.to_vec()), Lookup::Memory);
+        }
+        cache.dir.clone()
+    };
+    let path = dir.map(|dir| dir.join("tokens").join(format!("{:032x}", key)));
+    let (result, lookup, stream) = match path.as_deref().and_then(|path| read(path, source)) {
+        Some(tokens) => (Ok(tokens.to_vec()), Lookup::Disk, Some(tokens)),
+        None => {
+            let result = lex_uncached(source);
+            let stream: Option<Arc<[Token]>> = result.as_ref().ok().map(|t| t.as_slice().into());
+            if let (Some(path), Some(tokens)) = (&path, &stream) {
+                write(path, source, tokens);
+            }
+            (result, Lookup::Miss, stream)
+        }
+    };
+    let mut cache = global().lock().unwrap_or_else(|e| e.into_inner());
+    cache.stats.record(lookup);
+    if let Some(tokens) = stream {
+        cache.insert(key, source, tokens);
+    }
+    (result, lookup)
+}
+
+fn lex_uncached(source: &str) -> Result<Vec<Token>, CompileError> {
+    let mut lexer = Lexer::new(source);
+    let mut tokens = Vec::new();
+    loop {
+        let token = lexer.next_token();
+        if matches!(token.kind, TokenKind::Eof) {
+            return Ok(tokens);
+        }
+        if let TokenKind::Error(ref msg) = token.kind {
+            let span = Span::new(token.line, token.column);
+            return Err(CompileError::Lex(vec![Diagnostic::new(span, msg.as_str())]));
+        }
+        tokens.push(token);
+    }
+}
+
+/// 128-bit FNV-1a of the compiler version and `source`
+fn hash(source: &str) -> u128 {
+    let mut hash: u128 = 0x6c62_272e_07bb_0142_62b8_2175_6295_c58d;
+    let version = env!("CARGO_PKG_VERSION").as_bytes();
+    for &byte in version.iter().chain(&[0]).chain(source.as_bytes()) {
+        hash = (hash ^ u128::from(byte)).wrapping_mul(0x0000_0000_0100_0000_0000_0000_0000_013b);
+    }
+    hash
+}
+
+/// The token kinds without a payload, numbered by their place here
+const FIXED: &[TokenKind] = &[
+    TokenKind::Fn,
+    TokenKind::Let,
+    TokenKind::Mut,
+    TokenKind::If,
+    TokenKind::Else,
+    TokenKind::While,
+    TokenKind::For,
+    TokenKind::In,
+    TokenKind::Match,
+    TokenKind::Return,
+    TokenKind::Become,
+    TokenKind::Struct,
+    TokenKind::Enum,
+    TokenKind::Impl,
+    TokenKind::Trait,
+    TokenKind::Type,
+    TokenKind::Newtype,
+    TokenKind::Arena,
+    TokenKind::Defer,
+    TokenKind::Extern,
+    TokenKind::Pub,
+    TokenKind::Belief,
+    TokenKind::Where,
+    TokenKind::Macro,
+    TokenKind::Const,
+    TokenKind::Unsafe,
+    TokenKind::As,
+    TokenKind::True,
+    TokenKind::False,
+    TokenKind::Plus,
+    TokenKind::Minus,
+    TokenKind::Star,
+    TokenKind::Slash,
+    TokenKind::Percent,
+    TokenKind::Eq,
+    TokenKind::EqEq,
+    TokenKind::Ne,
+    TokenKind::Lt,
+    TokenKind::Le,
+    TokenKind::Gt,
+    TokenKind::Ge,
+    TokenKind::And,
+    TokenKind::Or,
+    TokenKind::Not,
+    TokenKind::Arrow,
+    TokenKind::FatArrow,
+    TokenKind::Ampersand,
+    TokenKind::Pipe,
+    TokenKind::Tilde,
+    TokenKind::LParen,
+    TokenKind::RParen,
+    TokenKind::LBrace,
+    TokenKind::RBrace,
+    TokenKind::LBracket,
+    TokenKind::RBracket,
+    TokenKind::Comma,
+    TokenKind::Semicolon,
+    TokenKind::Colon,
+    TokenKind::DoubleColon,
+    TokenKind::Dot,
+    TokenKind::DotDot,
+    TokenKind::DotDotEq,
+    TokenKind::Dollar,
+    TokenKind::Question,
+    TokenKind::Pound,
+];
+
+/// Tags of the token kinds with a payload, after those of [`FIXED`]
+const INTEGER: u8 = 0xfc;
+const FLOAT: u8 = 0xfd;
+const STRING: u8 = 0xfe;
+const IDENTIFIER: u8 = 0xff;
+
+/// `tokens` as a cache file: magic, version, the text they were lexed from
+/// and the distinct strings, each prefixed with its length in bytes, then
+/// the number of tokens and per
+/// token its kind's tag, lexeme, line, column and payload. Strings are
+/// written by their index among the distinct ones; numbers are
+/// little-endian, `u64` for the header and payloads and `u32` otherwise.
+/// `None` for a kind the layout has no tag for, or a position too large.
+fn encode(source: &str, tokens: &[Token]) -> Option<Vec<u8>> {
+    let mut strings: Vec<Symbol> = Vec::new();
+    let mut indices: HashMap<Symbol, u32> = HashMap::new();
+    let mut index = |symbol: Symbol| {
+        *indices.entry(symbol).or_insert_with(|| {
+            strings.push(symbol);
+            strings.len() as u32 - 1
+        })
+    };
+    let mut records = Vec::new();
+    for token in tokens {
+        let (tag, payload) = match &token.kind {
+            TokenKind::Integer(n, _) => (INTEGER, Some(*n as u64)),
+            TokenKind::Float(x, _) => (FLOAT, Some(x.to_bits())),
+            TokenKind::String(s) => (STRING, Some(u64::from(index(*s)))),
+            TokenKind::Identifier(_) => (IDENTIFIER, None),
+            kind => (FIXED.iter().position(|fixed| fixed == kind)? as u8, None),
+        };
+        records.push(tag);
+        for word in [
+            index(token.lexeme),
+            u32::try_from(token.line).ok()?,
+            u32::try_from(token.column).ok()?,
+        ] {
+            records.extend_from_slice(&word.to_le_bytes());
+        }
+        if let Some(payload) = payload {
+            records.extend_from_slice(&payload.to_le_bytes());
+        }
+    }
+
+    let mut out = MAGIC.to_vec();
+    out.extend_from_slice(&VERSION.to_le_bytes());
+    out.extend_from_slice(&(source.len() as u64).to_le_bytes());
+    out.extend_from_slice(source.as_bytes());
+    out.extend_from_slice(&(strings.len() as u64).to_le_bytes());
+    for string in strings {
+        out.extend_from_slice(&(string.len() as u64).to_le_bytes());
+        out.extend_from_slice(string.as_bytes());
+    }
+    out.extend_from_slice(&(tokens.len() as u64).to_le_bytes());
+    out.extend_from_slice(&records);
+    Some(out)
+}
+
+/// The tokens of a cache file, or `None` if it is not one this compiler
+/// wrote from `source`
+fn decode(data: &[u8], source: &str) -> Option<Vec<Token>> {
+    let mut data = Reader(data.strip_prefix(MAGIC.as_slice())?);
+    if data.word()? != VERSION {
+        return None;
+    }
+    let len = usize::try_from(data.word()?).ok()?;
+    if data.take(len)? != source.as_bytes() {
+        return None;
+    }
+    let strings = (0..data.word()?)
+        .map(|_| {
+            let len = usize::try_from(data.word()?).ok()?;
+            let string = std::str::from_utf8(data.take(len)?).ok()?;
+            Some(Symbol::intern(string))
+        })
+        .collect::<Option<Vec<Symbol>>>()?;
+    let string = |index: u64| {
+        usize::try_from(index)
+            .ok()
+            .and_then(|i| strings.get(i))
+            .copied()
+    };
+    let mut tokens = Vec::new();
+    for _ in 0..data.word()? {
+        let tag = data.take(1)?[0];
+        let lexeme = string(data.half()?.into())?;
+        let (line, column) = (data.half()? as usize, data.half()? as usize);
+        let kind = match tag {
+            INTEGER => TokenKind::Integer(data.word()? as i64, lexer::suffix(&lexeme)),
+            FLOAT => TokenKind::Float(f64::from_bits(data.word()?), lexer::suffix(&lexeme)),
+            STRING => TokenKind::String(string(data.word()?)?),
+            IDENTIFIER => TokenKind::Identifier(lexeme),
+            tag => FIXED.get(usize::from(tag))?.clone(),
+        };
+        tokens.push(Token::new(kind, lexeme, line, column));
+    }
+    data.0.is_empty().then_some(tokens)
+}
+
+/// The rest of a cache file
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.0.get(..len)?;
+        self.0 = &self.0[len..];
+        Some(bytes)
+    }
+
+    fn half(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(
+            self.take(4)?.try_into().expect("4 bytes"),
+        ))
+    }
+
+    fn word(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(
+            self.take(8)?.try_into().expect("8 bytes"),
+        ))
+    }
+}
+
+fn read(path: &Path, source: &str) -> Option<Arc<[Token]>> {
+    decode(&std::fs::read(path).ok()?, source).map(Into::into)
+}
+
+/// Write through a temporary file, so a build running alongside never reads
+/// half a stream
+fn write(path: &Path, source: &str, tokens: &[Token]) {
+    let Some(data) = encode(source, tokens) else {
+        return;
+    };
+    let partial = path.with_extension(format!("{}.tmp", std::process::id()));
+    let written = path
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|()| std::fs::write(&partial, data))
+        .and_then(|()| std::fs::rename(&partial, path));
+    if written.is_err() {
+        let _ = std::fs::remove_file(&partial);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(tokens: &[Token]) -> Vec<(TokenKind, Symbol, usize, usize)> {
+        tokens
+            .iter()
+            .map(|t| (t.kind.clone(), t.lexeme, t.line, t.column))
+            .collect()
+    }
+
+    #[test]
+    fn test_lex_is_cached() {
+        let source = "fn cached() -> f64 { let s = \"a\\nb\"; 1.5 + 2 }  // token_cache";
+        let (first, lookup) = lex(source);
+        assert_eq!(lookup, Lookup::Miss);
+        let (second, _) = lex(source);
+        assert_eq!(kinds(&first.unwrap()), kinds(&second.unwrap()));
+
+        // Errors are lexed again
+        let unclosed = "let s = \"open";
+        assert!(matches!(
+            lex(unclosed),
+            (Err(CompileError::Lex(_)), Lookup::Miss)
+        ));
+        assert!(matches!(
+            lex(unclosed),
+            (Err(CompileError::Lex(_)), Lookup::Miss)
+        ));
+
+        // Only the most recent streams are kept
+        let mut cache = Cache::default();
+        for key in 0..CAPACITY as u128 + 2 {
+            cache.insert(key, "", Arc::from([]));
+        }
+        cache.insert(5, "", Arc::from([]));
+        assert_eq!(cache.streams.len(), CAPACITY);
+        assert!(!cache.streams.contains_key(&1) && cache.streams.contains_key(&2));
+
+        // A text sharing its key with another is lexed, not given the
+        // other's tokens
+        let source = "fn collides() {}  // token_cache";
+        let other = lex_uncached("let other = 1;").unwrap();
+        global()
+            .lock()
+            .unwrap()
+            .insert(hash(source), "let other = 1;", other.into());
+        let (tokens, lookup) = lex(source);
+        assert_eq!(lookup, Lookup::Miss);
+        assert_eq!(
+            kinds(&tokens.unwrap()),
+            kinds(&lex_uncached(source).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_encode_round_trips() {
+        let source =
+            "#[inline] pub fn f(x: [i64; 3]) -> bool { x[0] >= -7 && \"é\\t\" != r.y::<T>() }";
+        let tokens = lex_uncached(source).unwrap();
+        let data = encode(source, &tokens).unwrap();
+        assert_eq!(kinds(&decode(&data, source).unwrap()), kinds(&tokens));
+        assert!(decode(&data[..data.len() - 1], source).is_none());
+        assert!(decode(b"\xffsoloprf", source).is_none());
+        assert!(decode(&data, "fn f() {}").is_none());
+
+        let dir = std::env::temp_dir().join(format!("solo-token-cache-{}", std::process::id()));
+        let path = dir.join("tokens").join("stream");
+        write(&path, source, &tokens);
+        assert_eq!(kinds(&read(&path, source).unwrap()), kinds(&tokens));
+        assert!(read(&path, "fn f() {}").is_none());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
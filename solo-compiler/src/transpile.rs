@@ -0,0 +1,395 @@
+//! Source-to-source translation between dialects
+//!
+//! `solo transpile --to <dialect>` converts a file to a sibling dialect of
+//! the My Language family. Me files become Solo through the
+//! [`legacy`](crate::legacy) front-end. Solo becomes Me from the checked
+//! program, as far as Me can express it: Me is a scripting language of
+//! top-level statements, so a translatable program is a `fn main()` (plus
+//! constants) using bindings, `if`/`else`, `print` and arithmetic, with
+//! beliefs declared from a distribution:
+//!
+//! | Solo                                   | Me                                    |
+//! |----------------------------------------|---------------------------------------|
+//! | the body of `fn main()`                | top-level statements                  |
+//! | `let x = e;`, `const X: T = e;`        | `x := e;`, `X := e;`                  |
+//! | `let x: Belief<bool> = ~Bernoulli(p);` | `belief x: Bool;` `x ~ Bernoulli(p);` |
+//! | `x = ~Normal(m, s);`                   | `x ~ Normal(m, s);`                   |
+//! | `bool`, `f64`, `i64`, `str`            | `Bool`, `Float`, `Int`, `String`      |
+//!
+//! Everything else has no Me equivalent and is reported at its span:
+//! other functions and types, loops, arenas, `defer`, references, arrays
+//! and belief inference (`update`, `marginalize`, ...), whose distribution
+//! semantics differ from Me's declared confidences.
+
+use crate::ast::*;
+use crate::builtins;
+use crate::error::{CompileError, Diagnostic};
+use crate::legacy::Dialect;
+use crate::typeck::Type;
+
+/// Translate `source`, written in `from`, to `to`
+pub fn transpile(source: &str, from: Dialect, to: Dialect) -> Result<String, CompileError> {
+    match (from, to) {
+        (Dialect::Legacy, Dialect::Solo) => crate::legacy::lower(source),
+        (Dialect::Solo, Dialect::Legacy) => to_me(source),
+        _ => Ok(source.to_string()),
+    }
+}
+
+/// Translate Solo source, which must type-check, to Me
+pub fn to_me(source: &str) -> Result<String, CompileError> {
+    let (program, _) = crate::analyze(source)?;
+    let mut me = Me {
+        exprs: &program.exprs,
+        out: String::new(),
+        indent: 0,
+        errors: Vec::new(),
+    };
+    for item in &program.items {
+        me.item(item);
+    }
+    if me.errors.is_empty() {
+        Ok(me.out)
+    } else {
+        me.errors.sort();
+        Err(CompileError::Translate(me.errors))
+    }
+}
+
+struct Me<'a> {
+    exprs: &'a Exprs,
+    out: String,
+    indent: usize,
+    errors: Vec<Diagnostic>,
+}
+
+impl Me<'_> {
+    fn unsupported(&mut self, span: Span, what: &str) {
+        self.errors.push(Diagnostic::new(
+            span,
+            format!("{} cannot be translated to Me", what),
+        ));
+    }
+
+    fn line(&mut self, text: &str) {
+        self.out.push_str(&"    ".repeat(self.indent));
+        self.out.push_str(text);
+        self.out.push('\n');
+    }
+
+    fn item(&mut self, item: &Item) {
+        match item {
+            Item::Function(function) if function.name == "main" => {
+                if function.return_type.is_some() {
+                    self.unsupported(function.span, "the result of `main`");
+                }
+                self.block(&function.body);
+            }
+            Item::Function(function) => self.unsupported(function.span, "functions besides `main`"),
+            Item::Const(item) => self.constant(item),
+            Item::Enum(def) => self.unsupported(def.span, "enums"),
+            Item::Struct(def) => self.unsupported(def.span, "structs"),
+            Item::Impl(block) => self.unsupported(block.span, "`impl` blocks"),
+            Item::Extern(block) => self.unsupported(block.span, "`extern` blocks"),
+            Item::TypeAlias(alias) => self.unsupported(alias.span, "type aliases"),
+        }
+    }
+
+    fn constant(&mut self, item: &ConstItem) {
+        let value = self.expr(&item.value);
+        self.line(&format!("{} := {};", item.name, value));
+    }
+
+    fn block(&mut self, block: &Block) {
+        for stmt in &block.stmts {
+            match stmt {
+                Stmt::Let(stmt) => self.binding(stmt),
+                Stmt::Const(item) => self.constant(item),
+                Stmt::Expr(expr) => self.statement(expr),
+                Stmt::Defer(expr) => self.unsupported(expr.span, "`defer` statements"),
+            }
+        }
+        if let Some(tail) = block.tail {
+            self.statement(&self.exprs[tail]);
+        }
+    }
+
+    fn binding(&mut self, stmt: &LetStmt) {
+        let belief = match &stmt.ty {
+            Some(TypeExpr::Generic {
+                name, args, bounds, ..
+            }) if name == "Belief" => {
+                if let Some(bound) = bounds.first() {
+                    self.unsupported(bound.span, "belief bounds");
+                }
+                match args.as_slice() {
+                    [TypeExpr::Named(name, _)] => me_type(name),
+                    _ => None,
+                }
+            }
+            _ => match &stmt.value {
+                Some(Expr {
+                    kind: ExprKind::Distribution(name, _),
+                    ..
+                }) => builtins::distribution(name).and_then(|(_, ty)| match ty {
+                    Type::Belief(inner) if *inner == Type::Bool => Some("Bool"),
+                    Type::Belief(_) => Some("Float"),
+                    _ => None,
+                }),
+                _ => None,
+            },
+        };
+
+        if let Some(ty) = belief {
+            self.line(&format!("belief {}: {};", stmt.name, ty));
+            if let Some(value) = &stmt.value {
+                self.sample(&stmt.name, value);
+            }
+            return;
+        }
+        match &stmt.value {
+            Some(value) => {
+                let value = self.expr(value);
+                self.line(&format!("{} := {};", stmt.name, value));
+            }
+            None => self.unsupported(stmt.span, "declarations without a value"),
+        }
+    }
+
+    /// `name ~ D(...);` for a distribution assigned to a belief
+    fn sample(&mut self, name: &str, value: &Expr) {
+        match &value.kind {
+            ExprKind::Distribution(distribution, args) => {
+                let args = self.list(args);
+                self.line(&format!("{} ~ {}({});", name, distribution, args));
+            }
+            ExprKind::Call(callee, _) if is_belief_op(&self.exprs[*callee]) => {
+                self.unsupported(self.exprs[*callee].span, "belief inference");
+            }
+            _ => self.unsupported(value.span, "beliefs not drawn from a distribution"),
+        }
+    }
+
+    fn statement(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Assign(target, value) => {
+                let ExprKind::Path(path) = &self.exprs[*target].kind else {
+                    return self.unsupported(
+                        self.exprs[*target].span,
+                        "assignments to fields and elements",
+                    );
+                };
+                match &self.exprs[*value].kind {
+                    ExprKind::Distribution(..) => {
+                        self.sample(&join_path(path), &self.exprs[*value])
+                    }
+                    _ => {
+                        let value = self.expr(&self.exprs[*value]);
+                        self.line(&format!("{} = {};", join_path(path), value));
+                    }
+                }
+            }
+            ExprKind::If(..) => self.conditional(expr, "if "),
+            ExprKind::While(..) => self.unsupported(expr.span, "`while` loops"),
+            ExprKind::For(..) => self.unsupported(expr.span, "`for` loops"),
+            ExprKind::Match(..) => self.unsupported(expr.span, "`match` expressions"),
+            ExprKind::Block(_) => self.unsupported(expr.span, "nested blocks"),
+            ExprKind::Arena(..) => self.unsupported(expr.span, "arenas"),
+            ExprKind::Unsafe(_) => self.unsupported(expr.span, "`unsafe` blocks"),
+            ExprKind::Return(_) => self.unsupported(expr.span, "`return`"),
+            ExprKind::Become(_) => self.unsupported(expr.span, "`become`"),
+            _ => {
+                let text = self.expr(expr);
+                self.line(&format!("{};", text));
+            }
+        }
+    }
+
+    /// An `if` statement, after `prefix` (`if ` or `} else if `)
+    fn conditional(&mut self, expr: &Expr, prefix: &str) {
+        let ExprKind::If(condition, then, otherwise) = &expr.kind else {
+            unreachable!("conditional is only called on `if`");
+        };
+        let condition = self.expr(&self.exprs[*condition]);
+        self.line(&format!("{}{} {{", prefix, condition));
+        self.nested(then);
+        match otherwise.map(|otherwise| &self.exprs[otherwise]) {
+            None => self.line("}"),
+            Some(
+                next @ Expr {
+                    kind: ExprKind::If(..),
+                    ..
+                },
+            ) => self.conditional(next, "} else if "),
+            Some(Expr {
+                kind: ExprKind::Block(block),
+                ..
+            }) => {
+                self.line("} else {");
+                self.nested(block);
+                self.line("}");
+            }
+            Some(other) => self.unsupported(other.span, "this `else` branch"),
+        }
+    }
+
+    fn nested(&mut self, block: &Block) {
+        self.indent += 1;
+        self.block(block);
+        self.indent -= 1;
+    }
+
+    fn list(&mut self, exprs: &[Expr]) -> String {
+        let items: Vec<String> = exprs.iter().map(|e| self.expr(e)).collect();
+        items.join(", ")
+    }
+
+    /// The Me text of an expression; untranslatable parts are reported and
+    /// written as `_`
+    fn expr(&mut self, expr: &Expr) -> String {
+        let what = match &expr.kind {
+            ExprKind::Int(n, _) => return n.to_string(),
+            ExprKind::Float(x, _) => return format!("{:?}", x),
+            ExprKind::Str(s) => return format!("{:?}", s),
+            ExprKind::Bool(b) => return b.to_string(),
+            ExprKind::Path(path) if path.len() == 1 => return path[0].to_string(),
+            ExprKind::Path(_) => "paths",
+            ExprKind::Unary(op @ (UnaryOp::Neg | UnaryOp::Not), operand) => {
+                let symbol = if *op == UnaryOp::Neg { "-" } else { "!" };
+                return format!("{}{}", symbol, self.operand(&self.exprs[*operand], u8::MAX));
+            }
+            ExprKind::Unary(UnaryOp::Deref, _) => "raw pointers",
+            ExprKind::Unary(..) => "references",
+            ExprKind::Cast(..) => "casts",
+            ExprKind::Binary(op, left, right) => {
+                let precedence = op.precedence();
+                let left = self.operand(&self.exprs[*left], precedence);
+                let right = self.operand(&self.exprs[*right], precedence + 1);
+                return format!("{} {} {}", left, op.symbol(), right);
+            }
+            ExprKind::Call(callee, args) if is_print(&self.exprs[*callee]) && args.len() == 1 => {
+                return format!("print({})", self.expr(&args[0]));
+            }
+            ExprKind::Call(callee, _) => {
+                let what = if is_belief_op(&self.exprs[*callee]) {
+                    "belief inference"
+                } else {
+                    "function calls"
+                };
+                self.unsupported(self.exprs[*callee].span, what);
+                return "_".to_string();
+            }
+            ExprKind::Index(..) => "indexing",
+            ExprKind::Range(..) => "ranges",
+            ExprKind::Field(..) => "field access",
+            ExprKind::MethodCall(..) => "method calls",
+            ExprKind::StructLit(..) => "struct literals",
+            ExprKind::Distribution(..) => "distributions not assigned to a belief",
+            ExprKind::Array(_) | ExprKind::ArrayRepeat(..) => "arrays",
+            ExprKind::If(..) => "`if` expressions",
+            ExprKind::While(..) => "`while` loops",
+            ExprKind::For(..) => "`for` loops",
+            ExprKind::Match(..) => "`match` expressions",
+            ExprKind::Block(_) => "block expressions",
+            ExprKind::Arena(..) => "arenas",
+            ExprKind::Unsafe(_) => "`unsafe` blocks",
+            ExprKind::Return(_) => "`return`",
+            ExprKind::Become(_) => "`become`",
+            ExprKind::Asm(..) => "inline assembly",
+            ExprKind::Assign(..) => "assignments used as values",
+        };
+        self.unsupported(expr.span, what);
+        "_".to_string()
+    }
+
+    /// `expr` as an operand of an operator binding at least as tightly as
+    /// `precedence`, parenthesized if it binds more loosely
+    fn operand(&mut self, expr: &Expr, precedence: u8) -> String {
+        let text = self.expr(expr);
+        match &expr.kind {
+            ExprKind::Binary(op, ..) if op.precedence() < precedence => {
+                format!("({})", text)
+            }
+            _ => text,
+        }
+    }
+}
+
+fn is_print(callee: &Expr) -> bool {
+    matches!(&callee.kind, ExprKind::Path(path) if path.len() == 1 && path[0] == "print")
+}
+
+fn is_belief_op(callee: &Expr) -> bool {
+    matches!(&callee.kind, ExprKind::Path(path) if path.len() == 1 && builtins::is_belief_op(&path[0]))
+}
+
+/// The Me name of a Solo primitive type
+fn me_type(solo: &str) -> Option<&'static str> {
+    match solo {
+        "bool" => Some("Bool"),
+        "f64" => Some("Float"),
+        "i64" => Some("Int"),
+        "str" => Some("String"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_me() {
+        let source = "
+            const THRESHOLD: f64 = 0.85;
+            fn main() {
+                let mut claim: Belief<bool> = ~Bernoulli(0.8);
+                let score = 0.5 * (2.0 + 1.0);
+                if score >= THRESHOLD && !false {
+                    print(\"PUBLISH\");
+                } else if score < 0.0 {
+                    print(-score);
+                } else {
+                    print(\"HOLD\")
+                }
+                claim = ~Bernoulli(0.3);
+            }
+        ";
+        assert_eq!(
+            to_me(source).unwrap(),
+            "THRESHOLD := 0.85;
+belief claim: Bool;
+claim ~ Bernoulli(0.8);
+score := 0.5 * (2.0 + 1.0);
+if score >= THRESHOLD && !false {
+    print(\"PUBLISH\");
+} else if score < 0.0 {
+    print(-score);
+} else {
+    print(\"HOLD\");
+}
+claim ~ Bernoulli(0.3);
+"
+        );
+
+        let err = to_me(
+            "#![feature(belief_inference)]
+struct P { x: i64 }
+fn main() {
+    let b = ~Normal(0.0, 1.0);
+    let c = update(b, ~Normal(1.0, 1.0));
+    arena { let p = P { x: 1 }; }
+}",
+        )
+        .unwrap_err();
+        let messages: Vec<String> = err.diagnostics().iter().map(|d| d.to_string()).collect();
+        assert_eq!(
+            messages,
+            [
+                "2:1: structs cannot be translated to Me",
+                "5:13: belief inference cannot be translated to Me",
+                "6:5: arenas cannot be translated to Me",
+            ]
+        );
+    }
+}
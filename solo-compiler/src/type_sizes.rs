@@ -0,0 +1,247 @@
+//! Type sizes and padding
+//!
+//! `solo check --print-type-sizes` lists every struct and enum of a program,
+//! largest first, with its size and alignment, and for structs each field
+//! in memory order with the padding between fields spelled out. A field no
+//! `.name` expression anywhere in the program reads is marked as never read:
+//! unless the struct is serialized (`#[derive(...)]`) or shared with C
+//! (`#[repr(C)]`), it only takes space.
+//!
+//! Structs are laid out to need as little padding as possible (see
+//! [`layout`](crate::layout)), except `#[repr(C)]` ones, which keep their
+//! declaration order. The `excess_padding` lint suggests a declaration order
+//! for those that would make them smaller.
+
+use std::collections::HashSet;
+use std::fmt::Write;
+
+use crate::ast::*;
+use crate::error::Diagnostic;
+use crate::layout::{self, Repr, StructLayout};
+use crate::lint::Warning;
+use crate::typeck::TypeInfo;
+use crate::visit::{self, Visit};
+
+/// Name of the lint suggesting field orders with less padding
+pub const LINT: &str = "excess_padding";
+
+/// The size report of every struct and enum of a checked program
+pub fn report(program: &Program, info: &TypeInfo) -> String {
+    let mut reads = FieldReads(&program.exprs, HashSet::new());
+    reads.visit_program(program);
+
+    let mut types: Vec<(u64, &str, String)> = Vec::new();
+    for item in &program.items {
+        match item {
+            Item::Struct(def) => {
+                let Some(computed) = info.layouts.get(def.name.as_str()) else {
+                    continue;
+                };
+                // Serialization and C code read fields behind our back
+                let all_read = def.derives().next().is_some() || computed.repr.c;
+                let read = |field: &str| all_read || reads.1.contains(field);
+                let text = struct_sizes(&program.exprs, def, computed, read);
+                types.push((computed.layout.size, &def.name, text));
+            }
+            Item::Enum(def) => {
+                let Some(variants) = info.enums.get(def.name.as_str()) else {
+                    continue;
+                };
+                let values: Vec<i64> = variants.iter().map(|(_, value)| *value).collect();
+                let computed = layout::enum_layout(&values);
+                let text = format!(
+                    "enum {}: size {}, align {}, {} variant(s)\n",
+                    def.name,
+                    computed.size,
+                    computed.align,
+                    variants.len()
+                );
+                types.push((computed.size, &def.name, text));
+            }
+            _ => {}
+        }
+    }
+    types.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(b.1)));
+    types.into_iter().map(|(_, _, text)| text).collect()
+}
+
+fn struct_sizes(
+    exprs: &Exprs,
+    def: &StructDef,
+    computed: &StructLayout,
+    read: impl Fn(&str) -> bool,
+) -> String {
+    let mut out = format!("struct {}", def.name);
+    if computed.repr != Repr::default() {
+        let _ = write!(out, " ({})", computed.repr);
+    }
+    let _ = writeln!(
+        out,
+        ": size {}, align {}, padding {}",
+        computed.layout.size,
+        computed.layout.align,
+        padding(computed)
+    );
+    let mut end = 0;
+    for (field, def) in in_memory_order(computed, def) {
+        if field.offset > end {
+            let _ = writeln!(
+                out,
+                "    padding: offset {}, size {}",
+                end,
+                field.offset - end
+            );
+        }
+        let _ = write!(
+            out,
+            "    {}: {}, offset {}, size {}, align {}",
+            field.name,
+            exprs.display(&def.ty),
+            field.offset,
+            field.layout.size,
+            field.layout.align
+        );
+        if !read(&field.name) {
+            out.push_str(" (never read)");
+        }
+        out.push('\n');
+        end = end.max(field.offset + field.layout.size);
+    }
+    if computed.layout.size > end {
+        let _ = writeln!(
+            out,
+            "    padding: offset {}, size {}",
+            end,
+            computed.layout.size - end
+        );
+    }
+    out
+}
+
+/// Fields of a struct by offset, with their definitions
+fn in_memory_order<'a>(
+    computed: &'a StructLayout,
+    def: &'a StructDef,
+) -> Vec<(&'a layout::FieldLayout, &'a FieldDef)> {
+    let mut fields: Vec<_> = computed.fields.iter().zip(&def.fields).collect();
+    fields.sort_by_key(|(field, _)| field.offset);
+    fields
+}
+
+/// Bytes of a struct not taken by its fields
+pub fn padding(computed: &StructLayout) -> u64 {
+    let fields: u64 = computed.fields.iter().map(|f| f.layout.size).sum();
+    computed.layout.size - fields
+}
+
+/// Suggest field orders for `#[repr(C)]` structs that would need less padding
+pub fn check(program: &Program, info: &TypeInfo) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    for item in &program.items {
+        let Item::Struct(def) = item else { continue };
+        let Some(computed) = info.layouts.get(def.name.as_str()) else {
+            continue;
+        };
+        if !computed.repr.c {
+            continue;
+        }
+        let fields: Vec<_> = computed
+            .fields
+            .iter()
+            .map(|f| (f.name.clone(), f.layout))
+            .collect();
+        let repr = Repr {
+            c: false,
+            ..computed.repr
+        };
+        let Some(better) = StructLayout::compute(&def.name, &fields, repr) else {
+            continue;
+        };
+        if better.layout.size >= computed.layout.size {
+            continue;
+        }
+        let order: Vec<&str> = in_memory_order(&better, def)
+            .into_iter()
+            .map(|(field, _)| field.name.as_str())
+            .collect();
+        warnings.push(Warning {
+            diagnostic: Diagnostic::new(
+                def.span,
+                format!(
+                    "struct `{}` takes {} bytes, {} of them padding; declaring its fields as `{}` would make it {} bytes",
+                    def.name,
+                    computed.layout.size,
+                    padding(computed),
+                    order.join(", "),
+                    better.layout.size
+                ),
+            ),
+            lint: LINT,
+        });
+    }
+    warnings
+}
+
+/// Names of the fields read anywhere, as `expr.name`
+struct FieldReads<'a>(&'a Exprs, HashSet<String>);
+
+impl<'a> Visit<'a> for FieldReads<'a> {
+    fn exprs(&self) -> &'a Exprs {
+        self.0
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        if let ExprKind::Field(_, name) = &expr.kind {
+            self.1.insert(name.to_string());
+        }
+        visit::walk_expr(self, expr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "
+        #[repr(C)]
+        struct Header { flag: bool, value: i64, tag: u16 }
+        struct Story { words: i64, draft: bool, score: i32 }
+        enum Desk { News, Sport, Arts }
+        fn main() {
+            let s = Story { words: 3, draft: false, score: 1 };
+            print(s.words);
+            print(s.score);
+        }";
+
+    #[test]
+    fn test_report() {
+        let (program, info) = crate::analyze(SOURCE).unwrap();
+        assert_eq!(
+            report(&program, &info),
+            "struct Header (repr(C)): size 24, align 8, padding 13
+    flag: bool, offset 0, size 1, align 1
+    padding: offset 1, size 7
+    value: i64, offset 8, size 8, align 8
+    tag: u16, offset 16, size 2, align 2
+    padding: offset 18, size 6
+struct Story: size 16, align 8, padding 3
+    words: i64, offset 0, size 8, align 8
+    score: i32, offset 8, size 4, align 4
+    draft: bool, offset 12, size 1, align 1 (never read)
+    padding: offset 13, size 3
+enum Desk: size 1, align 1, 3 variant(s)
+"
+        );
+    }
+
+    #[test]
+    fn test_excess_padding() {
+        let (program, info) = crate::analyze(SOURCE).unwrap();
+        let warnings = check(&program, &info);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(
+            warnings[0].diagnostic.message,
+            "struct `Header` takes 24 bytes, 13 of them padding; declaring its fields as `value, tag, flag` would make it 16 bytes"
+        );
+    }
+}
@@ -0,0 +1,6350 @@
+//! Type checker for Solo dialect
+//!
+//! Resolves names, infers expression types, and checks them against
+//! annotations. Constants, array lengths, and enum discriminants are
+//! computed here with the const evaluator, so their values are available
+//! to every later phase through [`TypeInfo`].
+//!
+//! Struct values are affine: using one by value moves it, and any later use
+//! of the moved place (or a part of it) is an error until it is reassigned.
+//! Struct layouts and serialization schemas are computed here as well.
+//!
+//! Bounds on belief types (`Belief<bool, confidence >= 0.9>`) are proved
+//! wherever a value meets an annotation, using [`confidence`] to track
+//! what is known about each belief.
+//!
+//! Structs, arrays, beliefs and references live in a [`Region`]; a value
+//! may only be stored, returned or be the value of an `arena` block where
+//! its region outlives the receiving place (see [`region`]).
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::ast::*;
+use crate::builtins;
+use crate::confidence::{self, Bounds};
+use crate::const_eval::{ConstEvaluator, ConstValue};
+use crate::error::{CompileError, Diagnostic, ErrorCode, Fix};
+use crate::ffi::{CType, Signature};
+use crate::layout::{self, DataLayout, Layout, StructLayout};
+use crate::lint;
+use crate::numeric::Numeric;
+use crate::region::{self, Region, Regions};
+use crate::stdlib::alloc;
+use crate::stdlib::atomic::{self, AtomicType};
+use crate::stdlib::record::{FieldType, Schema};
+use crate::stdlib::simd::{self, VectorType};
+use crate::stdlib::sync::GuardKind;
+use crate::suggest;
+use crate::symbol::Symbol;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Int,
+    Float,
+    /// A numeric type other than `i64` ([`Type::Int`]) and `f64`
+    /// ([`Type::Float`]), which mixes with no other in arithmetic
+    Sized(Numeric),
+    Bool,
+    Str,
+    Unit,
+    Array(Box<Type>, usize),
+    Ref(bool, Box<Type>),
+    /// Raw pointer, `*mut T` if mutable, dereferenced only in `unsafe`
+    Ptr(bool, Box<Type>),
+    Enum(String),
+    Struct(String),
+    /// A `newtype`: distinct from every other type, including the one it
+    /// is represented as (see [`TypeInfo::newtypes`])
+    Newtype(String),
+    /// Distribution over `f64` or `bool`
+    Belief(Box<Type>),
+    Fn(Vec<Type>, Box<Type>),
+    /// Growable array in an arena (see [`crate::stdlib::collections`])
+    Vec(Box<Type>),
+    /// Hash map in an arena
+    Map(Box<Type>, Box<Type>),
+    /// Growable UTF-8 string in an arena (see [`crate::stdlib::string`])
+    StringBuf,
+    /// Items produced for a `for` loop, such as the chars of a string
+    Iter(Box<Type>),
+    /// `Some` value of the type, or `None`
+    Option(Box<Type>),
+    /// `start..end` over integers of the type
+    Range(Box<Type>),
+    /// `[T]`: some of the elements of an array or `Vec`, as `xs[1..3]`
+    /// selects them
+    Slice(Box<Type>),
+    /// Fixed-width vector such as `f64x4` (see [`crate::stdlib::simd`])
+    Simd(&'static VectorType),
+    /// Handle to a thread spawned by `thread::spawn`, joined for its result
+    Thread(Box<Type>),
+    /// Sending end of a channel (see [`crate::stdlib::channel`]), copied
+    /// freely so that any number of threads can send
+    Sender(Box<Type>),
+    /// Receiving end of a channel, of which there is one
+    Receiver(Box<Type>),
+    /// Handle to an atomic integer such as `AtomicI64` (see
+    /// [`crate::stdlib::atomic`]); copies share the integer
+    Atomic(&'static AtomicType),
+    /// Handle to a value behind a lock (see [`crate::stdlib::sync`]);
+    /// copies share the value
+    Mutex(Box<Type>),
+    RwLock(Box<Type>),
+    /// A held lock, released when the guard is dropped or consumed by
+    /// `unlock`
+    Guard(GuardKind, Box<Type>),
+    /// Handle to an arena (`arena name { ... }` or an `Arena` parameter)
+    Arena,
+    /// Where an arena was when `handle.checkpoint()` was called, which
+    /// `handle.reset_to` rewinds it to
+    Checkpoint,
+    /// A value of any type, checked when it is converted to a type of its
+    /// own (see [`TypeInfo::any_casts`])
+    Any,
+    /// `!`, the type of `return`, `panic` and other expressions that never
+    /// finish; there are no values of it, so it fits wherever one is expected
+    Never,
+    /// Placeholder after an error, compatible with everything
+    Unknown,
+}
+
+impl Type {
+    fn compatible(&self, other: &Type) -> bool {
+        self.fits(other, true)
+    }
+
+    /// Whether the types are compatible, `any` matching every type if
+    /// `loose`. A value converted to or from `any` is checked as a whole,
+    /// so only it and its array elements are loose: what is behind a
+    /// reference or in a collection must match exactly.
+    fn fits(&self, other: &Type, loose: bool) -> bool {
+        match (self, other) {
+            (Type::Unknown, _) | (_, Type::Unknown) => true,
+            (_, Type::Never) => true,
+            (Type::Any, _) | (_, Type::Any) => loose || self == other,
+            (Type::Array(a, n), Type::Array(b, m)) => n == m && a.fits(b, loose),
+            // An array is a slice of all its elements
+            (Type::Slice(a), Type::Slice(b) | Type::Array(b, _)) => a.fits(b, loose),
+            (Type::Ref(m1, a), Type::Ref(m2, b)) | (Type::Ptr(m1, a), Type::Ptr(m2, b)) => {
+                (m1 == m2 || !m2) && a.fits(b, false)
+            }
+            (Type::Vec(a), Type::Vec(b))
+            | (Type::Iter(a), Type::Iter(b))
+            | (Type::Option(a), Type::Option(b))
+            | (Type::Range(a), Type::Range(b))
+            | (Type::Thread(a), Type::Thread(b))
+            | (Type::Sender(a), Type::Sender(b))
+            | (Type::Receiver(a), Type::Receiver(b))
+            | (Type::Mutex(a), Type::Mutex(b))
+            | (Type::RwLock(a), Type::RwLock(b)) => a.fits(b, false),
+            (Type::Guard(k1, a), Type::Guard(k2, b)) => k1 == k2 && a.fits(b, false),
+            (Type::Map(k1, v1), Type::Map(k2, v2)) => k1.fits(k2, false) && v1.fits(v2, false),
+            _ => self == other,
+        }
+    }
+
+    /// Whether this is `any` or an array of it, which converts loosely
+    fn contains_any(&self) -> bool {
+        match self {
+            Type::Any => true,
+            Type::Array(elem, _) => elem.contains_any(),
+            _ => false,
+        }
+    }
+
+    fn is_numeric(&self) -> bool {
+        matches!(
+            self,
+            Type::Int | Type::Float | Type::Sized(_) | Type::Any | Type::Unknown
+        )
+    }
+
+    /// The type of values of `numeric`
+    fn numeric(numeric: Numeric) -> Type {
+        match numeric {
+            Numeric::I64 => Type::Int,
+            Numeric::F64 => Type::Float,
+            sized => Type::Sized(sized),
+        }
+    }
+
+    /// The numeric type this is, if it is one
+    fn as_numeric(&self) -> Option<Numeric> {
+        match self {
+            Type::Int => Some(Numeric::I64),
+            Type::Float => Some(Numeric::F64),
+            Type::Sized(numeric) => Some(*numeric),
+            _ => None,
+        }
+    }
+
+    /// Whether values of this type live in a [`Region`]
+    fn has_region(&self) -> bool {
+        match self {
+            Type::Struct(_)
+            | Type::Array(..)
+            | Type::Slice(_)
+            | Type::Vec(_)
+            | Type::Map(..)
+            | Type::StringBuf
+            | Type::Belief(_)
+            | Type::Ref(..)
+            | Type::Arena
+            | Type::Checkpoint => true,
+            Type::Option(value) => value.has_region(),
+            _ => false,
+        }
+    }
+
+    /// A collection, channel or `Option` whose element types are still
+    /// unknown, as created by `Vec::new`, `Map::new`, `channel::unbounded`
+    /// and `None`
+    fn is_partial(&self) -> bool {
+        match self {
+            Type::Vec(elem) | Type::Receiver(elem) | Type::Option(elem) => **elem == Type::Unknown,
+            Type::Map(key, value) => **key == Type::Unknown || **value == Type::Unknown,
+            _ => false,
+        }
+    }
+
+    /// Copyable values are duplicated on use; all others are moved
+    pub fn is_copy(&self) -> bool {
+        match self {
+            Type::Struct(_)
+            | Type::Vec(_)
+            | Type::Map(..)
+            | Type::StringBuf
+            | Type::Iter(_)
+            | Type::Thread(_)
+            | Type::Receiver(_)
+            | Type::Guard(..)
+            | Type::Any => false,
+            Type::Array(elem, _) | Type::Slice(elem) | Type::Option(elem) => elem.is_copy(),
+            _ => true,
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Int => write!(f, "int"),
+            Type::Float => write!(f, "float"),
+            Type::Sized(numeric) => write!(f, "{}", numeric),
+            Type::Bool => write!(f, "bool"),
+            Type::Str => write!(f, "str"),
+            Type::Unit => write!(f, "()"),
+            Type::Array(elem, len) => write!(f, "[{}; {}]", elem, len),
+            Type::Slice(elem) => write!(f, "[{}]", elem),
+            Type::Ref(true, inner) => write!(f, "&mut {}", inner),
+            Type::Ref(false, inner) => write!(f, "&{}", inner),
+            Type::Ptr(true, inner) => write!(f, "*mut {}", inner),
+            Type::Ptr(false, inner) => write!(f, "*const {}", inner),
+            Type::Enum(name) | Type::Struct(name) | Type::Newtype(name) => write!(f, "{}", name),
+            Type::Belief(inner) => write!(f, "Belief<{}>", inner),
+            Type::Vec(elem) => write!(f, "Vec<{}>", elem),
+            Type::Map(key, value) => write!(f, "Map<{}, {}>", key, value),
+            Type::StringBuf => write!(f, "StringBuf"),
+            Type::Iter(item) => write!(f, "Iter<{}>", item),
+            Type::Option(value) => write!(f, "Option<{}>", value),
+            Type::Range(elem) => write!(f, "Range<{}>", elem),
+            Type::Simd(vector) => write!(f, "{}", vector.name),
+            Type::Atomic(atomic) => write!(f, "{}", atomic.name),
+            Type::Thread(result) => write!(f, "Thread<{}>", result),
+            Type::Sender(elem) => write!(f, "Sender<{}>", elem),
+            Type::Receiver(elem) => write!(f, "Receiver<{}>", elem),
+            Type::Mutex(value) => write!(f, "Mutex<{}>", value),
+            Type::RwLock(value) => write!(f, "RwLock<{}>", value),
+            Type::Guard(kind, value) => write!(f, "{}<{}>", kind.type_name(), value),
+            Type::Fn(params, ret) => {
+                write!(f, "fn(")?;
+                for (i, p) in params.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", p)?;
+                }
+                write!(f, ") -> {}", ret)
+            }
+            Type::Arena => write!(f, "{}", region::ARENA_TYPE),
+            Type::Checkpoint => write!(f, "{}", region::CHECKPOINT_TYPE),
+            Type::Any => write!(f, "any"),
+            Type::Never => write!(f, "!"),
+            Type::Unknown => write!(f, "{{unknown}}"),
+        }
+    }
+}
+
+/// Results of type checking used by later phases
+#[derive(Debug, Clone, Default)]
+pub struct TypeInfo {
+    /// Values of all global constants
+    pub consts: HashMap<String, ConstValue>,
+    /// Discriminants of every enum, in declaration order
+    pub enums: HashMap<String, Vec<(String, i64)>>,
+    /// Field types of every struct, in declaration order
+    pub structs: HashMap<String, Vec<(String, Type)>>,
+    /// The type each `newtype` is represented as
+    pub newtypes: HashMap<String, Type>,
+    /// Memory layout of every struct
+    pub layouts: HashMap<String, StructLayout>,
+    /// Record schemas of structs deriving `Csv` or `Binary`
+    pub schemas: HashMap<String, Schema>,
+    /// C signatures of the functions declared in `extern` blocks
+    pub externs: HashMap<String, Signature>,
+    /// C signatures of the `pub extern "C"` functions
+    pub exports: HashMap<String, Signature>,
+    /// Libraries named by `#[link]` attributes, in declaration order
+    pub links: Vec<String>,
+    /// Every definition and resolved use of a local, item, variant or field,
+    /// in source order
+    pub references: Vec<Reference>,
+    /// The type each expression of type `any` (or an array of it) is
+    /// converted to, by span, and checked against when it is evaluated
+    pub any_casts: HashMap<Span, Type>,
+    /// The sized numeric type of each arithmetic operation, negation and
+    /// `f32` literal that has one, by span, and the type each `as` cast
+    /// converts to
+    pub numeric: HashMap<Span, Numeric>,
+    /// The first statement or tail expression of a block that control never
+    /// reaches, and the statement of type `!` before it, for each such block
+    pub unreachable: Vec<(Span, Span)>,
+    /// The type of the allocator behind each `arena ... in` block, by span,
+    /// whose `allocate` and `reset` methods the arena calls
+    pub allocators: HashMap<Span, String>,
+}
+
+/// A name in the source and the definition it resolves to
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Reference {
+    /// Position of the syntax introducing the name: the name itself, or the
+    /// keyword, `.` or path it follows
+    pub span: Span,
+    pub name: String,
+    /// Position of the definition, in the same way; equal to `span` for
+    /// the definition itself
+    pub definition: Span,
+}
+
+#[derive(Debug, Clone)]
+struct Local {
+    id: usize,
+    ty: Type,
+    mutable: bool,
+    const_value: Option<ConstValue>,
+    /// Number of enclosing loops at the declaration
+    loop_depth: usize,
+    /// What is known about the belief held by this variable
+    bounds: Option<Bounds>,
+    /// Bounds every value assigned to it must meet
+    declared: Vec<Bound>,
+    /// Where its value lives; assigned values must outlive it
+    region: Region,
+    /// Where the variable itself lives
+    home: Region,
+    /// Where it is declared
+    span: Span,
+}
+
+/// A local variable or a field path inside one (`p`, `p.pos.x`)
+#[derive(Debug, Clone)]
+struct Place {
+    local: usize,
+    name: String,
+    path: Vec<String>,
+    ty: Type,
+    behind_ref: bool,
+    loop_depth: usize,
+}
+
+impl Place {
+    fn display(&self) -> String {
+        std::iter::once(self.name.as_str())
+            .chain(self.path.iter().map(String::as_str))
+            .collect::<Vec<_>>()
+            .join(".")
+    }
+
+    /// Do the two places overlap (one is a prefix of the other)?
+    fn overlaps(&self, other: &Place) -> bool {
+        self.local == other.local && self.path.iter().zip(&other.path).all(|(a, b)| a == b)
+    }
+}
+
+/// An unsuffixed numeric literal, or an expression made of them, which
+/// takes the numeric type expected of it
+#[derive(Debug, Clone)]
+enum Literal {
+    Int(i128),
+    Float,
+    /// An array of this many elements, all of the literals at these spans
+    Array(Vec<Span>, usize),
+    /// Arithmetic on the literals at these spans
+    Arithmetic(Span, Span),
+    /// `Some` of the literal at this span
+    Some(Span),
+}
+
+/// Attributes understood on struct items
+const DERIVES: &[&str] = &["Csv", "Binary"];
+
+/// Type check a program, reporting every error found in source order
+pub fn check(program: &Program) -> Result<TypeInfo, CompileError> {
+    check_for(program, DataLayout::LP64)
+}
+
+/// What the checker knows at one position, for completion in editors
+#[derive(Debug, Clone, Default)]
+pub struct Probe {
+    /// Type of the innermost expression starting there
+    pub ty: Option<Type>,
+    /// Locals in scope there, innermost first, without shadowed ones
+    pub locals: Vec<(String, Type)>,
+}
+
+/// Type check `program` whatever errors it has, probing the expression at
+/// `at`; what is known of an erroneous program is what [`check`] would have
+/// returned had the errors not been there
+pub fn probe(program: &Program, at: Span) -> (TypeInfo, Probe) {
+    let mut checker = TypeChecker::new(program, DataLayout::LP64);
+    checker.probe = Some((at, Probe::default()));
+    checker.check_program(program);
+    let probe = checker.probe.map(|(_, probe)| probe).unwrap_or_default();
+    (checker.info, probe)
+}
+
+/// Type check `program` whatever errors it has, for the names it resolves
+pub fn infer(program: &Program) -> TypeInfo {
+    let mut checker = TypeChecker::new(program, DataLayout::LP64);
+    checker.check_program(program);
+    checker.info
+}
+
+/// Type check with the struct layouts of a particular target
+pub fn check_for(program: &Program, data_layout: DataLayout) -> Result<TypeInfo, CompileError> {
+    let mut checker = TypeChecker::new(program, data_layout);
+    checker.check_program(program);
+    if checker.errors.is_empty() {
+        Ok(checker.info)
+    } else {
+        checker.errors.sort();
+        // Deferred expressions are checked on every path out of their block
+        checker.errors.dedup();
+        Err(CompileError::Type(checker.errors))
+    }
+}
+
+struct TypeChecker<'p> {
+    exprs: &'p Exprs,
+    evaluator: ConstEvaluator<'p>,
+    /// Signatures by call path (`name`, `Type::name`); receivers come first
+    functions: HashMap<String, (Vec<Type>, Type)>,
+    receivers: HashMap<String, Receiver>,
+    /// Declared belief bounds of parameters (after the receiver) and returns
+    signature_bounds: HashMap<String, (Vec<Vec<Bound>>, Vec<Bound>)>,
+    const_types: HashMap<String, Type>,
+    enum_names: Vec<String>,
+    struct_defs: HashMap<String, &'p StructDef>,
+    /// `type` and `newtype` items by name
+    aliases: HashMap<String, &'p TypeAlias>,
+    /// Type aliases being expanded, innermost last
+    expanding: Vec<String>,
+    scopes: Vec<HashMap<Symbol, Local>>,
+    next_local: usize,
+    loop_depth: usize,
+    /// Number of enclosing `unsafe` blocks
+    unsafe_depth: usize,
+    /// Number of enclosing `expect` arguments, plus one in a `#[model]`
+    /// function
+    probabilistic_depth: usize,
+    /// Functions marked `#[model]`
+    models: HashSet<String>,
+    /// Dereferences of `*mut` pointers, which may be assigned to
+    writable_derefs: HashSet<Span>,
+    /// Indexing with a range, which selects a copy of the elements that
+    /// cannot be written through
+    slicings: HashSet<Span>,
+    /// Places moved out of in the current function, with the move location
+    moves: Vec<(Place, Span)>,
+    /// Variables whose data a `reset_to` freed, by local id, with the
+    /// position of the reset
+    resets: Vec<(usize, Span)>,
+    /// The region each `handle.checkpoint()` starts, by position
+    checkpoints: HashMap<Span, Region>,
+    /// Places used by the expressions deferred in each enclosing block, and
+    /// whether they are moved, which happens when that block exits
+    deferred: Vec<Vec<(Place, Span, bool)>>,
+    /// Places used by the deferred expression being checked
+    deferring: Option<Vec<(Place, Span)>>,
+    /// Structs already reported as infinitely sized
+    recursive: HashSet<String>,
+    return_type: Type,
+    return_bounds: Vec<Bound>,
+    /// Expressions of the current function whose value is its result, by
+    /// position: where `become` may stand
+    tail_positions: HashSet<Span>,
+    /// Bounds of the tail of blocks producing beliefs, by block position
+    block_bounds: HashMap<Span, Bounds>,
+    regions: Regions,
+    /// Variables lent to an enclosing arena as its allocator, by local id,
+    /// with the arena's position
+    lent: Vec<(usize, Span)>,
+    /// What values returned by the current function must outlive; `None`
+    /// when they may live in any data the caller passed in
+    return_region: Option<Region>,
+    /// Regions of the tail of blocks, and of the arms of `match`
+    /// expressions, producing values with a region, by position
+    block_regions: HashMap<Span, Region>,
+    /// Resolved call path of each method call, by position
+    method_paths: HashMap<Span, String>,
+    /// Where each item, method, variant (`E::V`) and field (`S.f`) is defined
+    definitions: HashMap<String, Span>,
+    /// Start of the library part of a package's program
+    library: Option<Span>,
+    /// What the library does not make `pub`, keyed like `definitions`,
+    /// with the kind of thing it is
+    private: HashMap<String, (&'static str, Visibility)>,
+    info: TypeInfo,
+    /// Pointer sizes of the target, for struct layouts
+    data_layout: DataLayout,
+    errors: Vec<Diagnostic>,
+    /// Position to report types and scope at, and what was found there
+    probe: Option<(Span, Probe)>,
+    /// Unsuffixed numeric literals checked so far, by span
+    literals: HashMap<Span, Literal>,
+}
+
+impl<'p> TypeChecker<'p> {
+    fn new(program: &'p Program, data_layout: DataLayout) -> Self {
+        let enum_names = program
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                Item::Enum(e) => Some(e.name.to_string()),
+                _ => None,
+            })
+            .collect();
+        let struct_defs = program
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                Item::Struct(s) => Some((s.name.to_string(), s)),
+                _ => None,
+            })
+            .collect();
+        let aliases = program
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                Item::TypeAlias(alias) => Some((alias.name.to_string(), alias)),
+                _ => None,
+            })
+            .collect();
+        Self {
+            exprs: &program.exprs,
+            evaluator: ConstEvaluator::new(program),
+            functions: HashMap::new(),
+            receivers: HashMap::new(),
+            signature_bounds: HashMap::new(),
+            const_types: HashMap::new(),
+            enum_names,
+            struct_defs,
+            aliases,
+            expanding: Vec::new(),
+            scopes: Vec::new(),
+            next_local: 0,
+            loop_depth: 0,
+            unsafe_depth: 0,
+            probabilistic_depth: 0,
+            models: HashSet::new(),
+            writable_derefs: HashSet::new(),
+            slicings: HashSet::new(),
+            moves: Vec::new(),
+            resets: Vec::new(),
+            checkpoints: HashMap::new(),
+            deferred: Vec::new(),
+            deferring: None,
+            recursive: HashSet::new(),
+            return_type: Type::Unit,
+            return_bounds: Vec::new(),
+            tail_positions: HashSet::new(),
+            block_bounds: HashMap::new(),
+            regions: Regions::default(),
+            lent: Vec::new(),
+            return_region: None,
+            block_regions: HashMap::new(),
+            method_paths: HashMap::new(),
+            definitions: HashMap::new(),
+            library: program.library,
+            private: HashMap::new(),
+            info: TypeInfo::default(),
+            data_layout,
+            errors: Vec::new(),
+            probe: None,
+            literals: HashMap::new(),
+        }
+    }
+
+    fn check_program(&mut self, program: &Program) {
+        // The `Ordering` of atomics is in every program that does not
+        // define its own
+        if !self.enum_names.iter().any(|name| name == atomic::ORDERING) {
+            self.enum_names.push(atomic::ORDERING.to_string());
+            let variants = atomic::Ordering::ALL
+                .iter()
+                .enumerate()
+                .map(|(i, ordering)| (ordering.name().to_string(), i as i64))
+                .collect();
+            self.info
+                .enums
+                .insert(atomic::ORDERING.to_string(), variants);
+        }
+
+        // Pass 1: collect signatures so items may be used before their definition
+        let mut seen: HashMap<String, Span> = HashMap::new();
+        for item in &program.items {
+            let names = match item {
+                Item::Function(f) => vec![(f.name.to_string(), f.span)],
+                Item::Const(c) => vec![(c.name.to_string(), c.span)],
+                Item::Enum(e) => vec![(e.name.to_string(), e.span)],
+                Item::Struct(s) => vec![(s.name.to_string(), s.span)],
+                Item::TypeAlias(alias) => vec![(alias.name.to_string(), alias.span)],
+                Item::Impl(block) => block
+                    .methods
+                    .iter()
+                    .map(|m| (block.qualified(&m.name), m.span))
+                    .collect(),
+                Item::Extern(block) => block
+                    .functions
+                    .iter()
+                    .map(|f| (f.name.to_string(), f.span))
+                    .collect(),
+            };
+            for (name, span) in names {
+                self.definitions.entry(name.clone()).or_insert(span);
+                let Some(previous) = seen.insert(name.clone(), span) else {
+                    continue;
+                };
+                self.error(
+                    span,
+                    &format!(
+                        "the name `{}` is defined multiple times (previous definition at {}:{})",
+                        name, previous.line, previous.column
+                    ),
+                );
+            }
+        }
+
+        for item in &program.items {
+            match item {
+                Item::Enum(e) => {
+                    for v in &e.variants {
+                        let key = format!("{}::{}", e.name, v.name);
+                        self.definitions.entry(key).or_insert(v.span);
+                    }
+                }
+                Item::Struct(s) => {
+                    for f in &s.fields {
+                        let key = format!("{}.{}", s.name, f.name);
+                        self.definitions.entry(key).or_insert(f.span);
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.collect_private(program);
+        for (key, &span) in &self.definitions {
+            let name = key.rsplit([':', '.']).next().unwrap_or(key).to_string();
+            self.info.references.push(Reference {
+                span,
+                name,
+                definition: span,
+            });
+        }
+
+        for item in &program.items {
+            match item {
+                Item::Function(f) => {
+                    self.collect_function(f.name.to_string(), None, f);
+                    if f.exported {
+                        self.collect_export(f);
+                    }
+                }
+                Item::Impl(block) => {
+                    let self_ty = self.resolve_type(&TypeExpr::Named(block.self_ty, block.span));
+                    if !matches!(self_ty, Type::Struct(_) | Type::Enum(_))
+                        && self_ty != Type::Unknown
+                    {
+                        self.error(
+                            block.span,
+                            &format!(
+                                "inherent `impl` blocks are only allowed for structs and enums, not `{}`",
+                                self_ty
+                            ),
+                        );
+                    }
+                    for method in &block.methods {
+                        let name = block.qualified(&method.name);
+                        self.collect_function(name, Some(&self_ty), method);
+                    }
+                }
+                Item::Const(c) => {
+                    let ty = self.resolve_type(&c.ty);
+                    self.const_types.insert(c.name.to_string(), ty);
+                }
+                Item::Enum(e) => {
+                    for attr in &e.attrs {
+                        if attr.name == "allow" {
+                            self.check_allow(attr);
+                        } else {
+                            self.error(
+                                attr.span,
+                                &format!("cannot find attribute `{}` in this scope", attr.name),
+                            );
+                        }
+                    }
+                    match self.evaluator.enum_discriminants(&e.name) {
+                        Ok(values) => {
+                            self.info.enums.insert(e.name.to_string(), values);
+                        }
+                        Err(err) => self.errors.push(err),
+                    }
+                }
+                Item::Struct(s) => self.collect_struct(s),
+                Item::Extern(block) => self.collect_extern(block),
+                Item::TypeAlias(alias) => self.collect_type_alias(alias),
+            }
+        }
+
+        for item in &program.items {
+            if let Item::Struct(s) = item {
+                self.struct_layout(&s.name, &mut Vec::new());
+                self.derive(s);
+            }
+        }
+
+        // Pass 2: check bodies and evaluate constants
+        for item in &program.items {
+            match item {
+                Item::Function(f) => self.check_function(&f.name, f),
+                Item::Impl(block) => {
+                    for method in &block.methods {
+                        self.check_function(&block.qualified(&method.name), method);
+                    }
+                }
+                Item::Const(c) => {
+                    let declared = self.const_types[c.name.as_str()].clone();
+                    let actual = self.check_expr(&c.value);
+                    self.expect_type(&declared, &actual, c.value.span);
+                    match self.evaluator.eval_const(&c.name) {
+                        Ok(value) => {
+                            let value =
+                                self.check_const_range(&c.name, &declared, value, c.value.span);
+                            self.info.consts.insert(c.name.to_string(), value);
+                        }
+                        Err(err) => self.errors.push(err),
+                    }
+                }
+                Item::Enum(_) | Item::Struct(_) | Item::Extern(_) | Item::TypeAlias(_) => {}
+            }
+        }
+        // Deferred expressions are checked more than once
+        self.info.references.sort();
+        self.info.references.dedup();
+    }
+
+    /// Record the items, methods and fields of the library that are not
+    /// `pub`; enum variants are as visible as their enum
+    fn collect_private(&mut self, program: &Program) {
+        let mut private = Vec::new();
+        for item in program
+            .items
+            .iter()
+            .filter(|item| program.in_library(item.span()))
+        {
+            match item {
+                Item::Function(f) => private.push((f.name.to_string(), "function", f.vis)),
+                Item::Const(c) => private.push((c.name.to_string(), "constant", c.vis)),
+                Item::Enum(e) => private.push((e.name.to_string(), "enum", e.vis)),
+                Item::Struct(s) => {
+                    private.push((s.name.to_string(), "struct", s.vis));
+                    for f in &s.fields {
+                        private.push((format!("{}.{}", s.name, f.name), "field", f.vis));
+                    }
+                }
+                Item::TypeAlias(alias) => {
+                    let kind = if alias.newtype {
+                        "newtype"
+                    } else {
+                        "type alias"
+                    };
+                    private.push((alias.name.to_string(), kind, alias.vis));
+                }
+                Item::Impl(block) => {
+                    for m in &block.methods {
+                        private.push((block.qualified(&m.name), "method", m.vis));
+                    }
+                }
+                Item::Extern(_) => {}
+            }
+        }
+        for (key, kind, vis) in private {
+            if !vis.is_public() {
+                self.private.insert(key, (kind, vis));
+            }
+        }
+    }
+
+    /// Record the signature of a function; methods take the receiver first
+    fn collect_function(&mut self, name: String, self_ty: Option<&Type>, function: &Function) {
+        let receiver = match (function.receiver, self_ty) {
+            (Some(Receiver::Value | Receiver::MutValue), Some(ty)) => Some(ty.clone()),
+            (Some(Receiver::Ref), Some(ty)) => Some(Type::Ref(false, Box::new(ty.clone()))),
+            (Some(Receiver::RefMut), Some(ty)) => Some(Type::Ref(true, Box::new(ty.clone()))),
+            _ => None,
+        };
+        let params = receiver
+            .into_iter()
+            .chain(function.params.iter().map(|p| self.resolve_type(&p.ty)))
+            .collect::<Vec<_>>();
+        let ret = function
+            .return_type
+            .as_ref()
+            .map(|t| self.resolve_type(t))
+            .unwrap_or(Type::Unit);
+        if let Some(receiver) = function.receiver {
+            self.receivers.insert(name.clone(), receiver);
+        }
+        if function.is_model() {
+            self.models.insert(name.clone());
+        }
+        let bounds = (
+            function
+                .params
+                .iter()
+                .map(|p| p.ty.bounds().to_vec())
+                .collect(),
+            function
+                .return_type
+                .as_ref()
+                .map_or(Vec::new(), |t| t.bounds().to_vec()),
+        );
+        self.signature_bounds.insert(name.clone(), bounds);
+        self.functions.insert(name, (params, ret));
+    }
+
+    /// Record the signatures of the C functions declared in an `extern` block
+    fn collect_extern(&mut self, block: &ExternBlock) {
+        for attr in &block.attrs {
+            if attr.name != "link" {
+                self.error(
+                    attr.span,
+                    &format!("cannot find attribute `{}` in this scope", attr.name),
+                );
+            } else if attr.args.is_empty() {
+                self.error(
+                    attr.span,
+                    "`#[link]` needs a library name, e.g. `#[link(m)]`",
+                );
+            }
+        }
+        self.info.links.extend(block.links().map(str::to_string));
+
+        for function in &block.functions {
+            let mut params = Vec::new();
+            let mut c_params = Some(Vec::new());
+            for param in &function.params {
+                let ty = self.resolve_type(&param.ty);
+                let c_type = self.c_type(&param.ty, &ty, param.span, false);
+                c_params = c_params.zip(c_type).map(|(mut c_params, c_type)| {
+                    c_params.push(c_type);
+                    c_params
+                });
+                params.push(ty);
+            }
+            let (ret, c_ret) = match &function.return_type {
+                Some(ty_expr) => {
+                    let ty = self.resolve_type(ty_expr);
+                    let c_type = self.c_type(ty_expr, &ty, function.span, true);
+                    (ty, c_type)
+                }
+                None => (Type::Unit, Some(CType::Void)),
+            };
+            if let (Some(params), Some(ret)) = (c_params, c_ret) {
+                let signature = Signature { params, ret };
+                self.info
+                    .externs
+                    .insert(function.name.to_string(), signature);
+            }
+            let bounds = (vec![Vec::new(); params.len()], Vec::new());
+            self.signature_bounds
+                .insert(function.name.to_string(), bounds);
+            self.functions
+                .insert(function.name.to_string(), (params, ret));
+        }
+    }
+
+    /// Record the C signature of a function exported to C
+    fn collect_export(&mut self, function: &Function) {
+        let (params, ret) = self.functions[function.name.as_str()].clone();
+        let c_params: Vec<Option<CType>> = function
+            .params
+            .iter()
+            .zip(&params)
+            .map(|(param, ty)| self.c_type(&param.ty, ty, param.span, false))
+            .collect();
+        let c_ret = match &function.return_type {
+            Some(ty_expr) => self.c_type(ty_expr, &ret, function.span, true),
+            None => Some(CType::Void),
+        };
+        if let (Some(params), Some(ret)) = (c_params.into_iter().collect(), c_ret) {
+            let signature = Signature { params, ret };
+            self.info
+                .exports
+                .insert(function.name.to_string(), signature);
+        }
+    }
+
+    /// The C type of an extern function parameter or result of type `ty`
+    fn c_type(
+        &mut self,
+        ty_expr: &TypeExpr,
+        ty: &Type,
+        span: Span,
+        is_return: bool,
+    ) -> Option<CType> {
+        if let TypeExpr::Named(name, _) = ty_expr {
+            if let Some(alias) = self
+                .aliases
+                .get(name.as_str())
+                .filter(|alias| !alias.newtype)
+            {
+                return self.c_type(&alias.ty, ty, span, is_return);
+            }
+        }
+        let c_type = match ty_expr {
+            TypeExpr::Named(name, _) if name != "str" => CType::from_name(name),
+            TypeExpr::Ref {
+                mutable: false,
+                inner,
+            } if !is_return => match &**inner {
+                TypeExpr::Named(name, _) if name == "str" => Some(CType::Str),
+                _ => None,
+            },
+            TypeExpr::Unit | TypeExpr::Never(_) if is_return => Some(CType::Void),
+            _ => None,
+        };
+        if c_type.is_none() && *ty != Type::Unknown {
+            let msg = if is_return {
+                format!(
+                    "`{}` cannot be returned from C; use an integer type, `f64`, `bool` or `()`",
+                    ty
+                )
+            } else {
+                format!(
+                    "`{}` cannot be passed to C; use an integer type, `f64`, `bool` or `&str`",
+                    ty
+                )
+            };
+            self.error(span, &msg);
+        }
+        c_type
+    }
+
+    /// Check what an alias names; a newtype must wrap a scalar type
+    fn collect_type_alias(&mut self, alias: &TypeAlias) {
+        let ty = self.resolve_type(&alias.ty);
+        if !alias.newtype {
+            return;
+        }
+        match ty {
+            Type::Int | Type::Float | Type::Sized(_) | Type::Bool | Type::Str => {
+                self.info.newtypes.insert(alias.name.to_string(), ty);
+            }
+            Type::Unknown => {}
+            other => self.error(
+                alias.span,
+                &format!(
+                    "a newtype wraps a numeric type, `bool` or `str`, not `{}`",
+                    other
+                ),
+            ),
+        }
+    }
+
+    fn collect_struct(&mut self, def: &StructDef) {
+        let mut fields: Vec<(String, Type)> = Vec::new();
+        for field in &def.fields {
+            if fields.iter().any(|(name, _)| *name == field.name) {
+                self.error(
+                    field.span,
+                    &format!("field `{}` is already declared", field.name),
+                );
+                continue;
+            }
+            let ty = self.resolve_type(&field.ty);
+            fields.push((field.name.to_string(), ty));
+        }
+        self.info.structs.insert(def.name.to_string(), fields);
+    }
+
+    /// Compute (and cache) the layout of struct `name`
+    fn struct_layout(&mut self, name: &str, stack: &mut Vec<String>) -> Option<Layout> {
+        if let Some(computed) = self.info.layouts.get(name) {
+            return Some(computed.layout);
+        }
+        if self.recursive.contains(name) {
+            return None;
+        }
+        let def = *self.struct_defs.get(name)?;
+        if let Some(start) = stack.iter().position(|n| n == name) {
+            self.recursive.extend(stack[start..].iter().cloned());
+            self.error(
+                def.span,
+                &format!(
+                    "recursive type `{}` has infinite size (use a reference to break the cycle)",
+                    name
+                ),
+            );
+            return None;
+        }
+
+        stack.push(name.to_string());
+        let fields: Option<Vec<(String, Layout)>> = def
+            .fields
+            .iter()
+            .map(|field| Some((field.name.to_string(), self.layout_of(&field.ty, stack)?)))
+            .collect();
+        stack.pop();
+
+        // Invalid hints were reported by `derive`
+        let repr = layout::Repr::parse(def.repr_hints()).unwrap_or_default();
+        match StructLayout::compute(name, &fields?, repr) {
+            Some(computed) => {
+                let layout = computed.layout;
+                self.info.layouts.insert(name.to_string(), computed);
+                Some(layout)
+            }
+            None => {
+                self.error(def.span, &format!("type `{}` is too big", name));
+                None
+            }
+        }
+    }
+
+    fn layout_of(&mut self, ty: &TypeExpr, stack: &mut Vec<String>) -> Option<Layout> {
+        match ty {
+            TypeExpr::Named(name, _) => {
+                if let Some(primitive) = self.data_layout.primitive(name) {
+                    return Some(primitive);
+                }
+                if name == region::ARENA_TYPE {
+                    return Some(self.data_layout.pointer());
+                }
+                // Laid out like a `Vec`
+                if name == "StringBuf" {
+                    return self.data_layout.pointer().array(4);
+                }
+                // Aligned to their size, as vector registers are loaded
+                if let Some(vector) = simd::vector_type(name) {
+                    return Some(Layout::new(vector.size(), vector.size()));
+                }
+                // A handle to the atomic, which is shared
+                if atomic::atomic_type(name).is_some() {
+                    return Some(self.data_layout.pointer());
+                }
+                if let Some(variants) = self.info.enums.get(name.as_str()) {
+                    let values: Vec<i64> = variants.iter().map(|(_, d)| *d).collect();
+                    return Some(layout::enum_layout(&values));
+                }
+                if let Some(alias) = self.aliases.get(name.as_str()) {
+                    // Cycles were reported when the alias was resolved
+                    if stack.iter().any(|seen| seen == name) {
+                        return None;
+                    }
+                    stack.push(name.to_string());
+                    let layout = self.layout_of(&alias.ty, stack);
+                    stack.pop();
+                    return layout;
+                }
+                self.struct_layout(name, stack)
+            }
+            // Beliefs are boxed, and thread, channel and lock handles and
+            // guards point to shared state; other generic types were
+            // rejected when resolved
+            TypeExpr::Generic { name, .. }
+                if matches!(
+                    name.as_str(),
+                    "Belief" | "Thread" | "Sender" | "Receiver" | "Mutex" | "RwLock"
+                ) || GuardKind::from_type_name(name).is_some() =>
+            {
+                Some(self.data_layout.pointer())
+            }
+            // Buffer, length, capacity and arena
+            TypeExpr::Generic { name, .. } if name == "Vec" || name == "Map" => {
+                self.data_layout.pointer().array(4)
+            }
+            // A tag before the value
+            TypeExpr::Generic { name, args, .. } if name == "Option" && args.len() == 1 => {
+                let value = self.layout_of(&args[0], stack)?;
+                layout::option_layout(value)
+            }
+            // Both ends, either of which may be left out, and whether the
+            // last is included
+            TypeExpr::Generic { name, args, .. } if name == "Range" && args.len() == 1 => {
+                let end = layout::option_layout(self.layout_of(&args[0], stack)?)?;
+                let fields = [
+                    ("start".to_string(), end),
+                    ("end".to_string(), end),
+                    ("inclusive".to_string(), Layout::new(1, 1)),
+                ];
+                StructLayout::compute("Range", &fields, layout::Repr::default()).map(|r| r.layout)
+            }
+            TypeExpr::Generic { .. } => None,
+            TypeExpr::Ref { mutable, inner } => match inner.as_ref() {
+                TypeExpr::Named(name, _) if name == "str" && !*mutable => {
+                    Some(self.data_layout.fat_pointer())
+                }
+                TypeExpr::Slice { .. } => Some(self.data_layout.fat_pointer()),
+                _ => Some(self.data_layout.pointer()),
+            },
+            TypeExpr::Ptr { .. } => Some(self.data_layout.pointer()),
+            TypeExpr::Array { elem, len } => {
+                let elem = self.layout_of(elem, stack)?;
+                // Length errors were reported when the field type was resolved
+                let n = self.evaluator.eval_usize(len, &HashMap::new()).ok()?;
+                let array = elem.array(n as u64);
+                if array.is_none() {
+                    self.error(len.span, "array type is too big");
+                }
+                array
+            }
+            // A view of the elements: where they start and how many there are
+            TypeExpr::Slice { .. } => Some(self.data_layout.fat_pointer()),
+            TypeExpr::Unit | TypeExpr::Never(_) => Some(Layout::new(0, 1)),
+        }
+    }
+
+    /// `#[allow(...)]` names lints to keep quiet on an item, among those
+    /// checked per item ([`lint::ITEM_LINTS`])
+    fn check_allow(&mut self, attr: &Attribute) {
+        if attr.args.is_empty() {
+            self.error(
+                attr.span,
+                "`#[allow]` needs a lint name, e.g. `#[allow(dead_code)]`",
+            );
+        }
+        for name in &attr.args {
+            if !lint::ITEM_LINTS.contains(&name.as_str()) {
+                self.error(
+                    attr.span,
+                    &format!(
+                        "unknown lint `{}` in `#[allow]`; `{}` can be allowed on items",
+                        name,
+                        lint::ITEM_LINTS.join("` and `")
+                    ),
+                );
+            }
+        }
+    }
+
+    fn derive(&mut self, def: &StructDef) {
+        for attr in &def.attrs {
+            if attr.name == "repr" && attr.args.is_empty() {
+                self.error(attr.span, "`#[repr]` needs a hint, e.g. `#[repr(C)]`");
+            } else if attr.name == "allow" {
+                self.check_allow(attr);
+            } else if attr.name != "derive" && attr.name != "repr" {
+                self.error(
+                    attr.span,
+                    &format!("cannot find attribute `{}` in this scope", attr.name),
+                );
+            }
+        }
+
+        let repr_attr = def.attrs.iter().find(|attr| attr.name == "repr");
+        if let (Some(attr), Err(msg)) = (repr_attr, layout::Repr::parse(def.repr_hints())) {
+            self.error(attr.span, &msg);
+        }
+
+        let mut wants_schema = false;
+        for (name, span) in def.derives() {
+            if DERIVES.contains(&name) {
+                wants_schema = true;
+            } else {
+                self.error(span, &format!("cannot find derive macro `{}`", name));
+            }
+        }
+        if !wants_schema {
+            return;
+        }
+
+        let fields = self.info.structs[def.name.as_str()].clone();
+        let mut schema = Schema::new();
+        for ((name, ty), field) in fields.iter().zip(&def.fields) {
+            let field_type = match ty {
+                Type::Int => FieldType::Int,
+                Type::Float | Type::Sized(Numeric::F32) => FieldType::Float,
+                Type::Sized(_) => FieldType::Int,
+                Type::Bool => FieldType::Bool,
+                Type::Str => FieldType::Str,
+                Type::Unknown => continue,
+                other => {
+                    self.error(
+                        field.span,
+                        &format!(
+                            "cannot derive a record schema for `{}`: field `{}` has non-primitive type `{}`",
+                            def.name, name, other
+                        ),
+                    );
+                    continue;
+                }
+            };
+            schema = schema.field(name, field_type);
+        }
+        self.info.schemas.insert(def.name.to_string(), schema);
+    }
+
+    fn check_function(&mut self, name: &str, function: &Function) {
+        let (params, ret) = self.functions[name].clone();
+        let (param_bounds, return_bounds) = self.signature_bounds[name].clone();
+        for attr in &function.attrs {
+            if attr.name == "no_mangle" && attr.args.is_empty() {
+                if !function.exported {
+                    self.error(
+                        attr.span,
+                        "`#[no_mangle]` only applies to `pub extern \"C\"` functions",
+                    );
+                }
+            } else if attr.name == "allow" {
+                self.check_allow(attr);
+            } else if attr.name == "model" && attr.args.is_empty() {
+                if name.contains("::") {
+                    self.error(attr.span, "`#[model]` only applies to free functions");
+                }
+            } else if attr.name != "test" || !attr.args.is_empty() {
+                self.error(
+                    attr.span,
+                    &format!("cannot find attribute `{}` in this scope", attr.name),
+                );
+            } else if name.contains("::") {
+                self.error(attr.span, "`#[test]` only applies to free functions");
+            } else if !params.is_empty() || ret != Type::Unit {
+                self.error(
+                    function.span,
+                    "test functions must take no arguments and return `()`",
+                );
+            }
+        }
+        self.return_type = ret.clone();
+        self.return_bounds = return_bounds;
+        self.tail_positions.clear();
+        tail_positions(self.exprs, &function.body, &mut self.tail_positions);
+        self.probabilistic_depth = usize::from(self.models.contains(name));
+        self.moves.clear();
+        self.resets.clear();
+        self.regions
+            .reset(function.params.iter().map(|p| p.name.to_string()).collect());
+
+        self.scopes.push(HashMap::new());
+        let mut params = params.into_iter();
+        if let Some(receiver) = function.receiver {
+            let ty = params.next().unwrap_or(Type::Unknown);
+            self.declare(
+                Symbol::intern("self"),
+                ty,
+                receiver == Receiver::MutValue,
+                None,
+                function.span,
+            );
+            self.locate(Symbol::intern("self"), Region::Caller);
+        }
+        let mut handles = Vec::new();
+        for (index, ((param, ty), declared)) in function
+            .params
+            .iter()
+            .zip(params)
+            .zip(&param_bounds)
+            .enumerate()
+        {
+            let region = if ty == Type::Arena {
+                handles.push(index);
+                Region::Param(index)
+            } else {
+                Region::Caller
+            };
+            self.declare(param.name, ty, false, None, param.span);
+            self.refine(param.name, Bounds::declared(declared), declared);
+            self.locate(param.name, region);
+        }
+        // With a single arena parameter, results live in that arena
+        self.return_region = match handles.as_slice() {
+            [index] => Some(Region::Param(*index)),
+            _ => None,
+        };
+        let body_type = self.check_block(&function.body);
+        self.scopes.pop();
+
+        if let Some(tail) = &function.body.tail {
+            let known = self.block_bounds.get(&function.body.span).copied();
+            let declared = std::mem::take(&mut self.return_bounds);
+            self.expect_bounds(&declared, known, self.exprs[*tail].span);
+            if let Some(&region) = self.block_regions.get(&function.body.span) {
+                self.expect_returnable(region, self.exprs[*tail].span);
+            }
+        }
+
+        if body_type != Type::Never {
+            let span = function
+                .body
+                .tail
+                .as_ref()
+                .map(|t| self.exprs[*t].span)
+                .unwrap_or(function.body.span);
+            if !ret.compatible(&body_type) && !self.adapt_literal(&ret, span) {
+                self.error(
+                    span,
+                    &format!(
+                        "mismatched types: function `{}` returns `{}` but its body has type `{}`",
+                        name, ret, body_type
+                    ),
+                );
+            }
+            if let Some(tail) = &function.body.tail {
+                self.convert(&ret, &body_type, self.exprs[*tail].span);
+            }
+        }
+    }
+
+    fn check_block(&mut self, block: &Block) -> Type {
+        let mark = self.regions.mark();
+        self.scopes.push(HashMap::new());
+        self.deferred.push(Vec::new());
+        // The first statement of type `!`, after which the rest is unreachable
+        let mut diverges: Option<(usize, Span)> = None;
+        for (i, stmt) in block.stmts.iter().enumerate() {
+            if self.check_stmt(stmt) && diverges.is_none() {
+                diverges = Some((i, stmt.span()));
+            }
+        }
+        if let Some((i, at)) = diverges {
+            let next = block.stmts.get(i + 1).map(Stmt::span);
+            if let Some(next) = next.or(block.tail.map(|tail| self.exprs[tail].span)) {
+                self.info.unreachable.push((next, at));
+            }
+        }
+        let ty = match &block.tail {
+            Some(tail) => self.check_operand(&self.exprs[*tail]),
+            None => Type::Unit,
+        };
+        let ty = if diverges.is_some() { Type::Never } else { ty };
+        if let (Some(tail), Type::Belief(_)) = (&block.tail, &ty) {
+            if let Some(bounds) = self.bounds_of(&self.exprs[*tail]) {
+                self.block_bounds.insert(block.span, bounds);
+            }
+        }
+        if let (Some(tail), true) = (&block.tail, ty.has_region()) {
+            let region = self.region_of(&self.exprs[*tail]);
+            self.block_regions.insert(block.span, region);
+        }
+        let deferred = self.deferred.pop().unwrap_or_default();
+        if ty != Type::Never {
+            // Deferred expressions run latest first once the block is done
+            for (place, span, moved) in deferred.into_iter().rev() {
+                self.check_deferred(&place, span);
+                if moved {
+                    self.moves.push((place, span));
+                }
+            }
+        }
+        self.scopes.pop();
+        // Checkpoints taken in the block end with it
+        self.regions.restore(mark);
+        ty
+    }
+
+    /// Check a statement of a block, returning whether it has type `!`, so
+    /// that control never passes it
+    fn check_stmt(&mut self, stmt: &Stmt) -> bool {
+        match stmt {
+            Stmt::Let(let_stmt) => {
+                let declared = let_stmt.ty.as_ref().map(|t| self.resolve_type(t));
+                let actual = let_stmt.value.as_ref().map(|v| self.check_operand(v));
+                let ty = match (declared, actual) {
+                    (Some(declared), Some(actual)) => {
+                        let span = let_stmt
+                            .value
+                            .as_ref()
+                            .map(|v| v.span)
+                            .unwrap_or(let_stmt.span);
+                        self.expect_type(&declared, &actual, span);
+                        declared
+                    }
+                    (Some(declared), None) => declared,
+                    (None, Some(actual)) if actual.is_partial() => {
+                        self.error(
+                            let_stmt.span,
+                            &format!(
+                                "type annotations needed for `{}`: declare it as `{}` with \
+                                 the element types filled in",
+                                let_stmt.name,
+                                match actual {
+                                    Type::Vec(_) => "Vec<T>",
+                                    Type::Receiver(_) => "Receiver<T>",
+                                    Type::Option(_) => "Option<T>",
+                                    _ => "Map<K, V>",
+                                }
+                            ),
+                        );
+                        actual
+                    }
+                    (None, Some(actual)) => actual,
+                    (None, None) => {
+                        self.error(
+                            let_stmt.span,
+                            &format!("type annotations needed for `{}`", let_stmt.name),
+                        );
+                        Type::Unknown
+                    }
+                };
+                let declared = let_stmt.ty.as_ref().map_or(&[][..], |t| t.bounds());
+                let known = let_stmt.value.as_ref().and_then(|v| self.bounds_of(v));
+                if let Some(value) = &let_stmt.value {
+                    self.expect_bounds(declared, known, value.span);
+                }
+                // Mutable variables only keep what their annotation promises
+                let bounds = match known {
+                    Some(known) if !let_stmt.mutable => Some(known),
+                    _ => Bounds::declared(declared),
+                };
+                let region = match &let_stmt.value {
+                    Some(value) => self.region_of(value),
+                    None => self.regions.frame(),
+                };
+                let diverges = ty == Type::Never;
+                self.declare(let_stmt.name, ty, let_stmt.mutable, None, let_stmt.span);
+                self.refine(let_stmt.name, bounds, declared);
+                self.locate(let_stmt.name, region);
+                diverges
+            }
+            Stmt::Const(item) => {
+                let declared = self.resolve_type(&item.ty);
+                let actual = self.check_expr(&item.value);
+                self.expect_type(&declared, &actual, item.value.span);
+                let value = match self.evaluator.eval_expr(&item.value, &self.local_consts()) {
+                    Ok(value) => Some(value),
+                    Err(err) => {
+                        self.errors.push(err);
+                        None
+                    }
+                };
+                self.declare(item.name, declared, false, value, item.span);
+                false
+            }
+            Stmt::Expr(expr) => self.check_expr(expr) == Type::Never,
+            Stmt::Defer(expr) => {
+                // Checked here, where its names resolve, but its moves only
+                // happen when the block exits
+                let moved = self.moves.len();
+                let outer = self.deferring.replace(Vec::new());
+                self.check_expr(expr);
+                let used = std::mem::replace(&mut self.deferring, outer).unwrap_or_default();
+                let moves = self.moves.split_off(moved);
+                if let Some(deferred) = self.deferred.last_mut() {
+                    // Stored so that, walked latest first, uses precede moves
+                    deferred.extend(moves.into_iter().map(|(place, span)| (place, span, true)));
+                    deferred.extend(used.into_iter().map(|(place, span)| (place, span, false)));
+                }
+                false
+            }
+        }
+    }
+
+    fn check_expr(&mut self, expr: &Expr) -> Type {
+        let ty = self.check_expr_kind(expr);
+        if matches!(&self.probe, Some((at, probe)) if *at == expr.span && probe.ty.is_none()) {
+            let mut locals: Vec<(String, Type)> = Vec::new();
+            for scope in self.scopes.iter().rev() {
+                let mut names: Vec<_> = scope.iter().collect();
+                names.sort_by_key(|(name, _)| name.as_str());
+                for (name, local) in names {
+                    if !locals.iter().any(|(seen, _)| seen == name) {
+                        locals.push((name.to_string(), local.ty.clone()));
+                    }
+                }
+            }
+            self.probe = Some((
+                expr.span,
+                Probe {
+                    ty: Some(ty.clone()),
+                    locals,
+                },
+            ));
+        }
+        ty
+    }
+
+    fn check_expr_kind(&mut self, expr: &Expr) -> Type {
+        match &expr.kind {
+            ExprKind::Int(n, suffix) => self.check_int(i128::from(*n), *suffix, expr.span),
+            ExprKind::Float(_, suffix) => self.check_float(*suffix, expr.span),
+            ExprKind::Str(_) => Type::Str,
+            ExprKind::Bool(_) => Type::Bool,
+            ExprKind::Path(segments) => self.check_path(segments, expr.span),
+            // A negative literal is checked as one
+            ExprKind::Unary(UnaryOp::Neg, operand)
+                if matches!(
+                    self.exprs[*operand].kind,
+                    ExprKind::Int(..) | ExprKind::Float(..)
+                ) =>
+            {
+                match self.exprs[*operand].kind {
+                    ExprKind::Int(n, suffix) => self.check_int(-i128::from(n), suffix, expr.span),
+                    _ => self.check_float(None, expr.span),
+                }
+            }
+            ExprKind::Unary(op, operand) => {
+                let ty = self.check_expr(&self.exprs[*operand]);
+                match op {
+                    UnaryOp::Neg if self.representation(&ty).is_numeric() => {
+                        if let Type::Sized(numeric) = *self.representation(&ty) {
+                            if !numeric.is_signed() && !numeric.is_float() {
+                                self.error(
+                                    expr.span,
+                                    &format!("cannot negate a value of unsigned type `{}`", ty),
+                                );
+                            }
+                            self.info.numeric.insert(expr.span, numeric);
+                        }
+                        ty
+                    }
+                    UnaryOp::Not if ty.compatible(&Type::Bool) => Type::Bool,
+                    UnaryOp::Ref => {
+                        self.check_packed_borrow(&self.exprs[*operand], expr.span);
+                        Type::Ref(false, Box::new(ty))
+                    }
+                    UnaryOp::RefMut => {
+                        self.check_packed_borrow(&self.exprs[*operand], expr.span);
+                        if self.slicings.contains(&self.exprs[*operand].span) {
+                            self.error(
+                                expr.span,
+                                "cannot borrow a slice as mutable; borrow the whole array",
+                            );
+                        } else if !self.is_mutable_place(&self.exprs[*operand]) {
+                            self.error(
+                                expr.span,
+                                "cannot borrow as mutable: the value is not declared `mut`",
+                            );
+                        }
+                        Type::Ref(true, Box::new(ty))
+                    }
+                    UnaryOp::Deref => match &ty {
+                        Type::Ptr(mutable, inner) => {
+                            self.require_unsafe(
+                                expr.span,
+                                &format!("dereference of raw pointer `{}`", ty),
+                            );
+                            if *mutable {
+                                self.writable_derefs.insert(expr.span);
+                            }
+                            (**inner).clone()
+                        }
+                        Type::Unknown => Type::Unknown,
+                        Type::Ref(..) => {
+                            self.error(
+                                expr.span,
+                                &format!(
+                                    "`*` dereferences raw pointers, not `{}`; references are \
+                                     dereferenced automatically",
+                                    ty
+                                ),
+                            );
+                            Type::Unknown
+                        }
+                        _ => {
+                            self.error(expr.span, &format!("type `{}` cannot be dereferenced", ty));
+                            Type::Unknown
+                        }
+                    },
+                    _ => {
+                        self.error(
+                            expr.span,
+                            &format!("cannot apply unary operator to type `{}`", ty),
+                        );
+                        Type::Unknown
+                    }
+                }
+            }
+            ExprKind::Binary(op, lhs, rhs) => {
+                let (lhs, rhs) = (&self.exprs[*lhs], &self.exprs[*rhs]);
+                let left = self.check_expr(lhs);
+                let right = self.check_expr(rhs);
+                // An unsuffixed literal takes the sized type of the other side
+                let left = self.literal_operand(left, &right, lhs.span);
+                let right = self.literal_operand(right, &left, rhs.span);
+                let ty = self.check_binary(*op, &left, &right, expr.span);
+                if let Type::Sized(numeric) = *self.representation(&ty) {
+                    self.info.numeric.insert(expr.span, numeric);
+                } else if !op.is_comparison()
+                    && !op.is_logical()
+                    && self.literals.contains_key(&lhs.span)
+                    && self.literals.contains_key(&rhs.span)
+                {
+                    let literal = Literal::Arithmetic(lhs.span, rhs.span);
+                    self.literals.insert(expr.span, literal);
+                }
+                ty
+            }
+            ExprKind::Cast(value, target) => {
+                let ty = self.check_expr(&self.exprs[*value]);
+                let target = self.resolve_type(target);
+                self.check_cast(&ty, &target, expr.span)
+            }
+            ExprKind::Assign(target, value) => {
+                let value_ty = self.check_operand(&self.exprs[*value]);
+                // Assigning a whole variable re-initializes it, even if moved
+                let target_ty = match self.place(&self.exprs[*target]) {
+                    Some(place) if place.path.is_empty() => {
+                        self.refer_place(&self.exprs[*target]);
+                        self.check_not_lent(place.local, &place.name, self.exprs[*target].span);
+                        self.resets.retain(|(local, _)| *local != place.local);
+                        place.ty
+                    }
+                    _ => self.check_expr(&self.exprs[*target]),
+                };
+                if !self.is_mutable_place(&self.exprs[*target]) {
+                    match self.place(&self.exprs[*target]).filter(|p| p.behind_ref) {
+                        None if matches!(
+                            self.exprs[*target].kind,
+                            ExprKind::Unary(UnaryOp::Deref, _)
+                        ) =>
+                        {
+                            self.error(
+                                expr.span,
+                                "cannot assign through a `*const` pointer (make it `*mut`)",
+                            )
+                        }
+                        Some(place) => self.error(
+                            expr.span,
+                            &format!(
+                                "cannot assign to `{}`, which is behind a `&` reference",
+                                place.display()
+                            ),
+                        ),
+                        None => self.error(
+                            expr.span,
+                            "cannot assign twice to immutable variable (consider `let mut`)",
+                        ),
+                    }
+                }
+                if self.slicings.contains(&self.exprs[*target].span) {
+                    self.error(
+                        expr.span,
+                        "cannot assign to a slice; assign to its elements one at a time",
+                    );
+                }
+                self.expect_type(&target_ty, &value_ty, self.exprs[*value].span);
+                if let ExprKind::Path(segments) = &self.exprs[*target].kind {
+                    if let [name] = segments.as_slice() {
+                        let declared = self.lookup(*name).map(|l| l.declared.clone());
+                        let known = self.bounds_of(&self.exprs[*value]);
+                        self.expect_bounds(
+                            &declared.unwrap_or_default(),
+                            known,
+                            self.exprs[*value].span,
+                        );
+                    }
+                }
+                let target_local = self
+                    .root_local(&self.exprs[*target])
+                    .and_then(|name| self.lookup(name));
+                if let (Some(local), true) = (target_local, value_ty.has_region()) {
+                    let region = self.region_of(&self.exprs[*value]);
+                    if !self.regions.outlives(region, local.region) {
+                        self.error(
+                            self.exprs[*value].span,
+                            &format!(
+                                "value living in {} cannot be stored in `{}`, which outlives it",
+                                self.regions.describe(region),
+                                self.root_local(&self.exprs[*target])
+                                    .map_or("", Symbol::as_str)
+                            ),
+                        );
+                    }
+                }
+                if let Some(place) = self.place(&self.exprs[*target]).filter(|p| !p.behind_ref) {
+                    self.moves.retain(|(moved, _)| {
+                        !(moved.local == place.local && moved.path.starts_with(&place.path))
+                    });
+                }
+                Type::Unit
+            }
+            ExprKind::Call(callee, args) => self.check_call(&self.exprs[*callee], args, expr.span),
+            ExprKind::MethodCall(receiver, method, args) => {
+                self.check_method_call(&self.exprs[*receiver], method, args, expr.span)
+            }
+            ExprKind::Distribution(name, args) => {
+                let arg_types: Vec<Type> = args.iter().map(|a| self.check_expr(a)).collect();
+                let Some((params, belief)) = builtins::distribution(name) else {
+                    self.error(
+                        expr.span,
+                        &format!(
+                            "unknown distribution `{}` (expected `Normal` or `Bernoulli`)",
+                            name
+                        ),
+                    );
+                    return Type::Unknown;
+                };
+                if params.len() != args.len() {
+                    self.error(
+                        expr.span,
+                        &format!(
+                            "`{}` takes {} parameters but {} were supplied",
+                            name,
+                            params.len(),
+                            args.len()
+                        ),
+                    );
+                } else {
+                    for ((param, arg_ty), arg) in params.iter().zip(&arg_types).zip(args) {
+                        self.expect_type(param, arg_ty, arg.span);
+                    }
+                }
+                belief
+            }
+            ExprKind::Index(base, index) => {
+                let base_ty = self.check_expr(&self.exprs[*base]);
+                let index_ty = self.check_expr(&self.exprs[*index]);
+                // Indexing with a range selects a slice of the elements
+                let slicing = matches!(index_ty, Type::Range(_));
+                if !slicing {
+                    self.expect_integer(&index_ty, self.exprs[*index].span);
+                }
+                match auto_deref(&base_ty) {
+                    Type::Array(elem, _) | Type::Vec(elem) | Type::Slice(elem) if slicing => {
+                        self.slicings.insert(expr.span);
+                        Type::Slice(elem.clone())
+                    }
+                    Type::Array(elem, _) | Type::Vec(elem) | Type::Slice(elem) => (**elem).clone(),
+                    Type::Unknown => Type::Unknown,
+                    Type::Any => {
+                        self.opaque_any(expr.span, "cannot index into");
+                        Type::Unknown
+                    }
+                    other => {
+                        self.error(
+                            expr.span,
+                            &format!("cannot index into a value of type `{}`", other),
+                        );
+                        Type::Unknown
+                    }
+                }
+            }
+            ExprKind::Field(base, field) => {
+                if let Some(place) = self.place(expr) {
+                    self.refer_place(expr);
+                    self.check_not_moved(&place, expr.span);
+                    self.check_not_lent(place.local, &place.name, expr.span);
+                    self.check_not_reset(place.local, &place.name, expr.span);
+                    return place.ty;
+                }
+                let base_ty = self.check_expr(&self.exprs[*base]);
+                match auto_deref(&base_ty) {
+                    Type::Unknown => Type::Unknown,
+                    Type::Any => {
+                        self.opaque_any(expr.span, &format!("cannot access field `{}` of", field));
+                        Type::Unknown
+                    }
+                    ty => match self.field_type(ty, field) {
+                        Some(field_ty) => {
+                            self.refer(field, expr.span, &format!("{}.{}", ty, field));
+                            field_ty
+                        }
+                        None => {
+                            self.error(
+                                expr.span,
+                                &format!("no field `{}` on type `{}`", field, ty),
+                            );
+                            Type::Unknown
+                        }
+                    },
+                }
+            }
+            ExprKind::StructLit(name, fields) => self.check_struct_literal(name, fields, expr.span),
+            ExprKind::Array(elements) => {
+                let mut elem_ty = Type::Unknown;
+                for element in elements {
+                    let ty = self.check_operand(element);
+                    if elem_ty == Type::Unknown {
+                        elem_ty = ty;
+                    } else {
+                        self.expect_type(&elem_ty, &ty, element.span);
+                    }
+                }
+                if !elements.is_empty()
+                    && elements.iter().all(|e| self.literals.contains_key(&e.span))
+                {
+                    let spans = elements.iter().map(|e| e.span).collect();
+                    self.literals
+                        .insert(expr.span, Literal::Array(spans, elements.len()));
+                }
+                Type::Array(Box::new(elem_ty), elements.len())
+            }
+            ExprKind::ArrayRepeat(value, len) => {
+                let elem_ty = self.check_expr(&self.exprs[*value]);
+                if !elem_ty.is_copy() {
+                    self.error(
+                        self.exprs[*value].span,
+                        &format!(
+                            "`[value; N]` requires a copyable element type, found `{}`",
+                            elem_ty
+                        ),
+                    );
+                }
+                let len_ty = self.check_expr(&self.exprs[*len]);
+                self.expect_integer(&len_ty, self.exprs[*len].span);
+                match self
+                    .evaluator
+                    .eval_usize(&self.exprs[*len], &self.local_consts())
+                {
+                    Ok(n) => {
+                        let value = &self.exprs[*value];
+                        if self.literals.contains_key(&value.span) {
+                            self.literals
+                                .insert(expr.span, Literal::Array(vec![value.span], n));
+                        }
+                        Type::Array(Box::new(elem_ty), n)
+                    }
+                    Err(err) => {
+                        self.errors.push(err);
+                        Type::Unknown
+                    }
+                }
+            }
+            ExprKind::If(cond, then_block, else_branch) => {
+                let cond_ty = self.check_expr(&self.exprs[*cond]);
+                self.expect_type(&Type::Bool, &cond_ty, self.exprs[*cond].span);
+                let then_ty = self.check_block(then_block);
+                match else_branch {
+                    Some(else_expr) => {
+                        let else_ty = self.check_expr(&self.exprs[*else_expr]);
+                        if then_ty == Type::Never {
+                            else_ty
+                        } else if else_ty == Type::Never {
+                            then_ty
+                        } else {
+                            if !then_ty.compatible(&else_ty) {
+                                self.error(
+                                    self.exprs[*else_expr].span,
+                                    &format!(
+                                        "`if` and `else` have incompatible types: `{}` and `{}`",
+                                        then_ty, else_ty
+                                    ),
+                                );
+                            }
+                            then_ty
+                        }
+                    }
+                    None => {
+                        if !Type::Unit.compatible(&then_ty) {
+                            self.error(
+                                expr.span,
+                                &format!(
+                                    "`if` without `else` must have type `()`, found `{}`",
+                                    then_ty
+                                ),
+                            );
+                        }
+                        Type::Unit
+                    }
+                }
+            }
+            ExprKind::While(cond, body) => {
+                self.loop_depth += 1;
+                let cond_ty = self.check_expr(&self.exprs[*cond]);
+                self.expect_type(&Type::Bool, &cond_ty, self.exprs[*cond].span);
+                self.check_block(body);
+                self.loop_depth -= 1;
+                // Without `break`, only `return` or a panic ends `while true`
+                if let ExprKind::Bool(true) = self.exprs[*cond].kind {
+                    Type::Never
+                } else {
+                    Type::Unit
+                }
+            }
+            ExprKind::For(name, iterable, body) => {
+                let item = self.check_iteration(&self.exprs[*iterable]);
+                let (source, _) = self.exprs[*iterable].iteration(self.exprs);
+                let region = self.region_of(source);
+                self.loop_depth += 1;
+                self.scopes.push(HashMap::new());
+                self.declare(*name, item, false, None, expr.span);
+                self.locate(*name, region);
+                self.check_block(body);
+                self.scopes.pop();
+                self.loop_depth -= 1;
+                Type::Unit
+            }
+            ExprKind::Match(scrutinee, arms) => {
+                self.check_match(&self.exprs[*scrutinee], arms, expr.span)
+            }
+            ExprKind::Range(start, end, _) => self.check_range(*start, *end),
+            ExprKind::Block(block) => self.check_block(block),
+            ExprKind::Unsafe(block) => {
+                self.unsafe_depth += 1;
+                let ty = self.check_block(block);
+                self.unsafe_depth -= 1;
+                ty
+            }
+            ExprKind::Arena(name, allocator, block) => {
+                let lent = self.lent.len();
+                if let Some(allocator) = allocator {
+                    self.check_allocator(&self.exprs[*allocator], expr.span);
+                }
+                let arena = self.regions.enter(name.as_deref(), expr.span);
+                self.scopes.push(HashMap::new());
+                if let Some(name) = name {
+                    self.declare(*name, Type::Arena, false, None, expr.span);
+                    self.locate(*name, arena);
+                }
+                let ty = self.check_block(block);
+                self.scopes.pop();
+                if let (Some(tail), Some(&region)) =
+                    (&block.tail, self.block_regions.get(&block.span))
+                {
+                    if self.regions.outlives(arena, region) {
+                        self.error(
+                            self.exprs[*tail].span,
+                            &format!(
+                                "value allocated in {} escapes its arena",
+                                self.regions.describe(region)
+                            ),
+                        );
+                    }
+                }
+                self.regions.exit();
+                self.lent.truncate(lent);
+                ty
+            }
+            ExprKind::Return(value) => {
+                if self.deferring.is_some() {
+                    self.error(expr.span, "cannot `return` from a deferred expression");
+                }
+                let ty = match value {
+                    Some(value) => self.check_operand(&self.exprs[*value]),
+                    None => Type::Unit,
+                };
+                // Returning runs every pending deferred expression
+                let pending: Vec<_> = self.deferred.iter().flatten().rev().cloned().collect();
+                for (place, span, _) in pending {
+                    self.check_deferred(&place, span);
+                }
+                if let Some(value) = value {
+                    let declared = self.return_bounds.clone();
+                    let known = self.bounds_of(&self.exprs[*value]);
+                    self.expect_bounds(&declared, known, self.exprs[*value].span);
+                    if ty.has_region() {
+                        let region = self.region_of(&self.exprs[*value]);
+                        self.expect_returnable(region, self.exprs[*value].span);
+                    }
+                }
+                let expected = self.return_type.clone();
+                let adapted = value
+                    .is_some_and(|value| self.adapt_literal(&expected, self.exprs[value].span));
+                if !expected.compatible(&ty) && !adapted {
+                    self.error(
+                        expr.span,
+                        &format!(
+                            "mismatched types: expected return type `{}`, found `{}`",
+                            expected, ty
+                        ),
+                    );
+                }
+                if let Some(value) = value {
+                    self.convert(&expected, &ty, self.exprs[*value].span);
+                }
+                Type::Never
+            }
+            ExprKind::Become(call) => {
+                self.check_become(&self.exprs[*call], expr.span);
+                Type::Never
+            }
+            ExprKind::Asm(template, operands) => {
+                self.check_asm(template, operands, expr.span);
+                Type::Unit
+            }
+        }
+    }
+
+    /// Validate `asm!` operands against their register classes and the
+    /// placeholders of the template
+    fn check_asm(&mut self, template: &str, operands: &[AsmOperand], span: Span) {
+        for operand in operands {
+            let ty = self.check_expr(&operand.expr);
+            let fits = match operand.class.as_str() {
+                "reg" => match self.representation(&ty) {
+                    Type::Int | Type::Bool => true,
+                    Type::Sized(numeric) => !numeric.is_float(),
+                    _ => false,
+                },
+                "freg" => matches!(
+                    self.representation(&ty),
+                    Type::Float | Type::Sized(Numeric::F32)
+                ),
+                class => {
+                    self.error(
+                        operand.span,
+                        &format!(
+                            "unknown register class `{}` (expected `reg` or `freg`)",
+                            class
+                        ),
+                    );
+                    continue;
+                }
+            };
+            if !fits && ty != Type::Unknown {
+                self.error(
+                    operand.expr.span,
+                    &format!(
+                        "a value of type `{}` cannot be passed in a `{}` register",
+                        ty, operand.class
+                    ),
+                );
+            }
+            if operand.dir.writes() && !self.is_mutable_place(&operand.expr) {
+                let what = match self.place(&operand.expr) {
+                    Some(place) => format!("`{}`, which is not mutable", place.display()),
+                    None => "an expression that is not a place".to_string(),
+                };
+                self.error(
+                    operand.span,
+                    &format!(
+                        "`{}` operand must be a mutable place, found {}",
+                        operand.dir.keyword(),
+                        what
+                    ),
+                );
+            }
+        }
+
+        let used = match template_operands(template) {
+            Ok(used) => used,
+            Err(msg) => return self.error(span, &msg),
+        };
+        if let Some(&index) = used.iter().find(|&&index| index >= operands.len()) {
+            return self.error(
+                span,
+                &format!(
+                    "invalid reference to operand {{{}}}: there {} {} operand{}",
+                    index,
+                    if operands.len() == 1 { "is" } else { "are" },
+                    operands.len(),
+                    if operands.len() == 1 { "" } else { "s" }
+                ),
+            );
+        }
+        for (index, operand) in operands.iter().enumerate() {
+            if !used.contains(&index) {
+                self.error(
+                    operand.span,
+                    &format!("operand {{{}}} is never used in the template", index),
+                );
+            }
+        }
+    }
+
+    fn check_path(&mut self, segments: &[Symbol], span: Span) -> Type {
+        if let [name] = segments {
+            self.refer_local(*name, span);
+            if let Some(local) = self.lookup(*name) {
+                let place = Place {
+                    local: local.id,
+                    name: name.to_string(),
+                    path: Vec::new(),
+                    ty: local.ty.clone(),
+                    behind_ref: false,
+                    loop_depth: local.loop_depth,
+                };
+                self.check_not_moved(&place, span);
+                self.check_not_lent(place.local, &place.name, span);
+                self.check_not_reset(place.local, &place.name, span);
+                return place.ty;
+            }
+            self.refer(name, span, name);
+            if let Some(ty) = self.const_types.get(name.as_str()) {
+                return ty.clone();
+            }
+            if let Some((params, ret)) = self.functions.get(name.as_str()) {
+                return Type::Fn(params.clone(), Box::new(ret.clone()));
+            }
+            if let Some((params, ret)) = builtins::signature(name) {
+                return Type::Fn(params, Box::new(ret));
+            }
+            if name == "None" {
+                return Type::Option(Box::new(Type::Unknown));
+            }
+            if builtins::is_belief_op(name) {
+                self.error(
+                    span,
+                    &format!("belief operator `{}` can only be called directly", name),
+                );
+                return Type::Unknown;
+            }
+            if builtins::is_int_op(name) {
+                self.error(
+                    span,
+                    &format!("integer operation `{}` can only be called directly", name),
+                );
+                return Type::Unknown;
+            }
+            let candidates = self.value_names();
+            self.unresolved("value", name, span, &candidates);
+            return Type::Unknown;
+        }
+
+        let qualified = join_path(segments);
+        if let [ty, member] = segments {
+            self.refer(ty, span, ty);
+            self.refer(member, span, &qualified);
+        }
+        if let Some((params, ret)) = self.functions.get(&qualified) {
+            return Type::Fn(params.clone(), Box::new(ret.clone()));
+        }
+        if let Some((params, ret)) = builtins::signature(&qualified) {
+            return Type::Fn(params, Box::new(ret));
+        }
+
+        if let [enum_name, variant] = segments {
+            if let Some(variants) = self.info.enums.get(enum_name.as_str()) {
+                if variants.iter().any(|(v, _)| v == variant) {
+                    return Type::Enum(enum_name.to_string());
+                }
+                let message = format!("no variant `{}` in enum `{}`", variant, enum_name);
+                let names: Vec<&str> = variants.iter().map(|(v, _)| v.as_str()).collect();
+                let diagnostic = match suggest::closest(variant, names) {
+                    Some(closest) => {
+                        let path = format!("{}::{}", enum_name, closest);
+                        Diagnostic::new(span, format!("{} (did you mean `{}`?)", message, path))
+                            .with_fix(replace_path(span, &qualified, path))
+                    }
+                    None => Diagnostic::new(span, message),
+                };
+                self.errors.push(diagnostic);
+                return Type::Unknown;
+            }
+            if self.enum_names.iter().any(|name| name == enum_name) {
+                // Discriminant evaluation failed; already reported
+                return Type::Enum(enum_name.to_string());
+            }
+        }
+
+        let candidates = self.value_names();
+        self.unresolved("value", &qualified, span, &candidates);
+        Type::Unknown
+    }
+
+    fn check_binary(&mut self, op: BinaryOp, left: &Type, right: &Type, span: Span) -> Type {
+        if op.is_logical() {
+            // Both are checked by the operator itself
+            for operand in [left, right] {
+                if *operand != Type::Any {
+                    self.expect_type(&Type::Bool, operand, span);
+                }
+            }
+            return Type::Bool;
+        }
+
+        if !left.compatible(right) {
+            self.error(
+                span,
+                &format!(
+                    "mismatched types: cannot apply `{}` to `{}` and `{}`",
+                    op.symbol(),
+                    left,
+                    right
+                ),
+            );
+            return if op.is_comparison() {
+                Type::Bool
+            } else {
+                Type::Unknown
+            };
+        }
+
+        if op.is_comparison() {
+            let ordered =
+                matches!(op, BinaryOp::Eq | BinaryOp::Ne) || self.representation(left).is_numeric();
+            if !ordered {
+                self.error(
+                    span,
+                    &format!(
+                        "cannot compare values of type `{}` with `{}`",
+                        left,
+                        op.symbol()
+                    ),
+                );
+            }
+            return Type::Bool;
+        }
+
+        // Vectors are added, subtracted, multiplied and divided lane by lane
+        if matches!(left, Type::Simd(_)) && op != BinaryOp::Rem {
+            return left.clone();
+        }
+        if !self.representation(left).is_numeric() {
+            self.error(
+                span,
+                &format!("cannot apply `{}` to type `{}`", op.symbol(), left),
+            );
+            return Type::Unknown;
+        }
+        // The operands of the operator must match at run time
+        if matches!(left, Type::Unknown | Type::Any) {
+            right.clone()
+        } else {
+            left.clone()
+        }
+    }
+
+    fn check_call(&mut self, callee: &Expr, args: &[Expr], span: Span) -> Type {
+        if let ExprKind::Path(segments) = &callee.kind {
+            if let [name] = segments.as_slice() {
+                if self
+                    .aliases
+                    .get(name.as_str())
+                    .is_some_and(|alias| alias.newtype)
+                    && self.lookup(*name).is_none()
+                {
+                    return self.check_newtype_constructor(name, callee.span, args, span);
+                }
+                if self.info.externs.contains_key(name.as_str()) && self.lookup(*name).is_none() {
+                    self.require_unsafe(span, &format!("call to C function `{}`", name));
+                }
+                let shadowed =
+                    self.lookup(*name).is_some() || self.functions.contains_key(name.as_str());
+                if self.models.contains(name.as_str()) && self.lookup(*name).is_none() {
+                    self.require_probabilistic(span, &format!("call to model `{}`", name));
+                }
+                if builtins::is_belief_op(name) && !shadowed {
+                    if name == "observe" {
+                        self.require_probabilistic(span, "`observe`");
+                    }
+                    // Its argument is sampled, and may observe
+                    let sampled = usize::from(name == "expect");
+                    self.probabilistic_depth += sampled;
+                    let arg_types: Vec<Type> = args.iter().map(|a| self.check_expr(a)).collect();
+                    self.probabilistic_depth -= sampled;
+                    return match builtins::belief_op(name, &arg_types) {
+                        Ok(ty) => ty,
+                        Err(msg) => {
+                            self.error(span, &msg);
+                            Type::Unknown
+                        }
+                    };
+                }
+                if builtins::is_int_op(name) && !shadowed {
+                    return self.check_int_op(name, args, span);
+                }
+                if name == "Some" && !shadowed {
+                    return self.check_some(args, span);
+                }
+            }
+            if join_path(segments) == "thread::spawn"
+                && !self.functions.contains_key("thread::spawn")
+            {
+                return self.check_spawn(args, span);
+            }
+            let path = join_path(segments);
+            if matches!(path.as_str(), "Mutex::new" | "RwLock::new")
+                && !self.functions.contains_key(&path)
+            {
+                return self.check_lock_constructor(&segments[0], args, span);
+            }
+            if matches!(path.as_str(), "ptr::from_ref" | "ptr::from_mut")
+                && !self.functions.contains_key(&path)
+            {
+                return self.check_ptr_from(path == "ptr::from_mut", args, span);
+            }
+            if join_path(segments) == "atomic::fence" {
+                self.check_orderings("fence", args);
+            }
+        }
+
+        let callee_ty = self.check_expr(callee);
+        let arg_types: Vec<Type> = args.iter().map(|a| self.check_expr(a)).collect();
+
+        match callee_ty {
+            Type::Fn(params, ret) => {
+                if params.len() != args.len() {
+                    self.error(
+                        span,
+                        &format!(
+                            "this function takes {} arguments but {} were supplied",
+                            params.len(),
+                            args.len()
+                        ),
+                    );
+                } else {
+                    for ((param, arg_ty), arg) in params.iter().zip(&arg_types).zip(args) {
+                        self.expect_type(param, arg_ty, arg.span);
+                        // Builtins taking any type only borrow their argument,
+                        // except that a guard is released once passed
+                        if *param != Type::Unknown || matches!(arg_ty, Type::Guard(..)) {
+                            self.move_out(arg, arg_ty);
+                        }
+                    }
+                    if let Some((param_bounds, _)) = self.callee_bounds(callee) {
+                        self.expect_arg_bounds(&param_bounds, args);
+                    }
+                    let args: Vec<&Expr> = args.iter().collect();
+                    self.check_stored_through(&params, &args);
+                }
+                *ret
+            }
+            Type::Unknown => Type::Unknown,
+            other => {
+                self.error(span, &format!("expected function, found `{}`", other));
+                Type::Unknown
+            }
+        }
+    }
+
+    /// `become f(args)`, a call that reuses the caller's frame, so nothing
+    /// may be left for the caller to do once it starts: it must be in tail
+    /// position, outside arenas and deferred expressions, call a Solo
+    /// function returning what the caller does, and pass it nothing living
+    /// in the caller's frame
+    fn check_become(&mut self, call: &Expr, span: Span) {
+        let ExprKind::Call(callee, args) = &call.kind else {
+            return;
+        };
+        let ret = self.check_call(&self.exprs[*callee], args, call.span);
+        if self.deferring.is_some() {
+            self.error(span, "cannot `become` from a deferred expression");
+        } else if !self.tail_positions.contains(&span) {
+            self.error(
+                span,
+                "`become` must be in tail position, where the call's result is the function's",
+            );
+        } else if self.regions.allocation() != Region::Static {
+            let arena = self.regions.describe(self.regions.allocation());
+            self.error(
+                span,
+                &format!(
+                    "cannot `become` inside {}, which is freed after the call returns",
+                    arena
+                ),
+            );
+        } else if self.deferred.iter().any(|deferred| !deferred.is_empty()) {
+            self.error(
+                span,
+                "cannot `become` with deferred expressions pending: they run after the call returns",
+            );
+        }
+
+        let target = match &self.exprs[*callee].kind {
+            ExprKind::Path(segments)
+                if segments.len() > 1 || self.lookup(segments[0]).is_none() =>
+            {
+                let path = join_path(segments);
+                match self.functions.get(&path) {
+                    Some((params, _)) if !self.info.externs.contains_key(&path) => {
+                        Some((path, params.clone()))
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        let Some((path, params)) = target else {
+            self.error(
+                call.span,
+                "`become` needs a Solo function called by name, which it can jump to",
+            );
+            return;
+        };
+        if ret != self.return_type && !matches!(ret, Type::Never | Type::Unknown) {
+            self.error(
+                call.span,
+                &format!(
+                    "`become` calls `{}`, which returns `{}`, from a function returning `{}`",
+                    path, ret, self.return_type
+                ),
+            );
+        }
+        for (param, arg) in params.iter().zip(args) {
+            let region = self.region_of(arg);
+            if param.has_region()
+                && !matches!(region, Region::Static | Region::Caller | Region::Param(_))
+            {
+                self.error(
+                    arg.span,
+                    &format!(
+                        "cannot pass a value living in {} to a tail call, which reuses its frame",
+                        self.regions.describe(region)
+                    ),
+                );
+            }
+        }
+    }
+
+    /// `thread::spawn(f, args...)`, which calls `f(args...)` on a new thread.
+    /// The arguments move to that thread and the result moves back when it
+    /// is joined, so both must be sendable (see [`Self::not_send`])
+    fn check_spawn(&mut self, args: &[Expr], span: Span) -> Type {
+        let Some((function, args)) = args.split_first() else {
+            self.error(
+                span,
+                "`thread::spawn` takes a function followed by its arguments",
+            );
+            return Type::Unknown;
+        };
+        let function_ty = self.check_operand(function);
+        let arg_types: Vec<Type> = args.iter().map(|a| self.check_expr(a)).collect();
+        let (params, ret) = match function_ty {
+            Type::Fn(params, ret) => (params, *ret),
+            Type::Unknown => return Type::Thread(Box::new(Type::Unknown)),
+            other => {
+                self.error(
+                    function.span,
+                    &format!(
+                        "expected a function to run on the thread, found `{}`",
+                        other
+                    ),
+                );
+                return Type::Unknown;
+            }
+        };
+        if params.len() != args.len() {
+            self.error(
+                span,
+                &format!(
+                    "the spawned function takes {} arguments but {} were supplied",
+                    params.len(),
+                    args.len()
+                ),
+            );
+        } else {
+            for ((param, arg_ty), arg) in params.iter().zip(&arg_types).zip(args) {
+                self.expect_type(param, arg_ty, arg.span);
+                self.move_out(arg, arg_ty);
+                if let Some(reason) = self.not_send(param, &mut Vec::new()) {
+                    self.error(
+                        arg.span,
+                        &format!("`{}` cannot be sent to another thread: {}", param, reason),
+                    );
+                }
+            }
+        }
+        if let Some(reason) = self.not_send(&ret, &mut Vec::new()) {
+            self.error(
+                function.span,
+                &format!(
+                    "the result `{}` cannot be sent back from another thread: {}",
+                    ret, reason
+                ),
+            );
+        }
+        Type::Thread(Box::new(ret))
+    }
+
+    /// `ptr::from_ref(&place)` or `ptr::from_mut(&mut place)`. Making a raw
+    /// pointer is safe; only using it needs `unsafe`
+    fn check_ptr_from(&mut self, mutable: bool, args: &[Expr], span: Span) -> Type {
+        let [arg] = args else {
+            self.error(
+                span,
+                &format!(
+                    "this function takes 1 argument but {} were supplied",
+                    args.len()
+                ),
+            );
+            return Type::Unknown;
+        };
+        match self.check_expr(arg) {
+            Type::Ref(is_mut, inner) if is_mut || !mutable => Type::Ptr(mutable, inner),
+            Type::Unknown => Type::Unknown,
+            other => {
+                let expected = if mutable { "&mut T" } else { "&T" };
+                self.error(
+                    arg.span,
+                    &format!("expected `{}`, found `{}`", expected, other),
+                );
+                Type::Unknown
+            }
+        }
+    }
+
+    /// `Mutex::new(value)` or `RwLock::new(value)`, moving `value` behind a
+    /// lock. Locks exist to be shared between threads, so it must be
+    /// sendable
+    fn check_lock_constructor(&mut self, lock: &str, args: &[Expr], span: Span) -> Type {
+        let [value] = args else {
+            self.error(
+                span,
+                &format!(
+                    "`{}::new` takes 1 argument but {} were supplied",
+                    lock,
+                    args.len()
+                ),
+            );
+            return Type::Unknown;
+        };
+        let value_ty = self.check_operand(value);
+        if let Some(reason) = self.not_send(&value_ty, &mut Vec::new()) {
+            self.error(
+                value.span,
+                &format!(
+                    "`{}` cannot be shared between threads behind a lock: {}",
+                    value_ty, reason
+                ),
+            );
+        }
+        let value_ty = Box::new(value_ty);
+        if lock == "Mutex" {
+            Type::Mutex(value_ty)
+        } else {
+            Type::RwLock(value_ty)
+        }
+    }
+
+    /// Why values of `ty` cannot move between threads, or `None` if they
+    /// can. A thread owns the arenas it creates, so nothing allocated in
+    /// one may leave it, and a reference would let two threads reach the
+    /// same data; everything else is moved or copied whole, which is what
+    /// rules out data races. `seen` holds the structs being checked.
+    fn not_send(&self, ty: &Type, seen: &mut Vec<String>) -> Option<String> {
+        match ty {
+            Type::Ref(..) => Some(format!(
+                "`{}` is a reference and threads may not share data; move the value instead",
+                ty
+            )),
+            Type::Ptr(..) => Some(format!(
+                "`{}` is a raw pointer and threads may not share data",
+                ty
+            )),
+            Type::Vec(_) | Type::Map(..) | Type::StringBuf | Type::Belief(_) | Type::Arena => Some(
+                format!("`{}` lives in an arena owned by the spawning thread", ty),
+            ),
+            Type::Array(inner, _)
+            | Type::Iter(inner)
+            | Type::Option(inner)
+            | Type::Thread(inner)
+            | Type::Sender(inner)
+            | Type::Receiver(inner)
+            | Type::Mutex(inner)
+            | Type::RwLock(inner) => self.not_send(inner, seen),
+            Type::Guard(..) => Some(format!(
+                "`{}` holds a lock, which the thread that took it must release",
+                ty
+            )),
+            Type::Newtype(name) => self
+                .info
+                .newtypes
+                .get(name)
+                .and_then(|inner| self.not_send(inner, seen)),
+            Type::Struct(name) if !seen.contains(name) => {
+                seen.push(name.clone());
+                let fields = self.info.structs.get(name)?;
+                fields.iter().find_map(|(field, field_ty)| {
+                    let reason = self.not_send(field_ty, seen)?;
+                    Some(format!("in field `{}.{}`, {}", name, field, reason))
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Reject orderings written out as `Ordering::Name` that the atomic
+    /// operation `operation` cannot use; others are checked as the program
+    /// runs
+    fn check_orderings(&mut self, operation: &str, args: &[Expr]) {
+        // A program's own `Ordering` hides the one of atomics
+        if self.definitions.contains_key(atomic::ORDERING) {
+            return;
+        }
+        for (i, arg) in args.iter().enumerate() {
+            let ExprKind::Path(segments) = &arg.kind else {
+                continue;
+            };
+            let [name, variant] = segments.as_slice() else {
+                continue;
+            };
+            let Some(ordering) = atomic::Ordering::from_name(variant) else {
+                continue;
+            };
+            let failure = operation == "compare_exchange" && i == 3;
+            if name == atomic::ORDERING {
+                if let Err(msg) = atomic::check_ordering(operation, failure, ordering) {
+                    self.error(arg.span, &msg);
+                }
+            }
+        }
+    }
+
+    /// A checked or saturating integer operation, whose operands are of one
+    /// integer type; a literal operand takes the type of the other
+    fn check_int_op(&mut self, name: &str, args: &[Expr], span: Span) -> Type {
+        let mut arg_types: Vec<Type> = args.iter().map(|a| self.check_expr(a)).collect();
+        if let ([left, right], [left_ty, right_ty]) = (args, arg_types.as_slice()) {
+            let (left_ty, right_ty) = (left_ty.clone(), right_ty.clone());
+            arg_types[0] = self.literal_operand(left_ty.clone(), &right_ty, left.span);
+            arg_types[1] = self.literal_operand(right_ty, &left_ty, right.span);
+        }
+        match builtins::int_op(name, &arg_types) {
+            Ok(ty) => {
+                if let Some(Type::Sized(numeric)) = arg_types.first() {
+                    self.info.numeric.insert(span, *numeric);
+                    self.evaluator.record_numeric(span, *numeric);
+                }
+                ty
+            }
+            Err(msg) => {
+                self.error(span, &msg);
+                Type::Unknown
+            }
+        }
+    }
+
+    /// `Some(value)`, an `Option` holding `value`
+    fn check_some(&mut self, args: &[Expr], span: Span) -> Type {
+        let arg_types: Vec<Type> = args.iter().map(|a| self.check_expr(a)).collect();
+        let ([value], [ty]) = (args, arg_types.as_slice()) else {
+            self.error(
+                span,
+                &format!("`Some` takes 1 argument but {} were supplied", args.len()),
+            );
+            return Type::Unknown;
+        };
+        self.move_out(value, ty);
+        if self.literals.contains_key(&value.span) {
+            self.literals.insert(span, Literal::Some(value.span));
+        }
+        Type::Option(Box::new(ty.clone()))
+    }
+
+    /// `Name(value)`, wrapping `value` in the newtype `Name`
+    fn check_newtype_constructor(
+        &mut self,
+        name: &str,
+        name_span: Span,
+        args: &[Expr],
+        span: Span,
+    ) -> Type {
+        self.refer(name, name_span, name);
+        let arg_types: Vec<Type> = args.iter().map(|a| self.check_expr(a)).collect();
+        let [arg_ty] = arg_types.as_slice() else {
+            self.error(
+                span,
+                &format!(
+                    "`{}` takes 1 argument but {} were supplied",
+                    name,
+                    args.len()
+                ),
+            );
+            return Type::Newtype(name.to_string());
+        };
+        // Unknown when the newtype's own definition was rejected
+        let inner = self
+            .info
+            .newtypes
+            .get(name)
+            .cloned()
+            .unwrap_or(Type::Unknown);
+        self.expect_type(&inner, arg_ty, args[0].span);
+        Type::Newtype(name.to_string())
+    }
+
+    fn check_method_call(
+        &mut self,
+        receiver: &Expr,
+        method: &str,
+        args: &[Expr],
+        span: Span,
+    ) -> Type {
+        let receiver_ty = self.check_expr(receiver);
+        let arg_types: Vec<Type> = args.iter().map(|a| self.check_expr(a)).collect();
+
+        let self_ty = auto_deref(&receiver_ty).clone();
+        let name = match &self_ty {
+            Type::Struct(name) | Type::Enum(name) => format!("{}::{}", name, method),
+            Type::Arena if method == "alloc" => {
+                self.method_paths
+                    .insert(span, format!("{}::alloc", region::ARENA_TYPE));
+                let [value] = args else {
+                    self.error(
+                        span,
+                        &format!(
+                            "this method takes 1 argument but {} were supplied",
+                            args.len()
+                        ),
+                    );
+                    return Type::Unknown;
+                };
+                self.move_out(value, &arg_types[0]);
+                return arg_types[0].clone();
+            }
+            Type::Arena if matches!(method, "checkpoint" | "reset_to") => {
+                self.method_paths
+                    .insert(span, format!("{}::{}", region::ARENA_TYPE, method));
+                return self.check_checkpoint(receiver, method, args, &arg_types, span);
+            }
+            Type::Thread(result) if method == "join" => {
+                if !args.is_empty() {
+                    self.error(
+                        span,
+                        &format!(
+                            "this method takes 0 arguments but {} were supplied",
+                            args.len()
+                        ),
+                    );
+                }
+                // Joining consumes the handle, so a thread is joined once
+                self.consume_receiver(receiver, &receiver_ty, method, "the thread handle");
+                return (**result).clone();
+            }
+            // Unlocking consumes the guard, so a lock is released once and
+            // the guard cannot be used after it
+            Type::Guard(..) if method == "unlock" => {
+                if !args.is_empty() {
+                    self.error(
+                        span,
+                        &format!(
+                            "this method takes 0 arguments but {} were supplied",
+                            args.len()
+                        ),
+                    );
+                }
+                self.consume_receiver(receiver, &receiver_ty, method, "the guard");
+                return Type::Unit;
+            }
+            // Offsetting a pointer may leave the object it points into
+            Type::Ptr(..) => {
+                if matches!(method, "add" | "sub") {
+                    self.require_unsafe(span, "pointer arithmetic");
+                }
+                return self.check_collection_method(
+                    receiver,
+                    &receiver_ty,
+                    method,
+                    args,
+                    &arg_types,
+                    span,
+                );
+            }
+            // Unwrapping moves the value out, so an `Option` of a value that
+            // is not copied is consumed
+            Type::Option(value) => {
+                if matches!(method, "unwrap" | "expect" | "unwrap_or") && !value.is_copy() {
+                    self.consume_receiver(receiver, &receiver_ty, method, "the option");
+                }
+                return self.check_collection_method(
+                    receiver,
+                    &receiver_ty,
+                    method,
+                    args,
+                    &arg_types,
+                    span,
+                );
+            }
+            Type::Vec(_)
+            | Type::Array(..)
+            | Type::Slice(_)
+            | Type::Map(..)
+            | Type::Str
+            | Type::StringBuf
+            | Type::Simd(_)
+            | Type::Sender(_)
+            | Type::Receiver(_)
+            | Type::Mutex(_)
+            | Type::RwLock(_)
+            | Type::Guard(..) => {
+                return self.check_collection_method(
+                    receiver,
+                    &receiver_ty,
+                    method,
+                    args,
+                    &arg_types,
+                    span,
+                )
+            }
+            // Atomic operations need no `mut`: they are what may change
+            // data that is shared
+            Type::Atomic(_) => {
+                let ret = self.check_collection_method(
+                    receiver,
+                    &receiver_ty,
+                    method,
+                    args,
+                    &arg_types,
+                    span,
+                );
+                self.check_orderings(method, args);
+                return ret;
+            }
+            Type::Unknown => return Type::Unknown,
+            Type::Any => {
+                self.opaque_any(span, &format!("cannot call method `{}` on", method));
+                return Type::Unknown;
+            }
+            other => {
+                self.error(
+                    span,
+                    &format!("no method named `{}` found for type `{}`", method, other),
+                );
+                return Type::Unknown;
+            }
+        };
+        let Some((params, ret)) = self.functions.get(&name).cloned() else {
+            self.error(
+                span,
+                &format!("no method named `{}` found for type `{}`", method, self_ty),
+            );
+            return Type::Unknown;
+        };
+        self.method_paths.insert(span, name.clone());
+        self.refer(method, span, &name);
+        let Some(&kind) = self.receivers.get(&name) else {
+            self.error(
+                span,
+                &format!(
+                    "`{}` is an associated function, not a method (call it as `{}(...)`)",
+                    method, name
+                ),
+            );
+            return ret;
+        };
+
+        match kind {
+            Receiver::Value | Receiver::MutValue => {
+                if matches!(receiver_ty, Type::Ref(..)) && !self_ty.is_copy() {
+                    self.error(
+                        receiver.span,
+                        &format!(
+                            "cannot move out of a reference: `{}` takes `self` by value",
+                            method
+                        ),
+                    );
+                } else {
+                    self.move_out(receiver, &self_ty);
+                }
+            }
+            Receiver::Ref => {}
+            Receiver::RefMut => {
+                if !self.is_mutable_place(receiver)
+                    && receiver_ty != Type::Ref(true, Box::new(self_ty.clone()))
+                {
+                    self.error(
+                        receiver.span,
+                        &format!(
+                            "cannot borrow as mutable: `{}` takes `&mut self` but the receiver is not declared `mut`",
+                            method
+                        ),
+                    );
+                }
+            }
+        }
+
+        let params = &params[1..];
+        if params.len() != args.len() {
+            self.error(
+                span,
+                &format!(
+                    "this method takes {} arguments but {} were supplied",
+                    params.len(),
+                    args.len()
+                ),
+            );
+        } else {
+            for ((param, arg_ty), arg) in params.iter().zip(&arg_types).zip(args) {
+                self.expect_type(param, arg_ty, arg.span);
+                self.move_out(arg, arg_ty);
+            }
+            let param_bounds = self.signature_bounds[&name].0.clone();
+            self.expect_arg_bounds(&param_bounds, args);
+            let receiver_param = match kind {
+                Receiver::RefMut => Type::Ref(true, Box::new(self_ty)),
+                _ => Type::Unit,
+            };
+            let params: Vec<Type> = std::iter::once(receiver_param)
+                .chain(params.iter().cloned())
+                .collect();
+            let args: Vec<&Expr> = std::iter::once(receiver).chain(args).collect();
+            self.check_stored_through(&params, &args);
+        }
+        ret
+    }
+
+    /// A callee may store any argument into what a `&mut` argument refers
+    /// to, so each argument living in a region must outlive the data of
+    /// every `&mut` argument's referent, as if assigned to it directly
+    fn check_stored_through(&mut self, params: &[Type], args: &[&Expr]) {
+        let targets: Vec<(usize, Symbol, Region)> = params
+            .iter()
+            .zip(args)
+            .enumerate()
+            .filter_map(|(i, (param, arg))| match param {
+                Type::Ref(true, inner) if inner.has_region() => {
+                    let name = self.referent(arg)?;
+                    Some((i, name, self.lookup(name)?.region))
+                }
+                _ => None,
+            })
+            .collect();
+        for (i, (param, arg)) in params.iter().zip(args).enumerate() {
+            let region = match param {
+                Type::Ref(_, inner) if !inner.has_region() => continue,
+                // What the callee can store is a copy of the referent's data
+                Type::Ref(..) => match self.referent(arg).and_then(|name| self.lookup(name)) {
+                    Some(local) => local.region,
+                    None => self.region_of(arg),
+                },
+                _ if !param.has_region() => continue,
+                _ => self.region_of(arg),
+            };
+            for &(target, name, target_region) in &targets {
+                if target != i && !self.regions.outlives(region, target_region) {
+                    self.error(
+                        arg.span,
+                        &format!(
+                            "value living in {} cannot be passed alongside `&mut {}`, which \
+                             outlives it",
+                            self.regions.describe(region),
+                            name
+                        ),
+                    );
+                }
+            }
+        }
+    }
+
+    /// The local a reference argument refers into: `x` for `&mut x.f`, or
+    /// for a receiver `x` borrowed by its method
+    fn referent(&self, arg: &Expr) -> Option<Symbol> {
+        match &arg.kind {
+            ExprKind::Unary(UnaryOp::Ref | UnaryOp::RefMut, operand) => {
+                self.root_local(&self.exprs[*operand])
+            }
+            _ => self.root_local(arg),
+        }
+    }
+
+    fn resolve_type(&mut self, ty: &TypeExpr) -> Type {
+        match ty {
+            TypeExpr::Named(name, span) => match name.as_str() {
+                numeric if Numeric::parse(numeric).is_some() => {
+                    Type::numeric(Numeric::parse(numeric).unwrap())
+                }
+                "bool" => Type::Bool,
+                "any" => Type::Any,
+                "str" | "String" => Type::Str,
+                "StringBuf" => Type::StringBuf,
+                region::ARENA_TYPE => Type::Arena,
+                _ if self.enum_names.iter().any(|e| e == name) => {
+                    self.refer(name, *span, name);
+                    Type::Enum(name.to_string())
+                }
+                _ if self.struct_defs.contains_key(name.as_str()) => {
+                    self.refer(name, *span, name);
+                    Type::Struct(name.to_string())
+                }
+                _ if self.aliases.contains_key(name.as_str()) => {
+                    self.refer(name, *span, name);
+                    self.expand_alias(name)
+                }
+                _ => match (simd::vector_type(name), atomic::atomic_type(name)) {
+                    (Some(vector), _) => Type::Simd(vector),
+                    (_, Some(atomic)) => Type::Atomic(atomic),
+                    _ => {
+                        let candidates = self.type_names();
+                        self.unresolved("type", name, *span, &candidates);
+                        Type::Unknown
+                    }
+                },
+            },
+            TypeExpr::Generic {
+                name,
+                args,
+                bounds,
+                span,
+            } => match (name.as_str(), args.as_slice()) {
+                ("Belief", [arg]) => match self.resolve_type(arg) {
+                    ty @ (Type::Float | Type::Bool | Type::Unknown) => {
+                        let value_type = if ty == Type::Bool { "bool" } else { "f64" };
+                        if ty != Type::Unknown {
+                            for bound in bounds {
+                                if let Err(msg) = confidence::validate(bound, value_type) {
+                                    self.error(bound.span, &msg);
+                                }
+                            }
+                        }
+                        Type::Belief(Box::new(ty))
+                    }
+                    other => {
+                        self.error(
+                            *span,
+                            &format!(
+                                "`Belief<T>` is only supported for `f64` and `bool`, found `{}`",
+                                other
+                            ),
+                        );
+                        Type::Unknown
+                    }
+                },
+                ("Belief", _) => {
+                    self.error(
+                        *span,
+                        &format!(
+                            "`Belief` takes 1 type argument but {} were supplied",
+                            args.len()
+                        ),
+                    );
+                    Type::Unknown
+                }
+                ("Vec", [elem]) => Type::Vec(Box::new(self.resolve_element(elem, *span))),
+                ("Map", [key, value]) => {
+                    let key = self.resolve_element(key, *span);
+                    if !is_hashable(self.representation(&key)) {
+                        self.error(
+                            *span,
+                            &format!(
+                                "`Map` keys must be integers, `bool`, `str` or enums, not `{}`",
+                                key
+                            ),
+                        );
+                    }
+                    let value = self.resolve_element(value, *span);
+                    Type::Map(Box::new(key), Box::new(value))
+                }
+                ("Option", [value]) => Type::Option(Box::new(self.resolve_type(value))),
+                ("Range", [elem]) => {
+                    let elem_ty = self.resolve_type(elem);
+                    match elem_ty.as_numeric() {
+                        Some(numeric) if !numeric.is_float() => Type::Range(Box::new(elem_ty)),
+                        _ if elem_ty == Type::Unknown => Type::Range(Box::new(elem_ty)),
+                        _ => {
+                            self.error(
+                                *span,
+                                &format!(
+                                    "`Range<T>` is only supported for integers, found `{}`",
+                                    elem_ty
+                                ),
+                            );
+                            Type::Unknown
+                        }
+                    }
+                }
+                ("Thread", [result]) => Type::Thread(Box::new(self.resolve_type(result))),
+                ("Sender", [elem]) => Type::Sender(Box::new(self.resolve_element(elem, *span))),
+                ("Receiver", [elem]) => Type::Receiver(Box::new(self.resolve_element(elem, *span))),
+                ("Mutex", [value]) => Type::Mutex(Box::new(self.resolve_element(value, *span))),
+                ("RwLock", [value]) => Type::RwLock(Box::new(self.resolve_element(value, *span))),
+                (guard, [value]) if GuardKind::from_type_name(guard).is_some() => {
+                    let kind = GuardKind::from_type_name(guard).unwrap();
+                    Type::Guard(kind, Box::new(self.resolve_element(value, *span)))
+                }
+                _ if matches!(
+                    name.as_str(),
+                    "Vec"
+                        | "Map"
+                        | "Option"
+                        | "Range"
+                        | "Thread"
+                        | "Sender"
+                        | "Receiver"
+                        | "Mutex"
+                        | "RwLock"
+                ) || GuardKind::from_type_name(name).is_some() =>
+                {
+                    let expected = if name == "Map" { 2 } else { 1 };
+                    self.error(
+                        *span,
+                        &format!(
+                            "`{}` takes {} type argument(s) but {} were supplied",
+                            name,
+                            expected,
+                            args.len()
+                        ),
+                    );
+                    Type::Unknown
+                }
+                _ => {
+                    self.error(
+                        *span,
+                        &format!("cannot find generic type `{}` in this scope", name),
+                    );
+                    Type::Unknown
+                }
+            },
+            // String slices and owned strings share one representation
+            TypeExpr::Ref {
+                mutable: false,
+                inner,
+            } if matches!(inner.as_ref(), TypeExpr::Named(name, _) if name == "str") => Type::Str,
+            TypeExpr::Ptr { mutable, inner } => {
+                Type::Ptr(*mutable, Box::new(self.resolve_type(inner)))
+            }
+            TypeExpr::Ref { mutable, inner } => {
+                Type::Ref(*mutable, Box::new(self.resolve_type(inner)))
+            }
+            TypeExpr::Array { elem, len } => {
+                let elem = self.resolve_type(elem);
+                match self.evaluator.eval_usize(len, &self.local_consts()) {
+                    Ok(n) => Type::Array(Box::new(elem), n),
+                    Err(err) => {
+                        self.errors.push(err);
+                        Type::Unknown
+                    }
+                }
+            }
+            TypeExpr::Slice { elem } => Type::Slice(Box::new(self.resolve_type(elem))),
+            TypeExpr::Unit => Type::Unit,
+            TypeExpr::Never(_) => Type::Never,
+        }
+    }
+
+    fn is_mutable_place(&self, expr: &Expr) -> bool {
+        match &expr.kind {
+            ExprKind::Path(segments) if segments.len() == 1 => match self.lookup(segments[0]) {
+                Some(local) => local.mutable || matches!(local.ty, Type::Ref(true, _)),
+                None => false,
+            },
+            ExprKind::Index(base, _) | ExprKind::Field(base, _) => {
+                self.is_mutable_place(&self.exprs[*base])
+            }
+            ExprKind::Unary(UnaryOp::Deref, _) => self.writable_derefs.contains(&expr.span),
+            _ => false,
+        }
+    }
+
+    fn check_struct_literal(&mut self, name: &str, inits: &[FieldInit], span: Span) -> Type {
+        let Some(fields) = self.info.structs.get(name).cloned() else {
+            for init in inits {
+                self.check_expr(&init.value);
+            }
+            let candidates: Vec<String> = self.struct_defs.keys().cloned().collect();
+            self.unresolved("struct", name, span, &candidates);
+            return Type::Unknown;
+        };
+        self.refer(name, span, name);
+
+        let mut seen: Vec<&str> = Vec::new();
+        for init in inits {
+            self.refer(&init.name, init.span, &format!("{}.{}", name, init.name));
+            let ty = self.check_operand(&init.value);
+            if seen.contains(&init.name.as_str()) {
+                self.error(
+                    init.span,
+                    &format!("field `{}` specified more than once", init.name),
+                );
+                continue;
+            }
+            seen.push(&init.name);
+            match fields.iter().find(|(field, _)| *field == init.name) {
+                Some((_, field_ty)) => {
+                    self.expect_type(field_ty, &ty, init.value.span);
+                    let def = self.struct_defs[name];
+                    if let Some(field) = def.fields.iter().find(|f| f.name == init.name) {
+                        let known = self.bounds_of(&init.value);
+                        self.expect_bounds(field.ty.bounds(), known, init.value.span);
+                    }
+                }
+                None => self.error(
+                    init.span,
+                    &format!("struct `{}` has no field named `{}`", name, init.name),
+                ),
+            }
+        }
+
+        let missing: Vec<String> = fields
+            .iter()
+            .filter(|(field, _)| !seen.contains(&field.as_str()))
+            .map(|(field, _)| format!("`{}`", field))
+            .collect();
+        if !missing.is_empty() {
+            self.error(
+                span,
+                &format!(
+                    "missing fields {} in initializer of `{}`",
+                    missing.join(", "),
+                    name
+                ),
+            );
+        }
+        Type::Struct(name.to_string())
+    }
+
+    /// Check an expression whose value is used, moving it if not copyable
+    /// Type of the items a `for` loop over `iterable` binds
+    /// A method of `Vec` or `Map` on `receiver`, called with arguments of
+    /// `arg_types`
+    /// Move the receiver of a builtin method that takes `what` by value
+    fn consume_receiver(&mut self, receiver: &Expr, receiver_ty: &Type, method: &str, what: &str) {
+        if matches!(receiver_ty, Type::Ref(..)) {
+            self.error(
+                receiver.span,
+                &format!(
+                    "cannot move out of a reference: `{}` takes {} by value",
+                    method, what
+                ),
+            );
+        } else {
+            self.move_out(receiver, auto_deref(receiver_ty));
+        }
+    }
+
+    fn check_collection_method(
+        &mut self,
+        receiver: &Expr,
+        receiver_ty: &Type,
+        method: &str,
+        args: &[Expr],
+        arg_types: &[Type],
+        span: Span,
+    ) -> Type {
+        let self_ty = auto_deref(receiver_ty);
+        let Some((params, ret, mutates)) = builtins::collection_method(self_ty, method) else {
+            self.error(
+                span,
+                &format!("no method named `{}` found for type `{}`", method, self_ty),
+            );
+            return Type::Unknown;
+        };
+        if mutates && !self.is_mutable_place(receiver) && !matches!(receiver_ty, Type::Ref(true, _))
+        {
+            self.error(
+                receiver.span,
+                &format!(
+                    "cannot borrow as mutable: `{}` takes `&mut self` but the receiver is not declared `mut`",
+                    method
+                ),
+            );
+        }
+        if method == "get" && !ret.is_copy() {
+            let (container, instead) = match self_ty {
+                Type::Guard(..) => ("lock", "replace"),
+                _ => ("map", "remove"),
+            };
+            self.error(
+                span,
+                &format!(
+                    "`get` would copy a `{}` out of the {}, which is moved instead; use `{}`",
+                    ret, container, instead
+                ),
+            );
+        }
+        if params.len() != args.len() {
+            self.error(
+                span,
+                &format!(
+                    "this method takes {} arguments but {} were supplied",
+                    params.len(),
+                    args.len()
+                ),
+            );
+            return ret;
+        }
+        let container = self.region_of(receiver);
+        let grows = matches!(self_ty, Type::Vec(_) | Type::Map(..) | Type::StringBuf)
+            && matches!(method, "push" | "insert" | "push_str");
+        let allocation = self.regions.allocation_in(container);
+        if grows && allocation != container {
+            self.error(
+                span,
+                &format!(
+                    "cannot grow a collection living in {} here: its new buffer would live in \
+                     {}, which `reset_to` frees",
+                    self.regions.describe(container),
+                    self.regions.describe(allocation)
+                ),
+            );
+        }
+        for ((param, arg_ty), arg) in params.iter().zip(arg_types).zip(args) {
+            self.expect_type(param, arg_ty, arg.span);
+            self.move_out(arg, arg_ty);
+            if !arg_ty.has_region() {
+                continue;
+            }
+            let region = self.region_of(arg);
+            if !self.regions.outlives(region, container) {
+                self.error(
+                    arg.span,
+                    &format!(
+                        "value living in {} cannot be stored in a collection living in {}, \
+                         which outlives it",
+                        self.regions.describe(region),
+                        self.regions.describe(container)
+                    ),
+                );
+            }
+        }
+        ret
+    }
+
+    /// Element type of a collection, which holds values rather than
+    /// references so that it never outlives what it holds
+    fn resolve_element(&mut self, ty: &TypeExpr, span: Span) -> Type {
+        let resolved = self.resolve_type(ty);
+        if let Type::Ref(..) = resolved {
+            self.error(
+                span,
+                &format!(
+                    "collections hold values, not references like `{}`",
+                    resolved
+                ),
+            );
+        }
+        resolved
+    }
+
+    fn check_iteration(&mut self, iterable: &Expr) -> Type {
+        let (source, adapters) = iterable.iteration(self.exprs);
+        let source_ty = self.check_operand(source);
+        let mut item = match &source_ty {
+            Type::Array(elem, _) | Type::Vec(elem) | Type::Slice(elem) | Type::Iter(elem) => {
+                (**elem).clone()
+            }
+            Type::Range(elem) => {
+                if let ExprKind::Range(None, ..) = source.kind {
+                    self.error(source.span, "cannot iterate over a range with no start");
+                }
+                (**elem).clone()
+            }
+            Type::Struct(name) | Type::Enum(name) => self.check_iterator(name, source, false),
+            Type::Ref(mutable, inner) => match &**inner {
+                // Items of `&mut` arrays would have to be writable in place, which
+                // is not supported
+                Type::Array(elem, _) | Type::Vec(elem) | Type::Slice(elem) if !mutable => {
+                    Type::Ref(false, elem.clone())
+                }
+                Type::Struct(name) | Type::Enum(name) => {
+                    self.check_iterator(name, source, !mutable)
+                }
+                _ => self.not_iterable(source, &source_ty),
+            },
+            Type::Unknown => Type::Unknown,
+            _ => self.not_iterable(source, &source_ty),
+        };
+
+        for adapter in adapters {
+            let name = adapter.kind.name();
+            let [arg] = adapter.args else {
+                self.error(
+                    adapter.span,
+                    &format!(
+                        "`{}` takes 1 argument but {} were supplied",
+                        name,
+                        adapter.args.len()
+                    ),
+                );
+                adapter.args.iter().for_each(|arg| {
+                    self.check_expr(arg);
+                });
+                item = Type::Unknown;
+                continue;
+            };
+            let arg_ty = self.check_operand(arg);
+            item = match (adapter.kind, &arg_ty) {
+                (AdapterKind::Take, _) => {
+                    self.expect_type(&Type::Int, &arg_ty, arg.span);
+                    item
+                }
+                (_, Type::Unknown) => Type::Unknown,
+                (AdapterKind::Map, Type::Fn(params, ret)) if params.len() == 1 => {
+                    self.expect_type(&params[0], &item, arg.span);
+                    (**ret).clone()
+                }
+                (AdapterKind::Filter, Type::Fn(params, ret))
+                    if params.len() == 1 && **ret == Type::Bool =>
+                {
+                    let by_ref = Type::Ref(false, Box::new(item.clone()));
+                    if !params[0].compatible(&by_ref) {
+                        self.expect_type(&params[0], &item, arg.span);
+                        if !item.is_copy() {
+                            self.error(
+                                arg.span,
+                                &format!(
+                                    "`filter` cannot move the `{}` it tests; take `&{}` instead",
+                                    item, item
+                                ),
+                            );
+                        }
+                    }
+                    item
+                }
+                (AdapterKind::Map, _) => {
+                    self.error(
+                        arg.span,
+                        &format!(
+                            "`map` expects a function taking `{}`, found `{}`",
+                            item, arg_ty
+                        ),
+                    );
+                    Type::Unknown
+                }
+                (AdapterKind::Filter, _) => {
+                    self.error(
+                        arg.span,
+                        &format!(
+                            "`filter` expects a function from `{}` or `&{}` to `bool`, found `{}`",
+                            item, item, arg_ty
+                        ),
+                    );
+                    item
+                }
+            };
+        }
+        item
+    }
+
+    /// Item type of the iterator type `name`, which must have `has_next`
+    /// and `next` methods
+    fn check_iterator(&mut self, name: &str, source: &Expr, shared: bool) -> Type {
+        let has_next = format!("{}::has_next", name);
+        let next = format!("{}::next", name);
+        let (Some(&checks), Some(&advances)) =
+            (self.receivers.get(&has_next), self.receivers.get(&next))
+        else {
+            self.error(
+                source.span,
+                &format!(
+                    "`{}` is not iterable: it needs `has_next(&self) -> bool` and \
+                     `next(&mut self) -> T` methods",
+                    name
+                ),
+            );
+            return Type::Unknown;
+        };
+        let item = self.functions[&next].1.clone();
+        if self.functions[&has_next].1 != Type::Bool {
+            self.error(
+                source.span,
+                &format!("`{}` must return `bool` to be iterated over", has_next),
+            );
+        }
+        for (method, kind) in [(&has_next, checks), (&next, advances)] {
+            if matches!(kind, Receiver::Value | Receiver::MutValue) {
+                self.error(
+                    source.span,
+                    &format!(
+                        "`{}` must take `&self` or `&mut self` to be iterated over",
+                        method
+                    ),
+                );
+            } else if shared && kind == Receiver::RefMut {
+                self.error(
+                    source.span,
+                    &format!(
+                        "cannot iterate through a shared reference: `{}` takes `&mut self`",
+                        method
+                    ),
+                );
+            }
+            if self.library.is_some_and(|start| source.span < start) {
+                let short = method.rsplit("::").next().unwrap_or(method);
+                self.check_visible(short, source.span, method);
+            }
+        }
+        item
+    }
+
+    /// Check the allocator an arena at `span` takes its memory from, and
+    /// lend it to the arena until it ends
+    fn check_allocator(&mut self, allocator: &Expr, span: Span) {
+        let ty = self.check_expr(allocator);
+        let name = match auto_deref(&ty) {
+            Type::Struct(name) | Type::Enum(name) => name.clone(),
+            Type::Unknown => return,
+            ty => {
+                self.error(
+                    allocator.span,
+                    &format!(
+                        "`{}` cannot back an arena: expected a struct or enum with `{}` and \
+                         `{}` methods",
+                        ty,
+                        alloc::ALLOCATE,
+                        alloc::RESET
+                    ),
+                );
+                return;
+            }
+        };
+        let allocate = format!("{}::{}", name, alloc::ALLOCATE);
+        let reset = format!("{}::{}", name, alloc::RESET);
+        let takes_mut = |method: &String| self.receivers.get(method) == Some(&Receiver::RefMut);
+        let allocates = takes_mut(&allocate)
+            && self.functions[&allocate].0[1..] == [Type::Int, Type::Int]
+            && self.functions[&allocate].1 == Type::Option(Box::new(Type::Int));
+        let resets = takes_mut(&reset)
+            && self.functions[&reset].0.len() == 1
+            && self.functions[&reset].1 == Type::Unit;
+        if !allocates || !resets {
+            self.error(
+                allocator.span,
+                &format!(
+                    "`{}` cannot back an arena: it needs `allocate(&mut self, size: i64, \
+                     align: i64) -> Option<i64>` and `reset(&mut self)` methods",
+                    name
+                ),
+            );
+            return;
+        }
+        if !self.is_mutable_place(allocator) {
+            self.error(
+                allocator.span,
+                "an arena's allocator must be a mutable variable or behind a `&mut` reference",
+            );
+        }
+        if self.library.is_some_and(|start| allocator.span < start) {
+            self.check_visible(alloc::ALLOCATE, allocator.span, &allocate);
+            self.check_visible(alloc::RESET, allocator.span, &reset);
+        }
+        if let Some(local) = self
+            .root_local(allocator)
+            .and_then(|name| self.lookup(name))
+        {
+            self.lent.push((local.id, span));
+        }
+        self.info.allocators.insert(span, name);
+    }
+
+    /// Report a use of the variable `local` while an arena has it as its
+    /// allocator
+    fn check_not_lent(&mut self, local: usize, name: &str, span: Span) {
+        if let Some(&(_, arena)) = self.lent.iter().find(|(id, _)| *id == local) {
+            self.error(
+                span,
+                &format!(
+                    "cannot use `{}` while it backs the arena at {}:{}",
+                    name, arena.line, arena.column
+                ),
+            );
+        }
+    }
+
+    /// Check `handle.checkpoint()` or `handle.reset_to(checkpoint)`
+    fn check_checkpoint(
+        &mut self,
+        receiver: &Expr,
+        method: &str,
+        args: &[Expr],
+        arg_types: &[Type],
+        span: Span,
+    ) -> Type {
+        let arena = self.region_of(receiver);
+        if method == "checkpoint" {
+            if !args.is_empty() {
+                self.error(
+                    span,
+                    &format!(
+                        "this method takes 0 arguments but {} were supplied",
+                        args.len()
+                    ),
+                );
+            }
+            let backed = self
+                .regions
+                .span(arena)
+                .is_some_and(|at| self.info.allocators.contains_key(&at));
+            if backed {
+                self.error(
+                    span,
+                    &format!(
+                        "cannot take a checkpoint of {}: its allocator can only be reset as a \
+                         whole",
+                        self.regions.describe(arena)
+                    ),
+                );
+            } else {
+                match self.regions.checkpoint(arena, span) {
+                    Some(region) => {
+                        self.checkpoints.insert(span, region);
+                    }
+                    None => self.error(
+                        span,
+                        "can only take a checkpoint of the innermost arena, opened in this \
+                         function",
+                    ),
+                }
+            }
+            return Type::Checkpoint;
+        }
+        let [checkpoint] = args else {
+            self.error(
+                span,
+                &format!(
+                    "this method takes 1 argument but {} were supplied",
+                    args.len()
+                ),
+            );
+            return Type::Unit;
+        };
+        self.expect_type(&Type::Checkpoint, &arg_types[0], checkpoint.span);
+        if arg_types[0] != Type::Checkpoint {
+            return Type::Unit;
+        }
+        let region = self.region_of(checkpoint);
+        match self.regions.checkpoint_of(region) {
+            Some(of) if of != arena => {
+                self.error(
+                    checkpoint.span,
+                    &format!(
+                        "this checkpoint was taken of {}, not {}",
+                        self.regions.describe(of),
+                        self.regions.describe(arena)
+                    ),
+                );
+                return Type::Unit;
+            }
+            Some(_) if !self.regions.is_active(region) => {
+                self.error(
+                    checkpoint.span,
+                    "cannot reset to a checkpoint taken in a block that has ended",
+                );
+                return Type::Unit;
+            }
+            Some(_) => {}
+            None => return Type::Unit,
+        }
+        // Everything that may refer to data allocated since the checkpoint
+        // is dead from here on, except the checkpoint itself, which may be
+        // reset to again
+        let mut freed: Vec<(usize, Symbol, usize)> = self
+            .scopes
+            .iter()
+            .flat_map(|scope| scope.iter())
+            .filter(|(_, local)| {
+                local.ty.has_region()
+                    && self.regions.outlives(region, local.region)
+                    && !(local.ty == Type::Checkpoint && local.region == region)
+            })
+            .map(|(name, local)| (local.id, *name, local.loop_depth))
+            .collect();
+        freed.sort_by_key(|&(id, ..)| id);
+        for (id, name, loop_depth) in freed {
+            if loop_depth < self.loop_depth {
+                self.error(
+                    span,
+                    &format!(
+                        "`{}` would refer to memory freed by this reset in the next iteration \
+                         of the loop",
+                        name
+                    ),
+                );
+            } else {
+                self.resets.push((id, span));
+            }
+        }
+        Type::Unit
+    }
+
+    /// Report a use of the variable `local` after a `reset_to` freed the
+    /// data it refers to
+    fn check_not_reset(&mut self, local: usize, name: &str, span: Span) {
+        if let Some(&(_, at)) = self.resets.iter().find(|(id, _)| *id == local) {
+            self.error(
+                span,
+                &format!(
+                    "use of `{}` after the `reset_to` at {}:{} freed the data it refers to",
+                    name, at.line, at.column
+                ),
+            );
+        }
+    }
+
+    fn not_iterable(&mut self, source: &Expr, ty: &Type) -> Type {
+        self.error(
+            source.span,
+            &format!(
+                "`{}` is not iterable: expected an array, a `Vec`, a slice, a range, a \
+                 reference to any of those, or a type with `has_next` and `next` methods",
+                ty
+            ),
+        );
+        Type::Unknown
+    }
+
+    /// The type of `start..end`: a range over the integer type of its
+    /// ends, which must agree; a literal end takes the type of the other
+    fn check_range(&mut self, start: Option<ExprId>, end: Option<ExprId>) -> Type {
+        let mut ends = Vec::new();
+        for end in [start, end].into_iter().flatten() {
+            let end = &self.exprs[end];
+            let ty = self.check_expr(end);
+            self.expect_integer(&ty, end.span);
+            ends.push((ty, end.span));
+        }
+        match ends.as_slice() {
+            [(start_ty, start_span), (end_ty, end_span)] => {
+                let (start_ty, end_ty) = (start_ty.clone(), end_ty.clone());
+                let elem = self.literal_operand(start_ty.clone(), &end_ty, *start_span);
+                let end_ty = self.literal_operand(end_ty, &start_ty, *end_span);
+                self.expect_type(&elem, &end_ty, *end_span);
+                Type::Range(Box::new(elem))
+            }
+            [(ty, _)] => Type::Range(Box::new(ty.clone())),
+            _ => Type::Range(Box::new(Type::Int)),
+        }
+    }
+
+    /// The type of the `match` at `span`: that of its arms, each checked
+    /// with the bindings of its pattern in scope. Only one arm runs, so the
+    /// moves of each are undone before the next, and are all in effect
+    /// after the `match`.
+    fn check_match(&mut self, scrutinee: &Expr, arms: &[Arm], span: Span) -> Type {
+        let scrutinee_ty = self.check_expr(scrutinee);
+        let region = self.region_of(scrutinee);
+        let before = self.moves.clone();
+        let mut moves: Vec<(Place, Span)> = Vec::new();
+        let mut ty: Option<Type> = None;
+        let mut value_region: Option<Region> = None;
+        for arm in arms {
+            self.moves = before.clone();
+            self.scopes.push(HashMap::new());
+            let mut bindings = Vec::new();
+            self.check_pattern(&arm.pattern, &scrutinee_ty, false, &mut bindings);
+            let mut locals = Vec::new();
+            for (name, binding_ty, binding_span) in &bindings {
+                self.declare(*name, binding_ty.clone(), false, None, *binding_span);
+                self.locate(*name, region);
+                locals.extend(self.lookup(*name).map(|local| local.id));
+            }
+            if let Some(guard) = arm.guard {
+                let guard = &self.exprs[guard];
+                let moved = self.moves.len();
+                let guard_ty = self.check_expr(guard);
+                self.expect_type(&Type::Bool, &guard_ty, guard.span);
+                // When the guard fails the next arm matches the same value,
+                // so the guard may only borrow what the pattern binds
+                let guard_moves: Vec<_> = self.moves[moved..]
+                    .iter()
+                    .filter(|(place, _)| locals.contains(&place.local))
+                    .map(|(place, at)| (place.display(), *at))
+                    .collect();
+                for (place, at) in guard_moves {
+                    self.error(
+                        at,
+                        &format!("cannot move out of `{}` in a `match` guard", place),
+                    );
+                }
+            }
+            // Binding a value that is not copyable moves the scrutinee
+            if bindings
+                .iter()
+                .any(|(_, binding_ty, _)| !binding_ty.is_copy())
+            {
+                self.move_out(scrutinee, &scrutinee_ty);
+            }
+            let value = &self.exprs[arm.value];
+            let value_ty = self.check_operand(value);
+            if value_ty != Type::Never {
+                match &ty {
+                    None => ty = Some(value_ty.clone()),
+                    Some(expected) => {
+                        if !expected.compatible(&value_ty)
+                            && !self.adapt_literal(&expected.clone(), value.span)
+                        {
+                            self.error(
+                                value.span,
+                                &format!(
+                                    "`match` arms have incompatible types: `{}` and `{}`",
+                                    expected, value_ty
+                                ),
+                            );
+                        }
+                    }
+                }
+                if value_ty.has_region() {
+                    let arm_region = self.region_of(value);
+                    value_region = Some(match value_region {
+                        Some(region) => self.regions.meet(region, arm_region),
+                        None => arm_region,
+                    });
+                }
+            }
+            self.scopes.pop();
+            for (place, at) in std::mem::take(&mut self.moves) {
+                let seen = moves.iter().any(|(moved, moved_at)| {
+                    moved.local == place.local && moved.path == place.path && *moved_at == at
+                });
+                if !seen {
+                    moves.push((place, at));
+                }
+            }
+        }
+        self.moves = if arms.is_empty() { before } else { moves };
+        if let Some(region) = value_region {
+            self.block_regions.insert(span, region);
+        }
+
+        // Guards may fail, so guarded arms cover nothing
+        let patterns: Vec<&Pattern> = arms
+            .iter()
+            .filter(|arm| arm.guard.is_none())
+            .map(|arm| &arm.pattern)
+            .collect();
+        self.check_overlaps(&patterns);
+        let missing = self.missing_patterns(&patterns, &scrutinee_ty);
+        if !missing.is_empty() {
+            let quoted: Vec<String> = missing.iter().take(3).map(|p| format!("`{}`", p)).collect();
+            let listed = match (quoted.split_last(), missing.len()) {
+                (Some((last, [])), _) => last.clone(),
+                (Some((last, rest)), n) if n <= 3 => format!("{} and {}", rest.join(", "), last),
+                _ => format!("{} and {} more", quoted.join(", "), missing.len() - 3),
+            };
+            let mut message = format!("non-exhaustive patterns: {} not covered", listed);
+            if arms.iter().any(|arm| arm.guard.is_some()) {
+                message.push_str(" (arms with a guard do not count)");
+            }
+            self.error(span, &message);
+        }
+        match ty {
+            Some(ty) => ty,
+            // Every arm diverges
+            None if !arms.is_empty() => Type::Never,
+            None => Type::Unknown,
+        }
+    }
+
+    /// Check `pattern` against values of type `ty`, adding the names it
+    /// binds and their types to `bindings`. Matching through a reference
+    /// binds what is inside the referenced value `by_ref`.
+    fn check_pattern(
+        &mut self,
+        pattern: &Pattern,
+        ty: &Type,
+        by_ref: bool,
+        bindings: &mut Vec<(Symbol, Type, Span)>,
+    ) {
+        match &pattern.kind {
+            PatternKind::Wildcard => {}
+            PatternKind::Binding(name) => {
+                let ty = if by_ref {
+                    Type::Ref(false, Box::new(ty.clone()))
+                } else {
+                    ty.clone()
+                };
+                self.bind(*name, ty, pattern.span, bindings);
+            }
+            // Every alternative must bind the same names to the same types,
+            // as the arm uses them whichever matched
+            PatternKind::Or(alternatives) => {
+                let mut first: Option<Vec<(Symbol, Type, Span)>> = None;
+                for alternative in alternatives {
+                    let mut bound = Vec::new();
+                    self.check_pattern(alternative, ty, by_ref, &mut bound);
+                    let Some(first) = &first else {
+                        first = Some(bound);
+                        continue;
+                    };
+                    for (name, first_ty, _) in first {
+                        match bound.iter().find(|(other, ..)| other == name) {
+                            None => self.error(
+                                alternative.span,
+                                &format!("variable `{}` is not bound in all patterns", name),
+                            ),
+                            Some((_, other_ty, at)) if !first_ty.compatible(other_ty) => self
+                                .error(
+                                    *at,
+                                    &format!(
+                                        "mismatched types: `{}` is bound to `{}` here but to \
+                                         `{}` in the first alternative",
+                                        name, other_ty, first_ty
+                                    ),
+                                ),
+                            Some(_) => {}
+                        }
+                    }
+                    for (name, _, at) in &bound {
+                        if !first.iter().any(|(other, ..)| other == name) {
+                            self.error(
+                                *at,
+                                &format!("variable `{}` is not bound in all patterns", name),
+                            );
+                        }
+                    }
+                }
+                for (name, ty, span) in first.unwrap_or_default() {
+                    self.bind(name, ty, span, bindings);
+                }
+            }
+            _ if matches!(ty, Type::Ref(..)) => {
+                self.check_pattern(pattern, auto_deref(ty), true, bindings)
+            }
+            PatternKind::Int(value, suffix) => {
+                let actual = self.check_int(i128::from(*value), *suffix, pattern.span);
+                self.expect_type(ty, &actual, pattern.span);
+            }
+            PatternKind::Range(start, end, _) => {
+                for end in [start, end].into_iter().flatten() {
+                    self.check_pattern(end, ty, by_ref, bindings);
+                }
+                if let Some((Some(first), Some(last))) = pattern.interval() {
+                    if first > last {
+                        self.error(
+                            pattern.span,
+                            &format!("range pattern `{}` matches no values", pattern),
+                        );
+                    }
+                }
+            }
+            PatternKind::Bool(_) => self.expect_type(ty, &Type::Bool, pattern.span),
+            PatternKind::Str(_) => self.expect_type(ty, &Type::Str, pattern.span),
+            PatternKind::Variant(name, variant) => {
+                let actual = self.check_path(&[*name, *variant], pattern.span);
+                self.expect_type(ty, &actual, pattern.span);
+            }
+            PatternKind::Some(_) | PatternKind::None => {
+                let value_ty = match ty {
+                    Type::Option(value) => (**value).clone(),
+                    Type::Unknown => Type::Unknown,
+                    _ => {
+                        self.error(
+                            pattern.span,
+                            &format!("mismatched types: expected `{}`, found `Option<_>`", ty),
+                        );
+                        Type::Unknown
+                    }
+                };
+                if let PatternKind::Some(value) = &pattern.kind {
+                    self.check_pattern(value, &value_ty, by_ref, bindings);
+                }
+            }
+        }
+    }
+
+    /// Report integer and range patterns that an earlier arm's pattern
+    /// already covers, and ranges that partly overlap an earlier one
+    fn check_overlaps(&mut self, patterns: &[&Pattern]) {
+        let mut earlier: Vec<(&Pattern, i128, i128)> = Vec::new();
+        for pattern in patterns {
+            let mut flat = Vec::new();
+            alternatives(pattern, &mut flat);
+            let mut arm = Vec::new();
+            for alternative in flat {
+                let Some((first, last)) = alternative.interval() else {
+                    continue;
+                };
+                let (first, last) = (first.unwrap_or(i128::MIN), last.unwrap_or(i128::MAX));
+                if first > last {
+                    continue;
+                }
+                let is_range = |p: &Pattern| matches!(p.kind, PatternKind::Range(..));
+                if let Some((covering, ..)) =
+                    earlier.iter().find(|(_, a, b)| *a <= first && last <= *b)
+                {
+                    self.error(
+                        alternative.span,
+                        &format!(
+                            "unreachable pattern: `{}` is already matched by `{}`",
+                            alternative, covering
+                        ),
+                    );
+                } else if let Some((other, ..)) = earlier.iter().find(|(p, a, b)| {
+                    is_range(p)
+                        && is_range(alternative)
+                        && first <= *b
+                        && *a <= last
+                        && !(first <= *a && *b <= last)
+                }) {
+                    self.error(
+                        alternative.span,
+                        &format!(
+                            "range pattern `{}` overlaps `{}` of an earlier arm",
+                            alternative, other
+                        ),
+                    );
+                }
+                arm.push((alternative, first, last));
+            }
+            earlier.extend(arm);
+        }
+    }
+
+    fn bind(
+        &mut self,
+        name: Symbol,
+        ty: Type,
+        span: Span,
+        bindings: &mut Vec<(Symbol, Type, Span)>,
+    ) {
+        if bindings.iter().any(|(bound, ..)| *bound == name) {
+            self.error(
+                span,
+                &format!(
+                    "identifier `{}` is bound more than once in the same pattern",
+                    name
+                ),
+            );
+        } else {
+            bindings.push((name, ty, span));
+        }
+    }
+
+    /// Values of type `ty` none of `patterns` match, written as patterns
+    /// (`Some(false)`, `Color::Red`, `_`)
+    fn missing_patterns(&self, patterns: &[&Pattern], ty: &Type) -> Vec<String> {
+        let mut flat = Vec::new();
+        for pattern in patterns {
+            alternatives(pattern, &mut flat);
+        }
+        if flat.iter().any(|pattern| pattern.is_irrefutable()) {
+            return Vec::new();
+        }
+        match auto_deref(ty) {
+            Type::Bool => [true, false]
+                .into_iter()
+                .filter(|&value| {
+                    !flat
+                        .iter()
+                        .any(|p| matches!(p.kind, PatternKind::Bool(b) if b == value))
+                })
+                .map(|value| value.to_string())
+                .collect(),
+            Type::Enum(name) => {
+                let variants = self.info.enums.get(name).map_or(&[][..], Vec::as_slice);
+                variants
+                    .iter()
+                    .filter(|(variant, _)| {
+                        !flat.iter().any(|p| {
+                            matches!(&p.kind, PatternKind::Variant(_, v) if *v == variant.as_str())
+                        })
+                    })
+                    .map(|(variant, _)| format!("{}::{}", name, variant))
+                    .collect()
+            }
+            Type::Option(value_ty) => {
+                let values: Vec<&Pattern> = flat
+                    .iter()
+                    .filter_map(|p| match &p.kind {
+                        PatternKind::Some(value) => Some(&**value),
+                        _ => None,
+                    })
+                    .collect();
+                let mut missing: Vec<String> = if values.is_empty() {
+                    vec!["Some(_)".to_string()]
+                } else {
+                    self.missing_patterns(&values, value_ty)
+                        .into_iter()
+                        .map(|value| format!("Some({})", value))
+                        .collect()
+                };
+                if !flat.iter().any(|p| matches!(p.kind, PatternKind::None)) {
+                    missing.push("None".to_string());
+                }
+                missing
+            }
+            int @ (Type::Int | Type::Sized(_)) => match int.as_numeric() {
+                Some(numeric) if !numeric.is_float() => integer_gaps(&flat, numeric.range()),
+                _ => vec!["_".to_string()],
+            },
+            Type::Unknown => Vec::new(),
+            _ => vec!["_".to_string()],
+        }
+    }
+
+    fn check_operand(&mut self, expr: &Expr) -> Type {
+        let ty = self.check_expr(expr);
+        self.move_out(expr, &ty);
+        ty
+    }
+
+    /// Record that the value of `expr` (of type `ty`) is moved
+    fn move_out(&mut self, expr: &Expr, ty: &Type) {
+        if ty.is_copy() {
+            return;
+        }
+        if let ExprKind::Index(..) = expr.kind {
+            self.error(
+                expr.span,
+                &format!(
+                    "cannot move a `{}` out of an array element (borrow it with `&` instead)",
+                    ty
+                ),
+            );
+        } else if let ExprKind::Unary(UnaryOp::Deref, _) = expr.kind {
+            self.error(
+                expr.span,
+                &format!("cannot move a `{}` out of a raw pointer", ty),
+            );
+        } else if let Some(place) = self.place(expr) {
+            if place.behind_ref {
+                self.error(
+                    expr.span,
+                    &format!(
+                        "cannot move out of `{}`, which is behind a reference",
+                        place.display()
+                    ),
+                );
+            } else if place.loop_depth < self.loop_depth {
+                self.error(
+                    expr.span,
+                    &format!(
+                        "use of moved value: `{}` is moved in a previous iteration of the loop",
+                        place.display()
+                    ),
+                );
+            } else {
+                self.moves.push((place, expr.span));
+            }
+        }
+    }
+
+    fn check_not_moved(&mut self, place: &Place, span: Span) {
+        if let Some(used) = &mut self.deferring {
+            used.push((place.clone(), span));
+        }
+        let Some((moved, at)) = self.moves.iter().find(|(moved, _)| moved.overlaps(place)) else {
+            return;
+        };
+        let msg = if moved.path.len() > place.path.len() {
+            format!(
+                "use of partially moved value: `{}` (`{}` moved at {}:{})",
+                place.display(),
+                moved.display(),
+                at.line,
+                at.column
+            )
+        } else {
+            format!(
+                "use of moved value: `{}` (moved at {}:{})",
+                place.display(),
+                at.line,
+                at.column
+            )
+        };
+        self.error(span, &msg);
+    }
+
+    /// Check that a place used by a deferred expression is still there when
+    /// the expression runs
+    fn check_deferred(&mut self, place: &Place, span: Span) {
+        if let Some((moved, at)) = self.moves.iter().find(|(moved, _)| moved.overlaps(place)) {
+            let msg = format!(
+                "`{}` is moved at {}:{}, before this deferred expression runs",
+                moved.display(),
+                at.line,
+                at.column
+            );
+            self.error(span, &msg);
+        }
+    }
+
+    fn field_type(&self, ty: &Type, field: &str) -> Option<Type> {
+        if let (Type::Newtype(name), "0") = (ty, field) {
+            return self.info.newtypes.get(name).cloned();
+        }
+        let Type::Struct(name) = ty else {
+            return None;
+        };
+        self.info
+            .structs
+            .get(name)?
+            .iter()
+            .find(|(f, _)| f == field)
+            .map(|(_, ty)| ty.clone())
+    }
+
+    /// The place an expression denotes, if it is a variable or a field of one
+    fn place(&self, expr: &Expr) -> Option<Place> {
+        match &expr.kind {
+            ExprKind::Path(segments) if segments.len() == 1 => {
+                let local = self.lookup(segments[0])?;
+                Some(Place {
+                    local: local.id,
+                    name: segments[0].to_string(),
+                    path: Vec::new(),
+                    ty: local.ty.clone(),
+                    behind_ref: false,
+                    loop_depth: local.loop_depth,
+                })
+            }
+            ExprKind::Field(base, field) => {
+                let mut place = self.place(&self.exprs[*base])?;
+                if matches!(place.ty, Type::Ref(..)) {
+                    place.behind_ref = true;
+                }
+                place.ty = self.field_type(auto_deref(&place.ty), field)?;
+                place.path.push(field.to_string());
+                Some(place)
+            }
+            _ => None,
+        }
+    }
+
+    /// A field of a packed struct may sit at an address its type cannot be
+    /// loaded from through a reference
+    fn check_packed_borrow(&mut self, operand: &Expr, span: Span) {
+        let ExprKind::Field(base, field) = &operand.kind else {
+            return;
+        };
+        let Some(Type::Struct(name)) = self
+            .place(&self.exprs[*base])
+            .map(|p| auto_deref(&p.ty).clone())
+        else {
+            return;
+        };
+        let Some(layout) = self.info.layouts.get(&name) else {
+            return;
+        };
+        let unaligned = layout.repr.packed.is_some_and(|packed| {
+            layout
+                .fields
+                .iter()
+                .any(|f| f.name == *field && f.layout.align > packed)
+        });
+        if unaligned {
+            self.error(
+                span,
+                &format!(
+                    "reference to field `{}` of packed struct `{}` may be unaligned; \
+                     copy the field out instead",
+                    field, name
+                ),
+            );
+        }
+    }
+
+    fn declare(
+        &mut self,
+        name: Symbol,
+        ty: Type,
+        mutable: bool,
+        const_value: Option<ConstValue>,
+        span: Span,
+    ) {
+        if name != "self" {
+            self.info.references.push(Reference {
+                span,
+                name: name.to_string(),
+                definition: span,
+            });
+        }
+        let id = self.next_local;
+        self.next_local += 1;
+        let loop_depth = self.loop_depth;
+        let home = self.regions.frame();
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(
+                name,
+                Local {
+                    id,
+                    ty,
+                    mutable,
+                    const_value,
+                    loop_depth,
+                    bounds: None,
+                    declared: Vec::new(),
+                    region: Region::Static,
+                    home,
+                    span,
+                },
+            );
+        }
+    }
+
+    /// Attach belief bounds to the innermost local `name`
+    fn refine(&mut self, name: Symbol, bounds: Option<Bounds>, declared: &[Bound]) {
+        if let Some(local) = self
+            .scopes
+            .last_mut()
+            .and_then(|scope| scope.get_mut(&name))
+        {
+            local.bounds = bounds;
+            local.declared = declared.to_vec();
+        }
+    }
+
+    /// Record where the value of the innermost local `name` lives
+    fn locate(&mut self, name: Symbol, region: Region) {
+        if let Some(local) = self
+            .scopes
+            .last_mut()
+            .and_then(|scope| scope.get_mut(&name))
+        {
+            local.region = region;
+        }
+    }
+
+    fn lookup(&self, name: Symbol) -> Option<&Local> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(&name))
+    }
+
+    /// Record that `name` at `span` refers to the item, variant or field
+    /// `key`, which must be visible there
+    fn refer(&mut self, name: &str, span: Span, key: &str) {
+        if let Some(&definition) = self.definitions.get(key) {
+            self.info.references.push(Reference {
+                span,
+                name: name.to_string(),
+                definition,
+            });
+        }
+        if self.library.is_some_and(|start| span < start) {
+            self.check_visible(name, span, key);
+        }
+    }
+
+    /// Report a use outside the library of what it does not make `pub`,
+    /// with the fix that does
+    fn check_visible(&mut self, name: &str, span: Span, key: &str) {
+        let Some(&(kind, vis)) = self.private.get(key) else {
+            return;
+        };
+        let message = match vis {
+            Visibility::Crate { .. } => format!(
+                "{} `{}` is `pub(crate)`, visible only in the library (declare it `pub` to use it here)",
+                kind, name
+            ),
+            _ => format!(
+                "{} `{}` is private to the library (declare it `pub` to use it here)",
+                kind, name
+            ),
+        };
+        let (start, end, replacement) = vis.make_public();
+        self.errors
+            .push(Diagnostic::new(span, message).with_fix(Fix::new(start, end, replacement)));
+    }
+
+    /// Record that `name` at `span` refers to the local in scope
+    fn refer_local(&mut self, name: Symbol, span: Span) {
+        match self.lookup(name) {
+            Some(local) if name != "self" => {
+                let definition = local.span;
+                self.info.references.push(Reference {
+                    span,
+                    name: name.to_string(),
+                    definition,
+                });
+            }
+            _ => {}
+        }
+    }
+
+    /// Record the names in a place expression, which `place` resolves
+    /// without checking
+    fn refer_place(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Path(segments) if segments.len() == 1 => {
+                self.refer_local(segments[0], expr.span)
+            }
+            ExprKind::Field(base, field) => {
+                self.refer_place(&self.exprs[*base]);
+                if let Some(Type::Struct(name)) = self
+                    .place(&self.exprs[*base])
+                    .map(|p| auto_deref(&p.ty).clone())
+                {
+                    self.refer(field, expr.span, &format!("{}.{}", name, field));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Local `const` values visible from the current scope
+    fn local_consts(&self) -> HashMap<Symbol, ConstValue> {
+        let mut consts = HashMap::new();
+        for scope in &self.scopes {
+            for (name, local) in scope {
+                match &local.const_value {
+                    Some(value) => {
+                        consts.insert(*name, value.clone());
+                    }
+                    None => {
+                        consts.remove(name);
+                    }
+                }
+            }
+        }
+        consts
+    }
+
+    /// What is statically known about the belief `expr` evaluates to
+    ///
+    /// Called after `expr` has been checked, so only names still in scope
+    /// are visible; block results are recorded by `check_block`.
+    fn bounds_of(&mut self, expr: &Expr) -> Option<Bounds> {
+        match &expr.kind {
+            ExprKind::Distribution(name, args) => {
+                let consts = self.local_consts();
+                let params: Vec<Option<f64>> = args
+                    .iter()
+                    .map(|arg| match self.evaluator.eval_expr(arg, &consts) {
+                        Ok(ConstValue::Float(value)) => Some(value),
+                        _ => None,
+                    })
+                    .collect();
+                Bounds::distribution(name, &params)
+            }
+            ExprKind::Path(segments) => match segments.as_slice() {
+                [name] => self.lookup(*name)?.bounds,
+                _ => None,
+            },
+            ExprKind::Call(callee, args) => {
+                if let Some((_, ret)) = self.callee_bounds(&self.exprs[*callee]) {
+                    return Bounds::declared(&ret);
+                }
+                let ExprKind::Path(segments) = &self.exprs[*callee].kind else {
+                    return None;
+                };
+                match (segments.as_slice(), args.as_slice()) {
+                    ([op], [prior, evidence]) if op == "update" && self.lookup(*op).is_none() => {
+                        Bounds::update(self.bounds_of(prior)?, self.bounds_of(evidence)?)
+                    }
+                    ([op], [condition, if_true, if_false])
+                        if op == "marginalize" && self.lookup(*op).is_none() =>
+                    {
+                        let condition = self.bounds_of(condition);
+                        Bounds::marginalize(
+                            condition,
+                            self.bounds_of(if_true)?,
+                            self.bounds_of(if_false)?,
+                        )
+                    }
+                    _ => None,
+                }
+            }
+            ExprKind::Block(block) | ExprKind::Arena(_, _, block) | ExprKind::Unsafe(block) => {
+                self.block_bounds.get(&block.span).copied()
+            }
+            ExprKind::If(_, then_block, Some(else_expr)) => {
+                let then_bounds = self.block_bounds.get(&then_block.span).copied()?;
+                Bounds::join(then_bounds, self.bounds_of(&self.exprs[*else_expr])?)
+            }
+            _ => None,
+        }
+    }
+
+    /// Declared bounds of the user function `callee` names, if any
+    fn callee_bounds(&self, callee: &Expr) -> Option<(Vec<Vec<Bound>>, Vec<Bound>)> {
+        let ExprKind::Path(segments) = &callee.kind else {
+            return None;
+        };
+        if let [name] = segments.as_slice() {
+            if self.lookup(*name).is_some() {
+                return None;
+            }
+        }
+        self.signature_bounds.get(&join_path(segments)).cloned()
+    }
+
+    fn expect_arg_bounds(&mut self, param_bounds: &[Vec<Bound>], args: &[Expr]) {
+        for (declared, arg) in param_bounds.iter().zip(args) {
+            if !declared.is_empty() {
+                let known = self.bounds_of(arg);
+                self.expect_bounds(declared, known, arg.span);
+            }
+        }
+    }
+
+    /// Report each `declared` bound that `known` does not guarantee
+    fn expect_bounds(&mut self, declared: &[Bound], known: Option<Bounds>, span: Span) {
+        for bound in declared {
+            let result = match known {
+                Some(known) => known.check(bound),
+                None => Err("nothing is known about its distribution".to_string()),
+            };
+            if let Err(reason) = result {
+                self.error(
+                    span,
+                    &format!("cannot guarantee `{}` for this belief: {}", bound, reason),
+                );
+            }
+        }
+    }
+
+    /// Region the value of `expr` lives in
+    ///
+    /// Like [`Self::bounds_of`], called after `expr` has been checked and
+    /// only meaningful when its type [has a region](Type::has_region).
+    fn region_of(&self, expr: &Expr) -> Region {
+        match &expr.kind {
+            ExprKind::Path(segments) => match segments.as_slice() {
+                [name] => self.lookup(*name).map_or(Region::Static, |l| l.region),
+                _ => Region::Static,
+            },
+            ExprKind::Unary(UnaryOp::Ref | UnaryOp::RefMut, operand) => {
+                match self
+                    .root_local(&self.exprs[*operand])
+                    .and_then(|name| self.lookup(name))
+                {
+                    Some(local) => self.regions.meet(local.home, local.region),
+                    // A borrowed temporary lives in the current frame
+                    None => self.regions.frame(),
+                }
+            }
+            ExprKind::Field(base, _) | ExprKind::Index(base, _) => {
+                self.region_of(&self.exprs[*base])
+            }
+            ExprKind::StructLit(name, inits) => {
+                let fields = self.info.structs.get(name.as_str());
+                inits
+                    .iter()
+                    .filter(|init| {
+                        fields
+                            .and_then(|f| f.iter().find(|(n, _)| *n == init.name))
+                            .is_some_and(|(_, ty)| ty.has_region())
+                    })
+                    .fold(self.regions.allocation(), |region, init| {
+                        self.regions.meet(region, self.region_of(&init.value))
+                    })
+            }
+            ExprKind::Array(elements) => elements
+                .iter()
+                .fold(self.regions.allocation(), |region, e| {
+                    self.regions.meet(region, self.region_of(e))
+                }),
+            ExprKind::ArrayRepeat(value, _) => self.regions.meet(
+                self.regions.allocation(),
+                self.region_of(&self.exprs[*value]),
+            ),
+            ExprKind::Distribution(..) => self.regions.allocation(),
+            ExprKind::Call(callee, args) => {
+                let params = match &self.exprs[*callee].kind {
+                    ExprKind::Path(segments)
+                        if segments.len() > 1 || self.lookup(segments[0]).is_none() =>
+                    {
+                        let name = join_path(segments);
+                        match self.functions.get(&name) {
+                            Some((params, _)) => Some(params.clone()),
+                            // `Vec::new(arena)` lives in `arena`
+                            None => builtins::signature(&name).map(|(params, _)| params),
+                        }
+                    }
+                    _ => None,
+                };
+                let args: Vec<&Expr> = args.iter().collect();
+                self.call_region(params.as_deref(), &args)
+            }
+            ExprKind::MethodCall(receiver, _, args) => {
+                let path = self.method_paths.get(&expr.span);
+                if path.is_some_and(|p| *p == format!("{}::alloc", region::ARENA_TYPE)) {
+                    let arena = self.region_of(&self.exprs[*receiver]);
+                    return self.regions.allocation_in(arena);
+                }
+                if let Some(&checkpoint) = self.checkpoints.get(&expr.span) {
+                    return checkpoint;
+                }
+                let params = path.and_then(|p| self.functions.get(p)).map(|(p, _)| p);
+                let args: Vec<&Expr> = std::iter::once(&self.exprs[*receiver])
+                    .chain(args)
+                    .collect();
+                self.call_region(params.map(Vec::as_slice), &args)
+            }
+            ExprKind::Block(block) | ExprKind::Arena(_, _, block) | ExprKind::Unsafe(block) => self
+                .block_regions
+                .get(&block.span)
+                .copied()
+                .unwrap_or(Region::Static),
+            ExprKind::Match(..) => self
+                .block_regions
+                .get(&expr.span)
+                .copied()
+                .unwrap_or(Region::Static),
+            ExprKind::If(_, then_block, Some(else_expr)) => {
+                let then_region = self
+                    .block_regions
+                    .get(&then_block.span)
+                    .copied()
+                    .unwrap_or(Region::Static);
+                self.regions
+                    .meet(then_region, self.region_of(&self.exprs[*else_expr]))
+            }
+            _ => Region::Static,
+        }
+    }
+
+    /// Region of a call's result: the arena of its only `Arena` argument,
+    /// otherwise the current arena unless an argument lives shorter
+    fn call_region(&self, params: Option<&[Type]>, args: &[&Expr]) -> Region {
+        let Some(params) = params else {
+            return args.iter().fold(self.regions.allocation(), |region, arg| {
+                self.regions.meet(region, self.region_of(arg))
+            });
+        };
+        let handles: Vec<usize> = (0..params.len())
+            .filter(|&i| params[i] == Type::Arena)
+            .collect();
+        if let [index] = handles[..] {
+            if let Some(arg) = args.get(index) {
+                return self.regions.allocation_in(self.region_of(arg));
+            }
+        }
+        params
+            .iter()
+            .zip(args)
+            .filter(|(ty, _)| ty.has_region())
+            .fold(self.regions.allocation(), |region, (_, arg)| {
+                self.regions.meet(region, self.region_of(arg))
+            })
+    }
+
+    /// The local variable a place expression (`x`, `x.f`, `x[i]`) belongs to
+    fn root_local(&self, expr: &Expr) -> Option<Symbol> {
+        match &expr.kind {
+            ExprKind::Path(segments) => match segments.as_slice() {
+                [name] => Some(*name),
+                _ => None,
+            },
+            ExprKind::Field(base, _) | ExprKind::Index(base, _) => {
+                self.root_local(&self.exprs[*base])
+            }
+            _ => None,
+        }
+    }
+
+    fn expect_returnable(&mut self, region: Region, span: Span) {
+        let returnable = match self.return_region {
+            Some(expected) => self.regions.outlives(region, expected),
+            None => matches!(region, Region::Static | Region::Caller | Region::Param(_)),
+        };
+        if !returnable {
+            self.error(
+                span,
+                &format!(
+                    "cannot return a value living in {}",
+                    self.regions.describe(region)
+                ),
+            );
+        }
+    }
+
+    /// The type behind `name`, an alias: a newtype stays itself, and any
+    /// other alias stands for the type it names
+    fn expand_alias(&mut self, name: &str) -> Type {
+        let alias = self.aliases[name];
+        if alias.newtype {
+            return Type::Newtype(name.to_string());
+        }
+        if self.expanding.iter().any(|n| n == name) {
+            self.error(
+                alias.span,
+                &format!("type alias `{}` refers to itself", name),
+            );
+            return Type::Unknown;
+        }
+        self.expanding.push(name.to_string());
+        let ty = self.resolve_type(&alias.ty);
+        self.expanding.pop();
+        ty
+    }
+
+    /// The type values of `ty` are represented as: the wrapped type of a
+    /// newtype, and `ty` itself otherwise
+    fn representation<'t>(&'t self, ty: &'t Type) -> &'t Type {
+        match ty {
+            Type::Newtype(name) => self.info.newtypes.get(name).unwrap_or(&Type::Unknown),
+            _ => ty,
+        }
+    }
+
+    fn expect_type(&mut self, expected: &Type, actual: &Type, span: Span) {
+        if !expected.compatible(actual) && !self.adapt_literal(expected, span) {
+            self.error(
+                span,
+                &format!(
+                    "mismatched types: expected `{}`, found `{}`",
+                    expected, actual
+                ),
+            );
+        }
+        self.convert(expected, actual, span);
+    }
+
+    /// Check that an index or length has an integer type
+    fn expect_integer(&mut self, actual: &Type, span: Span) {
+        match actual.as_numeric() {
+            Some(numeric) if !numeric.is_float() => {}
+            _ => self.expect_type(&Type::Int, actual, span),
+        }
+    }
+
+    /// The type of an integer literal of `value`: that of its suffix, or
+    /// `i64` until another integer type is expected of it
+    fn check_int(&mut self, value: i128, suffix: Option<Numeric>, span: Span) -> Type {
+        match suffix {
+            Some(numeric) => {
+                if !numeric.contains(value) {
+                    self.error(span, &format!("literal out of range for `{}`", numeric));
+                }
+                Type::numeric(numeric)
+            }
+            None => {
+                self.literals.insert(span, Literal::Int(value));
+                Type::Int
+            }
+        }
+    }
+
+    /// The type of a float literal: that of its suffix, or `f64` until
+    /// `f32` is expected of it
+    fn check_float(&mut self, suffix: Option<Numeric>, span: Span) -> Type {
+        match suffix {
+            Some(Numeric::F32) => {
+                self.info.numeric.insert(span, Numeric::F32);
+                Type::Sized(Numeric::F32)
+            }
+            Some(numeric) => Type::numeric(numeric),
+            None => {
+                self.literals.insert(span, Literal::Float);
+                Type::Float
+            }
+        }
+    }
+
+    /// Give the unsuffixed literal at `span`, if there is one, the numeric
+    /// type `expected` (or an array of it), returning whether it took it;
+    /// an integer out of range of the type is reported here
+    fn adapt_literal(&mut self, expected: &Type, span: Span) -> bool {
+        let Some(literal) = self.literals.get(&span).cloned() else {
+            return false;
+        };
+        match (literal, expected.as_numeric()) {
+            (Literal::Int(value), Some(numeric)) if !numeric.is_float() => {
+                if !numeric.contains(value) {
+                    self.error(span, &format!("literal out of range for `{}`", numeric));
+                }
+                true
+            }
+            (Literal::Float, Some(numeric)) if numeric.is_float() => {
+                self.info.numeric.insert(span, numeric);
+                true
+            }
+            (Literal::Arithmetic(left, right), Some(numeric)) => {
+                let adapted =
+                    self.adapt_literal(expected, left) && self.adapt_literal(expected, right);
+                if adapted {
+                    self.info.numeric.insert(span, numeric);
+                }
+                adapted
+            }
+            (Literal::Some(value), _) => match expected {
+                Type::Option(expected) => self.adapt_literal(expected, value),
+                _ => false,
+            },
+            (Literal::Array(elements, len), _) => match expected {
+                Type::Array(elem, expected_len) if *expected_len == len => elements
+                    .iter()
+                    .all(|element| self.adapt_literal(elem, *element)),
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// The type of an operand of a binary operator: the sized numeric type
+    /// of the other operand, if the operand is a literal that takes it
+    fn literal_operand(&mut self, ty: Type, other: &Type, span: Span) -> Type {
+        if matches!(other, Type::Sized(_))
+            && !ty.compatible(other)
+            && self.adapt_literal(other, span)
+        {
+            other.clone()
+        } else {
+            ty
+        }
+    }
+
+    /// The type of `value as target`: a cast converts between numeric
+    /// types, and from `bool` and enums to integer types
+    fn check_cast(&mut self, ty: &Type, target: &Type, span: Span) -> Type {
+        if *target == Type::Unknown {
+            return Type::Unknown;
+        }
+        let allowed = match (ty, target.as_numeric()) {
+            (_, None) => false,
+            (Type::Unknown, Some(_)) => true,
+            (Type::Bool | Type::Enum(_), Some(numeric)) => !numeric.is_float(),
+            (ty, Some(_)) => ty.as_numeric().is_some(),
+        };
+        match target.as_numeric() {
+            Some(numeric) if allowed => {
+                self.info.numeric.insert(span, numeric);
+                target.clone()
+            }
+            _ => {
+                self.error(
+                    span,
+                    &format!(
+                        "cannot cast `{}` as `{}`; `as` converts between numeric types, \
+                         and from `bool` and enums to integers",
+                        ty, target
+                    ),
+                );
+                Type::Unknown
+            }
+        }
+    }
+
+    /// The value of the constant `name` declared as `ty`, checked to be in
+    /// range of a sized integer type and rounded to `f32`
+    fn check_const_range(
+        &mut self,
+        name: &str,
+        ty: &Type,
+        value: ConstValue,
+        span: Span,
+    ) -> ConstValue {
+        match (ty.as_numeric(), value) {
+            (Some(numeric), ConstValue::Int(n)) if !numeric.contains(i128::from(n)) => {
+                self.error(
+                    span,
+                    &format!(
+                        "the value of constant `{}`, {}, is out of range for `{}`",
+                        name, n, numeric
+                    ),
+                );
+                ConstValue::Int(n)
+            }
+            (Some(numeric), ConstValue::Float(x)) => ConstValue::Float(numeric.round(x)),
+            (_, value) => value,
+        }
+    }
+
+    /// Report that `action` needs to know what a value of type `any` holds
+    fn opaque_any(&mut self, span: Span, action: &str) {
+        self.error(
+            span,
+            &format!(
+                "{} a value of type `any`; convert it to the type it holds first, \
+                 as in `let point: Point = value;`",
+                action
+            ),
+        );
+    }
+
+    /// Record that the value at `span` is converted from `actual` to
+    /// `expected`, to be checked at run time if it may be any value
+    fn convert(&mut self, expected: &Type, actual: &Type, span: Span) {
+        if actual.contains_any()
+            && expected != actual
+            && !matches!(expected, Type::Any | Type::Unknown)
+        {
+            self.info.any_casts.insert(span, expected.clone());
+        }
+    }
+
+    fn error(&mut self, span: Span, msg: &str) {
+        self.errors.push(Diagnostic::new(span, msg));
+    }
+
+    /// Report that `written` at `span` names no `kind`, suggesting the
+    /// candidate it most likely means (see [`suggest`]) with a fix writing it
+    fn unresolved(&mut self, kind: &str, written: &str, span: Span, candidates: &[String]) {
+        let message = format!("cannot find {} `{}` in this scope", kind, written);
+        let diagnostic = match suggest::suggest(written, candidates) {
+            Some(name) => Diagnostic::new(span, format!("{} (did you mean `{}`?)", message, name))
+                .with_fix(replace_path(span, written, name)),
+            None => Diagnostic::new(span, message),
+        };
+        self.errors.push(diagnostic);
+    }
+
+    /// Locals in scope, constants, functions, builtins and enum variants
+    fn value_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .scopes
+            .iter()
+            .flat_map(|s| s.keys().map(|k| k.to_string()))
+            .collect();
+        names.extend(self.const_types.keys().cloned());
+        names.extend(self.functions.keys().cloned());
+        names.extend(builtins::FUNCTIONS.iter().map(|f| f.to_string()));
+        for (name, variants) in &self.info.enums {
+            names.extend(variants.iter().map(|(v, _)| format!("{}::{}", name, v)));
+        }
+        names
+    }
+
+    /// Primitive, struct, enum and alias names
+    fn type_names(&self) -> Vec<String> {
+        let primitives = [
+            "i8",
+            "i16",
+            "i32",
+            "i64",
+            "u8",
+            "u16",
+            "u32",
+            "u64",
+            "isize",
+            "usize",
+            "f32",
+            "f64",
+            "bool",
+            "str",
+            "String",
+            "StringBuf",
+        ];
+        let mut names: Vec<String> = primitives.iter().map(|p| p.to_string()).collect();
+        names.extend(self.enum_names.iter().cloned());
+        names.extend(self.struct_defs.keys().cloned());
+        names.extend(self.aliases.keys().cloned());
+        names
+    }
+
+    /// Report `operation` unless inside an `unsafe` block
+    fn require_unsafe(&mut self, span: Span, operation: &str) {
+        if self.unsafe_depth == 0 {
+            self.errors.push(
+                Diagnostic::new(
+                    span,
+                    format!("{} is unsafe and requires an `unsafe` block", operation),
+                )
+                .with_code(ErrorCode::UnsafeOperation),
+            );
+        }
+    }
+
+    fn require_probabilistic(&mut self, span: Span, operation: &str) {
+        if self.probabilistic_depth == 0 {
+            self.errors.push(
+                Diagnostic::new(
+                    span,
+                    format!(
+                        "{} is only allowed in an `expect` argument or a `#[model]` function",
+                        operation
+                    ),
+                )
+                .with_code(ErrorCode::OutsideModel),
+            );
+        }
+    }
+}
+
+/// Can values of `ty` be [`Map`](Type::Map) keys?
+/// The fix replacing the path `written` at `span` with `path`
+fn replace_path(span: Span, written: &str, path: impl Into<String>) -> Fix {
+    let end = Span::new(span.line, span.column + written.chars().count());
+    Fix::new(span, end, path)
+}
+
+fn is_hashable(ty: &Type) -> bool {
+    match ty {
+        Type::Sized(numeric) => !numeric.is_float(),
+        _ => matches!(
+            ty,
+            Type::Int | Type::Bool | Type::Str | Type::Enum(_) | Type::Unknown
+        ),
+    }
+}
+
+fn auto_deref(ty: &Type) -> &Type {
+    match ty {
+        Type::Ref(_, inner) => auto_deref(inner),
+        other => other,
+    }
+}
+
+/// The alternatives of `pattern`, through nested or-patterns
+fn alternatives<'p>(pattern: &'p Pattern, out: &mut Vec<&'p Pattern>) {
+    match &pattern.kind {
+        PatternKind::Or(patterns) => patterns.iter().for_each(|p| alternatives(p, out)),
+        _ => out.push(pattern),
+    }
+}
+
+/// The integers from `min` to `max` that none of `patterns` match,
+/// written as patterns (`..=-1`, `7`, `10..`)
+fn integer_gaps(patterns: &[&Pattern], (min, max): (i64, i64)) -> Vec<String> {
+    let (min, max) = (i128::from(min), i128::from(max));
+    let mut covered: Vec<(i128, i128)> = patterns
+        .iter()
+        .filter_map(|pattern| pattern.interval())
+        .map(|(first, last)| {
+            (
+                first.map_or(min, |n| n.max(min)),
+                last.map_or(max, |n| n.min(max)),
+            )
+        })
+        .filter(|(first, last)| first <= last)
+        .collect();
+    covered.sort_unstable();
+    let mut gaps = Vec::new();
+    let mut next = min;
+    for (first, last) in covered {
+        if first > next {
+            gaps.push((next, first - 1));
+        }
+        next = next.max(last + 1);
+    }
+    if next <= max {
+        gaps.push((next, max));
+    }
+    gaps.into_iter()
+        .map(|(first, last)| match (first == min, last == max) {
+            _ if first == last => first.to_string(),
+            (true, true) => "_".to_string(),
+            (true, false) => format!("..={}", last),
+            (false, true) => format!("{}..", first),
+            (false, false) => format!("{}..={}", first, last),
+        })
+        .collect()
+}
+
+/// Operand numbers referred to by the `{N}` placeholders of an `asm!`
+/// template, in order; `{{` and `}}` stand for literal braces
+fn template_operands(template: &str) -> Result<Vec<usize>, String> {
+    let mut used = Vec::new();
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.as_str().starts_with('{') => {
+                chars.next();
+            }
+            '}' if chars.as_str().starts_with('}') => {
+                chars.next();
+            }
+            '{' => {
+                let rest = chars.as_str();
+                let Some(end) = rest.find('}') else {
+                    return Err("unclosed `{` in assembly template".to_string());
+                };
+                let index = rest[..end].parse().map_err(|_| {
+                    format!(
+                        "invalid placeholder `{{{}}}` in assembly template (expected an operand number)",
+                        &rest[..end]
+                    )
+                })?;
+                used.push(index);
+                chars = rest[end + 1..].chars();
+            }
+            '}' => return Err("unmatched `}` in assembly template".to_string()),
+            _ => {}
+        }
+    }
+    Ok(used)
+}
+
+/// Add the positions of the expressions whose value is the value of
+/// `block`: its tail, or its last statement if it has none, and theirs
+fn tail_positions(exprs: &Exprs, block: &Block, tails: &mut HashSet<Span>) {
+    match (block.tail, block.stmts.last()) {
+        (Some(tail), _) => tail_position(exprs, &exprs[tail], tails),
+        (None, Some(Stmt::Expr(expr))) => tail_position(exprs, expr, tails),
+        _ => {}
+    }
+}
+
+/// Add the position of `expr`, whose value is a block's, and those of the
+/// expressions whose value is its value
+fn tail_position(exprs: &Exprs, expr: &Expr, tails: &mut HashSet<Span>) {
+    tails.insert(expr.span);
+    match &expr.kind {
+        ExprKind::Block(block) | ExprKind::Arena(_, _, block) | ExprKind::Unsafe(block) => {
+            tail_positions(exprs, block, tails)
+        }
+        ExprKind::If(_, then_block, else_expr) => {
+            tail_positions(exprs, then_block, tails);
+            if let Some(else_expr) = else_expr {
+                tail_position(exprs, &exprs[*else_expr], tails);
+            }
+        }
+        ExprKind::Match(_, arms) => {
+            for arm in arms {
+                tail_position(exprs, &exprs[arm.value], tails);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser;
+    use crate::token::TokenKind;
+
+    fn check_source(source: &str) -> Result<TypeInfo, String> {
+        let mut lexer = Lexer::new(source);
+        let mut tokens = Vec::new();
+        loop {
+            let token = lexer.next_token();
+            if token.kind == TokenKind::Eof {
+                break;
+            }
+            tokens.push(token);
+        }
+        let program = parser::parse(tokens).map_err(|err| err.to_string())?;
+        check(&program).map_err(|err| err.to_string())
+    }
+
+    #[test]
+    fn test_well_typed_program() {
+        let source = "
+            fn add(a: i32, b: i32) -> i32 { a + b }
+            fn main() -> i32 {
+                let mut total: i32 = 0;
+                while total < 10 { total = add(total, 1); }
+                if total == 10 { return 0; }
+                return 1;
+            }";
+        assert!(check_source(source).is_ok());
+    }
+
+    #[test]
+    fn test_mismatched_types() {
+        let err = check_source("fn f() -> bool { 1 + 2 }").unwrap_err();
+        assert!(
+            err.contains("returns `bool` but its body has type `int`"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_immutable_assignment() {
+        let err = check_source("fn f() { let x = 1; x = 2; }").unwrap_err();
+        assert!(err.contains("immutable"));
+    }
+
+    #[test]
+    fn test_const_array_length() {
+        let source = "
+            const fn square(n: i64) -> i64 { n * n }
+            const N: usize = square(3);
+            fn first(xs: [i64; N]) -> i64 { xs[0] }
+            fn main() { let xs = [0; 9]; first(xs); }";
+        let info = check_source(source).unwrap();
+        assert_eq!(info.consts["N"], ConstValue::Int(9));
+
+        let err = check_source("const N: usize = 3; fn main() { let xs: [i64; N] = [0; 4]; }")
+            .unwrap_err();
+        assert!(
+            err.contains("expected `[int; 3]`, found `[int; 4]`"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_const_overflow_is_a_compile_error() {
+        let err = check_source("const X: i64 = 9223372036854775807 * 2;").unwrap_err();
+        assert!(err.contains("attempt to multiply with overflow"));
+    }
+
+    #[test]
+    fn test_struct_literals_and_fields() {
+        let source = "
+            #[derive(Csv)]
+            struct Point { x: i64, y: f64 }
+            fn main() -> f64 { let p = Point { y: 2.0, x: 1 }; p.y }";
+        let info = check_source(source).unwrap();
+        assert_eq!(info.schemas["Point"].names(), ["x", "y"]);
+        assert_eq!(info.layouts["Point"].layout, Layout::new(16, 8));
+
+        let err = check_source(
+            "struct P { x: i64, y: i64 } fn main() { let p = P { x: 1, z: 2 }; p.w; }",
+        )
+        .unwrap_err();
+        assert!(err.contains("struct `P` has no field named `z`"), "{}", err);
+        assert!(
+            err.contains("missing fields `y` in initializer of `P`"),
+            "{}",
+            err
+        );
+        assert!(err.contains("no field `w` on type `P`"), "{}", err);
+    }
+
+    #[test]
+    fn test_repr_attributes() {
+        let source = "
+            #[repr(C, packed)]
+            struct Reg { ctrl: u8, value: u32 }
+            #[repr(align(16))]
+            struct Line { n: i64 }
+            fn main() { let r = Reg { ctrl: 1, value: 2 }; let c = &r.ctrl; let v = r.value; }";
+        let info = check_source(source).unwrap();
+        assert_eq!(info.layouts["Reg"].layout, Layout::new(5, 1));
+        assert_eq!(info.layouts["Reg"].fields[1].offset, 1);
+        assert_eq!(info.layouts["Line"].layout, Layout::new(16, 16));
+
+        let err = check_source(
+            "#[repr(packed(2))]
+             struct Reg { ctrl: u8, value: u32 }
+             #[repr(C, align(3))]
+             struct Bad { n: i64 }
+             fn main() { let r = Reg { ctrl: 1, value: 2 }; let v = &r.value; }",
+        )
+        .unwrap_err();
+        for message in [
+            "3:14: `align` takes a power of two no larger than 2^29, not `3`",
+            "5:69: reference to field `value` of packed struct `Reg` may be unaligned",
+        ] {
+            assert!(err.contains(message), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_use_after_move() {
+        let source = "
+            struct P { x: i64 }
+            struct W { inner: P, n: i64 }
+            fn take(p: P) {}
+            fn main() {
+                let w = W { inner: P { x: 1 }, n: 2 };
+                let r = &w;
+                take(w.inner);
+                w.n;
+                take(w.inner);
+                let mut p = P { x: 3 };
+                take(p);
+                p = P { x: 4 };
+                take(p);
+            }";
+        let err = check_source(source).unwrap_err();
+        let errors: Vec<&str> = err.lines().collect();
+        assert_eq!(errors.len(), 1, "{}", err);
+        assert!(
+            errors[0].contains("use of moved value: `w.inner` (moved at 8:23)"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_recursive_struct() {
+        let err =
+            check_source("struct A { b: B } struct B { a: A } struct C { c: &C }").unwrap_err();
+        assert_eq!(err.lines().count(), 1, "{}", err);
+        assert!(err.contains("recursive type `A` has infinite size"));
+    }
+
+    #[test]
+    fn test_method_receivers() {
+        let source = "
+            struct Counter { n: i64 }
+            impl Counter {
+                fn new() -> Self { Counter { n: 0 } }
+                fn get(&self) -> i64 { self.n }
+                fn bump(&mut self) { self.n = self.n + 1; }
+                fn into_inner(self) -> i64 { self.n }
+            }
+            fn main() -> i64 {
+                let mut c = Counter::new();
+                c.bump();
+                let n = c.into_inner();
+                c.get() + n
+            }";
+        let err = check_source(source).unwrap_err();
+        assert_eq!(err.lines().count(), 1, "{}", err);
+        assert!(err.contains("use of moved value: `c`"), "{}", err);
+
+        let source = "
+            struct Counter { n: i64 }
+            impl Counter {
+                fn peek(&self) { self.n = 0; }
+                fn bump(&mut self) {}
+            }
+            fn main() { let c = Counter { n: 0 }; c.bump(); c.reset(); Counter::bump(); }";
+        let err = check_source(source).unwrap_err();
+        assert!(
+            err.contains("cannot assign to `self.n`, which is behind a `&` reference"),
+            "{}",
+            err
+        );
+        assert!(
+            err.contains("`bump` takes `&mut self` but the receiver is not declared `mut`"),
+            "{}",
+            err
+        );
+        assert!(
+            err.contains("no method named `reset` found for type `Counter`"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_belief_operators() {
+        let source = "
+            fn main() -> f64 {
+                let prior: Belief<bool> = ~Bernoulli(0.5);
+                let rain = update(prior, ~Bernoulli(0.8));
+                let temp = marginalize(rain, ~Normal(10.0, 2.0), ~Normal(20.0, 2.0));
+                expectation(temp)
+            }";
+        assert!(check_source(source).is_ok());
+
+        let source = "
+            fn main() {
+                update(~Normal(0.0, 1.0), ~Bernoulli(0.5));
+                let x: Belief<i64> = ~Poisson(1.0);
+                ~Normal(0, 1);
+            }";
+        let err = check_source(source).unwrap_err();
+        assert!(
+            err.contains("expected `Belief<float>`, found `Belief<bool>`"),
+            "{}",
+            err
+        );
+        assert!(
+            err.contains("only supported for `f64` and `bool`"),
+            "{}",
+            err
+        );
+        assert!(err.contains("unknown distribution `Poisson`"), "{}", err);
+        assert!(err.contains("expected `float`, found `int`"), "{}", err);
+    }
+
+    #[test]
+    fn test_belief_bounds() {
+        let source = "
+            const STRONG: f64 = 0.8;
+            fn confirm(prior: belief<bool, confidence >= 0.9>) -> Belief<bool, confidence >= 0.95> {
+                update(prior, ~Bernoulli(STRONG))
+            }
+            fn main() -> f64 {
+                let claim = confirm(~Bernoulli(0.92));
+                let reading: Belief<f64, std_dev <= 1.0> = update(~Normal(20.0, 5.0), ~Normal(21.0, 1.0));
+                let wet: Belief<bool, confidence >= 0.9> = marginalize(claim, ~Bernoulli(0.95), ~Bernoulli(0.9));
+                expectation(reading)
+            }";
+        assert!(check_source(source).is_ok(), "{:?}", check_source(source));
+
+        let source = "
+            fn weaken(prior: Belief<bool>, q: f64) -> Belief<bool, confidence >= 0.5> {
+                update(prior, ~Bernoulli(q))
+            }
+            fn main() {
+                let mut x: Belief<bool, confidence >= 0.9> = ~Bernoulli(0.95);
+                x = update(x, ~Bernoulli(0.3));
+                let spread: Belief<f64, interval <= 4.0> = marginalize(x, ~Normal(0.0, 1.0), ~Normal(5.0, 1.0));
+                let bad: Belief<f64, confidence >= 0.5> = ~Normal(0.0, 1.0);
+            }";
+        let err = check_source(source).unwrap_err();
+        assert!(
+            err.contains("cannot guarantee `confidence >= 0.5` for this belief: nothing is known"),
+            "{}",
+            err
+        );
+        assert!(
+            err.contains("its confidence is only known to be between 0.7941 and 1"),
+            "{}",
+            err
+        );
+        assert!(
+            err.contains("its interval is only known to be at most"),
+            "{}",
+            err
+        );
+        assert!(
+            err.contains("`confidence` bounds apply to `Belief<bool>`"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_test_attributes() {
+        assert!(check_source("#[test] fn works() {} fn main() {}").is_ok());
+        let err =
+            check_source("#[test] fn takes(x: i64) -> i64 { x } #[bench] fn b() {}").unwrap_err();
+        assert!(
+            err.contains("test functions must take no arguments and return `()`"),
+            "{}",
+            err
+        );
+        assert!(err.contains("cannot find attribute `bench`"), "{}", err);
+    }
+
+    #[test]
+    fn test_extern_declarations() {
+        let info = check_source(
+            "#[link(m)]
+             extern \"C\" { fn pow(x: f64, y: f64) -> f64; fn puts(s: &str) -> i32; }
+             fn main() { unsafe { print(pow(2.0, 3.0)); puts(\"hi\"); } }",
+        )
+        .unwrap();
+        assert_eq!(info.links, ["m"]);
+        assert_eq!(info.externs["puts"].to_string(), "int32_t (const char *)");
+
+        let err = check_source(
+            "struct P { x: i64 }
+             #[inline]
+             extern \"C\" { fn take(p: P) -> str; }
+             fn main() { take(1); }",
+        )
+        .unwrap_err();
+        for message in [
+            "cannot find attribute `inline` in this scope",
+            "`P` cannot be passed to C; use an integer type, `f64`, `bool` or `&str`",
+            "`str` cannot be returned from C; use an integer type, `f64`, `bool` or `()`",
+            "mismatched types: expected `P`, found `int`",
+        ] {
+            assert!(err.contains(message), "{}", err);
+        }
+
+        let info = check_source("pub extern \"C\" fn twice(x: i32) -> i32 { x * 2 }").unwrap();
+        assert_eq!(info.exports["twice"].to_string(), "int32_t (int32_t)");
+        let err = check_source(
+            "struct P { x: i64 }
+             pub extern \"C\" fn make() -> P { P { x: 1 } }
+             #[no_mangle]
+             fn hidden() {}",
+        )
+        .unwrap_err();
+        for message in [
+            "`P` cannot be returned from C; use an integer type, `f64`, `bool` or `()`",
+            "`#[no_mangle]` only applies to `pub extern \"C\"` functions",
+        ] {
+            assert!(err.contains(message), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_deferred_moves() {
+        let err = check_source(
+            "struct File { fd: i64 }
+             fn close(f: File) {}
+             fn main() {
+                 let a = File { fd: 1 };
+                 defer close(a);
+                 let b = a;
+                 let c = File { fd: 2 };
+                 defer print(c.fd);
+                 if true { return close(c); }
+                 defer { return; }
+                 let d = File { fd: 3 };
+                 defer print(d.fd);
+                 defer close(d);
+             }",
+        )
+        .unwrap_err();
+        for message in [
+            "5:30: `a` is moved at 6:26, before this deferred expression runs",
+            "8:31: `c` is moved at 9:41, before this deferred expression runs",
+            "10:26: cannot `return` from a deferred expression",
+            "12:31: `d` is moved at 13:30, before this deferred expression runs",
+        ] {
+            assert!(err.contains(message), "{}", err);
+        }
+        assert_eq!(err.matches("deferred").count(), 4, "{}", err);
+    }
+
+    #[test]
+    fn test_type_aliases() {
+        let accepted = "
+            type Meters = f64;
+            type Pair = [Meters; 2];
+            newtype UserId = i64;
+            fn next(id: UserId) -> UserId { id + UserId(1) }
+            fn main() {
+                let p: Pair = [1.0, 2.5];
+                let d: Meters = p[0] + p[1];
+                let id = next(UserId(41));
+                if id > UserId(0) { print(id.0 + 1); }
+            }";
+        check_source(accepted).unwrap();
+
+        let err = check_source(
+            "newtype UserId = i64;
+             type A = B;
+             type B = A;
+             newtype Bad = [i64; 2];
+             fn main() {
+                 let id: UserId = 1;
+                 let sum = UserId(1) + 1;
+                 let x: A = 0;
+             }",
+        )
+        .unwrap_err();
+        for message in [
+            "type alias `A` refers to itself",
+            "a newtype wraps a numeric type, `bool` or `str`, not `[int; 2]`",
+            "expected `UserId`, found `int`",
+        ] {
+            assert!(err.contains(message), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_visibility() {
+        let source = "
+            fn main() {
+                let mut c = Counter::new();
+                c.tick();
+                print(c.count + c.step + LIMIT);
+                c.reset();
+                let p = Counter { count: 0, step: 1 };
+            }
+            #![library]
+            pub struct Counter { pub count: i64, step: i64 }
+            impl Counter {
+                pub fn new() -> Counter { Counter { count: 0, step: LIMIT } }
+                pub fn tick(&mut self) { self.reset(); self.count = self.step; }
+                fn reset(&mut self) {}
+            }
+            pub(crate) const LIMIT: i64 = 1;";
+        let mut lexer = Lexer::new(source);
+        let tokens = std::iter::from_fn(|| Some(lexer.next_token()))
+            .take_while(|token| token.kind != TokenKind::Eof)
+            .collect();
+        let program = parser::parse(tokens).unwrap();
+        let Err(CompileError::Type(errors)) = check(&program) else {
+            panic!("privacy is not checked");
+        };
+        let found: Vec<(usize, &str)> = errors
+            .iter()
+            .map(|d| (d.span.unwrap().line, d.message.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (5, "field `step` is private to the library (declare it `pub` to use it here)"),
+                (5, "constant `LIMIT` is `pub(crate)`, visible only in the library (declare it `pub` to use it here)"),
+                (6, "method `reset` is private to the library (declare it `pub` to use it here)"),
+                (7, "field `step` is private to the library (declare it `pub` to use it here)"),
+            ]
+        );
+        let fix = |i: usize| {
+            let fix = errors[i].fix.as_deref().unwrap();
+            (fix.start, fix.end, fix.replacement.as_str())
+        };
+        assert_eq!(fix(0), (Span::new(10, 50), Span::new(10, 50), "pub "));
+        assert_eq!(fix(1), (Span::new(16, 13), Span::new(16, 23), "pub"));
+    }
+
+    #[test]
+    fn test_arena_regions() {
+        let accepted = "
+            struct Point { x: i64, y: i64 }
+            fn make(a: Arena, x: i64) -> Point { a.alloc(Point { x: x, y: 0 }) }
+            fn main() {
+                arena outer {
+                    let mut kept = Point { x: 0, y: 0 };
+                    arena {
+                        let p = Point { x: 1, y: 1 };
+                        kept = make(outer, p.x);
+                        kept = outer.alloc(Point { x: p.y, y: 0 });
+                    }
+                    print(kept.x);
+                }
+            }";
+        assert!(
+            check_source(accepted).is_ok(),
+            "{:?}",
+            check_source(accepted)
+        );
+
+        let err = check_source(
+            "struct Point { x: i64, y: i64 }
+             fn leak() -> Point { arena { let p = Point { x: 1, y: 2 }; return p; } }
+             fn main() {
+                 let mut outer = Point { x: 0, y: 0 };
+                 let r = arena scratch {
+                     outer = Point { x: 1, y: 2 };
+                     Point { x: 3, y: 4 }
+                 };
+                 arena a { let mut h = a.alloc(outer); arena b { h = b.alloc(r); } }
+             }",
+        )
+        .unwrap_err();
+        for message in [
+            "cannot return a value living in the arena at 2:35",
+            "value living in arena `scratch` cannot be stored in `outer`, which outlives it",
+            "value allocated in arena `scratch` escapes its arena",
+            "value living in arena `b` cannot be stored in `h`, which outlives it",
+        ] {
+            assert!(err.contains(message), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_probe() {
+        let source =
+            "fn main() {\n    let x = 1;\n    let y = missing + x;\n    { let x = true; x }\n}";
+        let program = crate::compile_until(source, crate::Phase::Parse)
+            .unwrap()
+            .into_ast()
+            .unwrap();
+        let (_, probe) = super::probe(&program, Span::new(4, 21));
+        assert_eq!(probe.ty, Some(Type::Bool));
+        let locals: Vec<(&str, &Type)> =
+            probe.locals.iter().map(|(n, t)| (n.as_str(), t)).collect();
+        assert_eq!(locals, [("x", &Type::Bool), ("y", &Type::Int)]);
+    }
+
+    #[test]
+    fn test_references() {
+        let source = "struct P { x: i64 }\nfn get(p: P) -> i64 { p.x }\nfn main() {\n    let x = 1;\n    let p = P { x: x };\n    print(get(p) + x);\n}";
+        let program = crate::compile_until(source, crate::Phase::Parse)
+            .unwrap()
+            .into_ast()
+            .unwrap();
+        let info = super::infer(&program);
+        let uses = |name: &str, line: usize, column: usize| -> Vec<(usize, usize)> {
+            let mut spans: Vec<(usize, usize)> = info
+                .references
+                .iter()
+                .filter(|r| r.name == name && r.definition == Span::new(line, column))
+                .map(|r| (r.span.line, r.span.column))
+                .collect();
+            spans.dedup();
+            spans
+        };
+        // The local `x` and the field `x` are told apart
+        assert_eq!(uses("x", 4, 5), [(4, 5), (5, 20), (6, 20)]);
+        assert_eq!(uses("x", 1, 12), [(1, 12), (2, 24), (5, 17)]);
+        assert_eq!(uses("P", 1, 1), [(1, 1), (2, 11), (5, 13)]);
+        assert_eq!(uses("get", 2, 1), [(2, 1), (6, 11)]);
+    }
+
+    #[test]
+    fn test_iteration() {
+        let err = check_source(
+            "struct P { x: i64 }
+             struct Cursor { n: i64 }
+             impl Cursor {
+                 fn has_next(&self) -> bool { self.n > 0 }
+                 fn next(&mut self) -> P { self.n = self.n - 1; P { x: self.n } }
+             }
+             fn positive(p: P) -> bool { p.x > 0 }
+             fn main() {
+                 for x in 5 { print(x); }
+                 let cursor = Cursor { n: 2 };
+                 for p in &cursor { print(p.x); }
+                 for p in (Cursor { n: 2 }).filter(positive) { print(p.x); }
+                 let ps = [P { x: 1 }];
+                 for p in ps { print(p.x); }
+                 print(ps[0].x);
+             }",
+        )
+        .unwrap_err();
+        for message in [
+            "9:27: `int` is not iterable",
+            "11:27: cannot iterate through a shared reference: `Cursor::next` takes `&mut self`",
+            "12:52: `filter` cannot move the `P` it tests; take `&P` instead",
+            "15:24: use of moved value: `ps`",
+        ] {
+            assert!(err.contains(message), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_collections() {
+        let err = check_source(
+            "struct P { x: i64 }
+             fn main() {
+                 arena a {
+                     let mut v: Vec<P> = Vec::new(a);
+                     let w = Vec::new(a);
+                     let r: Vec<&i64> = Vec::new(a);
+                     let m: Map<P, i64> = Map::new(a);
+                     arena {
+                         let p = P { x: 1 };
+                         v.push(p);
+                     }
+                     let u: Vec<i64> = Vec::new(a);
+                     u.push(1);
+                     let t = StringBuf::new(a);
+                     t.push_str(\"x\");
+                     let n: str = t.slice(0, 1);
+                 }
+             }",
+        )
+        .unwrap_err();
+        for message in [
+            "5:22: type annotations needed for `w`",
+            "6:29: collections hold values, not references like `&int`",
+            "7:29: `Map` keys must be integers, `bool`, `str` or enums, not `P`",
+            "10:33: value living in the arena at 8:22 cannot be stored in a collection \
+             living in arena `a`",
+            "13:22: cannot borrow as mutable: `push` takes `&mut self`",
+            "15:22: cannot borrow as mutable: `push_str` takes `&mut self`",
+            "16:36: mismatched types: expected `str`, found `StringBuf`",
+        ] {
+            assert!(err.contains(message), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_simd() {
+        let err = check_source(
+            "fn main() {
+                 let x: f64x2 = f64x2::splat(1.0);
+                 let y = x % x;
+                 let z = x + i64x2::splat(1);
+                 let w = f32x4::from_array([1.0, 2.0]);
+                 let n: i64 = x.reduce_max();
+             }",
+        )
+        .unwrap_err();
+        for message in [
+            "3:28: cannot apply `%` to type `f64x2`",
+            "4:28: mismatched types: cannot apply `+` to `f64x2` and `i64x2`",
+            "5:44: mismatched types: expected `[float; 4]`, found `[float; 2]`",
+            "6:32: mismatched types: expected `int`, found `float`",
+        ] {
+            assert!(err.contains(message), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_threads() {
+        let ok = "struct Job { id: i64, name: String }
+            fn work(job: Job, n: i64) -> Job { job }
+            fn main() {
+                let job = Job { id: 1, name: \"a\" };
+                let handle: Thread<Job> = thread::spawn(work, job, 2);
+                let done = handle.join();
+            }";
+        assert!(check_source(ok).is_ok(), "{:?}", check_source(ok).err());
+
+        let err = check_source(
+            "struct Batch { items: Vec<i64> }
+             fn sum(b: Batch) -> i64 { 0 }
+             fn peek(n: &i64) -> i64 { 0 }
+             fn send(b: Batch) {
+                 let n = 1;
+                 let h = thread::spawn(peek, &n);
+                 let zero = 0;
+                 let g = thread::spawn(sum, b);
+                 let total = g.join() + g.join();
+                 print(b);
+             }",
+        )
+        .unwrap_err();
+        for message in [
+            "6:46: `&int` cannot be sent to another thread: `&int` is a reference",
+            "8:45: `Batch` cannot be sent to another thread: in field `Batch.items`, \
+             `Vec<int>` lives in an arena owned by the spawning thread",
+            "9:41: use of moved value: `g`",
+            "10:24: use of moved value: `b`",
+        ] {
+            assert!(err.contains(message), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_channels() {
+        let ok = "struct Job { id: i64 }
+            fn produce(tx: Sender<Job>, n: i64) { tx.send(Job { id: n }); }
+            fn main() {
+                let rx: Receiver<Job> = channel::bounded(4);
+                let a = thread::spawn(produce, rx.sender(), 1);
+                let b = thread::spawn(produce, rx.sender(), 2);
+                let job = rx.recv();
+            }";
+        assert!(check_source(ok).is_ok(), "{:?}", check_source(ok).err());
+
+        let err = check_source(
+            "struct Job { id: i64 }
+             fn consume(rx: Receiver<Job>) {}
+             fn main() {
+                 let rx = channel::unbounded();
+                 let jobs: Receiver<Job> = channel::unbounded();
+                 let job = Job { id: 1 };
+                 jobs.sender().send(job);
+                 print(job);
+                 let t = thread::spawn(consume, jobs);
+                 jobs.recv();
+             }",
+        )
+        .unwrap_err();
+        for message in [
+            "4:18: type annotations needed for `rx`: declare it as `Receiver<T>`",
+            "8:24: use of moved value: `job`",
+            "10:18: use of moved value: `jobs`",
+        ] {
+            assert!(err.contains(message), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_atomics() {
+        let ok = "fn count(hits: AtomicUsize, n: i64) {
+                hits.fetch_add(n, Ordering::Relaxed);
+            }
+            fn main() {
+                let hits = AtomicUsize::new(0);
+                let t = thread::spawn(count, hits, 2);
+                t.join();
+                atomic::fence(Ordering::SeqCst);
+                let seen: i64 = hits.load(Ordering::Acquire);
+                let old = hits.compare_exchange(2, 3, Ordering::AcqRel, Ordering::Acquire);
+            }";
+        assert!(check_source(ok).is_ok(), "{:?}", check_source(ok).err());
+
+        let err = check_source(
+            "fn main() {
+                 let flag = AtomicI32::new(0);
+                 flag.store(1, Ordering::Acquire);
+                 let n = flag.load(Ordering::Release);
+                 flag.compare_exchange(0, 1, Ordering::SeqCst, Ordering::AcqRel);
+                 atomic::fence(Ordering::Relaxed);
+                 let m = flag + 1;
+             }",
+        )
+        .unwrap_err();
+        for message in [
+            "3:32: `store` cannot use `Ordering::Acquire`",
+            "4:36: `load` cannot use `Ordering::Release`",
+            "5:64: the failure ordering of `compare_exchange` cannot use `Ordering::AcqRel`",
+            "6:32: `fence` cannot use `Ordering::Relaxed`",
+            "7:31: mismatched types: cannot apply `+` to `AtomicI32` and `int`",
+        ] {
+            assert!(err.contains(message), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_locks() {
+        let ok = "struct Job { id: i64 }
+            fn add(total: Mutex<i64>, n: i64) {
+                let guard = total.lock();
+                guard.set(guard.get() + n);
+            }
+            fn peek(guard: &RwLockReadGuard<i64>) -> i64 { guard.get() }
+            fn main() {
+                let total = Mutex::new(0);
+                let t = thread::spawn(add, total, 2);
+                t.join();
+                let config: RwLock<i64> = RwLock::new(1);
+                let r = config.read();
+                let n = peek(&r);
+                r.unlock();
+                let jobs = Mutex::new(Job { id: 1 });
+                let old: Job = jobs.lock().replace(Job { id: 2 });
+            }";
+        assert!(check_source(ok).is_ok(), "{:?}", check_source(ok).err());
+
+        let err = check_source(
+            "fn hold(guard: MutexGuard<i64>) {}
+             fn share(items: Vec<i64>) -> Mutex<Vec<i64>> { Mutex::new(items) }
+             fn main() {
+                 let m = Mutex::new(1);
+                 let g = m.lock();
+                 g.unlock();
+                 g.unlock();
+                 let t = thread::spawn(hold, m.lock());
+                 let job = RwLock::new(2).read();
+                 job.set(3);
+             }",
+        )
+        .unwrap_err();
+        for message in [
+            "2:72: `Vec<int>` cannot be shared between threads behind a lock: \
+             `Vec<int>` lives in an arena owned by the spawning thread",
+            "7:18: use of moved value: `g` (moved at 6:18)",
+            "8:47: `MutexGuard<int>` cannot be sent to another thread: \
+             `MutexGuard<int>` holds a lock, which the thread that took it must release",
+            "10:21: no method named `set` found for type `RwLockReadGuard<int>`",
+        ] {
+            assert!(err.contains(message), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_unsafe() {
+        let ok = "extern \"C\" { fn labs(x: i64) -> i64; }
+            struct P { x: i64 }
+            fn main() {
+                let mut xs = [1, 2, 3];
+                let p = xs.as_mut_ptr();
+                let mut point = P { x: 1 };
+                let q: *mut P = ptr::from_mut(&mut point);
+                let n = unsafe {
+                    *p.add(2) = 4;
+                    (*q).x = labs(*p.sub(0));
+                    *p
+                };
+            }";
+        assert!(check_source(ok).is_ok(), "{:?}", check_source(ok).err());
+
+        let err = check_source(
+            "extern \"C\" { fn labs(x: i64) -> i64; }
+             fn main() {
+                 let xs = [1, 2];
+                 let p = xs.as_ptr();
+                 let n = *p.add(1) + labs(1);
+                 unsafe { *p = 3; }
+                 let r = &xs;
+                 let m = *r;
+             }",
+        )
+        .unwrap_err();
+        for message in [
+            "error[E0001] at 5:28: pointer arithmetic is unsafe and requires an `unsafe` block",
+            "error[E0001] at 5:26: dereference of raw pointer `*const int` is unsafe",
+            "error[E0001] at 5:42: call to C function `labs` is unsafe",
+            "6:30: cannot assign through a `*const` pointer (make it `*mut`)",
+            "8:26: `*` dereferences raw pointers, not `&[int; 2]`",
+        ] {
+            assert!(err.contains(message), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_models() {
+        let ok = "#[model]
+            fn coin() -> bool {
+                let heads = sample(~Bernoulli(0.5));
+                observe(~Bernoulli(0.9), heads);
+                heads
+            }
+            #[model]
+            fn coins() -> bool { coin() && coin() }
+            fn main() {
+                let p = expect(coins());
+                let q = expect({ let x = sample(~Normal(0.0, 1.0)); observe(x > 0.0); x });
+            }";
+        assert!(check_source(ok).is_ok(), "{:?}", check_source(ok).err());
+
+        let err = check_source(
+            "#[model]
+             fn coin() -> bool { sample(~Bernoulli(0.5)) }
+             fn main() {
+                 let heads = coin();
+                 observe(heads);
+                 let p = expect(~Bernoulli(0.5));
+             }",
+        )
+        .unwrap_err();
+        for message in [
+            "error[E0002] at 4:34: call to model `coin` is only allowed in an `expect` argument \
+             or a `#[model]` function",
+            "error[E0002] at 5:25: `observe` is only allowed",
+            "6:32: mismatched types: expected `float` or `bool`, found `Belief<bool>`",
+        ] {
+            assert!(err.contains(message), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_any_type() {
+        let ok = "struct Point { x: i64 }
+            fn show(value: any) -> any { value }
+            fn first(values: [any; 2]) -> [i64; 2] { values }
+            fn main() {
+                let a: any = show(1);
+                let b: i64 = a + 1;
+                let p: Point = show(Point { x: 2 });
+                let f: f64 = show(1.5);
+                let both = first([b, p.x]);
+            }";
+        let info = check_source(ok).unwrap();
+        let mut casts: Vec<_> = info
+            .any_casts
+            .iter()
+            .map(|(span, ty)| (span.line, ty))
+            .collect();
+        casts.sort_by_key(|&(line, _)| line);
+        assert_eq!(
+            casts,
+            [
+                (3, &Type::Array(Box::new(Type::Int), 2)),
+                (7, &Type::Struct("Point".to_string())),
+                (8, &Type::Float),
+            ]
+        );
+
+        let err = check_source(
+            "struct Point { x: i64 }
+             fn main() {
+                 let mut n = 1;
+                 let p: any = Point { x: 1 };
+                 let x = p.x;
+                 let r: &mut any = &mut n;
+             }",
+        )
+        .unwrap_err();
+        for message in [
+            "5:27: cannot access field `x` of a value of type `any`; convert it to the type it \
+             holds first",
+            "6:36: mismatched types: expected `&mut any`, found `&mut int`",
+        ] {
+            assert!(err.contains(message), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_sized_numeric_types() {
+        let ok = "enum Level { Low, High = 10 }
+            const MASK: u8 = 240 as u8;
+            fn scale(x: f32) -> f32 { x * 2.0 }
+            fn main() {
+                let bytes: [u8; 3] = [1, 2, 255];
+                let sum = bytes[0] + bytes[1 as u8] * 2;
+                let wide = sum as i64 + Level::High as i64 + -1;
+                let half = scale(0.5) + 1.0f32;
+            }";
+        let info = check_source(ok).unwrap();
+        assert_eq!(info.consts["MASK"], ConstValue::Int(240));
+        let numeric: HashSet<Numeric> = info.numeric.values().copied().collect();
+        assert_eq!(
+            numeric,
+            HashSet::from([Numeric::U8, Numeric::I64, Numeric::F32])
+        );
+
+        let err = check_source(
+            "const BIG: i16 = 200 * 200;
+             fn main() {
+                 let a: u8 = 256;
+                 let b: i32 = 1;
+                 let c = a + b;
+                 let d = -a;
+                 let e = \"1\" as i64;
+                 let f: f64 = 1.0f32;
+             }",
+        )
+        .unwrap_err();
+        for message in [
+            "1:22: the value of constant `BIG`, 40000, is out of range for `i16`",
+            "3:30: literal out of range for `u8`",
+            "5:28: mismatched types: cannot apply `+` to `u8` and `i32`",
+            "6:26: cannot negate a value of unsigned type `u8`",
+            "7:30: cannot cast `str` as `int`",
+            "8:31: mismatched types: expected `float`, found `f32`",
+        ] {
+            assert!(err.contains(message), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_match() {
+        let ok = "enum Desk { News, Sports }
+            struct Story { words: i64 }
+            fn words(story: &Option<Story>) -> i64 {
+                match story { Some(s) => s.words, None => 0 }
+            }
+            fn main() {
+                let desk = match 1 { 0 | 1 => Desk::News, _ => Desk::Sports };
+                let name = match desk { Desk::News => \"news\", Desk::Sports => \"sports\" };
+                let draft = Some(Story { words: 1 });
+                let n = match draft { Some(s) if s.words > 0 => s.words, Some(_) => 0, None => -1 };
+                let done = match n { 0 => return, n => n };
+            }";
+        assert!(check_source(ok).is_ok(), "{:?}", check_source(ok).err());
+
+        let err = check_source(
+            "enum Desk { News, Sports } struct Story { words: i64 }
+             fn main() {
+                 let a = match Desk::News { Desk::News => 1 };
+                 let b = match Some(true) { Some(true) => 1, Some(b) if b => 2, None => 3 };
+                 let c = match 1 { x | 2 => x, y => y + \"1\" };
+                 let d = match 1 { 1 => 1, _ => 1.5 };
+                 let e = match true { Some(x) => 1, _ => 0 };
+                 let s = Some(Story { words: 1 });
+                 let f = match s { Some(x) => x.words, None => 0 };
+                 let g = s;
+             }",
+        )
+        .unwrap_err();
+        for message in [
+            "3:26: non-exhaustive patterns: `Desk::Sports` not covered",
+            "4:26: non-exhaustive patterns: `Some(false)` not covered \
+             (arms with a guard do not count)",
+            "5:40: variable `x` is not bound in all patterns",
+            "5:55: mismatched types: cannot apply `+` to `int` and `str`",
+            "6:49: `match` arms have incompatible types: `int` and `float`",
+            "7:39: mismatched types: expected `bool`, found `Option<_>`",
+            "10:26: use of moved value: `s`",
+        ] {
+            assert!(err.contains(message), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_range_patterns() {
+        let ok = "fn main() {
+                let a = match 5 { ..0 => 0, 0..=9 => 1, 10.. => 2 };
+                let b = match 5u8 { 0 => 0, 1..=254 | 255 => 1 };
+                let c = match 5 { 3 => 0, 0..10 => 1, _ => 2 };
+            }";
+        assert!(check_source(ok).is_ok(), "{:?}", check_source(ok).err());
+
+        let err = check_source(
+            "fn main() {
+                 let a = match 5 { 0..10 => 0, 5..20 => 1, 7 => 2 };
+                 let b = match 5u8 { 1..=9 => 0, 20..200 => 1 };
+                 let c = match 5 { 3..=2 => 0, true => 1, _ => 2 };
+             }",
+        )
+        .unwrap_err();
+        for message in [
+            "2:26: non-exhaustive patterns: `..=-1` and `20..` not covered",
+            "2:48: range pattern `5..20` overlaps `0..10` of an earlier arm",
+            "2:60: unreachable pattern: `7` is already matched by `0..10`",
+            "3:26: non-exhaustive patterns: `0`, `10..=19` and `200..` not covered",
+            "4:36: range pattern `3..=2` matches no values",
+            "4:48: mismatched types: expected `int`, found `bool`",
+        ] {
+            assert!(err.contains(message), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_never_type() {
+        let ok = "fn fail() -> ! { panic(\"no\") }
+            fn spin() -> ! { while true {} }
+            fn pick(c: bool) -> i64 {
+                let a = if c { 1 } else { return 0; };
+                let b: str = if c { fail() } else { \"b\" };
+                let d = match c { true => a, false => spin() };
+                let e: bool = { return d; };
+                a
+            }
+            fn main() { print(pick(true)); }";
+        assert!(check_source(ok).is_ok(), "{:?}", check_source(ok).err());
+
+        let err = check_source(
+            "fn stop() -> ! { print(1); }
+             fn main() { let a = if true { 1 } else { print(2) }; }",
+        )
+        .unwrap_err();
+        for message in [
+            "1:16: mismatched types: function `stop` returns `!` but its body has type `()`",
+            "2:53: `if` and `else` have incompatible types: `int` and `()`",
+        ] {
+            assert!(err.contains(message), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_become() {
+        let ok = "fn count(n: i64, total: i64) -> i64 {
+                if n == 0 {
+                    return total;
+                }
+                become count(n - 1, total + n);
+            }
+            fn even(n: i64) -> bool {
+                match n { 0 => true, _ => { become odd(n - 1) } }
+            }
+            fn odd(n: i64) -> bool {
+                if n == 0 { false } else if n > 0 { become even(n - 1) } else { become odd(-n) }
+            }
+            fn main() { print(count(3, 0)); print(even(4)); }";
+        assert!(check_source(ok).is_ok(), "{:?}", check_source(ok).err());
+
+        let err = check_source(
+            "fn f(n: i64) -> i64 { n }
+             fn g(n: i64) -> bool { n > 0 }
+             fn h(r: &i64) -> i64 { 0 }
+             fn early(n: i64) -> i64 { let a = become f(n); a }
+             fn scoped(n: i64) -> i64 { arena { become f(n) } }
+             fn deferred(n: i64) -> i64 { defer print(n); become f(n); }
+             fn builtin(n: i64) { become print(n); }
+             fn mismatched(n: i64) -> i64 { become g(n) }
+             fn borrowed(n: i64) -> i64 { let x = n; become h(&x) }
+             fn main() {}",
+        )
+        .unwrap_err();
+        for message in [
+            "4:48: `become` must be in tail position, where the call's result is the function's",
+            "5:49: cannot `become` inside the arena at 5:41, which is freed after the call returns",
+            "6:59: cannot `become` with deferred expressions pending: they run after the call \
+             returns",
+            "7:47: `become` needs a Solo function called by name, which it can jump to",
+            "8:53: `become` calls `g`, which returns `bool`, from a function returning `int`",
+            "9:63: cannot pass a value living in a local variable to a tail call, which reuses \
+             its frame",
+        ] {
+            assert!(err.contains(message), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_arena_allocators() {
+        let pool = "struct Pool { next: i64 }
+            impl Pool {
+                fn allocate(&mut self, size: i64, align: i64) -> Option<i64> { None }
+                fn reset(&mut self) { self.next = 0; }
+            }
+            struct Bad { next: i64 }
+            impl Bad {
+                fn allocate(&self, size: i64) -> i64 { 0 }
+            }
+            struct Point { x: i64, y: i64 }
+            ";
+        let ok = format!(
+            "{}fn scratch(pool: &mut Pool) -> i64 {{ arena in pool {{ Point {{ x: 1, y: 2 }}.x }} }}
+            fn main() {{
+                let mut pool = Pool {{ next: 0 }};
+                let mut spare = Pool {{ next: 0 }};
+                arena frame in pool {{ print(scratch(&mut spare)); }}
+                print(pool.next);
+            }}",
+            pool
+        );
+        let info = check_source(&ok).unwrap_or_else(|err| panic!("{}", err));
+        let mut allocators: Vec<_> = info.allocators.values().cloned().collect();
+        allocators.sort();
+        assert_eq!(allocators, ["Pool", "Pool"]);
+
+        let err = check_source(&format!(
+            "{}fn main() {{
+                let pool = Pool {{ next: 0 }};
+                arena in pool {{ }}
+                let mut bad = Bad {{ next: 0 }};
+                arena in bad {{ }}
+                let mut n = 5;
+                arena in n {{ }}
+                let mut lent = Pool {{ next: 0 }};
+                arena in lent {{ lent.next = 1; arena in lent {{ }} }}
+            }}",
+            pool
+        ))
+        .unwrap_err();
+        for message in [
+            "13:26: an arena's allocator must be a mutable variable or behind a `&mut` reference",
+            "15:26: `Bad` cannot back an arena: it needs `allocate(&mut self, size: i64, align: \
+             i64) -> Option<i64>` and `reset(&mut self)` methods",
+            "17:26: `int` cannot back an arena: expected a struct or enum with `allocate` and \
+             `reset` methods",
+            "19:37: cannot use `lent` while it backs the arena at 19:17",
+            "19:57: cannot use `lent` while it backs the arena at 19:17",
+        ] {
+            assert!(err.contains(message), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_arena_checkpoints() {
+        let ok = "struct Body { x: i64, v: i64 }
+            fn step(a: Arena, b: &Body) -> Body { a.alloc(Body { x: b.x + b.v, v: b.v }) }
+            fn main() {
+                arena frame {
+                    let origin = Body { x: 0, v: 1 };
+                    let start = frame.checkpoint();
+                    let mut t = 0;
+                    while t < 3 {
+                        let mut body = step(frame, &origin);
+                        body = step(frame, &body);
+                        print(body.x);
+                        frame.reset_to(start);
+                        body = Body { x: 0, v: 0 };
+                        print(body.x + origin.x);
+                        t = t + 1;
+                    }
+                }
+            }";
+        assert!(check_source(ok).is_ok(), "{:?}", check_source(ok).err());
+
+        let err = check_source(
+            "struct Body { x: i64, v: i64 }
+             fn main() {
+                 arena frame {
+                     let start = frame.checkpoint();
+                     let kept = Body { x: 0, v: 0 };
+                     let mut t = 0;
+                     while t < 3 {
+                         let b = Body { x: t, v: 1 };
+                         frame.reset_to(start);
+                         print(b.x);
+                         t = t + 1;
+                     }
+                     arena inner {
+                         inner.reset_to(start);
+                         let nested = frame.checkpoint();
+                     }
+                 }
+             }",
+        )
+        .unwrap_err();
+        for message in [
+            "9:31: `kept` would refer to memory freed by this reset in the next iteration of the \
+             loop",
+            "10:33: use of `b` after the `reset_to` at 9:31 freed the data it refers to",
+            "14:41: this checkpoint was taken of arena `frame`, not arena `inner`",
+            "15:44: can only take a checkpoint of the innermost arena, opened in this function",
+        ] {
+            assert!(err.contains(message), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_inline_asm() {
+        let ok = "fn main() {
+                let a = 2;
+                let mut b = 0;
+                let mut f = 1.5;
+                asm!(\"mov {1}, {0}\", in(reg) a, out(reg) b);
+                asm!(\"fsqrt {0} {{}}\", inout(freg) f);
+            }";
+        assert!(check_source(ok).is_ok(), "{:?}", check_source(ok).err());
+
+        let err = check_source(
+            "fn main() {
+                 let a = 2;
+                 let mut x = 1.0;
+                 asm!(\"nop {0}\", out(reg) a);
+                 asm!(\"{0} {1}\", in(reg) x, in(vreg) 1);
+                 asm!(\"{0} {2}\", in(reg) 1, in(reg) 2);
+                 asm!(\"{x}\", inout(reg) 1);
+             }",
+        )
+        .unwrap_err();
+        for message in [
+            "4:34: `out` operand must be a mutable place, found `a`, which is not mutable",
+            "5:42: a value of type `float` cannot be passed in a `reg` register",
+            "5:45: unknown register class `vreg` (expected `reg` or `freg`)",
+            "6:18: invalid reference to operand {2}: there are 2 operands",
+            "7:18: invalid placeholder `{x}` in assembly template (expected an operand number)",
+            "7:30: `inout` operand must be a mutable place, found an expression that is not a place",
+        ] {
+            assert!(err.contains(message), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_did_you_mean() {
+        let source = "struct Story { words: i64 }
+            enum Desk { News, Sport }
+            fn main() {
+                let words = 3;
+                print(wrods);
+                let n = arg(1);
+                let d = Desk::Nwes;
+                let s = Stroy { words: 1 };
+                let t: Strng = \"x\";
+                print(headline);
+            }";
+        let mut lexer = Lexer::new(source);
+        let tokens = std::iter::from_fn(|| Some(lexer.next_token()))
+            .take_while(|token| token.kind != TokenKind::Eof)
+            .collect();
+        let program = parser::parse(tokens).unwrap();
+        let Err(CompileError::Type(errors)) = check(&program) else {
+            panic!("names are not resolved");
+        };
+        let found: Vec<(&str, Option<&str>)> = errors
+            .iter()
+            .map(|d| {
+                let fix = d.fix.as_deref().map(|f| f.replacement.as_str());
+                (d.message.as_str(), fix)
+            })
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (
+                    "cannot find value `wrods` in this scope (did you mean `words`?)",
+                    Some("words")
+                ),
+                (
+                    "cannot find value `arg` in this scope (did you mean `env::arg`?)",
+                    Some("env::arg")
+                ),
+                (
+                    "no variant `Nwes` in enum `Desk` (did you mean `Desk::News`?)",
+                    Some("Desk::News")
+                ),
+                (
+                    "cannot find struct `Stroy` in this scope (did you mean `Story`?)",
+                    Some("Story")
+                ),
+                (
+                    "cannot find type `Strng` in this scope (did you mean `String`?)",
+                    Some("String")
+                ),
+                ("cannot find value `headline` in this scope", None),
+            ]
+        );
+        let fix = errors[2].fix.as_deref().unwrap();
+        assert_eq!((fix.start, fix.end), (Span::new(7, 25), Span::new(7, 35)));
+    }
+}
@@ -0,0 +1,348 @@
+//! Unicode support shared by the lexer and the standard library
+//!
+//! Identifier classification, canonical normalization (NFD/NFC), case
+//! folding, and (behind the `graphemes` feature) extended grapheme cluster
+//! segmentation. The lexer normalizes identifiers with the same tables the
+//! `std::string` functions use, so `café` spelled with a precomposed `é` and
+//! with `e` + U+0301 name the same binding.
+
+mod tables;
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use tables::{CANONICAL_PAIRS, COMBINING_CLASSES};
+
+const HANGUL_S_BASE: u32 = 0xAC00;
+const HANGUL_L_BASE: u32 = 0x1100;
+const HANGUL_V_BASE: u32 = 0x1161;
+const HANGUL_T_BASE: u32 = 0x11A7;
+const HANGUL_L_COUNT: u32 = 19;
+const HANGUL_V_COUNT: u32 = 21;
+const HANGUL_T_COUNT: u32 = 28;
+const HANGUL_N_COUNT: u32 = HANGUL_V_COUNT * HANGUL_T_COUNT;
+const HANGUL_S_COUNT: u32 = HANGUL_L_COUNT * HANGUL_N_COUNT;
+
+/// Can `ch` begin an identifier?
+pub fn is_ident_start(ch: char) -> bool {
+    ch == '_' || ch.is_alphabetic()
+}
+
+/// Can `ch` continue an identifier?
+pub fn is_ident_continue(ch: char) -> bool {
+    ch == '_' || ch.is_alphanumeric() || is_combining_mark(ch)
+}
+
+/// Combining marks that attach to the preceding character
+pub fn is_combining_mark(ch: char) -> bool {
+    matches!(ch,
+        '\u{0300}'..='\u{036F}'
+        | '\u{0483}'..='\u{0489}'
+        | '\u{1AB0}'..='\u{1AFF}'
+        | '\u{1DC0}'..='\u{1DFF}'
+        | '\u{20D0}'..='\u{20FF}'
+        | '\u{FE20}'..='\u{FE2F}')
+}
+
+/// Canonical combining class (0 for starters)
+pub fn combining_class(ch: char) -> u8 {
+    COMBINING_CLASSES
+        .binary_search_by_key(&ch, |&(c, _)| c)
+        .map(|i| COMBINING_CLASSES[i].1)
+        .unwrap_or(0)
+}
+
+fn decompositions() -> &'static HashMap<char, (char, char)> {
+    static MAP: OnceLock<HashMap<char, (char, char)>> = OnceLock::new();
+    MAP.get_or_init(|| {
+        CANONICAL_PAIRS
+            .iter()
+            .map(|&(composed, base, mark)| (composed, (base, mark)))
+            .collect()
+    })
+}
+
+fn compositions() -> &'static HashMap<(char, char), char> {
+    static MAP: OnceLock<HashMap<(char, char), char>> = OnceLock::new();
+    MAP.get_or_init(|| {
+        CANONICAL_PAIRS
+            .iter()
+            .map(|&(composed, base, mark)| ((base, mark), composed))
+            .collect()
+    })
+}
+
+fn decompose_char(ch: char, out: &mut Vec<char>) {
+    let code = ch as u32;
+    if (HANGUL_S_BASE..HANGUL_S_BASE + HANGUL_S_COUNT).contains(&code) {
+        let index = code - HANGUL_S_BASE;
+        let l = HANGUL_L_BASE + index / HANGUL_N_COUNT;
+        let v = HANGUL_V_BASE + (index % HANGUL_N_COUNT) / HANGUL_T_COUNT;
+        let t = HANGUL_T_BASE + index % HANGUL_T_COUNT;
+        out.extend(char::from_u32(l));
+        out.extend(char::from_u32(v));
+        if t != HANGUL_T_BASE {
+            out.extend(char::from_u32(t));
+        }
+        return;
+    }
+
+    match decompositions().get(&ch) {
+        Some(&(base, mark)) => {
+            decompose_char(base, out);
+            out.push(mark);
+        }
+        None => out.push(ch),
+    }
+}
+
+fn compose_pair(first: char, second: char) -> Option<char> {
+    let (a, b) = (first as u32, second as u32);
+
+    // Hangul LV and LV + T
+    if (HANGUL_L_BASE..HANGUL_L_BASE + HANGUL_L_COUNT).contains(&a)
+        && (HANGUL_V_BASE..HANGUL_V_BASE + HANGUL_V_COUNT).contains(&b)
+    {
+        let index = (a - HANGUL_L_BASE) * HANGUL_N_COUNT + (b - HANGUL_V_BASE) * HANGUL_T_COUNT;
+        return char::from_u32(HANGUL_S_BASE + index);
+    }
+    if (HANGUL_S_BASE..HANGUL_S_BASE + HANGUL_S_COUNT).contains(&a)
+        && (a - HANGUL_S_BASE).is_multiple_of(HANGUL_T_COUNT)
+        && (HANGUL_T_BASE + 1..HANGUL_T_BASE + HANGUL_T_COUNT).contains(&b)
+    {
+        return char::from_u32(a + (b - HANGUL_T_BASE));
+    }
+
+    compositions().get(&(first, second)).copied()
+}
+
+/// Canonical decomposition with canonical ordering (NFD)
+pub fn nfd(s: &str) -> String {
+    let mut chars = Vec::with_capacity(s.len());
+    for ch in s.chars() {
+        decompose_char(ch, &mut chars);
+    }
+
+    // Canonical ordering: stable sort each run of non-starters by class
+    let mut start = 0;
+    while start < chars.len() {
+        if combining_class(chars[start]) == 0 {
+            start += 1;
+            continue;
+        }
+        let mut end = start;
+        while end < chars.len() && combining_class(chars[end]) != 0 {
+            end += 1;
+        }
+        chars[start..end].sort_by_key(|&c| combining_class(c));
+        start = end;
+    }
+
+    chars.into_iter().collect()
+}
+
+/// Canonical composition (NFC)
+pub fn nfc(s: &str) -> String {
+    // Fast path: ASCII is always in NFC
+    if s.is_ascii() {
+        return s.to_string();
+    }
+
+    let mut result: Vec<char> = Vec::with_capacity(s.len());
+    let mut starter: Option<usize> = None;
+    let mut last_class = 0u8;
+
+    for ch in nfd(s).chars() {
+        let class = combining_class(ch);
+        if let Some(pos) = starter {
+            let adjacent = result.len() == pos + 1;
+            let blocked = !adjacent && (last_class == 0 || last_class >= class);
+            if !blocked {
+                if let Some(composed) = compose_pair(result[pos], ch) {
+                    result[pos] = composed;
+                    continue;
+                }
+            }
+        }
+        if class == 0 {
+            starter = Some(result.len());
+        }
+        last_class = class;
+        result.push(ch);
+    }
+
+    result.into_iter().collect()
+}
+
+/// Is `s` already in NFC?
+pub fn is_nfc(s: &str) -> bool {
+    s.is_ascii() || nfc(s) == s
+}
+
+/// Full case folding for caseless comparison
+///
+/// Lowercases, expands the characters whose folds are multi-character
+/// (`ß` → `ss`), and unifies final sigma.
+pub fn case_fold(s: &str) -> String {
+    let mut folded = String::with_capacity(s.len());
+    for ch in s.chars() {
+        match ch {
+            'ß' | 'ẞ' => folded.push_str("ss"),
+            'ς' => folded.push('σ'),
+            'ﬁ' => folded.push_str("fi"),
+            'ﬂ' => folded.push_str("fl"),
+            'ﬀ' => folded.push_str("ff"),
+            _ => folded.extend(ch.to_lowercase()),
+        }
+    }
+    folded
+}
+
+/// Split `s` into extended grapheme clusters
+#[cfg(feature = "graphemes")]
+pub fn graphemes(s: &str) -> Vec<&str> {
+    let mut clusters = Vec::new();
+    let mut start = 0;
+    let mut prev: Option<char> = None;
+    let mut regional_run = 0usize;
+
+    for (offset, ch) in s.char_indices() {
+        if let Some(p) = prev {
+            if is_grapheme_boundary(p, ch, regional_run) {
+                clusters.push(&s[start..offset]);
+                start = offset;
+                regional_run = 0;
+            }
+        }
+        if is_regional_indicator(ch) {
+            regional_run += 1;
+        }
+        prev = Some(ch);
+    }
+    if start < s.len() {
+        clusters.push(&s[start..]);
+    }
+    clusters
+}
+
+#[cfg(feature = "graphemes")]
+fn is_grapheme_boundary(prev: char, next: char, regional_run: usize) -> bool {
+    const ZWJ: char = '\u{200D}';
+
+    if prev == '\r' && next == '\n' {
+        return false;
+    }
+    if prev.is_control() || next.is_control() {
+        return true;
+    }
+    if is_combining_mark(next) || is_grapheme_extend(next) || next == ZWJ {
+        return false;
+    }
+    if prev == ZWJ && is_pictographic(next) {
+        return false;
+    }
+    if is_regional_indicator(prev) && is_regional_indicator(next) {
+        return regional_run.is_multiple_of(2);
+    }
+    !hangul_joins(prev, next)
+}
+
+#[cfg(feature = "graphemes")]
+fn is_grapheme_extend(ch: char) -> bool {
+    matches!(ch, '\u{FE00}'..='\u{FE0F}' | '\u{1F3FB}'..='\u{1F3FF}' | '\u{E0020}'..='\u{E007F}')
+}
+
+#[cfg(feature = "graphemes")]
+fn is_regional_indicator(ch: char) -> bool {
+    ('\u{1F1E6}'..='\u{1F1FF}').contains(&ch)
+}
+
+#[cfg(feature = "graphemes")]
+fn is_pictographic(ch: char) -> bool {
+    matches!(ch, '\u{2600}'..='\u{27BF}' | '\u{1F000}'..='\u{1FAFF}')
+}
+
+#[cfg(feature = "graphemes")]
+fn hangul_joins(prev: char, next: char) -> bool {
+    #[derive(PartialEq)]
+    enum Jamo {
+        L,
+        V,
+        T,
+        Lv,
+        Lvt,
+        Other,
+    }
+    let kind = |ch: char| {
+        let code = ch as u32;
+        match code {
+            0x1100..=0x115F | 0xA960..=0xA97C => Jamo::L,
+            0x1160..=0x11A7 | 0xD7B0..=0xD7C6 => Jamo::V,
+            0x11A8..=0x11FF | 0xD7CB..=0xD7FB => Jamo::T,
+            _ if (HANGUL_S_BASE..HANGUL_S_BASE + HANGUL_S_COUNT).contains(&code) => {
+                if (code - HANGUL_S_BASE).is_multiple_of(HANGUL_T_COUNT) {
+                    Jamo::Lv
+                } else {
+                    Jamo::Lvt
+                }
+            }
+            _ => Jamo::Other,
+        }
+    };
+    matches!(
+        (kind(prev), kind(next)),
+        (Jamo::L, Jamo::L | Jamo::V | Jamo::Lv | Jamo::Lvt)
+            | (Jamo::Lv | Jamo::V, Jamo::V | Jamo::T)
+            | (Jamo::Lvt | Jamo::T, Jamo::T)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nfc_composes_latin() {
+        assert_eq!(nfc("cafe\u{0301}"), "caf\u{00E9}");
+        assert_eq!(nfc("A\u{030A}"), "\u{00C5}");
+        assert!(is_nfc("caf\u{00E9}"));
+        assert!(!is_nfc("cafe\u{0301}"));
+    }
+
+    #[test]
+    fn test_nfc_reorders_marks() {
+        // dot below (220) sorts before circumflex (230); both compose onto `a`
+        assert_eq!(nfc("a\u{0302}\u{0323}"), "\u{1EAD}");
+    }
+
+    #[test]
+    fn test_hangul_round_trip() {
+        let syllable = "\u{D55C}";
+        let decomposed = nfd(syllable);
+        assert_eq!(decomposed.chars().count(), 3);
+        assert_eq!(nfc(&decomposed), syllable);
+    }
+
+    #[test]
+    fn test_case_fold() {
+        assert_eq!(case_fold("Straße"), "strasse");
+        assert_eq!(case_fold("ΣΑΣ"), case_fold("σας"));
+    }
+
+    #[test]
+    fn test_identifier_classes() {
+        assert!(is_ident_start('é'));
+        assert!(is_ident_start('_'));
+        assert!(!is_ident_start('1'));
+        assert!(is_ident_continue('\u{0301}'));
+    }
+
+    #[cfg(feature = "graphemes")]
+    #[test]
+    fn test_graphemes() {
+        assert_eq!(graphemes("e\u{0301}x"), vec!["e\u{0301}", "x"]);
+        assert_eq!(graphemes("\r\n").len(), 1);
+        assert_eq!(graphemes("\u{1F1EB}\u{1F1F7}\u{1F1E9}\u{1F1EA}").len(), 2);
+        assert_eq!(graphemes("\u{1F469}\u{200D}\u{1F4BB}").len(), 1);
+    }
+}
@@ -0,0 +1,818 @@
+//! Traversals of the AST
+//!
+//! [`Visit`] walks a tree by reference and [`Fold`] rebuilds one by value.
+//! Each has a method per node type whose default recurses into the node's
+//! children, so an analysis overrides only the nodes it cares about:
+//!
+//! ```
+//! use solo::ast::{Expr, ExprKind, Exprs};
+//! use solo::visit::{self, Visit};
+//! use solo::Phase;
+//!
+//! /// Counts calls anywhere in a program
+//! struct Calls<'a>(&'a Exprs, usize);
+//!
+//! impl<'a> Visit<'a> for Calls<'a> {
+//!     fn exprs(&self) -> &'a Exprs {
+//!         self.0
+//!     }
+//!
+//!     fn visit_expr(&mut self, expr: &Expr) {
+//!         if let ExprKind::Call(..) = expr.kind {
+//!             self.1 += 1;
+//!         }
+//!         visit::walk_expr(self, expr);
+//!     }
+//! }
+//!
+//! let source = "fn main() { print(abs(-1)); }";
+//! let program = solo::compile_until(source, Phase::Parse).unwrap();
+//! let program = program.into_ast().unwrap();
+//! let mut calls = Calls(&program.exprs, 0);
+//! calls.visit_program(&program);
+//! assert_eq!(calls.1, 2);
+//! ```
+//!
+//! An overriding method calls the matching `walk_*` (for `Visit`) or
+//! `noop_fold_*` (for `Fold`) function to carry on into the children, or
+//! leaves it out to skip them.
+
+use crate::ast::*;
+
+/// Walks the AST by reference
+///
+/// A visitor holds the [`Exprs`] of the program it walks, in which the
+/// `walk_*` functions look up the expressions nested in others.
+pub trait Visit<'a> {
+    fn exprs(&self) -> &'a Exprs;
+
+    fn visit_program(&mut self, program: &Program) {
+        walk_program(self, program);
+    }
+
+    fn visit_item(&mut self, item: &Item) {
+        walk_item(self, item);
+    }
+
+    fn visit_function(&mut self, function: &Function) {
+        walk_function(self, function);
+    }
+
+    fn visit_param(&mut self, param: &Param) {
+        walk_param(self, param);
+    }
+
+    fn visit_impl(&mut self, block: &ImplBlock) {
+        walk_impl(self, block);
+    }
+
+    fn visit_extern(&mut self, block: &ExternBlock) {
+        walk_extern(self, block);
+    }
+
+    fn visit_extern_fn(&mut self, function: &ExternFn) {
+        walk_extern_fn(self, function);
+    }
+
+    fn visit_const(&mut self, item: &ConstItem) {
+        walk_const(self, item);
+    }
+
+    fn visit_type_alias(&mut self, alias: &TypeAlias) {
+        walk_type_alias(self, alias);
+    }
+
+    fn visit_enum(&mut self, def: &EnumDef) {
+        walk_enum(self, def);
+    }
+
+    fn visit_variant(&mut self, variant: &Variant) {
+        walk_variant(self, variant);
+    }
+
+    fn visit_struct(&mut self, def: &StructDef) {
+        walk_struct(self, def);
+    }
+
+    fn visit_field_def(&mut self, field: &FieldDef) {
+        walk_field_def(self, field);
+    }
+
+    fn visit_type(&mut self, ty: &TypeExpr) {
+        walk_type(self, ty);
+    }
+
+    fn visit_block(&mut self, block: &Block) {
+        walk_block(self, block);
+    }
+
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_let(&mut self, stmt: &LetStmt) {
+        walk_let(self, stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        walk_expr(self, expr);
+    }
+
+    fn visit_field_init(&mut self, field: &FieldInit) {
+        walk_field_init(self, field);
+    }
+
+    fn visit_arm(&mut self, arm: &Arm) {
+        walk_arm(self, arm);
+    }
+}
+
+pub fn walk_program<'a, V: Visit<'a> + ?Sized>(v: &mut V, program: &Program) {
+    for item in &program.items {
+        v.visit_item(item);
+    }
+}
+
+pub fn walk_item<'a, V: Visit<'a> + ?Sized>(v: &mut V, item: &Item) {
+    match item {
+        Item::Function(function) => v.visit_function(function),
+        Item::Const(item) => v.visit_const(item),
+        Item::Enum(def) => v.visit_enum(def),
+        Item::Struct(def) => v.visit_struct(def),
+        Item::Impl(block) => v.visit_impl(block),
+        Item::Extern(block) => v.visit_extern(block),
+        Item::TypeAlias(alias) => v.visit_type_alias(alias),
+    }
+}
+
+pub fn walk_function<'a, V: Visit<'a> + ?Sized>(v: &mut V, function: &Function) {
+    for param in &function.params {
+        v.visit_param(param);
+    }
+    if let Some(ty) = &function.return_type {
+        v.visit_type(ty);
+    }
+    v.visit_block(&function.body);
+}
+
+pub fn walk_param<'a, V: Visit<'a> + ?Sized>(v: &mut V, param: &Param) {
+    v.visit_type(&param.ty);
+}
+
+pub fn walk_impl<'a, V: Visit<'a> + ?Sized>(v: &mut V, block: &ImplBlock) {
+    for method in &block.methods {
+        v.visit_function(method);
+    }
+}
+
+pub fn walk_extern<'a, V: Visit<'a> + ?Sized>(v: &mut V, block: &ExternBlock) {
+    for function in &block.functions {
+        v.visit_extern_fn(function);
+    }
+}
+
+pub fn walk_extern_fn<'a, V: Visit<'a> + ?Sized>(v: &mut V, function: &ExternFn) {
+    for param in &function.params {
+        v.visit_param(param);
+    }
+    if let Some(ty) = &function.return_type {
+        v.visit_type(ty);
+    }
+}
+
+pub fn walk_const<'a, V: Visit<'a> + ?Sized>(v: &mut V, item: &ConstItem) {
+    v.visit_type(&item.ty);
+    v.visit_expr(&item.value);
+}
+
+pub fn walk_type_alias<'a, V: Visit<'a> + ?Sized>(v: &mut V, alias: &TypeAlias) {
+    v.visit_type(&alias.ty);
+}
+
+pub fn walk_enum<'a, V: Visit<'a> + ?Sized>(v: &mut V, def: &EnumDef) {
+    for variant in &def.variants {
+        v.visit_variant(variant);
+    }
+}
+
+pub fn walk_variant<'a, V: Visit<'a> + ?Sized>(v: &mut V, variant: &Variant) {
+    if let Some(discriminant) = &variant.discriminant {
+        v.visit_expr(discriminant);
+    }
+}
+
+pub fn walk_struct<'a, V: Visit<'a> + ?Sized>(v: &mut V, def: &StructDef) {
+    for field in &def.fields {
+        v.visit_field_def(field);
+    }
+}
+
+pub fn walk_field_def<'a, V: Visit<'a> + ?Sized>(v: &mut V, field: &FieldDef) {
+    v.visit_type(&field.ty);
+}
+
+pub fn walk_type<'a, V: Visit<'a> + ?Sized>(v: &mut V, ty: &TypeExpr) {
+    match ty {
+        TypeExpr::Named(..) | TypeExpr::Unit | TypeExpr::Never(_) => {}
+        TypeExpr::Generic { args, .. } => {
+            for arg in args {
+                v.visit_type(arg);
+            }
+        }
+        TypeExpr::Ref { inner, .. }
+        | TypeExpr::Ptr { inner, .. }
+        | TypeExpr::Slice { elem: inner } => v.visit_type(inner),
+        TypeExpr::Array { elem, len } => {
+            v.visit_type(elem);
+            v.visit_expr(len);
+        }
+    }
+}
+
+pub fn walk_block<'a, V: Visit<'a> + ?Sized>(v: &mut V, block: &Block) {
+    for stmt in &block.stmts {
+        v.visit_stmt(stmt);
+    }
+    if let Some(tail) = block.tail {
+        v.visit_expr(&v.exprs()[tail]);
+    }
+}
+
+pub fn walk_stmt<'a, V: Visit<'a> + ?Sized>(v: &mut V, stmt: &Stmt) {
+    match stmt {
+        Stmt::Let(stmt) => v.visit_let(stmt),
+        Stmt::Const(item) => v.visit_const(item),
+        Stmt::Expr(expr) | Stmt::Defer(expr) => v.visit_expr(expr),
+    }
+}
+
+pub fn walk_let<'a, V: Visit<'a> + ?Sized>(v: &mut V, stmt: &LetStmt) {
+    if let Some(ty) = &stmt.ty {
+        v.visit_type(ty);
+    }
+    if let Some(value) = &stmt.value {
+        v.visit_expr(value);
+    }
+}
+
+pub fn walk_expr<'a, V: Visit<'a> + ?Sized>(v: &mut V, expr: &Expr) {
+    match &expr.kind {
+        ExprKind::Int(..)
+        | ExprKind::Float(..)
+        | ExprKind::Str(_)
+        | ExprKind::Bool(_)
+        | ExprKind::Path(_) => {}
+        ExprKind::Unary(_, operand) | ExprKind::Field(operand, _) => {
+            v.visit_expr(&v.exprs()[*operand])
+        }
+        ExprKind::Cast(value, ty) => {
+            v.visit_expr(&v.exprs()[*value]);
+            v.visit_type(ty);
+        }
+        ExprKind::Binary(_, left, right)
+        | ExprKind::Assign(left, right)
+        | ExprKind::Index(left, right)
+        | ExprKind::ArrayRepeat(left, right) => {
+            v.visit_expr(&v.exprs()[*left]);
+            v.visit_expr(&v.exprs()[*right]);
+        }
+        ExprKind::Call(callee, args) | ExprKind::MethodCall(callee, _, args) => {
+            v.visit_expr(&v.exprs()[*callee]);
+            for arg in args {
+                v.visit_expr(arg);
+            }
+        }
+        ExprKind::StructLit(_, fields) => {
+            for field in fields {
+                v.visit_field_init(field);
+            }
+        }
+        ExprKind::Distribution(_, args) | ExprKind::Array(args) => {
+            for arg in args {
+                v.visit_expr(arg);
+            }
+        }
+        ExprKind::If(cond, then, otherwise) => {
+            v.visit_expr(&v.exprs()[*cond]);
+            v.visit_block(then);
+            if let Some(otherwise) = otherwise {
+                v.visit_expr(&v.exprs()[*otherwise]);
+            }
+        }
+        ExprKind::While(cond, body) | ExprKind::For(_, cond, body) => {
+            v.visit_expr(&v.exprs()[*cond]);
+            v.visit_block(body);
+        }
+        ExprKind::Match(scrutinee, arms) => {
+            v.visit_expr(&v.exprs()[*scrutinee]);
+            for arm in arms {
+                v.visit_arm(arm);
+            }
+        }
+        ExprKind::Range(start, end, _) => {
+            for end in [start, end].into_iter().flatten() {
+                v.visit_expr(&v.exprs()[*end]);
+            }
+        }
+        ExprKind::Arena(_, allocator, block) => {
+            if let Some(allocator) = allocator {
+                v.visit_expr(&v.exprs()[*allocator]);
+            }
+            v.visit_block(block)
+        }
+        ExprKind::Block(block) | ExprKind::Unsafe(block) => v.visit_block(block),
+        ExprKind::Return(value) => {
+            if let Some(value) = value {
+                v.visit_expr(&v.exprs()[*value]);
+            }
+        }
+        ExprKind::Become(call) => v.visit_expr(&v.exprs()[*call]),
+        ExprKind::Asm(_, operands) => {
+            for operand in operands {
+                v.visit_expr(&operand.expr);
+            }
+        }
+    }
+}
+
+pub fn walk_field_init<'a, V: Visit<'a> + ?Sized>(v: &mut V, field: &FieldInit) {
+    v.visit_expr(&field.value);
+}
+
+pub fn walk_arm<'a, V: Visit<'a> + ?Sized>(v: &mut V, arm: &Arm) {
+    if let Some(guard) = arm.guard {
+        v.visit_expr(&v.exprs()[guard]);
+    }
+    v.visit_expr(&v.exprs()[arm.value]);
+}
+
+/// Rebuilds the AST by value, for transforms that replace nodes
+///
+/// A folder holds the [`Exprs`] it rebuilds nested expressions in:
+/// [`noop_fold_program`] moves the program's into it, and back once the
+/// items are folded. A nested expression is folded in place, keeping its
+/// [`ExprId`].
+pub trait Fold {
+    fn exprs(&mut self) -> &mut Exprs;
+
+    fn fold_program(&mut self, program: Program) -> Program {
+        noop_fold_program(self, program)
+    }
+
+    fn fold_item(&mut self, item: Item) -> Item {
+        noop_fold_item(self, item)
+    }
+
+    fn fold_function(&mut self, function: Function) -> Function {
+        noop_fold_function(self, function)
+    }
+
+    fn fold_param(&mut self, param: Param) -> Param {
+        noop_fold_param(self, param)
+    }
+
+    fn fold_impl(&mut self, block: ImplBlock) -> ImplBlock {
+        noop_fold_impl(self, block)
+    }
+
+    fn fold_extern(&mut self, block: ExternBlock) -> ExternBlock {
+        noop_fold_extern(self, block)
+    }
+
+    fn fold_extern_fn(&mut self, function: ExternFn) -> ExternFn {
+        noop_fold_extern_fn(self, function)
+    }
+
+    fn fold_const(&mut self, item: ConstItem) -> ConstItem {
+        noop_fold_const(self, item)
+    }
+
+    fn fold_type_alias(&mut self, alias: TypeAlias) -> TypeAlias {
+        noop_fold_type_alias(self, alias)
+    }
+
+    fn fold_enum(&mut self, def: EnumDef) -> EnumDef {
+        noop_fold_enum(self, def)
+    }
+
+    fn fold_variant(&mut self, variant: Variant) -> Variant {
+        noop_fold_variant(self, variant)
+    }
+
+    fn fold_struct(&mut self, def: StructDef) -> StructDef {
+        noop_fold_struct(self, def)
+    }
+
+    fn fold_field_def(&mut self, field: FieldDef) -> FieldDef {
+        noop_fold_field_def(self, field)
+    }
+
+    fn fold_type(&mut self, ty: TypeExpr) -> TypeExpr {
+        noop_fold_type(self, ty)
+    }
+
+    fn fold_block(&mut self, block: Block) -> Block {
+        noop_fold_block(self, block)
+    }
+
+    fn fold_stmt(&mut self, stmt: Stmt) -> Stmt {
+        noop_fold_stmt(self, stmt)
+    }
+
+    fn fold_let(&mut self, stmt: LetStmt) -> LetStmt {
+        noop_fold_let(self, stmt)
+    }
+
+    fn fold_expr(&mut self, expr: Expr) -> Expr {
+        noop_fold_expr(self, expr)
+    }
+
+    fn fold_field_init(&mut self, field: FieldInit) -> FieldInit {
+        noop_fold_field_init(self, field)
+    }
+
+    fn fold_arm(&mut self, arm: Arm) -> Arm {
+        noop_fold_arm(self, arm)
+    }
+}
+
+pub fn noop_fold_program<F: Fold + ?Sized>(f: &mut F, mut program: Program) -> Program {
+    std::mem::swap(f.exprs(), &mut program.exprs);
+    let items = program
+        .items
+        .into_iter()
+        .map(|item| f.fold_item(item))
+        .collect();
+    std::mem::swap(f.exprs(), &mut program.exprs);
+    Program { items, ..program }
+}
+
+pub fn noop_fold_item<F: Fold + ?Sized>(f: &mut F, item: Item) -> Item {
+    match item {
+        Item::Function(function) => Item::Function(f.fold_function(function)),
+        Item::Const(item) => Item::Const(f.fold_const(item)),
+        Item::Enum(def) => Item::Enum(f.fold_enum(def)),
+        Item::Struct(def) => Item::Struct(f.fold_struct(def)),
+        Item::Impl(block) => Item::Impl(f.fold_impl(block)),
+        Item::Extern(block) => Item::Extern(f.fold_extern(block)),
+        Item::TypeAlias(alias) => Item::TypeAlias(f.fold_type_alias(alias)),
+    }
+}
+
+pub fn noop_fold_function<F: Fold + ?Sized>(f: &mut F, function: Function) -> Function {
+    Function {
+        params: fold_params(f, function.params),
+        return_type: function.return_type.map(|ty| f.fold_type(ty)),
+        body: f.fold_block(function.body),
+        ..function
+    }
+}
+
+fn fold_params<F: Fold + ?Sized>(f: &mut F, params: Vec<Param>) -> Vec<Param> {
+    params
+        .into_iter()
+        .map(|param| f.fold_param(param))
+        .collect()
+}
+
+pub fn noop_fold_param<F: Fold + ?Sized>(f: &mut F, param: Param) -> Param {
+    Param {
+        ty: f.fold_type(param.ty),
+        ..param
+    }
+}
+
+pub fn noop_fold_impl<F: Fold + ?Sized>(f: &mut F, block: ImplBlock) -> ImplBlock {
+    ImplBlock {
+        methods: block
+            .methods
+            .into_iter()
+            .map(|method| f.fold_function(method))
+            .collect(),
+        ..block
+    }
+}
+
+pub fn noop_fold_extern<F: Fold + ?Sized>(f: &mut F, block: ExternBlock) -> ExternBlock {
+    ExternBlock {
+        functions: block
+            .functions
+            .into_iter()
+            .map(|function| f.fold_extern_fn(function))
+            .collect(),
+        ..block
+    }
+}
+
+pub fn noop_fold_extern_fn<F: Fold + ?Sized>(f: &mut F, function: ExternFn) -> ExternFn {
+    ExternFn {
+        params: fold_params(f, function.params),
+        return_type: function.return_type.map(|ty| f.fold_type(ty)),
+        ..function
+    }
+}
+
+pub fn noop_fold_const<F: Fold + ?Sized>(f: &mut F, item: ConstItem) -> ConstItem {
+    ConstItem {
+        ty: f.fold_type(item.ty),
+        value: f.fold_expr(item.value),
+        ..item
+    }
+}
+
+pub fn noop_fold_type_alias<F: Fold + ?Sized>(f: &mut F, alias: TypeAlias) -> TypeAlias {
+    TypeAlias {
+        ty: f.fold_type(alias.ty),
+        ..alias
+    }
+}
+
+pub fn noop_fold_enum<F: Fold + ?Sized>(f: &mut F, def: EnumDef) -> EnumDef {
+    EnumDef {
+        variants: def
+            .variants
+            .into_iter()
+            .map(|variant| f.fold_variant(variant))
+            .collect(),
+        ..def
+    }
+}
+
+pub fn noop_fold_variant<F: Fold + ?Sized>(f: &mut F, variant: Variant) -> Variant {
+    Variant {
+        discriminant: variant.discriminant.map(|expr| f.fold_expr(expr)),
+        ..variant
+    }
+}
+
+pub fn noop_fold_struct<F: Fold + ?Sized>(f: &mut F, def: StructDef) -> StructDef {
+    StructDef {
+        fields: def
+            .fields
+            .into_iter()
+            .map(|field| f.fold_field_def(field))
+            .collect(),
+        ..def
+    }
+}
+
+pub fn noop_fold_field_def<F: Fold + ?Sized>(f: &mut F, field: FieldDef) -> FieldDef {
+    FieldDef {
+        ty: f.fold_type(field.ty),
+        ..field
+    }
+}
+
+pub fn noop_fold_type<F: Fold + ?Sized>(f: &mut F, ty: TypeExpr) -> TypeExpr {
+    match ty {
+        TypeExpr::Named(..) | TypeExpr::Unit | TypeExpr::Never(_) => ty,
+        TypeExpr::Generic {
+            name,
+            args,
+            bounds,
+            span,
+        } => TypeExpr::Generic {
+            name,
+            args: args.into_iter().map(|arg| f.fold_type(arg)).collect(),
+            bounds,
+            span,
+        },
+        TypeExpr::Ref { mutable, inner } => TypeExpr::Ref {
+            mutable,
+            inner: Box::new(f.fold_type(*inner)),
+        },
+        TypeExpr::Ptr { mutable, inner } => TypeExpr::Ptr {
+            mutable,
+            inner: Box::new(f.fold_type(*inner)),
+        },
+        TypeExpr::Array { elem, len } => TypeExpr::Array {
+            elem: Box::new(f.fold_type(*elem)),
+            len: Box::new(f.fold_expr(*len)),
+        },
+        TypeExpr::Slice { elem } => TypeExpr::Slice {
+            elem: Box::new(f.fold_type(*elem)),
+        },
+    }
+}
+
+pub fn noop_fold_block<F: Fold + ?Sized>(f: &mut F, block: Block) -> Block {
+    Block {
+        stmts: block
+            .stmts
+            .into_iter()
+            .map(|stmt| f.fold_stmt(stmt))
+            .collect(),
+        tail: block.tail.map(|tail| fold_id(f, tail)),
+        ..block
+    }
+}
+
+pub fn noop_fold_stmt<F: Fold + ?Sized>(f: &mut F, stmt: Stmt) -> Stmt {
+    match stmt {
+        Stmt::Let(stmt) => Stmt::Let(f.fold_let(stmt)),
+        Stmt::Const(item) => Stmt::Const(f.fold_const(item)),
+        Stmt::Expr(expr) => Stmt::Expr(f.fold_expr(expr)),
+        Stmt::Defer(expr) => Stmt::Defer(f.fold_expr(expr)),
+    }
+}
+
+pub fn noop_fold_let<F: Fold + ?Sized>(f: &mut F, stmt: LetStmt) -> LetStmt {
+    LetStmt {
+        ty: stmt.ty.map(|ty| f.fold_type(ty)),
+        value: stmt.value.map(|value| f.fold_expr(value)),
+        ..stmt
+    }
+}
+
+pub fn noop_fold_expr<F: Fold + ?Sized>(f: &mut F, expr: Expr) -> Expr {
+    let kind = match expr.kind {
+        kind @ (ExprKind::Int(..)
+        | ExprKind::Float(..)
+        | ExprKind::Str(_)
+        | ExprKind::Bool(_)
+        | ExprKind::Path(_)) => kind,
+        ExprKind::Unary(op, operand) => ExprKind::Unary(op, fold_id(f, operand)),
+        ExprKind::Cast(value, ty) => ExprKind::Cast(fold_id(f, value), f.fold_type(ty)),
+        ExprKind::Binary(op, left, right) => {
+            ExprKind::Binary(op, fold_id(f, left), fold_id(f, right))
+        }
+        ExprKind::Assign(target, value) => ExprKind::Assign(fold_id(f, target), fold_id(f, value)),
+        ExprKind::Call(callee, args) => ExprKind::Call(fold_id(f, callee), fold_exprs(f, args)),
+        ExprKind::Index(base, index) => ExprKind::Index(fold_id(f, base), fold_id(f, index)),
+        ExprKind::Field(base, name) => ExprKind::Field(fold_id(f, base), name),
+        ExprKind::MethodCall(receiver, method, args) => {
+            ExprKind::MethodCall(fold_id(f, receiver), method, fold_exprs(f, args))
+        }
+        ExprKind::StructLit(name, fields) => ExprKind::StructLit(
+            name,
+            fields
+                .into_iter()
+                .map(|field| f.fold_field_init(field))
+                .collect(),
+        ),
+        ExprKind::Distribution(name, args) => ExprKind::Distribution(name, fold_exprs(f, args)),
+        ExprKind::Array(elems) => ExprKind::Array(fold_exprs(f, elems)),
+        ExprKind::ArrayRepeat(value, len) => {
+            ExprKind::ArrayRepeat(fold_id(f, value), fold_id(f, len))
+        }
+        ExprKind::If(cond, then, otherwise) => ExprKind::If(
+            fold_id(f, cond),
+            f.fold_block(then),
+            otherwise.map(|otherwise| fold_id(f, otherwise)),
+        ),
+        ExprKind::While(cond, body) => ExprKind::While(fold_id(f, cond), f.fold_block(body)),
+        ExprKind::For(name, iterable, body) => {
+            ExprKind::For(name, fold_id(f, iterable), f.fold_block(body))
+        }
+        ExprKind::Match(scrutinee, arms) => ExprKind::Match(
+            fold_id(f, scrutinee),
+            arms.into_iter().map(|arm| f.fold_arm(arm)).collect(),
+        ),
+        ExprKind::Range(start, end, inclusive) => ExprKind::Range(
+            start.map(|start| fold_id(f, start)),
+            end.map(|end| fold_id(f, end)),
+            inclusive,
+        ),
+        ExprKind::Block(block) => ExprKind::Block(f.fold_block(block)),
+        ExprKind::Arena(name, allocator, block) => ExprKind::Arena(
+            name,
+            allocator.map(|allocator| fold_id(f, allocator)),
+            f.fold_block(block),
+        ),
+        ExprKind::Unsafe(block) => ExprKind::Unsafe(f.fold_block(block)),
+        ExprKind::Return(value) => ExprKind::Return(value.map(|value| fold_id(f, value))),
+        ExprKind::Become(call) => ExprKind::Become(fold_id(f, call)),
+        ExprKind::Asm(template, operands) => ExprKind::Asm(
+            template,
+            operands
+                .into_iter()
+                .map(|operand| AsmOperand {
+                    expr: f.fold_expr(operand.expr),
+                    ..operand
+                })
+                .collect(),
+        ),
+    };
+    Expr { kind, ..expr }
+}
+
+/// Fold a nested expression in place
+fn fold_id<F: Fold + ?Sized>(f: &mut F, id: ExprId) -> ExprId {
+    let expr = f.exprs().take(id);
+    f.exprs()[id] = f.fold_expr(expr);
+    id
+}
+
+fn fold_exprs<F: Fold + ?Sized>(f: &mut F, exprs: Vec<Expr>) -> Vec<Expr> {
+    exprs.into_iter().map(|expr| f.fold_expr(expr)).collect()
+}
+
+pub fn noop_fold_field_init<F: Fold + ?Sized>(f: &mut F, field: FieldInit) -> FieldInit {
+    FieldInit {
+        value: f.fold_expr(field.value),
+        ..field
+    }
+}
+
+pub fn noop_fold_arm<F: Fold + ?Sized>(f: &mut F, arm: Arm) -> Arm {
+    Arm {
+        guard: arm.guard.map(|guard| fold_id(f, guard)),
+        value: fold_id(f, arm.value),
+        ..arm
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Phase;
+
+    fn parse(source: &str) -> Program {
+        crate::compile_until(source, Phase::Parse)
+            .unwrap()
+            .into_ast()
+            .unwrap()
+    }
+
+    /// Spans of every expression, in the order they are visited
+    struct Spans<'a>(&'a Exprs, Vec<Span>);
+
+    impl<'a> Visit<'a> for Spans<'a> {
+        fn exprs(&self) -> &'a Exprs {
+            self.0
+        }
+
+        fn visit_expr(&mut self, expr: &Expr) {
+            self.1.push(expr.span);
+            walk_expr(self, expr);
+        }
+    }
+
+    #[test]
+    fn test_visit() {
+        let program = parse(
+            "const N: i64 = 1;\n\
+             fn main() {\n    let x = [0; N];\n    print(x[0] + 2);\n}",
+        );
+        let mut spans = Spans(&program.exprs, Vec::new());
+        spans.visit_program(&program);
+        let columns: Vec<(usize, usize)> = spans.1.iter().map(|s| (s.line, s.column)).collect();
+        assert_eq!(
+            columns,
+            // Calls are at their `(`, indexing at its `[` and binary
+            // expressions at their operator
+            [
+                (1, 16),
+                (3, 13),
+                (3, 14),
+                (3, 17),
+                (4, 10),
+                (4, 5),
+                (4, 16),
+                (4, 12),
+                (4, 11),
+                (4, 13),
+                (4, 18),
+            ]
+        );
+    }
+
+    /// Replaces integer literals with their double
+    #[derive(Default)]
+    struct Double(Exprs);
+
+    impl Fold for Double {
+        fn exprs(&mut self) -> &mut Exprs {
+            &mut self.0
+        }
+
+        fn fold_expr(&mut self, expr: Expr) -> Expr {
+            match expr.kind {
+                ExprKind::Int(n, suffix) => Expr::new(ExprKind::Int(n * 2, suffix), expr.span),
+                _ => noop_fold_expr(self, expr),
+            }
+        }
+    }
+
+    #[test]
+    fn test_fold() {
+        let program = parse("fn main() -> i64 { let x = 3; x + if true { 4 } else { 5 } }");
+        let program = Double::default().fold_program(program);
+        let mut ints = Vec::new();
+        struct Ints<'a, 'b>(&'a Exprs, &'b mut Vec<i64>);
+        impl<'a> Visit<'a> for Ints<'a, '_> {
+            fn exprs(&self) -> &'a Exprs {
+                self.0
+            }
+
+            fn visit_expr(&mut self, expr: &Expr) {
+                if let ExprKind::Int(n, _) = expr.kind {
+                    self.1.push(n);
+                }
+                walk_expr(self, expr);
+            }
+        }
+        Ints(&program.exprs, &mut ints).visit_program(&program);
+        assert_eq!(ints, [6, 8, 10]);
+    }
+}
@@ -0,0 +1,120 @@
+//! The compiler in a browser
+//!
+//! The library builds for `wasm32-unknown-unknown`, so a playground can
+//! check and run programs client-side instead of through `solo serve
+//! --playground`. With the `wasm` feature these functions are exported to
+//! JavaScript by `wasm-bindgen`:
+//!
+//! ```bash
+//! cargo build --lib --release --target wasm32-unknown-unknown --features wasm
+//! wasm-bindgen --target web --out-dir pkg target/wasm32-unknown-unknown/release/solo.wasm
+//! ```
+//!
+//! Each returns a JSON string in the shape of the playground's responses,
+//! with diagnostics in a file named [`playground::FILE_NAME`]. Nothing here
+//! touches the filesystem or spawns processes, and the interpreter has no
+//! time limit: run it in a Web Worker and terminate the worker to stop a
+//! program that does not finish.
+
+use std::path::Path;
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::error::CompileError;
+use crate::interp::{Interpreter, RunOptions};
+use crate::json::Json;
+use crate::playground;
+
+/// Compile `source`, returning `{"ok", "diagnostics"}`
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn compile_to_diagnostics(source: &str, unstable: bool) -> String {
+    let result = stable(source, unstable).and_then(|()| crate::compile(source));
+    outcome(source, result, Vec::new()).to_string()
+}
+
+/// Compile and interpret `source` with the random seed `seed`, returning
+/// `{"ok", "diagnostics", "output", "value"}`
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn run_in_interpreter(source: &str, unstable: bool, seed: u64) -> String {
+    let options = RunOptions {
+        seed,
+        args: vec![playground::FILE_NAME.to_string()],
+        ..RunOptions::default()
+    };
+    let mut output = Vec::new();
+    let result = stable(source, unstable).and_then(|()| {
+        let (program, info) = crate::analyze(source)?;
+        Interpreter::new(&program, &info, &options, &mut output).run_main()
+    });
+    let value = match &result {
+        Ok(value) => Json::Str(value.to_string()),
+        Err(_) => Json::Null,
+    };
+    let output = Json::Str(String::from_utf8_lossy(&output).into_owned());
+    outcome(
+        source,
+        result.map(drop),
+        vec![("output", output), ("value", value)],
+    )
+    .to_string()
+}
+
+fn stable(source: &str, unstable: bool) -> Result<(), CompileError> {
+    if unstable {
+        Ok(())
+    } else {
+        crate::check_stable(source)
+    }
+}
+
+fn outcome(source: &str, result: Result<(), CompileError>, extra: Vec<(&str, Json)>) -> Json {
+    let diagnostics = match result {
+        Ok(()) => Vec::new(),
+        Err(err) => err
+            .in_file(Path::new(playground::FILE_NAME))
+            .to_json(source),
+    };
+    let mut fields = vec![
+        ("ok", Json::Bool(diagnostics.is_empty())),
+        ("diagnostics", Json::Array(diagnostics)),
+    ];
+    fields.extend(extra);
+    Json::object(fields)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_browser_api() {
+        let parse = |json: String| Json::parse(&json).unwrap();
+
+        let compiled = parse(compile_to_diagnostics("fn main() { print(1); }", false));
+        assert_eq!(compiled.get("ok"), Some(&Json::Bool(true)));
+        let failed = parse(compile_to_diagnostics(
+            "fn main() { let x: i32 = true; }",
+            false,
+        ));
+        assert_eq!(failed.get("ok"), Some(&Json::Bool(false)));
+        assert!(failed.to_string().contains(playground::FILE_NAME));
+
+        let ran = parse(run_in_interpreter(
+            "fn main() -> i64 { print(41 + 1); 7 }",
+            false,
+            0,
+        ));
+        assert_eq!(ran.get("ok"), Some(&Json::Bool(true)));
+        assert_eq!(ran.get("output").and_then(Json::as_str), Some("42\n"));
+        assert_eq!(ran.get("value").and_then(Json::as_str), Some("7"));
+
+        let panicked = parse(run_in_interpreter(
+            "fn main() { print(1); panic(\"no\"); }",
+            false,
+            0,
+        ));
+        assert_eq!(panicked.get("ok"), Some(&Json::Bool(false)));
+        assert_eq!(panicked.get("output").and_then(Json::as_str), Some("1\n"));
+    }
+}
@@ -0,0 +1,63 @@
+//! Playground sandbox: programs run through [`sandbox::run_program`], the
+//! way `solo serve --playground` runs them, with the real `solo` executable
+
+use std::path::Path;
+use std::time::Duration;
+
+use solo::sandbox::{self, Outcome};
+
+fn run(source: &str) -> sandbox::ProgramRun {
+    run_unstable(source, false)
+}
+
+fn run_unstable(source: &str, unstable: bool) -> sandbox::ProgramRun {
+    let exe = Path::new(env!("CARGO_BIN_EXE_solo"));
+    sandbox::run_program(exe, source, Duration::from_secs(30), None, unstable)
+        .expect("the sandbox child starts")
+}
+
+#[test]
+fn programs_cannot_touch_files() {
+    let run_ok = run("fn main() { print(1 + 2); }");
+    assert_eq!(run_ok.outcome, Outcome::Passed, "{}", run_ok.stderr);
+    assert_eq!(run_ok.stdout, "3\n");
+
+    let read = run("fn main() { print(io::read_file(\"/etc/hostname\")); }");
+    assert_eq!(read.outcome, Outcome::Failed(Some(1)));
+    assert_eq!(read.stdout, "");
+    assert!(
+        read.stderr
+            .contains("cannot call `io::read_file`: file access is disabled"),
+        "{}",
+        read.stderr
+    );
+
+    let target = std::env::temp_dir().join(format!("solo-playground-{}", std::process::id()));
+    let write = run(&format!(
+        "fn main() {{ io::write_file({:?}, \"owned\"); }}",
+        target.to_str().unwrap()
+    ));
+    assert_eq!(write.outcome, Outcome::Failed(Some(1)));
+    assert!(!target.exists());
+}
+
+#[test]
+fn programs_cannot_call_c() {
+    let source = "#![feature(extern_c)]
+        #[link(c)]
+        extern \"C\" {
+            fn system(command: &str) -> i32;
+        }
+        fn main() {
+            unsafe { system(\"echo owned\"); }
+        }";
+    let run = run_unstable(source, true);
+    assert_eq!(run.outcome, Outcome::Failed(Some(1)));
+    assert_eq!(run.stdout, "");
+    assert!(
+        run.stderr
+            .contains("cannot call C function `system`: calling C is disabled"),
+        "{}",
+        run.stderr
+    );
+}
@@ -0,0 +1,182 @@
+//! Program tests: every `.solo` file in `tests/run-pass` and
+//! `tests/compile-fail`
+//!
+//! A run-pass program is run with `solo run` and must print what the
+//! `.stdout` file next to it holds (nothing, when there is none) and exit
+//! with status 0, or with the status of an `//@ exit-code: <n>` line.
+//!
+//! A compile-fail program must fail `solo check` with exactly the errors
+//! its comments expect. `//~ ERROR <text>` expects an error on its own
+//! line whose message contains the text, or whose code it is (`E0001`);
+//! `//~^ ERROR <text>` expects one on the line above, and each further `^`
+//! one more line up. Every error must be expected, and every expectation
+//! met.
+//!
+//! An `//@ flags: <flags...>` line passes flags such as `--unstable` or
+//! `--release` to either command. After changing what a run-pass program
+//! prints, run `SOLO_BLESS=1 cargo test --test programs` to rewrite its
+//! `.stdout` file, and review the diff.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+use solo::json::Json;
+
+#[test]
+fn run_pass() {
+    let bless = env::var_os("SOLO_BLESS").is_some_and(|value| value != "0");
+    let (dir, files) = programs("run-pass");
+    let mut failures = Vec::new();
+    for file in &files {
+        let source = fs::read_to_string(file).expect("programs are readable");
+        let output = solo(&dir, "run", file, &source);
+        let stdout = String::from_utf8_lossy(&output.stdout);
+
+        let expected_file = file.with_extension("stdout");
+        if bless {
+            if stdout.is_empty() {
+                let _ = fs::remove_file(&expected_file);
+            } else {
+                fs::write(&expected_file, stdout.as_bytes()).expect("snapshots are writable");
+            }
+        }
+        let expected = fs::read_to_string(&expected_file).unwrap_or_default();
+        let status = directive(&source, "exit-code").map_or(0, |code| {
+            code.parse()
+                .unwrap_or_else(|_| panic!("{}: exit-code must be an integer", file.display()))
+        });
+        if output.status.code() != Some(status) {
+            failures.push(format!(
+                "{}: expected exit status {}, found {}\n--- stderr\n{}",
+                file.display(),
+                status,
+                output.status,
+                String::from_utf8_lossy(&output.stderr)
+            ));
+        } else if stdout != expected {
+            failures.push(format!(
+                "{}:\n--- expected stdout\n{}--- actual stdout\n{}",
+                file.display(),
+                expected,
+                stdout
+            ));
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{} of {} run-pass programs failed \
+         (rerun with SOLO_BLESS=1 to update their output):\n\n{}",
+        failures.len(),
+        files.len(),
+        failures.join("\n")
+    );
+}
+
+#[test]
+fn compile_fail() {
+    let (dir, files) = programs("compile-fail");
+    let mut failures = Vec::new();
+    for file in &files {
+        let source = fs::read_to_string(file).expect("programs are readable");
+        let mut expected = expectations(&source);
+        assert!(
+            !expected.is_empty(),
+            "{}: a compile-fail program needs an `//~ ERROR` annotation",
+            file.display()
+        );
+        let output = solo(&dir, "check", file, &source);
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let errors: Vec<Json> = stdout.lines().filter_map(|l| Json::parse(l).ok()).collect();
+        if output.status.success() || errors.is_empty() {
+            failures.push(format!("{}: compiled without errors", file.display()));
+            continue;
+        }
+
+        let mut unexpected = Vec::new();
+        for error in &errors {
+            let line = error.get("line").and_then(Json::as_int);
+            let message = error.get("message").and_then(Json::as_str).unwrap_or("");
+            let code = error.get("code").and_then(Json::as_str);
+            let matched = expected.iter().position(|(expected_line, text)| {
+                line == Some(*expected_line as i64)
+                    && (message.contains(text.as_str()) || code == Some(text.as_str()))
+            });
+            match matched {
+                Some(i) => drop(expected.remove(i)),
+                None => unexpected.push(format!(
+                    "  unexpected error at line {}: {}",
+                    line.map_or("?".to_string(), |line| line.to_string()),
+                    message
+                )),
+            }
+        }
+        let missing = expected.iter().map(|(line, text)| {
+            format!("  expected error at line {} not reported: {}", line, text)
+        });
+        let problems: Vec<String> = unexpected.into_iter().chain(missing).collect();
+        if !problems.is_empty() {
+            failures.push(format!("{}:\n{}", file.display(), problems.join("\n")));
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{} of {} compile-fail programs failed:\n\n{}",
+        failures.len(),
+        files.len(),
+        failures.join("\n")
+    );
+}
+
+/// The directory `tests/<kind>` and its `.solo` files, in order
+fn programs(kind: &str) -> (PathBuf, Vec<PathBuf>) {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join(kind);
+    let mut files: Vec<PathBuf> = fs::read_dir(&dir)
+        .unwrap_or_else(|err| panic!("cannot read {}: {}", dir.display(), err))
+        .map(|entry| entry.expect("test directories are readable").path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "solo"))
+        .collect();
+    files.sort();
+    (dir, files)
+}
+
+/// Run `solo <command>` on `file` from its directory, so that paths in
+/// diagnostics are just the file name
+fn solo(dir: &Path, command: &str, file: &Path, source: &str) -> Output {
+    let flags = directive(source, "flags").unwrap_or("");
+    let mut solo = Command::new(env!("CARGO_BIN_EXE_solo"));
+    solo.arg(command).args(flags.split_whitespace());
+    if command == "check" {
+        solo.arg("--message-format=json");
+    }
+    solo.arg(file.file_name().expect("files have names"))
+        .current_dir(dir)
+        .env_remove("SOLO_BACKTRACE")
+        .output()
+        .expect("the compiler runs")
+}
+
+/// The value of the `//@ <name>: <value>` line of `source`
+fn directive<'a>(source: &'a str, name: &str) -> Option<&'a str> {
+    source.lines().find_map(|line| {
+        let rest = line.trim().strip_prefix("//@")?.trim_start();
+        Some(rest.strip_prefix(name)?.strip_prefix(':')?.trim())
+    })
+}
+
+/// The lines and texts of the `//~ ERROR` annotations of `source`
+fn expectations(source: &str) -> Vec<(usize, String)> {
+    source
+        .lines()
+        .enumerate()
+        .filter_map(|(i, line)| {
+            let annotation = &line[line.find("//~")? + 3..];
+            let carets = annotation.chars().take_while(|&c| c == '^').count();
+            let text = annotation[carets..].trim_start().strip_prefix("ERROR")?;
+            Some((i + 1 - carets, text.trim().to_string()))
+        })
+        .collect()
+}
@@ -0,0 +1,249 @@
+//! The servers answering more than one request: `solo lsp` over one
+//! stream, and the playground and `solo serve --api` over TCP, where every
+//! request may intern names the requests before it did not
+
+use std::io::{BufRead, BufReader, Cursor, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::thread;
+
+use solo::json::Json;
+use solo::playground::{self, Playground};
+use solo::{lsp, rpc};
+
+/// Frame `messages` the way an LSP client sends them
+fn lsp_input(messages: &[Json]) -> Cursor<Vec<u8>> {
+    let mut input = Vec::new();
+    for message in messages {
+        let content = message.to_string();
+        write!(
+            input,
+            "Content-Length: {}\r\n\r\n{}",
+            content.len(),
+            content
+        )
+        .unwrap();
+    }
+    Cursor::new(input)
+}
+
+/// The messages of an LSP server's output
+fn lsp_output(mut output: &[u8]) -> Vec<Json> {
+    let mut messages = Vec::new();
+    while !output.is_empty() {
+        let mut length = 0;
+        loop {
+            let mut header = String::new();
+            output.read_line(&mut header).unwrap();
+            let header = header.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some(value) = header.strip_prefix("Content-Length:") {
+                length = value.trim().parse().unwrap();
+            }
+        }
+        let mut content = vec![0; length];
+        output.read_exact(&mut content).unwrap();
+        messages.push(Json::parse(std::str::from_utf8(&content).unwrap()).unwrap());
+    }
+    messages
+}
+
+fn lsp_request(id: i64, method: &str, params: Json) -> Json {
+    Json::object([
+        ("jsonrpc", Json::str("2.0")),
+        ("id", Json::Int(id)),
+        ("method", Json::str(method)),
+        ("params", params),
+    ])
+}
+
+fn lsp_notification(method: &str, params: Json) -> Json {
+    Json::object([
+        ("jsonrpc", Json::str("2.0")),
+        ("method", Json::str(method)),
+        ("params", params),
+    ])
+}
+
+fn open(uri: &str, text: &str) -> Json {
+    let document = Json::object([
+        ("uri", Json::str(uri)),
+        ("languageId", Json::str("solo")),
+        ("version", Json::Int(1)),
+        ("text", Json::str(text)),
+    ]);
+    lsp_notification(
+        "textDocument/didOpen",
+        Json::object([("textDocument", document)]),
+    )
+}
+
+fn document(uri: &str) -> Json {
+    Json::object([("textDocument", Json::object([("uri", Json::str(uri))]))])
+}
+
+/// The result of the response to request `id`, which must not be an error
+fn result(messages: &[Json], id: i64) -> &Json {
+    let response = messages
+        .iter()
+        .find(|message| message.get("id") == Some(&Json::Int(id)))
+        .unwrap_or_else(|| panic!("no response to request {}", id));
+    assert_eq!(response.get("error"), None, "{}", response);
+    response.get("result").unwrap()
+}
+
+#[test]
+fn lsp_answers_requests_on_documents_opened_earlier() {
+    let first = "fn square(x: i64) -> i64 { x * x }\nfn main() { print(square(3)); }\n";
+    let second = "struct Point { x: i64, y: i64 }\nfn main() { let origin = 0; print(origin); }\n";
+    let input = lsp_input(&[
+        lsp_request(1, "initialize", Json::object::<&str>([])),
+        lsp_notification("initialized", Json::object::<&str>([])),
+        open("file:///first.solo", first),
+        open("file:///second.solo", second),
+        lsp_request(
+            2,
+            "textDocument/semanticTokens/full",
+            document("file:///first.solo"),
+        ),
+        lsp_request(
+            3,
+            "textDocument/documentSymbol",
+            document("file:///first.solo"),
+        ),
+        lsp_request(
+            4,
+            "textDocument/documentSymbol",
+            document("file:///second.solo"),
+        ),
+        lsp_request(
+            5,
+            "textDocument/semanticTokens/full",
+            document("file:///second.solo"),
+        ),
+        lsp_request(6, "shutdown", Json::Null),
+        lsp_notification("exit", Json::Null),
+    ]);
+    let mut output = Vec::new();
+    lsp::serve(input, &mut output, false).unwrap();
+    let messages = lsp_output(&output);
+
+    let names = |id| match result(&messages, id) {
+        Json::Array(symbols) => symbols
+            .iter()
+            .map(|symbol| {
+                symbol
+                    .get("name")
+                    .and_then(Json::as_str)
+                    .unwrap()
+                    .to_string()
+            })
+            .collect::<Vec<_>>(),
+        other => panic!("not a list of symbols: {}", other),
+    };
+    assert_eq!(names(3), ["square", "main"]);
+    assert_eq!(names(4), ["Point", "main"]);
+    for id in [2, 5] {
+        let data = result(&messages, id).get("data").unwrap();
+        assert!(
+            matches!(data, Json::Array(data) if !data.is_empty()),
+            "{}",
+            data
+        );
+    }
+}
+
+/// A playground serving on a port of its own
+fn start_playground() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let playground = Playground::new(PathBuf::from(env!("CARGO_BIN_EXE_solo")));
+    thread::spawn(move || playground::serve(listener, playground));
+    port
+}
+
+/// POST `source` to `path` and read the status and body of the response
+fn post(port: u16, path: &str, source: &str) -> (u16, Json) {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    write!(
+        stream,
+        "POST {} HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n{}",
+        path,
+        source.len(),
+        source
+    )
+    .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+    (status, Json::parse(body).unwrap())
+}
+
+#[test]
+fn playground_checks_the_same_source_twice() {
+    let port = start_playground();
+    let source = "fn main() { let answer = 42; print(missing); }";
+    for _ in 0..2 {
+        let (status, body) = post(port, "/check", source);
+        assert_eq!(status, 200);
+        assert_eq!(body.get("ok"), Some(&Json::Bool(false)), "{}", body);
+        let Some(Json::Array(diagnostics)) = body.get("diagnostics") else {
+            panic!("no diagnostics: {}", body);
+        };
+        let message = diagnostics[0].get("message").and_then(Json::as_str);
+        assert_eq!(message, Some("cannot find value `missing` in this scope"));
+    }
+
+    let (status, body) = post(port, "/check", "fn main() { let fresh = 1; print(fresh); }");
+    assert_eq!(status, 200);
+    assert_eq!(body.get("ok"), Some(&Json::Bool(true)), "{}", body);
+}
+
+#[test]
+fn rpc_answers_requests_on_one_connection_and_the_next() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    thread::spawn(move || rpc::serve(listener, false));
+
+    let source = "fn twice(n: i64) -> i64 { n * 2 }\nfn main() -> i64 { twice(21) }";
+    for _ in 0..2 {
+        let stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        let mut writer = stream.try_clone().unwrap();
+        let mut lines = BufReader::new(stream).lines();
+        let mut call = |id: i64, method: &str, params: Json| {
+            let request = lsp_request(id, method, params);
+            writeln!(writer, "{}", request).unwrap();
+            let response = Json::parse(&lines.next().unwrap().unwrap()).unwrap();
+            assert_eq!(response.get("id"), Some(&Json::Int(id)));
+            assert_eq!(response.get("error"), None, "{}", response);
+            response.get("result").unwrap().clone()
+        };
+
+        let name = || ("name", Json::str("twice.solo"));
+        call(
+            1,
+            "open",
+            Json::object([name(), ("source", Json::str(source))]),
+        );
+        let checked = call(2, "check", Json::object([name()]));
+        assert_eq!(checked.get("ok"), Some(&Json::Bool(true)), "{}", checked);
+        let evaluated = call(3, "eval", Json::object([name()]));
+        assert_eq!(
+            evaluated.get("value"),
+            Some(&Json::str("42")),
+            "{}",
+            evaluated
+        );
+        let fresh = "fn main() { let unseen = 7; print(unseen); }";
+        let evaluated = call(4, "eval", Json::object([("source", Json::str(fresh))]));
+        assert_eq!(
+            evaluated.get("output"),
+            Some(&Json::str("7\n")),
+            "{}",
+            evaluated
+        );
+    }
+}
@@ -0,0 +1,73 @@
+//! UI tests: the diagnostics of every `.solo` file in `tests/ui`
+//!
+//! Each file is compiled with `solo check`, or with the command of a first
+//! line `//@ <command> [options...]`, and what the compiler writes to stderr
+//! is compared with the `.stderr` snapshot next to it. Files that compile
+//! without diagnostics have no snapshot.
+//!
+//! After changing a diagnostic, run `SOLO_BLESS=1 cargo test --test ui` to
+//! rewrite the snapshots from the compiler's output, and review their diff.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+#[test]
+fn ui() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/ui");
+    let bless = env::var_os("SOLO_BLESS").is_some_and(|value| value != "0");
+    let mut files: Vec<PathBuf> = fs::read_dir(&dir)
+        .expect("tests/ui exists")
+        .map(|entry| entry.expect("tests/ui is readable").path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "solo"))
+        .collect();
+    files.sort();
+
+    let mut failures = Vec::new();
+    for file in &files {
+        let name = file.file_name().expect("files have names");
+        let source = fs::read_to_string(file).expect("UI tests are readable");
+        let command = match source.lines().next().and_then(|l| l.strip_prefix("//@")) {
+            Some(command) => command.split_whitespace().collect(),
+            None => vec!["check"],
+        };
+        // Run from the directory so that paths in diagnostics are just the
+        // file name
+        let output = Command::new(env!("CARGO_BIN_EXE_solo"))
+            .args(&command)
+            .arg(name)
+            .current_dir(&dir)
+            .env_remove("SOLO_BACKTRACE")
+            .output()
+            .expect("the compiler runs");
+        let actual = String::from_utf8_lossy(&output.stderr);
+
+        let snapshot = file.with_extension("stderr");
+        if bless {
+            if actual.is_empty() {
+                let _ = fs::remove_file(&snapshot);
+            } else {
+                fs::write(&snapshot, actual.as_bytes()).expect("snapshots are writable");
+            }
+            continue;
+        }
+        let expected = fs::read_to_string(&snapshot).unwrap_or_default();
+        if actual != expected {
+            failures.push(format!(
+                "{}:\n--- expected\n{}--- actual\n{}",
+                file.display(),
+                expected,
+                actual
+            ));
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{} of {} UI tests differ from their snapshots \
+         (rerun with SOLO_BLESS=1 to update them):\n\n{}",
+        failures.len(),
+        files.len(),
+        failures.join("\n")
+    );
+}
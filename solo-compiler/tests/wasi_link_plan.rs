@@ -0,0 +1,80 @@
+//! The link plan of `solo build --target wasm32-wasi`, a planned target
+//!
+//! Needs no WebAssembly tools: the compiler does not generate code yet, so
+//! `solo build` stops at the link plan, which is checked here together with
+//! the target's data layout. No module is built or run until object files
+//! are written; a test running one under `wasmtime` will go beside this.
+
+use std::env;
+use std::fs;
+use std::process::{Command, Output};
+
+fn solo(dir: &std::path::Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_solo"))
+        .args(args)
+        .current_dir(dir)
+        .env("WASI_SYSROOT", "/opt/wasi-sysroot")
+        .output()
+        .expect("the compiler runs")
+}
+
+#[test]
+fn wasi_link_plan() {
+    let dir = env::temp_dir().join(format!("solo-wasi-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join("hello.solo"),
+        "struct Sizes { n: usize }\nfn main() { print(env::args_len()); }\n",
+    )
+    .unwrap();
+
+    let built = solo(
+        &dir,
+        &[
+            "build",
+            "--target",
+            "wasm32-wasi",
+            "--print-link-args",
+            "hello.solo",
+        ],
+    );
+    let checked = solo(
+        &dir,
+        &[
+            "check",
+            "--target",
+            "wasm32-wasi",
+            "--print-layouts",
+            "hello.solo",
+        ],
+    );
+    fs::remove_dir_all(&dir).unwrap();
+
+    let stdout = String::from_utf8_lossy(&built.stdout);
+    assert!(
+        built.status.success(),
+        "{}",
+        String::from_utf8_lossy(&built.stderr)
+    );
+    assert!(
+        stdout.contains(
+            "wasm-ld -o hello.wasm /opt/wasi-sysroot/lib/wasm32-wasi/crt1-command.o hello.o \
+             -L/opt/wasi-sysroot/lib/wasm32-wasi -lc"
+        ),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("(wasm32-wasi)"), "{}", stdout);
+
+    let layouts = String::from_utf8_lossy(&checked.stdout);
+    assert!(
+        checked.status.success(),
+        "{}",
+        String::from_utf8_lossy(&checked.stderr)
+    );
+    assert!(
+        layouts.contains("struct Sizes: size 4, align 4"),
+        "{}",
+        layouts
+    );
+}